@@ -0,0 +1,246 @@
+//! Computes the stdlib root digest embedded into fardrun and fardverify.
+//!
+//! The digest commits to the builtin surface the runtime actually ships:
+//!   builtin_enum  — the variants of the `Builtin` enum in src/builtin_surface.rs
+//!   exports       — the (module, export, kind) table `STD_MODULES` in src/builtin_surface.rs
+//!   sig_table     — the (name, arity_min, value_first) entries of src/builtin_sig_table_v1.rs
+//!   ontology      — ontology/stdlib_surface.v1_0.ontology.json
+//!
+//! src/builtin_surface.rs is compiled into this script, so the enum and the export table
+//! are hashed from their values, not their source text. Each component is hashed in a
+//! canonical form: sorted entries with whitespace dropped, and the ontology re-serialized
+//! with sorted keys. Editing a comment or reordering declarations does not change the
+//! runtime identity.
+//!
+//! It also writes `$OUT_DIR/stdlib_exports.rs`, the (module, export, kind) table of
+//! `STD_MODULES` and the export types of src/stdlib_types_v1.txt, which fardcheck uses
+//! to type stdlib imports.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+mod surface {
+    include!("src/builtin_surface.rs");
+}
+
+const SURFACE_SRC: &str = "src/builtin_surface.rs";
+const SIG_TABLE_SRC: &str = "src/builtin_sig_table_v1.rs";
+const ONTOLOGY: &str = "ontology/stdlib_surface.v1_0.ontology.json";
+const STDLIB_TYPES: &str = "src/stdlib_types_v1.txt";
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut h = Sha256::new();
+    h.update(bytes);
+    h.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `src` with `//` and `/* */` comments removed and every whitespace run outside
+/// string literals dropped.
+fn strip_code(src: &str) -> String {
+    let mut out = String::new();
+    let mut cs = src.chars().peekable();
+    while let Some(c) = cs.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = cs.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(cs.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if cs.peek() == Some(&'/') => {
+                for c in cs.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if cs.peek() == Some(&'*') => {
+                cs.next();
+                let mut prev = ' ';
+                for c in cs.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Split on `sep` outside (), <>, [] and {}.
+fn split_top(src: &str, sep: char) -> Vec<&str> {
+    let (mut depth, mut start, mut out) = (0i32, 0, Vec::new());
+    for (i, c) in src.char_indices() {
+        match c {
+            '(' | '<' | '[' | '{' => depth += 1,
+            ')' | '>' | ']' | '}' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                out.push(&src[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&src[start..]);
+    out.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+/// One line per `Builtin` variant with its payload types, sorted.
+fn canonical_enum() -> String {
+    let mut variants: Vec<String> = surface::BUILTIN_VARIANTS
+        .iter()
+        .map(|v| v.chars().filter(|c| !c.is_whitespace()).collect())
+        .collect();
+    variants.sort();
+    variants.iter().map(|v| format!("{}\n", v)).collect()
+}
+
+/// One `name arity_min=N value_first=B` line per entry of `builtin_sig_table_v1`, sorted.
+/// Entries name their signature inline or through a `let` binding.
+fn canonical_sig_table(src: &str) -> String {
+    let code = strip_code(src);
+    let sig = |lit: &str| -> Option<String> {
+        let fields = lit.strip_prefix("BuiltinSig{")?.strip_suffix('}')?;
+        let mut arity = None;
+        let mut value_first = None;
+        for f in split_top(fields, ',') {
+            match f.split_once(':')? {
+                ("arity_min", n) => arity = Some(n.parse::<usize>().ok()?),
+                ("value_first", b) => value_first = Some(b.parse::<bool>().ok()?),
+                _ => return None,
+            }
+        }
+        Some(format!("arity_min={} value_first={}", arity?, value_first?))
+    };
+    let mut bound = std::collections::BTreeMap::new();
+    let mut entries = Vec::new();
+    for stmt in split_top(&code, ';') {
+        if let Some((name, lit)) = stmt.strip_prefix("let").and_then(|r| r.split_once('=')) {
+            if let Some(s) = sig(lit) {
+                bound.insert(name.to_string(), s);
+            }
+        } else if let Some(args) = stmt.strip_prefix("m.insert(").and_then(|r| r.strip_suffix(')')) {
+            let (key, value) = match split_top(args, ',')[..] {
+                [key, value] => (key, value),
+                _ => panic!("build.rs: malformed sig table entry `{}`", stmt),
+            };
+            let value = bound
+                .get(value)
+                .cloned()
+                .or_else(|| sig(value))
+                .unwrap_or_else(|| panic!("build.rs: unknown signature `{}` for {}", value, key));
+            entries.push(format!("{} {}\n", key.trim_matches('"'), value));
+        }
+    }
+    assert!(!entries.is_empty(), "build.rs: no entries found in {}", SIG_TABLE_SRC);
+    entries.sort();
+    entries.concat()
+}
+
+/// Every export of `STD_MODULES` as (module, export, kind) with kind one of fn/float/record.
+fn stdlib_exports() -> Vec<(String, String, &'static str)> {
+    let mut out = Vec::new();
+    for (module, exports) in surface::STD_MODULES {
+        for (name, export) in exports.iter() {
+            let kind = match export {
+                surface::StdExport::Fn(_) => "fn",
+                surface::StdExport::Float(_) => "float",
+                surface::StdExport::Record(_) => "record",
+            };
+            out.push((module.to_string(), name.to_string(), kind));
+        }
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// The `module::export signature` lines of src/stdlib_types_v1.txt. Each must name
+/// a function export, once, and every function export must have one.
+fn stdlib_types(src: &str, exports: &[(String, String, &'static str)]) -> Vec<(String, String)> {
+    let mut out: Vec<(String, String)> = Vec::new();
+    for line in src.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (key, sig) = line
+            .split_once(char::is_whitespace)
+            .unwrap_or_else(|| panic!("build.rs: {}: no signature in `{}`", STDLIB_TYPES, line));
+        let is_fn = key
+            .split_once("::")
+            .is_some_and(|(m, name)| exports.iter().any(|(em, en, kind)| em == m && en == name && *kind == "fn"));
+        if !is_fn {
+            panic!("build.rs: {}: {} is not a function export of STD_MODULES", STDLIB_TYPES, key);
+        }
+        if out.iter().any(|(k, _)| k == key) {
+            panic!("build.rs: {}: {} is listed twice", STDLIB_TYPES, key);
+        }
+        out.push((key.to_string(), sig.trim().to_string()));
+    }
+    let missing: Vec<String> = exports
+        .iter()
+        .filter(|(_, _, kind)| *kind == "fn")
+        .map(|(m, name, _)| format!("{}::{}", m, name))
+        .filter(|key| !out.iter().any(|(k, _)| k == key))
+        .collect();
+    if !missing.is_empty() {
+        panic!("build.rs: {}: no signature for {}", STDLIB_TYPES, missing.join(", "));
+    }
+    out.sort();
+    out
+}
+
+fn main() {
+    for p in [SURFACE_SRC, SIG_TABLE_SRC, ONTOLOGY, STDLIB_TYPES] {
+        println!("cargo:rerun-if-changed={}", p);
+    }
+
+    let sig_table = fs::read_to_string(SIG_TABLE_SRC).expect("build.rs: read sig table");
+    let ontology = fs::read_to_string(ONTOLOGY).expect("build.rs: read ontology");
+
+    let ontology: serde_json::Value = serde_json::from_str(&ontology).expect("build.rs: parse ontology");
+    let exports = stdlib_exports();
+    let components = [
+        ("builtin_enum", canonical_enum()),
+        ("exports", exports.iter().map(|(m, name, kind)| format!("{} {} {}\n", m, name, kind)).collect()),
+        ("ontology", serde_json::to_string(&ontology).expect("build.rs: serialize ontology")),
+        ("sig_table", canonical_sig_table(&sig_table)),
+    ];
+
+    let mut pre = String::from("stdlib_root_v1\n");
+    for (name, body) in &components {
+        pre.push_str(name);
+        pre.push_str("=sha256:");
+        pre.push_str(&sha256_hex(body.as_bytes()));
+        pre.push('\n');
+    }
+
+    println!(
+        "cargo:rustc-env=FARD_STDLIB_ROOT_DIGEST=sha256:{}",
+        sha256_hex(pre.as_bytes())
+    );
+
+    let mut table = String::from("/// (module, export, kind) for every export of fardrun's builtin std modules.\n");
+    table.push_str("pub const STDLIB_EXPORTS: &[(&str, &str, &str)] = &[\n");
+    for (m, name, kind) in &exports {
+        table.push_str(&format!("    ({:?}, {:?}, {:?}),\n", m, name, kind));
+    }
+    table.push_str("];\n");
+    let types = fs::read_to_string(STDLIB_TYPES).expect("build.rs: read stdlib types");
+    table.push_str("/// (module::export, signature) from src/stdlib_types_v1.txt.\n");
+    table.push_str("pub const STDLIB_SIGS: &[(&str, &str)] = &[\n");
+    for (key, sig) in stdlib_types(&types, &exports) {
+        table.push_str(&format!("    ({:?}, {:?}),\n", key, sig));
+    }
+    table.push_str("];\n");
+    let out_dir = std::env::var("OUT_DIR").expect("build.rs: OUT_DIR");
+    fs::write(Path::new(&out_dir).join("stdlib_exports.rs"), table).expect("build.rs: write stdlib_exports.rs");
+}
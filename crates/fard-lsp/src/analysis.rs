@@ -0,0 +1,499 @@
+//! Editor features computed from the token stream: document symbols, rename,
+//! signature help and semantic tokens. Everything is per-file and lexical — a name
+//! is matched by spelling, not by scope.
+
+use crate::lexer::{Kind, Token, KEYWORDS};
+use tower_lsp::lsp_types::*;
+
+/// Code tokens (comments dropped) with the bracket structure around each one.
+pub struct Code {
+    pub toks: Vec<Token>,
+    /// Index of the innermost open bracket enclosing each token
+    open: Vec<Option<usize>>,
+}
+
+impl Code {
+    pub fn new(all: &[Token]) -> Self {
+        let toks: Vec<Token> = all.iter().filter(|t| t.kind != Kind::Comment).cloned().collect();
+        let mut stack: Vec<usize> = Vec::new();
+        let mut open = Vec::with_capacity(toks.len());
+        for (i, t) in toks.iter().enumerate() {
+            if t.kind == Kind::Sym && matches!(t.text.as_str(), ")" | "]" | "}") {
+                stack.pop();
+            }
+            open.push(stack.last().copied());
+            if t.kind == Kind::Sym && matches!(t.text.as_str(), "(" | "[" | "{") {
+                stack.push(i);
+            }
+        }
+        Code { toks, open }
+    }
+
+    fn at(&self, i: usize) -> Option<&Token> {
+        self.toks.get(i)
+    }
+    fn prev(&self, i: usize, k: usize) -> Option<&Token> {
+        i.checked_sub(k).and_then(|j| self.toks.get(j))
+    }
+    fn depth0(&self, i: usize) -> bool {
+        self.open[i].is_none()
+    }
+    fn enclosing(&self, i: usize) -> Option<&Token> {
+        self.open[i].map(|j| &self.toks[j])
+    }
+
+    /// `x.name` — a record field or module member, not a variable
+    fn is_member(&self, i: usize) -> bool {
+        self.prev(i, 1).is_some_and(|t| t.is_sym("."))
+    }
+    /// `{ name: v }` — a record key
+    fn is_record_key(&self, i: usize) -> bool {
+        self.at(i + 1).is_some_and(|t| t.is_sym(":")) && self.enclosing(i).is_some_and(|t| t.is_sym("{"))
+    }
+    /// A parameter in `fn name(a, b)` or `fn(a, b)`
+    fn is_param(&self, i: usize) -> bool {
+        let Some(o) = self.open[i] else { return false };
+        if !self.toks[o].is_sym("(") {
+            return false;
+        }
+        let after_fn = self.prev(o, 1).is_some_and(|t| t.is_kw("fn"))
+            || (self.prev(o, 1).is_some_and(|t| t.kind == Kind::Ident)
+                && self.prev(o, 2).is_some_and(|t| t.is_kw("fn")));
+        after_fn
+            && self.prev(i, 1).is_some_and(|t| t.is_sym("(") || t.is_sym(","))
+            && self.at(i + 1).is_some_and(|t| t.is_sym(",") || t.is_sym(")") || t.is_sym(":"))
+    }
+    /// The place a name is bound: `fn name`, `let name`, `as name`, a parameter,
+    /// or a `let {a, b}` / `let [a, b]` destructuring pattern.
+    fn is_definition(&self, i: usize) -> bool {
+        if self.toks[i].kind != Kind::Ident {
+            return false;
+        }
+        if self.prev(i, 1).is_some_and(|t| t.is_kw("fn") || t.is_kw("let") || t.is_kw("as")) {
+            return true;
+        }
+        if self.is_param(i) {
+            return true;
+        }
+        self.open[i].is_some_and(|o| {
+            self.prev(o, 1).is_some_and(|t| t.is_kw("let"))
+                && self.prev(i, 1).is_some_and(|t| t.is_sym("{") || t.is_sym("[") || t.is_sym(","))
+        })
+    }
+    /// Index of the identifier token at a position
+    fn ident_at(&self, line: u32, col: u32) -> Option<usize> {
+        self.toks.iter().position(|t| t.kind == Kind::Ident && t.contains(line, col))
+    }
+}
+
+fn range_of(t: &Token) -> Range {
+    Range {
+        start: Position { line: t.line, character: t.col },
+        end: Position { line: t.end_line, character: t.end_col },
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '"' || c == '`')
+}
+
+/// `import("path") as alias` pairs, in order.
+pub fn imports(code: &Code) -> Vec<(String, String)> {
+    code.toks
+        .windows(6)
+        .filter(|w| {
+            w[0].is_kw("import") && w[1].is_sym("(") && w[2].kind == Kind::Str
+                && w[3].is_sym(")") && w[4].is_kw("as") && w[5].kind == Kind::Ident
+        })
+        .map(|w| (w[5].text.clone(), unquote(&w[2].text).to_string()))
+        .collect()
+}
+
+/// `name(p1, p2)` for each `fn name(p1, p2)` in the file.
+pub fn local_fn_labels(code: &Code) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    for i in 0..code.toks.len() {
+        if !(code.toks[i].is_kw("fn")
+            && code.at(i + 1).is_some_and(|t| t.kind == Kind::Ident)
+            && code.at(i + 2).is_some_and(|t| t.is_sym("(")))
+        {
+            continue;
+        }
+        let params: Vec<&str> = (i + 3..code.toks.len())
+            .filter(|&j| code.is_param(j) && code.open[j] == Some(i + 2))
+            .map(|j| code.toks[j].text.as_str())
+            .collect();
+        let name = code.toks[i + 1].text.clone();
+        out.push((name.clone(), format!("{}({})", name, params.join(", "))));
+    }
+    out
+}
+
+/// Top-level items: functions, lets, imports and tests. Each item's range runs
+/// up to the start of the next top-level item.
+#[allow(deprecated)]
+pub fn document_symbols(code: &Code) -> Vec<DocumentSymbol> {
+    let labels = local_fn_labels(code);
+    let mut items: Vec<(usize, String, SymbolKind, Option<String>, Range)> = Vec::new();
+    for (i, t) in code.toks.iter().enumerate() {
+        if !code.depth0(i) || t.kind != Kind::Keyword {
+            continue;
+        }
+        let next = code.at(i + 1);
+        let item = match t.text.as_str() {
+            "fn" => next.filter(|n| n.kind == Kind::Ident).map(|n| {
+                let detail = labels.iter().find(|(k, _)| *k == n.text).map(|(_, l)| l.clone());
+                (n.text.clone(), SymbolKind::FUNCTION, detail, range_of(n))
+            }),
+            "let" => next
+                .filter(|n| n.kind == Kind::Ident)
+                .map(|n| (n.text.clone(), SymbolKind::VARIABLE, None, range_of(n))),
+            "test" => next
+                .filter(|n| n.kind == Kind::Str)
+                .map(|n| (unquote(&n.text).to_string(), SymbolKind::EVENT, None, range_of(n))),
+            "import" => {
+                let w = &code.toks[i..(i + 6).min(code.toks.len())];
+                (w.len() == 6 && w[4].is_kw("as") && w[5].kind == Kind::Ident).then(|| {
+                    (w[5].text.clone(), SymbolKind::MODULE, Some(unquote(&w[2].text).to_string()), range_of(&w[5]))
+                })
+            }
+            _ => None,
+        };
+        if let Some((name, kind, detail, sel)) = item {
+            items.push((i, name, kind, detail, sel));
+        }
+    }
+    let mut syms = Vec::new();
+    for (k, (start, name, kind, detail, selection_range)) in items.iter().enumerate() {
+        let last = match items.get(k + 1) {
+            Some((next, ..)) => next - 1,
+            None => code.toks.len() - 1,
+        };
+        let range = Range {
+            start: Position { line: code.toks[*start].line, character: code.toks[*start].col },
+            end: Position { line: code.toks[last].end_line, character: code.toks[last].end_col },
+        };
+        syms.push(DocumentSymbol {
+            name: name.clone(),
+            detail: detail.clone(),
+            kind: *kind,
+            tags: None,
+            deprecated: None,
+            range,
+            selection_range: *selection_range,
+            children: None,
+        });
+    }
+    syms
+}
+
+/// Document symbols whose name contains `query` (case-insensitive), as workspace symbols.
+#[allow(deprecated)]
+pub fn workspace_symbols(code: &Code, uri: &Url, query: &str) -> Vec<SymbolInformation> {
+    let q = query.to_lowercase();
+    document_symbols(code)
+        .into_iter()
+        .filter(|s| s.name.to_lowercase().contains(&q))
+        .map(|s| SymbolInformation {
+            name: s.name,
+            kind: s.kind,
+            tags: None,
+            deprecated: None,
+            location: Location { uri: uri.clone(), range: s.selection_range },
+            container_name: None,
+        })
+        .collect()
+}
+
+/// The renameable identifier at a position: a variable, function or parameter
+/// (not a record key or `.member`) that is bound somewhere in this file.
+fn rename_target(code: &Code, line: u32, col: u32) -> std::result::Result<usize, String> {
+    let i = code.ident_at(line, col).ok_or("no identifier at cursor")?;
+    if code.is_member(i) || code.is_record_key(i) {
+        return Err(format!("`{}` is a record field, not a variable", code.toks[i].text));
+    }
+    let name = &code.toks[i].text;
+    if !(0..code.toks.len()).any(|j| code.toks[j].text == *name && code.is_definition(j)) {
+        return Err(format!("`{}` is not defined in this file", name));
+    }
+    Ok(i)
+}
+
+pub fn prepare_rename(code: &Code, line: u32, col: u32) -> Option<Range> {
+    rename_target(code, line, col).ok().map(|i| range_of(&code.toks[i]))
+}
+
+/// Edits renaming every variable use of the identifier at a position.
+pub fn rename(code: &Code, line: u32, col: u32, new_name: &str) -> std::result::Result<Vec<TextEdit>, String> {
+    let valid = new_name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && new_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&new_name);
+    if !valid {
+        return Err(format!("`{}` is not a valid identifier", new_name));
+    }
+    let i = rename_target(code, line, col)?;
+    let name = &code.toks[i].text;
+    Ok((0..code.toks.len())
+        .filter(|&j| {
+            code.toks[j].kind == Kind::Ident && code.toks[j].text == *name
+                && !code.is_member(j) && !code.is_record_key(j)
+        })
+        .map(|j| TextEdit { range: range_of(&code.toks[j]), new_text: new_name.to_string() })
+        .collect())
+}
+
+/// The call surrounding a position: (module alias, callee name, index of the active argument).
+pub fn call_at(code: &Code, line: u32, col: u32) -> Option<(Option<String>, String, u32)> {
+    let mut stack: Vec<(usize, u32)> = Vec::new();
+    for (i, t) in code.toks.iter().enumerate() {
+        if (t.line, t.col) >= (line, col) {
+            break;
+        }
+        if t.kind != Kind::Sym {
+            continue;
+        }
+        match t.text.as_str() {
+            "(" | "[" | "{" => stack.push((i, 0)),
+            ")" | "]" | "}" => {
+                stack.pop();
+            }
+            "," => {
+                if let Some(top) = stack.last_mut() {
+                    top.1 += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    let &(open, active) = stack.last()?;
+    if !code.toks[open].is_sym("(") {
+        return None;
+    }
+    let callee = code.prev(open, 1).filter(|t| t.kind == Kind::Ident)?;
+    if code.prev(open, 2).is_some_and(|t| t.is_kw("fn")) {
+        return None;
+    }
+    let alias = (code.prev(open, 2).is_some_and(|t| t.is_sym("."))
+        && code.prev(open, 3).is_some_and(|t| t.kind == Kind::Ident))
+        .then(|| code.toks[open - 3].text.clone());
+    Some((alias, callee.text.clone(), active))
+}
+
+/// Signature help for a label such as `map(list, fn) -> list`; parameters are the
+/// comma-separated parts between the first `(` and its matching `)`.
+pub fn signature_help(label: &str, documentation: Option<String>, active: u32) -> SignatureHelp {
+    let mut params = Vec::new();
+    if let Some(open) = label.find('(') {
+        let mut depth = 0usize;
+        let mut start = open + 1;
+        for (i, c) in label.char_indices().skip_while(|(i, _)| *i <= open) {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' if depth > 0 => depth -= 1,
+                ',' | ')' if depth == 0 => {
+                    let part = &label[start..i];
+                    let lead = part.len() - part.trim_start().len();
+                    let (s, e) = (start + lead, start + part.trim_end().len());
+                    if e > s {
+                        let u16 = |b: usize| label[..b].encode_utf16().count() as u32;
+                        params.push(ParameterInformation {
+                            label: ParameterLabel::LabelOffsets([u16(s), u16(e)]),
+                            documentation: None,
+                        });
+                    }
+                    start = i + 1;
+                    if c == ')' {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label: label.to_string(),
+            documentation: documentation.map(Documentation::String),
+            parameters: Some(params),
+            active_parameter: Some(active),
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(active),
+    }
+}
+
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+pub const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[SemanticTokenModifier::DECLARATION];
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+
+fn type_index(t: SemanticTokenType) -> u32 {
+    TOKEN_TYPES.iter().position(|x| *x == t).unwrap_or(0) as u32
+}
+
+/// Classify an identifier: (token type, is a declaration).
+fn classify_ident(code: &Code, aliases: &[String], fns: &[String], i: usize) -> (SemanticTokenType, bool) {
+    let t = &code.toks[i];
+    let call = code.at(i + 1).is_some_and(|n| n.is_sym("("));
+    let decl = code.is_definition(i);
+    let ty = if code.is_member(i) {
+        if call { SemanticTokenType::FUNCTION } else { SemanticTokenType::PROPERTY }
+    } else if code.is_record_key(i) {
+        SemanticTokenType::PROPERTY
+    } else if aliases.contains(&t.text) {
+        SemanticTokenType::NAMESPACE
+    } else if code.is_param(i) {
+        SemanticTokenType::PARAMETER
+    } else if call || fns.contains(&t.text) {
+        SemanticTokenType::FUNCTION
+    } else {
+        SemanticTokenType::VARIABLE
+    };
+    (ty, decl)
+}
+
+/// Full-document semantic tokens. Tokens spanning several lines (multi-line
+/// strings) are split into one token per line.
+pub fn semantic_tokens(src: &str, all: &[Token]) -> Vec<SemanticToken> {
+    let code = Code::new(all);
+    let aliases: Vec<String> = imports(&code).into_iter().map(|(a, _)| a).collect();
+    let fns: Vec<String> = local_fn_labels(&code).into_iter().map(|(n, _)| n).collect();
+    let line_len: Vec<u32> = src.split('\n').map(|l| l.encode_utf16().count() as u32).collect();
+
+    let mut spans: Vec<(u32, u32, u32, u32, u32)> = Vec::new();
+    let mut ci = 0usize;
+    for t in all {
+        let (ty, decl) = match t.kind {
+            Kind::Comment => (SemanticTokenType::COMMENT, false),
+            _ => {
+                let i = ci;
+                ci += 1;
+                match t.kind {
+                    Kind::Keyword => (SemanticTokenType::KEYWORD, false),
+                    Kind::Number => (SemanticTokenType::NUMBER, false),
+                    Kind::Str => (SemanticTokenType::STRING, false),
+                    Kind::Ident => classify_ident(&code, &aliases, &fns, i),
+                    Kind::Sym if !matches!(t.text.as_str(), "(" | ")" | "[" | "]" | "{" | "}" | "," | ";" | ":" | ".") => {
+                        (SemanticTokenType::OPERATOR, false)
+                    }
+                    _ => continue,
+                }
+            }
+        };
+        let (ty, mods) = (type_index(ty), if decl { 1 } else { 0 });
+        for line in t.line..=t.end_line {
+            let start = if line == t.line { t.col } else { 0 };
+            let end = if line == t.end_line { t.end_col } else { line_len.get(line as usize).copied().unwrap_or(0) };
+            if end > start {
+                spans.push((line, start, end - start, ty, mods));
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(spans.len());
+    let (mut pl, mut pc) = (0u32, 0u32);
+    for (line, col, length, token_type, token_modifiers_bitset) in spans {
+        let delta_line = line - pl;
+        let delta_start = if delta_line == 0 { col - pc } else { col };
+        out.push(SemanticToken { delta_line, delta_start, length, token_type, token_modifiers_bitset });
+        pl = line;
+        pc = col;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    const SRC: &str = "\
+import(\"std/list\") as list
+fn double(n) { n * 2 }
+let rec = { n: 1 }
+let xs = list.map([1, 2], fn(x) { double(x) + rec.n })
+test \"doubles\" { double(2) == 4 }";
+
+    fn code() -> Code {
+        Code::new(&lex(SRC))
+    }
+
+    #[test]
+    fn document_symbols_cover_top_level_items() {
+        let syms = document_symbols(&code());
+        let names: Vec<(&str, SymbolKind)> = syms.iter().map(|s| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(names, [
+            ("list", SymbolKind::MODULE),
+            ("double", SymbolKind::FUNCTION),
+            ("rec", SymbolKind::VARIABLE),
+            ("xs", SymbolKind::VARIABLE),
+            ("doubles", SymbolKind::EVENT),
+        ]);
+        assert_eq!(syms[1].detail.as_deref(), Some("double(n)"));
+        assert_eq!(syms[1].range.start.line, 1);
+        assert_eq!(syms[1].range.end.line, 1);
+    }
+
+    #[test]
+    fn rename_skips_record_keys_and_members() {
+        // `n` is a parameter of double, a record key and a field access
+        let edits = rename(&code(), 1, 11, "k").unwrap();
+        let at: Vec<(u32, u32)> = edits.iter().map(|e| (e.range.start.line, e.range.start.character)).collect();
+        assert_eq!(at, [(1, 10), (1, 15)]);
+    }
+
+    #[test]
+    fn rename_rejects_undefined_and_invalid_names() {
+        assert!(rename(&code(), 3, 14, "m").is_err()); // `map` member
+        assert!(rename(&code(), 1, 4, "let").is_err());
+        assert!(prepare_rename(&code(), 1, 4).is_some());
+        let edits = rename(&code(), 1, 4, "twice").unwrap();
+        assert_eq!(edits.len(), 3);
+    }
+
+    #[test]
+    fn call_at_finds_member_call_and_argument() {
+        // inside list.map([1, 2], |
+        let c = code();
+        let (alias, name, active) = call_at(&c, 3, 25).unwrap();
+        assert_eq!((alias.as_deref(), name.as_str(), active), (Some("list"), "map", 1));
+        // directly inside the list literal there is no call to help with
+        assert_eq!(call_at(&c, 3, 19).map(|c| c.2), None);
+    }
+
+    #[test]
+    fn signature_help_marks_parameters() {
+        let h = signature_help("map(list, fn) -> list", None, 1);
+        let ps = h.signatures[0].parameters.as_ref().unwrap();
+        assert_eq!(ps.len(), 2);
+        assert!(matches!(ps[1].label, ParameterLabel::LabelOffsets([10, 12])));
+    }
+
+    #[test]
+    fn semantic_tokens_classify_identifiers() {
+        let toks = lex(SRC);
+        let sem = semantic_tokens(SRC, &toks);
+        // first token: `import` keyword at 0:0
+        assert_eq!((sem[0].delta_line, sem[0].delta_start, sem[0].token_type), (0, 0, type_index(SemanticTokenType::KEYWORD)));
+        let ns = type_index(SemanticTokenType::NAMESPACE);
+        assert_eq!(sem.iter().filter(|t| t.token_type == ns).count(), 2);
+        let param = type_index(SemanticTokenType::PARAMETER);
+        assert_eq!(sem.iter().filter(|t| t.token_type == param).count(), 2);
+    }
+}
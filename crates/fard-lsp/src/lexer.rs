@@ -0,0 +1,231 @@
+//! Lexical view of a FARD document for editor features.
+//!
+//! Follows fardrun's lexer (keywords, `#` and `//` comments, `"..."` strings with
+//! `${expr}` interpolation, backtick strings) but never fails: unterminated input
+//! simply ends the token. Positions are LSP positions (0-based line, UTF-16 column).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Keyword,
+    Ident,
+    Number,
+    Str,
+    Comment,
+    Sym,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: Kind,
+    pub text: String,
+    pub line: u32,
+    pub col: u32,
+    pub end_line: u32,
+    pub end_col: u32,
+}
+
+impl Token {
+    /// True if the position falls inside the token or touches its end.
+    pub fn contains(&self, line: u32, col: u32) -> bool {
+        (line, col) >= (self.line, self.col) && (line, col) <= (self.end_line, self.end_col)
+    }
+    pub fn is_sym(&self, s: &str) -> bool {
+        self.kind == Kind::Sym && self.text == s
+    }
+    pub fn is_kw(&self, s: &str) -> bool {
+        self.kind == Kind::Keyword && self.text == s
+    }
+}
+
+/// Keywords recognised by fardrun's lexer.
+pub const KEYWORDS: &[&str] = &[
+    "let", "in", "fn", "if", "then", "else", "import", "as", "export", "match", "test", "while",
+    "return", "using", "true", "false", "null",
+];
+
+const TWO_CHAR_SYMS: &[&str] = &[
+    "|>", "==", "!=", "<=", ">=", "&&", "||", "=>", "->", "..", "::", "++",
+];
+
+struct Cursor {
+    s: Vec<char>,
+    i: usize,
+    line: u32,
+    col: u32,
+}
+
+impl Cursor {
+    fn peek(&self, k: usize) -> Option<char> {
+        self.s.get(self.i + k).copied()
+    }
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.i += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 0;
+        } else {
+            self.col += c.len_utf16() as u32;
+        }
+        Some(c)
+    }
+}
+
+/// Where a string scan stopped.
+enum StrEnd {
+    Closed,
+    Interp,
+    Eof,
+}
+
+pub fn lex(src: &str) -> Vec<Token> {
+    let mut cur = Cursor { s: src.chars().collect(), i: 0, line: 0, col: 0 };
+    let mut toks = Vec::new();
+    // One entry per open `${`: the brace depth inside that interpolation
+    let mut interp: Vec<usize> = Vec::new();
+
+    while let Some(c) = cur.peek(0) {
+        let (line, col, start) = (cur.line, cur.col, cur.i);
+
+        let kind = if c.is_whitespace() {
+            cur.bump();
+            continue;
+        } else if c == '#' || (c == '/' && cur.peek(1) == Some('/')) {
+            while let Some(d) = cur.peek(0) {
+                if d == '\n' {
+                    break;
+                }
+                cur.bump();
+            }
+            Kind::Comment
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while cur.peek(0).is_some_and(|d| d.is_ascii_alphanumeric() || d == '_') {
+                cur.bump();
+            }
+            let word: String = cur.s[start..cur.i].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) { Kind::Keyword } else { Kind::Ident }
+        } else if c.is_ascii_digit() {
+            while cur.peek(0).is_some_and(|d| d.is_ascii_digit()) {
+                cur.bump();
+            }
+            if cur.peek(0) == Some('.') && cur.peek(1).is_some_and(|d| d.is_ascii_digit()) {
+                cur.bump();
+                while cur.peek(0).is_some_and(|d| d.is_ascii_digit()) {
+                    cur.bump();
+                }
+                if matches!(cur.peek(0), Some('e' | 'E')) {
+                    cur.bump();
+                    if matches!(cur.peek(0), Some('+' | '-')) {
+                        cur.bump();
+                    }
+                    while cur.peek(0).is_some_and(|d| d.is_ascii_digit()) {
+                        cur.bump();
+                    }
+                }
+            }
+            Kind::Number
+        } else if c == '`' {
+            cur.bump();
+            while let Some(d) = cur.bump() {
+                if d == '`' {
+                    break;
+                }
+            }
+            Kind::Str
+        } else if c == '"' {
+            cur.bump();
+            if let StrEnd::Interp = scan_string(&mut cur) {
+                interp.push(0);
+            }
+            Kind::Str
+        } else if c == '}' && interp.last() == Some(&0) {
+            // end of `${...}`: the rest of the string is its own token
+            interp.pop();
+            cur.bump();
+            if let StrEnd::Interp = scan_string(&mut cur) {
+                interp.push(0);
+            }
+            Kind::Str
+        } else {
+            let two: String = cur.s[start..(start + 2).min(cur.s.len())].iter().collect();
+            if TWO_CHAR_SYMS.contains(&two.as_str()) {
+                cur.bump();
+                cur.bump();
+            } else {
+                cur.bump();
+                if let Some(depth) = interp.last_mut() {
+                    match c {
+                        '{' => *depth += 1,
+                        '}' => *depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            Kind::Sym
+        };
+
+        toks.push(Token {
+            kind,
+            text: cur.s[start..cur.i].iter().collect(),
+            line,
+            col,
+            end_line: cur.line,
+            end_col: cur.col,
+        });
+    }
+    toks
+}
+
+/// Scan string contents after an opening quote (or after the `}` closing an interpolation).
+fn scan_string(cur: &mut Cursor) -> StrEnd {
+    while let Some(d) = cur.bump() {
+        match d {
+            '"' => return StrEnd::Closed,
+            '\\' => {
+                cur.bump();
+            }
+            '$' if cur.peek(0) == Some('{') => {
+                cur.bump();
+                return StrEnd::Interp;
+            }
+            _ => {}
+        }
+    }
+    StrEnd::Eof
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<(Kind, String)> {
+        lex(src).into_iter().map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn lexes_keywords_idents_and_comments() {
+        let k = kinds("let x = 1.5 // note\n# hash\nfn f(a) { a |> g }");
+        assert_eq!(k[0], (Kind::Keyword, "let".to_string()));
+        assert_eq!(k[1], (Kind::Ident, "x".to_string()));
+        assert_eq!(k[3], (Kind::Number, "1.5".to_string()));
+        assert_eq!(k[4], (Kind::Comment, "// note".to_string()));
+        assert_eq!(k[5], (Kind::Comment, "# hash".to_string()));
+        assert!(k.contains(&(Kind::Sym, "|>".to_string())));
+    }
+
+    #[test]
+    fn interpolation_exposes_inner_identifiers() {
+        let t = lex("\"a ${x + {k: 1}.k} b\"");
+        let idents: Vec<&str> = t.iter().filter(|t| t.kind == Kind::Ident).map(|t| t.text.as_str()).collect();
+        assert_eq!(idents, ["x", "k", "k"]);
+        assert_eq!(t.first().unwrap().text, "\"a ${");
+        assert_eq!(t.last().unwrap().text, "} b\"");
+    }
+
+    #[test]
+    fn columns_are_utf16() {
+        let t = lex("\"𝄞\" x");
+        assert_eq!(t[0].end_col, 4);
+        assert_eq!(t[1].col, 5);
+    }
+}
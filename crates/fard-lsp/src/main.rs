@@ -1,31 +1,140 @@
-use tower_lsp::jsonrpc::Result;
+mod analysis;
+mod lexer;
+
+use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
+use fard_v0_5_language_gate::SourceDiagnostic;
 
 #[derive(Debug)]
 struct FardLsp {
     client: Client,
     docs: Arc<RwLock<HashMap<String, String>>>,
+    /// Latest version of each open document
+    versions: Versions,
+    roots: Arc<RwLock<Vec<PathBuf>>>,
 }
 
-async fn publish(client: &Client, uri: Url, text: &str) {
-    let errors = fard_v0_5_language_gate::parse_check(text, &uri.to_string());
-    let diags: Vec<Diagnostic> = errors.into_iter().map(|(line, col, msg)| {
-        Diagnostic {
-            range: Range {
-                start: Position { line, character: col },
-                end:   Position { line, character: col + 80 },
-            },
-            severity: Some(DiagnosticSeverity::ERROR),
-            message: msg,
-            source: Some("fard-lsp".to_string()),
-            ..Default::default()
+type Versions = Arc<RwLock<HashMap<String, i32>>>;
+
+/// How long typing must pause before a changed document is checked again.
+const CHANGE_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Convert a 0-based char column on `line` to a UTF-16 column.
+fn utf16_col(text: &str, line: u32, col: u32) -> u32 {
+    let l = text.split('\n').nth(line as usize).unwrap_or("");
+    l.chars().take(col as usize).map(|c| c.len_utf16() as u32).sum()
+}
+
+/// A single edit replacing only the lines that differ between `old` and `new`.
+fn line_diff_edits(old: &str, new: &str) -> Vec<TextEdit> {
+    if old == new {
+        return vec![];
+    }
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let pre = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suf = a[pre..].iter().rev().zip(b[pre..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let end_line = (a.len() - suf) as u32;
+    let end = if (a.len() - suf) < a.len() || old.ends_with('\n') {
+        Position { line: end_line, character: 0 }
+    } else {
+        let last = end_line.saturating_sub(1);
+        Position { line: last, character: utf16_col(old, last, u32::MAX) }
+    };
+    vec![TextEdit {
+        range: Range { start: Position { line: pre as u32, character: 0 }, end },
+        new_text: b[pre..b.len() - suf].concat(),
+    }]
+}
+
+fn to_diagnostic(text: &str, d: SourceDiagnostic, source: &str) -> Diagnostic {
+    Diagnostic {
+        range: Range {
+            start: Position { line: d.line, character: utf16_col(text, d.line, d.col) },
+            end:   Position { line: d.end_line, character: utf16_col(text, d.end_line, d.end_col) },
+        },
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(d.code)),
+        message: d.message,
+        source: Some(source.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Parse errors from `fardrun check`; once the file parses, type errors from fardcheck.
+/// Diagnostics for a `version` the document has moved past by the time they are ready are dropped.
+async fn publish(client: &Client, versions: &Versions, uri: Url, text: &str, version: i32) {
+    let src = text.to_string();
+    let path = uri.to_file_path().ok();
+    let diags = tokio::task::spawn_blocking(move || {
+        let parse = fard_v0_5_language_gate::check_source(&src);
+        if !parse.is_empty() {
+            return parse.into_iter().map(|d| to_diagnostic(&src, d, "fardrun")).collect();
+        }
+        fard_v0_5_language_gate::type_check_source(&src, path.as_deref())
+            .into_iter()
+            .map(|d| to_diagnostic(&src, d, "fardcheck"))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+    if versions.read().await.get(uri.as_str()) != Some(&version) {
+        return;
+    }
+    client.publish_diagnostics(uri, diags, Some(version)).await;
+}
+
+/// Signature label for `alias.name(` or a local `name(`: the hand-written stdlib
+/// table first, then the arity from builtin_sig_table_v1, then local `fn` definitions.
+fn signature_label(code: &analysis::Code, alias: Option<&str>, name: &str) -> Option<(String, Option<String>)> {
+    if let Some(alias) = alias {
+        let path = analysis::imports(code)
+            .into_iter()
+            .find(|(a, _)| a == alias)
+            .map(|(_, p)| p)
+            .unwrap_or_else(|| format!("std/{}", alias));
+        let module = path.rsplit('/').next().unwrap_or(&path).to_string();
+        if let Some((_, sig)) = stdlib_members(&module).and_then(|ms| ms.into_iter().find(|(n, _)| *n == name)) {
+            return Some((sig.to_string(), Some(path)));
+        }
+        let table = fard_v0_5_language_gate::builtin_sig_table_v1::builtin_sig_table_v1();
+        let sig = table.get(format!("{}::{}", path, name).as_str())?;
+        let params: Vec<String> = (0..sig.arity_min)
+            .map(|i| if i == 0 && sig.value_first { "value".to_string() } else { format!("arg{}", i) })
+            .collect();
+        return Some((format!("{}({})", name, params.join(", ")), Some(path)));
+    }
+    analysis::local_fn_labels(code).into_iter().find(|(n, _)| n == name).map(|(_, l)| (l, None))
+}
+
+/// `.fard` files under the workspace roots, skipping build output and VCS dirs.
+fn workspace_files(roots: &[PathBuf]) -> Vec<PathBuf> {
+    fn walk(dir: &std::path::Path, depth: usize, out: &mut Vec<PathBuf>) {
+        if depth > 8 { return; }
+        let Ok(rd) = std::fs::read_dir(dir) else { return };
+        for e in rd.flatten() {
+            let p = e.path();
+            let name = e.file_name().to_string_lossy().to_string();
+            if p.is_dir() {
+                if !name.starts_with('.') && !matches!(name.as_str(), "target" | "node_modules" | "out") {
+                    walk(&p, depth + 1, out);
+                }
+            } else if name.ends_with(".fard") {
+                out.push(p);
+            }
         }
-    }).collect();
-    client.publish_diagnostics(uri, diags, None).await;
+    }
+    let mut out = Vec::new();
+    for r in roots {
+        walk(r, 0, &mut out);
+    }
+    out.sort();
+    out
 }
 
 fn stdlib_members(module: &str) -> Option<Vec<(&'static str, &'static str)>> {
@@ -350,7 +459,7 @@ const STDLIB_MODULES: &[&str] = &[
     "std/compress", "std/crypto", "std/graph", "std/type", "std/witness",
     "std/ffi", "std/process", "std/env", "std/net", "std/trace", "std/result",
     "std/option", "std/rec", "std/record", "std/linalg", "std/cell",
-    "std/grow", "std/flow", "std/bits", "std/cast",
+    "std/grow", "std/flow", "std/bits", "std/cast", "std/sqlite", "std/png",
 ];
 
 const KEYWORDS: &[&str] = &[
@@ -449,7 +558,17 @@ fn make_completion_item(label: &str, detail: &str, kind: CompletionItemKind) ->
 
 #[tower_lsp::async_trait]
 impl LanguageServer for FardLsp {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let mut roots: Vec<PathBuf> = params.workspace_folders.unwrap_or_default()
+            .into_iter()
+            .filter_map(|f| f.uri.to_file_path().ok())
+            .collect();
+        if roots.is_empty() {
+            if let Some(p) = params.root_uri.and_then(|u| u.to_file_path().ok()) {
+                roots.push(p);
+            }
+        }
+        *self.roots.write().await = roots;
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
@@ -462,6 +581,27 @@ impl LanguageServer for FardLsp {
                 }),
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                        legend: analysis::legend(),
+                        full: Some(SemanticTokensFullOptions::Bool(true)),
+                        range: None,
+                        work_done_progress_options: Default::default(),
+                    }),
+                ),
                 ..Default::default()
             },
             server_info: Some(ServerInfo {
@@ -479,16 +619,26 @@ impl LanguageServer for FardLsp {
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri;
-        let text = params.text_document.text;
+        let (text, version) = (params.text_document.text, params.text_document.version);
         self.docs.write().await.insert(uri.to_string(), text.clone());
-        publish(&self.client, uri, &text).await;
+        self.versions.write().await.insert(uri.to_string(), version);
+        publish(&self.client, &self.versions, uri, &text, version).await;
     }
 
+    /// Checks the new text once typing pauses; a later change supersedes this one.
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
+        let version = params.text_document.version;
         if let Some(change) = params.content_changes.into_iter().last() {
             self.docs.write().await.insert(uri.to_string(), change.text.clone());
-            publish(&self.client, uri, &change.text).await;
+            self.versions.write().await.insert(uri.to_string(), version);
+            let (client, versions) = (self.client.clone(), self.versions.clone());
+            tokio::spawn(async move {
+                tokio::time::sleep(CHANGE_DEBOUNCE).await;
+                if versions.read().await.get(uri.as_str()) == Some(&version) {
+                    publish(&client, &versions, uri, &change.text, version).await;
+                }
+            });
         }
     }
 
@@ -496,7 +646,8 @@ impl LanguageServer for FardLsp {
         let uri = params.text_document.uri;
         if let Some(text) = params.text {
             self.docs.write().await.insert(uri.to_string(), text.clone());
-            publish(&self.client, uri, &text).await;
+            let version = self.versions.read().await.get(uri.as_str()).copied().unwrap_or(0);
+            publish(&self.client, &self.versions, uri, &text, version).await;
         }
     }
 
@@ -631,6 +782,120 @@ impl LanguageServer for FardLsp {
         Ok(Some(locs))
     }
 
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let Some(text) = self.text(&params.text_document.uri).await else { return Ok(None) };
+        let code = analysis::Code::new(&lexer::lex(&text));
+        let pos = params.position;
+        Ok(analysis::prepare_rename(&code, pos.line, pos.character).map(PrepareRenameResponse::Range))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
+        let Some(text) = self.text(&uri).await else { return Ok(None) };
+        let code = analysis::Code::new(&lexer::lex(&text));
+        let edits = analysis::rename(&code, pos.line, pos.character, &params.new_name)
+            .map_err(Error::invalid_params)?;
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, edits)])),
+            ..Default::default()
+        }))
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let Some(text) = self.text(&params.text_document.uri).await else { return Ok(None) };
+        let src = text.clone();
+        let formatted = tokio::task::spawn_blocking(move || fard_v0_5_language_gate::format_source(&src))
+            .await
+            .ok()
+            .flatten();
+        let Some(formatted) = formatted else { return Ok(None) };
+        if formatted == text {
+            return Ok(Some(vec![]));
+        }
+        let last = text.split('\n').count() as u32 - 1;
+        let end = Position { line: last, character: utf16_col(&text, last, u32::MAX) };
+        Ok(Some(vec![TextEdit {
+            range: Range { start: Position { line: 0, character: 0 }, end },
+            new_text: formatted,
+        }]))
+    }
+
+    async fn range_formatting(&self, params: DocumentRangeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let Some(text) = self.text(&params.text_document.uri).await else { return Ok(None) };
+        let Range { start, end } = params.range;
+        // a selection ending at column 0 does not include that line
+        let end_line = if end.character == 0 && end.line > start.line { end.line - 1 } else { end.line };
+        let src = text.clone();
+        let formatted = tokio::task::spawn_blocking(move || {
+            fard_v0_5_language_gate::format_range_source(&src, start.line, end_line)
+        })
+        .await
+        .ok()
+        .flatten();
+        Ok(formatted.map(|f| line_diff_edits(&text, &f)))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        let Some(text) = self.text(&uri).await else { return Ok(None) };
+        let code = analysis::Code::new(&lexer::lex(&text));
+        let Some((alias, name, active)) = analysis::call_at(&code, pos.line, pos.character) else {
+            return Ok(None);
+        };
+        Ok(signature_label(&code, alias.as_deref(), &name)
+            .map(|(label, doc)| analysis::signature_help(&label, doc, active)))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let Some(text) = self.text(&params.text_document.uri).await else { return Ok(None) };
+        let code = analysis::Code::new(&lexer::lex(&text));
+        Ok(Some(DocumentSymbolResponse::Nested(analysis::document_symbols(&code))))
+    }
+
+    async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+        // Open buffers win over their on-disk copies
+        let mut sources: Vec<(Url, String)> = self.docs.read().await.iter()
+            .filter_map(|(u, t)| Url::parse(u).ok().map(|u| (u, t.clone())))
+            .collect();
+        let roots = self.roots.read().await.clone();
+        for path in workspace_files(&roots) {
+            let Ok(uri) = Url::from_file_path(&path) else { continue };
+            if sources.iter().any(|(u, _)| *u == uri) { continue; }
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                sources.push((uri, text));
+            }
+        }
+        sources.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        let mut out = Vec::new();
+        for (uri, text) in &sources {
+            let code = analysis::Code::new(&lexer::lex(text));
+            out.extend(analysis::workspace_symbols(&code, uri, &params.query));
+        }
+        Ok(Some(out))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let Some(text) = self.text(&params.text_document.uri).await else { return Ok(None) };
+        let data = analysis::semantic_tokens(&text, &lexer::lex(&text));
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens { result_id: None, data })))
+    }
+}
+
+impl FardLsp {
+    async fn text(&self, uri: &Url) -> Option<String> {
+        self.docs.read().await.get(uri.as_str()).cloned()
+    }
 }
 
 
@@ -752,6 +1017,8 @@ async fn main() {
     let (service, socket) = LspService::new(|client| FardLsp {
         client,
         docs: Arc::new(RwLock::new(HashMap::new())),
+        versions: Arc::new(RwLock::new(HashMap::new())),
+        roots: Arc::new(RwLock::new(Vec::new())),
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }
@@ -823,3 +1090,71 @@ let result = compute(10)";
         }));
     }
 }
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use super::*;
+    use futures::StreamExt;
+    use tower::{Service, ServiceExt};
+    use tower_lsp::jsonrpc::Request;
+    use tower_lsp::ClientSocket;
+
+    async fn send(service: &mut LspService<FardLsp>, req: Request) {
+        service.ready().await.unwrap().call(req).await.unwrap();
+    }
+
+    fn did_change(uri: &str, version: i32, text: &str) -> Request {
+        Request::build("textDocument/didChange")
+            .params(serde_json::json!({
+                "textDocument": {"uri": uri, "version": version},
+                "contentChanges": [{"text": text}],
+            }))
+            .finish()
+    }
+
+    /// The next `publishDiagnostics` the server sends, as (version, diagnostic count).
+    async fn next_diagnostics(socket: &mut ClientSocket) -> (Option<i64>, usize) {
+        let next = async {
+            loop {
+                let req = socket.next().await.expect("client socket closed");
+                if req.method() == "textDocument/publishDiagnostics" {
+                    let p = req.params().unwrap();
+                    return (p["version"].as_i64(), p["diagnostics"].as_array().unwrap().len());
+                }
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), next).await.expect("no diagnostics")
+    }
+
+    #[tokio::test]
+    async fn changes_are_debounced_and_diagnostics_carry_their_version() {
+        let (mut service, mut socket) = LspService::new(|client| FardLsp {
+            client,
+            docs: Default::default(),
+            versions: Default::default(),
+            roots: Default::default(),
+        });
+        send(&mut service, Request::build("initialize").params(serde_json::json!({"capabilities": {}})).id(1).finish()).await;
+        send(&mut service, Request::build("initialized").params(serde_json::json!({})).finish()).await;
+        let uri = "file:///tmp/fard_lsp_debounce.fard";
+        let open = Request::build("textDocument/didOpen")
+            .params(serde_json::json!({
+                "textDocument": {"uri": uri, "languageId": "fard", "version": 1, "text": "let x = 1\nx\n"},
+            }))
+            .finish();
+        // `didOpen` publishes before it returns, so the socket has to be read concurrently.
+        let ((), first) = tokio::join!(send(&mut service, open), next_diagnostics(&mut socket));
+        assert_eq!(first, (Some(1), 0));
+
+        // A burst of edits is checked once, at its last version.
+        send(&mut service, did_change(uri, 2, "let x = \n")).await;
+        send(&mut service, did_change(uri, 3, "let x = (\n")).await;
+        send(&mut service, did_change(uri, 4, "let y = 2\ny + \n")).await;
+        let (version, count) = next_diagnostics(&mut socket).await;
+        assert_eq!(version, Some(4));
+        assert!(count > 0);
+
+        send(&mut service, did_change(uri, 5, "let y = 2\ny\n")).await;
+        assert_eq!(next_diagnostics(&mut socket).await, (Some(5), 0));
+    }
+}
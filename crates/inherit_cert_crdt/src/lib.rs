@@ -20,7 +20,8 @@
 //! 3. Associative:  merge(merge(a,b), c) = merge(a, merge(b,c))
 //! 4. Monotone:     a <= merge(a, b)  (where <= is the natural partial order)
 //!
-//! All four laws are verified by property tests in this module.
+//! All four laws are verified by property tests in this module, for whole
+//! states and for deltas.
 //!
 //! ## Convergence guarantee
 //!
@@ -28,10 +29,31 @@
 //! and proposed RunIDs, after one round of merge they hold identical state.
 //! The canonical RunID for each effect is the lexicographic minimum over all
 //! proposals — a deterministic, replica-independent choice.
+//!
+//! ## Delta-state replication
+//!
+//! Replicas need not ship whole states. `propose` returns the delta it caused,
+//! deltas join like states do, and applying a delta twice or out of order
+//! changes nothing — so a replica records its deltas in a [`DeltaLog`], sends
+//! each peer the join of what it has not acknowledged, and forgets a delta once
+//! every peer has.
+//!
+//! ## Persistence
+//!
+//! [`CertStore`] keeps a state durably and is written through deltas, so large
+//! states are never rewritten whole: [`MemoryCertStore`] holds it in memory and
+//! `SqliteCertStore` (feature `sqlite`) in a `crdt_certs` table. Every store also
+//! keeps the delta log of what changed it and each peer's acknowledgement, so a
+//! replica that restarts still sends its peers only what they have not seen.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteCertStore;
+
 // ── Effect key ────────────────────────────────────────────────────────────────
 
 /// The canonical key for an effect: SHA-256 of the effect's canonical encoding.
@@ -137,11 +159,22 @@ impl InheritCertState {
     /// Propose a RunID for an effect key.
     /// If the effect is new, records the proposal.
     /// If the effect exists, keeps the minimum.
-    pub fn propose(&mut self, key: EffectKey, run_id: RunID) {
-        let entry = self.certs.entry(key).or_insert_with(|| MinRegister::new(run_id.clone()));
-        if run_id < entry.value {
-            entry.value = run_id;
+    /// Returns the delta this caused — empty when the state already had an
+    /// equal or smaller RunID.
+    pub fn propose(&mut self, key: EffectKey, run_id: RunID) -> InheritCertDelta {
+        let mut delta = InheritCertDelta::new();
+        match self.certs.get_mut(&key) {
+            Some(entry) if entry.value <= run_id => {}
+            Some(entry) => {
+                entry.value = run_id.clone();
+                delta.updates.insert(key, run_id);
+            }
+            None => {
+                self.certs.insert(key.clone(), MinRegister::new(run_id.clone()));
+                delta.updates.insert(key, run_id);
+            }
         }
+        delta
     }
 
     /// Merge another state into this one (pointwise minimum).
@@ -241,7 +274,8 @@ impl InheritCertState {
 
 /// A delta: a minimal state update that can be sent between replicas.
 /// Contains only the keys that changed relative to a known state.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Deltas form the same semilattice as states: join is pointwise minimum.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct InheritCertDelta {
     pub updates: BTreeMap<EffectKey, RunID>,
 }
@@ -249,6 +283,18 @@ pub struct InheritCertDelta {
 impl InheritCertDelta {
     pub fn new() -> Self { InheritCertDelta { updates: BTreeMap::new() } }
 
+    /// A delta proposing one RunID.
+    pub fn single(key: EffectKey, run_id: RunID) -> Self {
+        InheritCertDelta { updates: BTreeMap::from([(key, run_id)]) }
+    }
+
+    /// A whole state, as a delta from the empty state.
+    pub fn from_state(state: &InheritCertState) -> Self {
+        InheritCertDelta {
+            updates: state.certs.iter().map(|(k, r)| (k.clone(), r.value.clone())).collect(),
+        }
+    }
+
     /// Compute the delta needed to bring `other` up to `self`.
     /// Returns updates that are strictly smaller in self than in other.
     pub fn compute(from: &InheritCertState, to: &InheritCertState) -> Self {
@@ -267,20 +313,222 @@ impl InheritCertDelta {
     }
 
     /// Apply a delta to a state.
-    pub fn apply_to(&self, state: &mut InheritCertState) {
+    /// Returns the part of the delta that changed the state.
+    pub fn apply_to(&self, state: &mut InheritCertState) -> InheritCertDelta {
+        let mut applied = Self::new();
         for (key, run_id) in &self.updates {
-            state.propose(key.clone(), run_id.clone());
+            applied.merge_into(&state.propose(key.clone(), run_id.clone()));
+        }
+        applied
+    }
+
+    /// Join two deltas (pointwise minimum).
+    pub fn merge(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        result.merge_into(other);
+        result
+    }
+
+    /// Join in place.
+    pub fn merge_into(&mut self, other: &Self) {
+        for (key, run_id) in &other.updates {
+            match self.updates.get_mut(key) {
+                Some(existing) if *existing <= *run_id => {}
+                Some(existing) => *existing = run_id.clone(),
+                None => {
+                    self.updates.insert(key.clone(), run_id.clone());
+                }
+            }
         }
     }
 
     pub fn is_empty(&self) -> bool { self.updates.is_empty() }
     pub fn len(&self) -> usize { self.updates.len() }
+
+    /// Serialize to JSON wire format.
+    pub fn to_json(&self) -> serde_json::Value {
+        let updates: serde_json::Map<String, serde_json::Value> = self.updates.iter()
+            .map(|(k, r)| (k.0.clone(), serde_json::Value::String(r.0.clone())))
+            .collect();
+        serde_json::json!({
+            "kind": "fard/inherit_cert_delta/v0.1",
+            "updates": updates,
+        })
+    }
+
+    /// Deserialize from JSON wire format.
+    pub fn from_json(v: &serde_json::Value) -> Result<Self, String> {
+        let updates_obj = v.get("updates")
+            .and_then(|v| v.as_object())
+            .ok_or("missing updates object")?;
+        let mut delta = Self::new();
+        for (k, v) in updates_obj {
+            let run_id_str = v.as_str().ok_or("update value must be string")?;
+            let run_id = RunID(run_id_str.to_string());
+            if !run_id.is_valid() {
+                return Err(format!("invalid RunID: {}", run_id_str));
+            }
+            delta.updates.insert(EffectKey(k.clone()), run_id);
+        }
+        Ok(delta)
+    }
 }
 
 impl Default for InheritCertDelta {
     fn default() -> Self { Self::new() }
 }
 
+// ── Delta log ─────────────────────────────────────────────────────────────────
+
+/// Deltas produced by one replica, numbered from 1, with the highest number
+/// each peer has acknowledged.
+///
+/// A peer is sent the join of every delta after its acknowledgement; a lost or
+/// repeated send is harmless because applying a delta is idempotent. Deltas
+/// every peer has acknowledged are dropped. A peer the log has no record of —
+/// or one behind what was dropped — must first be sent the full state, then
+/// acknowledged at the `head()` observed when that state was read.
+#[derive(Clone, Debug, Default)]
+pub struct DeltaLog {
+    /// Number of the last dropped delta.
+    base: u64,
+    deltas: VecDeque<InheritCertDelta>,
+    acks: BTreeMap<String, u64>,
+}
+
+impl DeltaLog {
+    pub fn new() -> Self { Self::default() }
+
+    /// Number of the newest delta recorded.
+    pub fn head(&self) -> u64 { self.base + self.deltas.len() as u64 }
+
+    /// Record a local delta and return its number. Empty deltas are not recorded.
+    pub fn push(&mut self, delta: InheritCertDelta) -> u64 {
+        if !delta.is_empty() {
+            self.deltas.push_back(delta);
+            self.compact();
+        }
+        self.head()
+    }
+
+    /// What `peer` has not acknowledged, as `(head, delta)`; acknowledge `head`
+    /// once the peer has applied it. `None` when the peer needs the full state.
+    pub fn pending(&self, peer: &str) -> Option<(u64, InheritCertDelta)> {
+        let acked = *self.acks.get(peer)?;
+        if acked < self.base {
+            return None;
+        }
+        let mut delta = InheritCertDelta::new();
+        for d in self.deltas.iter().skip((acked - self.base) as usize) {
+            delta.merge_into(d);
+        }
+        Some((self.head(), delta))
+    }
+
+    /// Record that `peer` has applied every delta up to `upto`.
+    /// Acknowledgements never move backwards.
+    pub fn ack(&mut self, peer: &str, upto: u64) {
+        let upto = upto.min(self.head());
+        let acked = self.acks.entry(peer.to_string()).or_insert(upto);
+        *acked = (*acked).max(upto);
+        self.compact();
+    }
+
+    /// Stop tracking `peer`; its unacknowledged deltas no longer hold the log back.
+    pub fn remove_peer(&mut self, peer: &str) {
+        self.acks.remove(peer);
+        self.compact();
+    }
+
+    /// Highest number `peer` has acknowledged.
+    pub fn acked(&self, peer: &str) -> Option<u64> { self.acks.get(peer).copied() }
+
+    /// Number of deltas still retained.
+    pub fn len(&self) -> usize { self.deltas.len() }
+    pub fn is_empty(&self) -> bool { self.deltas.is_empty() }
+
+    fn compact(&mut self) {
+        let floor = self.acks.values().copied().min().unwrap_or(self.head());
+        while self.base < floor && self.deltas.pop_front().is_some() {
+            self.base += 1;
+        }
+    }
+}
+
+// ── Persistence ───────────────────────────────────────────────────────────────
+
+/// Durable storage for an `InheritCertState`, written through deltas, and the
+/// [`DeltaLog`] that replicates it.
+pub trait CertStore {
+    /// The canonical RunID for one effect.
+    fn get(&self, key: &EffectKey) -> Result<Option<RunID>, String>;
+
+    /// Merge `delta` atomically. Returns the part of it that changed the store,
+    /// which is also recorded in the delta log.
+    fn apply(&mut self, delta: &InheritCertDelta) -> Result<InheritCertDelta, String>;
+
+    /// What `peer` has not acknowledged, as `(head, delta)`: the join of the logged
+    /// deltas after its acknowledgement, or the whole state for a peer the log
+    /// cannot serve. Acknowledge `head` once the peer has applied it.
+    fn pending(&self, peer: &str) -> Result<(u64, InheritCertDelta), String>;
+
+    /// Record that `peer` has applied every delta up to `upto`.
+    fn ack(&mut self, peer: &str, upto: u64) -> Result<(), String>;
+
+    /// The whole state.
+    fn load(&self) -> Result<InheritCertState, String>;
+
+    /// Number of effects stored.
+    fn len(&self) -> Result<usize, String>;
+
+    fn is_empty(&self) -> Result<bool, String> { Ok(self.len()? == 0) }
+
+    /// Propose one RunID; returns the delta it caused.
+    fn propose(&mut self, key: EffectKey, run_id: RunID) -> Result<InheritCertDelta, String> {
+        self.apply(&InheritCertDelta::single(key, run_id))
+    }
+}
+
+/// A `CertStore` that lives only as long as the process.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryCertStore {
+    state: InheritCertState,
+    log: DeltaLog,
+}
+
+impl MemoryCertStore {
+    pub fn new() -> Self { Self::default() }
+}
+
+impl CertStore for MemoryCertStore {
+    fn get(&self, key: &EffectKey) -> Result<Option<RunID>, String> {
+        Ok(self.state.get(key).cloned())
+    }
+
+    fn apply(&mut self, delta: &InheritCertDelta) -> Result<InheritCertDelta, String> {
+        let applied = delta.apply_to(&mut self.state);
+        self.log.push(applied.clone());
+        Ok(applied)
+    }
+
+    fn pending(&self, peer: &str) -> Result<(u64, InheritCertDelta), String> {
+        Ok(self.log.pending(peer).unwrap_or_else(|| (self.log.head(), InheritCertDelta::from_state(&self.state))))
+    }
+
+    fn ack(&mut self, peer: &str, upto: u64) -> Result<(), String> {
+        self.log.ack(peer, upto);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<InheritCertState, String> {
+        Ok(self.state.clone())
+    }
+
+    fn len(&self) -> Result<usize, String> {
+        Ok(self.state.len())
+    }
+}
+
 // ── Semilattice law verification ──────────────────────────────────────────────
 
 /// Check all four semilattice laws for a given set of states.
@@ -449,4 +697,233 @@ mod tests {
         assert!(r_low < r_mid);
         assert!(r_mid < r_high);
     }
+
+    #[test]
+    fn test_propose_returns_delta() {
+        let mut state = InheritCertState::new();
+        let key = make_key("e1");
+        assert_eq!(state.propose(key.clone(), make_run(5)), InheritCertDelta::single(key.clone(), make_run(5)));
+        assert!(state.propose(key.clone(), make_run(9)).is_empty());
+        assert!(state.propose(key.clone(), make_run(5)).is_empty());
+        assert_eq!(state.propose(key.clone(), make_run(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_delta_json_roundtrip() {
+        let mut delta = InheritCertDelta::single(make_key("e1"), make_run(1));
+        delta.merge_into(&InheritCertDelta::single(make_key("e2"), make_run(2)));
+        let json = delta.to_json();
+        assert_eq!(json["kind"], "fard/inherit_cert_delta/v0.1");
+        assert_eq!(InheritCertDelta::from_json(&json).expect("roundtrip"), delta);
+    }
+
+    #[test]
+    fn test_delta_log_acks_per_peer() {
+        let mut log = DeltaLog::new();
+        let mut replica = InheritCertState::new();
+        // Peers bootstrapped from the (empty) full state.
+        log.ack("b", 0);
+        log.ack("c", 0);
+        log.push(replica.propose(make_key("e1"), make_run(5)));
+        log.push(replica.propose(make_key("e2"), make_run(3)));
+        assert_eq!(log.push(replica.propose(make_key("e1"), make_run(9))), 2); // no change, not logged
+
+        let (upto, delta) = log.pending("b").expect("b is tracked");
+        assert_eq!(upto, 2);
+        assert_eq!(delta, InheritCertDelta::from_state(&replica));
+        log.ack("b", upto);
+        assert!(log.pending("b").unwrap().1.is_empty());
+        assert_eq!(log.len(), 2); // c still needs both
+
+        log.push(replica.propose(make_key("e1"), make_run(1)));
+        assert_eq!(log.pending("b").unwrap().1, InheritCertDelta::single(make_key("e1"), make_run(1)));
+        assert_eq!(log.pending("c").unwrap().1.len(), 2);
+        log.ack("c", 3);
+        assert_eq!(log.len(), 1);
+        log.ack("b", 1); // stale acks do not move backwards
+        assert_eq!(log.acked("b"), Some(2));
+        log.remove_peer("b");
+        assert!(log.is_empty());
+
+        // Unknown peers, and peers behind what was dropped, need the full state.
+        assert!(log.pending("d").is_none());
+        log.ack("d", 1);
+        assert!(log.pending("d").is_none());
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryCertStore::new();
+        assert!(store.is_empty().unwrap());
+        assert_eq!(store.propose(make_key("e1"), make_run(4)).unwrap().len(), 1);
+        assert!(store.propose(make_key("e1"), make_run(6)).unwrap().is_empty());
+        assert_eq!(store.get(&make_key("e1")).unwrap(), Some(make_run(4)));
+        assert_eq!(store.len().unwrap(), 1);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store_persists() {
+        let path = std::env::temp_dir().join(format!("inherit_cert_{}_{:?}.db", std::process::id(), std::thread::current().id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut store = SqliteCertStore::open(&path).unwrap();
+            store.ack("b", 0).unwrap();
+            let mut delta = InheritCertDelta::single(make_key("e1"), make_run(7));
+            delta.merge_into(&InheritCertDelta::single(make_key("e2"), make_run(2)));
+            assert_eq!(store.apply(&delta).unwrap(), delta);
+            store.ack("b", 1).unwrap();
+            let applied = store.propose(make_key("e1"), make_run(3)).unwrap();
+            assert_eq!(applied, InheritCertDelta::single(make_key("e1"), make_run(3)));
+        }
+        let store = SqliteCertStore::open(&path).unwrap();
+        // The delta log and acknowledgements survive too.
+        assert_eq!(store.pending("b").unwrap(), (2, InheritCertDelta::single(make_key("e1"), make_run(3))));
+        assert_eq!(store.pending("c").unwrap().1.len(), 2);
+        assert_eq!(store.len().unwrap(), 2);
+        assert_eq!(store.get(&make_key("e1")).unwrap(), Some(make_run(3)));
+        assert_eq!(store.get(&make_key("e9")).unwrap(), None);
+        let mut expected = InheritCertState::new();
+        expected.propose(make_key("e1"), make_run(3));
+        expected.propose(make_key("e2"), make_run(2));
+        assert_eq!(store.load().unwrap(), expected);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // ── Property tests ────────────────────────────────────────────────────────
+
+    use proptest::prelude::*;
+
+    fn arb_proposals() -> impl Strategy<Value = Vec<(u8, u8)>> {
+        prop::collection::vec((0u8..8, any::<u8>()), 0..16)
+    }
+
+    fn arb_state() -> impl Strategy<Value = InheritCertState> {
+        arb_proposals().prop_map(|props| {
+            let mut s = InheritCertState::new();
+            for (k, r) in props {
+                s.propose(make_key(&format!("e{}", k)), make_run(r));
+            }
+            s
+        })
+    }
+
+    fn arb_delta() -> impl Strategy<Value = InheritCertDelta> {
+        arb_state().prop_map(|s| InheritCertDelta::from_state(&s))
+    }
+
+    /// Proposals, and an order delivering each one's delta at least once, some twice.
+    fn arb_delivery() -> impl Strategy<Value = (Vec<(u8, u8)>, Vec<usize>)> {
+        arb_proposals().prop_flat_map(|props| {
+            let n = props.len();
+            let order = prop::collection::vec(0..n.max(1), 0..=n).prop_flat_map(move |dups| {
+                let mut all: Vec<usize> = (0..n).collect();
+                all.extend(dups.into_iter().filter(|_| n > 0));
+                Just(all).prop_shuffle()
+            });
+            (Just(props), order)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_semilattice_laws(a in arb_state(), b in arb_state(), c in arb_state()) {
+            prop_assert_eq!(verify_semilattice_laws(&a, &b, &c), Ok(()));
+        }
+
+        #[test]
+        fn prop_delta_join_is_a_semilattice(a in arb_delta(), b in arb_delta(), c in arb_delta()) {
+            prop_assert_eq!(a.merge(&a), a.clone());
+            prop_assert_eq!(a.merge(&b), b.merge(&a));
+            prop_assert_eq!(a.merge(&b).merge(&c), a.merge(&b.merge(&c)));
+        }
+
+        #[test]
+        fn prop_delta_merge_matches_state_merge(a in arb_state(), b in arb_state()) {
+            let mut via_delta = a.clone();
+            InheritCertDelta::compute(&a, &b).apply_to(&mut via_delta);
+            prop_assert_eq!(&via_delta, &a.merge(&b));
+            let mut via_full = a.clone();
+            InheritCertDelta::from_state(&b).apply_to(&mut via_full);
+            prop_assert_eq!(via_full, via_delta);
+        }
+
+        #[test]
+        fn prop_deltas_converge_under_duplicate_and_reordered_delivery((props, order) in arb_delivery()) {
+            let mut source = InheritCertState::new();
+            let deltas: Vec<InheritCertDelta> = props.iter()
+                .map(|(k, r)| source.propose(make_key(&format!("e{}", k)), make_run(*r)))
+                .collect();
+
+            let mut one_by_one = InheritCertState::new();
+            for i in &order {
+                deltas[*i].apply_to(&mut one_by_one);
+            }
+            prop_assert_eq!(&one_by_one, &source);
+
+            // Joining deltas before shipping them gives the same result.
+            let mut batched = InheritCertState::new();
+            for chunk in order.chunks(3) {
+                let joined = chunk.iter().fold(InheritCertDelta::new(), |acc, i| acc.merge(&deltas[*i]));
+                joined.apply_to(&mut batched);
+                joined.apply_to(&mut batched);
+            }
+            prop_assert_eq!(&batched, &source);
+        }
+
+        #[test]
+        fn prop_delta_log_converges_despite_lost_sends(props in arb_proposals(), delivered in prop::collection::vec(any::<bool>(), 16)) {
+            let (mut a, mut b) = (InheritCertState::new(), InheritCertState::new());
+            let mut log = DeltaLog::new();
+            log.ack("b", 0);
+            for (i, (k, r)) in props.iter().enumerate() {
+                log.push(a.propose(make_key(&format!("e{}", k)), make_run(*r)));
+                let (upto, delta) = log.pending("b").unwrap();
+                if delivered[i] {
+                    delta.apply_to(&mut b);
+                    log.ack("b", upto);
+                }
+            }
+            let (upto, delta) = log.pending("b").unwrap();
+            delta.apply_to(&mut b);
+            log.ack("b", upto);
+            prop_assert_eq!(&b, &a);
+            prop_assert!(log.is_empty());
+        }
+
+        #[test]
+        fn prop_stores_agree(ops in prop::collection::vec((arb_delta(), any::<bool>()), 0..6)) {
+            let mut expected = InheritCertState::new();
+            let mut memory = MemoryCertStore::new();
+            #[cfg(feature = "sqlite")]
+            let mut sqlite = SqliteCertStore::open_in_memory().unwrap();
+            // "b" is sent deltas and acknowledges some; "c" is never heard from.
+            let mut b = InheritCertState::new();
+            for (d, acked) in &ops {
+                let applied = d.apply_to(&mut expected);
+                prop_assert_eq!(&memory.apply(d).unwrap(), &applied);
+                #[cfg(feature = "sqlite")]
+                prop_assert_eq!(&sqlite.apply(d).unwrap(), &applied);
+                let (upto, pending) = memory.pending("b").unwrap();
+                #[cfg(feature = "sqlite")]
+                prop_assert_eq!(&sqlite.pending("b").unwrap(), &(upto, pending.clone()));
+                if *acked {
+                    pending.apply_to(&mut b);
+                    memory.ack("b", upto).unwrap();
+                    #[cfg(feature = "sqlite")]
+                    sqlite.ack("b", upto).unwrap();
+                }
+            }
+            memory.pending("b").unwrap().1.apply_to(&mut b);
+            prop_assert_eq!(&b, &expected);
+            prop_assert_eq!(&memory.pending("c").unwrap().1, &InheritCertDelta::from_state(&expected));
+            prop_assert_eq!(&memory.load().unwrap(), &expected);
+            #[cfg(feature = "sqlite")]
+            {
+                prop_assert_eq!(&sqlite.pending("b").unwrap(), &memory.pending("b").unwrap());
+                prop_assert_eq!(&sqlite.pending("c").unwrap(), &memory.pending("c").unwrap());
+                prop_assert_eq!(&sqlite.load().unwrap(), &expected);
+            }
+        }
+    }
 }
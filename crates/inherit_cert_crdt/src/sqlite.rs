@@ -0,0 +1,167 @@
+//! `CertStore` backed by a SQLite table.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{CertStore, EffectKey, InheritCertDelta, InheritCertState, MinRegister, RunID};
+
+/// One row per effect, plus the delta log: the changes not yet acknowledged by every
+/// peer, numbered by `seq`, and each peer's acknowledgement. Registries share these
+/// tables with their receipts database.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS crdt_certs (
+        effect_key TEXT PRIMARY KEY,
+        run_id     TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS crdt_deltas (
+        seq   INTEGER PRIMARY KEY AUTOINCREMENT,
+        delta TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS crdt_acks (
+        peer TEXT PRIMARY KEY,
+        upto INTEGER NOT NULL
+    );
+";
+
+/// A `CertStore` in the `crdt_certs` table of a SQLite database, with its delta log in
+/// `crdt_deltas` and `crdt_acks`. Each `apply` and `ack` is one transaction.
+pub struct SqliteCertStore {
+    conn: Connection,
+}
+
+fn sql_err(e: rusqlite::Error) -> String {
+    format!("crdt_certs: {}", e)
+}
+
+impl SqliteCertStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Self::from_connection(Connection::open(path).map_err(sql_err)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        Self::from_connection(Connection::open_in_memory().map_err(sql_err)?)
+    }
+
+    /// Use `conn`, creating the table if needed.
+    pub fn from_connection(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch(SCHEMA).map_err(sql_err)?;
+        Ok(SqliteCertStore { conn })
+    }
+}
+
+/// Number of the newest delta ever logged; AUTOINCREMENT keeps it after compaction.
+fn head(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row("SELECT COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'crdt_deltas'), 0)", [], |r| {
+        r.get::<_, i64>(0)
+    })
+    .map(|n| n as u64)
+}
+
+/// Drop the deltas every peer has acknowledged — all of them when no peer is tracked,
+/// as `DeltaLog` does.
+fn compact(conn: &Connection) -> rusqlite::Result<()> {
+    let floor = match conn.query_row("SELECT MIN(upto) FROM crdt_acks", [], |r| r.get::<_, Option<i64>>(0))? {
+        Some(floor) => floor,
+        None => head(conn)? as i64,
+    };
+    conn.execute("DELETE FROM crdt_deltas WHERE seq <= ?1", params![floor])?;
+    Ok(())
+}
+
+impl CertStore for SqliteCertStore {
+    fn get(&self, key: &EffectKey) -> Result<Option<RunID>, String> {
+        self.conn
+            .query_row("SELECT run_id FROM crdt_certs WHERE effect_key = ?1", params![key.as_str()], |r| r.get(0))
+            .optional()
+            .map(|v| v.map(RunID))
+            .map_err(sql_err)
+    }
+
+    fn apply(&mut self, delta: &InheritCertDelta) -> Result<InheritCertDelta, String> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        let mut applied = InheritCertDelta::new();
+        {
+            let mut current = tx.prepare("SELECT run_id FROM crdt_certs WHERE effect_key = ?1").map_err(sql_err)?;
+            let mut upsert = tx
+                .prepare(
+                    "INSERT INTO crdt_certs (effect_key, run_id) VALUES (?1, ?2)
+                     ON CONFLICT(effect_key) DO UPDATE SET run_id = excluded.run_id",
+                )
+                .map_err(sql_err)?;
+            for (key, run_id) in &delta.updates {
+                let existing: Option<String> =
+                    current.query_row(params![key.as_str()], |r| r.get(0)).optional().map_err(sql_err)?;
+                if existing.is_some_and(|e| e.as_str() <= run_id.as_str()) {
+                    continue;
+                }
+                upsert.execute(params![key.as_str(), run_id.as_str()]).map_err(sql_err)?;
+                applied.updates.insert(key.clone(), run_id.clone());
+            }
+        }
+        if !applied.is_empty() {
+            tx.execute("INSERT INTO crdt_deltas (delta) VALUES (?1)", params![applied.to_json().to_string()])
+                .map_err(sql_err)?;
+            compact(&tx).map_err(sql_err)?;
+        }
+        tx.commit().map_err(sql_err)?;
+        Ok(applied)
+    }
+
+    fn pending(&self, peer: &str) -> Result<(u64, InheritCertDelta), String> {
+        let tx = self.conn.unchecked_transaction().map_err(sql_err)?;
+        let head = head(&tx).map_err(sql_err)?;
+        let acked: Option<i64> = tx
+            .query_row("SELECT upto FROM crdt_acks WHERE peer = ?1", params![peer], |r| r.get(0))
+            .optional()
+            .map_err(sql_err)?;
+        let base: i64 = tx
+            .query_row("SELECT COALESCE(MIN(seq) - 1, ?1) FROM crdt_deltas", params![head as i64], |r| r.get(0))
+            .map_err(sql_err)?;
+        let acked = match acked {
+            Some(acked) if acked >= base => acked,
+            _ => return Ok((head, InheritCertDelta::from_state(&self.load()?))),
+        };
+        let mut stmt = tx.prepare("SELECT delta FROM crdt_deltas WHERE seq > ?1 ORDER BY seq").map_err(sql_err)?;
+        let rows = stmt.query_map(params![acked], |r| r.get::<_, String>(0)).map_err(sql_err)?;
+        let mut delta = InheritCertDelta::new();
+        for row in rows {
+            let json: serde_json::Value = serde_json::from_str(&row.map_err(sql_err)?).map_err(|e| format!("crdt_deltas: {}", e))?;
+            delta.merge_into(&InheritCertDelta::from_json(&json)?);
+        }
+        Ok((head, delta))
+    }
+
+    fn ack(&mut self, peer: &str, upto: u64) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        let upto = upto.min(head(&tx).map_err(sql_err)?);
+        tx.execute(
+            "INSERT INTO crdt_acks (peer, upto) VALUES (?1, ?2)
+             ON CONFLICT(peer) DO UPDATE SET upto = MAX(upto, excluded.upto)",
+            params![peer, upto as i64],
+        )
+        .map_err(sql_err)?;
+        compact(&tx).map_err(sql_err)?;
+        tx.commit().map_err(sql_err)
+    }
+
+    fn load(&self) -> Result<InheritCertState, String> {
+        let mut stmt = self.conn.prepare("SELECT effect_key, run_id FROM crdt_certs").map_err(sql_err)?;
+        let rows = stmt
+            .query_map([], |r| Ok((EffectKey(r.get(0)?), RunID(r.get(1)?))))
+            .map_err(sql_err)?;
+        let mut state = InheritCertState::new();
+        for row in rows {
+            let (key, run_id) = row.map_err(sql_err)?;
+            state.certs.insert(key, MinRegister::new(run_id));
+        }
+        Ok(state)
+    }
+
+    fn len(&self) -> Result<usize, String> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM crdt_certs", [], |r| r.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(sql_err)
+    }
+}
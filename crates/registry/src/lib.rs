@@ -106,15 +106,9 @@ pub fn crdt_propose(effect_key: EffectKey, run_id: RunID) -> Result<()> {
 /// Returns the number of updates applied.
 pub fn crdt_merge_delta(delta: &InheritCertDelta) -> Result<usize> {
     let mut state = crdt_load()?;
-    let before = state.len();
-    delta.apply_to(&mut state);
-    let after = state.len();
+    let applied = delta.apply_to(&mut state);
     crdt_save(&state)?;
-    Ok(after - before + delta.updates.values()
-        .filter(|run_id| state.get(&delta.updates.keys()
-            .find(|k| state.certs.get(*k).map(|r| &r.value) == Some(run_id))
-            .unwrap_or(&EffectKey("".to_string()))).is_some())
-        .count())
+    Ok(applied.len())
 }
 
 /// Merge another full state into the persistent CRDT state.
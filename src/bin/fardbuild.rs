@@ -1,60 +1,68 @@
 //! fardbuild — witnessed build pipeline runner for FARD
 //!
-//! Takes a build spec (FARD program), runs each step via fardrun,
-//! chains the receipts, and produces a build manifest that proves
-//! exactly what was built from what.
+//! The subcommand front end to the same build subsystem as `fard-build`:
+//! a build spec (FARD program) or a fard.build.toml is run step by step via
+//! fardrun, the receipts are chained, and a build manifest proves exactly
+//! what was built from what.
 //!
 //! USAGE:
-//!   fardbuild run   --spec <build.fard> --out <dir>
-//!   fardbuild check --spec <build.fard>
-//!   fardbuild show  --manifest <dir/build-manifest.json>
+//!   fardbuild run     (--spec <build.fard> | --config <fard.build.toml>) --out <dir>
+//!   fardbuild check   (--spec <build.fard> | --config <fard.build.toml>)
+//!   fardbuild show    --manifest <dir/build.receipt.json>
+//!   fardbuild migrate --manifest <old manifest> [--out <file>]
 
-use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::time::{SystemTime, UNIX_EPOCH};
 
-use valuecore::json::{JsonVal as J, from_slice, to_string, to_string_pretty};
-use valuecore::{hex_lower, sha256::Sha256};
+use fard_v0_5_language_gate::build::{self, BuildConfig, BuildOptions};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const HELP: &str = r#"fardbuild — witnessed build pipeline runner
 
 USAGE:
-  fardbuild run   --spec <build.fard> --out <dir> [--no-trace]
-  fardbuild check --spec <build.fard>
-  fardbuild show  --manifest <build-manifest.json>
+  fardbuild run     (--spec <build.fard> | --config <fard.build.toml>) --out <dir> [--no-trace] [-j N]
+  fardbuild check   (--spec <build.fard> | --config <fard.build.toml>)
+  fardbuild show    --manifest <build.receipt.json>
+  fardbuild migrate --manifest <old manifest> [--out <file>]
 
 DESCRIPTION:
   fardbuild run:
-    Executes the build spec as a FARD program via fardrun.
-    Each step in the pipeline produces a cryptographic receipt.
-    All receipts are chained into a build manifest.
+    Executes the build. A spec is a FARD program run as a single step with
+    its outputs in the out directory; a fard.build.toml runs its steps as a
+    DAG. Each step produces a cryptographic receipt and all receipts are
+    chained into the build manifest.
 
-    Output directory contains:
+    Output directory contains (spec form):
       result.json          — final build result
-      build-manifest.json  — cryptographic proof of the entire build
+      build.receipt.json   — build manifest: proof of the entire build
       digests.json         — standard fardrun digests
       trace.ndjson         — execution trace (unless --no-trace)
 
   fardbuild check:
-    Validates that the build spec is parseable without running it.
+    Validates that the build definition is parseable without running it.
 
   fardbuild show:
-    Pretty-prints a build manifest.
+    Pretty-prints a build manifest and checks it against the schema.
 
-MANIFEST FORMAT:
+  fardbuild migrate:
+    Converts a build-manifest.json from an older fardbuild, or a
+    build.receipt.json from an older fard-build, to the current schema.
+
+MANIFEST FORMAT (kind "fard/build_manifest/v1"):
   {
-    "fard_build_version": "1.0.0",
-    "spec":     "<sha256 of build spec>",
-    "run_id":   "<sha256 preimage digest>",
-    "ok":       true,
-    "steps":    [...],
-    "built_at": <unix timestamp>,
-    "duration_ms": <ms>
+    "kind":         "fard/build_manifest/v1",
+    "name":         "<build name>",
+    "version":      "<build version>",
+    "source":       { "form": "spec" | "toml", "path": ..., "digest": "sha256:..." },
+    "ok":           true,
+    "chain_digest": "sha256:...",
+    "steps":        [{ "name", "ok", "skipped", "run_digest", "prior_digest",
+                       "depends_on", "out", "duration_ms", "error", "key", "cache" }],
+    "built_at":     <unix timestamp>,
+    "total_duration_ms": <ms>,
+    "step_count", "passed", "failed", "skipped", "cache_hits"
   }
 "#;
 
@@ -65,235 +73,156 @@ fn main() {
         std::process::exit(2);
     }
 
-    match args[1].as_str() {
-        "run"   => {
-            if let Err(e) = cmd_run(&args[2..]) {
-                eprintln!("fardbuild: {}", e);
-                std::process::exit(1);
-            }
-        }
-        "check" => {
-            if let Err(e) = cmd_check(&args[2..]) {
-                eprintln!("fardbuild: {}", e);
-                std::process::exit(1);
-            }
-        }
-        "show"  => {
-            if let Err(e) = cmd_show(&args[2..]) {
-                eprintln!("fardbuild: {}", e);
-                std::process::exit(1);
-            }
-        }
+    let result = match args[1].as_str() {
+        "run"     => cmd_run(&args[2..]),
+        "check"   => cmd_check(&args[2..]),
+        "show"    => cmd_show(&args[2..]),
+        "migrate" => cmd_migrate(&args[2..]),
         "--help" | "-h" | "help" => {
             print!("{}", HELP);
+            Ok(())
         }
         "--version" | "-V" => {
             println!("fardbuild {}", VERSION);
+            Ok(())
         }
         other => {
             eprintln!("fardbuild: unknown subcommand: {}", other);
             eprintln!("Run 'fardbuild --help' for usage.");
             std::process::exit(2);
         }
+    };
+    if let Err(e) = result {
+        eprintln!("fardbuild: {}", e);
+        std::process::exit(1);
     }
 }
 
-// ── cmd_run ──────────────────────────────────────────────────────────────────
+// ── argument parsing ─────────────────────────────────────────────────────────
 
-fn cmd_run(args: &[String]) -> Result<(), String> {
-    let mut spec: Option<PathBuf> = None;
-    let mut out:  Option<PathBuf> = None;
-    let mut no_trace = false;
+#[derive(Default)]
+struct Args {
+    spec:     Option<PathBuf>,
+    config:   Option<PathBuf>,
+    out:      Option<PathBuf>,
+    manifest: Option<PathBuf>,
+    jobs:     Option<usize>,
+    no_trace: bool,
+}
 
+fn parse_args(args: &[String], allowed: &[&str]) -> Result<Args, String> {
+    let mut parsed = Args::default();
     let mut i = 0;
     while i < args.len() {
-        match args[i].as_str() {
-            "--spec"     => { i += 1; spec     = Some(PathBuf::from(&args[i])); }
-            "--out"      => { i += 1; out      = Some(PathBuf::from(&args[i])); }
-            "--no-trace" => { no_trace = true; }
-            other => return Err(format!("unknown argument: {}", other)),
+        let flag = args[i].as_str();
+        if !allowed.contains(&flag) {
+            return Err(format!("unknown argument: {}", flag));
+        }
+        if flag == "--no-trace" {
+            parsed.no_trace = true;
+            i += 1;
+            continue;
         }
-        i += 1;
+        let value = args.get(i + 1).ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag {
+            "--spec"     => parsed.spec     = Some(PathBuf::from(value)),
+            "--config"   => parsed.config   = Some(PathBuf::from(value)),
+            "--out"      => parsed.out      = Some(PathBuf::from(value)),
+            "--manifest" => parsed.manifest = Some(PathBuf::from(value)),
+            _ => {
+                let jobs = value.parse().ok().filter(|&n| n > 0);
+                parsed.jobs = Some(jobs.ok_or_else(|| format!("-j expects a positive number, got {:?}", value))?);
+            }
+        }
+        i += 2;
     }
+    Ok(parsed)
+}
 
-    let spec = spec.ok_or("--spec is required")?;
-    let out  = out.ok_or("--out is required")?;
-
-    if !spec.exists() {
-        return Err(format!("spec not found: {}", spec.display()));
-    }
+/// The build named by `--spec` or `--config`; `out` is where a spec build writes.
+fn load_config(args: &Args, out: &Path) -> Result<BuildConfig, String> {
+    let loaded = match (&args.spec, &args.config) {
+        (Some(spec), None) => {
+            if !spec.exists() {
+                return Err(format!("spec not found: {}", spec.display()));
+            }
+            BuildConfig::from_spec(spec, out, args.no_trace)
+        }
+        (None, Some(config)) => BuildConfig::from_toml(config),
+        (Some(_), Some(_)) => return Err("--spec and --config are mutually exclusive".to_string()),
+        (None, None) => return Err("--spec or --config is required".to_string()),
+    };
+    loaded.map_err(|e| format!("{:#}", e))
+}
 
-    fs::create_dir_all(&out).map_err(|e| format!("cannot create out dir: {}", e))?;
+fn read_manifest(path: &Path) -> Result<serde_json::Value, String> {
+    let bytes = fs::read(path).map_err(|e| format!("cannot read manifest: {}", e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("invalid JSON: {}", e))
+}
 
-    let spec_bytes = fs::read(&spec).map_err(|e| format!("cannot read spec: {}", e))?;
-    let spec_hash  = sha256_hex(&spec_bytes);
+// ── cmd_run ──────────────────────────────────────────────────────────────────
 
-    let started_ms = now_ms();
+fn cmd_run(args: &[String]) -> Result<(), String> {
+    let args = parse_args(args, &["--spec", "--config", "--out", "--no-trace", "-j"])?;
+    let out = args.out.clone().ok_or("--out is required")?;
+    let config = load_config(&args, &out)?;
 
-    // Run fardrun on the spec
-    let fardrun = find_fardrun()?;
-    let mut cmd = Command::new(&fardrun);
-    cmd.arg("run")
-       .arg("--program").arg(&spec)
-       .arg("--out").arg(&out);
-    if no_trace {
-        cmd.arg("--no-trace");
+    let mut opts = BuildOptions::new(&out);
+    if let Some(jobs) = args.jobs {
+        opts.jobs = jobs;
     }
+    let manifest = build::run_build(&config, &opts).map_err(|e| format!("{:#}", e))?;
 
-    let status = cmd
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()
-        .map_err(|e| format!("failed to run fardrun: {}", e))?;
-
-    let duration_ms = now_ms() - started_ms;
-    let ok = status.success();
-
-    // Read the run_id from digests.json if it exists
-    let run_id = read_run_id(&out).unwrap_or_else(|| "unknown".to_string());
-
-    // Read result or error
-    let result_val = if ok {
-        read_json(&out.join("result.json")).ok()
-    } else {
-        None
-    };
-
-    // Build the manifest
-    let manifest = build_manifest(
-        &spec_hash,
-        &run_id,
-        ok,
-        result_val.as_ref(),
-        duration_ms,
-    );
-
-    let manifest_path = out.join("build-manifest.json");
-    let manifest_bytes = to_string_pretty(&manifest).into_bytes();
-    fs::write(&manifest_path, &manifest_bytes)
-        .map_err(|e| format!("cannot write build-manifest.json: {}", e))?;
-
-    let manifest_hash = sha256_hex(&manifest_bytes);
-
-    if ok {
+    if manifest["ok"] == true {
         println!("fardbuild: ok");
-        println!("fardbuild: run_id={}", run_id);
-        println!("fardbuild: manifest=sha256:{}", manifest_hash);
-        println!("fardbuild: duration={}ms", duration_ms);
+        println!("fardbuild: chain_digest={}", manifest["chain_digest"].as_str().unwrap_or(""));
+        println!("fardbuild: manifest={}", out.join(build::MANIFEST_FILE).display());
+        println!("fardbuild: duration={}ms", manifest["total_duration_ms"]);
+        Ok(())
     } else {
-        eprintln!("fardbuild: FAILED after {}ms", duration_ms);
-        eprintln!("fardbuild: manifest=sha256:{}", manifest_hash);
-        return Err("build failed".to_string());
+        eprintln!("fardbuild: FAILED after {}ms", manifest["total_duration_ms"]);
+        Err("build failed".to_string())
     }
-
-    Ok(())
 }
 
 // ── cmd_check ────────────────────────────────────────────────────────────────
 
 fn cmd_check(args: &[String]) -> Result<(), String> {
-    let mut spec: Option<PathBuf> = None;
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--spec" => { i += 1; spec = Some(PathBuf::from(&args[i])); }
-            other    => return Err(format!("unknown argument: {}", other)),
-        }
-        i += 1;
-    }
-
-    let spec = spec.ok_or("--spec is required")?;
-    if !spec.exists() {
-        return Err(format!("spec not found: {}", spec.display()));
-    }
-
-    let bytes = fs::read(&spec).map_err(|e| format!("cannot read spec: {}", e))?;
-    let hash  = sha256_hex(&bytes);
+    let args = parse_args(args, &["--spec", "--config"])?;
+    let config = load_config(&args, Path::new("."))?;
 
     println!("fardbuild check: ok");
-    println!("spec: {}", spec.display());
-    println!("sha256:{}", hash);
+    println!("{}: {}", config.source.form, config.source.path.display());
+    println!("{}", config.source.digest);
+    println!("{} step(s)", config.steps.len());
     Ok(())
 }
 
 // ── cmd_show ─────────────────────────────────────────────────────────────────
 
 fn cmd_show(args: &[String]) -> Result<(), String> {
-    let mut manifest: Option<PathBuf> = None;
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--manifest" => { i += 1; manifest = Some(PathBuf::from(&args[i])); }
-            other        => return Err(format!("unknown argument: {}", other)),
-        }
-        i += 1;
-    }
-
-    let path = manifest.ok_or("--manifest is required")?;
-    let bytes = fs::read(&path).map_err(|e| format!("cannot read manifest: {}", e))?;
-    let val   = from_slice(&bytes).map_err(|e| format!("invalid JSON: {}", e))?;
-    println!("{}", to_string_pretty(&val));
-    Ok(())
+    let args = parse_args(args, &["--manifest"])?;
+    let path = args.manifest.ok_or("--manifest is required")?;
+    let manifest = read_manifest(&path)?;
+    println!("{}", serde_json::to_string_pretty(&manifest).unwrap_or_default());
+    build::validate_manifest(&manifest).map_err(|e| format!("{:#}", e))
 }
 
-// ── helpers ──────────────────────────────────────────────────────────────────
-
-fn find_fardrun() -> Result<PathBuf, String> {
-    // Look next to current binary first
-    if let Ok(exe) = env::current_exe() {
-        let sibling = exe.parent().unwrap_or(Path::new(".")).join("fardrun");
-        if sibling.exists() {
-            return Ok(sibling);
+// ── cmd_migrate ──────────────────────────────────────────────────────────────
+
+fn cmd_migrate(args: &[String]) -> Result<(), String> {
+    let args = parse_args(args, &["--manifest", "--out"])?;
+    let path = args.manifest.ok_or("--manifest is required")?;
+    let old = read_manifest(&path)?;
+    let migrated = build::migrate_manifest(&old, path.parent().unwrap_or(Path::new(".")))
+        .map_err(|e| format!("{:#}", e))?;
+    let text = serde_json::to_string_pretty(&migrated).map_err(|e| e.to_string())?;
+    match args.out {
+        Some(out) => {
+            fs::write(&out, text).map_err(|e| format!("cannot write {}: {}", out.display(), e))?;
+            println!("fardbuild: migrated {} → {}", path.display(), out.display());
         }
+        None => println!("{}", text),
     }
-    // Fall back to PATH
-    Ok(PathBuf::from("fardrun"))
-}
-
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis() as u64)
-        .unwrap_or(0)
-}
-
-fn sha256_hex(bytes: &[u8]) -> String {
-    let mut h = Sha256::new();
-    h.update(bytes);
-    hex_lower(&h.finalize())
-}
-
-fn read_run_id(out_dir: &Path) -> Option<String> {
-    let bytes = fs::read(out_dir.join("digests.json")).ok()?;
-    let val   = from_slice(&bytes).ok()?;
-    if let J::Str(s) = val.get("preimage_sha256")? {
-        Some(s.clone())
-    } else {
-        None
-    }
-}
-
-fn read_json(path: &Path) -> Result<J, String> {
-    let bytes = fs::read(path).map_err(|e| e.to_string())?;
-    from_slice(&bytes).map_err(|e| e.to_string())
-}
-
-fn build_manifest(
-    spec_hash:   &str,
-    run_id:      &str,
-    ok:          bool,
-    result:      Option<&J>,
-    duration_ms: u64,
-) -> J {
-    let mut m = BTreeMap::new();
-    m.insert("fard_build_version".to_string(), J::Str("1.0.0".to_string()));
-    m.insert("spec_sha256".to_string(),        J::Str(format!("sha256:{}", spec_hash)));
-    m.insert("run_id".to_string(),             J::Str(run_id.to_string()));
-    m.insert("ok".to_string(),                 J::Bool(ok));
-    m.insert("built_at".to_string(),           J::Int(now_ms() as i64 / 1000));
-    m.insert("duration_ms".to_string(),        J::Int(duration_ms as i64));
-    if let Some(r) = result {
-        m.insert("result".to_string(), r.clone());
-    }
-    J::Object(m)
+    Ok(())
 }
@@ -1,9 +1,29 @@
-//! fardcheck — best-effort type checker for FARD programs
-//! Reports definite type errors in pure expressions.
-//! Imports and builtins are typed as Dynamic (propagates without error).
-
-use anyhow::{bail, Result};
-use std::collections::HashMap;
+//! fardcheck — Hindley-Milner type checker for FARD programs
+//! Usage: fardcheck [--program] <file.fard> [--registry DIR] [--as PATH] [--hex-haiku]
+//!
+//! Infers a type for every binding with let-polymorphism and reports definite type errors
+//! as `TYPE ERROR file:line:col: message`, one per line, exiting 1 when there are any.
+//!
+//!   - stdlib imports are typed from fardrun's own `STD_MODULES` export table (generated by
+//!     build.rs): core modules carry full signatures, every other export is a function whose
+//!     minimum arity comes from `builtin_sig_table_v1`; unknown modules and exports are errors
+//!   - local (`./x`, `lib/x`), `registry/x` and `pkg:name@ver` imports are followed and checked
+//!     in turn; errors inside them carry their own file. Packages that cannot be found locally
+//!     are typed as Dynamic
+//!   - `a T is { .. }` and `a T is V(..) or W` declarations give nominal types; constructor
+//!     calls check required fields, and a `match` on a sum type must cover every variant
+//!
+//! Dynamic (`?`) absorbs anything, so untyped code never produces errors. Int and Float
+//! unify with each other except where an operator would mix them, and null unifies with any type.
+//!
+//! --as PATH checks the file as if it lived at PATH: imports resolve relative to it and
+//! errors are labelled with it (used by editors for unsaved buffers).
+
+use fard_v0_5_language_gate::builtin_sig_table_v1::builtin_sig_table_v1;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+include!(concat!(env!("OUT_DIR"), "/stdlib_exports.rs"));
 
 // ── Types ────────────────────────────────────────────────────────────────────
 #[derive(Clone, Debug, PartialEq)]
@@ -13,1117 +33,2421 @@ enum Ty {
     Bool,
     Str,
     Null,
+    Bytes,
     List(Box<Ty>),
     Rec(Vec<(String, Ty)>),
     Func(Vec<Ty>, Box<Ty>),
+    AnyFn(usize),   // untyped builtin taking at least n arguments
+    Named(String),  // a declared `a T is ..` type, keyed "T@file"
+    Module(String), // an imported module, keyed by std path or file
     Var(u32),       // unification variable
-    Dynamic,        // unknown / from import — no errors propagate through this
+    Dynamic,        // unknown — no errors propagate through this
 }
 
 /// A polymorphic type scheme: ∀(vars). ty
-/// vars are the generalized type variables
 #[derive(Clone, Debug)]
 struct Scheme {
     vars: Vec<u32>,
     ty: Ty,
+    /// Parameter names, for named calls
+    params: Option<Vec<String>>,
+    /// Set for the constructors of declared types: (type key, variant)
+    ctor: Option<(String, Option<String>)>,
 }
 
 impl Scheme {
-    fn mono(ty: Ty) -> Self { Scheme { vars: vec![], ty } }
+    fn mono(ty: Ty) -> Self {
+        Scheme { vars: vec![], ty, params: None, ctor: None }
+    }
 }
 
 impl Ty {
-    fn display(&self) -> String {
+    fn free_vars(&self, out: &mut Vec<u32>) {
         match self {
+            Ty::Var(n) => out.push(*n),
+            Ty::List(inner) => inner.free_vars(out),
+            Ty::Func(ps, r) => {
+                ps.iter().for_each(|p| p.free_vars(out));
+                r.free_vars(out);
+            }
+            Ty::Rec(fs) => fs.iter().for_each(|(_, t)| t.free_vars(out)),
+            _ => {}
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Ty::Int | Ty::Float)
+    }
+
+    /// Concrete types that no arithmetic or comparison operator accepts.
+    fn non_numeric(&self) -> bool {
+        !matches!(self, Ty::Int | Ty::Float | Ty::Var(_) | Ty::Dynamic | Ty::Null)
+    }
+}
+
+/// Renders types for messages, naming type variables a, b, c… in order of appearance.
+#[derive(Default)]
+struct Shower {
+    names: HashMap<u32, String>,
+}
+
+impl Shower {
+    fn show(&mut self, t: &Ty) -> String {
+        match t {
             Ty::Int => "Int".to_string(),
             Ty::Float => "Float".to_string(),
             Ty::Bool => "Bool".to_string(),
             Ty::Str => "Text".to_string(),
             Ty::Null => "Null".to_string(),
+            Ty::Bytes => "Bytes".to_string(),
             Ty::Dynamic => "?".to_string(),
-            Ty::Var(n) => format!("t{}", n),
-            Ty::List(inner) => format!("List({})", inner.display()),
-            Ty::Func(params, ret) => {
-                let ps: Vec<String> = params.iter().map(|p| p.display()).collect();
-                format!("({}) -> {}", ps.join(", "), ret.display())
-            }
-            Ty::Rec(fields) => {
-                let fs: Vec<String> = fields.iter().map(|(k, v)| format!("{}: {}", k, v.display())).collect();
-                format!("{{ {} }}", fs.join(", "))
+            Ty::AnyFn(_) => "fn".to_string(),
+            Ty::Named(k) => k.split('@').next().unwrap_or(k).to_string(),
+            Ty::Module(k) => format!("module {}", k),
+            Ty::Var(n) => {
+                let next = self.names.len();
+                self.names
+                    .entry(*n)
+                    .or_insert_with(|| {
+                        let c = (b'a' + (next % 26) as u8) as char;
+                        if next < 26 { c.to_string() } else { format!("{}{}", c, next / 26) }
+                    })
+                    .clone()
             }
-        }
-    }
-
-    fn free_vars(&self) -> Vec<u32> {
-        match self {
-            Ty::Var(n) => vec![*n],
-            Ty::List(inner) => inner.free_vars(),
-            Ty::Func(params, ret) => {
-                let mut vs: Vec<u32> = params.iter().flat_map(|p| p.free_vars()).collect();
-                vs.extend(ret.free_vars());
-                vs.sort(); vs.dedup();
-                vs
+            Ty::List(inner) => format!("List<{}>", self.show(inner)),
+            Ty::Func(ps, r) => {
+                let ps: Vec<String> = ps.iter().map(|p| self.show(p)).collect();
+                format!("({}) -> {}", ps.join(", "), self.show(r))
             }
-            Ty::Rec(fields) => {
-                let mut vs: Vec<u32> = fields.iter().flat_map(|(_, v)| v.free_vars()).collect();
-                vs.sort(); vs.dedup();
-                vs
+            Ty::Rec(fs) => {
+                let fs: Vec<String> = fs.iter().map(|(k, v)| format!("{}: {}", k, self.show(v))).collect();
+                format!("{{ {} }}", fs.join(", "))
             }
-            _ => vec![],
         }
     }
 }
 
+/// A declared type: `a Point is { x: Int, y: Int }` or `a Shape is Circle(r: Float) or Dot`.
 #[derive(Clone, Debug)]
+enum TypeDecl {
+    Record(Vec<(String, Ty)>),
+    Sum(Vec<(String, Vec<(String, Ty)>)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct TyError {
-    msg: String,
+    file: String,
     line: usize,
+    col: usize,
+    msg: String,
 }
 
-// ── Unification ───────────────────────────────────────────────────────────────
-struct Subst(HashMap<u32, Ty>);
-
-impl Subst {
-    fn new() -> Self { Subst(HashMap::new()) }
+/// Names fardrun binds in every module before the first item.
+const BASE_ENV: &[(&str, &str)] = &[
+    ("unit", "Null"),
+    ("len", "(?) -> Int"),
+    ("emit", "fn"),
+    ("import_artifact", "fn"),
+    ("import_artifact_named", "fn"),
+    ("emit_artifact", "fn"),
+    ("emit_artifact_derived", "fn"),
+];
+
+/// Parses a signature like `(List<a>, (a) -> b) -> List<b>`; single lowercase letters
+/// are the scheme's type variables.
+struct SigParser<'a> {
+    s: &'a [u8],
+    i: usize,
+    vars: HashMap<u8, u32>,
+}
 
-    fn apply(&self, t: &Ty) -> Ty {
-        match t {
-            Ty::Var(n) => {
-                if let Some(t2) = self.0.get(n) {
-                    self.apply(t2)
-                } else {
-                    t.clone()
+impl SigParser<'_> {
+    fn ws(&mut self) {
+        while self.s.get(self.i) == Some(&b' ') {
+            self.i += 1;
+        }
+    }
+    fn eat(&mut self, t: &str) -> bool {
+        self.ws();
+        if self.s[self.i..].starts_with(t.as_bytes()) {
+            self.i += t.len();
+            true
+        } else {
+            false
+        }
+    }
+    fn ty(&mut self, fresh: &mut impl FnMut() -> u32) -> Ty {
+        if self.eat("(") {
+            let mut ps = Vec::new();
+            if !self.eat(")") {
+                loop {
+                    ps.push(self.ty(fresh));
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.eat(",");
                 }
             }
-            Ty::List(inner) => Ty::List(Box::new(self.apply(inner))),
-            Ty::Func(ps, r) => Ty::Func(
-                ps.iter().map(|p| self.apply(p)).collect(),
-                Box::new(self.apply(r)),
-            ),
-            Ty::Rec(fields) => Ty::Rec(
-                fields.iter().map(|(k, v)| (k.clone(), self.apply(v))).collect(),
-            ),
-            other => other.clone(),
+            self.eat("->");
+            return Ty::Func(ps, Box::new(self.ty(fresh)));
+        }
+        if self.eat("List<") {
+            let t = self.ty(fresh);
+            self.eat(">");
+            return Ty::List(Box::new(t));
+        }
+        self.ws();
+        let start = self.i;
+        while self.s.get(self.i).is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'?') {
+            self.i += 1;
+        }
+        match &self.s[start..self.i] {
+            b"Int" => Ty::Int,
+            b"Float" => Ty::Float,
+            b"Bool" => Ty::Bool,
+            b"Text" => Ty::Str,
+            b"Null" => Ty::Null,
+            b"Bytes" => Ty::Bytes,
+            b"fn" => Ty::AnyFn(0),
+            [c] if c.is_ascii_lowercase() => Ty::Var(*self.vars.entry(*c).or_insert_with(&mut *fresh)),
+            _ => Ty::Dynamic,
         }
     }
+}
 
-    fn bind(&mut self, n: u32, t: Ty) {
-        self.0.insert(n, t);
-    }
+// ── Lexer ────────────────────────────────────────────────────────────────────
+const KEYWORDS: &[&str] = &[
+    "let", "in", "fn", "if", "then", "else", "import", "as", "export", "match", "test", "while",
+    "return", "using", "true", "false", "null",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TK {
+    Kw,
+    Ident,
+    Num,
+    Str,
+    Sym,
+    Eof,
+}
 
-    fn unify(&mut self, a: &Ty, b: &Ty) -> Result<()> {
-        let a = self.apply(a);
-        let b = self.apply(b);
-        match (&a, &b) {
-            (Ty::Dynamic, _) | (_, Ty::Dynamic) => Ok(()),
-            (Ty::Var(n), t) | (t, Ty::Var(n)) => {
-                let n = *n;
-                if !self.occurs(n, t) {
-                    self.bind(n, t.clone());
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Sp {
+    line: usize,
+    col: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Tok {
+    kind: TK,
+    text: String,
+    sp: Sp,
+    nl_before: bool,
+    /// `${..}` sources inside a string literal, with where each starts
+    interp: Vec<(String, Sp)>,
+}
+
+fn lex(src: &str, origin: Sp) -> Result<Vec<Tok>, String> {
+    let s: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    let mut line = origin.line;
+    // index of the first char of the current line, offset so the origin column comes out right
+    let mut line_start = -(origin.col as isize - 1);
+    let col = |i: usize, line_start: isize| (i as isize - line_start) as usize + 1;
+    let mut toks: Vec<Tok> = Vec::new();
+    loop {
+        let mut nl_before = toks.is_empty();
+        loop {
+            match s.get(i) {
+                Some('\n') => {
+                    nl_before = true;
+                    line += 1;
+                    i += 1;
+                    line_start = i as isize;
                 }
-                Ok(())
-            }
-            (Ty::Int, Ty::Int) | (Ty::Float, Ty::Float) |
-            (Ty::Bool, Ty::Bool) | (Ty::Str, Ty::Str) |
-            (Ty::Null, Ty::Null) => Ok(()),
-            (Ty::List(a), Ty::List(b)) => self.unify(a, b),
-            (Ty::Func(ap, ar), Ty::Func(bp, br)) => {
-                if ap.len() != bp.len() {
-                    bail!("arity mismatch: expected {} params, got {}", ap.len(), bp.len());
+                Some(c) if c.is_whitespace() => i += 1,
+                Some('#') => {
+                    while i < s.len() && s[i] != '\n' {
+                        i += 1;
+                    }
                 }
-                for (a, b) in ap.iter().zip(bp.iter()) {
-                    self.unify(a, b)?;
+                Some('/') if s.get(i + 1) == Some(&'/') => {
+                    while i < s.len() && s[i] != '\n' {
+                        i += 1;
+                    }
                 }
-                self.unify(ar, br)
+                _ => break,
             }
-            _ => bail!("type mismatch: {} vs {}", a.display(), b.display()),
         }
+        let sp = Sp { line, col: col(i, line_start) };
+        let start = i;
+        let mut interp = Vec::new();
+        let Some(&c) = s.get(i) else {
+            toks.push(Tok { kind: TK::Eof, text: String::new(), sp, nl_before, interp });
+            return Ok(toks);
+        };
+        let kind = if c.is_ascii_alphabetic() || c == '_' {
+            while s.get(i).is_some_and(|d| d.is_ascii_alphanumeric() || *d == '_') {
+                i += 1;
+            }
+            let word: String = s[start..i].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) { TK::Kw } else { TK::Ident }
+        } else if c.is_ascii_digit() {
+            while s.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                i += 1;
+            }
+            if s.get(i) == Some(&'.') && s.get(i + 1).is_some_and(|d| d.is_ascii_digit()) {
+                i += 1;
+                while s.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                    i += 1;
+                }
+                if matches!(s.get(i), Some('e' | 'E')) {
+                    i += 1;
+                    if matches!(s.get(i), Some('+' | '-')) {
+                        i += 1;
+                    }
+                    while s.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                        i += 1;
+                    }
+                }
+            }
+            TK::Num
+        } else if c == '`' || c == '"' {
+            i += 1;
+            while let Some(&d) = s.get(i) {
+                i += 1;
+                match d {
+                    _ if d == c => break,
+                    '\n' => {
+                        line += 1;
+                        line_start = i as isize;
+                    }
+                    '\\' if c == '"' => i += 1,
+                    '$' if c == '"' && s.get(i) == Some(&'{') => {
+                        i += 1;
+                        let inner_sp = Sp { line, col: col(i, line_start) };
+                        let inner_start = i;
+                        let mut depth = 1usize;
+                        loop {
+                            match s.get(i) {
+                                None => return Err(format!("unterminated ${{}} at line {}", line)),
+                                Some('{') => depth += 1,
+                                Some('}') => {
+                                    depth -= 1;
+                                    if depth == 0 {
+                                        break;
+                                    }
+                                }
+                                Some('\n') => {
+                                    line += 1;
+                                    line_start = i as isize + 1;
+                                }
+                                _ => {}
+                            }
+                            i += 1;
+                        }
+                        interp.push((s[inner_start..i].iter().collect(), inner_sp));
+                        i += 1;
+                    }
+                    _ => {}
+                }
+            }
+            TK::Str
+        } else {
+            let three: String = s[i..(i + 3).min(s.len())].iter().collect();
+            let two: String = s[i..(i + 2).min(s.len())].iter().collect();
+            if three == "..." {
+                i += 3;
+            } else if ["||", "!=", "==", "<=", ">=", "&&", "->", "=>", "|>"].contains(&two.as_str()) {
+                i += 2;
+            } else if "(){}[],:.+-*/=%|<>?!".contains(c) {
+                i += 1;
+            } else {
+                return Err(format!("unexpected char: {} at line {}", c, line));
+            }
+            TK::Sym
+        };
+        toks.push(Tok { kind, text: s[start..i].iter().collect(), sp, nl_before, interp });
     }
+}
 
-    fn occurs(&self, n: u32, t: &Ty) -> bool {
-        match t {
-            Ty::Var(m) => *m == n,
-            Ty::List(inner) => self.occurs(n, inner),
-            Ty::Func(ps, r) => ps.iter().any(|p| self.occurs(n, p)) || self.occurs(n, r),
-            Ty::Rec(fs) => fs.iter().any(|(_, v)| self.occurs(n, v)),
-            _ => false,
+/// The contents of a string literal token.
+fn unquote(text: &str) -> String {
+    let inner = text.get(1..text.len().saturating_sub(1)).unwrap_or("");
+    if text.starts_with('`') {
+        return inner.to_string();
+    }
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(e) => out.push(e),
+            None => {}
         }
     }
+    out
 }
 
-// ── Checker ───────────────────────────────────────────────────────────────────
-struct Checker {
-    subst: Subst,
-    next_var: u32,
-    errors: Vec<TyError>,
-    env: Vec<HashMap<String, Scheme>>,
+// ── AST ──────────────────────────────────────────────────────────────────────
+#[derive(Clone, Debug)]
+struct Item {
+    k: IK,
+    sp: Sp,
 }
 
-impl Checker {
-    fn new() -> Self {
-        Checker {
-            subst: Subst::new(),
-            next_var: 0,
-            errors: Vec::new(),
-            env: vec![HashMap::new()],
-        }
-    }
+#[derive(Clone, Debug)]
+enum IK {
+    Import(String, String),
+    Artifact(String),
+    Let(Pat, Expr),
+    Fn(String, Vec<Param>, Option<TypeAnn>, Expr),
+    Export(Vec<String>),
+    TypeDef(String, TypeDefKind),
+    Test(String, Expr),
+    Expr(Expr),
+}
 
-    fn fresh(&mut self) -> Ty {
-        let n = self.next_var;
-        self.next_var += 1;
-        Ty::Var(n)
-    }
+/// `field: Type` pairs of a declared record or variant
+type Fields = Vec<(String, String)>;
 
-    fn push(&mut self) { self.env.push(HashMap::new()); }
-    fn pop(&mut self) { self.env.pop(); }
+#[derive(Clone, Debug)]
+enum TypeDefKind {
+    Record(Fields),
+    Sum(Vec<(String, Fields)>),
+}
 
-    fn define(&mut self, name: &str, ty: Ty) {
-        self.define_scheme(name, Scheme::mono(ty));
+#[derive(Clone, Debug)]
+enum TypeAnn {
+    Named(String, Vec<TypeAnn>),
+    Rec(Vec<(String, TypeAnn)>),
+    Func(Vec<TypeAnn>, Box<TypeAnn>),
+}
+
+#[derive(Clone, Debug)]
+struct Param {
+    pat: Pat,
+    ann: Option<TypeAnn>,
+}
+
+#[derive(Clone, Debug)]
+enum Pat {
+    Wild,
+    Bind(String),
+    Lit(String),
+    Obj(Vec<(String, Option<Pat>)>, Option<String>),
+    List(Vec<Pat>, Option<String>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Let(Pat, Expr),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+struct Arm {
+    pat: Pat,
+    guard: Option<Expr>,
+    body: Expr,
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    k: EK,
+    sp: Sp,
+}
+
+#[derive(Clone, Debug)]
+enum EK {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Null,
+    Interp(Vec<Expr>),
+    Var(String),
+    List(Vec<Expr>),
+    Comp(Box<Expr>, Pat, Box<Expr>, Option<Box<Expr>>),
+    Rec(Vec<(String, Expr)>),
+    Fn(Vec<Param>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    NamedCall(Box<Expr>, Vec<(String, Expr)>),
+    Get(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Try(Box<Expr>),
+    Unary(String, Box<Expr>),
+    Bin(String, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Block(Vec<Stmt>),
+    LetIn(Pat, Box<Expr>, Box<Expr>),
+    Using(Pat, Box<Expr>, Box<Expr>),
+    Match(Box<Expr>, Vec<Arm>),
+    While(Box<Expr>, Box<Expr>, Box<Expr>),
+    For(Pat, Box<Expr>, Box<Expr>),
+    Return(Box<Expr>),
+}
+
+// ── Parser (mirrors fardrun's grammar; pipes are desugared into calls) ───────
+type PResult<T> = Result<T, String>;
+
+struct Parser {
+    toks: Vec<Tok>,
+    i: usize,
+}
+
+fn parse_module(src: &str) -> PResult<Vec<Item>> {
+    let mut p = Parser { toks: lex(src, Sp { line: 1, col: 1 })?, i: 0 };
+    let mut items = Vec::new();
+    while p.peek().kind != TK::Eof {
+        items.push(p.parse_item()?);
     }
+    Ok(items)
+}
 
-    fn define_scheme(&mut self, name: &str, scheme: Scheme) {
-        if let Some(top) = self.env.last_mut() {
-            top.insert(name.to_string(), scheme);
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.i.min(self.toks.len() - 1)]
+    }
+    fn peek_n(&self, n: usize) -> &Tok {
+        &self.toks[(self.i + n).min(self.toks.len() - 1)]
+    }
+    fn sp(&self) -> Sp {
+        self.peek().sp
+    }
+    fn bump(&mut self) -> Tok {
+        let t = self.peek().clone();
+        if self.i < self.toks.len() - 1 {
+            self.i += 1;
         }
+        t
     }
-
-    fn lookup(&mut self, name: &str) -> Ty {
-        let scheme = self.env.iter().rev()
-            .find_map(|scope| scope.get(name))
-            .cloned();
-        match scheme {
-            Some(s) => self.instantiate_scheme(&s),
-            None => Ty::Dynamic,
+    fn err<T>(&self, msg: &str) -> PResult<T> {
+        let t = self.peek();
+        let got = if t.kind == TK::Eof { "end of input".to_string() } else { format!("{:?}", t.text) };
+        Err(format!("ERROR_PARSE {}; got {} at line {}:{}", msg, got, t.sp.line, t.sp.col))
+    }
+    fn is_sym(&self, s: &str) -> bool {
+        let t = self.peek();
+        t.kind == TK::Sym && t.text == s
+    }
+    fn is_sym_n(&self, n: usize, s: &str) -> bool {
+        let t = self.peek_n(n);
+        t.kind == TK::Sym && t.text == s
+    }
+    fn eat_sym(&mut self, s: &str) -> bool {
+        self.is_sym(s) && {
+            self.bump();
+            true
+        }
+    }
+    fn expect_sym(&mut self, s: &str) -> PResult<()> {
+        if self.eat_sym(s) { Ok(()) } else { self.err(&format!("expected symbol {:?}", s)) }
+    }
+    /// fardrun's eat_kw also accepts identifiers (`for`, `do`, `artifact`)
+    fn is_kw(&self, s: &str) -> bool {
+        let t = self.peek();
+        matches!(t.kind, TK::Kw | TK::Ident) && t.text == s
+    }
+    fn eat_kw(&mut self, s: &str) -> bool {
+        self.is_kw(s) && {
+            self.bump();
+            true
         }
     }
+    fn expect_kw(&mut self, s: &str) -> PResult<()> {
+        if self.eat_kw(s) { Ok(()) } else { self.err(&format!("expected keyword {}", s)) }
+    }
+    fn expect_ident(&mut self) -> PResult<String> {
+        if self.peek().kind == TK::Ident { Ok(self.bump().text) } else { self.err("expected identifier") }
+    }
+    fn expect_str(&mut self, what: &str) -> PResult<String> {
+        if self.peek().kind == TK::Str { Ok(unquote(&self.bump().text)) } else { self.err(what) }
+    }
 
-    fn instantiate_scheme(&mut self, scheme: &Scheme) -> Ty {
-        if scheme.vars.is_empty() {
-            return scheme.ty.clone();
+    fn parse_item(&mut self) -> PResult<Item> {
+        let sp = self.sp();
+        let k = self.parse_item_kind()?;
+        Ok(Item { k, sp })
+    }
+    fn parse_item_kind(&mut self) -> PResult<IK> {
+        if self.eat_kw("test") {
+            let label = self.expect_str("test expects a string label")?;
+            let sp = self.sp();
+            self.expect_sym("{")?;
+            return Ok(IK::Test(label, self.parse_block(sp)?));
+        }
+        if self.peek().kind == TK::Ident && self.peek().text == "a" {
+            self.bump();
+            let name = self.expect_ident()?;
+            if !(self.peek().kind == TK::Ident && self.peek().text == "is") {
+                return self.err("expected 'is'");
+            }
+            self.bump();
+            let kind = if self.eat_sym("{") {
+                TypeDefKind::Record(self.parse_fields("}")?)
+            } else {
+                let mut variants = Vec::new();
+                loop {
+                    let v = self.expect_ident()?;
+                    let fields = if self.eat_sym("(") { self.parse_fields(")")? } else { Vec::new() };
+                    variants.push((v, fields));
+                    if !(self.peek().kind == TK::Ident && self.peek().text == "or") {
+                        break;
+                    }
+                    self.bump();
+                }
+                TypeDefKind::Sum(variants)
+            };
+            return Ok(IK::TypeDef(name, kind));
         }
-        let mut inst_subst = Subst::new();
-        for &v in &scheme.vars {
-            let fresh = self.fresh();
-            inst_subst.bind(v, fresh);
+        if self.eat_kw("import") {
+            self.expect_sym("(")?;
+            let path = self.expect_str("import() requires string")?;
+            self.expect_sym(")")?;
+            self.expect_kw("as")?;
+            return Ok(IK::Import(path, self.expect_ident()?));
         }
-        inst_subst.apply(&scheme.ty)
+        if self.eat_kw("artifact") {
+            let name = self.expect_ident()?;
+            self.expect_sym("=")?;
+            self.expect_str("artifact requires run_id string")?;
+            return Ok(IK::Artifact(name));
+        }
+        if self.eat_kw("export") {
+            self.expect_sym("{")?;
+            let mut names = vec![self.expect_ident()?];
+            while self.eat_sym(",") && !self.is_sym("}") {
+                names.push(self.expect_ident()?);
+            }
+            self.expect_sym("}")?;
+            return Ok(IK::Export(names));
+        }
+        if self.eat_kw("fn") {
+            let name = self.expect_ident()?;
+            self.expect_sym("(")?;
+            let mut params = Vec::new();
+            if !self.eat_sym(")") {
+                loop {
+                    let pat = self.parse_pat()?;
+                    if matches!(pat, Pat::Bind(_)) && self.eat_sym("=") {
+                        self.parse_expr()?;
+                        params.push(Param { pat, ann: None });
+                    } else {
+                        let ann = if self.eat_sym(":") { Some(self.parse_type()?) } else { None };
+                        params.push(Param { pat, ann });
+                    }
+                    if self.eat_sym(")") {
+                        break;
+                    }
+                    self.expect_sym(",")?;
+                }
+            }
+            let ret = if self.eat_sym("->") { Some(self.parse_type()?) } else { None };
+            let sp = self.sp();
+            self.expect_sym("{")?;
+            return Ok(IK::Fn(name, params, ret, self.parse_block(sp)?));
+        }
+        if self.is_kw("let") {
+            let sp = self.sp();
+            self.bump();
+            let pat = self.parse_pat()?;
+            self.expect_sym("=")?;
+            let rhs = self.parse_expr()?;
+            if self.eat_kw("in") {
+                let body = self.parse_expr()?;
+                return Ok(IK::Expr(Expr { k: EK::LetIn(pat, Box::new(rhs), Box::new(body)), sp }));
+            }
+            return Ok(IK::Let(pat, rhs));
+        }
+        Ok(IK::Expr(self.parse_expr()?))
+    }
+    fn parse_fields(&mut self, close: &str) -> PResult<Fields> {
+        let mut fields = Vec::new();
+        while !self.is_sym(close) {
+            let f = self.expect_ident()?;
+            self.expect_sym(":")?;
+            fields.push((f, self.expect_ident()?));
+            self.eat_sym(",");
+        }
+        self.bump();
+        Ok(fields)
     }
 
-    fn generalize(&self, ty: &Ty) -> Scheme {
-        // Collect free vars in ty that are NOT free in the env
-        let ty_vars = self.subst.apply(ty).free_vars();
-        let env_vars = self.env_free_vars();
-        let generalized: Vec<u32> = ty_vars.into_iter()
-            .filter(|v| !env_vars.contains(v))
-            .collect();
-        Scheme { vars: generalized, ty: self.subst.apply(ty) }
+    /// Statements after `{` up to and including the closing `}`, as a Block expression.
+    fn parse_block(&mut self, sp: Sp) -> PResult<Expr> {
+        let mut stmts = Vec::new();
+        loop {
+            if self.eat_kw("let") {
+                let lsp = self.sp();
+                if self.is_sym("{") || self.is_sym("[") {
+                    let pat = self.parse_pat()?;
+                    self.expect_sym("=")?;
+                    stmts.push(Stmt::Let(pat, self.parse_expr()?));
+                    continue;
+                }
+                let name = self.expect_ident()?;
+                self.expect_sym("=")?;
+                let rhs = self.parse_expr()?;
+                if self.eat_kw("in") {
+                    let body = self.parse_expr()?;
+                    let k = EK::LetIn(Pat::Bind(name), Box::new(rhs), Box::new(body));
+                    stmts.push(Stmt::Expr(Expr { k, sp: lsp }));
+                    break;
+                }
+                self.eat_seq_bar();
+                stmts.push(Stmt::Let(Pat::Bind(name), rhs));
+                continue;
+            }
+            let e = self.parse_expr()?;
+            stmts.push(Stmt::Expr(e));
+            if !self.eat_seq_bar() {
+                break;
+            }
+            // after `expr |` only expressions may follow
+            loop {
+                stmts.push(Stmt::Expr(self.parse_expr()?));
+                if !self.eat_seq_bar() {
+                    break;
+                }
+            }
+            break;
+        }
+        self.expect_sym("}")?;
+        Ok(Expr { k: EK::Block(stmts), sp })
+    }
+    fn eat_seq_bar(&mut self) -> bool {
+        self.is_sym("|") && !self.is_sym_n(1, "|") && {
+            self.bump();
+            true
+        }
     }
 
-    fn env_free_vars(&self) -> Vec<u32> {
-        let mut vars = Vec::new();
-        for scope in &self.env {
-            for scheme in scope.values() {
-                let applied = self.subst.apply(&scheme.ty);
-                vars.extend(applied.free_vars());
+    fn parse_type(&mut self) -> PResult<TypeAnn> {
+        if self.eat_sym("(") {
+            let t = self.parse_type()?;
+            self.expect_sym(")")?;
+            return Ok(t);
+        }
+        if !matches!(self.peek().kind, TK::Ident | TK::Kw) {
+            return self.err("expected type");
+        }
+        let name = self.bump().text;
+        match name.as_str() {
+            "Int" | "String" | "Bool" | "Unit" | "Dynamic" => Ok(TypeAnn::Named(name, Vec::new())),
+            "Rec" => {
+                self.expect_sym("{")?;
+                let mut fields = Vec::new();
+                if !self.eat_sym("}") {
+                    loop {
+                        let k = self.expect_ident()?;
+                        self.expect_sym(":")?;
+                        fields.push((k, self.parse_type()?));
+                        if self.eat_sym("}") {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                    }
+                }
+                Ok(TypeAnn::Rec(fields))
+            }
+            "Func" => {
+                self.expect_sym("(")?;
+                let mut args = Vec::new();
+                if !self.eat_sym(")") {
+                    loop {
+                        args.push(self.parse_type()?);
+                        if self.eat_sym(")") {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                    }
+                }
+                self.expect_sym("->")?;
+                Ok(TypeAnn::Func(args, Box::new(self.parse_type()?)))
+            }
+            _ => {
+                let mut args = Vec::new();
+                if self.eat_sym("<") && !self.eat_sym(">") {
+                    loop {
+                        args.push(self.parse_type()?);
+                        if self.eat_sym(">") {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                    }
+                }
+                Ok(TypeAnn::Named(name, args))
             }
         }
-        vars.sort(); vars.dedup();
-        vars
     }
 
-    fn err(&mut self, msg: String, line: usize) {
-        self.errors.push(TyError { msg, line });
+    fn parse_pat(&mut self) -> PResult<Pat> {
+        let t = self.peek().clone();
+        match t.kind {
+            TK::Ident if t.text == "_" => {
+                self.bump();
+                Ok(Pat::Wild)
+            }
+            TK::Kw if matches!(t.text.as_str(), "true" | "false" | "null") => {
+                self.bump();
+                Ok(Pat::Lit(t.text))
+            }
+            TK::Ident | TK::Kw => {
+                self.bump();
+                Ok(Pat::Bind(t.text))
+            }
+            TK::Num if !t.text.contains('.') => {
+                self.bump();
+                Ok(Pat::Lit(t.text))
+            }
+            TK::Str => {
+                self.bump();
+                Ok(Pat::Lit(t.text))
+            }
+            TK::Sym if t.text == "{" || t.text == "[" => {
+                let close = if t.text == "{" { "}" } else { "]" };
+                self.bump();
+                let mut fields = Vec::new();
+                let mut items = Vec::new();
+                let mut rest = None;
+                if !self.eat_sym(close) {
+                    loop {
+                        if self.eat_sym("...") {
+                            rest = Some(self.expect_ident()?);
+                            self.expect_sym(close)?;
+                            break;
+                        }
+                        if close == "}" {
+                            let k = self.expect_ident()?;
+                            let sub = if self.eat_sym(":") { Some(self.parse_pat()?) } else { None };
+                            fields.push((k, sub));
+                        } else {
+                            items.push(self.parse_pat()?);
+                        }
+                        if self.eat_sym(close) {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                        if self.eat_sym(close) {
+                            break;
+                        }
+                    }
+                }
+                Ok(if close == "}" { Pat::Obj(fields, rest) } else { Pat::List(items, rest) })
+            }
+            _ => self.err("expected pattern"),
+        }
     }
 
-    fn unify_or_err(&mut self, a: &Ty, b: &Ty, line: usize, ctx: &str) {
-        let a = self.subst.apply(a);
-        let b = self.subst.apply(b);
-        if let Err(e) = self.subst.unify(&a, &b) {
-            self.err(format!("{ctx}: {e}"), line);
+    fn parse_expr(&mut self) -> PResult<Expr> {
+        let sp = self.sp();
+        let mk = |k| Ok(Expr { k, sp });
+        if self.eat_kw("using") || self.is_kw("let") {
+            let using = self.toks[self.i - 1].text == "using" && !self.is_kw("let");
+            if !using {
+                self.bump();
+            }
+            let pat = self.parse_pat()?;
+            self.expect_sym("=")?;
+            let rhs = Box::new(self.parse_expr()?);
+            self.expect_kw("in")?;
+            let body = Box::new(self.parse_expr()?);
+            return mk(if using { EK::Using(pat, rhs, body) } else { EK::LetIn(pat, rhs, body) });
+        }
+        if self.eat_kw("match") {
+            let scrut = self.parse_expr()?;
+            self.expect_sym("{")?;
+            let mut arms = Vec::new();
+            while !self.is_sym("}") {
+                let pat = self.parse_pat()?;
+                let guard = if self.eat_kw("if") { Some(self.parse_expr()?) } else { None };
+                self.expect_sym("=>")?;
+                arms.push(Arm { pat, guard, body: self.parse_expr()? });
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+            self.expect_sym("}")?;
+            return mk(EK::Match(Box::new(scrut), arms));
+        }
+        if self.eat_kw("while") {
+            let init = self.parse_expr()?;
+            let cond = self.parse_expr()?;
+            let body = self.parse_expr()?;
+            return mk(EK::While(Box::new(init), Box::new(cond), Box::new(body)));
+        }
+        if self.eat_kw("for") {
+            let pat = self.parse_pat()?;
+            self.expect_kw("in")?;
+            let xs = self.parse_expr()?;
+            self.expect_kw("do")?;
+            return mk(EK::For(pat, Box::new(xs), Box::new(self.parse_expr()?)));
+        }
+        if self.eat_kw("return") {
+            return mk(EK::Return(Box::new(self.parse_expr()?)));
+        }
+        if self.eat_kw("if") {
+            let c = self.parse_expr()?;
+            self.expect_kw("then")?;
+            let is_block = self.is_sym("{")
+                && ((self.peek_n(1).kind == TK::Kw && matches!(self.peek_n(1).text.as_str(), "let" | "return"))
+                    || self.is_sym_n(1, "}"));
+            let t = if is_block {
+                let bsp = self.sp();
+                self.bump();
+                self.parse_block(bsp)?
+            } else {
+                self.parse_expr()?
+            };
+            self.expect_kw("else")?;
+            let f = self.parse_expr()?;
+            return mk(EK::If(Box::new(c), Box::new(t), Box::new(f)));
+        }
+        self.parse_infix(0)
+    }
+    fn infix_prec(&self) -> Option<u8> {
+        let t = self.peek();
+        if t.kind != TK::Sym {
+            return None;
+        }
+        match t.text.as_str() {
+            "||" => Some(1),
+            "&&" => Some(2),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(3),
+            "+" | "-" => Some(4),
+            "*" | "/" | "%" => Some(5),
+            _ => None,
+        }
+    }
+    fn parse_infix(&mut self, min_prec: u8) -> PResult<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(prec) = self.infix_prec().filter(|p| *p >= min_prec) {
+            let op = self.bump();
+            let rhs = self.parse_infix(prec + 1)?;
+            lhs = Expr { k: EK::Bin(op.text, Box::new(lhs), Box::new(rhs)), sp: op.sp };
+        }
+        Ok(lhs)
+    }
+    fn parse_unary(&mut self) -> PResult<Expr> {
+        let sp = self.sp();
+        if self.is_sym("-") || self.is_sym("!") {
+            let op = self.bump().text;
+            return Ok(Expr { k: EK::Unary(op, Box::new(self.parse_unary()?)), sp });
+        }
+        let mut e = self.parse_postfix()?;
+        while self.is_sym("|>") {
+            let psp = self.bump().sp;
+            let rhs = self.parse_postfix()?;
+            e = match rhs.k {
+                EK::Call(f, mut args) => {
+                    args.insert(0, e);
+                    Expr { k: EK::Call(f, args), sp: rhs.sp }
+                }
+                _ => Expr { k: EK::Call(Box::new(rhs), vec![e]), sp: psp },
+            };
+        }
+        Ok(e)
+    }
+    fn parse_postfix(&mut self) -> PResult<Expr> {
+        let mut e = self.parse_primary()?;
+        loop {
+            let sp = self.sp();
+            if self.eat_sym("?") {
+                e = Expr { k: EK::Try(Box::new(e)), sp };
+            } else if self.eat_sym(".") {
+                let n = self.expect_ident()?;
+                e = Expr { k: EK::Get(Box::new(e), n), sp };
+            } else if self.eat_sym("(") {
+                let mut args = Vec::new();
+                let mut named = Vec::new();
+                while !self.is_sym(")") {
+                    if self.peek().kind == TK::Ident && self.is_sym_n(1, ":") {
+                        let name = self.expect_ident()?;
+                        self.bump();
+                        named.push((name, self.parse_expr()?));
+                    } else {
+                        args.push(self.parse_expr()?);
+                    }
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(")")?;
+                // fardrun drops positional arguments from a call that names any
+                let k = if named.is_empty() {
+                    EK::Call(Box::new(e), args)
+                } else {
+                    EK::NamedCall(Box::new(e), named)
+                };
+                e = Expr { k, sp };
+            } else if self.is_sym("[") && !self.peek().nl_before && !is_literal(&e) {
+                self.bump();
+                let idx = self.parse_expr()?;
+                self.expect_sym("]")?;
+                e = Expr { k: EK::Index(Box::new(e), Box::new(idx)), sp };
+            } else {
+                return Ok(e);
+            }
+        }
+    }
+    fn parse_primary(&mut self) -> PResult<Expr> {
+        let sp = self.sp();
+        let mk = |k| Ok(Expr { k, sp });
+        let lambda = |name: String| Param { pat: Pat::Bind(name), ann: None };
+        if self.peek().kind == TK::Ident && self.is_sym_n(1, "=>") {
+            let name = self.bump().text;
+            self.bump();
+            return mk(EK::Fn(vec![lambda(name)], Box::new(self.parse_expr()?)));
+        }
+        if self.is_sym("(") {
+            // (a, b) => body
+            let mut n = 1;
+            let mut names = Vec::new();
+            let mut ok = true;
+            if !self.is_sym_n(1, ")") {
+                loop {
+                    if self.peek_n(n).kind != TK::Ident {
+                        ok = false;
+                        break;
+                    }
+                    names.push(self.peek_n(n).text.clone());
+                    n += 1;
+                    if self.is_sym_n(n, ",") {
+                        n += 1;
+                        continue;
+                    }
+                    ok = self.is_sym_n(n, ")");
+                    break;
+                }
+            }
+            if ok && self.is_sym_n(n + 1, "=>") {
+                for _ in 0..n + 2 {
+                    self.bump();
+                }
+                let params = names.into_iter().map(lambda).collect();
+                return mk(EK::Fn(params, Box::new(self.parse_expr()?)));
+            }
+        }
+        if self.eat_kw("fn") {
+            self.expect_sym("(")?;
+            let mut params = Vec::new();
+            while !self.is_sym(")") {
+                params.push(Param { pat: self.parse_pat()?, ann: None });
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+            self.expect_sym(")")?;
+            let bsp = self.sp();
+            self.expect_sym("{")?;
+            return mk(EK::Fn(params, Box::new(self.parse_block(bsp)?)));
+        }
+        let t = self.peek().clone();
+        match t.kind {
+            TK::Num => {
+                self.bump();
+                mk(if t.text.contains('.') { EK::Float } else { EK::Int })
+            }
+            TK::Str if t.interp.is_empty() => {
+                self.bump();
+                mk(EK::Str)
+            }
+            TK::Str => {
+                self.bump();
+                let mut parts = Vec::new();
+                for (src, at) in &t.interp {
+                    let mut p = Parser { toks: lex(src, *at)?, i: 0 };
+                    parts.push(p.parse_expr().map_err(|e| format!("{} (in string interpolation)", e))?);
+                }
+                mk(EK::Interp(parts))
+            }
+            TK::Kw if t.text == "true" || t.text == "false" => {
+                self.bump();
+                mk(EK::Bool)
+            }
+            TK::Kw if t.text == "null" => {
+                self.bump();
+                mk(EK::Null)
+            }
+            TK::Ident => {
+                self.bump();
+                mk(EK::Var(t.text))
+            }
+            TK::Sym if t.text == "(" => {
+                self.bump();
+                let e = self.parse_expr()?;
+                self.expect_sym(")")?;
+                Ok(e)
+            }
+            TK::Sym if t.text == "[" => {
+                self.bump();
+                let mut items = Vec::new();
+                if !self.is_sym("]") {
+                    let first = self.parse_expr()?;
+                    if self.eat_kw("for") {
+                        let pat = self.parse_pat()?;
+                        self.expect_kw("in")?;
+                        let iter = self.parse_expr()?;
+                        let cond = if self.eat_kw("if") { Some(Box::new(self.parse_expr()?)) } else { None };
+                        self.expect_sym("]")?;
+                        return mk(EK::Comp(Box::new(first), pat, Box::new(iter), cond));
+                    }
+                    items.push(first);
+                    while self.eat_sym(",") && !self.is_sym("]") {
+                        items.push(self.parse_expr()?);
+                    }
+                }
+                self.expect_sym("]")?;
+                mk(EK::List(items))
+            }
+            TK::Sym if t.text == "{" => {
+                self.bump();
+                let mut fields = Vec::new();
+                while !self.is_sym("}") {
+                    let k = self.peek().clone();
+                    if !matches!(k.kind, TK::Ident | TK::Kw | TK::Str) {
+                        return self.err("record key must be ident or string");
+                    }
+                    self.bump();
+                    self.expect_sym(":")?;
+                    let key = if k.kind == TK::Str { unquote(&k.text) } else { k.text };
+                    fields.push((key, self.parse_expr()?));
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym("}")?;
+                mk(EK::Rec(fields))
+            }
+            _ => self.err("unexpected token"),
         }
     }
 }
 
-// ── Simple line-tracking tokenizer ───────────────────────────────────────────
-#[derive(Clone, Debug, PartialEq)]
-enum Token {
-    // literals
-    Int(i64), Float(f64), Bool(bool), Str(String), Null,
-    // identifiers / keywords
-    Ident(String),
-    // punctuation
-    LParen, RParen, LBrace, RBrace, LBracket, RBracket,
-    Dot, Comma, Colon, Eq, Arrow, FatArrow,
-    Plus, Minus, Star, Slash, Percent,
-    EqEq, BangEq, Lt, Gt, LtEq, GtEq,
-    AmpAmp, PipePipe, Bang,
-    // keywords
-    Let, In, Fn, If, Then, Else, Import, As, Match, While, Return, Test,
-    // misc
-    Eof,
+/// fardrun never indexes a literal: `[1][0]` is two expressions.
+fn is_literal(e: &Expr) -> bool {
+    matches!(e.k, EK::Int | EK::Float | EK::Bool | EK::Str | EK::Null | EK::List(_) | EK::Rec(_))
 }
 
-struct Lexer {
-    chars: Vec<char>,
-    pos: usize,
-    line: usize,
+// ── Names bound anywhere in a file ───────────────────────────────────────────
+// A name that is not in scope where it is used, but is bound somewhere in the file,
+// may be a closure's forward reference; it is typed Dynamic rather than reported.
+fn pat_names(p: &Pat, out: &mut HashSet<String>) {
+    match p {
+        Pat::Bind(n) => {
+            out.insert(n.clone());
+        }
+        Pat::Obj(fs, rest) => {
+            for (k, sub) in fs {
+                match sub {
+                    Some(s) => pat_names(s, out),
+                    None => {
+                        out.insert(k.clone());
+                    }
+                }
+            }
+            out.extend(rest.iter().cloned());
+        }
+        Pat::List(ps, rest) => {
+            ps.iter().for_each(|s| pat_names(s, out));
+            out.extend(rest.iter().cloned());
+        }
+        Pat::Wild | Pat::Lit(_) => {}
+    }
 }
 
-impl Lexer {
-    fn new(src: &str) -> Self {
-        Lexer { chars: src.chars().collect(), pos: 0, line: 1 }
+fn expr_names(e: &Expr, out: &mut HashSet<String>) {
+    let mut go = |e: &Expr| expr_names(e, out);
+    match &e.k {
+        EK::Int | EK::Float | EK::Bool | EK::Str | EK::Null | EK::Var(_) => {}
+        EK::Interp(es) | EK::List(es) => es.iter().for_each(go),
+        EK::Rec(fs) => fs.iter().for_each(|(_, v)| go(v)),
+        EK::NamedCall(f, args) => {
+            go(f);
+            args.iter().for_each(|(_, v)| go(v));
+        }
+        EK::Call(f, args) => {
+            go(f);
+            args.iter().for_each(go);
+        }
+        EK::Get(b, _) | EK::Try(b) | EK::Unary(_, b) | EK::Return(b) => go(b),
+        EK::Index(a, b) | EK::Bin(_, a, b) => {
+            go(a);
+            go(b);
+        }
+        EK::If(a, b, c) | EK::While(a, b, c) => {
+            go(a);
+            go(b);
+            go(c);
+        }
+        EK::Fn(ps, body) => {
+            ps.iter().for_each(|p| pat_names(&p.pat, out));
+            expr_names(body, out);
+        }
+        EK::Comp(body, p, xs, cond) => {
+            pat_names(p, out);
+            expr_names(body, out);
+            expr_names(xs, out);
+            if let Some(c) = cond {
+                expr_names(c, out);
+            }
+        }
+        EK::LetIn(p, a, b) | EK::Using(p, a, b) | EK::For(p, a, b) => {
+            pat_names(p, out);
+            expr_names(a, out);
+            expr_names(b, out);
+        }
+        EK::Block(stmts) => {
+            for s in stmts {
+                match s {
+                    Stmt::Let(p, e) => {
+                        pat_names(p, out);
+                        expr_names(e, out);
+                    }
+                    Stmt::Expr(e) => expr_names(e, out),
+                }
+            }
+        }
+        EK::Match(s, arms) => {
+            expr_names(s, out);
+            for a in arms {
+                pat_names(&a.pat, out);
+                if let Some(g) = &a.guard {
+                    expr_names(g, out);
+                }
+                expr_names(&a.body, out);
+            }
+        }
     }
+}
 
-    fn peek(&self) -> char { self.chars.get(self.pos).copied().unwrap_or('\0') }
-    fn advance(&mut self) -> char {
-        let c = self.peek();
-        self.pos += 1;
-        if c == '\n' { self.line += 1; }
-        c
+/// The direct sub-expressions of `e`.
+fn children(e: &Expr) -> Vec<&Expr> {
+    match &e.k {
+        EK::Int | EK::Float | EK::Bool | EK::Str | EK::Null | EK::Var(_) => vec![],
+        EK::Interp(es) | EK::List(es) => es.iter().collect(),
+        EK::Rec(fs) => fs.iter().map(|(_, v)| v).collect(),
+        EK::NamedCall(f, args) => std::iter::once(&**f).chain(args.iter().map(|(_, v)| v)).collect(),
+        EK::Call(f, args) => std::iter::once(&**f).chain(args.iter()).collect(),
+        EK::Get(b, _) | EK::Try(b) | EK::Unary(_, b) | EK::Return(b) | EK::Fn(_, b) => vec![b],
+        EK::Index(a, b) | EK::Bin(_, a, b) | EK::LetIn(_, a, b) | EK::Using(_, a, b) | EK::For(_, a, b) => {
+            vec![a, b]
+        }
+        EK::If(a, b, c) | EK::While(a, b, c) => vec![a, b, c],
+        EK::Comp(body, _, xs, cond) => [&**body, &**xs].into_iter().chain(cond.as_deref()).collect(),
+        EK::Block(stmts) => stmts
+            .iter()
+            .map(|s| match s {
+                Stmt::Let(_, e) | Stmt::Expr(e) => e,
+            })
+            .collect(),
+        EK::Match(s, arms) => std::iter::once(&**s)
+            .chain(arms.iter().flat_map(|a| a.guard.iter().chain(std::iter::once(&a.body))))
+            .collect(),
     }
+}
 
-    fn skip_ws(&mut self) {
-        loop {
-            match self.peek() {
-                ' ' | '\t' | '\r' | '\n' => { self.advance(); }
-                '/' if self.chars.get(self.pos+1) == Some(&'/') => {
-                    while self.peek() != '\n' && self.peek() != '\0' { self.advance(); }
-                }
-                _ => break,
+/// `(module alias, variable)` for every `m.of(x)` in `e`. A variable whose type is
+/// inspected at run time is used at several types on purpose, so it stays Dynamic.
+fn type_inspected(e: &Expr, out: &mut Vec<(String, String)>) {
+    if let EK::Call(f, args) = &e.k {
+        if let (EK::Get(m, name), [Expr { k: EK::Var(x), .. }]) = (&f.k, args.as_slice()) {
+            if let (EK::Var(m), "of") = (&m.k, name.as_str()) {
+                out.push((m.clone(), x.clone()));
             }
         }
     }
+    children(e).into_iter().for_each(|c| type_inspected(c, out));
+}
 
-    fn next_tok(&mut self) -> (Token, usize) {
-        self.skip_ws();
-        let line = self.line;
-        let c = self.peek();
-        if c == '\0' { return (Token::Eof, line); }
-        self.advance();
-        let tok = match c {
-            '(' => Token::LParen,
-            ')' => Token::RParen,
-            '{' => Token::LBrace,
-            '}' => Token::RBrace,
-            '[' => Token::LBracket,
-            ']' => Token::RBracket,
-            '.' => Token::Dot,
-            ',' => Token::Comma,
-            ':' => Token::Colon,
-            '+' => Token::Plus,
-            '*' => Token::Star,
-            '%' => Token::Percent,
-            '-' => if self.peek() == '>' { self.advance(); Token::Arrow } else { Token::Minus },
-            '/' => Token::Slash,
-            '=' => if self.peek() == '=' { self.advance(); Token::EqEq }
-                   else if self.peek() == '>' { self.advance(); Token::FatArrow }
-                   else { Token::Eq },
-            '!' => if self.peek() == '=' { self.advance(); Token::BangEq } else { Token::Bang },
-            '<' => if self.peek() == '=' { self.advance(); Token::LtEq } else { Token::Lt },
-            '>' => if self.peek() == '=' { self.advance(); Token::GtEq } else { Token::Gt },
-            '&' => { if self.peek() == '&' { self.advance(); } Token::AmpAmp },
-            '|' => { if self.peek() == '|' { self.advance(); } Token::PipePipe },
-            '"' => {
-                let mut s = String::new();
-                while self.peek() != '"' && self.peek() != '\0' {
-                    let ch = self.advance();
-                    if ch == '\\' { self.advance(); } // skip escape
-                    else if ch == '$' && self.peek() == '{' {
-                        // skip interpolation
-                        self.advance();
-                        let mut depth = 1;
-                        while depth > 0 && self.peek() != '\0' {
-                            match self.advance() {
-                                '{' => depth += 1,
-                                '}' => depth -= 1,
-                                _ => {}
-                            }
-                        }
-                    } else {
-                        s.push(ch);
-                    }
+fn file_names(items: &[Item]) -> HashSet<String> {
+    let mut out = HashSet::new();
+    for it in items {
+        match &it.k {
+            IK::Import(_, n) | IK::Artifact(n) => {
+                out.insert(n.clone());
+            }
+            IK::Let(p, e) => {
+                pat_names(p, &mut out);
+                expr_names(e, &mut out);
+            }
+            IK::Fn(n, ps, _, body) => {
+                out.insert(n.clone());
+                ps.iter().for_each(|p| pat_names(&p.pat, &mut out));
+                expr_names(body, &mut out);
+            }
+            IK::TypeDef(n, kind) => {
+                out.insert(n.clone());
+                if let TypeDefKind::Sum(vs) = kind {
+                    out.extend(vs.iter().map(|(v, _)| v.clone()));
                 }
-                self.advance(); // closing "
-                Token::Str(s)
-            }
-            '`' => {
-                while self.peek() != '`' && self.peek() != '\0' { self.advance(); }
-                self.advance();
-                Token::Str(String::new())
-            }
-            _ if c.is_ascii_digit() => {
-                let mut s = String::from(c);
-                while self.peek().is_ascii_digit() { s.push(self.advance()); }
-                if self.peek() == '.' && self.chars.get(self.pos+1).map(|c| c.is_ascii_digit()).unwrap_or(false) {
-                    s.push(self.advance());
-                    while self.peek().is_ascii_digit() { s.push(self.advance()); }
-                    Token::Float(s.parse().unwrap_or(0.0))
-                } else {
-                    Token::Int(s.parse().unwrap_or(0))
-                }
-            }
-            _ if c.is_alphabetic() || c == '_' => {
-                let mut s = String::from(c);
-                while self.peek().is_alphanumeric() || self.peek() == '_' { s.push(self.advance()); }
-                match s.as_str() {
-                    "let" => Token::Let,
-                    "in" => Token::In,
-                    "fn" => Token::Fn,
-                    "if" => Token::If,
-                    "then" => Token::Then,
-                    "else" => Token::Else,
-                    "true" => Token::Bool(true),
-                    "false" => Token::Bool(false),
-                    "null" => Token::Null,
-                    "import" => Token::Import,
-                    "as" => Token::As,
-                    "match" => Token::Match,
-                    "while" => Token::While,
-                    "return" => Token::Return,
-                    "test" => Token::Test,
-                    _ => Token::Ident(s),
-                }
-            }
-            _ => Token::Ident(String::from(c)),
-        };
-        (tok, line)
-    }
-
-    fn tokenize(&mut self) -> Vec<(Token, usize)> {
-        let mut toks = Vec::new();
-        loop {
-            let t = self.next_tok();
-            let eof = t.0 == Token::Eof;
-            toks.push(t);
-            if eof { break; }
+            }
+            IK::Test(_, e) | IK::Expr(e) => expr_names(e, &mut out),
+            IK::Export(_) => {}
         }
-        toks
     }
+    out
 }
 
-// ── Parser (produces typed AST nodes inline) ─────────────────────────────────
-struct Parser {
-    toks: Vec<(Token, usize)>,
-    pos: usize,
+// ── Checker ──────────────────────────────────────────────────────────────────
+/// Per-file state; swapped out while an imported module is checked.
+#[derive(Default)]
+struct FileCtx {
+    label: String,
+    here: PathBuf,
+    env: Vec<HashMap<String, Scheme>>,
+    /// Every name bound somewhere in the file
+    names: HashSet<String>,
+    /// Declared type name → type key
+    types: HashMap<String, String>,
+    /// One flag per enclosing fn: did its body `return` early?
+    returns: Vec<bool>,
 }
 
-#[derive(Clone, Debug)]
-enum Expr {
-    Int(i64, usize),
-    Float(f64, usize),
-    Bool(bool, usize),
-    Str(usize),
-    Null(usize),
-    Var(String, usize),
-    List(Vec<Expr>, usize),
-    Rec(Vec<(String, Expr)>, usize),
-    Get(Box<Expr>, String, usize),
-    Index(Box<Expr>, Box<Expr>, usize),
-    Call(Box<Expr>, Vec<Expr>, usize),
-    Fn(Vec<String>, Box<Expr>, usize),
-    Let(String, Box<Expr>, Box<Expr>, usize),
-    If(Box<Expr>, Box<Expr>, Box<Expr>, usize),
-    Bin(String, Box<Expr>, Box<Expr>, usize),
-    Unary(String, Box<Expr>, usize),
-    Block(Vec<(String, Expr)>, Box<Expr>, usize), // let chain ending in expr
+struct Checker {
+    subst: HashMap<u32, Ty>,
+    /// Variables bound since the start, so a failed trial unification can be undone
+    trail: Vec<u32>,
+    next_var: u32,
+    errors: Vec<TyError>,
+    decls: HashMap<String, TypeDecl>,
+    /// Module key → its exports, or None when they are unknown
+    modules: HashMap<String, Option<BTreeMap<String, Scheme>>>,
+    loading: Vec<String>,
+    root: PathBuf,
+    registry: Option<PathBuf>,
+    deps: HashMap<String, String>,
+    arity_min: HashMap<String, usize>,
+    cx: FileCtx,
 }
 
-impl Parser {
-    fn new(toks: Vec<(Token, usize)>) -> Self { Parser { toks, pos: 0 } }
+impl Checker {
+    fn new(root: PathBuf, registry: Option<PathBuf>) -> Self {
+        let mut deps = HashMap::new();
+        // fard.toml [deps]: short name = "name@version"
+        if let Ok(src) = std::fs::read_to_string(root.join("fard.toml")) {
+            let mut in_deps = false;
+            for line in src.lines().map(str::trim) {
+                if line.starts_with('[') {
+                    in_deps = line == "[deps]";
+                } else if let (true, Some((k, v))) = (in_deps, line.split_once('=')) {
+                    deps.insert(k.trim().to_string(), v.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+        let arity_min = builtin_sig_table_v1()
+            .into_iter()
+            .map(|(k, s)| (k.to_string(), s.arity_min))
+            .collect();
+        Checker {
+            subst: HashMap::new(),
+            trail: Vec::new(),
+            next_var: 0,
+            errors: Vec::new(),
+            decls: HashMap::new(),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            root,
+            registry,
+            deps,
+            arity_min,
+            cx: FileCtx::default(),
+        }
+    }
+
+    fn fresh(&mut self) -> Ty {
+        let n = self.next_var;
+        self.next_var += 1;
+        Ty::Var(n)
+    }
+
+    fn push(&mut self) {
+        self.cx.env.push(HashMap::new());
+    }
+    fn pop(&mut self) {
+        self.cx.env.pop();
+    }
+
+    fn define(&mut self, name: &str, ty: Ty) {
+        self.define_scheme(name, Scheme::mono(ty));
+    }
+    fn define_scheme(&mut self, name: &str, scheme: Scheme) {
+        if let Some(top) = self.cx.env.last_mut() {
+            top.insert(name.to_string(), scheme);
+        }
+    }
+    fn scheme_of(&self, name: &str) -> Option<Scheme> {
+        self.cx.env.iter().rev().find_map(|scope| scope.get(name)).cloned()
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Ty {
+        if scheme.vars.is_empty() {
+            return scheme.ty.clone();
+        }
+        let map: HashMap<u32, Ty> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        fn go(t: &Ty, m: &HashMap<u32, Ty>) -> Ty {
+            match t {
+                Ty::Var(n) => m.get(n).cloned().unwrap_or(Ty::Var(*n)),
+                Ty::List(i) => Ty::List(Box::new(go(i, m))),
+                Ty::Func(ps, r) => Ty::Func(ps.iter().map(|p| go(p, m)).collect(), Box::new(go(r, m))),
+                Ty::Rec(fs) => Ty::Rec(fs.iter().map(|(k, v)| (k.clone(), go(v, m))).collect()),
+                other => other.clone(),
+            }
+        }
+        go(&scheme.ty, &map)
+    }
 
-    fn peek(&self) -> &Token { &self.toks[self.pos.min(self.toks.len()-1)].0 }
-    fn line(&self) -> usize { self.toks[self.pos.min(self.toks.len()-1)].1 }
+    fn generalize(&self, ty: &Ty) -> Scheme {
+        let ty = self.apply(ty);
+        let mut vars = Vec::new();
+        ty.free_vars(&mut vars);
+        let mut env_vars = Vec::new();
+        for scope in &self.cx.env {
+            for s in scope.values() {
+                self.apply(&s.ty).free_vars(&mut env_vars);
+            }
+        }
+        vars.retain(|v| !env_vars.contains(v));
+        vars.sort();
+        vars.dedup();
+        Scheme { vars, ty, params: None, ctor: None }
+    }
 
-    fn eat(&mut self) -> &Token {
-        let p = self.pos;
-        if self.pos < self.toks.len() - 1 { self.pos += 1; }
-        &self.toks[p].0
+    fn apply(&self, t: &Ty) -> Ty {
+        match t {
+            Ty::Var(n) => match self.subst.get(n) {
+                Some(t2) => self.apply(t2),
+                None => t.clone(),
+            },
+            Ty::List(inner) => Ty::List(Box::new(self.apply(inner))),
+            Ty::Func(ps, r) => Ty::Func(ps.iter().map(|p| self.apply(p)).collect(), Box::new(self.apply(r))),
+            Ty::Rec(fs) => Ty::Rec(fs.iter().map(|(k, v)| (k.clone(), self.apply(v))).collect()),
+            other => other.clone(),
+        }
     }
 
-    fn expect(&mut self, t: &Token) -> Result<()> {
-        if self.peek() == t { self.eat(); Ok(()) }
-        else { bail!("expected {:?}, got {:?} at line {}", t, self.peek(), self.line()) }
+    fn occurs(&self, n: u32, t: &Ty) -> bool {
+        let mut vs = Vec::new();
+        self.apply(t).free_vars(&mut vs);
+        vs.contains(&n)
     }
 
-    fn parse_program(&mut self) -> Vec<TopItem> {
-        let mut items = Vec::new();
-        loop {
-            match self.peek() {
-                Token::Eof => break,
-                Token::Import => { self.eat(); self.skip_import(); }
-                Token::Test => { self.eat(); items.push(self.parse_test()); }
-                Token::Let => {
-                    self.eat();
-                    if let Ok(item) = self.parse_top_let() {
-                        items.push(item);
+    fn unify(&mut self, a: &Ty, b: &Ty) -> Result<(), ()> {
+        let a = self.apply(a);
+        let b = self.apply(b);
+        match (&a, &b) {
+            (Ty::Dynamic, _) | (_, Ty::Dynamic) => Ok(()),
+            (Ty::Var(x), Ty::Var(y)) if x == y => Ok(()),
+            (Ty::Var(n), t) | (t, Ty::Var(n)) => {
+                if !self.occurs(*n, t) {
+                    self.subst.insert(*n, t.clone());
+                    self.trail.push(*n);
+                }
+                Ok(())
+            }
+            (Ty::Null, _) | (_, Ty::Null) => Ok(()),
+            (x, y) if x.is_numeric() && y.is_numeric() => Ok(()),
+            (Ty::Bool, Ty::Bool) | (Ty::Str, Ty::Str) | (Ty::Bytes, Ty::Bytes) => Ok(()),
+            (Ty::List(x), Ty::List(y)) => self.unify(x, y),
+            (Ty::Rec(fa), Ty::Rec(fb)) => {
+                for (k, ta) in fa {
+                    if let Some((_, tb)) = fb.iter().find(|(kb, _)| kb == k) {
+                        self.unify(ta, tb)?;
                     }
                 }
-                Token::Fn => {
-                    self.eat();
-                    if let Ok(item) = self.parse_top_fn() {
-                        items.push(item);
+                Ok(())
+            }
+            (Ty::Func(pa, ra), Ty::Func(pb, rb)) => {
+                if pa.len() != pb.len() {
+                    return Err(());
+                }
+                for (x, y) in pa.iter().zip(pb.iter()) {
+                    self.unify(x, y)?;
+                }
+                self.unify(ra, rb)
+            }
+            (Ty::AnyFn(_), Ty::Func(..) | Ty::AnyFn(_)) | (Ty::Func(..), Ty::AnyFn(_)) => Ok(()),
+            (Ty::Named(x), Ty::Named(y)) => if x == y { Ok(()) } else { Err(()) },
+            (Ty::Named(k), Ty::Rec(fs)) | (Ty::Rec(fs), Ty::Named(k)) => {
+                if let Some(TypeDecl::Record(decl)) = self.decls.get(k).cloned() {
+                    for (f, t) in fs {
+                        if let Some((_, dt)) = decl.iter().find(|(d, _)| d == f) {
+                            self.unify(dt, t)?;
+                        }
                     }
                 }
-                _ => { self.eat(); } // skip unknown top-level tokens
+                Ok(())
             }
+            (Ty::Module(_), Ty::Module(_) | Ty::Rec(_)) | (Ty::Rec(_), Ty::Module(_)) => Ok(()),
+            _ => Err(()),
         }
-        items
     }
 
-    fn skip_import(&mut self) {
-        // import("...") as name  — skip to past 'as name'
-        while !matches!(self.peek(), Token::As | Token::Eof) { self.eat(); }
-        if self.peek() == &Token::As { self.eat(); self.eat(); } // as <name>
+    /// Unify, or undo any partial bindings and report failure.
+    fn try_unify(&mut self, a: &Ty, b: &Ty) -> bool {
+        let mark = self.trail.len();
+        if self.unify(a, b).is_ok() {
+            return true;
+        }
+        for v in self.trail.drain(mark..) {
+            self.subst.remove(&v);
+        }
+        false
     }
 
-    fn parse_test(&mut self) -> TopItem {
-        let line = self.line();
-        // test "label" { expr }
-        let label = match self.eat().clone() {
-            Token::Str(s) => s,
-            _ => String::new(),
-        };
-        let _ = self.expect(&Token::LBrace);
-        let expr = self.parse_expr();
-        let _ = self.expect(&Token::RBrace);
-        TopItem::Test(label, expr, line)
+    /// The common type of two branches or list elements; Dynamic when they differ.
+    /// Lists and branches may legitimately mix types, so two distinct variables are never
+    /// unified here — only a variable meeting a concrete type is bound.
+    fn join(&mut self, a: &Ty, b: &Ty) -> Ty {
+        let (a, b) = (self.apply(a), self.apply(b));
+        match (&a, &b) {
+            _ if a == b => a,
+            (Ty::Null, _) => b,
+            (_, Ty::Null) => a,
+            (Ty::Var(_), Ty::Var(_)) => Ty::Dynamic,
+            (Ty::Var(_), _) | (_, Ty::Var(_)) if self.try_unify(&a, &b) => self.apply(&a),
+            (Ty::List(x), Ty::List(y)) => Ty::List(Box::new(self.join(x, y))),
+            (Ty::Rec(fa), Ty::Rec(fb))
+                if fa.len() == fb.len() && fa.iter().all(|(k, _)| fb.iter().any(|(kb, _)| kb == k)) =>
+            {
+                let fields = fa
+                    .iter()
+                    .map(|(k, ta)| {
+                        let tb = fb.iter().find(|(kb, _)| kb == k).map(|(_, t)| t.clone()).unwrap_or(Ty::Dynamic);
+                        (k.clone(), self.join(ta, &tb))
+                    })
+                    .collect();
+                Ty::Rec(fields)
+            }
+            (Ty::Named(_), Ty::Rec(_)) | (Ty::Rec(_), Ty::Named(_)) if self.try_unify(&a, &b) => {
+                if matches!(a, Ty::Named(_)) { a } else { b }
+            }
+            _ => Ty::Dynamic,
+        }
     }
 
-    fn parse_top_let(&mut self) -> Result<TopItem> {
-        let line = self.line();
-        let name = match self.eat().clone() {
-            Token::Ident(s) => s,
-            t => bail!("expected ident, got {:?}", t),
-        };
-        self.expect(&Token::Eq)?;
-        let expr = self.parse_expr();
-        Ok(TopItem::Let(name, expr, line))
+    fn err(&mut self, sp: Sp, msg: String) {
+        let e = TyError { file: self.cx.label.clone(), line: sp.line, col: sp.col, msg };
+        if !self.errors.contains(&e) {
+            self.errors.push(e);
+        }
     }
 
-    fn parse_top_fn(&mut self) -> Result<TopItem> {
-        let line = self.line();
-        let name = match self.eat().clone() {
-            Token::Ident(s) => s,
-            t => bail!("expected ident, got {:?}", t),
-        };
-        self.expect(&Token::LParen)?;
-        let mut params = Vec::new();
-        while self.peek() != &Token::RParen && self.peek() != &Token::Eof {
-            if let Token::Ident(p) = self.eat().clone() { params.push(p); }
-            if self.peek() == &Token::Comma { self.eat(); }
-        }
-        self.expect(&Token::RParen)?;
-        self.expect(&Token::LBrace)?;
-        let body = self.parse_block_expr();
-        self.expect(&Token::RBrace)?;
-        Ok(TopItem::Fn(name, params, body, line))
-    }
-
-    fn parse_block_expr(&mut self) -> Expr {
-        // sequence of let-bindings ending in an expression
-        let line = self.line();
-        let mut bindings: Vec<(String, Expr)> = Vec::new();
-        loop {
-            if self.peek() == &Token::Let {
-                self.eat();
-                let name = match self.eat().clone() {
-                    Token::Ident(s) => s,
-                    _ => "_".to_string(),
-                };
-                // skip optional pattern, expect =
-                while !matches!(self.peek(), Token::Eq | Token::Eof) { self.eat(); }
-                self.eat(); // =
-                let val = self.parse_expr();
-                // optional 'in'
-                if self.peek() == &Token::In { self.eat(); }
-                bindings.push((name, val));
-            } else {
-                break;
-            }
-        }
-        let tail = self.parse_expr();
-        if bindings.is_empty() {
-            tail
-        } else {
-            Expr::Block(bindings, Box::new(tail), line)
+    /// Unify `expected` with `got`, reporting "{ctx}: expected X, got Y" on failure.
+    fn expect(&mut self, expected: &Ty, got: &Ty, sp: Sp, ctx: &str) {
+        if !self.try_unify(expected, got) {
+            let mut sh = Shower::default();
+            let (e, g) = (sh.show(&self.apply(expected)), sh.show(&self.apply(got)));
+            self.err(sp, format!("{}: expected {}, got {}", ctx, e, g));
         }
     }
 
-    fn parse_expr(&mut self) -> Expr {
-        self.parse_or()
+    fn show(&self, t: &Ty) -> String {
+        Shower::default().show(&self.apply(t))
     }
 
-    fn parse_or(&mut self) -> Expr {
-        let mut lhs = self.parse_and();
-        while self.peek() == &Token::PipePipe {
-            let line = self.line();
-            self.eat();
-            let rhs = self.parse_and();
-            lhs = Expr::Bin("||".to_string(), Box::new(lhs), Box::new(rhs), line);
+    // ── Declared types ───────────────────────────────────────────────────────
+    fn field_ty(&self, name: &str) -> Ty {
+        match name {
+            "Int" => Ty::Int,
+            "Float" => Ty::Float,
+            "Bool" => Ty::Bool,
+            "String" | "Str" | "Text" => Ty::Str,
+            "Null" | "Unit" => Ty::Null,
+            "Bytes" => Ty::Bytes,
+            "List" => Ty::List(Box::new(Ty::Dynamic)),
+            _ => self.cx.types.get(name).map(|k| Ty::Named(k.clone())).unwrap_or(Ty::Dynamic),
         }
-        lhs
     }
 
-    fn parse_and(&mut self) -> Expr {
-        let mut lhs = self.parse_cmp();
-        while self.peek() == &Token::AmpAmp {
-            let line = self.line();
-            self.eat();
-            let rhs = self.parse_cmp();
-            lhs = Expr::Bin("&&".to_string(), Box::new(lhs), Box::new(rhs), line);
+    fn ann_ty(&self, t: &TypeAnn) -> Ty {
+        match t {
+            TypeAnn::Named(n, args) if n == "List" => {
+                Ty::List(Box::new(args.first().map(|a| self.ann_ty(a)).unwrap_or(Ty::Dynamic)))
+            }
+            TypeAnn::Named(n, _) => self.field_ty(n),
+            TypeAnn::Rec(fs) => Ty::Rec(fs.iter().map(|(k, v)| (k.clone(), self.ann_ty(v))).collect()),
+            TypeAnn::Func(ps, r) => Ty::Func(ps.iter().map(|p| self.ann_ty(p)).collect(), Box::new(self.ann_ty(r))),
         }
-        lhs
     }
 
-    fn parse_cmp(&mut self) -> Expr {
-        let mut lhs = self.parse_add();
-        loop {
-            let op = match self.peek() {
-                Token::EqEq => "==",
-                Token::BangEq => "!=",
-                Token::Lt => "<",
-                Token::Gt => ">",
-                Token::LtEq => "<=",
-                Token::GtEq => ">=",
-                _ => break,
-            }.to_string();
-            let line = self.line();
-            self.eat();
-            let rhs = self.parse_add();
-            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs), line);
+    fn declare_types(&mut self, items: &[Item]) {
+        for it in items {
+            if let IK::TypeDef(name, _) = &it.k {
+                let key = format!("{}@{}", name, self.cx.label);
+                self.cx.types.insert(name.clone(), key);
+            }
+        }
+        for it in items {
+            let IK::TypeDef(name, kind) = &it.k else { continue };
+            let key = self.cx.types[name].clone();
+            let fields = |fs: &Fields| -> Vec<(String, Ty)> {
+                fs.iter().map(|(f, t)| (f.clone(), self.field_ty(t))).collect()
+            };
+            let decl = match kind {
+                TypeDefKind::Record(fs) => TypeDecl::Record(fields(fs)),
+                TypeDefKind::Sum(vs) => TypeDecl::Sum(vs.iter().map(|(v, fs)| (v.clone(), fields(fs))).collect()),
+            };
+            let ctor = |variant: Option<&String>| Scheme {
+                vars: vec![],
+                ty: Ty::Func(vec![Ty::Dynamic], Box::new(Ty::Named(key.clone()))),
+                params: None,
+                ctor: Some((key.clone(), variant.cloned())),
+            };
+            match kind {
+                TypeDefKind::Record(_) => {
+                    let s = ctor(None);
+                    self.define_scheme(name, s);
+                }
+                TypeDefKind::Sum(vs) => {
+                    for (v, _) in vs {
+                        let s = ctor(Some(v));
+                        self.define_scheme(v, s);
+                    }
+                }
+            }
+            self.decls.insert(key, decl);
         }
-        lhs
     }
 
-    fn parse_add(&mut self) -> Expr {
-        let mut lhs = self.parse_mul();
-        loop {
-            let op = match self.peek() {
-                Token::Plus => "+",
-                Token::Minus => "-",
-                _ => break,
-            }.to_string();
-            let line = self.line();
-            self.eat();
-            let rhs = self.parse_mul();
-            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs), line);
+    /// `Point({..})` / `Circle({..})`: the argument must be a record with every declared field.
+    fn check_ctor(&mut self, key: &str, variant: Option<&str>, args: &[Expr], sp: Sp) -> Ty {
+        let arg_tys: Vec<Ty> = args.iter().map(|a| self.infer(a)).collect();
+        let tname = key.split('@').next().unwrap_or(key).to_string();
+        let label = match variant {
+            Some(v) => format!("{}::{}", tname, v),
+            None => tname,
+        };
+        let fields = match (self.decls.get(key), variant) {
+            (Some(TypeDecl::Record(fs)), None) => fs.clone(),
+            (Some(TypeDecl::Sum(vs)), Some(v)) => {
+                vs.iter().find(|(n, _)| n == v).map(|(_, fs)| fs.clone()).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+        if arg_tys.len() != 1 {
+            self.err(sp, format!("{} takes exactly one record argument, got {}", label, arg_tys.len()));
+            return Ty::Named(key.to_string());
+        }
+        match self.apply(&arg_tys[0]) {
+            Ty::Rec(got) => {
+                let mut missing: Vec<&str> = fields
+                    .iter()
+                    .filter(|(f, _)| !got.iter().any(|(g, _)| g == f))
+                    .map(|(f, _)| f.as_str())
+                    .collect();
+                if !missing.is_empty() {
+                    missing.sort();
+                    self.err(args[0].sp, format!("{}: missing required field(s): {}", label, missing.join(", ")));
+                }
+                for (f, ft) in &fields {
+                    if let Some((_, gt)) = got.iter().find(|(g, _)| g == f) {
+                        let gt = gt.clone();
+                        self.expect(ft, &gt, args[0].sp, &format!("field '{}' of {}", f, label));
+                    }
+                }
+            }
+            Ty::Named(k) if k == key => {}
+            t @ (Ty::Int | Ty::Float | Ty::Bool | Ty::Str | Ty::List(_) | Ty::Func(..) | Ty::Named(_)) => {
+                let got = Shower::default().show(&t);
+                self.err(args[0].sp, format!("{} expects a record, got {}", label, got));
+            }
+            _ => {}
         }
-        lhs
+        Ty::Named(key.to_string())
     }
 
-    fn parse_mul(&mut self) -> Expr {
-        let mut lhs = self.parse_unary();
-        loop {
-            let op = match self.peek() {
-                Token::Star => "*",
-                Token::Slash => "/",
-                Token::Percent => "%",
-                _ => break,
-            }.to_string();
-            let line = self.line();
-            self.eat();
-            let rhs = self.parse_unary();
-            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs), line);
+    // ── Modules ──────────────────────────────────────────────────────────────
+    fn std_module(&mut self, key: &str) -> Option<BTreeMap<String, Scheme>> {
+        let mut out = BTreeMap::new();
+        for (m, name, kind) in STDLIB_EXPORTS {
+            if *m != key {
+                continue;
+            }
+            let full = format!("{}::{}", m, name);
+            let ty = match STDLIB_SIGS.iter().find(|(k, _)| *k == full) {
+                // Optional or variadic arguments: untyped, at least `arity_min` of them
+                Some((_, "fn")) => Ty::AnyFn(self.arity_min.get(&full).copied().unwrap_or(0)),
+                Some((_, sig)) => {
+                    let mut sp = SigParser { s: sig.as_bytes(), i: 0, vars: HashMap::new() };
+                    let mut n = self.next_var;
+                    let ty = sp.ty(&mut || {
+                        n += 1;
+                        n - 1
+                    });
+                    self.next_var = n;
+                    ty
+                }
+                None => match *kind {
+                    "fn" => Ty::AnyFn(self.arity_min.get(&full).copied().unwrap_or(0)),
+                    "float" => Ty::Float,
+                    "int" => Ty::Int,
+                    _ => Ty::Dynamic,
+                },
+            };
+            let mut vars = Vec::new();
+            ty.free_vars(&mut vars);
+            vars.sort();
+            vars.dedup();
+            out.insert(name.to_string(), Scheme { vars, ty, params: None, ctor: None });
         }
-        lhs
+        if out.is_empty() { None } else { Some(out) }
     }
 
-    fn parse_unary(&mut self) -> Expr {
-        let line = self.line();
-        if self.peek() == &Token::Bang {
-            self.eat();
-            return Expr::Unary("!".to_string(), Box::new(self.parse_unary()), line);
+    /// Type an `import(path) as alias`, checking the imported file the first time it is seen.
+    fn import(&mut self, path: &str, sp: Sp) -> Ty {
+        if let Some(m) = path.strip_prefix("std/") {
+            let key = if m == "record" { "std/rec".to_string() } else { path.to_string() };
+            if !self.modules.contains_key(&key) {
+                let exports = self.std_module(&key);
+                if exports.is_none() {
+                    self.err(sp, format!("unknown std module '{}'", path));
+                    return Ty::Dynamic;
+                }
+                self.modules.insert(key.clone(), exports);
+            }
+            return Ty::Module(key);
+        }
+        let file = if let Some(spec) = path.strip_prefix("pkg:").or_else(|| path.strip_prefix("pkg/")) {
+            match self.resolve_pkg(spec) {
+                Some(f) => f,
+                None => return Ty::Dynamic,
+            }
+        } else if let Some(rest) = path.strip_prefix("registry/") {
+            match &self.registry {
+                Some(reg) => reg.join(format!("{}.fard", rest)),
+                None => return Ty::Dynamic,
+            }
+        } else {
+            let base = if path.starts_with("lib/") { self.root.clone() } else { self.cx.here.clone() };
+            let f = base.join(format!("{}.fard", path));
+            if !f.is_file() {
+                self.err(sp, format!("cannot find module '{}' (looked for {})", path, f.display()));
+                return Ty::Dynamic;
+            }
+            f
+        };
+        let key = std::fs::canonicalize(&file).unwrap_or_else(|_| file.clone()).display().to_string();
+        if self.loading.contains(&key) {
+            return Ty::Dynamic;
         }
-        if self.peek() == &Token::Minus {
-            self.eat();
-            return Expr::Unary("-".to_string(), Box::new(self.parse_unary()), line);
+        if !self.modules.contains_key(&key) {
+            let src = match std::fs::read_to_string(&file) {
+                Ok(s) => s,
+                Err(e) => {
+                    self.err(sp, format!("cannot read module '{}': {}", path, e));
+                    return Ty::Dynamic;
+                }
+            };
+            let items = match parse_module(&src) {
+                Ok(items) => items,
+                Err(e) => {
+                    self.err(sp, format!("module '{}' does not parse: {}", path, e));
+                    return Ty::Dynamic;
+                }
+            };
+            self.loading.push(key.clone());
+            let here = file.parent().map(Path::to_path_buf).unwrap_or_default();
+            let saved = std::mem::take(&mut self.cx);
+            let exports = self.check_file(&items, file.display().to_string(), here);
+            self.cx = saved;
+            self.loading.pop();
+            self.modules.insert(key.clone(), exports);
         }
-        self.parse_postfix()
+        Ty::Module(key)
     }
 
-    fn parse_postfix(&mut self) -> Expr {
-        let mut e = self.parse_atom();
-        loop {
-            let line = self.line();
-            match self.peek() {
-                Token::Dot => {
-                    self.eat();
-                    if let Token::Ident(f) = self.eat().clone() {
-                        if self.peek() == &Token::LParen {
-                            self.eat();
-                            let args = self.parse_args();
-                            self.eat(); // )
-                            // method call: e.f(args) -> Call(Get(e, f), args)
-                            let getter = Expr::Get(Box::new(e), f, line);
-                            e = Expr::Call(Box::new(getter), args, line);
-                        } else {
-                            e = Expr::Get(Box::new(e), f, line);
-                        }
+    /// `name@ver[/mod]` (or a short name from fard.toml [deps]) in --registry or the
+    /// local package cache; None when the package is not available offline.
+    fn resolve_pkg(&self, spec: &str) -> Option<PathBuf> {
+        let spec = if spec.contains('@') { spec.to_string() } else { self.deps.get(spec)?.clone() };
+        let (pkg, ver_and_mod) = spec.split_once('@')?;
+        let (ver, mod_id) = ver_and_mod.split_once('/').unwrap_or((ver_and_mod, "main"));
+        let base = match &self.registry {
+            Some(reg) => reg.join("pkgs").join(pkg).join(ver),
+            None => {
+                let home = std::env::var("HOME").map(PathBuf::from).ok()?;
+                let dir = home.join(".fard").join("cache").join(format!("{}@{}", pkg, ver));
+                if dir.join(pkg).exists() { dir.join(pkg) } else { dir }
+            }
+        };
+        let toml = base.join("fard.toml");
+        let file = if toml.exists() {
+            let entry = std::fs::read_to_string(&toml)
+                .ok()
+                .and_then(|s| {
+                    s.lines()
+                        .find(|l| l.starts_with("entry"))
+                        .and_then(|l| l.split('=').nth(1))
+                        .map(|s| s.trim().trim_matches('"').to_string())
+                })
+                .unwrap_or_else(|| format!("{}.fard", mod_id));
+            base.join(entry)
+        } else {
+            let rel = std::fs::read_to_string(base.join("package.json"))
+                .ok()
+                .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+                .and_then(|j| j["entrypoints"][mod_id].as_str().map(str::to_string))
+                .unwrap_or_else(|| format!("{}.fard", mod_id));
+            base.join("files").join(rel)
+        };
+        file.is_file().then_some(file)
+    }
+
+    /// Check a whole file and return its exports: the `export {..}` names, else the fields
+    /// of a trailing record literal, else None (unknown).
+    fn check_file(&mut self, items: &[Item], label: String, here: PathBuf) -> Option<BTreeMap<String, Scheme>> {
+        self.cx = FileCtx { label, here, env: vec![HashMap::new()], names: file_names(items), ..FileCtx::default() };
+        for (name, sig) in BASE_ENV {
+            let mut sp = SigParser { s: sig.as_bytes(), i: 0, vars: HashMap::new() };
+            let ty = sp.ty(&mut || 0);
+            self.define(name, ty);
+        }
+        self.push();
+        self.declare_types(items);
+        let mut exports: Option<Vec<(String, Sp)>> = None;
+        let mut last: Option<&Expr> = None;
+        for it in items {
+            last = None;
+            match &it.k {
+                IK::Import(path, alias) => {
+                    let t = self.import(path, it.sp);
+                    self.define(alias, t);
+                }
+                IK::Artifact(name) => self.define(name, Ty::Dynamic),
+                IK::Let(pat, e) => {
+                    let t = self.infer(e);
+                    self.bind_let(pat, e, t);
+                }
+                IK::Fn(name, params, ret, body) => {
+                    // monomorphic while its own body is checked, generalized afterwards
+                    let self_ty = self.fresh();
+                    self.define(name, self_ty.clone());
+                    let ft = self.infer_fn(params, ret.as_ref(), body);
+                    self.unify(&self_ty, &ft).ok();
+                    if let Some(top) = self.cx.env.last_mut() {
+                        top.remove(name);
                     }
+                    let mut s = self.generalize(&ft);
+                    s.params = Some(param_names(params));
+                    self.define_scheme(name, s);
                 }
-                Token::LBracket => {
-                    self.eat();
-                    let idx = self.parse_expr();
-                    let _ = self.expect(&Token::RBracket);
-                    e = Expr::Index(Box::new(e), Box::new(idx), line);
+                IK::Export(names) => exports = Some(names.iter().map(|n| (n.clone(), it.sp)).collect()),
+                IK::TypeDef(..) => {}
+                IK::Test(label, body) => {
+                    let t = self.infer(body);
+                    if !self.try_unify(&Ty::Bool, &t) {
+                        let got = self.show(&t);
+                        self.err(it.sp, format!("test '{}' must return Bool, got {}", label, got));
+                    }
                 }
-                Token::LParen => {
-                    self.eat();
-                    let args = self.parse_args();
-                    self.eat(); // )
-                    e = Expr::Call(Box::new(e), args, line);
+                IK::Expr(e) => {
+                    self.infer(e);
+                    last = Some(e);
                 }
-                _ => break,
             }
         }
-        e
+        match (exports, last) {
+            (Some(names), _) => {
+                let mut out = BTreeMap::new();
+                for (n, sp) in names {
+                    match self.scheme_of(&n) {
+                        Some(s) => {
+                            out.insert(n, s);
+                        }
+                        None => self.err(sp, format!("export of unbound name '{}'", n)),
+                    }
+                }
+                Some(out)
+            }
+            (None, Some(Expr { k: EK::Rec(fields), .. })) => {
+                let mut out = BTreeMap::new();
+                for (k, v) in fields {
+                    let s = match &v.k {
+                        EK::Var(n) => self.scheme_of(n).unwrap_or_else(|| Scheme::mono(Ty::Dynamic)),
+                        _ => {
+                            let t = self.infer(v);
+                            self.generalize(&t)
+                        }
+                    };
+                    out.insert(k.clone(), s);
+                }
+                Some(out)
+            }
+            _ => None,
+        }
     }
 
-    fn parse_args(&mut self) -> Vec<Expr> {
-        let mut args = Vec::new();
-        while !matches!(self.peek(), Token::RParen | Token::Eof) {
-            // skip named arg label: "name: value" -> just parse value
-            if matches!(self.peek(), Token::Ident(_)) {
-                let next_pos = self.pos + 1;
-                if next_pos < self.toks.len() && self.toks[next_pos].0 == Token::Colon {
-                    self.eat(); // ident
-                    self.eat(); // :
+    fn bind_let(&mut self, pat: &Pat, e: &Expr, t: Ty) {
+        match pat {
+            Pat::Bind(n) => {
+                let mut s = self.generalize(&t);
+                if let EK::Fn(ps, _) = &e.k {
+                    s.params = Some(param_names(ps));
                 }
+                self.define_scheme(n, s);
             }
-            args.push(self.parse_expr());
-            if self.peek() == &Token::Comma { self.eat(); }
+            _ => self.bind_pat(pat, &t),
         }
-        args
     }
 
-    fn parse_atom(&mut self) -> Expr {
-        let line = self.line();
-        match self.peek().clone() {
-            Token::Int(n) => { self.eat(); Expr::Int(n, line) }
-            Token::Float(f) => { self.eat(); Expr::Float(f, line) }
-            Token::Bool(b) => { self.eat(); Expr::Bool(b, line) }
-            Token::Str(_) => { self.eat(); Expr::Str(line) }
-            Token::Null => { self.eat(); Expr::Null(line) }
-            Token::Ident(s) => { self.eat(); Expr::Var(s, line) }
-            Token::LParen => {
-                self.eat();
-                let e = self.parse_expr();
-                let _ = self.expect(&Token::RParen);
-                e
-            }
-            Token::LBracket => {
-                self.eat();
-                let mut items = Vec::new();
-                while !matches!(self.peek(), Token::RBracket | Token::Eof) {
-                    items.push(self.parse_expr());
-                    if self.peek() == &Token::Comma { self.eat(); }
+    fn bind_pat(&mut self, pat: &Pat, t: &Ty) {
+        let t = self.apply(t);
+        match pat {
+            Pat::Wild | Pat::Lit(_) => {}
+            Pat::Bind(n) => self.define(n, t),
+            Pat::Obj(fields, rest) => {
+                for (k, sub) in fields {
+                    let ft = self.field_of(&t, k).unwrap_or(Ty::Dynamic);
+                    match sub {
+                        Some(p) => self.bind_pat(p, &ft),
+                        None => self.define(k, ft),
+                    }
+                }
+                if let Some(r) = rest {
+                    self.define(r, Ty::Dynamic);
                 }
-                self.eat(); // ]
-                Expr::List(items, line)
             }
-            Token::LBrace => {
-                self.eat();
-                // Could be record literal or block
-                // Heuristic: if next is ident followed by colon => record
-                let mut fields = Vec::new();
-                while !matches!(self.peek(), Token::RBrace | Token::Eof) {
-                    if let Token::Ident(k) = self.peek().clone() {
-                        let k = k.clone();
-                        self.eat();
-                        if self.peek() == &Token::Colon {
-                            self.eat();
-                            let v = self.parse_expr();
-                            fields.push((k, v));
-                            if self.peek() == &Token::Comma { self.eat(); }
-                            continue;
-                        }
-                    }
-                    // Not a record — skip
-                    self.eat();
-                }
-                self.eat(); // }
-                Expr::Rec(fields, line)
-            }
-            Token::Fn => {
-                self.eat();
-                self.eat(); // (
-                let mut params = Vec::new();
-                while !matches!(self.peek(), Token::RParen | Token::Eof) {
-                    if let Token::Ident(p) = self.eat().clone() { params.push(p); }
-                    if self.peek() == &Token::Comma { self.eat(); }
-                }
-                self.eat(); // )
-                self.eat(); // {
-                let body = self.parse_block_expr();
-                self.eat(); // }
-                Expr::Fn(params, Box::new(body), line)
-            }
-            Token::If => {
-                self.eat();
-                let cond = self.parse_expr();
-                // optional 'then'
-                if self.peek() == &Token::Then { self.eat(); }
-                let then = self.parse_expr();
-                // optional 'else'
-                if self.peek() == &Token::Else { self.eat(); }
-                let else_ = self.parse_expr();
-                Expr::If(Box::new(cond), Box::new(then), Box::new(else_), line)
-            }
-            Token::Let => {
-                self.eat();
-                let name = match self.eat().clone() {
-                    Token::Ident(s) => s,
-                    _ => "_".to_string(),
+            Pat::List(items, rest) => {
+                let elem = match &t {
+                    Ty::List(e) => (**e).clone(),
+                    _ => Ty::Dynamic,
                 };
-                while !matches!(self.peek(), Token::Eq | Token::Eof) { self.eat(); }
-                self.eat(); // =
-                let val = self.parse_expr();
-                if self.peek() == &Token::In { self.eat(); }
-                let body = self.parse_expr();
-                Expr::Let(name, Box::new(val), Box::new(body), line)
+                for p in items {
+                    self.bind_pat(p, &elem);
+                }
+                if let Some(r) = rest {
+                    self.define(r, Ty::List(Box::new(elem)));
+                }
             }
-            _ => { Expr::Null(line) }  // don't consume unknown tokens
         }
     }
-}
 
-#[derive(Debug)]
-enum TopItem {
-    Let(String, Expr, usize),
-    Fn(String, Vec<String>, Expr, usize),
-    Test(String, Expr, usize),
-}
+    /// The declared or inferred type of field `k`, if the type is known to have it.
+    fn field_of(&self, t: &Ty, k: &str) -> Option<Ty> {
+        match t {
+            Ty::Rec(fs) => fs.iter().find(|(f, _)| f == k).map(|(_, t)| t.clone()),
+            Ty::Named(key) => match self.decls.get(key)? {
+                TypeDecl::Record(fs) => fs.iter().find(|(f, _)| f == k).map(|(_, t)| t.clone()),
+                TypeDecl::Sum(_) => None,
+            },
+            _ => None,
+        }
+    }
 
-// ── Type inference ────────────────────────────────────────────────────────────
-impl Checker {
+    fn infer_fn(&mut self, params: &[Param], ret: Option<&TypeAnn>, body: &Expr) -> Ty {
+        let mut inspected = Vec::new();
+        type_inspected(body, &mut inspected);
+        let dynamic: Vec<String> = inspected
+            .into_iter()
+            .filter(|(m, _)| {
+                let t = self.scheme_of(m).map(|s| self.apply(&s.ty));
+                matches!(t, Some(Ty::Module(k)) if k == "std/type")
+            })
+            .map(|(_, x)| x)
+            .collect();
+        self.push();
+        let mut ptys = Vec::new();
+        for p in params {
+            let t = match (&p.ann, &p.pat) {
+                (Some(a), _) => self.ann_ty(a),
+                (None, Pat::Bind(n)) if dynamic.contains(n) => Ty::Dynamic,
+                (None, _) => self.fresh(),
+            };
+            self.bind_pat(&p.pat, &t);
+            ptys.push(t);
+        }
+        self.cx.returns.push(false);
+        let mut rt = self.infer(body);
+        if self.cx.returns.pop() == Some(true) {
+            rt = Ty::Dynamic;
+        }
+        if let Some(r) = ret {
+            let want = self.ann_ty(r);
+            self.expect(&want, &rt, body.sp, "return type");
+            rt = want;
+        }
+        self.pop();
+        Ty::Func(ptys, Box::new(rt))
+    }
+
+    /// The scheme behind a callee, for constructors and named calls.
+    fn callee_scheme(&mut self, f: &Expr) -> Option<Scheme> {
+        match &f.k {
+            EK::Var(n) => self.scheme_of(n),
+            EK::Get(base, name) => {
+                let EK::Var(m) = &base.k else { return None };
+                let Ty::Module(key) = self.apply(&self.scheme_of(m)?.ty) else { return None };
+                self.modules.get(&key)?.as_ref()?.get(name).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    fn describe(f: &Expr) -> String {
+        match &f.k {
+            EK::Var(n) => n.clone(),
+            EK::Get(b, n) => format!("{}.{}", Self::describe(b), n),
+            EK::Fn(..) => "fn".to_string(),
+            _ => "function".to_string(),
+        }
+    }
+
+    // ── Inference ────────────────────────────────────────────────────────────
     fn infer(&mut self, e: &Expr) -> Ty {
-        match e {
-            Expr::Int(_, _) => Ty::Int,
-            Expr::Float(_, _) => Ty::Float,
-            Expr::Bool(_, _) => Ty::Bool,
-            Expr::Str(_) => Ty::Str,
-            Expr::Null(_) => Ty::Null,
-
-            Expr::Var(name, _) => self.lookup(name),
-
-            Expr::List(items, line) => {
-                let elem = self.fresh();
-                let mut heterogeneous = false;
+        let sp = e.sp;
+        match &e.k {
+            EK::Int => Ty::Int,
+            EK::Float => Ty::Float,
+            EK::Bool => Ty::Bool,
+            EK::Str => Ty::Str,
+            EK::Null => Ty::Null,
+            EK::Interp(parts) => {
+                parts.iter().for_each(|p| {
+                    self.infer(p);
+                });
+                Ty::Str
+            }
+
+            EK::Var(name) => match self.scheme_of(name) {
+                Some(s) => self.instantiate(&s),
+                None => {
+                    if !self.cx.names.contains(name) {
+                        self.err(sp, format!("unbound variable '{}'", name));
+                    }
+                    Ty::Dynamic
+                }
+            },
+
+            EK::List(items) => {
+                let mut elem = Ty::Null;
                 for item in items {
                     let t = self.infer(item);
-                    if !heterogeneous {
-                        let ea = self.subst.apply(&elem);
-                        let ta = self.subst.apply(&t);
-                        if self.subst.unify(&ea, &ta).is_err() {
-                            heterogeneous = true; // mixed types — treat as Dynamic list
-                        }
-                    }
+                    elem = if matches!(elem, Ty::Null) { t } else { self.join(&elem, &t) };
                 }
-                let elem_ty = if heterogeneous { Ty::Dynamic } else { self.subst.apply(&elem) };
-                Ty::List(Box::new(elem_ty))
+                if items.is_empty() {
+                    elem = self.fresh();
+                }
+                Ty::List(Box::new(self.apply(&elem)))
             }
 
-            Expr::Rec(fields, _) => {
-                let mut ftys = Vec::new();
-                for (k, v) in fields {
-                    let t = self.infer(v);
-                    ftys.push((k.clone(), t));
+            EK::Comp(body, pat, xs, cond) => {
+                let xt = self.infer(xs);
+                let elem = self.elem_of(&xt, xs.sp);
+                self.push();
+                self.bind_pat(pat, &elem);
+                if let Some(c) = cond {
+                    let ct = self.infer(c);
+                    self.expect(&Ty::Bool, &ct, c.sp, "comprehension filter");
                 }
-                Ty::Rec(ftys)
+                let bt = self.infer(body);
+                self.pop();
+                Ty::List(Box::new(bt))
+            }
+
+            EK::For(pat, xs, body) => {
+                let xt = self.infer(xs);
+                let elem = self.elem_of(&xt, xs.sp);
+                self.push();
+                self.bind_pat(pat, &elem);
+                let bt = self.infer(body);
+                self.pop();
+                Ty::List(Box::new(bt))
             }
 
-            Expr::Get(base, field, line) => {
+            EK::Rec(fields) => Ty::Rec(fields.iter().map(|(k, v)| (k.clone(), self.infer(v))).collect()),
+
+            EK::Get(base, field) => {
                 let bt = self.infer(base);
-                let bt = self.subst.apply(&bt);
-                match &bt {
-                    Ty::Dynamic => Ty::Dynamic,
-                    Ty::Rec(fields) => {
-                        if let Some((_, ft)) = fields.iter().find(|(k, _)| k == field) {
-                            ft.clone()
-                        } else {
-                            // field not found in known record — warn but return Dynamic
-                            self.err(format!("no field '{}' in record", field), *line);
+                match self.apply(&bt) {
+                    Ty::Module(key) => match self.modules.get(&key).cloned().flatten() {
+                        Some(exports) => match exports.get(field) {
+                            Some(s) => self.instantiate(s),
+                            None => {
+                                self.err(sp, format!("module '{}' has no export '{}'", key_label(&key), field));
+                                Ty::Dynamic
+                            }
+                        },
+                        None => Ty::Dynamic,
+                    },
+                    Ty::Rec(fs) => match fs.iter().find(|(k, _)| k == field) {
+                        Some((_, t)) => t.clone(),
+                        None => {
+                            let t = self.show(&Ty::Rec(fs));
+                            self.err(sp, format!("no field '{}' in record {}", field, t));
                             Ty::Dynamic
                         }
-                    }
-                    _ => Ty::Dynamic, // could be module access
+                    },
+                    t => self.field_of(&t, field).unwrap_or(Ty::Dynamic),
                 }
             }
 
-            Expr::Index(base, idx, line) => {
+            EK::Index(base, idx) => {
                 let bt = self.infer(base);
                 let it = self.infer(idx);
-                let bt = self.subst.apply(&bt);
-                match &bt {
+                match self.apply(&bt) {
                     Ty::List(elem) => {
-                        let it = self.subst.apply(&it);
-                        if !matches!(it, Ty::Int | Ty::Dynamic) {
-                            self.err("list index must be Int".to_string(), *line);
-                        }
-                        *elem.clone()
-                    }
-                    Ty::Dynamic => Ty::Dynamic,
-                    _ => {
-                        Ty::Dynamic
+                        self.expect(&Ty::Int, &it, idx.sp, "list index");
+                        *elem
                     }
+                    _ => Ty::Dynamic,
                 }
             }
 
-            Expr::Call(f, args, line) => {
+            EK::Call(f, args) => {
+                if let Some((key, variant)) = self.callee_scheme(f).and_then(|s| s.ctor) {
+                    return self.check_ctor(&key, variant.as_deref(), args, sp);
+                }
                 let ft = self.infer(f);
                 let arg_tys: Vec<Ty> = args.iter().map(|a| self.infer(a)).collect();
-                let ft = self.subst.apply(&ft);
-                match ft {
+                let name = Self::describe(f);
+                match self.apply(&ft) {
                     Ty::Dynamic => Ty::Dynamic,
-                    Ty::Func(param_tys, ret) => {
-                        if param_tys.len() != arg_tys.len() {
-                            self.err(
-                                format!("arity error: expected {} args, got {}", param_tys.len(), arg_tys.len()),
-                                *line,
-                            );
+                    Ty::AnyFn(min) => {
+                        if arg_tys.len() < min {
+                            self.err(sp, format!("{} expects at least {} argument(s), got {}", name, min, arg_tys.len()));
+                        }
+                        Ty::Dynamic
+                    }
+                    Ty::Func(ps, ret) => {
+                        if ps.len() != arg_tys.len() {
+                            self.err(sp, format!("{} expects {} argument(s), got {}", name, ps.len(), arg_tys.len()));
                         } else {
-                            for (p, a) in param_tys.iter().zip(arg_tys.iter()) {
-                                self.unify_or_err(p, a, *line, "argument type mismatch");
+                            for (i, (p, a)) in ps.iter().zip(arg_tys.iter()).enumerate() {
+                                self.expect(p, a, args[i].sp, &format!("argument {} of {}", i + 1, name));
                             }
                         }
                         *ret
                     }
+                    v @ Ty::Var(_) => {
+                        let ret = self.fresh();
+                        self.unify(&v, &Ty::Func(arg_tys, Box::new(ret.clone()))).ok();
+                        ret
+                    }
+                    Ty::Null => Ty::Dynamic,
                     other => {
-                        // calling a non-function non-dynamic — definite error
-                        if !matches!(other, Ty::Var(_)) {
-                            self.err(format!("calling non-function: {}", other.display()), *line);
-                        }
+                        let t = self.show(&other);
+                        self.err(sp, format!("{} is not a function: {}", name, t));
                         Ty::Dynamic
                     }
                 }
             }
 
-            Expr::Fn(params, body, _) => {
+            EK::NamedCall(f, named) => {
+                let scheme = self.callee_scheme(f);
+                let ft = self.infer(f);
+                let arg_tys: Vec<(String, Ty, Sp)> =
+                    named.iter().map(|(n, v)| (n.clone(), self.infer(v), v.sp)).collect();
+                let name = Self::describe(f);
+                let (Some(params), Ty::Func(ps, ret)) = (scheme.and_then(|s| s.params), self.apply(&ft)) else {
+                    return Ty::Dynamic;
+                };
+                for (n, t, asp) in &arg_tys {
+                    match params.iter().position(|p| p == n) {
+                        Some(i) if i < ps.len() => self.expect(&ps[i], t, *asp, &format!("argument '{}' of {}", n, name)),
+                        _ => self.err(*asp, format!("{} has no parameter '{}'", name, n)),
+                    }
+                }
+                for p in &params {
+                    if !arg_tys.iter().any(|(n, _, _)| n == p) {
+                        self.err(sp, format!("missing argument '{}' in named call to {}", p, name));
+                    }
+                }
+                *ret
+            }
+
+            EK::Fn(params, body) => self.infer_fn(params, None, body),
+
+            EK::Block(stmts) => {
                 self.push();
-                let param_tys: Vec<Ty> = params.iter().map(|p| {
-                    let t = self.fresh();
-                    self.define(p, t.clone());
-                    t
-                }).collect();
-                let ret = self.infer(body);
+                let mut t = Ty::Null;
+                for s in stmts {
+                    match s {
+                        Stmt::Let(pat, e) => {
+                            let vt = self.infer(e);
+                            self.bind_let(pat, e, vt);
+                            t = Ty::Null;
+                        }
+                        Stmt::Expr(e) => t = self.infer(e),
+                    }
+                }
                 self.pop();
-                Ty::Func(param_tys, Box::new(ret))
+                t
             }
 
-            Expr::Let(name, val, body, _) => {
+            EK::LetIn(pat, val, body) => {
                 let vt = self.infer(val);
-                self.define(name, vt);
-                self.infer(body)
+                self.push();
+                self.bind_let(pat, val, vt);
+                let t = self.infer(body);
+                self.pop();
+                t
             }
 
-            Expr::Block(bindings, tail, _) => {
+            EK::Using(pat, val, body) => {
+                self.infer(val);
                 self.push();
-                for (name, val) in bindings {
-                    let vt = self.infer(val);
-                    self.define(name, vt);
-                }
-                let t = self.infer(tail);
+                self.bind_pat(pat, &Ty::Dynamic);
+                let t = self.infer(body);
                 self.pop();
                 t
             }
 
-            Expr::If(cond, then, else_, line) => {
+            EK::If(cond, then, else_) => {
                 let ct = self.infer(cond);
-                let ct = self.subst.apply(&ct);
-                if !matches!(ct, Ty::Bool | Ty::Dynamic | Ty::Var(_)) {
-                    self.err(format!("if condition must be Bool, got {}", ct.display()), *line);
+                if !self.try_unify(&Ty::Bool, &ct) {
+                    let got = self.show(&ct);
+                    self.err(cond.sp, format!("if condition must be Bool, got {}", got));
                 }
                 let tt = self.infer(then);
                 let et = self.infer(else_);
-                // branches should unify but we allow mismatch (dynamic)
-                let tt = self.subst.apply(&tt);
-                let et = self.subst.apply(&et);
-                if self.subst.unify(&tt, &et).is_ok() {
-                    self.subst.apply(&tt)
-                } else {
-                    Ty::Dynamic
+                self.join(&tt, &et)
+            }
+
+            EK::Match(scrut, arms) => {
+                let st = self.infer(scrut);
+                let mut result: Option<Ty> = None;
+                for arm in arms {
+                    self.push();
+                    self.bind_pat(&arm.pat, &st);
+                    if let Some(g) = &arm.guard {
+                        let gt = self.infer(g);
+                        self.expect(&Ty::Bool, &gt, g.sp, "match guard");
+                    }
+                    let bt = self.infer(&arm.body);
+                    self.pop();
+                    result = Some(match result {
+                        Some(r) => self.join(&r, &bt),
+                        None => bt,
+                    });
                 }
+                self.check_exhaustive(&st, arms, sp);
+                result.unwrap_or(Ty::Dynamic)
             }
 
-            Expr::Bin(op, lhs, rhs, line) => {
-                let lt = self.infer(lhs);
-                let rt = self.infer(rhs);
-                let lt = self.subst.apply(&lt);
-                let rt = self.subst.apply(&rt);
-                match op.as_str() {
-                    "+" | "-" | "*" | "/" | "%" => {
-                        // Error only on definitively non-numeric types
-                        let non_numeric = |t: &Ty| matches!(t,
-                            Ty::Bool | Ty::Null |
-                            Ty::List(_) | Ty::Rec(_) | Ty::Func(_, _)
-                        );
-                        // Text + Text is valid (string concat)
-                        let text_concat = op == "+" && matches!((&lt, &rt),
-                            (Ty::Str, Ty::Str) | (Ty::Str, Ty::Dynamic) |
-                            (Ty::Dynamic, Ty::Str));
-                        if text_concat {
-                            return Ty::Str;
-                        }
-                        if non_numeric(&lt) || non_numeric(&rt) {
-                            self.err(
-                                format!("operator '{}' requires numeric operands, got {} and {}", op, lt.display(), rt.display()),
-                                *line,
-                            );
-                            return Ty::Dynamic;
-                        }
-                        match (&lt, &rt) {
-                            (Ty::Dynamic, _) | (_, Ty::Dynamic) => Ty::Dynamic,
-                            (Ty::Int, Ty::Int) => Ty::Int,
-                            (Ty::Float, Ty::Float) => Ty::Float,
-                            (Ty::Int, Ty::Float) | (Ty::Float, Ty::Int) => Ty::Float,
-                            // Var operands — unify together, return same type
-                            (Ty::Var(_), _) | (_, Ty::Var(_)) => {
-                                self.subst.unify(&lt, &rt).ok();
-                                self.subst.apply(&lt)
-                            }
-                            _ => Ty::Dynamic,
+            EK::While(init, cond, body) => {
+                let st = self.infer(init);
+                let ct = self.infer(cond);
+                self.expect(&Ty::Func(vec![st.clone()], Box::new(Ty::Bool)), &ct, cond.sp, "while condition");
+                let bt = self.infer(body);
+                self.expect(&Ty::Func(vec![st.clone()], Box::new(st.clone())), &bt, body.sp, "while body");
+                Ty::Rec(vec![
+                    ("value".to_string(), self.apply(&st)),
+                    ("steps".to_string(), Ty::Int),
+                    ("chain_hex".to_string(), Ty::Str),
+                ])
+            }
+
+            EK::Return(inner) => {
+                self.infer(inner);
+                if let Some(r) = self.cx.returns.last_mut() {
+                    *r = true;
+                }
+                Ty::Dynamic
+            }
+
+            EK::Try(inner) => {
+                self.infer(inner);
+                Ty::Dynamic
+            }
+
+            EK::Unary(op, inner) => {
+                let t = self.infer(inner);
+                match (op.as_str(), self.apply(&t)) {
+                    ("!", t) => {
+                        if !self.try_unify(&Ty::Bool, &t) {
+                            let got = self.show(&t);
+                            self.err(sp, format!("'!' expects Bool, got {}", got));
                         }
+                        Ty::Bool
                     }
-                    "&&" | "||" => {
-                        match (&lt, &rt) {
-                            (Ty::Dynamic, _) | (_, Ty::Dynamic) => Ty::Bool,
-                            (Ty::Bool, Ty::Bool) => Ty::Bool,
-                            (Ty::Var(_), _) | (_, Ty::Var(_)) => Ty::Bool,
-                            _ => {
-                                self.err(
-                                    format!("operator '{}' requires Bool operands, got {} and {}", op, lt.display(), rt.display()),
-                                    *line,
-                                );
-                                Ty::Bool
-                            }
-                        }
+                    (_, t @ (Ty::Int | Ty::Float | Ty::Bytes | Ty::Var(_))) => t,
+                    (_, Ty::Dynamic | Ty::Null) => Ty::Dynamic,
+                    (_, t) => {
+                        let got = self.show(&t);
+                        self.err(sp, format!("unary '-' expects Int or Float, got {}", got));
+                        Ty::Dynamic
                     }
-                    "==" | "!=" | "<" | ">" | "<=" | ">=" => Ty::Bool,
-                    _ => Ty::Dynamic,
                 }
             }
 
-            Expr::Unary(op, inner, line) => {
-                let t = self.infer(inner);
-                let t = self.subst.apply(&t);
+            EK::Bin(op, lhs, rhs) => {
+                let lt = self.infer(lhs);
+                let rt = self.infer(rhs);
+                let (lt, rt) = (self.apply(&lt), self.apply(&rt));
                 match op.as_str() {
-                    "!" => {
-                        match &t {
-                            Ty::Bool | Ty::Dynamic => {}
-                            Ty::Var(_) => { self.subst.unify(&t, &Ty::Bool).ok(); }
-                            _ => { self.err(format!("'!' requires Bool, got {}", t.display()), *line); }
+                    "&&" | "||" => {
+                        for (t, side) in [(&lt, lhs), (&rt, rhs)] {
+                            if !self.try_unify(&Ty::Bool, t) {
+                                let got = self.show(t);
+                                self.err(side.sp, format!("operator '{}' expects Bool operands, got {}", op, got));
+                            }
                         }
                         Ty::Bool
                     }
-                    "-" => {
-                        match t {
-                            Ty::Dynamic | Ty::Var(_) => Ty::Dynamic,
-                            Ty::Int => Ty::Int,
-                            Ty::Float => Ty::Float,
-                            other => {
-                                self.err(format!("unary '-' requires numeric, got {:?}", other), *line);
-                                Ty::Dynamic
+                    "==" | "!=" => Ty::Bool,
+                    _ => {
+                        let cmp = matches!(op.as_str(), "<" | ">" | "<=" | ">=");
+                        let (ls, rs) = {
+                            let mut sh = Shower::default();
+                            (sh.show(&lt), sh.show(&rt))
+                        };
+                        let result = if lt.non_numeric() || rt.non_numeric() {
+                            self.err(sp, format!("operator '{}' expects Int or Float operands, got {} and {}", op, ls, rs));
+                            Ty::Dynamic
+                        } else if lt.is_numeric() && rt.is_numeric() && lt != rt {
+                            self.err(sp, format!("operator '{}' cannot mix {} and {}", op, ls, rs));
+                            Ty::Dynamic
+                        } else if op == "%" && (lt == Ty::Float || rt == Ty::Float) {
+                            self.err(sp, format!("operator '%' expects Int operands, got {} and {}", ls, rs));
+                            Ty::Dynamic
+                        } else {
+                            self.unify(&lt, &rt).ok();
+                            match (&lt, &rt) {
+                                (Ty::Dynamic | Ty::Null, t) | (t, Ty::Dynamic | Ty::Null) => self.apply(t),
+                                _ => self.apply(&lt),
                             }
-                        }
+                        };
+                        if cmp { Ty::Bool } else { result }
                     }
-                    _ => Ty::Dynamic,
                 }
             }
         }
     }
+
+    fn elem_of(&mut self, xs: &Ty, sp: Sp) -> Ty {
+        let elem = self.fresh();
+        self.expect(&Ty::List(Box::new(elem.clone())), xs, sp, "iteration");
+        elem
+    }
+
+    // ── Match exhaustiveness ─────────────────────────────────────────────────
+    /// A match on a sum type needs an unguarded arm for every variant: a catch-all, or a
+    /// record pattern that only names the variant's own fields with irrefutable sub-patterns.
+    /// A match on Bool needs both `true` and `false` (or a catch-all).
+    fn check_exhaustive(&mut self, st: &Ty, arms: &[Arm], sp: Sp) {
+        let unguarded: Vec<&Pat> = arms.iter().filter(|a| a.guard.is_none()).map(|a| &a.pat).collect();
+        if unguarded.iter().any(|p| matches!(p, Pat::Wild | Pat::Bind(_))) {
+            return;
+        }
+        let missing: Vec<String> = match self.apply(st) {
+            Ty::Named(key) => match self.decls.get(&key) {
+                Some(TypeDecl::Sum(vs)) => vs
+                    .iter()
+                    .filter(|(_, fields)| !unguarded.iter().any(|p| covers(p, fields)))
+                    .map(|(v, _)| v.clone())
+                    .collect(),
+                _ => return,
+            },
+            Ty::Bool => ["true", "false"]
+                .iter()
+                .filter(|b| !unguarded.iter().any(|p| matches!(p, Pat::Lit(l) if l == *b)))
+                .map(|b| b.to_string())
+                .collect(),
+            _ => return,
+        };
+        if !missing.is_empty() {
+            let t = self.show(st);
+            self.err(sp, format!("non-exhaustive match on {}: no arm covers {}", t, missing.join(", ")));
+        }
+    }
+}
+
+fn covers(p: &Pat, fields: &[(String, Ty)]) -> bool {
+    match p {
+        Pat::Wild | Pat::Bind(_) => true,
+        Pat::Obj(fs, _) => fs.iter().all(|(k, sub)| {
+            fields.iter().any(|(f, _)| f == k) && matches!(sub, None | Some(Pat::Wild | Pat::Bind(_)))
+        }),
+        _ => false,
+    }
+}
+
+fn param_names(params: &[Param]) -> Vec<String> {
+    params
+        .iter()
+        .map(|p| match &p.pat {
+            Pat::Bind(n) => n.clone(),
+            _ => "_".to_string(),
+        })
+        .collect()
+}
+
+/// How a module key reads in messages: std paths as-is, files by name.
+fn key_label(key: &str) -> String {
+    if key.starts_with("std/") {
+        return key.to_string();
+    }
+    Path::new(key).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_else(|| key.to_string())
+}
+
+// ── Main ─────────────────────────────────────────────────────────────────────
+fn fn_semantic_lines(body: &Expr) -> usize {
+    match &body.k {
+        EK::Block(stmts) => stmts.len(),
+        _ => 1,
+    }
 }
 
-// ── Main ──────────────────────────────────────────────────────────────────────
-fn count_fn_lines(body: &str) -> usize {
-    body.lines().filter(|l| !l.trim().is_empty()).count()
+fn usage() -> ! {
+    eprintln!("usage: fardcheck [--program] <file.fard> [--registry DIR] [--as PATH] [--hex-haiku]");
+    std::process::exit(1);
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let hex_haiku = args.iter().any(|a| a == "--hex-haiku");
-    let program = args.iter().skip(1)
-        .find(|a| !a.starts_with('-'))
-        .cloned()
-        .or_else(|| {
-            // --program <path>
-            args.windows(2).find(|w| w[0] == "--program").map(|w| w[1].clone())
-        });
-
-    let path = match program {
-        Some(p) => p,
-        None => {
-            eprintln!("usage: fardcheck [--program] <file.fard>");
-            std::process::exit(1);
+    let mut args = std::env::args().skip(1);
+    let mut program: Option<String> = None;
+    let mut registry: Option<PathBuf> = None;
+    let mut as_path: Option<String> = None;
+    let mut hex_haiku = false;
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--hex-haiku" => hex_haiku = true,
+            "--program" => program = Some(args.next().unwrap_or_else(|| usage())),
+            "--registry" => registry = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--as" => as_path = Some(args.next().unwrap_or_else(|| usage())),
+            _ if !a.starts_with('-') && program.is_none() => program = Some(a),
+            _ => usage(),
         }
-    };
+    }
+    let Some(path) = program else { usage() };
 
     let src = match std::fs::read_to_string(&path) {
         Ok(s) => s,
-        Err(e) => { eprintln!("error reading {path}: {e}"); std::process::exit(1); }
+        Err(e) => {
+            eprintln!("error reading {path}: {e}");
+            std::process::exit(1);
+        }
     };
-
-    let mut lx = Lexer::new(&src);
-    let toks = lx.tokenize();
-    let mut parser = Parser::new(toks);
-    let items = parser.parse_program();
-
-    let mut checker = Checker::new();
-
-    // Pre-define imports as Dynamic (they'll shadow nothing meaningful)
-    // Actually imports define module aliases which are looked up as Dynamic vars
-
-    for item in &items {
-        match item {
-            TopItem::Let(name, expr, _line) => {
-                let t = checker.infer(expr);
-                checker.define(name, t);
-            }
-            TopItem::Fn(name, params, body, _line) => {
-                // Pre-register with a fresh var for recursive self-reference
-                let self_var = checker.fresh();
-                checker.define(name, self_var.clone());
-                checker.push();
-                let param_tys: Vec<Ty> = params.iter().map(|p| {
-                    let t = checker.fresh();
-                    checker.define(p, t.clone());
-                    t
-                }).collect();
-                let ret = checker.infer(body);
-                checker.pop();
-                let ft = Ty::Func(param_tys, Box::new(ret));
-                // Unify self-reference var with actual fn type
-                checker.subst.unify(&self_var, &ft).ok();
-                let ft_applied = checker.subst.apply(&ft);
-                // Use monomorphic type (no generalization)
-                // Generalization requires type classes for numeric operators
-                checker.define(name, ft_applied);
-            }
-            TopItem::Test(label, expr, line) => {
-                let t = checker.infer(expr);
-                let t = checker.subst.apply(&t);
-                if !matches!(t, Ty::Bool | Ty::Dynamic | Ty::Var(_)) {
-                    checker.err(
-                        format!("test '{}' body must return Bool, got {}", label, t.display()),
-                        *line,
-                    );
-                }
-            }
+    let items = match parse_module(&src) {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("fardcheck: {}: {}", path, e);
+            std::process::exit(2);
         }
-    }
+    };
+
+    let label = as_path.unwrap_or(path);
+    let here = Path::new(&label).parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut checker = Checker::new(here.clone(), registry);
+    let main_key = std::fs::canonicalize(&label).unwrap_or_else(|_| PathBuf::from(&label)).display().to_string();
+    checker.loading.push(main_key);
+    checker.check_file(&items, label, here);
 
     // Hex-Haiku check
     let mut hh_warnings = 0;
     if hex_haiku {
         for item in &items {
-            if let TopItem::Fn(name, _, body, line) = item {
-                // Count semantic lines: number of let-bindings + 1 (return expr)
-                let line_count = match body {
-                    Expr::Block(bindings, _, _) => bindings.len() + 1,
-                    _ => 1,
-                };
+            if let IK::Fn(name, _, _, body) = &item.k {
+                let line_count = fn_semantic_lines(body);
                 if line_count > 6 {
-                    eprintln!("HEX_HAIKU line {}: fn '{}' is {} semantic lines (max 6)", line, name, line_count);
+                    eprintln!("HEX_HAIKU line {}: fn '{}' is {} semantic lines (max 6)", item.sp.line, name, line_count);
                     hh_warnings += 1;
                 } else {
                     println!("✓ fn '{}': {} line(s)", name, line_count);
@@ -1142,7 +2466,7 @@ fn main() {
         std::process::exit(0);
     } else if !checker.errors.is_empty() {
         for e in &checker.errors {
-            eprintln!("TYPE ERROR line {}: {}", e.line, e.msg);
+            eprintln!("TYPE ERROR {}:{}:{}: {}", e.file, e.line, e.col, e.msg);
         }
         eprintln!("{} error(s)", checker.errors.len());
         std::process::exit(1);
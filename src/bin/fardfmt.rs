@@ -1,46 +1,97 @@
 //! fardfmt — FARD code formatter
-//! Usage: fardfmt [--check] [--stdin] [file.fard ...]
+//! Usage: fardfmt [--check] [--stdin] [--range START:END] [--ast] [file.fard ...]
+//!
+//! The source is parsed with the same grammar as fardrun into a surface AST that keeps
+//! comments attached to nodes, then laid out by a Wadler-style pretty printer.
 //!
 //! Formatting rules:
 //!   - 2-space indent inside fn/match/test blocks
 //!   - One space around binary operators
-//!   - Record literals: { k: v, k: v } with spaces inside braces
-//!   - let chains: one per line
-//!   - Max line length: 100 chars (soft)
-//!   - Trailing whitespace removed
+//!   - Record literals: { k: v, k: v } with spaces inside braces; one field per line when broken
+//!   - Calls, lists, records and params break one element per line when they do not fit;
+//!     a trailing fn/record/list argument stays on the call line when it can
+//!   - Pipelines and operator chains break before each operator
+//!   - Match arms one per line with `=>` aligned; consecutive imports align `as`
+//!   - Max line length: 100 chars
+//!   - At most one blank line kept between items, statements and elements
 //!   - Single trailing newline
+//!
+//! Every result is checked before it is emitted: the output must reparse to the same AST,
+//! keep every comment, and format to itself. A file that fails to parse is left untouched.
+//!
+//! --range START:END formats only the top-level items touching lines START..=END (1-based).
+//! --ast prints the parsed AST without comments or layout, for comparing two sources.
 
 use std::process;
 
+const WIDTH: usize = 100;
+
 fn main() {
     let args: Vec<String> = std::env::args().skip(1).collect();
     if args.is_empty() {
-        eprintln!("usage: fardfmt [--check] [--stdin] [file.fard ...]");
+        eprintln!("usage: fardfmt [--check] [--stdin] [--range START:END] [--ast] [file.fard ...]");
         eprintln!("       fardfmt --check main.fard   # exit 1 if not formatted");
         eprintln!("       fardfmt --stdin             # read from stdin");
+        eprintln!("       fardfmt --stdin --range 3:9 # format only items on lines 3..=9");
         process::exit(0);
     }
 
     let check_mode = args.iter().any(|a| a == "--check");
     let stdin_mode = args.iter().any(|a| a == "--stdin");
-    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let ast_mode = args.iter().any(|a| a == "--ast");
+    let range = match args.iter().position(|a| a == "--range") {
+        Some(i) => match args.get(i + 1).and_then(|r| parse_range(r)) {
+            Some(r) => Some(r),
+            None => {
+                eprintln!("fardfmt: --range expects START:END (1-based lines)");
+                process::exit(2);
+            }
+        },
+        None => None,
+    };
+    let range_arg = range.map(|(a, b)| format!("{}:{}", a, b));
+    let files: Vec<&String> = args
+        .iter()
+        .filter(|a| !a.starts_with("--") && Some(*a) != range_arg.as_ref())
+        .collect();
 
-    let mut any_changed = false;
+    let run = |src: &str| -> Result<String, String> {
+        if ast_mode {
+            return parse_module(src).map(|m| dump_module(&m));
+        }
+        match range {
+            Some((lo, hi)) => format_range(src, lo, hi),
+            None => format_src(src),
+        }
+    };
 
     if stdin_mode {
         let mut input = String::new();
         std::io::Read::read_to_string(&mut std::io::stdin(), &mut input).unwrap();
-        let formatted = format_src(&input);
-        print!("{}", formatted);
+        match run(&input) {
+            Ok(formatted) => print!("{}", formatted),
+            Err(e) => {
+                eprintln!("fardfmt: <stdin>: {}", e);
+                process::exit(2);
+            }
+        }
         return;
     }
 
+    let mut any_changed = false;
     for file in &files {
         let src = match std::fs::read_to_string(file) {
             Ok(s) => s,
             Err(e) => { eprintln!("fardfmt: {}: {}", file, e); process::exit(2); }
         };
-        let formatted = format_src(&src);
+        let formatted = match run(&src) {
+            Ok(f) => f,
+            Err(e) => { eprintln!("fardfmt: {}: {}", file, e); process::exit(2); }
+        };
+        if ast_mode {
+            print!("{}", formatted);
+            continue;
+        }
         if formatted != src {
             any_changed = true;
             if check_mode {
@@ -57,217 +108,1986 @@ fn main() {
     }
 }
 
-fn format_src(src: &str) -> String {
-    let mut out = String::new();
-    let mut indent: usize = 0;
-    let mut lines = src.lines().peekable();
-    let mut prev_blank = false;
+fn parse_range(r: &str) -> Option<(usize, usize)> {
+    let (a, b) = r.split_once(':')?;
+    let (a, b) = (a.parse().ok()?, b.parse().ok()?);
+    if a == 0 || b < a {
+        return None;
+    }
+    Some((a, b))
+}
 
-    while let Some(line) = lines.next() {
-        let trimmed = line.trim();
+fn format_src(src: &str) -> Result<String, String> {
+    let m = parse_module(src)?;
+    let out = print_module(&m);
+    verify(src, &m, &out)?;
+    Ok(out)
+}
 
-        // Preserve intentional blank lines (max 1 consecutive)
-        if trimmed.is_empty() {
-            if !prev_blank && !out.is_empty() {
-                out.push('\n');
-                prev_blank = true;
-            }
-            continue;
+/// Reformat only the top-level items that touch lines `lo..=hi`; everything else is kept
+/// byte for byte. Items sharing a source line are formatted together, and runs of adjacent
+/// selected items are formatted as one, so a range covering the whole file formats it fully.
+fn format_range(src: &str, lo: usize, hi: usize) -> Result<String, String> {
+    let m = parse_module(src)?;
+    let pads = import_pads(&m.items);
+    let n = m.items.len();
+    let mut selected = vec![false; n];
+    let mut i = 0;
+    while i < n {
+        // a cluster of items sharing source lines is selected or skipped as a whole
+        let (mut j, mut last) = (i + 1, m.items[i].last_line);
+        while j < n && m.items[j].first_line <= last {
+            last = last.max(m.items[j].last_line);
+            j += 1;
         }
-        prev_blank = false;
-
-        // Adjust indent based on closing braces at start
-        let close_count = trimmed.chars().take_while(|&c| c == '}').count();
-        if close_count > 0 && indent >= close_count * 2 {
-            indent -= close_count * 2;
+        let hit = m.items[i].first_line <= hi && last >= lo;
+        selected[i..j].iter_mut().for_each(|s| *s = hit);
+        i = j;
+    }
+    let lines: Vec<&str> = src.split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut next = 1;
+    let mut i = 0;
+    while i < n {
+        if !selected[i] {
+            i += 1;
+            continue;
         }
-
-        // Format the line
-        let formatted_line = format_line(trimmed, indent);
-
-        // Count net brace change for next line indent
-        let opens = count_unquoted(trimmed, '{');
-        let closes = count_unquoted(trimmed, '}');
-        let net = opens as isize - closes as isize;
-        if net > 0 {
-            indent += (net as usize) * 2;
-        } else if net < 0 {
-            let _decrease = (-net as usize) * 2;
-            // Already adjusted for leading closes above
-            // Only adjust for non-leading closes
-            let leading_closes = trimmed.chars().take_while(|&c| c == '}').count();
-            let trailing_net = opens as isize - (closes as isize - leading_closes as isize);
-            if trailing_net < 0 {
-                let dec = (-trailing_net as usize) * 2;
-                if indent >= dec { indent -= dec; }
+        let mut parts = vec![item_elem_doc(&m.items[i], pads[i])];
+        let mut j = i + 1;
+        while j < n && selected[j] {
+            parts.push(Doc::HardLine);
+            if m.items[j].blank {
+                parts.push(Doc::HardLine);
             }
+            parts.push(item_elem_doc(&m.items[j], pads[j]));
+            j += 1;
         }
-
-        out.push_str(&formatted_line);
+        for l in &lines[next - 1..m.items[i].first_line - 1] {
+            out.push_str(l);
+        }
+        out.push_str(render(&Doc::Cat(parts), WIDTH).trim_end());
         out.push('\n');
+        next = m.items[i..j].iter().map(|it| it.last_line).max().unwrap_or(0) + 1;
+        i = j;
     }
-
-    // Ensure single trailing newline
-    while out.ends_with("\n\n") {
+    for l in lines.iter().skip(next - 1) {
+        out.push_str(l);
+    }
+    if !src.ends_with('\n') && out.ends_with('\n') && next <= lines.len() {
         out.pop();
     }
-    if !out.ends_with('\n') {
-        out.push('\n');
+    let reparsed = parse_module(&out)?;
+    if dump_module(&reparsed) != dump_module(&m) || comment_texts(&out)? != comment_texts(src)? {
+        return Err("internal error: range formatting changed the program".to_string());
     }
-    out
+    Ok(out)
 }
 
-fn format_line(line: &str, indent: usize) -> String {
-    let prefix = " ".repeat(indent);
-    let body = format_tokens(line);
-    format!("{}{}", prefix, body)
+/// The formatted text must parse to the same AST, keep every comment, and be a fixed point.
+fn verify(src: &str, m: &Module, out: &str) -> Result<(), String> {
+    let again = parse_module(out).map_err(|e| format!("internal error: output does not parse: {}", e))?;
+    if dump_module(&again) != dump_module(m) {
+        return Err("internal error: formatting changed the AST".to_string());
+    }
+    if comment_texts(out)? != comment_texts(src)? {
+        return Err("internal error: formatting moved a comment out of order".to_string());
+    }
+    if print_module(&again) != out {
+        return Err("internal error: formatting is not idempotent".to_string());
+    }
+    Ok(())
 }
 
-fn format_tokens(line: &str) -> String {
-    // Tokenize and reformat spacing around operators and punctuation
-    let mut out = String::new();
-    let chars: Vec<char> = line.chars().collect();
-    let mut i = 0;
+fn comment_texts(src: &str) -> Result<Vec<String>, String> {
+    Ok(lex(src)?.into_iter().flat_map(|t| t.comments).map(|c| c.text).collect())
+}
 
-    while i < chars.len() {
-        let c = chars[i];
+// ── Lexer ───────────────────────────────────────────────────────────────────
 
-        // String literals — copy verbatim
-        if c == '"' {
-            out.push(c);
-            i += 1;
-            while i < chars.len() {
-                let sc = chars[i];
-                out.push(sc);
+const KEYWORDS: &[&str] = &[
+    "let", "in", "fn", "if", "then", "else", "import", "as", "export", "match", "test", "while",
+    "return", "using", "true", "false", "null",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TK {
+    Kw,
+    Ident,
+    Num,
+    Str,
+    StrInterp,
+    Sym,
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Comment {
+    text: String,
+    line: usize,
+    /// Nothing but whitespace precedes it on its line
+    own_line: bool,
+    blank_before: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Tok {
+    kind: TK,
+    text: String,
+    line: usize,
+    end_line: usize,
+    nl_before: bool,
+    blank_before: bool,
+    comments: Vec<Comment>,
+}
+
+fn lex(src: &str) -> Result<Vec<Tok>, String> {
+    let s: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    let mut line = 1;
+    let mut toks: Vec<Tok> = Vec::new();
+    loop {
+        // whitespace and comments up to the next token
+        let mut comments = Vec::new();
+        let mut newlines = 0;
+        let mut any_nl = toks.is_empty();
+        loop {
+            match s.get(i) {
+                Some('\n') => {
+                    newlines += 1;
+                    any_nl = true;
+                    line += 1;
+                    i += 1;
+                }
+                Some(c) if c.is_whitespace() => i += 1,
+                Some('#') | Some('/') if s[i] == '#' || s.get(i + 1) == Some(&'/') => {
+                    let start = i;
+                    while i < s.len() && s[i] != '\n' {
+                        i += 1;
+                    }
+                    let text: String = s[start..i].iter().collect();
+                    comments.push(Comment {
+                        text: text.trim_end().to_string(),
+                        line,
+                        own_line: any_nl,
+                        blank_before: newlines >= 2,
+                    });
+                    newlines = 0;
+                    any_nl = false;
+                }
+                _ => break,
+            }
+        }
+        let nl_before = any_nl || comments.iter().any(|c| c.own_line) || !comments.is_empty();
+        let blank_before = newlines >= 2;
+        let start_line = line;
+        let start = i;
+        let Some(&c) = s.get(i) else {
+            toks.push(Tok { kind: TK::Eof, text: String::new(), line, end_line: line, nl_before, blank_before, comments });
+            return Ok(toks);
+        };
+        let kind = if c.is_ascii_alphabetic() || c == '_' {
+            while s.get(i).is_some_and(|d| d.is_ascii_alphanumeric() || *d == '_') {
+                i += 1;
+            }
+            let word: String = s[start..i].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) { TK::Kw } else { TK::Ident }
+        } else if c.is_ascii_digit() {
+            while s.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                i += 1;
+            }
+            if i - start > 1 && c == '0' {
+                return Err(format!("ERROR_PARSE leading zero integer literal at line {}", line));
+            }
+            if s.get(i) == Some(&'.') && s.get(i + 1).is_some_and(|d| d.is_ascii_digit()) {
                 i += 1;
-                if sc == '\\' && i < chars.len() {
-                    out.push(chars[i]);
+                while s.get(i).is_some_and(|d| d.is_ascii_digit()) {
                     i += 1;
-                } else if sc == '"' {
+                }
+                if matches!(s.get(i), Some('e' | 'E')) {
+                    i += 1;
+                    if matches!(s.get(i), Some('+' | '-')) {
+                        i += 1;
+                    }
+                    while s.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                        i += 1;
+                    }
+                }
+            }
+            TK::Num
+        } else if c == '`' {
+            i += 1;
+            while let Some(&d) = s.get(i) {
+                i += 1;
+                if d == '\n' {
+                    line += 1;
+                }
+                if d == '`' {
                     break;
                 }
             }
-            continue;
-        }
-
-        // Comments — copy rest of line verbatim
-        if c == '/' && i + 1 < chars.len() && chars[i + 1] == '/' {
-            // Add space before comment if needed
-            if !out.is_empty() && !out.ends_with(' ') {
-                out.push(' ');
+            TK::Str
+        } else if c == '"' {
+            i += 1;
+            let mut interp = false;
+            while let Some(&d) = s.get(i) {
+                i += 1;
+                match d {
+                    '"' => break,
+                    '\n' => line += 1,
+                    '\\' => {
+                        match s.get(i) {
+                            Some('n' | 'r' | 't' | '"' | '\\') => i += 1,
+                            Some(e) => return Err(format!("bad escape: \\{} at line {}", e, line)),
+                            None => return Err(format!("bad escape at line {}", line)),
+                        }
+                    }
+                    '$' if s.get(i) == Some(&'{') => {
+                        interp = true;
+                        i += 1;
+                        let inner_start = i;
+                        let mut depth = 1usize;
+                        loop {
+                            match s.get(i) {
+                                None => return Err(format!("unterminated ${{}} at line {}", line)),
+                                Some('{') => depth += 1,
+                                Some('}') => {
+                                    depth -= 1;
+                                    if depth == 0 {
+                                        break;
+                                    }
+                                }
+                                Some('\n') => line += 1,
+                                _ => {}
+                            }
+                            i += 1;
+                        }
+                        let inner: String = s[inner_start..i].iter().collect();
+                        i += 1;
+                        let mut p = Parser::new(lex(&inner)?);
+                        p.parse_expr().map_err(|e| format!("{} (in string interpolation)", e))?;
+                    }
+                    _ => {}
+                }
             }
-            while i < chars.len() {
-                out.push(chars[i]);
+            if interp { TK::StrInterp } else { TK::Str }
+        } else {
+            let three: String = s[i..(i + 3).min(s.len())].iter().collect();
+            let two: String = s[i..(i + 2).min(s.len())].iter().collect();
+            if three == "..." {
+                i += 3;
+            } else if ["||", "!=", "==", "<=", ">=", "&&", "->", "=>", "|>"].contains(&two.as_str()) {
+                i += 2;
+            } else if "(){}[],:.+-*/=%|<>?!".contains(c) {
                 i += 1;
+            } else {
+                return Err(format!("unexpected char: {} at line {}", c, line));
+            }
+            TK::Sym
+        };
+        toks.push(Tok {
+            kind,
+            text: s[start..i].iter().collect(),
+            line: start_line,
+            end_line: line,
+            nl_before,
+            blank_before,
+            comments,
+        });
+    }
+}
+
+// ── Surface AST ─────────────────────────────────────────────────────────────
+
+/// A node in a list of siblings (items, statements, elements, arms) with its comments.
+#[derive(Clone, Debug)]
+struct Elem<T> {
+    node: T,
+    lead: Vec<Comment>,
+    trail: Option<Comment>,
+    /// Blank line before the element (before its first leading comment, if any)
+    blank: bool,
+    /// Blank line between the leading comments and the element itself
+    blank_after_lead: bool,
+    first_line: usize,
+    last_line: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Seq<T> {
+    elems: Vec<Elem<T>>,
+    /// Comments after the last element, before the closing delimiter
+    dangling: Vec<Comment>,
+}
+
+#[derive(Clone, Debug)]
+struct Module {
+    items: Vec<Elem<Item>>,
+    dangling: Vec<Comment>,
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Import(String, String),
+    Artifact(String, String),
+    Let(Pat, Expr),
+    Fn(String, Seq<Param>, Option<Type>, Block),
+    Export(Seq<String>),
+    TypeDef(String, TypeDefKind),
+    Test(String, Block),
+    Expr(Expr),
+}
+
+/// `field: Type` pairs of a declared record or variant
+type Fields = Vec<(String, String)>;
+
+#[derive(Clone, Debug)]
+enum TypeDefKind {
+    Record(Fields),
+    Sum(Vec<(String, Option<Fields>)>),
+}
+
+#[derive(Clone, Debug)]
+struct Param {
+    pat: Pat,
+    ann: Option<Type>,
+    default: Option<Expr>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Named(String, Option<Vec<Type>>),
+    Rec(Vec<(String, Type)>),
+    Func(Vec<Type>, Box<Type>),
+    Paren(Box<Type>),
+}
+
+#[derive(Clone, Debug)]
+enum Pat {
+    Wild,
+    Bind(String),
+    Lit(String),
+    Obj(Vec<(String, Option<Pat>)>, Option<String>),
+    List(Vec<Pat>, Option<String>),
+}
+
+#[derive(Clone, Debug)]
+struct Block {
+    stmts: Vec<Elem<Stmt>>,
+    dangling: Vec<Comment>,
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    /// `let pat = rhs`, optionally followed by `|`
+    Let(Pat, Expr, bool),
+    /// An expression, optionally followed by `|`; the last statement is the block's value
+    Expr(Expr, bool),
+}
+
+#[derive(Clone, Debug)]
+struct Arg {
+    name: Option<String>,
+    value: Expr,
+}
+
+#[derive(Clone, Debug)]
+struct Arm {
+    pat: Pat,
+    guard: Option<Expr>,
+    body: Expr,
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    kind: EK,
+    lead: Vec<Comment>,
+}
+
+#[derive(Clone, Debug)]
+enum EK {
+    /// Number, `true`, `false` or `null`
+    Lit(String),
+    /// String literal, kept verbatim
+    Str(String),
+    Interp(String),
+    Var(String),
+    Paren(Box<Expr>),
+    List(Seq<Expr>),
+    Comp(Box<Expr>, Pat, Box<Expr>, Option<Box<Expr>>),
+    Rec(Seq<(String, Expr)>),
+    Fn(Seq<Pat>, Block),
+    /// `x => e` or `(a, b) => e`; the flag records the parentheses
+    Lambda(Vec<String>, bool, Box<Expr>),
+    Call(Box<Expr>, Seq<Arg>),
+    Get(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Try(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Unary(String, Box<Expr>),
+    Bin(String, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `then { let ... }` block
+    Block(Block),
+    LetIn(Pat, Box<Expr>, Box<Expr>),
+    Using(Pat, Box<Expr>, Box<Expr>),
+    Match(Box<Expr>, Seq<Arm>),
+    While(Box<Expr>, Box<Expr>, Box<Expr>),
+    For(Pat, Box<Expr>, Box<Expr>),
+    Return(Box<Expr>),
+}
+
+fn ex(kind: EK) -> Expr {
+    Expr { kind, lead: Vec::new() }
+}
+
+// ── Parser (mirrors fardrun's grammar, without desugaring) ────────────────────
+
+type PResult<T> = Result<T, String>;
+
+struct Parser {
+    toks: Vec<Tok>,
+    i: usize,
+    /// Comments passed over that no node has claimed yet
+    pending: Vec<Comment>,
+}
+
+fn parse_module(src: &str) -> PResult<Module> {
+    let mut p = Parser::new(lex(src)?);
+    let mut items = Vec::new();
+    while p.peek().kind != TK::Eof {
+        let e = p.elem(|p| p.parse_item())?;
+        items.push(e);
+    }
+    let dangling = p.take_lead();
+    Ok(Module { items, dangling })
+}
+
+impl Parser {
+    fn new(toks: Vec<Tok>) -> Self {
+        Parser { toks, i: 0, pending: Vec::new() }
+    }
+    fn peek(&self) -> &Tok {
+        &self.toks[self.i.min(self.toks.len() - 1)]
+    }
+    fn peek_n(&self, n: usize) -> &Tok {
+        &self.toks[(self.i + n).min(self.toks.len() - 1)]
+    }
+    fn bump(&mut self) -> Tok {
+        let idx = self.i.min(self.toks.len() - 1);
+        let c = std::mem::take(&mut self.toks[idx].comments);
+        self.pending.extend(c);
+        if self.i < self.toks.len() - 1 {
+            self.i += 1;
+        }
+        self.toks[idx].clone()
+    }
+    fn err<T>(&self, msg: &str) -> PResult<T> {
+        let t = self.peek();
+        let got = if t.kind == TK::Eof { "end of input".to_string() } else { format!("{:?}", t.text) };
+        Err(format!("ERROR_PARSE {}; got {} at line {}", msg, got, t.line))
+    }
+    fn is_sym(&self, s: &str) -> bool {
+        let t = self.peek();
+        t.kind == TK::Sym && t.text == s
+    }
+    fn is_sym_n(&self, n: usize, s: &str) -> bool {
+        let t = self.peek_n(n);
+        t.kind == TK::Sym && t.text == s
+    }
+    fn eat_sym(&mut self, s: &str) -> bool {
+        self.is_sym(s) && {
+            self.bump();
+            true
+        }
+    }
+    fn expect_sym(&mut self, s: &str) -> PResult<()> {
+        if self.eat_sym(s) { Ok(()) } else { self.err(&format!("expected symbol {:?}", s)) }
+    }
+    /// fardrun's eat_kw also accepts identifiers (`for`, `do`, `artifact`)
+    fn is_kw(&self, s: &str) -> bool {
+        let t = self.peek();
+        matches!(t.kind, TK::Kw | TK::Ident) && t.text == s
+    }
+    fn eat_kw(&mut self, s: &str) -> bool {
+        self.is_kw(s) && {
+            self.bump();
+            true
+        }
+    }
+    fn expect_kw(&mut self, s: &str) -> PResult<()> {
+        if self.eat_kw(s) { Ok(()) } else { self.err(&format!("expected keyword {}", s)) }
+    }
+    fn expect_ident(&mut self) -> PResult<String> {
+        if self.peek().kind == TK::Ident { Ok(self.bump().text) } else { self.err("expected identifier") }
+    }
+    fn expect_str(&mut self, what: &str) -> PResult<String> {
+        if self.peek().kind == TK::Str { Ok(self.bump().text) } else { self.err(what) }
+    }
+    fn prev_end_line(&self) -> usize {
+        self.toks[self.i.saturating_sub(1)].end_line
+    }
+
+    /// Claim the pending comments plus those before the current token.
+    fn take_lead(&mut self) -> Vec<Comment> {
+        let idx = self.i.min(self.toks.len() - 1);
+        let mut lead = std::mem::take(&mut self.pending);
+        lead.extend(std::mem::take(&mut self.toks[idx].comments));
+        lead
+    }
+    /// A comment on the same line as the previous token belongs to the element that just ended.
+    fn take_trail(&mut self) -> Option<Comment> {
+        let idx = self.i.min(self.toks.len() - 1);
+        if self.pending.is_empty() && self.toks[idx].comments.first().is_some_and(|c| !c.own_line) {
+            Some(self.toks[idx].comments.remove(0))
+        } else {
+            None
+        }
+    }
+    fn elem<T>(&mut self, f: impl FnOnce(&mut Self) -> PResult<T>) -> PResult<Elem<T>> {
+        let tok_blank = self.peek().blank_before;
+        let lead = self.take_lead();
+        let blank = lead.first().map(|c| c.blank_before).unwrap_or(tok_blank);
+        let first_line = lead.first().map(|c| c.line).unwrap_or(self.peek().line);
+        let node = f(self)?;
+        let trail = self.take_trail();
+        Ok(Elem {
+            node,
+            blank_after_lead: !lead.is_empty() && tok_blank,
+            lead,
+            trail,
+            blank,
+            first_line,
+            last_line: self.prev_end_line(),
+        })
+    }
+    /// Comma-separated elements up to `close`, allowing a trailing comma when `trailing`.
+    fn seq<T>(&mut self, close: &str, trailing: bool, mut f: impl FnMut(&mut Self) -> PResult<T>) -> PResult<Seq<T>> {
+        let mut elems: Vec<Elem<T>> = Vec::new();
+        if !self.is_sym(close) {
+            loop {
+                let mut e = self.elem(&mut f)?;
+                if self.is_sym(close) {
+                    elems.push(e);
+                    break;
+                }
+                self.expect_sym(",")?;
+                if e.trail.is_none() {
+                    e.trail = self.take_trail();
+                }
+                elems.push(e);
+                if trailing && self.is_sym(close) {
+                    break;
+                }
             }
-            continue;
         }
+        let dangling = self.take_lead();
+        self.expect_sym(close)?;
+        Ok(Seq { elems, dangling })
+    }
 
-        // Binary operators: ensure single space around them
-        if is_op_char(c) {
-            // Collect full operator
-            let op_start = i;
-            while i < chars.len() && is_op_char(chars[i]) {
-                i += 1;
+    fn parse_item(&mut self) -> PResult<Item> {
+        if self.eat_kw("test") {
+            let label = self.expect_str("test expects a string label")?;
+            self.expect_sym("{")?;
+            return Ok(Item::Test(label, self.parse_block()?));
+        }
+        if self.peek().kind == TK::Ident && self.peek().text == "a" {
+            self.bump();
+            let name = self.expect_ident()?;
+            if !(self.peek().kind == TK::Ident && self.peek().text == "is") {
+                return self.err("expected 'is'");
+            }
+            self.bump();
+            let kind = if self.eat_sym("{") {
+                let mut fields = Vec::new();
+                while !self.is_sym("}") {
+                    let f = self.expect_ident()?;
+                    self.expect_sym(":")?;
+                    fields.push((f, self.expect_ident()?));
+                    self.eat_sym(",");
+                }
+                self.bump();
+                TypeDefKind::Record(fields)
+            } else {
+                let mut variants = Vec::new();
+                loop {
+                    let v = self.expect_ident()?;
+                    let fields = if self.eat_sym("(") {
+                        let mut fields = Vec::new();
+                        while !self.is_sym(")") {
+                            let f = self.expect_ident()?;
+                            self.expect_sym(":")?;
+                            fields.push((f, self.expect_ident()?));
+                            self.eat_sym(",");
+                        }
+                        self.bump();
+                        Some(fields)
+                    } else {
+                        None
+                    };
+                    variants.push((v, fields));
+                    if !(self.peek().kind == TK::Ident && self.peek().text == "or") {
+                        break;
+                    }
+                    self.bump();
+                }
+                TypeDefKind::Sum(variants)
+            };
+            return Ok(Item::TypeDef(name, kind));
+        }
+        if self.eat_kw("import") {
+            self.expect_sym("(")?;
+            let path = self.expect_str("import() requires string")?;
+            self.expect_sym(")")?;
+            self.expect_kw("as")?;
+            return Ok(Item::Import(path, self.expect_ident()?));
+        }
+        if self.eat_kw("artifact") {
+            let name = self.expect_ident()?;
+            self.expect_sym("=")?;
+            return Ok(Item::Artifact(name, self.expect_str("artifact requires run_id string")?));
+        }
+        if self.eat_kw("export") {
+            self.expect_sym("{")?;
+            let names = self.seq("}", true, |p| p.expect_ident())?;
+            if names.elems.is_empty() {
+                return self.err("expected identifier");
+            }
+            return Ok(Item::Export(names));
+        }
+        if self.eat_kw("fn") {
+            let name = self.expect_ident()?;
+            self.expect_sym("(")?;
+            let params = self.seq(")", false, |p| {
+                let pat = p.parse_pat()?;
+                if matches!(pat, Pat::Bind(_)) && p.eat_sym("=") {
+                    return Ok(Param { pat, ann: None, default: Some(p.parse_expr()?) });
+                }
+                let ann = if p.eat_sym(":") { Some(p.parse_type()?) } else { None };
+                Ok(Param { pat, ann, default: None })
+            })?;
+            let ret = if self.eat_sym("->") { Some(self.parse_type()?) } else { None };
+            self.expect_sym("{")?;
+            return Ok(Item::Fn(name, params, ret, self.parse_block()?));
+        }
+        if self.eat_kw("let") {
+            let pat = self.parse_pat()?;
+            self.expect_sym("=")?;
+            let rhs = self.parse_expr()?;
+            if self.eat_kw("in") {
+                let body = self.parse_expr()?;
+                return Ok(Item::Expr(ex(EK::LetIn(pat, Box::new(rhs), Box::new(body)))));
             }
-            let op: String = chars[op_start..i].iter().collect();
+            return Ok(Item::Let(pat, rhs));
+        }
+        Ok(Item::Expr(self.parse_expr()?))
+    }
 
-            // Don't add spaces around -> in type annotations or => in match
-            let needs_space = !matches!(op.as_str(), "." | "::" | "@");
+    /// Statements after `{` up to and including the closing `}`.
+    fn parse_block(&mut self) -> PResult<Block> {
+        let mut stmts = Vec::new();
+        loop {
+            let mut done = false;
+            let e = self.elem(|p| {
+                if p.eat_kw("let") {
+                    if p.is_sym("{") || p.is_sym("[") {
+                        let pat = p.parse_pat()?;
+                        p.expect_sym("=")?;
+                        return Ok(Stmt::Let(pat, p.parse_expr()?, false));
+                    }
+                    let name = p.expect_ident()?;
+                    p.expect_sym("=")?;
+                    let rhs = p.parse_expr()?;
+                    if p.eat_kw("in") {
+                        done = true;
+                        let body = p.parse_expr()?;
+                        return Ok(Stmt::Expr(ex(EK::LetIn(Pat::Bind(name), Box::new(rhs), Box::new(body))), false));
+                    }
+                    let seq = p.is_seq_bar() && {
+                        p.bump();
+                        true
+                    };
+                    return Ok(Stmt::Let(Pat::Bind(name), rhs, seq));
+                }
+                let e = p.parse_expr()?;
+                let seq = p.is_seq_bar() && {
+                    p.bump();
+                    true
+                };
+                done = !seq;
+                Ok(Stmt::Expr(e, seq))
+            })?;
+            // after `expr |` only expressions may follow
+            let after_seq_expr = matches!(e.node, Stmt::Expr(_, true));
+            stmts.push(e);
+            if done {
+                break;
+            }
+            if after_seq_expr {
+                loop {
+                    let e = self.elem(|p| {
+                        let e = p.parse_expr()?;
+                        let seq = p.is_seq_bar() && {
+                            p.bump();
+                            true
+                        };
+                        Ok(Stmt::Expr(e, seq))
+                    })?;
+                    let more = matches!(e.node, Stmt::Expr(_, true));
+                    stmts.push(e);
+                    if !more {
+                        break;
+                    }
+                }
+                break;
+            }
+        }
+        let dangling = self.take_lead();
+        self.expect_sym("}")?;
+        Ok(Block { stmts, dangling })
+    }
+    fn is_seq_bar(&self) -> bool {
+        self.is_sym("|") && !self.is_sym_n(1, "|")
+    }
 
-            if needs_space {
-                // Ensure space before
-                if !out.is_empty() && !out.ends_with(' ') && !out.ends_with('(') {
-                    out.push(' ');
+    fn parse_type(&mut self) -> PResult<Type> {
+        if self.eat_sym("(") {
+            let t = self.parse_type()?;
+            self.expect_sym(")")?;
+            return Ok(Type::Paren(Box::new(t)));
+        }
+        if !matches!(self.peek().kind, TK::Ident | TK::Kw) {
+            return self.err("expected type");
+        }
+        let name = self.bump().text;
+        match name.as_str() {
+            "Int" | "String" | "Bool" | "Unit" | "Dynamic" => Ok(Type::Named(name, None)),
+            "List" => {
+                self.expect_sym("<")?;
+                let t = self.parse_type()?;
+                self.expect_sym(">")?;
+                Ok(Type::Named(name, Some(vec![t])))
+            }
+            "Rec" => {
+                self.expect_sym("{")?;
+                let mut fields = Vec::new();
+                if !self.eat_sym("}") {
+                    loop {
+                        let k = self.expect_ident()?;
+                        self.expect_sym(":")?;
+                        fields.push((k, self.parse_type()?));
+                        if self.eat_sym("}") {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                    }
                 }
-                out.push_str(&op);
-                // Ensure space after (if not at end or followed by space)
-                if i < chars.len() && chars[i] != ' ' && chars[i] != '\n' {
-                    out.push(' ');
+                Ok(Type::Rec(fields))
+            }
+            "Func" => {
+                self.expect_sym("(")?;
+                let mut args = Vec::new();
+                if !self.eat_sym(")") {
+                    loop {
+                        args.push(self.parse_type()?);
+                        if self.eat_sym(")") {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                    }
+                }
+                self.expect_sym("->")?;
+                Ok(Type::Func(args, Box::new(self.parse_type()?)))
+            }
+            _ => {
+                if !self.eat_sym("<") {
+                    return Ok(Type::Named(name, None));
+                }
+                let mut args = Vec::new();
+                if !self.eat_sym(">") {
+                    loop {
+                        args.push(self.parse_type()?);
+                        if self.eat_sym(">") {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                    }
+                }
+                Ok(Type::Named(name, Some(args)))
+            }
+        }
+    }
+
+    fn parse_pat(&mut self) -> PResult<Pat> {
+        let t = self.peek().clone();
+        match t.kind {
+            TK::Ident if t.text == "_" => {
+                self.bump();
+                Ok(Pat::Wild)
+            }
+            TK::Kw if matches!(t.text.as_str(), "true" | "false" | "null") => {
+                self.bump();
+                Ok(Pat::Lit(t.text))
+            }
+            TK::Ident | TK::Kw => {
+                self.bump();
+                Ok(Pat::Bind(t.text))
+            }
+            TK::Num if !t.text.contains('.') => {
+                self.bump();
+                Ok(Pat::Lit(t.text))
+            }
+            TK::Str => {
+                self.bump();
+                Ok(Pat::Lit(t.text))
+            }
+            TK::Sym if t.text == "{" || t.text == "[" => {
+                let close = if t.text == "{" { "}" } else { "]" };
+                self.bump();
+                let mut fields = Vec::new();
+                let mut items = Vec::new();
+                let mut rest = None;
+                if !self.eat_sym(close) {
+                    loop {
+                        if self.eat_sym("...") {
+                            rest = Some(self.expect_ident()?);
+                            self.expect_sym(close)?;
+                            break;
+                        }
+                        if close == "}" {
+                            let k = self.expect_ident()?;
+                            let sub = if self.eat_sym(":") { Some(self.parse_pat()?) } else { None };
+                            fields.push((k, sub));
+                        } else {
+                            items.push(self.parse_pat()?);
+                        }
+                        if self.eat_sym(close) {
+                            break;
+                        }
+                        self.expect_sym(",")?;
+                        if self.eat_sym(close) {
+                            break;
+                        }
+                    }
                 }
+                Ok(if close == "}" { Pat::Obj(fields, rest) } else { Pat::List(items, rest) })
+            }
+            _ => self.err("expected pattern"),
+        }
+    }
+
+    fn parse_expr(&mut self) -> PResult<Expr> {
+        let lead = self.take_lead();
+        let mut e = self.parse_expr_inner()?;
+        if !lead.is_empty() {
+            e.lead.splice(0..0, lead);
+        }
+        Ok(e)
+    }
+    fn parse_expr_inner(&mut self) -> PResult<Expr> {
+        if self.eat_kw("using") || self.is_kw("let") {
+            let using = self.toks[self.i - 1].text == "using" && !self.is_kw("let");
+            if !using {
+                self.bump();
+            }
+            let pat = self.parse_pat()?;
+            self.expect_sym("=")?;
+            let rhs = self.parse_expr()?;
+            self.expect_kw("in")?;
+            let body = self.parse_expr()?;
+            let (rhs, body) = (Box::new(rhs), Box::new(body));
+            return Ok(ex(if using { EK::Using(pat, rhs, body) } else { EK::LetIn(pat, rhs, body) }));
+        }
+        if self.eat_kw("match") {
+            let scrut = self.parse_expr()?;
+            self.expect_sym("{")?;
+            let arms = self.seq("}", true, |p| {
+                let pat = p.parse_pat()?;
+                let guard = if p.eat_kw("if") { Some(p.parse_expr()?) } else { None };
+                p.expect_sym("=>")?;
+                Ok(Arm { pat, guard, body: p.parse_expr()? })
+            })?;
+            return Ok(ex(EK::Match(Box::new(scrut), arms)));
+        }
+        if self.eat_kw("while") {
+            let init = self.parse_expr()?;
+            let cond = self.parse_expr()?;
+            let body = self.parse_expr()?;
+            return Ok(ex(EK::While(Box::new(init), Box::new(cond), Box::new(body))));
+        }
+        if self.eat_kw("for") {
+            let pat = self.parse_pat()?;
+            self.expect_kw("in")?;
+            let xs = self.parse_expr()?;
+            self.expect_kw("do")?;
+            return Ok(ex(EK::For(pat, Box::new(xs), Box::new(self.parse_expr()?))));
+        }
+        if self.eat_kw("return") {
+            return Ok(ex(EK::Return(Box::new(self.parse_expr()?))));
+        }
+        if self.eat_kw("if") {
+            let c = self.parse_expr()?;
+            self.expect_kw("then")?;
+            let is_block = self.is_sym("{")
+                && ((self.peek_n(1).kind == TK::Kw && matches!(self.peek_n(1).text.as_str(), "let" | "return"))
+                    || self.is_sym_n(1, "}"));
+            let t = if is_block {
+                self.bump();
+                ex(EK::Block(self.parse_block()?))
             } else {
-                out.push_str(&op);
+                self.parse_expr()?
+            };
+            self.expect_kw("else")?;
+            let f = self.parse_expr()?;
+            return Ok(ex(EK::If(Box::new(c), Box::new(t), Box::new(f))));
+        }
+        self.parse_infix(0)
+    }
+    fn infix_prec(&self) -> Option<u8> {
+        let t = self.peek();
+        if t.kind != TK::Sym {
+            return None;
+        }
+        match t.text.as_str() {
+            "||" => Some(1),
+            "&&" => Some(2),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(3),
+            "+" | "-" => Some(4),
+            "*" | "/" | "%" => Some(5),
+            _ => None,
+        }
+    }
+    fn parse_infix(&mut self, min_prec: u8) -> PResult<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(prec) = self.infix_prec().filter(|p| *p >= min_prec) {
+            let op = self.bump().text;
+            let rhs = self.parse_infix(prec + 1)?;
+            lhs = ex(EK::Bin(op, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+    fn parse_unary(&mut self) -> PResult<Expr> {
+        let lead = self.take_lead();
+        let mut e = if self.is_sym("-") || self.is_sym("!") {
+            let op = self.bump().text;
+            ex(EK::Unary(op, Box::new(self.parse_unary()?)))
+        } else {
+            let mut e = self.parse_postfix()?;
+            while self.eat_sym("|>") {
+                let rhs = self.parse_postfix_lead()?;
+                e = ex(EK::Pipe(Box::new(e), Box::new(rhs)));
+            }
+            e
+        };
+        if !lead.is_empty() {
+            e.lead.splice(0..0, lead);
+        }
+        Ok(e)
+    }
+    fn parse_postfix_lead(&mut self) -> PResult<Expr> {
+        let lead = self.take_lead();
+        let mut e = self.parse_postfix()?;
+        if !lead.is_empty() {
+            e.lead.splice(0..0, lead);
+        }
+        Ok(e)
+    }
+    fn parse_postfix(&mut self) -> PResult<Expr> {
+        let mut e = self.parse_primary()?;
+        loop {
+            if self.eat_sym("?") {
+                e = ex(EK::Try(Box::new(e)));
+            } else if self.eat_sym(".") {
+                let n = self.expect_ident()?;
+                e = ex(EK::Get(Box::new(e), n));
+            } else if self.eat_sym("(") {
+                let args = self.seq(")", true, |p| {
+                    if p.peek().kind == TK::Ident && p.is_sym_n(1, ":") {
+                        let name = p.expect_ident()?;
+                        p.bump();
+                        return Ok(Arg { name: Some(name), value: p.parse_expr()? });
+                    }
+                    Ok(Arg { name: None, value: p.parse_expr()? })
+                })?;
+                e = ex(EK::Call(Box::new(e), args));
+            } else if self.is_sym("[") && !self.peek().nl_before && !is_literal(&e) {
+                self.bump();
+                let idx = self.parse_expr()?;
+                self.expect_sym("]")?;
+                e = ex(EK::Index(Box::new(e), Box::new(idx)));
+            } else {
+                return Ok(e);
             }
-            continue;
         }
+    }
+    fn parse_primary(&mut self) -> PResult<Expr> {
+        if self.peek().kind == TK::Ident && self.is_sym_n(1, "=>") {
+            let name = self.bump().text;
+            self.bump();
+            return Ok(ex(EK::Lambda(vec![name], false, Box::new(self.parse_expr()?))));
+        }
+        if self.is_sym("(") {
+            // (a, b) => body
+            let mut n = 1;
+            let mut names = Vec::new();
+            let mut ok = true;
+            if !self.is_sym_n(1, ")") {
+                loop {
+                    if self.peek_n(n).kind != TK::Ident {
+                        ok = false;
+                        break;
+                    }
+                    names.push(self.peek_n(n).text.clone());
+                    n += 1;
+                    if self.is_sym_n(n, ",") {
+                        n += 1;
+                        continue;
+                    }
+                    ok = self.is_sym_n(n, ")");
+                    break;
+                }
+            }
+            if ok && self.is_sym_n(n + 1, "=>") {
+                for _ in 0..n + 2 {
+                    self.bump();
+                }
+                return Ok(ex(EK::Lambda(names, true, Box::new(self.parse_expr()?))));
+            }
+        }
+        if self.eat_kw("fn") {
+            self.expect_sym("(")?;
+            let params = self.seq(")", true, |p| p.parse_pat())?;
+            self.expect_sym("{")?;
+            return Ok(ex(EK::Fn(params, self.parse_block()?)));
+        }
+        let t = self.peek().clone();
+        match t.kind {
+            TK::Num => {
+                self.bump();
+                Ok(ex(EK::Lit(t.text)))
+            }
+            TK::Str => {
+                self.bump();
+                Ok(ex(EK::Str(t.text)))
+            }
+            TK::StrInterp => {
+                self.bump();
+                Ok(ex(EK::Interp(t.text)))
+            }
+            TK::Kw if matches!(t.text.as_str(), "true" | "false" | "null") => {
+                self.bump();
+                Ok(ex(EK::Lit(t.text)))
+            }
+            TK::Ident => {
+                self.bump();
+                Ok(ex(EK::Var(t.text)))
+            }
+            TK::Sym if t.text == "(" => {
+                self.bump();
+                let e = self.parse_expr()?;
+                self.expect_sym(")")?;
+                Ok(ex(EK::Paren(Box::new(e))))
+            }
+            TK::Sym if t.text == "[" => {
+                self.bump();
+                if !self.is_sym("]") {
+                    let first_elem = self.elem(|p| p.parse_expr())?;
+                    if self.eat_kw("for") {
+                        let pat = self.parse_pat()?;
+                        self.expect_kw("in")?;
+                        let iter = self.parse_expr()?;
+                        let cond = if self.eat_kw("if") { Some(Box::new(self.parse_expr()?)) } else { None };
+                        self.expect_sym("]")?;
+                        let mut body = first_elem.node;
+                        body.lead.splice(0..0, first_elem.lead);
+                        if let Some(c) = first_elem.trail {
+                            self.pending.insert(0, c);
+                        }
+                        return Ok(ex(EK::Comp(Box::new(body), pat, Box::new(iter), cond)));
+                    }
+                    let mut elems = vec![first_elem];
+                    if !self.is_sym("]") {
+                        self.expect_sym(",")?;
+                        let last = elems.last_mut().unwrap();
+                        if last.trail.is_none() {
+                            last.trail = self.take_trail();
+                        }
+                        let rest = self.seq("]", true, |p| p.parse_expr())?;
+                        elems.extend(rest.elems);
+                        return Ok(ex(EK::List(Seq { elems, dangling: rest.dangling })));
+                    }
+                    let dangling = self.take_lead();
+                    self.bump();
+                    return Ok(ex(EK::List(Seq { elems, dangling })));
+                }
+                let dangling = self.take_lead();
+                self.bump();
+                Ok(ex(EK::List(Seq { elems: Vec::new(), dangling })))
+            }
+            TK::Sym if t.text == "{" => {
+                self.bump();
+                let fields = self.seq("}", true, |p| {
+                    let k = p.peek().clone();
+                    if !matches!(k.kind, TK::Ident | TK::Kw | TK::Str) {
+                        return p.err("record key must be ident or string");
+                    }
+                    p.bump();
+                    p.expect_sym(":")?;
+                    Ok((k.text, p.parse_expr()?))
+                })?;
+                Ok(ex(EK::Rec(fields)))
+            }
+            _ => self.err("unexpected token"),
+        }
+    }
+}
 
-        // Opening brace: ensure space inside
-        if c == '{' {
-            if !out.is_empty() && !out.ends_with(' ') {
-                out.push(' ');
+/// fardrun never indexes a literal: `[1][0]` is two expressions.
+fn is_literal(e: &Expr) -> bool {
+    match &e.kind {
+        EK::Lit(_) | EK::Str(_) | EK::List(_) | EK::Rec(_) => true,
+        EK::Paren(inner) => is_literal(inner),
+        _ => false,
+    }
+}
+
+// ── Layout engine ───────────────────────────────────────────────────────────
+
+#[derive(Clone, Debug)]
+enum Doc {
+    Text(String),
+    /// A space, or a newline when the enclosing group breaks
+    Line,
+    /// Nothing, or a newline when the enclosing group breaks
+    SoftLine,
+    HardLine,
+    /// A newline unless the output is already at the start of a line
+    FreshLine,
+    /// Forces the enclosing groups to break
+    BreakParent,
+    Cat(Vec<Doc>),
+    Nest(Box<Doc>),
+    /// Laid out flat when it fits; the flag is set when it contains a forced break
+    Group(Box<Doc>, bool),
+    /// Alternative layouts: the first flat if it fits, else the first whose opening line fits
+    Choice(Vec<Doc>, bool),
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn cat(v: Vec<Doc>) -> Doc {
+    Doc::Cat(v)
+}
+
+fn nest(d: Doc) -> Doc {
+    Doc::Nest(Box::new(d))
+}
+
+fn group(d: Doc) -> Doc {
+    let b = is_hard(&d);
+    Doc::Group(Box::new(d), b)
+}
+
+fn choice(states: Vec<Doc>) -> Doc {
+    let b = is_hard(&states[0]);
+    Doc::Choice(states, b)
+}
+
+fn is_hard(d: &Doc) -> bool {
+    match d {
+        Doc::HardLine | Doc::FreshLine | Doc::BreakParent => true,
+        Doc::Cat(v) => v.iter().any(is_hard),
+        Doc::Nest(d) => is_hard(d),
+        Doc::Group(_, b) | Doc::Choice(_, b) => *b,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut col = 0usize;
+    let mut stack: Vec<(usize, Mode, &Doc)> = vec![(0, Mode::Break, doc)];
+    while let Some((ind, mode, d)) = stack.pop() {
+        match d {
+            Doc::Text(s) => {
+                out.push_str(s);
+                col = match s.rfind('\n') {
+                    Some(p) => s[p + 1..].chars().count(),
+                    None => col + s.chars().count(),
+                };
             }
-            out.push('{');
-            i += 1;
-            // Skip existing spaces
-            while i < chars.len() && chars[i] == ' ' { i += 1; }
-            if i < chars.len() && chars[i] != '}' {
+            Doc::Line if mode == Mode::Flat => {
                 out.push(' ');
+                col += 1;
+            }
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => newline(&mut out, &mut col, ind),
+            Doc::FreshLine => {
+                if !out.rsplit('\n').next().unwrap_or("").trim().is_empty() {
+                    newline(&mut out, &mut col, ind);
+                }
+            }
+            Doc::BreakParent => {}
+            Doc::Cat(v) => {
+                for x in v.iter().rev() {
+                    stack.push((ind, mode, x));
+                }
+            }
+            Doc::Nest(x) => stack.push((ind + 2, mode, x)),
+            Doc::Group(x, brk) => {
+                let m = if mode == Mode::Flat
+                    || (!brk && fits(width as isize - col as isize, (ind, Mode::Flat, x), &stack))
+                {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+                stack.push((ind, m, x));
+            }
+            Doc::Choice(states, brk) => {
+                let rem = width as isize - col as isize;
+                if mode == Mode::Flat || (!brk && fits(rem, (ind, Mode::Flat, &states[0]), &stack)) {
+                    stack.push((ind, Mode::Flat, &states[0]));
+                } else {
+                    let pick = states[1..]
+                        .iter()
+                        .find(|s| fits(rem, (ind, Mode::Break, s), &stack))
+                        .unwrap_or(states.last().unwrap());
+                    stack.push((ind, Mode::Break, pick));
+                }
             }
-            continue;
         }
+    }
+    out
+}
 
-        // Closing brace: ensure space before
-        if c == '}' {
-            if !out.is_empty() && !out.ends_with(' ') && !out.ends_with('{') {
-                out.push(' ');
+fn newline(out: &mut String, col: &mut usize, ind: usize) {
+    while out.ends_with(' ') {
+        out.pop();
+    }
+    out.push('\n');
+    out.push_str(&" ".repeat(ind));
+    *col = ind;
+}
+
+/// Whether the text up to the next possible line break fits in `rem` columns.
+fn fits(mut rem: isize, first: (usize, Mode, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack = vec![first];
+    let mut rest_i = rest.len();
+    loop {
+        let (ind, mode, d) = match stack.pop() {
+            Some(x) => x,
+            None => {
+                if rest_i == 0 {
+                    return true;
+                }
+                rest_i -= 1;
+                rest[rest_i]
+            }
+        };
+        match d {
+            Doc::Text(s) => {
+                if let Some(p) = s.find('\n') {
+                    return rem >= s[..p].chars().count() as isize;
+                }
+                rem -= s.chars().count() as isize;
+                if rem < 0 {
+                    return false;
+                }
+            }
+            Doc::Line | Doc::SoftLine => {
+                if mode == Mode::Break {
+                    return true;
+                }
+                if matches!(d, Doc::Line) {
+                    rem -= 1;
+                    if rem < 0 {
+                        return false;
+                    }
+                }
+            }
+            Doc::HardLine | Doc::FreshLine => return true,
+            Doc::BreakParent => {}
+            Doc::Cat(v) => {
+                for x in v.iter().rev() {
+                    stack.push((ind, mode, x));
+                }
+            }
+            Doc::Nest(x) => stack.push((ind + 2, mode, x)),
+            Doc::Group(x, brk) => stack.push((ind, if *brk { Mode::Break } else { mode }, x)),
+            Doc::Choice(states, brk) => {
+                let s = if mode == Mode::Break && *brk { states.last().unwrap() } else { &states[0] };
+                stack.push((ind, mode, s));
             }
-            out.push('}');
-            i += 1;
-            continue;
         }
+    }
+}
 
-        // Comma: no space before, one space after
-        if c == ',' {
-            // Remove trailing space before comma
-            while out.ends_with(' ') { out.pop(); }
-            out.push(',');
-            i += 1;
-            while i < chars.len() && chars[i] == ' ' { i += 1; }
-            if i < chars.len() && chars[i] != ')' && chars[i] != ']' && chars[i] != '}' {
-                out.push(' ');
+// ── AST → Doc ───────────────────────────────────────────────────────────────
+
+fn print_module(m: &Module) -> String {
+    let pads = import_pads(&m.items);
+    let mut parts = Vec::new();
+    for (i, it) in m.items.iter().enumerate() {
+        if i > 0 {
+            parts.push(Doc::HardLine);
+            if it.blank {
+                parts.push(Doc::HardLine);
+            }
+        }
+        parts.push(item_elem_doc(it, pads[i]));
+    }
+    for (i, c) in m.dangling.iter().enumerate() {
+        if !parts.is_empty() {
+            parts.push(Doc::HardLine);
+            if c.blank_before && (i > 0 || !m.items.is_empty()) {
+                parts.push(Doc::HardLine);
             }
-            continue;
         }
+        parts.push(text(&c.text));
+    }
+    let mut out = render(&cat(parts), WIDTH).trim_end().to_string();
+    out.push('\n');
+    out
+}
 
-        // Colon in record field: "key: value"
-        if c == ':' && i + 1 < chars.len() && chars[i + 1] != ':' {
-            while out.ends_with(' ') { out.pop(); }
-            out.push(':');
+/// Column padding for runs of consecutive imports so their `as` lines up.
+fn import_pads(items: &[Elem<Item>]) -> Vec<usize> {
+    let mut pads = vec![0; items.len()];
+    let mut i = 0;
+    while i < items.len() {
+        if !matches!(items[i].node, Item::Import(..)) {
             i += 1;
-            while i < chars.len() && chars[i] == ' ' { i += 1; }
-            out.push(' ');
             continue;
         }
+        let mut j = i + 1;
+        while j < items.len() && matches!(items[j].node, Item::Import(..)) && !items[j].blank {
+            j += 1;
+        }
+        let w = |it: &Elem<Item>| match &it.node {
+            Item::Import(p, _) => p.chars().count(),
+            _ => 0,
+        };
+        let max = items[i..j].iter().map(w).max().unwrap_or(0);
+        for k in i..j {
+            pads[k] = max - w(&items[k]);
+        }
+        i = j;
+    }
+    pads
+}
 
-        // Whitespace: collapse multiple spaces to one
-        if c == ' ' || c == '\t' {
-            if !out.is_empty() && !out.ends_with(' ') {
-                out.push(' ');
+fn item_elem_doc(e: &Elem<Item>, pad: usize) -> Doc {
+    elem_doc(e, item_doc(&e.node, pad))
+}
+
+/// Leading comments, the node, then a trailing comment.
+fn elem_doc<T>(e: &Elem<T>, node: Doc) -> Doc {
+    cat(vec![elem_lead(e), node, trail_doc(&e.trail)])
+}
+
+fn elem_lead<T>(e: &Elem<T>) -> Doc {
+    if e.lead.is_empty() {
+        return cat(vec![]);
+    }
+    let mut v = vec![Doc::FreshLine];
+    for (i, c) in e.lead.iter().enumerate() {
+        if i > 0 && c.blank_before {
+            v.push(Doc::HardLine);
+        }
+        v.push(text(&c.text));
+        v.push(Doc::HardLine);
+    }
+    if e.blank_after_lead {
+        v.push(Doc::HardLine);
+    }
+    cat(v)
+}
+
+fn trail_doc(c: &Option<Comment>) -> Doc {
+    match c {
+        Some(c) => cat(vec![text(format!(" {}", c.text)), Doc::BreakParent]),
+        None => cat(vec![]),
+    }
+}
+
+fn dangling_doc(cs: &[Comment]) -> Doc {
+    let mut v = Vec::new();
+    for c in cs {
+        v.push(Doc::HardLine);
+        if c.blank_before {
+            v.push(Doc::HardLine);
+        }
+        v.push(text(&c.text));
+    }
+    cat(v)
+}
+
+fn item_doc(it: &Item, pad: usize) -> Doc {
+    match it {
+        Item::Import(path, alias) => text(format!("import({}){} as {}", path, " ".repeat(pad), alias)),
+        Item::Artifact(name, run_id) => text(format!("artifact {} = {}", name, run_id)),
+        Item::Let(pat, rhs) => cat(vec![text(format!("let {} =", pat_str(pat))), rhs_doc(rhs)]),
+        Item::Fn(name, params, ret, body) => {
+            let params = seq_doc("(", ")", false, params, param_doc);
+            let ret = ret.as_ref().map(|t| text(format!(" -> {}", type_str(t)))).unwrap_or(cat(vec![]));
+            cat(vec![text(format!("fn {}", name)), params, ret, text(" "), block_doc(body)])
+        }
+        Item::Export(names) => cat(vec![text("export "), seq_doc("{", "}", true, names, |n| text(n))]),
+        Item::TypeDef(name, kind) => {
+            let fields = |fs: &[(String, String)]| {
+                fs.iter().map(|(f, t)| format!("{}: {}", f, t)).collect::<Vec<_>>().join(", ")
+            };
+            match kind {
+                TypeDefKind::Record(fs) if fs.is_empty() => text(format!("a {} is {{}}", name)),
+                TypeDefKind::Record(fs) => text(format!("a {} is {{ {} }}", name, fields(fs))),
+                TypeDefKind::Sum(vs) => {
+                    let vs: Vec<String> = vs
+                        .iter()
+                        .map(|(v, fs)| match fs {
+                            Some(fs) => format!("{}({})", v, fields(fs)),
+                            None => v.clone(),
+                        })
+                        .collect();
+                    text(format!("a {} is {}", name, vs.join(" or ")))
+                }
             }
-            i += 1;
-            continue;
         }
+        Item::Test(label, body) => cat(vec![text(format!("test {} ", label)), block_doc(body)]),
+        Item::Expr(e) => expr_doc(e),
+    }
+}
+
+fn param_doc(p: &Param) -> Doc {
+    let mut v = vec![text(pat_str(&p.pat))];
+    if let Some(t) = &p.ann {
+        v.push(text(format!(": {}", type_str(t))));
+    }
+    if let Some(d) = &p.default {
+        v.push(text(" ="));
+        v.push(rhs_doc(d));
+    }
+    cat(v)
+}
+
+/// `open elem, elem close`, breaking one element per line when it does not fit.
+fn seq_doc<T>(open: &str, close: &str, pad: bool, s: &Seq<T>, f: impl Fn(&T) -> Doc) -> Doc {
+    if s.elems.is_empty() && s.dangling.is_empty() {
+        return text(format!("{}{}", open, close));
+    }
+    let edge = if pad { Doc::Line } else { Doc::SoftLine };
+    group(cat(vec![
+        text(open),
+        nest(cat(vec![edge.clone(), seq_body(s, f)])),
+        edge,
+        text(close),
+    ]))
+}
+
+fn seq_body<T>(s: &Seq<T>, f: impl Fn(&T) -> Doc) -> Doc {
+    let n = s.elems.len();
+    let mut v = Vec::new();
+    for (i, e) in s.elems.iter().enumerate() {
+        if i > 0 {
+            v.push(Doc::Line);
+            if e.blank {
+                v.push(Doc::HardLine);
+            }
+        }
+        v.push(elem_lead(e));
+        v.push(f(&e.node));
+        if i + 1 < n {
+            v.push(text(","));
+        }
+        v.push(trail_doc(&e.trail));
+    }
+    v.push(dangling_doc(&s.dangling));
+    cat(v)
+}
+
+fn block_doc(b: &Block) -> Doc {
+    if b.stmts.len() == 1 && b.dangling.is_empty() {
+        let s = &b.stmts[0];
+        if let (Stmt::Expr(e, false), true, None) = (&s.node, s.lead.is_empty(), &s.trail) {
+            return group(cat(vec![text("{"), nest(cat(vec![Doc::Line, expr_doc(e)])), Doc::Line, text("}")]));
+        }
+    }
+    let mut v = Vec::new();
+    for (i, s) in b.stmts.iter().enumerate() {
+        v.push(Doc::HardLine);
+        if i > 0 && s.blank {
+            v.push(Doc::HardLine);
+        }
+        let node = match &s.node {
+            Stmt::Let(pat, rhs, seq) => cat(vec![
+                text(format!("let {} =", pat_str(pat))),
+                rhs_doc(rhs),
+                text(if *seq { " |" } else { "" }),
+            ]),
+            Stmt::Expr(e, seq) => cat(vec![expr_doc(e), text(if *seq { " |" } else { "" })]),
+        };
+        v.push(elem_doc(s, node));
+    }
+    v.push(dangling_doc(&b.dangling));
+    cat(vec![text("{"), nest(cat(v)), Doc::HardLine, text("}")])
+}
+
+/// The part after `=`, `:` or `=>`: operator chains and conditionals move to the next line
+/// as a whole when they do not fit; everything else stays on the line and breaks inside.
+fn rhs_doc(e: &Expr) -> Doc {
+    let d = expr_doc(e);
+    let hop = matches!(e.kind, EK::Bin(..) | EK::Pipe(..) | EK::If(..)) && !is_hard(&d);
+    if !e.lead.is_empty() || hop {
+        group(nest(cat(vec![Doc::Line, d])))
+    } else {
+        cat(vec![text(" "), d])
+    }
+}
+
+fn expr_doc(e: &Expr) -> Doc {
+    let body = expr_kind_doc(&e.kind);
+    if e.lead.is_empty() {
+        return body;
+    }
+    let mut v = vec![Doc::FreshLine];
+    for c in &e.lead {
+        v.push(text(&c.text));
+        v.push(Doc::HardLine);
+    }
+    v.push(body);
+    cat(v)
+}
+
+fn expr_kind_doc(k: &EK) -> Doc {
+    match k {
+        EK::Lit(s) | EK::Str(s) | EK::Interp(s) | EK::Var(s) => text(s),
+        EK::Paren(e) => cat(vec![text("("), expr_doc(e), text(")")]),
+        EK::List(s) => seq_doc("[", "]", false, s, expr_doc),
+        EK::Comp(body, pat, iter, cond) => {
+            let mut v = vec![
+                text("["),
+                expr_doc(body),
+                text(format!(" for {} in ", pat_str(pat))),
+                expr_doc(iter),
+            ];
+            if let Some(c) = cond {
+                v.push(text(" if "));
+                v.push(expr_doc(c));
+            }
+            v.push(text("]"));
+            cat(v)
+        }
+        EK::Rec(s) => seq_doc("{", "}", true, s, |(k, v)| cat(vec![text(format!("{}:", k)), rhs_doc(v)])),
+        EK::Fn(params, body) => cat(vec![
+            text("fn"),
+            seq_doc("(", ")", false, params, |p| text(pat_str(p))),
+            text(" "),
+            block_doc(body),
+        ]),
+        EK::Lambda(names, parens, body) => {
+            let head = if *parens || names.len() != 1 {
+                format!("({}) =>", names.join(", "))
+            } else {
+                format!("{} =>", names[0])
+            };
+            cat(vec![text(head), rhs_doc(body)])
+        }
+        EK::Call(callee, args) => call_doc(expr_doc(callee), args),
+        EK::Get(base, name) => cat(vec![expr_doc(base), text(format!(".{}", name))]),
+        EK::Index(base, idx) => cat(vec![expr_doc(base), text("["), expr_doc(idx), text("]")]),
+        EK::Try(e) => cat(vec![expr_doc(e), text("?")]),
+        EK::Pipe(..) => {
+            let mut stages = Vec::new();
+            let mut cur = k;
+            let mut head = None;
+            while let EK::Pipe(lhs, rhs) = cur {
+                stages.push(rhs);
+                if !lhs.lead.is_empty() {
+                    head = Some(expr_doc(lhs));
+                    break;
+                }
+                cur = &lhs.kind;
+            }
+            let head = head.unwrap_or_else(|| expr_kind_doc(cur));
+            let tail: Vec<Doc> = stages
+                .iter()
+                .rev()
+                .flat_map(|s| [Doc::Line, text("|> "), expr_doc(s)])
+                .collect();
+            group(cat(vec![head, nest(cat(tail))]))
+        }
+        EK::Unary(op, e) => cat(vec![text(op), expr_doc(e)]),
+        EK::Bin(op, ..) => {
+            let prec = bin_prec(op);
+            let mut operands = Vec::new();
+            let mut cur = k;
+            loop {
+                match cur {
+                    EK::Bin(op, lhs, rhs) if bin_prec(op) == prec => {
+                        operands.push((Some(op), rhs));
+                        if !lhs.lead.is_empty() {
+                            operands.push((None, lhs));
+                            break;
+                        }
+                        cur = &lhs.kind;
+                    }
+                    _ => break,
+                }
+            }
+            let head = match operands.last() {
+                Some((None, lhs)) => expr_doc(lhs),
+                _ => expr_kind_doc(cur),
+            };
+            let tail: Vec<Doc> = operands
+                .iter()
+                .rev()
+                .filter_map(|(op, e)| op.map(|op| cat(vec![Doc::Line, text(format!("{} ", op)), expr_doc(e)])))
+                .collect();
+            group(cat(vec![head, nest(cat(tail))]))
+        }
+        EK::If(c, t, f) => {
+            let then = match &t.kind {
+                EK::Block(b) if t.lead.is_empty() => cat(vec![text(" "), block_doc(b)]),
+                _ => group(nest(cat(vec![Doc::Line, expr_doc(t)]))),
+            };
+            let els = if matches!(f.kind, EK::If(..)) && f.lead.is_empty() {
+                cat(vec![text(" "), expr_doc(f)])
+            } else {
+                group(nest(cat(vec![Doc::Line, expr_doc(f)])))
+            };
+            group(cat(vec![text("if "), expr_doc(c), text(" then"), then, Doc::Line, text("else"), els]))
+        }
+        EK::Block(b) => block_doc(b),
+        EK::LetIn(pat, rhs, body) | EK::Using(pat, rhs, body) => {
+            let kw = if matches!(k, EK::LetIn(..)) { "let" } else { "using" };
+            group(cat(vec![
+                text(format!("{} {} =", kw, pat_str(pat))),
+                rhs_doc(rhs),
+                text(" in"),
+                Doc::Line,
+                expr_doc(body),
+            ]))
+        }
+        EK::Match(scrut, arms) => match_doc(scrut, arms),
+        EK::While(init, cond, body) => {
+            // `x\n[..]` is two expressions, `x [..]` is an index
+            let sep = |e: &Expr| if starts_with_bracket(e) { Doc::HardLine } else { Doc::Line };
+            group(cat(vec![
+                text("while "),
+                expr_doc(init),
+                nest(cat(vec![sep(cond), expr_doc(cond), sep(body), expr_doc(body)])),
+            ]))
+        }
+        EK::For(pat, xs, body) => cat(vec![
+            text(format!("for {} in ", pat_str(pat))),
+            expr_doc(xs),
+            text(" do"),
+            rhs_doc(body),
+        ]),
+        EK::Return(e) => cat(vec![text("return"), rhs_doc(e)]),
+    }
+}
+
+fn bin_prec(op: &str) -> u8 {
+    match op {
+        "||" => 1,
+        "&&" => 2,
+        "==" | "!=" | "<" | ">" | "<=" | ">=" => 3,
+        "+" | "-" => 4,
+        _ => 5,
+    }
+}
+
+fn starts_with_bracket(e: &Expr) -> bool {
+    if !e.lead.is_empty() {
+        return false;
+    }
+    match &e.kind {
+        EK::List(_) | EK::Comp(..) => true,
+        EK::Call(b, _) | EK::Get(b, _) | EK::Index(b, _) | EK::Try(b) | EK::Pipe(b, _) | EK::Bin(_, b, _) => {
+            starts_with_bracket(b)
+        }
+        _ => false,
+    }
+}
+
+/// `f(a, b)`; broken one argument per line, or with a trailing fn/record/list argument
+/// opened on the call line.
+fn call_doc(callee: Doc, args: &Seq<Arg>) -> Doc {
+    let arg_doc = |a: &Arg| match &a.name {
+        Some(n) => cat(vec![text(format!("{}:", n)), rhs_doc(&a.value)]),
+        None => expr_doc(&a.value),
+    };
+    let normal = cat(vec![callee.clone(), seq_doc("(", ")", false, args, arg_doc)]);
+    let n = args.elems.len();
+    let huggable = n > 0
+        && args.dangling.is_empty()
+        && args.elems.iter().all(|e| e.lead.is_empty() && e.trail.is_none() && !e.blank)
+        && args.elems[n - 1].node.value.lead.is_empty()
+        && matches!(args.elems[n - 1].node.value.kind, EK::Fn(..) | EK::Rec(_) | EK::List(_) | EK::Lambda(..))
+        && args.elems[..n - 1].iter().all(|e| !is_hard(&arg_doc(&e.node)) && !is_huggable_kind(&e.node.value.kind));
+    if !huggable {
+        return normal;
+    }
+    let mut hug = vec![callee, text("(")];
+    for e in &args.elems[..n - 1] {
+        hug.push(arg_doc(&e.node));
+        hug.push(text(", "));
+    }
+    hug.push(arg_doc(&args.elems[n - 1].node));
+    hug.push(text(")"));
+    let expanded = cat(vec![Doc::BreakParent, normal.clone()]);
+    choice(vec![normal, cat(hug), group(expanded)])
+}
+
+fn is_huggable_kind(k: &EK) -> bool {
+    matches!(k, EK::Fn(..) | EK::Lambda(..))
+}
+
+fn match_doc(scrut: &Expr, arms: &Seq<Arm>) -> Doc {
+    let head_doc = |a: &Arm| {
+        let mut v = vec![text(pat_str(&a.pat))];
+        if let Some(g) = &a.guard {
+            v.push(text(" if "));
+            v.push(expr_doc(g));
+        }
+        cat(v)
+    };
+    // Align `=>` when every arm head is a short single line
+    let heads: Vec<Option<String>> = arms
+        .elems
+        .iter()
+        .map(|e| {
+            let d = head_doc(&e.node);
+            if is_hard(&d) {
+                return None;
+            }
+            Some(render(&group(d), usize::MAX / 4))
+        })
+        .collect();
+    let width = heads.iter().map(|h| h.as_ref().map(|s| s.chars().count()).unwrap_or(usize::MAX)).max().unwrap_or(0);
+    let align = width <= 40;
+    let mut v = Vec::new();
+    let n = arms.elems.len();
+    for (i, e) in arms.elems.iter().enumerate() {
+        v.push(Doc::HardLine);
+        if i > 0 && e.blank {
+            v.push(Doc::HardLine);
+        }
+        let head = match (&heads[i], align) {
+            (Some(h), true) => text(format!("{}{}", h, " ".repeat(width - h.chars().count()))),
+            _ => head_doc(&e.node),
+        };
+        let mut arm = vec![head, text(" =>"), rhs_doc(&e.node.body)];
+        if i + 1 < n {
+            arm.push(text(","));
+        }
+        v.push(elem_doc(e, cat(arm)));
+    }
+    v.push(dangling_doc(&arms.dangling));
+    if n == 0 && arms.dangling.is_empty() {
+        return cat(vec![text("match "), expr_doc(scrut), text(" {}")]);
+    }
+    cat(vec![text("match "), expr_doc(scrut), text(" {"), nest(cat(v)), Doc::HardLine, text("}")])
+}
+
+fn pat_str(p: &Pat) -> String {
+    match p {
+        Pat::Wild => "_".to_string(),
+        Pat::Bind(s) | Pat::Lit(s) => s.clone(),
+        Pat::Obj(fields, rest) => {
+            let mut parts: Vec<String> = fields
+                .iter()
+                .map(|(k, sub)| match sub {
+                    Some(sub) => format!("{}: {}", k, pat_str(sub)),
+                    None => k.clone(),
+                })
+                .collect();
+            if let Some(r) = rest {
+                parts.push(format!("...{}", r));
+            }
+            if parts.is_empty() { "{}".to_string() } else { format!("{{ {} }}", parts.join(", ")) }
+        }
+        Pat::List(items, rest) => {
+            let mut parts: Vec<String> = items.iter().map(pat_str).collect();
+            if let Some(r) = rest {
+                parts.push(format!("...{}", r));
+            }
+            format!("[{}]", parts.join(", "))
+        }
+    }
+}
+
+fn type_str(t: &Type) -> String {
+    match t {
+        Type::Named(n, None) => n.clone(),
+        Type::Named(n, Some(args)) => {
+            format!("{}<{}>", n, args.iter().map(type_str).collect::<Vec<_>>().join(", "))
+        }
+        Type::Rec(fields) => {
+            let fs: Vec<String> = fields.iter().map(|(k, t)| format!("{}: {}", k, type_str(t))).collect();
+            if fs.is_empty() { "Rec {}".to_string() } else { format!("Rec {{ {} }}", fs.join(", ")) }
+        }
+        Type::Func(args, ret) => format!(
+            "Func({}) -> {}",
+            args.iter().map(type_str).collect::<Vec<_>>().join(", "),
+            type_str(ret)
+        ),
+        Type::Paren(t) => format!("({})", type_str(t)),
+    }
+}
+
+// ── AST dump (no comments, parentheses or layout) ────────────────────────────
 
-        out.push(c);
-        i += 1;
+fn dump_module(m: &Module) -> String {
+    let mut out = String::new();
+    for it in &m.items {
+        out.push_str(&dump_item(&it.node));
+        out.push('\n');
     }
+    out
+}
 
-    out.trim_end().to_string()
+fn dump_item(it: &Item) -> String {
+    match it {
+        Item::Import(p, a) => format!("(import {} {})", p, a),
+        Item::Artifact(n, r) => format!("(artifact {} {})", n, r),
+        Item::Let(p, e) => format!("(let {} {})", dump_pat(p), dump(e)),
+        Item::Fn(name, params, ret, body) => {
+            let ps: Vec<String> = params
+                .elems
+                .iter()
+                .map(|e| {
+                    let p = &e.node;
+                    format!(
+                        "({} {} {})",
+                        dump_pat(&p.pat),
+                        p.ann.as_ref().map(dump_type).unwrap_or_default(),
+                        p.default.as_ref().map(dump).unwrap_or_default()
+                    )
+                })
+                .collect();
+            let ret = ret.as_ref().map(dump_type).unwrap_or_default();
+            format!("(fn {} ({}) {} {})", name, ps.join(" "), ret, dump_block(body))
+        }
+        Item::Export(ns) => {
+            format!("(export {})", ns.elems.iter().map(|e| e.node.clone()).collect::<Vec<_>>().join(" "))
+        }
+        Item::TypeDef(n, k) => format!("(type {} {:?})", n, k),
+        Item::Test(l, b) => format!("(test {} {})", l, dump_block(b)),
+        Item::Expr(e) => dump(e),
+    }
 }
 
-fn is_op_char(c: char) -> bool {
-    matches!(c, '+' | '-' | '*' | '/' | '%' | '=' | '<' | '>' | '!' | '&' | '|' | '^' | '~')
+fn dump_block(b: &Block) -> String {
+    let ss: Vec<String> = b
+        .stmts
+        .iter()
+        .map(|s| match &s.node {
+            Stmt::Let(p, e, seq) => format!("(let {} {} {})", dump_pat(p), dump(e), seq),
+            Stmt::Expr(e, seq) => format!("({} {})", dump(e), seq),
+        })
+        .collect();
+    format!("(block {})", ss.join(" "))
 }
 
-fn count_unquoted(s: &str, target: char) -> usize {
-    let mut count = 0;
-    let mut in_str = false;
-    let mut chars = s.chars().peekable();
-    while let Some(c) = chars.next() {
-        if c == '"' && !in_str { in_str = true; continue; }
-        if c == '"' && in_str { in_str = false; continue; }
-        if c == '\\' && in_str { chars.next(); continue; }
-        if !in_str && c == target { count += 1; }
+fn dump_pat(p: &Pat) -> String {
+    match p {
+        Pat::Obj(fields, rest) => {
+            let fs: Vec<String> = fields
+                .iter()
+                .map(|(k, sub)| format!("{}:{}", k, sub.as_ref().map(dump_pat).unwrap_or_else(|| k.clone())))
+                .collect();
+            format!("{{{} ...{:?}}}", fs.join(" "), rest)
+        }
+        Pat::List(items, rest) => {
+            format!("[{} ...{:?}]", items.iter().map(dump_pat).collect::<Vec<_>>().join(" "), rest)
+        }
+        _ => pat_str(p),
+    }
+}
+
+fn dump_type(t: &Type) -> String {
+    match t {
+        Type::Paren(t) => dump_type(t),
+        _ => type_str(t),
+    }
+}
+
+fn dump_seq(s: &Seq<Expr>) -> String {
+    s.elems.iter().map(|e| dump(&e.node)).collect::<Vec<_>>().join(" ")
+}
+
+fn dump(e: &Expr) -> String {
+    match &e.kind {
+        EK::Lit(s) | EK::Str(s) | EK::Interp(s) | EK::Var(s) => s.clone(),
+        EK::Paren(e) => dump(e),
+        EK::List(s) => format!("[{}]", dump_seq(s)),
+        EK::Comp(b, p, it, c) => format!(
+            "(comp {} {} {} {})",
+            dump(b),
+            dump_pat(p),
+            dump(it),
+            c.as_ref().map(|c| dump(c)).unwrap_or_default()
+        ),
+        EK::Rec(s) => {
+            let fs: Vec<String> = s.elems.iter().map(|e| format!("{}={}", e.node.0, dump(&e.node.1))).collect();
+            format!("{{{}}}", fs.join(" "))
+        }
+        EK::Fn(ps, b) => format!(
+            "(fn ({}) {})",
+            ps.elems.iter().map(|e| dump_pat(&e.node)).collect::<Vec<_>>().join(" "),
+            dump_block(b)
+        ),
+        EK::Lambda(ns, _, b) => format!("(lambda ({}) {})", ns.join(" "), dump(b)),
+        EK::Call(f, args) => {
+            let a: Vec<String> = args
+                .elems
+                .iter()
+                .map(|e| match &e.node.name {
+                    Some(n) => format!("{}:{}", n, dump(&e.node.value)),
+                    None => dump(&e.node.value),
+                })
+                .collect();
+            format!("(call {} {})", dump(f), a.join(" "))
+        }
+        EK::Get(b, n) => format!("(get {} {})", dump(b), n),
+        EK::Index(b, i) => format!("(index {} {})", dump(b), dump(i)),
+        EK::Try(b) => format!("(try {})", dump(b)),
+        EK::Pipe(a, b) => format!("(pipe {} {})", dump(a), dump(b)),
+        EK::Unary(op, b) => format!("({} {})", op, dump(b)),
+        EK::Bin(op, a, b) => format!("({} {} {})", op, dump(a), dump(b)),
+        EK::If(c, t, f) => format!("(if {} {} {})", dump(c), dump(t), dump(f)),
+        EK::Block(b) => dump_block(b),
+        EK::LetIn(p, a, b) => format!("(let-in {} {} {})", dump_pat(p), dump(a), dump(b)),
+        EK::Using(p, a, b) => format!("(using {} {} {})", dump_pat(p), dump(a), dump(b)),
+        EK::Match(s, arms) => {
+            let a: Vec<String> = arms
+                .elems
+                .iter()
+                .map(|e| {
+                    let arm = &e.node;
+                    format!(
+                        "({} {} {})",
+                        dump_pat(&arm.pat),
+                        arm.guard.as_ref().map(dump).unwrap_or_default(),
+                        dump(&arm.body)
+                    )
+                })
+                .collect();
+            format!("(match {} {})", dump(s), a.join(" "))
+        }
+        EK::While(a, b, c) => format!("(while {} {} {})", dump(a), dump(b), dump(c)),
+        EK::For(p, xs, b) => format!("(for {} {} {})", dump_pat(p), dump(xs), dump(b)),
+        EK::Return(b) => format!("(return {})", dump(b)),
     }
-    count
 }
@@ -0,0 +1,69 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use fard_v0_5_language_gate::signing::{generate_key, key_id, load_signing_key, public_key_pem, signing_key_pem};
+
+fn usage() -> ! {
+    eprintln!("usage:");
+    eprintln!("  fardkey generate --out <key.pem> [--pub <key.pub.pem>] [--force]");
+    eprintln!("  fardkey pub      <key.pem>");
+    std::process::exit(2);
+}
+
+fn fail(e: anyhow::Error) -> ! {
+    eprintln!("{:#}", e);
+    std::process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        usage();
+    }
+    let flag = |name: &str| args.windows(2).find(|w| w[0] == name).map(|w| w[1].clone());
+
+    if args[1] == "generate" {
+        let out = flag("--out").unwrap_or_else(|| usage());
+        if Path::new(&out).exists() && !args.iter().any(|a| a == "--force") {
+            eprintln!("ERROR_SIGN {} exists; pass --force to overwrite", out);
+            std::process::exit(1);
+        }
+        let key = generate_key().unwrap_or_else(|e| fail(e));
+        if let Err(e) = write_private(&out, &signing_key_pem(&key)) {
+            eprintln!("ERROR_SIGN cannot write {}: {}", out, e);
+            std::process::exit(1);
+        }
+        if let Some(p) = flag("--pub") {
+            if let Err(e) = fs::write(&p, public_key_pem(&key.verifying_key())) {
+                eprintln!("ERROR_SIGN cannot write {}: {}", p, e);
+                std::process::exit(1);
+            }
+        }
+        println!("{}", key_id(&key.verifying_key()));
+        std::process::exit(0);
+    }
+
+    if args[1] == "pub" {
+        let key = load_signing_key(Path::new(&args[2])).unwrap_or_else(|e| fail(e));
+        println!("{}", key_id(&key.verifying_key()));
+        std::process::exit(0);
+    }
+
+    usage();
+}
+
+/// Write a private key readable by its owner only.
+fn write_private(path: &str, pem: &str) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut f = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+        f.write_all(pem.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        fs::write(path, pem)
+    }
+}
@@ -164,6 +164,11 @@ fn cmd_show_preimage(args: &[String]) -> Result<()> {
         let mut m = std::collections::BTreeMap::new();
         m.insert("files".to_string(), files);
         m.insert("ok".to_string(), ok);
+        for k in ["derived_from", "package", "policy_digest"] {
+            if let Some(v) = dobj.get(k) {
+                m.insert(k.to_string(), v.clone());
+            }
+        }
         m.insert("runtime_version".to_string(), runtime_version);
         m.insert("stdlib_root_digest".to_string(), stdlib_root_digest);
         m.insert("trace_format_version".to_string(), trace_format_version);
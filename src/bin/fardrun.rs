@@ -37,14 +37,10 @@ type Map = std::collections::BTreeMap<String, J>;
 use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use fard_v0_5_language_gate::{parse_toml_array, parse_toml_int, parse_toml_str, parse_toml_str_array, toml_lines, toml_quote, TomlLine};
+use fard_v0_5_language_gate::receipt_store::ReceiptStore;
+use fard_v0_5_language_gate::signing::{load_signing_key, package_name, RunSignature};
 thread_local! {
-    static VM_FNS: std::cell::RefCell<Vec<VmCompiledFn>> = std::cell::RefCell::new(Vec::new());
-    static VM_SELF_SLOTS: std::cell::RefCell<std::collections::HashMap<usize, (usize, Val)>> = std::cell::RefCell::new(std::collections::HashMap::<usize, (usize, Val)>::new());
-    /// Raw pointer to VM_FNS vec — valid for entire program execution, avoids per-call clone
-    static VM_FNS_PTR: std::cell::Cell<*const Vec<VmCompiledFn>> = std::cell::Cell::new(std::ptr::null());
-    /// Region-based slot arena — reusable Vec<Val> frames, avoids per-call heap allocation
-    static SLOT_ARENA: std::cell::RefCell<Vec<Vec<Val>>> = std::cell::RefCell::new(Vec::new());
-
     static PROGRAM_ARGS: std::cell::RefCell<Vec<String>> = std::cell::RefCell::new(vec![]);
     static CALL_DEPTH: std::cell::RefCell<usize> = std::cell::RefCell::new(0);
 }
@@ -288,7 +284,11 @@ fn resolve_version<'a>(range: &str, versions: &[&'a str]) -> Option<&'a str> {
 }
 
 /// Search packages in registry by query string.
+/// Uses a fardregistry server's `/search` when one is configured, else scans the static index.
 fn search_packages(query: &str) -> Result<Vec<(String, String, String)>> {
+    if let Some(url) = std::env::var("FARD_SEARCH_REGISTRY").ok().or_else(|| std::env::var("FARD_REGISTRY_URL").ok()) {
+        return search_registry(url.trim_end_matches('/'), query);
+    }
     let registry_body = ureq::get(REGISTRY_URL)
         .call()
         .map_err(|e| anyhow!("ERROR_REGISTRY failed to fetch registry: {e}"))?
@@ -316,6 +316,33 @@ fn search_packages(query: &str) -> Result<Vec<(String, String, String)>> {
     Ok(results)
 }
 
+/// Page through `GET <registry>/search?q=<query>`.
+fn search_registry(url: &str, query: &str) -> Result<Vec<(String, String, String)>> {
+    let mut results = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut req = ureq::get(&format!("{}/search", url)).query("q", query).query("limit", "1000");
+        if let Some(c) = &cursor {
+            req = req.query("cursor", c);
+        }
+        let body = req.call()
+            .map_err(|e| anyhow!("ERROR_REGISTRY search {}: {e}", url))?
+            .into_string()?;
+        let page: J = json_from_slice(body.as_bytes())?;
+        let packages = page.get("packages")
+            .and_then(|p| p.as_array())
+            .ok_or_else(|| anyhow!("ERROR_REGISTRY {} returned no packages", url))?;
+        for p in packages {
+            let field = |k: &str| p.get(k).and_then(|v| v.as_str()).unwrap_or("").to_string();
+            results.push((field("name"), field("version"), field("description")));
+        }
+        cursor = page.get("next_cursor").and_then(|c| c.as_str()).map(str::to_string);
+        if cursor.is_none() {
+            return Ok(results);
+        }
+    }
+}
+
 fn fetch_package(pkg_name: &str, version: &str) -> Result<PathBuf> {
     let cache_dir = fard_cache_dir();
     let pkg_dir = cache_dir.join(format!("{}@{}", pkg_name, version));
@@ -427,10 +454,28 @@ fn write_m5_digests(
     files.insert("trace.ndjson".to_string(), trace_h.clone());
     files.insert("module_graph.json".to_string(), modg_h.clone());
     files.insert(leaf_name.to_string(), leaf_h.clone());
+    // Only runs under a capability policy commit to one; unrestricted runs keep the v0.5 surface.
+    let policy_digest = CAP_POLICY.with(|p| p.borrow().as_ref().map(|p| p.digest()));
+    // Likewise for the runs a run imported and the package it ran in, which its receipt
+    // repeats; binding them here keeps a receipt's lineage from being rewritten.
+    let derived_from: Vec<J> = WITNESS_DEPS.with(|d| d.borrow().iter().cloned().map(J::Str).collect());
+    let package = RUN_PACKAGE.with(|p| p.borrow().clone());
+    let bound = |m: &mut Map| {
+        if !derived_from.is_empty() {
+            m.insert("derived_from".to_string(), J::Array(derived_from.clone()));
+        }
+        if let Some(pkg) = &package {
+            m.insert("package".to_string(), J::Str(pkg.clone()));
+        }
+    };
     let preimage = {
         let mut m = Map::new();
+        bound(&mut m);
         m.insert("files".to_string(), J::Object(files.iter().map(|(k,v)| (k.clone(), J::Str(v.clone()))).collect()));
         m.insert("ok".to_string(), J::Bool(ok));
+        if let Some(pd) = &policy_digest {
+            m.insert("policy_digest".to_string(), J::Str(pd.clone()));
+        }
         m.insert("runtime_version".to_string(), J::Str(runtime_version.to_string()));
         m.insert("stdlib_root_digest".to_string(), J::Str(stdlib_root_digest.to_string()));
         m.insert("trace_format_version".to_string(), J::Str(trace_format_version.to_string()));
@@ -441,8 +486,12 @@ fn write_m5_digests(
     println!("fard_run_digest={}", preimage_sha256);
     let dig = {
         let mut m = Map::new();
+        bound(&mut m);
         m.insert("files".to_string(), J::Object(files.into_iter().map(|(k,v)| (k, J::Str(v))).collect()));
         m.insert("ok".to_string(), J::Bool(ok));
+        if let Some(pd) = policy_digest {
+            m.insert("policy_digest".to_string(), J::Str(pd));
+        }
         m.insert("preimage_sha256".to_string(), J::Str(preimage_sha256.to_string()));
         m.insert("runtime_version".to_string(), J::Str(runtime_version.to_string()));
         m.insert("stdlib_root_digest".to_string(), J::Str(stdlib_root_digest.to_string()));
@@ -461,6 +510,86 @@ thread_local! {
     static SELF_DIGEST: std::cell::RefCell<String> = std::cell::RefCell::new(String::new());
     static SELF_DIGEST_ACCESSED: std::cell::RefCell<bool> = std::cell::RefCell::new(false);
     static FFI_LIBS: std::cell::RefCell<std::collections::HashMap<String, libloading::Library>> = std::cell::RefCell::new(std::collections::HashMap::new());
+    static ORACLE_MODE: std::cell::RefCell<OracleMode> = const { std::cell::RefCell::new(OracleMode::Live) };
+    static CAP_POLICY: std::cell::RefCell<Option<CapPolicy>> = const { std::cell::RefCell::new(None) };
+    /// Package named by the program's `fard.toml`, if any
+    static RUN_PACKAGE: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+    static ORACLE_CHILD_ANSWERS: std::cell::RefCell<Arc<HashMap<String, Vec<J>>>> = std::cell::RefCell::new(Arc::new(HashMap::new()));
+    static HTTP_FIXTURES: std::cell::RefCell<Option<Arc<HttpFixtures>>> = const { std::cell::RefCell::new(None) };
+    /// Open databases by handle; child tasks inherit their parent's
+    static SQLITE_DBS: std::cell::RefCell<HashMap<String, SqliteDb>> = std::cell::RefCell::new(HashMap::new());
+    /// Stdlib roots, besides this runtime's own, accepted on parent runs (`--stdlib-roots`)
+    static EXTRA_STDLIB_ROOTS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    /// When the running `net.listen` handler must finish; calls made after it fail
+    static DEADLINE: std::cell::RefCell<Option<std::time::Instant>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Fails once the thread's `DEADLINE` has passed. The evaluator checks it on every call
+/// and `chan.recv` while it waits, so a handler past its `timeout_ms` stops at the next one.
+fn check_deadline() -> Result<()> {
+    if DEADLINE.with(|d| d.borrow().is_some_and(|d| std::time::Instant::now() >= d)) {
+        bail!("ERROR_NET handler deadline passed");
+    }
+    Ok(())
+}
+
+/// A connection shared by the task that opened it and the tasks it starts.
+type SqliteDb = Arc<Mutex<rusqlite::Connection>>;
+static SQLITE_SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+static RECEIPT_STORE: Mutex<Option<Arc<ReceiptStore>>> = Mutex::new(None);
+
+/// The receipt store run IDs resolve through; the default store until a run configures one.
+fn receipt_store() -> Arc<ReceiptStore> {
+    let mut g = RECEIPT_STORE.lock().unwrap();
+    g.get_or_insert_with(|| {
+        Arc::new(ReceiptStore::configure(&[], None, None).unwrap_or_else(|_| ReceiptStore::new(vec![], None)))
+    })
+    .clone()
+}
+
+fn set_receipt_store(store: ReceiptStore) {
+    *RECEIPT_STORE.lock().unwrap() = Some(Arc::new(store));
+}
+
+fn set_extra_stdlib_roots(path: Option<&Path>) -> Result<()> {
+    let mut roots = Vec::new();
+    if let Some(p) = path {
+        let text = fs::read_to_string(p).with_context(|| format!("cannot read --stdlib-roots {}", p.display()))?;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if !line.is_empty() {
+                roots.push(line.to_string());
+            }
+        }
+    }
+    EXTRA_STDLIB_ROOTS.with(|c| *c.borrow_mut() = roots);
+    Ok(())
+}
+
+/// Refuse a parent run (an `artifact` import, a witnessed receipt or a `--replay` trace)
+/// that a runtime with an unknown builtin surface produced.
+fn check_parent_stdlib_root(what: &str, root: Option<&str>) -> Result<(), String> {
+    let root = root.ok_or_else(|| format!("{} records no stdlib_root_digest", what))?;
+    if root != env!("FARD_STDLIB_ROOT_DIGEST") && !EXTRA_STDLIB_ROOTS.with(|c| c.borrow().iter().any(|k| k == root)) {
+        return Err(format!("{} comes from a runtime with unknown stdlib root {} (list it in --stdlib-roots to accept it)", what, root));
+    }
+    Ok(())
+}
+
+/// The stdlib root a stored receipt's preimage commits to.
+fn receipt_stdlib_root(receipt: &J) -> Option<&str> {
+    receipt.get("preimage")?.get("stdlib_root_digest")?.as_str()
+}
+
+/// How non-deterministic builtins (time, randomness, env, stdin, http, process) get answered.
+enum OracleMode {
+    /// Ask the outside world; nothing is written to the trace.
+    Live,
+    /// Ask the outside world and write each answer as an `oracle` trace event.
+    Record,
+    /// Answer from the `oracle` events of a recorded trace, in call order.
+    Replay(std::collections::VecDeque<J>),
 }
 
 fn edit_distance(a: &str, b: &str) -> usize {
@@ -523,93 +652,6 @@ entry = \"main.fard\"\n", name))?;
 }
 
 
-
-fn expr_contains_while(expr: &Expr) -> bool {
-    match expr {
-        Expr::While(..) => true,
-        Expr::Let(_, e1, e2) => expr_contains_while(e1) || expr_contains_while(e2),
-        Expr::LetPat(_, e1, e2) => expr_contains_while(e1) || expr_contains_while(e2),
-        Expr::If(c, t, f) => expr_contains_while(c) || expr_contains_while(t) || expr_contains_while(f),
-        Expr::Bin(_, a, b) => expr_contains_while(a) || expr_contains_while(b),
-        Expr::Unary(_, e) => expr_contains_while(e),
-        Expr::Call(f, args) => expr_contains_while(f) || args.iter().any(|a| expr_contains_while(a)),
-        Expr::Fn(_, body) | Expr::Lambda(_, body) => expr_contains_while(body),
-        Expr::Get(e, _) => expr_contains_while(e),
-        Expr::List(xs) => xs.iter().any(|x| expr_contains_while(x)),
-        Expr::Rec(kvs) => kvs.iter().any(|(_, v)| expr_contains_while(v)),
-        Expr::Try(e) | Expr::Return(e) => expr_contains_while(e),
-        Expr::Match(e, arms) => expr_contains_while(e) || arms.iter().any(|a| expr_contains_while(&a.body)),
-        Expr::Using(_, e1, e2) => expr_contains_while(e1) || expr_contains_while(e2),
-        Expr::Index(a, b) => expr_contains_while(a) || expr_contains_while(b),
-        Expr::NamedCall(f, args) => expr_contains_while(f) || args.iter().any(|(_, v)| expr_contains_while(v)),
-        _ => false,
-    }
-}
-
-fn expr_contains_var(expr: &Expr, name: &str) -> bool {
-    match expr {
-        Expr::Var(n) => n == name,
-        Expr::Int(_) | Expr::FloatLit(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Null => false,
-        Expr::Let(_, e1, e2) => expr_contains_var(e1, name) || expr_contains_var(e2, name),
-        Expr::LetPat(_, e1, e2) => expr_contains_var(e1, name) || expr_contains_var(e2, name),
-        Expr::If(c, t, f) => expr_contains_var(c, name) || expr_contains_var(t, name) || expr_contains_var(f, name),
-        Expr::Bin(_, a, b) => expr_contains_var(a, name) || expr_contains_var(b, name),
-        Expr::Unary(_, e) => expr_contains_var(e, name),
-        Expr::Call(f, args) => expr_contains_var(f, name) || args.iter().any(|a| expr_contains_var(a, name)),
-        Expr::Fn(_, body) | Expr::Lambda(_, body) => expr_contains_var(body, name),
-        Expr::Get(e, _) => expr_contains_var(e, name),
-        Expr::List(xs) => xs.iter().any(|x| expr_contains_var(x, name)),
-        Expr::Rec(kvs) => kvs.iter().any(|(_, v)| expr_contains_var(v, name)),
-        Expr::Try(e) | Expr::Return(e) => expr_contains_var(e, name),
-        Expr::Match(e, arms) => expr_contains_var(e, name) || arms.iter().any(|a| expr_contains_var(&a.body, name)),
-        Expr::While(a, b, c) => expr_contains_var(a, name) || expr_contains_var(b, name) || expr_contains_var(c, name),
-        Expr::Using(_, e1, e2) => expr_contains_var(e1, name) || expr_contains_var(e2, name),
-        Expr::Index(a, b) => expr_contains_var(a, name) || expr_contains_var(b, name),
-        Expr::NamedCall(f, args) => expr_contains_var(f, name) || args.iter().any(|(_, v)| expr_contains_var(v, name)),
-        Expr::StrInterp(_) => false,
-    }
-}
-
-
-
-
-fn main() -> Result<()> {
-    let (run, want_version, want_repl, test_args, publish_args, install_args, new_args) = fard_v0_5_language_gate::cli::fardrun_cli::Cli::parse_compat();
-
-    // Handle search subcommand
-    if std::env::var("FARD_SEARCH_MODE").is_ok() {
-        let query = std::env::var("FARD_SEARCH_QUERY").unwrap_or_default();
-        match search_packages(&query) {
-            Ok(results) => {
-                if results.is_empty() {
-                    println!("No packages found{}", if query.is_empty() { String::new() } else { format!(" matching {:?}", query) });
-                } else {
-                    println!("{} package(s) found:\n", results.len());
-                    for (name, ver, desc) in &results {
-                        if desc.is_empty() {
-                            println!("  {}@{}", name, ver);
-                        } else {
-                            println!("  {}@{}  —  {}", name, ver, desc);
-                        }
-                    }
-                }
-            }
-            Err(e) => eprintln!("search error: {}", e),
-        }
-        return Ok(());
-    }
-    if want_version {
-        println!("fard_runtime_version={}", env!("CARGO_PKG_VERSION"));
-        println!("trace_format_version=0.1.0");
-        println!("stdlib_root_cid=sha256:dev");
-        return Ok(());
-    }
-    // fardrun new <name> [--template minimal|server|ci]
-    if let Some(new_args) = new_args {
-        use fard_v0_5_language_gate::cli::fardrun_cli::NewArgs;
-        return cmd_new(new_args);
-    }
-
 fn pretty_print_val(v: &Val, indent: usize) -> String {
     let pad = "  ".repeat(indent);
     let pad1 = "  ".repeat(indent + 1);
@@ -677,6 +719,463 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
     }
 }
 
+// ── Notebook kernel ──────────────────────────────────────────────────────────
+
+const NOTEBOOK_CELL_KIND: &str = "fard/notebook_cell/v1";
+const NOTEBOOK_RECEIPT_KIND: &str = "fard/notebook_receipt/v1";
+
+/// One ```fard``` block of a notebook.
+struct NbCell {
+    src: String,
+    /// 1-based notebook line of the cell's first source line
+    line: usize,
+    /// Names the cell binds at top level
+    defines: Vec<String>,
+    /// Every identifier the cell mentions, a superset of the names it reads
+    mentions: std::collections::BTreeSet<String>,
+    /// Earlier cells that last bound a name this cell mentions
+    upstream: Vec<usize>,
+    digest: String,
+}
+
+enum NbSegment {
+    Text(String),
+    Cell(usize),
+}
+
+/// Split a notebook into prose and cells, dropping the output blocks of a previous run.
+fn nb_parse(input: &str, file: &str) -> (Vec<NbSegment>, Vec<NbCell>) {
+    let lines: Vec<&str> = input.lines().collect();
+    let mut segments = Vec::new();
+    let mut cells = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].trim_start() != "```fard" {
+            if lines[i].trim_start() == "```output" {
+                while i < lines.len() && lines[i].trim() != "```" { i += 1; }
+            } else {
+                text.push_str(lines[i]);
+                text.push('\n');
+            }
+            i += 1;
+            continue;
+        }
+        i += 1;
+        let line = i + 1;
+        let mut src = String::new();
+        while i < lines.len() && lines[i].trim() != "```" {
+            src.push_str(lines[i]);
+            src.push('\n');
+            i += 1;
+        }
+        i += 1; // closing ```
+        // The output block written for this cell last time, with the blank line before it
+        let mut j = i;
+        while j < lines.len() && lines[j].trim().is_empty() { j += 1; }
+        if j < lines.len() && lines[j].trim_start() == "```output" {
+            while j < lines.len() && lines[j].trim() != "```" { j += 1; }
+            i = j + 1;
+        }
+
+        let mut defines = Vec::new();
+        let mut mentions = std::collections::BTreeSet::new();
+        if let Ok(mut p) = Parser::from_src(&src, file) {
+            mentions.extend(p.toks.iter().filter_map(|t| match t {
+                Tok::Ident(n) => Some(n.clone()),
+                _ => None,
+            }));
+            for it in p.parse_module().unwrap_or_default() {
+                match it {
+                    Item::Import(_, name) | Item::Artifact(name, _) | Item::Let(name, _, _) | Item::Fn(name, _, _, _) => {
+                        defines.push(name)
+                    }
+                    Item::TypeDef(name, kind) => {
+                        defines.push(name);
+                        if let TypeDefKind::Sum(variants) = kind {
+                            defines.extend(variants.into_iter().map(|(v, _)| v));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        segments.push(NbSegment::Text(std::mem::take(&mut text)));
+        segments.push(NbSegment::Cell(cells.len()));
+        cells.push(NbCell { src, line, defines, mentions, upstream: Vec::new(), digest: String::new() });
+    }
+    segments.push(NbSegment::Text(text));
+    (segments, cells)
+}
+
+/// A cell's digest covers its source, the runtime and the digests of its upstream cells,
+/// so editing a cell invalidates exactly the cells that (transitively) read its bindings.
+fn nb_cell_digest(src: &str, upstream: &[&str]) -> String {
+    let mut pre = format!("{}\n{}\n", NOTEBOOK_CELL_KIND, env!("CARGO_PKG_VERSION"));
+    for u in upstream {
+        pre.push_str(u);
+        pre.push('\n');
+    }
+    pre.push('\n');
+    pre.push_str(src);
+    format!("sha256:{}", sha256_bytes_hex(pre.as_bytes()))
+}
+
+fn nb_cache_path(cache_dir: &Path, digest: &str) -> PathBuf {
+    cache_dir.join(format!("{}.json", digest.trim_start_matches("sha256:")))
+}
+
+fn nb_cache_load(cache_dir: &Path, digest: &str) -> Option<Map> {
+    let bytes = fs::read(nb_cache_path(cache_dir, digest)).ok()?;
+    match json_from_slice(&bytes).ok()? {
+        J::Object(m) if m.get("digest") == Some(&J::Str(digest.to_string())) && matches!(m.get("trace"), Some(J::Str(_))) => {
+            Some(m)
+        }
+        _ => None,
+    }
+}
+
+/// The cell's bindings as JSON, or null when any of them (a function, an imported
+/// module) cannot be restored from JSON unchanged.
+fn nb_bindings(cell: &NbCell, env: &Env) -> J {
+    let mut m = Map::new();
+    for name in &cell.defines {
+        let Some(v) = env.get(name) else { return J::Null };
+        let Some(j) = v.to_json() else { return J::Null };
+        match json_from_str(&json_to_string(&j)).ok().and_then(|back| val_from_json(&back).ok()) {
+            Some(back) if val_eq(&back, &v) => { m.insert(name.clone(), j); }
+            _ => return J::Null,
+        }
+    }
+    J::Object(m)
+}
+
+fn nb_eval_cell(cell: &NbCell, file: &str, env: &mut Env, loader: &mut ModuleLoader, tracer: &mut Tracer, here: &Path) -> Result<Val> {
+    let mut p = Parser::from_src(&cell.src, file)?;
+    let items = p.parse_module()?;
+    loader.eval_items(items, env, tracer, here)
+}
+
+fn nb_render_value(v: &Val) -> String {
+    match v.to_json() {
+        Some(j) => {
+            let text = json_to_string(&j);
+            serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|sv| serde_json::to_string_pretty(&sv).ok())
+                .unwrap_or(text)
+        }
+        None => pretty_print_val(v, 0),
+    }
+}
+
+/// The error of a failed cell as JSON and as text, pointing at the notebook line when
+/// the error carries a span.
+fn nb_render_error(e: &anyhow::Error, cell: &NbCell, file: &str) -> (J, String) {
+    let message = e.root_cause().to_string();
+    let code = message
+        .split_whitespace()
+        .find(|w| w.starts_with("ERROR_"))
+        .unwrap_or("ERROR_RUNTIME")
+        .to_string();
+    let span = match e.downcast_ref::<SpannedRuntimeError>() {
+        Some(se) => Some(&se.span),
+        None => e.downcast_ref::<ParseError>().map(|pe| &pe.span),
+    };
+    // (line, col, width) within the cell
+    let at = match span {
+        Some(sp) => {
+            let (line, col) = line_col_at(&cell.src, sp.byte_start);
+            Some((line, col, sp.byte_end.saturating_sub(sp.byte_start)))
+        }
+        None => e.chain().find_map(|cause| {
+            let s = cause.to_string();
+            let mut parts = s.strip_prefix("  --> ")?.rsplitn(3, ':');
+            let col = parts.next()?.trim().parse().ok()?;
+            let line = parts.next()?.trim().parse().ok()?;
+            Some((line, col, 1))
+        }),
+    };
+    // Parse errors that carry no span: ask the diagnostics pass where the parser stopped
+    let at = at.or_else(|| {
+        let diag = check_diagnostics(&cell.src, file).into_iter().next()?;
+        let J::Object(d) = diag else { return None };
+        let Some(J::Object(sp)) = d.get("span") else { return None };
+        let int = |k: &str| match sp.get(k) { Some(J::Int(n)) => Some(*n as usize), _ => None };
+        let (line, col) = (int("line")?, int("col")?);
+        let width = if int("end_line")? == line { int("end_col")?.saturating_sub(col) } else { 1 };
+        Some((line, col, width))
+    });
+
+    let mut em = Map::new();
+    em.insert("code".to_string(), J::Str(code));
+    em.insert("message".to_string(), J::Str(message.clone()));
+    let mut text = format!("error: {}", message);
+    match at {
+        Some((line, col, width)) => {
+            let (line, col) = (line.max(1), col.max(1));
+            let nb_line = cell.line + line - 1;
+            let src_line = cell.src.lines().nth(line - 1).unwrap_or("");
+            let rest = src_line.chars().count().saturating_sub(col - 1);
+            let gutter = " ".repeat(nb_line.to_string().len());
+            text.push_str(&format!(
+                "\n{g}--> {file}:{nb_line}:{col}\n{g} |\n{nb_line} | {src_line}\n{g} | {pad}{carets}",
+                g = gutter,
+                pad = " ".repeat(col - 1),
+                carets = "^".repeat(width.min(rest).max(1)),
+            ));
+            let mut sm = Map::new();
+            sm.insert("file".to_string(), J::Str(file.to_string()));
+            sm.insert("line".to_string(), J::Int(nb_line as i64));
+            sm.insert("col".to_string(), J::Int(col as i64));
+            sm.insert("cell_line".to_string(), J::Int(line as i64));
+            em.insert("span".to_string(), J::Object(sm));
+        }
+        None => {
+            em.insert("span".to_string(), J::Null);
+        }
+    }
+    (J::Object(em), text)
+}
+
+/// `fardrun notebook`: evaluate the cells in order in one environment, reusing the
+/// cached result of every cell whose digest is unchanged, and write per-cell receipts
+/// plus a notebook receipt whose Merkle root covers them.
+fn run_notebook(nb: fard_v0_5_language_gate::cli::fardrun_cli::NotebookArgs) -> Result<()> {
+    let input = fs::read_to_string(&nb.input)
+        .with_context(|| format!("cannot read notebook: {}", nb.input.display()))?;
+    let file = nb.input.to_string_lossy().to_string();
+    let out_dir = PathBuf::from(&nb.out_dir);
+    let cache_dir = out_dir.join("cache");
+    let cells_dir = out_dir.join("cells");
+    let _ = fs::remove_dir_all(&cells_dir);
+    fs::create_dir_all(&cache_dir)?;
+    fs::create_dir_all(&cells_dir)?;
+
+    let (segments, mut cells) = nb_parse(&input, &file);
+    let mut definer: HashMap<String, usize> = HashMap::new();
+    for i in 0..cells.len() {
+        let upstream: std::collections::BTreeSet<usize> =
+            cells[i].mentions.iter().filter_map(|n| definer.get(n).copied()).collect();
+        let digests: Vec<&str> = upstream.iter().map(|&u| cells[u].digest.as_str()).collect();
+        let digest = nb_cell_digest(&cells[i].src, &digests);
+        cells[i].digest = digest;
+        cells[i].upstream = upstream.into_iter().collect();
+        for name in &cells[i].defines {
+            definer.insert(name.clone(), i);
+        }
+    }
+
+    let cached: Vec<Option<Map>> = cells
+        .iter()
+        .map(|c| if nb.force { None } else { nb_cache_load(&cache_dir, &c.digest) })
+        .collect();
+    // A cached cell is replayed when a cell that runs reads bindings its cache entry cannot restore
+    let mut replay = vec![false; cells.len()];
+    for i in (0..cells.len()).rev() {
+        if cached[i].is_some() && !replay[i] {
+            continue;
+        }
+        for &u in &cells[i].upstream {
+            if matches!(&cached[u], Some(c) if !matches!(c.get("bindings"), Some(J::Object(_)))) {
+                replay[u] = true;
+            }
+        }
+    }
+
+    let here = nb.input.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let fard_toml_path = here.join("fard.toml");
+    let mut loader = ModuleLoader::new(&here);
+    loader.load_fard_toml(&fard_toml_path);
+    set_receipt_store(ReceiptStore::configure(&[], None, Some(&fard_toml_path))?);
+    // Artifact CIDs carry over from cell to cell; each cell's events go to its own trace
+    let mut artifact_cids = BTreeMap::new();
+    let mut env = base_env();
+
+    let mut outputs: Vec<String> = Vec::with_capacity(cells.len());
+    let mut entries: Vec<J> = Vec::with_capacity(cells.len());
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(cells.len());
+    let (mut ran, mut hits, mut replayed, mut failed) = (0usize, 0usize, 0usize, 0usize);
+    for (idx, cell) in cells.iter().enumerate() {
+        let t0 = std::time::Instant::now();
+        let trace_path = cells_dir.join(format!("cell_{}.ndjson", idx));
+        let (status, outcome) = match &cached[idx] {
+            Some(entry) if !replay[idx] => {
+                if let Some(J::Object(bindings)) = entry.get("bindings") {
+                    for (name, j) in bindings {
+                        env.set(name.clone(), val_from_json(j)?);
+                    }
+                }
+                let result = entry.get("result").cloned().unwrap_or(J::Null);
+                let output = match entry.get("output") {
+                    Some(J::Str(s)) => s.clone(),
+                    _ => String::new(),
+                };
+                if let Some(J::Str(trace)) = entry.get("trace") {
+                    fs::write(&trace_path, trace)?;
+                }
+                hits += 1;
+                ("cached", Ok((result, output)))
+            }
+            _ => {
+                let status = if cached[idx].is_some() { replayed += 1; "replayed" } else { ran += 1; "ran" };
+                let mut tracer = Tracer::new(&out_dir, &trace_path)?;
+                tracer.artifact_cids = std::mem::take(&mut artifact_cids);
+                tracer.spawn_prefix = format!("cell_{}", idx);
+                let v = nb_eval_cell(cell, &file, &mut env, &mut loader, &mut tracer, &here);
+                let ended = tracer.end_span();
+                let v = v.and_then(|v| ended.map(|_| v));
+                artifact_cids = std::mem::take(&mut tracer.artifact_cids);
+                drop(tracer);
+                match v {
+                    Ok(v) => {
+                        let result = v.to_json().unwrap_or(J::Null);
+                        let output = nb_render_value(&v);
+                        let mut entry = Map::new();
+                        entry.insert("digest".to_string(), J::Str(cell.digest.clone()));
+                        entry.insert("result".to_string(), result.clone());
+                        entry.insert("output".to_string(), J::Str(output.clone()));
+                        entry.insert("bindings".to_string(), nb_bindings(cell, &env));
+                        entry.insert("trace".to_string(), J::Str(fs::read_to_string(&trace_path)?));
+                        let path = nb_cache_path(&cache_dir, &cell.digest);
+                        let tmp = path.with_extension("json.tmp");
+                        fs::write(&tmp, json_to_string(&J::Object(entry)))?;
+                        fs::rename(&tmp, &path)?;
+                        (status, Ok((result, output)))
+                    }
+                    Err(e) => (status, Err(nb_render_error(&e, cell, &file))),
+                }
+            }
+        };
+
+        let mut receipt = Map::new();
+        receipt.insert("kind".to_string(), J::Str(NOTEBOOK_CELL_KIND.to_string()));
+        receipt.insert("index".to_string(), J::Int(idx as i64));
+        receipt.insert("digest".to_string(), J::Str(cell.digest.clone()));
+        receipt.insert(
+            "source_sha256".to_string(),
+            J::Str(format!("sha256:{}", sha256_bytes_hex(cell.src.as_bytes()))),
+        );
+        receipt.insert(
+            "upstream".to_string(),
+            J::Array(cell.upstream.iter().map(|&u| J::Str(cells[u].digest.clone())).collect()),
+        );
+        let trace_bytes = fs::read(&trace_path).unwrap_or_default();
+        receipt.insert("trace".to_string(), J::Str(format!("cells/cell_{}.ndjson", idx)));
+        receipt.insert("trace_sha256".to_string(), J::Str(format!("sha256:{}", sha256_bytes_hex(&trace_bytes))));
+        let ok = outcome.is_ok();
+        receipt.insert("ok".to_string(), J::Bool(ok));
+        let output = match outcome {
+            Ok((result, output)) => {
+                receipt.insert("result".to_string(), result);
+                receipt.insert("error".to_string(), J::Null);
+                eprintln!("[cell {}] ok ({})", idx, status);
+                output
+            }
+            Err((error, text)) => {
+                failed += 1;
+                receipt.insert("result".to_string(), J::Null);
+                receipt.insert("error".to_string(), error);
+                eprintln!("[cell {}] error ({})", idx, status);
+                text
+            }
+        };
+        receipt.insert("output".to_string(), J::Str(output.clone()));
+        let bytes = json_to_string(&J::Object(receipt)).into_bytes();
+        fs::write(cells_dir.join(format!("cell_{}.json", idx)), &bytes)?;
+        let leaf: [u8; 32] = sha256_raw(&bytes).as_slice().try_into().unwrap_or([0u8; 32]);
+        leaves.push(leaf);
+
+        let mut entry = Map::new();
+        entry.insert("index".to_string(), J::Int(idx as i64));
+        entry.insert("digest".to_string(), J::Str(cell.digest.clone()));
+        entry.insert("receipt_sha256".to_string(), J::Str(format!("sha256:{}", hex_lower(&leaf))));
+        entry.insert("ok".to_string(), J::Bool(ok));
+        entry.insert("status".to_string(), J::Str(status.to_string()));
+        entry.insert("duration_ms".to_string(), J::Int(t0.elapsed().as_millis() as i64));
+        entries.push(J::Object(entry));
+        outputs.push(output);
+    }
+
+    let merkle_root = format!("sha256:{}", hex_lower(&merkle_root_bytes(&leaves)));
+    let mut nr = Map::new();
+    nr.insert("kind".to_string(), J::Str(NOTEBOOK_RECEIPT_KIND.to_string()));
+    nr.insert("notebook".to_string(), J::Str(file.clone()));
+    nr.insert("runtime_version".to_string(), J::Str(env!("CARGO_PKG_VERSION").to_string()));
+    nr.insert("ok".to_string(), J::Bool(failed == 0));
+    nr.insert("merkle_root".to_string(), J::Str(merkle_root.clone()));
+    nr.insert("cell_count".to_string(), J::Int(cells.len() as i64));
+    nr.insert("ran".to_string(), J::Int(ran as i64));
+    nr.insert("cached".to_string(), J::Int(hits as i64));
+    nr.insert("replayed".to_string(), J::Int(replayed as i64));
+    nr.insert("failed".to_string(), J::Int(failed as i64));
+    nr.insert("cells".to_string(), J::Array(entries));
+    fs::write(out_dir.join("notebook.receipt.json"), json_to_string(&J::Object(nr)))?;
+
+    let mut output_md = String::new();
+    for seg in &segments {
+        match seg {
+            NbSegment::Text(t) => output_md.push_str(t),
+            NbSegment::Cell(idx) => {
+                output_md.push_str("```fard\n");
+                output_md.push_str(&cells[*idx].src);
+                output_md.push_str("```\n\n```output\n");
+                output_md.push_str(&outputs[*idx]);
+                output_md.push_str("\n```\n");
+            }
+        }
+    }
+    let out_path = nb.output.as_ref().unwrap_or(&nb.input);
+    fs::write(out_path, &output_md)?;
+    eprintln!(
+        "notebook: {} cell(s): {} ran, {} cached, {} replayed, {} failed → {}",
+        cells.len(), ran, hits, replayed, failed, out_path.display()
+    );
+    eprintln!("notebook: merkle_root={}", merkle_root);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+
+fn main() -> Result<()> {
+    let (run, want_version, want_repl, test_args, publish_args, install_args, new_args) = fard_v0_5_language_gate::cli::fardrun_cli::Cli::parse_compat();
+
+    // Handle search subcommand
+    if std::env::var("FARD_SEARCH_MODE").is_ok() {
+        let query = std::env::var("FARD_SEARCH_QUERY").unwrap_or_default();
+        match search_packages(&query) {
+            Ok(results) => {
+                if results.is_empty() {
+                    println!("No packages found{}", if query.is_empty() { String::new() } else { format!(" matching {:?}", query) });
+                } else {
+                    println!("{} package(s) found:\n", results.len());
+                    for (name, ver, desc) in &results {
+                        if desc.is_empty() {
+                            println!("  {}@{}", name, ver);
+                        } else {
+                            println!("  {}@{}  —  {}", name, ver, desc);
+                        }
+                    }
+                }
+            }
+            Err(e) => eprintln!("search error: {}", e),
+        }
+        return Ok(());
+    }
+    if want_version {
+        println!("fard_runtime_version={}", env!("CARGO_PKG_VERSION"));
+        println!("trace_format_version=0.1.0");
+        println!("stdlib_root_cid={}", env!("FARD_STDLIB_ROOT_DIGEST"));
+        return Ok(());
+    }
+    // fardrun new <name> [--template minimal|server|ci]
+    if let Some(new_args) = new_args {
+        use fard_v0_5_language_gate::cli::fardrun_cli::NewArgs;
+        return cmd_new(new_args);
+    }
+
     if want_repl {
         use rustyline::error::ReadlineError;
         use rustyline::DefaultEditor;
@@ -826,6 +1325,11 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
         let mut parser = Parser::from_src(&src, &file)?;
         let items = parser.parse_module()?;
         let mut loader = ModuleLoader::new(program.parent().unwrap_or(Path::new(".")));
+        set_receipt_store(ReceiptStore::configure(&[], None, Some(&program.parent().unwrap_or(Path::new(".")).join("fard.toml")))?);
+        if let Some(dir) = &targs.http_fixtures {
+            let fx = HttpFixtures::load(dir, false)?;
+            HTTP_FIXTURES.with(|f| *f.borrow_mut() = Some(Arc::new(fx)));
+        }
         let t = std::env::temp_dir();
         let tp = t.join("fard_test_trace.ndjson");
         let mut tracer = Tracer::new(&t, &tp).expect("tracer");
@@ -840,8 +1344,6 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
         let non_test: Vec<Item> = items.iter().filter(|i| !matches!(i, Item::Test(..)))
             .cloned().collect();
         loader.eval_items(non_test, &mut env, &mut tracer, program.parent().unwrap_or(Path::new(".")))?;
-        // Set VM_FNS_PTR after all fns compiled — avoids per-call clone
-        ModuleLoader::set_vm_fns_ptr();
         // Second pass: run tests
         let tests: Vec<(String, Expr, ErrorSpan)> = items.into_iter().filter_map(|i| {
             if let Item::Test(label, body, span) = i { Some((label, body, span)) } else { None }
@@ -1127,94 +1629,35 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
 
 
 
+    // ── Check subcommand (parse only, for editors) ────────────────────────────
+    if let Some(check) = fard_v0_5_language_gate::cli::fardrun_cli::Cli::parse_compat_check() {
+        let src = fs::read_to_string(&check.program)
+            .with_context(|| format!("cannot read {}", check.program.display()))?;
+        if check.ast {
+            let items = Parser::from_src(&src, &check.program.to_string_lossy()).and_then(|mut p| p.parse_module());
+            match items {
+                Ok(items) => println!("{}", ast_without_spans(&items)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            std::process::exit(0);
+        }
+        let diags = check_diagnostics(&src, &check.program.to_string_lossy());
+        for d in &diags {
+            println!("{}", json_to_string(d));
+        }
+        std::process::exit(if diags.is_empty() { 0 } else { 1 });
+    }
+
     // ── Notebook subcommand ───────────────────────────────────────────────────
     if let Some(fard_v0_5_language_gate::cli::fardrun_cli::Command::Notebook(nb)) = {
         use fard_v0_5_language_gate::cli::fardrun_cli::Cli;
         Cli::parse_compat_notebook()
     }
     {
-        use std::io::Write;
-        let input = std::fs::read_to_string(&nb.input)
-            .with_context(|| format!("cannot read notebook: {}", nb.input.display()))?;
-        let out_dir = std::path::PathBuf::from(&nb.out_dir);
-        std::fs::create_dir_all(&out_dir)?;
-
-        // Parse cells: extract ```fard ... ``` blocks
-        let mut output_md = String::new();
-        let mut cell_idx = 0usize;
-        let mut i = 0;
-        let lines: Vec<&str> = input.lines().collect();
-
-        while i < lines.len() {
-            let line = lines[i];
-            if line.trim_start() == "```fard" {
-                // Collect cell source
-                let mut cell_src = String::new();
-                i += 1;
-                while i < lines.len() && lines[i].trim() != "```" {
-                    cell_src.push_str(lines[i]);
-                    cell_src.push('\n');
-                    i += 1;
-                }
-                i += 1; // skip closing ```
-
-                // Write cell to temp file
-                let cell_path = out_dir.join(format!("cell_{}.fard", cell_idx));
-                let cell_out = out_dir.join(format!("cell_{}_out", cell_idx));
-                std::fs::write(&cell_path, &cell_src)?;
-                std::fs::create_dir_all(&cell_out)?;
-
-                // Run cell
-                let exe = std::env::current_exe()?;
-                let status = std::process::Command::new(&exe)
-                    .args(["run", "--program",
-                        cell_path.to_str().unwrap(),
-                        "--out", cell_out.to_str().unwrap(),
-                        "--no-trace"])
-                    .output()?;
-
-                // Read result
-                let result = if let Ok(r) = std::fs::read_to_string(cell_out.join("result.json")) {
-                    // Pretty print the result
-                    if let Ok(v) = serde_json::from_str::<serde_json::Value>(&r) {
-                        let inner = v.get("result").unwrap_or(&v);
-                        serde_json::to_string_pretty(inner).unwrap_or(r)
-                    } else { r }
-                } else if !status.stderr.is_empty() {
-                    String::from_utf8_lossy(&status.stderr).trim().to_string()
-                } else {
-                    "null".to_string()
-                };
-
-                // Append to output
-                output_md.push_str("```fard\n");
-                output_md.push_str(&cell_src);
-                output_md.push_str("```\n\n");
-                output_md.push_str("```output\n");
-                output_md.push_str(&result);
-                output_md.push('\n');
-                output_md.push_str("```\n");
-
-                eprintln!("[cell {}] ok", cell_idx);
-                cell_idx += 1;
-            } else {
-                // Skip existing output blocks
-                if line.trim_start() == "```output" {
-                    while i < lines.len() && lines[i].trim() != "```" { i += 1; }
-                    i += 1;
-                } else {
-                    output_md.push_str(line);
-                    output_md.push('\n');
-                    i += 1;
-                }
-            }
-        }
-
-        // Write output
-        let out_path = nb.output.as_ref().unwrap_or(&nb.input);
-        std::fs::write(out_path, &output_md)?;
-        eprintln!("notebook: {} cell(s) executed → {}", cell_idx, out_path.display());
-        return Ok(());
+        return run_notebook(nb);
     }
 
     let program = run.program;
@@ -1222,19 +1665,34 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
 
     // ── Strict type checking ──────────────────────────────────────────────────
     if run.strict_types {
-        let src_text = match fs::read_to_string(&program) {
-            Ok(s) => s,
-            Err(e) => bail!("cannot read {}: {}", program.display(), e),
+        if !program.is_file() {
+            bail!("cannot read {}", program.display());
+        }
+        let errors = match fard_v0_5_language_gate::type_check_file(&program, run.registry.as_deref()) {
+            Ok(errors) => errors,
+            Err(e) => {
+                fs::create_dir_all(&out_dir).ok();
+                let mut em = Map::new();
+                em.insert("code".to_string(), J::Str("ERROR_TYPE".to_string()));
+                em.insert("message".to_string(), J::Str(format!("cannot type-check: {:#}", e)));
+                em.insert("strict_types".to_string(), J::Bool(true));
+                fs::write(
+                    out_dir.join("error.json"),
+                    json_to_string(&J::Object(em)).into_bytes(),
+                )?;
+                eprintln!("cannot type-check: {:#} — run aborted (--strict-types)", e);
+                std::process::exit(2);
+            }
         };
-        let errors = fard_v0_5_language_gate::type_check_strict(&src_text, &program.to_string_lossy());
         if !errors.is_empty() {
             fs::create_dir_all(&out_dir).ok();
             // Write error.json with type errors
-            let type_errors: Vec<_> = errors.iter().map(|(line, col, msg)| {
+            let type_errors: Vec<_> = errors.iter().map(|e| {
                 let mut m = Map::new();
-                m.insert("line".to_string(), J::Int(*line as i64));
-                m.insert("col".to_string(), J::Int(*col as i64));
-                m.insert("message".to_string(), J::Str(msg.clone()));
+                m.insert("file".to_string(), J::Str(e.file.clone()));
+                m.insert("line".to_string(), J::Int(e.line as i64));
+                m.insert("col".to_string(), J::Int(e.col as i64));
+                m.insert("message".to_string(), J::Str(e.message.clone()));
                 J::Object(m)
             }).collect();
             let mut em = Map::new();
@@ -1246,8 +1704,8 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
                 out_dir.join("error.json"),
                 json_to_string(&J::Object(em)).into_bytes(),
             )?;
-            for (line, _col, msg) in &errors {
-                eprintln!("TYPE ERROR line {}: {}", line, msg);
+            for e in &errors {
+                eprintln!("TYPE ERROR {}:{}:{}: {}", e.file, e.line, e.col, e.message);
             }
             eprintln!("{} type error(s) — run aborted (--strict-types)", errors.len());
             std::process::exit(2);
@@ -1268,7 +1726,23 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
     let mut loader = ModuleLoader::new(program.parent().unwrap_or(Path::new(".")));
     // Load fard.toml from program directory for pkg dep resolution
     let fard_toml_path = program.parent().unwrap_or(Path::new(".")).join("fard.toml");
-    loader.load_fard_toml(&fard_toml_path);
+    let package = loader.load_fard_toml(&fard_toml_path);
+    let policy = match &run.policy {
+        Some(pp) => {
+            let src = fs::read_to_string(pp)
+                .with_context(|| format!("ERROR_POLICY cannot read {}", pp.display()))?;
+            CapPolicy::parse(&src, true, pp.parent().unwrap_or(Path::new("")))?
+        }
+        None => match fs::read_to_string(&fard_toml_path) {
+            Ok(src) => CapPolicy::parse(&src, false, fard_toml_path.parent().unwrap_or(Path::new("")))?,
+            Err(_) => None,
+        },
+    };
+    CAP_POLICY.with(|p| *p.borrow_mut() = policy);
+    set_receipt_store(ReceiptStore::configure(&run.receipts, run.receipt_cache.as_deref(), Some(&fard_toml_path))?);
+    set_extra_stdlib_roots(run.stdlib_roots.as_deref())?;
+    let sign_key = run.sign_key.as_deref().map(load_signing_key).transpose()?;
+    RUN_PACKAGE.with(|p| *p.borrow_mut() = package.clone());
     let runtime_version = env!("CARGO_PKG_VERSION");
     let trace_format_version = "0.1.0";
     if let Some(rp) = registry_dir.clone() {
@@ -1280,6 +1754,21 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
         bail!("ERROR_LOCK --enforce-lockfile requires --lockfile <path>");
     }
     loader.enforce_lockfile = run.enforce_lockfile;
+    if run.record && run.replay.is_some() {
+        bail!("ERROR_REPLAY --record and --replay are mutually exclusive");
+    }
+    let replay = match &run.replay {
+        Some(dir) => Some(oracle_load_replay(dir)?),
+        None => None,
+    };
+    if run.http_capture && run.http_fixtures.is_none() {
+        bail!("ERROR_HTTP_FIXTURE --http-capture requires --http-fixtures <dir>");
+    }
+    let http_fixtures = match &run.http_fixtures {
+        Some(dir) => Some(Arc::new(HttpFixtures::load(dir, run.http_capture)?)),
+        None => None,
+    };
+    HTTP_FIXTURES.with(|f| *f.borrow_mut() = http_fixtures);
     let mut _fp_prev_digest: Option<String> = None;
     let mut _fp_attempt = 0u32;
     SELF_DIGEST_ACCESSED.with(|a| *a.borrow_mut() = false);
@@ -1299,7 +1788,22 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
         SELF_DIGEST_ACCESSED.with(|a| *a.borrow_mut() = false);
         WITNESS_DEPS.with(|d| d.borrow_mut().clear());
     }
-    let v = match loader.eval_main(&program, &mut tracer) {
+    ORACLE_MODE.with(|m| *m.borrow_mut() = match &replay {
+        Some((evs, _)) => OracleMode::Replay(evs.iter().cloned().collect()),
+        None if run.record => OracleMode::Record,
+        None => OracleMode::Live,
+    });
+    ORACLE_CHILD_ANSWERS.with(|c| *c.borrow_mut() = Arc::new(
+        replay.as_ref().map(|(_, children)| children.clone()).unwrap_or_default()
+    ));
+    vm_stats_reset(run.vm_stats);
+    let v = loader.eval_main(&program, &mut tracer).and_then(|v| oracle_replay_finish().map(|_| v));
+    let ended = tracer.end_span();
+    let v = v.and_then(|v| ended.map(|_| v));
+    if run.vm_stats {
+        vm_stats_report(&out_dir)?;
+    }
+    let v = match v {
         Ok(v) => v,
         Err(e) if e.downcast_ref::<QMarkUnwind>().is_some() => {
             // Top-level QMarkUnwind — the program's final expression used ?
@@ -1618,9 +2122,6 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
                             bail!("ERROR_SELF_DIGEST_DIVERGE w.self_digest() did not converge after 3 iterations");
                         }
                         SELF_DIGEST.with(|d| *d.borrow_mut() = run_id.clone());
-                        let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-                        fs::create_dir_all("receipts").ok();
-                        let receipt_path = format!("receipts/sha256_{}.json", hex);
                         let output: J = fs::read(out_dir.join("result.json"))
                             .ok()
                             .and_then(|b| json_from_slice(&b).ok())
@@ -1631,8 +2132,28 @@ fn pretty_print_val(v: &Val, indent: usize) -> String {
                         receipt.insert("derived_from".to_string(),
                             J::Array(deps.into_iter().map(J::Str).collect()));
                         receipt.insert("output".to_string(), output);
-                        receipt.insert("run_id".to_string(), J::Str(run_id));
-                        let _ = fs::write(&receipt_path, canonical_json_bytes(&J::Object(receipt)));
+                        let mut preimage = dig_json.as_object().cloned().unwrap_or_default();
+                        preimage.remove("preimage_sha256");
+                        receipt.insert("preimage".to_string(), J::Object(preimage));
+                        receipt.insert("run_id".to_string(), J::Str(run_id.clone()));
+                        if let Some(pkg) = &package {
+                            receipt.insert("package".to_string(), J::Str(pkg.clone()));
+                        }
+                        if let Some(key) = &sign_key {
+                            let sig = RunSignature::sign(key, &run_id, package.as_deref().unwrap_or(""));
+                            let mut sm = BTreeMap::new();
+                            sm.insert("alg".to_string(), J::Str("ed25519".to_string()));
+                            sm.insert("key".to_string(), J::Str(sig.key));
+                            sm.insert("sig".to_string(), J::Str(sig.sig));
+                            let mut detached = sm.clone();
+                            detached.insert("run_id".to_string(), J::Str(run_id.clone()));
+                            if let Some(pkg) = &package {
+                                detached.insert("package".to_string(), J::Str(pkg.clone()));
+                            }
+                            fs::write(out_dir.join("signature.json"), canonical_json_bytes(&J::Object(detached)))?;
+                            receipt.insert("signature".to_string(), J::Object(sm));
+                        }
+                        let _ = receipt_store().put(&run_id, &canonical_json_bytes(&J::Object(receipt)));
                     }
                 }
             }
@@ -1647,6 +2168,12 @@ struct Tracer {
     artifact_cids: std::collections::BTreeMap<String, String>,
     w: fs::File,
     out_dir: PathBuf,
+    /// Spawn id of the task writing this trace ("" for the main task)
+    spawn_prefix: String,
+    /// Children spawned so far; numbers the next spawn id
+    spawn_seq: usize,
+    /// Receipts of spawned children in spawn order, `None` until the child is joined
+    pending_receipts: std::collections::VecDeque<(String, Option<J>)>,
 }
 impl Tracer {
     fn emit_raw(&mut self, line: &str) -> Result<()> {
@@ -1684,6 +2211,9 @@ impl Tracer {
             w,
             out_dir: out_dir.to_path_buf(),
             artifact_cids: std::collections::BTreeMap::new(),
+            spawn_prefix: String::new(),
+            spawn_seq: 0,
+            pending_receipts: Default::default(),
         })
     }
     fn emit(&mut self, v: &J) -> Result<()> {
@@ -1710,12 +2240,10 @@ impl Tracer {
         Ok(())
     }
     fn note_artifact_dep(&mut self, run_id: &str) -> Result<()> {
-        let mut m = std::collections::BTreeMap::new();
+        let mut m = Map::new();
         m.insert("run_id".to_string(), J::Str(run_id.to_string()));
         m.insert("t".to_string(), J::Str("artifact_dep".to_string()));
-        let line = json_to_string(&J::Object(m)) + "
-";
-        std::io::Write::write_all(&mut self.w, line.as_bytes())?;
+        self.emit_event(J::Object(m))?;
         WITNESS_DEPS.with(|d| d.borrow_mut().push(run_id.to_string()));
         Ok(())
     }
@@ -1727,13 +2255,66 @@ impl Tracer {
         self.emit_event(J::Object(m))
     }
 
-    fn child_receipt(&mut self, spawn_id: &str, run_digest: &str, result_digest: &str) -> Result<()> {
+    /// Spawn the next child task: allocate its deterministic spawn id
+    /// (`spawn_0`, `spawn_0.1`, ...) and open its sub-trace under `<out>/children/`.
+    fn child_tracer(&mut self) -> Result<(String, PathBuf, Tracer)> {
+        let spawn_id = if self.spawn_prefix.is_empty() {
+            format!("spawn_{}", self.spawn_seq)
+        } else {
+            format!("{}.{}", self.spawn_prefix, self.spawn_seq)
+        };
+        self.spawn_seq += 1;
+        let dir = self.out_dir.join("children");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.ndjson", spawn_id));
+        let child = Tracer {
+            first_event: true,
+            artifact_cids: self.artifact_cids.clone(),
+            w: fs::File::create(&path)?,
+            out_dir: self.out_dir.clone(),
+            spawn_prefix: spawn_id.clone(),
+            spawn_seq: 0,
+            pending_receipts: Default::default(),
+        };
+        self.child_spawn(&spawn_id)?;
+        self.pending_receipts.push_back((spawn_id.clone(), None));
+        Ok((spawn_id, path, child))
+    }
+
+    /// Close a child's span: the receipt names its sub-trace under `children/`,
+    /// `run_digest` is the sha256 of that file and `result_digest` that of its value.
+    /// Receipts merge in spawn order, so one waits until every earlier child is joined.
+    fn child_receipt(&mut self, spawn_id: &str, trace_path: &Path, result: &Val) -> Result<()> {
+        let trace_bytes = fs::read(trace_path).unwrap_or_default();
+        let result_digest = match result.to_json() {
+            Some(j) => format!("sha256:{}", sha256_bytes_hex(json_to_string(&j).as_bytes())),
+            None => "sha256:no-result".to_string(),
+        };
         let mut m = Map::new();
         m.insert("t".to_string(), J::Str("child_receipt".to_string()));
         m.insert("spawn_id".to_string(), J::Str(spawn_id.to_string()));
-        m.insert("run_digest".to_string(), J::Str(run_digest.to_string()));
-        m.insert("result_digest".to_string(), J::Str(result_digest.to_string()));
-        self.emit_event(J::Object(m))
+        m.insert("trace".to_string(), J::Str(format!("children/{}.ndjson", spawn_id)));
+        m.insert("run_digest".to_string(), J::Str(format!("sha256:{}", sha256_bytes_hex(&trace_bytes))));
+        m.insert("result_digest".to_string(), J::Str(result_digest));
+        if let Some(slot) = self.pending_receipts.iter_mut().find(|(id, _)| id == spawn_id) {
+            slot.1 = Some(J::Object(m));
+        }
+        while let Some((_, Some(_))) = self.pending_receipts.front() {
+            if let Some((_, Some(receipt))) = self.pending_receipts.pop_front() {
+                self.emit_event(receipt)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// End this trace's span: emit the receipts still queued behind children that were never joined.
+    fn end_span(&mut self) -> Result<()> {
+        while let Some((_, receipt)) = self.pending_receipts.pop_front() {
+            if let Some(receipt) = receipt {
+                self.emit_event(receipt)?;
+            }
+        }
+        Ok(())
     }
 
     fn artifact_in(&mut self, path: &str, cid: &str) -> Result<()> {
@@ -1938,8 +2519,90 @@ fn line_col_at(src: &str, byte_pos: usize) -> (usize, usize) {
     }
     (line, col)
 }
-struct Lex {
-    s: Vec<char>,
+/// Parse-only diagnostics for `fardrun check`. Each is
+/// `{code, message, span: {line, col, end_line, end_col}}`, 1-based, columns in chars,
+/// covering the token the lexer or parser stopped at.
+/// `Debug` of a parsed module with every `ErrorSpan` cut down to its name, so sources
+/// that differ only in layout and comments print the same (`fardrun check --ast`).
+fn ast_without_spans(items: &[Item]) -> String {
+    let full = format!("{:#?}", items);
+    let mut out = String::with_capacity(full.len());
+    let mut rest = full.as_str();
+    while let Some(at) = rest.find("ErrorSpan {") {
+        out.push_str(&rest[..at + "ErrorSpan".len()]);
+        // Skip to the matching brace; `file` is the only text field and may hold braces.
+        let (mut depth, mut in_str, mut escaped, mut end) = (0usize, false, false, rest.len());
+        for (i, c) in rest[at..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_str => escaped = true,
+                '"' => in_str = !in_str,
+                '{' if !in_str => depth += 1,
+                '}' if !in_str => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = at + i + 1;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn check_diagnostics(src: &str, file: &str) -> Vec<J> {
+    let (e, start, end) = match Parser::from_src(src, file) {
+        Err(e) => {
+            // Re-lex to find the offending token; from_src does not keep its position
+            let mut lx = Lex::new(src);
+            let mut at = (0, 0);
+            loop {
+                lx.skip_ws();
+                let start = lx.i;
+                match lx.next() {
+                    Ok(Tok::Eof) => break,
+                    Ok(_) => {}
+                    Err(_) => { at = (start, lx.i); break; }
+                }
+            }
+            (e, at.0, at.1)
+        }
+        Ok(mut p) => match p.parse_module() {
+            Ok(_) => return Vec::new(),
+            Err(e) => {
+                let span = match e.downcast_ref::<ParseError>() {
+                    Some(pe) => pe.span.clone(),
+                    None => p.tok_span(p.i.min(p.toks.len().saturating_sub(1))),
+                };
+                (e, span.byte_start, span.byte_end)
+            }
+        },
+    };
+    let message = e.to_string();
+    let code = message
+        .split_whitespace()
+        .find(|w| w.starts_with("ERROR_"))
+        .unwrap_or("ERROR_PARSE")
+        .to_string();
+    let (line, col) = line_col_at(src, start);
+    let (end_line, end_col) = line_col_at(src, end.max(start + 1));
+    let mut span = Map::new();
+    span.insert("line".to_string(), J::Int(line as i64));
+    span.insert("col".to_string(), J::Int(col as i64));
+    span.insert("end_line".to_string(), J::Int(end_line as i64));
+    span.insert("end_col".to_string(), J::Int(end_col as i64));
+    let mut m = Map::new();
+    m.insert("code".to_string(), J::Str(code));
+    m.insert("message".to_string(), J::Str(message));
+    m.insert("span".to_string(), J::Object(span));
+    vec![J::Object(m)]
+}
+struct Lex {
+    s: Vec<char>,
     i: usize,
 }
 impl Lex {
@@ -2113,6 +2776,7 @@ impl Lex {
                     let e = self.bump().ok_or_else(|| anyhow!("bad escape"))?;
                     match e {
                         'n' => t.push('\n'),
+                        'r' => t.push('\r'),
                         't' => t.push('\t'),
                         '"' => t.push('"'),
                         '\\' => t.push('\\'),
@@ -3371,9 +4035,9 @@ enum Val {
     Chan(Arc<Mutex<std::collections::VecDeque<Val>>>, Arc<Mutex<bool>>),
     Mtx(Arc<Mutex<Val>>),
     Big(Box<BigInt>),
-    Promise(Arc<Mutex<Option<Result<Val, String>>>>, String, PathBuf),  // slot, spawn_id, trace_path
+    Promise(ChildSlot, String, PathBuf),  // slot, spawn_id, trace_path
     /// VM-compiled function — executed by the bytecode VM, not the tree-walker
-    VmFunc(usize),  // index into a Vec<CompiledFn> held by the VM
+    VmFunc(Arc<VmClosure>),
 }
 
 impl Val {
@@ -3395,7 +4059,7 @@ impl Val {
             Val::Mtx(..) => "mutex",
             Val::Big(..) => "bigint",
             Val::Promise(..) => "promise",
-            Val::VmFunc(_) => "vm-func",
+            Val::VmFunc(_) => "func",
         }
     }
 }
@@ -3422,263 +4086,14 @@ struct Func {
     body: Expr,
     env: Env,
 }
-#[derive(Clone, Debug)]
-enum Builtin {
-    PngRed1x1,
-    Unimplemented(&'static str),
-    // Type checking constructors
-    TypeCheck(String, Vec<String>),   // type_name, required_fields
-    // std/math
-    MathAbs, MathMin, MathMax, MathPow, MathSqrt,
-    MathFloor, MathCeil, MathRound, MathLog, MathLog2, MathExp,
-    // std/bits
-    BitAnd, BitOr, BitXor, BitNot, BitShl, BitShr, BitPopcount,
-    // std/bytes
-    BytesConcat, BytesLen, BytesGet, BytesOfList, BytesMerkleRoot, BytesOfStr, BytesToList, BytesToStr,
-    // std/io
-    IoReadFile, IoWriteFile, IoAppendFile, IoReadLines, IoFileExists, IoDeleteFile,
-    IoReadStdin, IoListDir, IoMakeDir, IoReadStdinLines,
-    ChanNew, ChanSend, ChanRecv, ChanTryRecv, ChanClose,
-    MutexNew, MutexLock, MutexUnlock, MutexWithLock,
-    // std/cli
-    CliArgs, CliGet, CliGetInt, CliGetFloat, CliGetBool, CliHas,
-    // std/null
-    NullIsNull, NullCoalesce, NullGuard,
-    // std/path
-    PathBase, PathDir, PathExt, PathIsAbs, PathJoin, PathJoinAll, PathNormalize,
-    ListMap,
-    ListFilter,
-    ListRange,
-    ListRepeat,
-    ListConcat,
-    ListFold,
-    StrTrim,
-    StrToLower,
-    StrSplitLines,
-    ResultOk,
-    ResultAndThen,
-    ResultUnwrapOk,
-    ResultUnwrapErr,
-    ResultUnwrapOr,
-    ResultIsOk,
-    ResultIsErr,
-    ResultMap,
-    ResultMapErr,
-    ResultOrElse,
-    TraceInfo,
-    TraceWarn,
-    TraceError,
-    TraceSpan,
-    ListGroupBy,
-    SembitPartition,
-    HttpGet,
-    HttpPost,
-    HttpRequest,
-    TimeNow,
-    TimeParse,
-    TimeFormat,
-    TimeAdd,
-    TimeSub,
-    TimeDurationMs,
-    TimeDurationSec,
-    TimeDurationMin,
-    OptionNone,
-    OptionSome,
-    OptionIsNone,
-    OptionIsSome,
-    OptionFromNullable,
-    OptionToNullable,
-    OptionMap,
-    OptionAndThen,
-    OptionUnwrapOr,
-    OptionUnwrapOrElse,
-    OptionToResult,
-    RecEmpty,
-    RecKeys,
-    RecValues,
-    RecHas,
-    RecGet,
-    RecGetOr,
-    RecGetOrErr,
-    RecSet,
-    RecRemove,
-    RecMerge,
-    RecSelect,
-    RecRename,
-    RecUpdate,
-    ResultErr,
-    ListGet,
-    ListLen,
-    ListHead,
-    ListTail,
-    ListAppend,
-    ListZip,
-    ListReverse,
-    ListFlatten,
-    ListSortByIntKey,
-    GrowUnfoldTree,
-    GrowAppend,
-    ImportArtifact,
-    ImportArtifactNamed,
-    EmitArtifact,
-    EmitArtifactDerived,
-    Emit,
-    Len,
-    IntParse,
-    IntPow,
-    IntAdd,
-    IntEq,
-    SortInt,
-    DedupeSortedInt,
-    HistInt,
-    Unfold,
-    FlowPipe,
-    FlowId,
-    FlowTap,
-    StrLen,
-    StrConcat,
-    MapGet,
-    MapSet,
-    JsonEncode,
-    JsonDecode,
-    JsonCanonicalize,
-    CryptoEd25519Verify,
-    CryptoHmacSha256,
-    CodecBase64UrlEncode,
-    CodecBase64UrlDecode,
-    CodecBase64UrlEncodeHex,
-    RandUuidV4,
-    StrSplit,
-    StrUpper,
-    StrContains,
-    StrStartsWith,
-    StrEndsWith,
-    StrReplace,
-    StrSlice,
-    StrFormat,
-    StrFromInt,
-    StrFromFloat,
-    StrPadLeft,
-    StrPadRight,
-    StrRepeat,
-    StrIndexOf,
-    StrChars,
-    FsReadText,
-    FsWriteText,
-    FsExists,
-    FsReadDir,
-    FsStat,
-    FsDelete,
-    FsMakeDir,
-    CodecHexEncode,
-    CodecHexDecode,
-    HashSha256Text,
-    WitnessSelfDigest,   // witness.self_digest() -> Text
-    WitnessDeps,         // witness.deps() -> List of run_id Text
-    WitnessVerify,       // witness.verify(run_id) -> {ok: record} | {err: text}
-    WitnessVerifyChain,  // witness.verify_chain(run_id) -> {ok: depth} | {err: {run_id, reason}}
-    FfiOpen,   // ffi.open(path) -> {ok: handle_id} | {err: text}
-    FfiCall,   // ffi.call(handle_id, symbol, args) -> {ok: val} | {err: text}
-    FfiClose,  // ffi.close(handle_id) -> null
-    FfiCallPure, // ffi.call_pure(handle_id, symbol, args) -> same as call but hashed into witness
-    FfiCallStr,
-    FfiCallChecked,  // ffi.call_str(handle_id, symbol, args) -> {ok: text} | {err: text}
-    NetServe,   // net.serve(port, handler) -> never (blocking)
-    NetRespond, // net.respond(req, status, headers, body) -> null (internal)
-    CryptoSha512,         // crypto.sha512(bytes) -> text
-    CryptoAesEncrypt,     // crypto.aes_encrypt(key_hex, nonce_hex, plaintext) -> {ok: hex} | {err: text}
-    CryptoAesDecrypt,     // crypto.aes_decrypt(key_hex, nonce_hex, ciphertext_hex) -> {ok: text} | {err: text}
-    CryptoMerkleRoot,     // crypto.merkle_root(list_of_hex) -> hex
-    CompressGzip,         // compress.gzip(text) -> bytes_hex
-    CompressGunzip,       // compress.gunzip(bytes_hex) -> {ok: text} | {err: text}
-    GraphOf,       // graph.of(run_id) -> {nodes, edges} | {err: text}
-    GraphAncestors, // graph.ancestors(run_id) -> list of run_ids
-    GraphLeaves,    // graph.leaves(run_id) -> list of root run_ids
-    GraphToDot,     // graph.to_dot(graph) -> dot string
-    HashSha256Bytes,
-    IntMul,
-    IntDiv,
-    IntSub,
-    IntAbs,
-    IntMin,
-    IntMax,
-    IntToText,
-    IntFromText,
-    IntNeg,
-    IntClamp,
-    IntMod,
-    IntLt,
-    IntGt,
-    IntLe,
-    IntGe,
-    // float builtins
-    FloatFromInt,
-    FloatToInt,
-    FloatFromText,
-    FloatToText,
-    FloatAdd,
-    FloatSub,
-    FloatMul,
-    FloatDiv,
-    FloatExp,
-    FloatLn,
-    FloatSqrt,
-    FloatPow,
-    FloatAbs,
-    FloatNeg,
-    FloatFloor,
-    FloatCeil,
-    FloatRound,
-    FloatLt,
-    FloatGt,
-    FloatLe,
-    FloatGe,
-    FloatEq,
-    FloatNan,
-    FloatInf,
-    FloatIsNan,
-    FloatIsFinite,
-    FloatMin,
-    FloatMax,
-    // linalg builtins
-    LinalgDot,
-    LinalgNorm,
-    LinalgZeros,
-    LinalgEye,
-    LinalgMatvec,
-    LinalgMatmul,
-    LinalgRelu,
-    LinalgSoftmax,
-    LinalgArgmax,
-    LinalgVecExp, LinalgVecLog, LinalgVecSum, LinalgVecMax, LinalgVecMul,
-    LinalgVecRelu, LinalgVecReluGrad, LinalgSoftmaxGrad, LinalgCrossEntropy,
-    LinalgOuter, LinalgMatMulVecGrad, LinalgVecScalarAdd, LinalgMatRowSum,
-    ListSet,
-    CastFloat, CastInt, CastText, StrJoin, ListAny, ListAll, ListFind, ListFindIndex, ListTake, ListDrop, ListFlatMap,
-    MathSin, MathCos, MathTan, MathAtan2, IntToHex, IntToBin, FloatIsInf, TypeOf,
-    EnvGet, EnvArgs, ProcessSpawn, ProcessExit,
-    ReMatch, ReFind, ReFindAll, ReSplit, ReReplace, FardEval,
-    Base64Encode, Base64Decode, CsvParse, CsvEncode,
-    MapDelete, MapEntries,
-    SetNew, SetAdd, SetRemove, SetHas, SetUnion, SetIntersect, SetDiff, SetToList, SetFromList, SetSize,
-    ListZipWith, ListChunk, ListSortBy,
-    MathAsin, MathAcos, MathAtan, MathLog10,
-    FloatToStrFixed,
-    UuidV4, UuidValidate,
-    IntToStrPadded,
-    BigFromInt, BigFromStr, BigAdd, BigSub, BigMul, BigDiv, BigPow, BigToStr, BigEq, BigLt, BigGt, BigMod,
-    PromiseSpawn, PromiseAwait, PromiseSpawnOrdered,
-    AstParse,
-    DateTimeNow, DateTimeFormat, DateTimeParse, DateTimeAdd, DateTimeSub, DateTimeField,
-    ListParMap,
-    CellNew, CellGet, CellSet,
-    LinalgTranspose,
-    LinalgEigh,
-    LinalgVecAdd,
-    LinalgVecSub,
-    LinalgVecScale,
-    LinalgMatAdd,
-    LinalgMatScale,
+include!("../builtin_surface.rs");
+
+fn std_export_val(e: &StdExport) -> Val {
+    match e {
+        StdExport::Fn(b) => Val::Builtin(b.clone()),
+        StdExport::Float(x) => Val::Float(*x),
+        StdExport::Record(fs) => Val::Record(fs.iter().map(|(k, b)| (k.to_string(), Val::Builtin(b.clone()))).collect()),
+    }
 }
 
 #[derive(Debug)]
@@ -4012,45 +4427,14 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
                     StrPart::Lit(s) => result.push_str(s),
                     StrPart::Expr(e) => {
                         let v = eval(e, env, tracer, loader)?;
-                        let s = match v {
-                            Val::Text(s) => s,
-                            Val::Int(n) => n.to_string(),
-                            Val::Float(f) => f.to_string(),
-                            Val::Bool(b) => b.to_string(),
-                            other => other.to_json().map(|j| canon_json(&j).unwrap_or_default()).unwrap_or_else(|| "?".to_string()),
-                        };
-                        result.push_str(&s);
+                        result.push_str(&interp_text(v));
                     }
                 }
             }
             Ok(Val::Text(result))
         },
         Expr::Null => Ok(Val::Unit),
-        Expr::Var(n) => env.get(n).ok_or_else(|| {
-            // Suggest stdlib import if name matches a known module
-            let stdlib_modules = [
-                "str","list","math","io","json","hash","http","re","map","set",
-                "result","option","ffi","witness","process","env","net","trace",
-                "float","bigint","bits","path","datetime","uuid","base64","csv",
-                "compress","crypto","graph","linalg","type","promise","chan",
-                "mutex","ast","eval","cell","grow","flow","cast","rec","record",
-            ];
-            if stdlib_modules.contains(&n.as_str()) {
-                anyhow!("unbound var: {n} -- did you forget to import? Try: import(\"std/{n}\") as {n}")
-            } else {
-                // Find similar names in env using edit distance
-                let env_keys: Vec<String> = env.keys();
-                let suggestion = env_keys.iter()
-                    .filter(|k| edit_distance(k, n) <= 2 && !k.is_empty())
-                    .min_by_key(|k| edit_distance(k, n))
-                    .cloned();
-                if let Some(s) = suggestion {
-                    anyhow!("unbound var: {n} -- did you mean '{s}'?")
-                } else {
-                    anyhow!("unbound var: {n}")
-                }
-            }
-        }),
+        Expr::Var(n) => env.get(n).ok_or_else(|| unbound_var(n, env)),
         Expr::List(xs) => {
             let mut out = Vec::new();
             for x in xs {
@@ -4068,75 +4452,11 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
         Expr::Index(obj, idx) => {
             let v = eval(obj, env, tracer, loader)?;
             let i = eval(idx, env, tracer, loader)?;
-            match (v, i) {
-                (Val::List(xs), Val::Int(n)) => {
-                    if n < 0 || n as usize >= xs.len() {
-                        bail!("ERROR_OOB index {} out of bounds (len {})", n, xs.len());
-                    }
-                    Ok(xs[n as usize].clone())
-                }
-                (Val::Record(m), Val::Text(k)) => {
-                    m.get(&k).cloned().ok_or_else(|| anyhow!("ERROR_KEY key {:?} not found", k))
-                }
-                _ => bail!("ERROR_BADARG index operator requires list[int] or rec[str]"),
-            }
+            index_val(v, i)
         }
         Expr::Get(obj, k) => {
             let o = eval(obj, env, tracer, loader)?;
-            match &o {
-                Val::Record(m) => m
-                    .get(k)
-                    .cloned()
-                    .ok_or_else(|| {
-                        // Suggest similar field names
-                        let keys: Vec<&String> = m.keys().collect();
-                        let suggestion = keys.iter()
-                            .filter(|candidate| edit_distance(candidate, k) <= 2)
-                            .min_by_key(|candidate| edit_distance(candidate, k))
-                            .map(|s| s.as_str());
-                        if let Some(s) = suggestion {
-                            anyhow!("no member '{k}' -- did you mean '{s}'?")
-                        } else {
-                            let available: Vec<&str> = keys.iter().map(|s| s.as_str()).take(8).collect();
-                            anyhow!("no member '{k}' -- available: {}", available.join(", "))
-                        }
-                    }),
-                // Method-style dispatch: val.method looks up k in the stdlib
-                // module for that value type and returns a BoundMethod that
-                // prepends the receiver when called. xs.map(f) -> map(xs, f).
-                v => {
-                    let type_mod = match v {
-                        Val::List(_)  => Some("std/list"),
-                        Val::Text(_)  => Some("std/str"),
-                        Val::Int(_)   => Some("std/int"),
-                        Val::Bytes(_) => Some("std/bytes"),
-                        Val::Float(_) => Some("std/float"),
-                        _             => None,
-                    };
-                    if let Some(mod_name) = type_mod {
-                        let here = loader.root_dir.clone();
-                        let m = loader.load_module(mod_name, &here, tracer)
-                            .map_err(|e| anyhow!("method dispatch: {mod_name}: {e}"))?;
-                        if let Some(f) = m.get(k) {
-                            return Ok(Val::BoundMethod(Box::new(o.clone()), Box::new(f.clone())));
-                        }
-                        bail!("method not found: {k} on type {mod_name}");
-                    }
-                    bail!("no methods on type: {}", match v {
-                        Val::Unit => "unit", Val::Bool(_) => "bool",
-                        Val::Int(_) => "int", Val::Float(_) => "float",
-                        Val::Text(_) => "text", Val::Bytes(_) => "bytes",
-                        Val::List(_) => "list", Val::Func(_) | Val::Builtin(_) => "function",
-                        Val::BoundMethod(..) => "bound-method",
-                        Val::Err{..} => "err", Val::Record(_) => "record",
-                        Val::Chan(..) => "chan",
-                        Val::Mtx(..) => "mutex",
-                        Val::Big(..) => "bigint",
-                        Val::Promise(..) => "promise",
-                        Val::VmFunc(_) => "vm-func",
-                    })
-                }
-            }
+            get_member(o, k, tracer, loader)
         }
         Expr::Let(name, e1, e2) => {
             let v1 = eval(e1, env, tracer, loader)?;
@@ -4154,29 +4474,12 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
         }
         Expr::NamedCall(f_expr, named_args) => {
             let fv = eval(f_expr, env, tracer, loader)?;
-            // Extract param names from function
-            let params = match &fv {
-                Val::Func(f) => f.params.iter().map(|p| match p {
-                    Pat::Bind(n) => n.clone(),
-                    _ => "_".to_string(),
-                }).collect::<Vec<_>>(),
-                _ => bail!("named call on non-function"),
-            };
-            // Build ordered args
-            let mut ordered: Vec<Val> = vec![Val::Unit; params.len()];
-            let mut filled = vec![false; params.len()];
+            let params = named_call_params(&fv)?;
+            let mut named = Vec::with_capacity(named_args.len());
             for (name, expr) in named_args {
-                let v = eval(expr, env, tracer, loader)?;
-                if let Some(i) = params.iter().position(|p| p == name) {
-                    ordered[i] = v;
-                    filled[i] = true;
-                } else {
-                    bail!("named arg '{}' not found in function params {:?}", name, params);
-                }
-            }
-            for (i, ok) in filled.iter().enumerate() {
-                if !ok { bail!("named arg '{}' not provided", params[i]); }
+                named.push((name.clone(), eval(expr, env, tracer, loader)?));
             }
+            let ordered = order_named_args(&params, named)?;
             call(fv, ordered, tracer, loader)
         }
         Expr::Return(e) => {
@@ -4185,43 +4488,10 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
             bail!("FARD_EARLY_RETURN");
         }
         Expr::While(init_expr, cond_expr, body_expr) => {
-            let mut state = eval(init_expr, env, tracer, loader)?;
-            let mut chain: [u8;32] = sha256_raw(b"").try_into().unwrap_or([0u8;32]);
-            let mut step_idx: i64 = 0;
-            let compact = std::env::var("FARD_COMPACT_WHILE").is_ok();
-            if compact {
-                let _ = tracer.emit_raw(&format!(r#"{{"t":"while_start"}}"#));
-            }
-            loop {
-                let cv = call(eval(cond_expr, env, tracer, loader)?, vec![state.clone()], tracer, loader)?;
-                match cv {
-                    Val::Bool(false) => break,
-                    Val::Bool(true) => {
-                        let before = state.clone();
-                        state = call(eval(body_expr, env, tracer, loader)?, vec![before.clone()], tracer, loader)?;
-                        let before_j = before.to_json().map(|j| json_to_string(&j)).unwrap_or_else(|| "null".to_string());
-                        let after_j  = state.to_json().map(|j| json_to_string(&j)).unwrap_or_else(|| "null".to_string());
-                        let pre_hex  = hex_lower(&chain);
-                        let args_str = format!("{{\"step\":{},\"before\":{},\"after\":{}}}", step_idx, before_j, after_j);
-                        let digest_input = format!("{{\"args\":{},\"op\":\"WHILE_STEP\",\"post\":\"{}\",\"pre\":\"{}\"}}", args_str, after_j, pre_hex);
-                        chain = sha256_raw(digest_input.as_bytes()).try_into().unwrap_or([0u8;32]);
-                        if compact {
-                            let ch16 = &hex_lower(&chain)[..16];
-                            let _ = tracer.emit_raw(&format!(r#"{{"t":"while_step","s":{},"h":"{}"}}"#, step_idx, ch16));
-                        }
-                        step_idx += 1;
-                    }
-                    _ => bail!("while cond_fn must return bool"),
-                }
-            }
-            if compact {
-                let _ = tracer.emit_raw(&format!(r#"{{"t":"while_end","steps":{},"chain_hex":"{}"}}"#, step_idx, hex_lower(&chain)));
-            }
-            let mut result = BTreeMap::new();
-            result.insert("value".to_string(), state);
-            result.insert("steps".to_string(), Val::Int(step_idx));
-            result.insert("chain_hex".to_string(), Val::Text(hex_lower(&chain)));
-            Ok(Val::Record(result))
+            let state = eval(init_expr, env, tracer, loader)?;
+            let cond_fn = eval(cond_expr, env, tracer, loader)?;
+            let body_fn = eval(body_expr, env, tracer, loader)?;
+            run_while(state, cond_fn, body_fn, tracer, loader)
         }
         Expr::If(c, t, f) => {
             let cv = eval(c, env, tracer, loader)?;
@@ -4243,60 +4513,12 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
         })),
         Expr::Unary(op, a) => {
             let v = eval(a, env, tracer, loader)?;
-            match (op.as_str(), v) {
-                ("-", Val::Int(n)) => Ok(Val::Int(-n)),
-                ("-", Val::Bytes(b)) => { let f = f64::from_le_bytes(b.as_slice().try_into().unwrap_or([0u8;8])); Ok(Val::Bytes((-f).to_le_bytes().to_vec())) }
-                ("-", Val::Float(f)) => Ok(Val::Float(-f)),
-                ("!", Val::Bool(b)) => Ok(Val::Bool(!b)),
-                _ => bail!("bad unary op"),
-            }
+            unary_op(op, v)
         }
         Expr::Bin(op, a, b) => {
             let x = eval(a, env, tracer, loader)?;
             let y = eval(b, env, tracer, loader)?;
-            match (op.as_str(), x, y) {
-                ("+", Val::Int(l), Val::Int(r)) => Ok(Val::Int(l + r)),
-                ("-", Val::Int(l), Val::Int(r)) => Ok(Val::Int(l - r)),
-                ("*", Val::Int(l), Val::Int(r)) => Ok(Val::Int(l * r)),
-                ("/", Val::Int(l), Val::Int(r)) => { if r == 0 { bail!("ERROR_DIV_ZERO division by zero") } Ok(Val::Int(l / r)) }
-                ("+", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l + r)),
-                ("-", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l - r)),
-                ("*", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l * r)),
-                ("/", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l / r)),
-                ("<", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l < r)),
-                (">", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l > r)),
-                ("<=", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l <= r)),
-                (">=", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l >= r)),
-                ("==", l, r) => {
-                    if matches!((&l, &r), (Val::Float(_), _) | (_, Val::Float(_))) {
-                        let mut lm = BTreeMap::new();
-                        lm.insert("level".to_string(), J::Str("warn".to_string()));
-                        lm.insert("msg".to_string(), J::Str("LINT_FLOAT_EQ: == on float values is unreliable; use float.eq instead".to_string()));
-                        let _ = tracer.emit(&J::Object(lm));
-                    }
-                    Ok(Val::Bool(val_eq(&l, &r)))
-                }
-                ("!=", l, r) => {
-                    if matches!((&l, &r), (Val::Float(_), _) | (_, Val::Float(_))) {
-                        let mut lm = BTreeMap::new();
-                        lm.insert("level".to_string(), J::Str("warn".to_string()));
-                        lm.insert("msg".to_string(), J::Str("LINT_FLOAT_EQ: != on float values is unreliable; use float.eq instead".to_string()));
-                        let _ = tracer.emit(&J::Object(lm));
-                    }
-                    Ok(Val::Bool(!val_eq(&l, &r)))
-                }
-                ("&&", Val::Bool(l), Val::Bool(r)) => Ok(Val::Bool(l && r)),
-                ("||", Val::Bool(l), Val::Bool(r)) => Ok(Val::Bool(l || r)),
-                ("<", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l < r)),
-                (">", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l > r)),
-                ("<=", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l <= r)),
-                (">=", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l >= r)),
-                ("%", Val::Int(l), Val::Int(r)) => {
-                    if r == 0 { bail!("ERROR_DIV_ZERO modulo by zero") }
-                    Ok(Val::Int(l % r))
-                }
-                _ => bail!("bad binop {op}"),
-            }
+            binary_op(op, x, y, tracer)
         }
         Expr::Call(f, args) => {
             let fv = eval(f, env, tracer, loader)?;
@@ -4308,26 +4530,9 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
         }
         Expr::Try(x) => {
             let rv = eval(x, env, tracer, loader)?;
-
-            let ok = match result_is_ok(&rv) {
-                Ok(b) => b,
-                Err(e) => {
-                    let msg = format!("{}", e);
-                    if msg.contains("QMARK_EXPECT_RESULT ok missing v") {
-                        bail!("QMARK_EXPECT_RESULT ok missing v");
-                    }
-                    if msg.contains("QMARK_EXPECT_RESULT err missing e") {
-                        bail!("QMARK_EXPECT_RESULT err missing e");
-                    }
-                    bail!("{} expected result", QMARK_EXPECT_RESULT);
-                }
-            };
-
-            if ok {
-                result_unwrap_ok(&rv)
-            } else {
-                let e = result_unwrap_err(&rv)?;
-                return Err(QMarkUnwind { err: e }.into());
+            match qmark_split(&rv)? {
+                Ok(v) => Ok(v),
+                Err(e) => Err(QMarkUnwind { err: e }.into()),
             }
         }
         Expr::Match(scrut, _arms) => {
@@ -4342,16 +4547,7 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
                             Val::Bool(false) => {
                                 continue;
                             }
-                            _ => {
-                                let sp = arm
-                                    .guard_span
-                                    .clone()
-                                    .expect("guard_span must exist when guard exists");
-                                return Err(anyhow!(SpannedRuntimeError {
-                                    span: sp,
-                                    message: "ERROR_RUNTIME match guard not bool".to_string(),
-                                }));
-                            }
+                            _ => return Err(guard_not_bool(arm.guard_span.clone())),
                         }
                     }
                     match eval(&arm.body, &mut env2, tracer, loader) {
@@ -4372,6 +4568,265 @@ fn eval(e: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut ModuleLoader)
         }
     }
 }
+fn unbound_var(n: &str, env: &Env) -> anyhow::Error {
+    // Suggest stdlib import if name matches a known module
+    let stdlib_modules = [
+        "str","list","math","io","json","hash","http","re","map","set",
+        "result","option","ffi","witness","process","env","net","trace",
+        "float","bigint","bits","path","datetime","uuid","base64","csv",
+        "compress","crypto","graph","linalg","type","promise","chan",
+        "mutex","ast","eval","cell","grow","flow","cast","rec","record",
+    ];
+    if stdlib_modules.contains(&n) {
+        anyhow!("unbound var: {n} -- did you forget to import? Try: import(\"std/{n}\") as {n}")
+    } else {
+        // Find similar names in env using edit distance
+        let env_keys: Vec<String> = env.keys();
+        let suggestion = env_keys.iter()
+            .filter(|k| edit_distance(k, n) <= 2 && !k.is_empty())
+            .min_by_key(|k| edit_distance(k, n))
+            .cloned();
+        if let Some(s) = suggestion {
+            anyhow!("unbound var: {n} -- did you mean '{s}'?")
+        } else {
+            anyhow!("unbound var: {n}")
+        }
+    }
+}
+fn interp_text(v: Val) -> String {
+    match v {
+        Val::Text(s) => s,
+        Val::Int(n) => n.to_string(),
+        Val::Float(f) => f.to_string(),
+        Val::Bool(b) => b.to_string(),
+        other => other.to_json().map(|j| canon_json(&j).unwrap_or_default()).unwrap_or_else(|| "?".to_string()),
+    }
+}
+fn index_val(v: Val, i: Val) -> Result<Val> {
+    match (v, i) {
+        (Val::List(xs), Val::Int(n)) => {
+            if n < 0 || n as usize >= xs.len() {
+                bail!("ERROR_OOB index {} out of bounds (len {})", n, xs.len());
+            }
+            Ok(xs[n as usize].clone())
+        }
+        (Val::Record(m), Val::Text(k)) => {
+            m.get(&k).cloned().ok_or_else(|| anyhow!("ERROR_KEY key {:?} not found", k))
+        }
+        _ => bail!("ERROR_BADARG index operator requires list[int] or rec[str]"),
+    }
+}
+fn get_member(o: Val, k: &str, tracer: &mut Tracer, loader: &mut ModuleLoader) -> Result<Val> {
+    match &o {
+        Val::Record(m) => m
+            .get(k)
+            .cloned()
+            .ok_or_else(|| {
+                // Suggest similar field names
+                let keys: Vec<&String> = m.keys().collect();
+                let suggestion = keys.iter()
+                    .filter(|candidate| edit_distance(candidate, k) <= 2)
+                    .min_by_key(|candidate| edit_distance(candidate, k))
+                    .map(|s| s.as_str());
+                if let Some(s) = suggestion {
+                    anyhow!("no member '{k}' -- did you mean '{s}'?")
+                } else {
+                    let available: Vec<&str> = keys.iter().map(|s| s.as_str()).take(8).collect();
+                    anyhow!("no member '{k}' -- available: {}", available.join(", "))
+                }
+            }),
+        // Method-style dispatch: val.method looks up k in the stdlib
+        // module for that value type and returns a BoundMethod that
+        // prepends the receiver when called. xs.map(f) -> map(xs, f).
+        v => {
+            let type_mod = match v {
+                Val::List(_)  => Some("std/list"),
+                Val::Text(_)  => Some("std/str"),
+                Val::Int(_)   => Some("std/int"),
+                Val::Bytes(_) => Some("std/bytes"),
+                Val::Float(_) => Some("std/float"),
+                _             => None,
+            };
+            if let Some(mod_name) = type_mod {
+                let here = loader.root_dir.clone();
+                let m = loader.load_module(mod_name, &here, tracer)
+                    .map_err(|e| anyhow!("method dispatch: {mod_name}: {e}"))?;
+                if let Some(f) = m.get(k) {
+                    return Ok(Val::BoundMethod(Box::new(o.clone()), Box::new(f.clone())));
+                }
+                bail!("method not found: {k} on type {mod_name}");
+            }
+            bail!("no methods on type: {}", match v {
+                Val::Unit => "unit", Val::Bool(_) => "bool",
+                Val::Int(_) => "int", Val::Float(_) => "float",
+                Val::Text(_) => "text", Val::Bytes(_) => "bytes",
+                Val::List(_) => "list", Val::Func(_) | Val::Builtin(_) | Val::VmFunc(_) => "function",
+                Val::BoundMethod(..) => "bound-method",
+                Val::Err{..} => "err", Val::Record(_) => "record",
+                Val::Chan(..) => "chan",
+                Val::Mtx(..) => "mutex",
+                Val::Big(..) => "bigint",
+                Val::Promise(..) => "promise",
+            })
+        }
+    }
+}
+fn unary_op(op: &str, v: Val) -> Result<Val> {
+    match (op, v) {
+        ("-", Val::Int(n)) => Ok(Val::Int(-n)),
+        ("-", Val::Bytes(b)) => { let f = f64::from_le_bytes(b.as_slice().try_into().unwrap_or([0u8;8])); Ok(Val::Bytes((-f).to_le_bytes().to_vec())) }
+        ("-", Val::Float(f)) => Ok(Val::Float(-f)),
+        ("!", Val::Bool(b)) => Ok(Val::Bool(!b)),
+        _ => bail!("bad unary op"),
+    }
+}
+fn binary_op(op: &str, x: Val, y: Val, tracer: &mut Tracer) -> Result<Val> {
+    match (op, x, y) {
+        ("+", Val::Int(l), Val::Int(r)) => Ok(Val::Int(l + r)),
+        ("-", Val::Int(l), Val::Int(r)) => Ok(Val::Int(l - r)),
+        ("*", Val::Int(l), Val::Int(r)) => Ok(Val::Int(l * r)),
+        ("/", Val::Int(l), Val::Int(r)) => { if r == 0 { bail!("ERROR_DIV_ZERO division by zero") } Ok(Val::Int(l / r)) }
+        ("+", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l + r)),
+        ("-", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l - r)),
+        ("*", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l * r)),
+        ("/", Val::Float(l), Val::Float(r)) => Ok(Val::Float(l / r)),
+        ("<", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l < r)),
+        (">", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l > r)),
+        ("<=", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l <= r)),
+        (">=", Val::Float(l), Val::Float(r)) => Ok(Val::Bool(l >= r)),
+        ("==", l, r) => {
+            if matches!((&l, &r), (Val::Float(_), _) | (_, Val::Float(_))) {
+                let mut lm = BTreeMap::new();
+                lm.insert("level".to_string(), J::Str("warn".to_string()));
+                lm.insert("msg".to_string(), J::Str("LINT_FLOAT_EQ: == on float values is unreliable; use float.eq instead".to_string()));
+                let _ = tracer.emit(&J::Object(lm));
+            }
+            Ok(Val::Bool(val_eq(&l, &r)))
+        }
+        ("!=", l, r) => {
+            if matches!((&l, &r), (Val::Float(_), _) | (_, Val::Float(_))) {
+                let mut lm = BTreeMap::new();
+                lm.insert("level".to_string(), J::Str("warn".to_string()));
+                lm.insert("msg".to_string(), J::Str("LINT_FLOAT_EQ: != on float values is unreliable; use float.eq instead".to_string()));
+                let _ = tracer.emit(&J::Object(lm));
+            }
+            Ok(Val::Bool(!val_eq(&l, &r)))
+        }
+        ("&&", Val::Bool(l), Val::Bool(r)) => Ok(Val::Bool(l && r)),
+        ("||", Val::Bool(l), Val::Bool(r)) => Ok(Val::Bool(l || r)),
+        ("<", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l < r)),
+        (">", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l > r)),
+        ("<=", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l <= r)),
+        (">=", Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l >= r)),
+        ("%", Val::Int(l), Val::Int(r)) => {
+            if r == 0 { bail!("ERROR_DIV_ZERO modulo by zero") }
+            Ok(Val::Int(l % r))
+        }
+        _ => bail!("bad binop {op}"),
+    }
+}
+/// Splits the operand of `?` into its ok value or the err payload to propagate.
+fn qmark_split(rv: &Val) -> Result<std::result::Result<Val, Val>> {
+    let ok = match result_is_ok(rv) {
+        Ok(b) => b,
+        Err(e) => {
+            let msg = format!("{}", e);
+            if msg.contains("QMARK_EXPECT_RESULT ok missing v") {
+                bail!("QMARK_EXPECT_RESULT ok missing v");
+            }
+            if msg.contains("QMARK_EXPECT_RESULT err missing e") {
+                bail!("QMARK_EXPECT_RESULT err missing e");
+            }
+            bail!("{} expected result", QMARK_EXPECT_RESULT);
+        }
+    };
+    if ok {
+        Ok(Ok(result_unwrap_ok(rv)?))
+    } else {
+        Ok(Err(result_unwrap_err(rv)?))
+    }
+}
+fn guard_not_bool(span: Option<ErrorSpan>) -> anyhow::Error {
+    match span {
+        Some(span) => anyhow!(SpannedRuntimeError {
+            span,
+            message: "ERROR_RUNTIME match guard not bool".to_string(),
+        }),
+        None => anyhow!("ERROR_RUNTIME match guard not bool"),
+    }
+}
+/// Parameter names a named call is matched against; destructuring params are `_`.
+fn named_call_params(f: &Val) -> Result<Vec<String>> {
+    match f {
+        Val::Func(f) => Ok(f.params.iter().map(|p| match p {
+            Pat::Bind(n) => n.clone(),
+            _ => "_".to_string(),
+        }).collect()),
+        Val::VmFunc(c) => Ok(c.proto.param_names.clone()),
+        _ => bail!("named call on non-function"),
+    }
+}
+fn order_named_args(params: &[String], named: Vec<(String, Val)>) -> Result<Vec<Val>> {
+    let mut ordered: Vec<Val> = vec![Val::Unit; params.len()];
+    let mut filled = vec![false; params.len()];
+    for (name, v) in named {
+        if let Some(i) = params.iter().position(|p| *p == name) {
+            ordered[i] = v;
+            filled[i] = true;
+        } else {
+            bail!("named arg '{}' not found in function params {:?}", name, params);
+        }
+    }
+    for (i, ok) in filled.iter().enumerate() {
+        if !ok { bail!("named arg '{}' not provided", params[i]); }
+    }
+    Ok(ordered)
+}
+/// `while init cond body`: steps `body` until `cond` is false, hash-chaining every step.
+fn run_while(
+    mut state: Val,
+    cond_fn: Val,
+    body_fn: Val,
+    tracer: &mut Tracer,
+    loader: &mut ModuleLoader,
+) -> Result<Val> {
+    let mut chain: [u8;32] = sha256_raw(b"").try_into().unwrap_or([0u8;32]);
+    let mut step_idx: i64 = 0;
+    let compact = std::env::var("FARD_COMPACT_WHILE").is_ok();
+    if compact {
+        let _ = tracer.emit_raw(&format!(r#"{{"t":"while_start"}}"#));
+    }
+    loop {
+        let cv = call(cond_fn.clone(), vec![state.clone()], tracer, loader)?;
+        match cv {
+            Val::Bool(false) => break,
+            Val::Bool(true) => {
+                let before = state.clone();
+                state = call(body_fn.clone(), vec![before.clone()], tracer, loader)?;
+                let before_j = before.to_json().map(|j| json_to_string(&j)).unwrap_or_else(|| "null".to_string());
+                let after_j  = state.to_json().map(|j| json_to_string(&j)).unwrap_or_else(|| "null".to_string());
+                let pre_hex  = hex_lower(&chain);
+                let args_str = format!("{{\"step\":{},\"before\":{},\"after\":{}}}", step_idx, before_j, after_j);
+                let digest_input = format!("{{\"args\":{},\"op\":\"WHILE_STEP\",\"post\":\"{}\",\"pre\":\"{}\"}}", args_str, after_j, pre_hex);
+                chain = sha256_raw(digest_input.as_bytes()).try_into().unwrap_or([0u8;32]);
+                if compact {
+                    let ch16 = &hex_lower(&chain)[..16];
+                    let _ = tracer.emit_raw(&format!(r#"{{"t":"while_step","s":{},"h":"{}"}}"#, step_idx, ch16));
+                }
+                step_idx += 1;
+            }
+            _ => bail!("while cond_fn must return bool"),
+        }
+    }
+    if compact {
+        let _ = tracer.emit_raw(&format!(r#"{{"t":"while_end","steps":{},"chain_hex":"{}"}}"#, step_idx, hex_lower(&chain)));
+    }
+    let mut result = BTreeMap::new();
+    result.insert("value".to_string(), state);
+    result.insert("steps".to_string(), Val::Int(step_idx));
+    result.insert("chain_hex".to_string(), Val::Text(hex_lower(&chain)));
+    Ok(Val::Record(result))
+}
 #[allow(dead_code)]
 fn is_result_val(v: &Val) -> bool {
     match v {
@@ -4400,129 +4855,2431 @@ fn fs_sandbox_check(path: &str) -> Result<()> {
     Ok(())
 }
 
-fn http_response_to_val(resp: ureq::Response) -> Result<Val> {
-    let status = resp.status() as i64;
-    let body = resp.into_string().unwrap_or_default();
-    let mut m = BTreeMap::new();
-    m.insert("status".to_string(), Val::Int(status));
-    m.insert("body".to_string(), Val::Text(body));
-    Ok(Val::Record(m))
+/// Allow-lists granted to a program by `--policy` or the `[permissions]` section of fard.toml.
+/// With no policy every capability is granted; with a policy anything not listed is denied.
+#[derive(Clone, Debug, Default)]
+struct CapPolicy {
+    /// Directory relative roots are resolved against: the one holding the policy file
+    base: PathBuf,
+    fs_read: Vec<String>,
+    fs_write: Vec<String>,
+    http_hosts: Vec<String>,
+    exec: Vec<String>,
+    ffi: Vec<String>,
+    listen: Vec<i64>,
+    env: Vec<String>,
 }
 
-fn http_response_to_val_with_status(code: u16, resp: ureq::Response) -> Result<Val> {
-    let body = resp.into_string().unwrap_or_default();
-    let mut m = BTreeMap::new();
-    m.insert("status".to_string(), Val::Int(code as i64));
-    m.insert("body".to_string(), Val::Text(body));
-    Ok(Val::Record(m))
-}
+impl CapPolicy {
+    /// Parse the `[permissions]` section of `src`, the text of a file in `base`. When
+    /// `bare` is set (a dedicated policy file), keys before any section header are read
+    /// as permissions too. Returns `None` when no permissions section is present.
+    fn parse(src: &str, bare: bool, base: &Path) -> Result<Option<CapPolicy>> {
+        let base = std::env::current_dir().unwrap_or_default().join(base);
+        let mut pol = CapPolicy { base, ..CapPolicy::default() };
+        let mut found = bare;
+        let mut in_perms = bare;
+        for (n, line) in toml_lines(src).map_err(|e| anyhow!("ERROR_POLICY {}", e))? {
+            let (k, v) = match line {
+                TomlLine::Table(name) => {
+                    in_perms = name == "permissions";
+                    found |= in_perms;
+                    continue;
+                }
+                TomlLine::ArrayTable(_) => {
+                    in_perms = false;
+                    continue;
+                }
+                TomlLine::Pair(k, v) => (k, v),
+            };
+            if !in_perms { continue; }
+            let bad = |e: anyhow::Error| anyhow!("ERROR_POLICY line {}: {} must be a list: {}", n, k, e);
+            match k.as_str() {
+                "fs_read" => pol.fs_read = parse_toml_str_array(&v).map_err(bad)?,
+                "fs_write" => pol.fs_write = parse_toml_str_array(&v).map_err(bad)?,
+                "http_hosts" => pol.http_hosts = parse_toml_str_array(&v).map_err(bad)?,
+                "exec" => pol.exec = parse_toml_str_array(&v).map_err(bad)?,
+                "ffi" => pol.ffi = parse_toml_str_array(&v).map_err(bad)?,
+                "env" => pol.env = parse_toml_str_array(&v).map_err(bad)?,
+                "listen" => pol.listen = parse_toml_array(&v, parse_toml_int).map_err(bad)?,
+                other => bail!("ERROR_POLICY unknown permission {}", other),
+            }
+        }
+        Ok(if found { Some(pol) } else { None })
+    }
 
-fn days_since_epoch(year: i64, month: i64, day: i64) -> i64 {
-    // Proleptic Gregorian calendar days since 1970-01-01
-    let y = if month <= 2 { year - 1 } else { year };
-    let m = if month <= 2 { month + 12 } else { month };
-    let d = day;
-    let era = y.div_euclid(400);
-    let yoe = y - era * 400;
-    let doy = (153 * (m - 3) + 2) / 5 + d - 1;
-    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
-    era * 146097 + doe - 719468
+    /// A root as written in the policy, resolved against the policy's directory.
+    fn root(&self, r: &str) -> PathBuf {
+        cap_resolve(self.base.join(r), 0)
+    }
+
+    /// Canonical JSON of the effective policy; its sha256 is the `policy_digest` of the run.
+    fn to_json(&self) -> J {
+        let list = |xs: &[String]| {
+            let mut xs = xs.to_vec();
+            xs.sort();
+            xs.dedup();
+            J::Array(xs.into_iter().map(J::Str).collect())
+        };
+        let mut ports = self.listen.clone();
+        ports.sort();
+        ports.dedup();
+        let mut m = Map::new();
+        m.insert("env".to_string(), list(&self.env));
+        m.insert("exec".to_string(), list(&self.exec));
+        m.insert("ffi".to_string(), list(&self.ffi));
+        m.insert("fs_read".to_string(), list(&self.fs_read));
+        m.insert("fs_write".to_string(), list(&self.fs_write));
+        m.insert("http_hosts".to_string(), list(&self.http_hosts));
+        m.insert("listen".to_string(), J::Array(ports.into_iter().map(J::Int).collect()));
+        J::Object(m)
+    }
+
+    fn digest(&self) -> String {
+        format!("sha256:{}", sha256_bytes_hex(&canonical_json_bytes(&self.to_json())))
+    }
 }
 
-fn unix_secs_to_iso8601(secs: i64) -> String {
-    let (secs, _neg) = if secs < 0 { (-secs, true) } else { (secs, false) };
-    let days = secs / 86400;
-    let rem = secs % 86400;
-    let h = rem / 3600;
-    let m = (rem % 3600) / 60;
-    let s = rem % 60;
-    // Convert days since epoch to year/month/day
-    let z = days + 719468;
-    let era = z.div_euclid(146097);
-    let doe = z - era * 146097;
-    let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
-    let y = yoe + era * 400;
-    let doy = doe - (365*yoe + yoe/4 - yoe/100);
-    let mp = (5*doy + 2)/153;
-    let d = doy - (153*mp+2)/5 + 1;
-    let mo = if mp < 10 { mp + 3 } else { mp - 9 };
-    let y = if mo <= 2 { y + 1 } else { y };
-    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, mo, d, h, m, s)
+/// Resolve `p` against the working directory the way the OS will, so symlinks
+/// cannot lead out of an allowed root: the longest existing prefix is canonicalized,
+/// the components after it are appended with `.`/`..` dropped lexically, and a
+/// dangling symlink among them is followed to where a write would land.
+fn cap_abs_path(p: &str) -> PathBuf {
+    cap_resolve(std::env::current_dir().unwrap_or_default().join(p), 0)
 }
 
-fn result_is_ok(v: &Val) -> Result<bool> {
-    match v {
-        Val::Record(m) => {
-            if m.len() != 2 {
-                bail!("{} expected result", QMARK_EXPECT_RESULT);
-            }
-            match m.get(RESULT_TAG_KEY) {
-                Some(Val::Text(t)) if t == RESULT_OK_TAG => {
-                    if !m.contains_key(RESULT_OK_VAL_KEY) {
-                        bail!("QMARK_EXPECT_RESULT ok missing v");
-                    }
-                    Ok(true)
-                }
-                Some(Val::Text(t)) if t == RESULT_ERR_TAG => {
-                    if !m.contains_key(RESULT_ERR_VAL_KEY) {
-                        bail!("QMARK_EXPECT_RESULT err missing e");
+fn cap_resolve(full: PathBuf, depth: usize) -> PathBuf {
+    use std::path::Component;
+    let comps: Vec<Component> = full.components().collect();
+    for i in (1..=comps.len()).rev() {
+        let Ok(mut out) = comps[..i].iter().collect::<PathBuf>().canonicalize() else { continue };
+        for (k, c) in comps[i..].iter().enumerate() {
+            match c {
+                Component::CurDir => {}
+                Component::ParentDir => { out.pop(); }
+                c => {
+                    out.push(c);
+                    if let (Ok(target), true) = (fs::read_link(&out), depth < 40) {
+                        out.pop();
+                        let rest: PathBuf = comps[i + k + 1..].iter().collect();
+                        return cap_resolve(out.join(target).join(rest), depth + 1);
                     }
-                    Ok(false)
                 }
-                _ => bail!("{} expected result tag", QMARK_EXPECT_RESULT),
             }
         }
-        _ => bail!("{} expected result", QMARK_EXPECT_RESULT),
+        return out;
     }
+    full
 }
 
-fn result_unwrap_ok(v: &Val) -> Result<Val> {
-    match v {
-        Val::Record(m) => match m.get(RESULT_TAG_KEY) {
-            Some(Val::Text(t)) if t == RESULT_OK_TAG => m
-                .get(RESULT_OK_VAL_KEY)
-                .cloned()
-                .ok_or_else(|| anyhow!("QMARK_EXPECT_RESULT ok missing v")),
-            Some(Val::Text(t)) if t == RESULT_ERR_TAG => {
-                bail!("QMARK_EXPECT_RESULT tried unwrap ok on err")
+/// `*` as the last character of an entry matches any suffix.
+fn cap_name_allowed(allowed: &[String], name: &str) -> bool {
+    allowed.iter().any(|a| match a.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => a == name,
+    })
+}
+
+fn cap_url_host(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host_port = authority.rsplit('@').next().unwrap_or("");
+    let host = if let Some(v6) = host_port.strip_prefix('[') {
+        v6.split(']').next().unwrap_or("")
+    } else {
+        host_port.split(':').next().unwrap_or("")
+    };
+    host.to_lowercase()
+}
+
+/// Check one capability against the active policy. Denials are traced as
+/// `capability_denied` and raised as `ERROR_CAPABILITY`.
+fn cap_check(tracer: &mut Tracer, cap: &str, op: &str, target: &str) -> Result<()> {
+    let allowed = CAP_POLICY.with(|p| {
+        let p = p.borrow();
+        let Some(pol) = p.as_ref() else { return true };
+        match cap {
+            "fs_read" | "fs_write" => {
+                let roots = if cap == "fs_read" { &pol.fs_read } else { &pol.fs_write };
+                let path = cap_abs_path(target);
+                roots.iter().any(|r| path.starts_with(pol.root(r)))
+            }
+            "http" => {
+                let host = cap_url_host(target);
+                pol.http_hosts.iter().any(|h| match h.strip_prefix("*.") {
+                    Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+                    None => host == h.to_lowercase(),
+                })
             }
-            Some(_) => bail!("{} expected result tag", QMARK_EXPECT_RESULT),
-            None => bail!("{} expected result", QMARK_EXPECT_RESULT),
-        },
-        _ => bail!("{} expected result", QMARK_EXPECT_RESULT),
+            "exec" => {
+                let base = Path::new(target).file_name().map(|s| s.to_string_lossy().to_string());
+                pol.exec.iter().any(|e| e == target || Some(e) == base.as_ref())
+            }
+            "ffi" => {
+                let path = cap_abs_path(target);
+                pol.ffi.iter().any(|f| f == target || pol.root(f) == path)
+            }
+            "listen" => target.parse::<i64>().map(|port| pol.listen.contains(&port)).unwrap_or(false),
+            "env" => cap_name_allowed(&pol.env, target),
+            _ => false,
+        }
+    });
+    if allowed {
+        return Ok(());
     }
+    let mut m = Map::new();
+    m.insert("t".to_string(), J::Str("capability_denied".to_string()));
+    m.insert("cap".to_string(), J::Str(cap.to_string()));
+    m.insert("op".to_string(), J::Str(op.to_string()));
+    m.insert("target".to_string(), J::Str(target.to_string()));
+    tracer.emit_event(J::Object(m))?;
+    bail!("ERROR_CAPABILITY {} denied {} {}", op, cap, target)
 }
-fn result_unwrap_err(v: &Val) -> Result<Val> {
-    match v {
-        Val::Record(m) => match m.get(RESULT_TAG_KEY) {
-            Some(Val::Text(t)) if t == RESULT_ERR_TAG => match m.get(RESULT_ERR_VAL_KEY) {
-                Some(x) => Ok(x.clone()),
-                None => bail!("QMARK_EXPECT_RESULT err missing e"),
-            },
-            Some(Val::Text(t)) if t == RESULT_OK_TAG => {
-                bail!("QMARK_EXPECT_RESULT tried unwrap err on ok")
+
+/// Load the `oracle` events of `<outdir>/trace.ndjson` for `--replay`: those of the
+/// main task, and those of each child's sub-trace named by its `child_receipt`, keyed
+/// by spawn id. The recorded run must come from a runtime with a known stdlib root.
+fn oracle_load_replay(outdir: &Path) -> Result<(Vec<J>, HashMap<String, Vec<J>>)> {
+    fn collect(
+        outdir: &Path,
+        text: &str,
+        events: &mut Vec<J>,
+        children: &mut HashMap<String, Vec<J>>,
+    ) -> Result<()> {
+        for line in text.split('\n') {
+            if line.is_empty() { continue; }
+            let ev = json_from_str(line).map_err(|_| anyhow!("ERROR_REPLAY malformed trace line"))?;
+            match ev.get("t").and_then(|t| t.as_str()) {
+                Some("oracle") => events.push(ev),
+                Some("child_receipt") => {
+                    let spawn_id = ev.get("spawn_id").and_then(|v| v.as_str()).unwrap_or("").to_string();
+                    let Some(rel) = ev.get("trace").and_then(|v| v.as_str()) else { continue };
+                    if rel != format!("children/{}.ndjson", spawn_id) {
+                        bail!("ERROR_REPLAY child {} names unexpected trace {}", spawn_id, rel);
+                    }
+                    let path = outdir.join(rel);
+                    let text = fs::read_to_string(&path)
+                        .with_context(|| format!("ERROR_REPLAY cannot read {}", path.display()))?;
+                    let mut child = Vec::new();
+                    collect(outdir, &text, &mut child, children)?;
+                    children.insert(spawn_id, child);
+                }
+                _ => {}
             }
-            Some(_) => bail!("{} expected result tag", QMARK_EXPECT_RESULT),
-            None => bail!("{} expected result", QMARK_EXPECT_RESULT),
-        },
-        _ => bail!("{} expected result", QMARK_EXPECT_RESULT),
+        }
+        Ok(())
     }
+    let digests_path = outdir.join("digests.json");
+    let digests = fs::read(&digests_path)
+        .ok()
+        .and_then(|b| json_from_slice(&b).ok())
+        .ok_or_else(|| anyhow!("ERROR_REPLAY cannot read {}", digests_path.display()))?;
+    check_parent_stdlib_root(
+        &format!("recorded run {}", outdir.display()),
+        digests.get("stdlib_root_digest").and_then(|v| v.as_str()),
+    )
+    .map_err(|e| anyhow!("ERROR_REPLAY {}", e))?;
+    let trace_path = outdir.join("trace.ndjson");
+    let text = fs::read_to_string(&trace_path)
+        .with_context(|| format!("ERROR_REPLAY cannot read {}", trace_path.display()))?;
+    let mut events = Vec::new();
+    let mut children = HashMap::new();
+    collect(outdir, &text, &mut events, &mut children)
+        .with_context(|| format!("ERROR_REPLAY in {}", trace_path.display()))?;
+    Ok((events, children))
 }
-fn mk_result_ok(v: Val) -> Val {
-    let mut m = BTreeMap::new();
-    m.insert(
-        RESULT_TAG_KEY.to_string(),
-        Val::Text(RESULT_OK_TAG.to_string()),
-    );
-    m.insert(RESULT_OK_VAL_KEY.to_string(), v);
-    Val::Record(m)
+
+fn oracle_args_digest(args: &[Val]) -> String {
+    let j = J::Array(args.iter().map(|a| a.to_json().unwrap_or(J::Null)).collect());
+    format!("sha256:{}", sha256_bytes_hex(&canonical_json_bytes(&j)))
 }
 
-fn mk_result_err(e: Val) -> Val {
-    let mut m = BTreeMap::new();
-    m.insert(
-        RESULT_TAG_KEY.to_string(),
-        Val::Text(RESULT_ERR_TAG.to_string()),
+/// Answer an oracle call according to the current `OracleMode`.
+/// `live` performs the real effect; it is not invoked when replaying.
+fn oracle_answer(
+    op: &str,
+    args: &[Val],
+    tracer: &mut Tracer,
+    live: impl FnOnce() -> Result<Val>,
+) -> Result<Val> {
+    let recorded = ORACLE_MODE.with(|m| match &mut *m.borrow_mut() {
+        OracleMode::Live => None,
+        OracleMode::Record => Some(None),
+        OracleMode::Replay(q) => Some(Some(q.pop_front())),
+    });
+    let args_digest = oracle_args_digest(args);
+    let ev = match recorded {
+        None => return live(),
+        Some(None) => {
+            let mut m = Map::new();
+            m.insert("t".to_string(), J::Str("oracle".to_string()));
+            m.insert("op".to_string(), J::Str(op.to_string()));
+            m.insert("args_digest".to_string(), J::Str(args_digest));
+            match live() {
+                Ok(v) => { m.insert("v".to_string(), v.to_json().unwrap_or(J::Null)); }
+                Err(e) => { m.insert("err".to_string(), J::Str(e.root_cause().to_string())); }
+            }
+            J::Object(m)
+        }
+        Some(Some(None)) => bail!("ERROR_REPLAY_EXHAUSTED {} called but the recorded trace has no more oracle answers", op),
+        Some(Some(Some(ev))) => {
+            let rec_op = ev.get("op").and_then(|v| v.as_str()).unwrap_or("");
+            if rec_op != op {
+                bail!("ERROR_REPLAY_DIVERGE expected {} but program called {}", rec_op, op);
+            }
+            let rec_args = ev.get("args_digest").and_then(|v| v.as_str()).unwrap_or("");
+            if rec_args != args_digest {
+                bail!("ERROR_REPLAY_DIVERGE {} called with different arguments: recorded {} got {}", op, rec_args, args_digest);
+            }
+            ev
+        }
+    };
+    tracer.emit_event(ev.clone())?;
+    if let Some(J::Str(e)) = ev.get("err") {
+        bail!("{}", e);
+    }
+    val_from_json(ev.get("v").unwrap_or(&J::Null))
+}
+
+/// In replay mode, fail if the program made fewer oracle calls than were recorded.
+fn oracle_replay_finish() -> Result<()> {
+    let left = ORACLE_MODE.with(|m| match &*m.borrow() {
+        OracleMode::Replay(q) => q.len(),
+        _ => 0,
+    });
+    if left > 0 {
+        bail!("ERROR_REPLAY_DIVERGE {} recorded oracle answer(s) were never consumed", left);
+    }
+    Ok(())
+}
+
+/// Per-thread runtime state a child task inherits from the task that spawned it.
+struct ChildCtx {
+    oracle: OracleMode,
+    policy: Option<CapPolicy>,
+    program_args: Vec<String>,
+    child_answers: Arc<HashMap<String, Vec<J>>>,
+    http_fixtures: Option<Arc<HttpFixtures>>,
+    sqlite_dbs: HashMap<String, SqliteDb>,
+    stdlib_roots: Vec<String>,
+    deadline: Option<std::time::Instant>,
+}
+
+impl ChildCtx {
+    /// Snapshot the current thread's state for the child `spawn_id`. When replaying,
+    /// the child answers from the oracle events recorded under its own receipt.
+    fn capture(spawn_id: &str) -> ChildCtx {
+        let child_answers = ORACLE_CHILD_ANSWERS.with(|c| c.borrow().clone());
+        let oracle = ORACLE_MODE.with(|m| match &*m.borrow() {
+            OracleMode::Live => OracleMode::Live,
+            OracleMode::Record => OracleMode::Record,
+            OracleMode::Replay(_) => OracleMode::Replay(
+                child_answers.get(spawn_id).cloned().unwrap_or_default().into(),
+            ),
+        });
+        ChildCtx {
+            oracle,
+            policy: CAP_POLICY.with(|c| c.borrow().clone()),
+            program_args: PROGRAM_ARGS.with(|c| c.borrow().clone()),
+            child_answers,
+            http_fixtures: HTTP_FIXTURES.with(|c| c.borrow().clone()),
+            sqlite_dbs: SQLITE_DBS.with(|c| c.borrow().clone()),
+            stdlib_roots: EXTRA_STDLIB_ROOTS.with(|c| c.borrow().clone()),
+            deadline: DEADLINE.with(|c| *c.borrow()),
+        }
+    }
+
+    /// Install this state on the current thread, returning what it replaced.
+    fn enter(self) -> ChildCtx {
+        ChildCtx {
+            oracle: ORACLE_MODE.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.oracle)),
+            policy: CAP_POLICY.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.policy)),
+            program_args: PROGRAM_ARGS.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.program_args)),
+            child_answers: ORACLE_CHILD_ANSWERS.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.child_answers)),
+            http_fixtures: HTTP_FIXTURES.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.http_fixtures)),
+            sqlite_dbs: SQLITE_DBS.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.sqlite_dbs)),
+            stdlib_roots: EXTRA_STDLIB_ROOTS.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.stdlib_roots)),
+            deadline: DEADLINE.with(|c| std::mem::replace(&mut *c.borrow_mut(), self.deadline)),
+        }
+    }
+}
+
+/// Bounded pool running `list.par_map` items and `promise.spawn` tasks.
+/// Sized by `FARD_THREADS`, defaulting to the available parallelism.
+fn task_pool() -> &'static rayon::ThreadPool {
+    static POOL: std::sync::OnceLock<rayon::ThreadPool> = std::sync::OnceLock::new();
+    POOL.get_or_init(|| {
+        let n = std::env::var("FARD_THREADS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4));
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .stack_size(16 << 20)
+            .thread_name(|i| format!("fard-task-{}", i))
+            .build()
+            .expect("task pool")
+    })
+}
+
+/// Outcome of a child task, handed back to the spawning task at join.
+struct ChildDone {
+    result: Result<Val, String>,
+    artifacts: BTreeMap<String, String>,
+    /// The child's loader, folded into the parent's at join (one per `par_map` chunk)
+    loader: Option<ModuleLoader>,
+}
+
+/// Where a spawned child leaves its `ChildDone` for the task that joins it, signalling the condvar.
+type ChildSlot = Arc<(Mutex<Option<ChildDone>>, std::sync::Condvar)>;
+
+impl std::fmt::Debug for ChildDone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChildDone").field("result", &self.result).finish()
+    }
+}
+
+/// Run `f(args)` as a child task on the current thread under `ctx`.
+fn run_child(ctx: ChildCtx, f: Val, args: Vec<Val>, mut tracer: Tracer, loader: &mut ModuleLoader) -> ChildDone {
+    let saved = ctx.enter();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let v = call(f, args, &mut tracer, loader).and_then(|v| oracle_replay_finish().map(|_| v));
+        let ended = tracer.end_span();
+        v.and_then(|v| ended.map(|_| v))
+    }));
+    let result = match result {
+        Ok(r) => r.map_err(|e| format!("{:#}", e)),
+        Err(_) => Err(format!("ERROR_RUNTIME child task {} panicked", tracer.spawn_prefix)),
+    };
+    let _ = std::io::Write::flush(&mut tracer.w);
+    saved.enter();
+    ChildDone { result, artifacts: tracer.artifact_cids, loader: None }
+}
+
+/// Spawn `f()` on the task pool with its own span, loader fork and inherited state.
+fn child_spawn_task(tracer: &mut Tracer, loader: &ModuleLoader, f: Val) -> Result<(String, PathBuf, ChildSlot)> {
+    let (spawn_id, trace_path, child) = tracer.child_tracer()?;
+    let ctx = ChildCtx::capture(&spawn_id);
+    let mut child_loader = loader.fork();
+    let slot: ChildSlot = Arc::new((Mutex::new(None), std::sync::Condvar::new()));
+    let slot2 = slot.clone();
+    task_pool().spawn(move || {
+        let mut done = run_child(ctx, f, vec![], child, &mut child_loader);
+        done.loader = Some(child_loader);
+        *slot2.0.lock().unwrap() = Some(done);
+        slot2.1.notify_all();
+    });
+    Ok((spawn_id, trace_path, slot))
+}
+
+/// Block until a spawned child has finished. A waiter on a pool thread first runs queued
+/// pool work, which may be the child itself; once none is left, the child is running on
+/// another thread and the waiter sleeps until it signals.
+fn child_wait(slot: &(Mutex<Option<ChildDone>>, std::sync::Condvar)) -> ChildDone {
+    let (done, signal) = slot;
+    while rayon::yield_now() == Some(rayon::Yield::Executed) {
+        if let Some(d) = done.lock().unwrap().take() {
+            return d;
+        }
+    }
+    let mut g = signal.wait_while(done.lock().unwrap(), |d| d.is_none()).unwrap();
+    g.take().expect("child slot filled")
+}
+
+/// Merge a finished child into its parent: artifacts, loaded modules and the
+/// child's span, closed by its `child_receipt`.
+fn child_join(tracer: &mut Tracer, loader: &mut ModuleLoader, spawn_id: &str, trace_path: &Path, done: ChildDone) -> Result<Val> {
+    tracer.artifact_cids.extend(done.artifacts);
+    if let Some(child) = done.loader {
+        loader.absorb(child);
+    }
+    let val = done.result.map_err(|e| anyhow!("{}", e))?;
+    tracer.child_receipt(spawn_id, trace_path, &val)?;
+    Ok(val)
+}
+
+/// Argument and return kinds of an `ffi.bind` signature.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum FfiTy {
+    Void,
+    I32,
+    I64,
+    Bool,
+    Ptr,
+    F32,
+    F64,
+    /// NUL-terminated copy of a Text argument; as a return, a `char*` read back as Text
+    Text,
+    /// Bytes copied into a scratch buffer and passed as (ptr, len); copied back out after the call
+    Bytes,
+    /// Zeroed buffer of the Int capacity argument, passed as (ptr, len) and returned
+    OutBytes,
+    /// Pointer to an 8-byte slot seeded from the argument and returned
+    OutI64,
+    OutF64,
+}
+
+impl FfiTy {
+    fn parse(s: &str) -> Result<FfiTy> {
+        Ok(match s {
+            "void" => FfiTy::Void,
+            "i32" => FfiTy::I32,
+            "i64" => FfiTy::I64,
+            "bool" => FfiTy::Bool,
+            "ptr" => FfiTy::Ptr,
+            "f32" => FfiTy::F32,
+            "f64" => FfiTy::F64,
+            "text" => FfiTy::Text,
+            "bytes" => FfiTy::Bytes,
+            "out_bytes" => FfiTy::OutBytes,
+            "out_i64" => FfiTy::OutI64,
+            "out_f64" => FfiTy::OutF64,
+            other => bail!("ERROR_FFI unknown ffi type {:?}", other),
+        })
+    }
+
+    fn is_out(self) -> bool {
+        matches!(self, FfiTy::Bytes | FfiTy::OutBytes | FfiTy::OutI64 | FfiTy::OutF64)
+    }
+
+    /// The C parameters this kind is passed as.
+    fn c_types(self) -> Vec<libffi::middle::Type> {
+        use libffi::middle::Type;
+        match self {
+            FfiTy::Void => Vec::new(),
+            FfiTy::I32 => vec![Type::i32()],
+            FfiTy::I64 => vec![Type::i64()],
+            FfiTy::Bool => vec![Type::u8()],
+            FfiTy::F32 => vec![Type::f32()],
+            FfiTy::F64 => vec![Type::f64()],
+            FfiTy::Bytes | FfiTy::OutBytes => vec![Type::pointer(), Type::usize()],
+            FfiTy::Ptr | FfiTy::Text | FfiTy::OutI64 | FfiTy::OutF64 => vec![Type::pointer()],
+        }
+    }
+}
+
+/// A parsed `ffi.bind` signature.
+struct FfiSig {
+    args: Vec<FfiTy>,
+    ret: FfiTy,
+    /// For a variadic callee, how many of `args` are its fixed parameters
+    fixed: Option<usize>,
+}
+
+/// One C argument, kept where libffi can point at it during the call.
+enum FfiArg {
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    F32(f32),
+    F64(f64),
+    Ptr(*mut std::ffi::c_void),
+    Usize(usize),
+}
+
+impl FfiArg {
+    fn arg(&self) -> libffi::middle::Arg {
+        use libffi::middle::Arg;
+        match self {
+            FfiArg::I32(x) => Arg::new(x),
+            FfiArg::I64(x) => Arg::new(x),
+            FfiArg::U8(x) => Arg::new(x),
+            FfiArg::F32(x) => Arg::new(x),
+            FfiArg::F64(x) => Arg::new(x),
+            FfiArg::Ptr(x) => Arg::new(x),
+            FfiArg::Usize(x) => Arg::new(x),
+        }
+    }
+}
+
+/// Call `symbol` with `args` through a call frame libffi lays out from `sig`, as a C
+/// compiler would for that prototype, and read the return value back as `sig.ret`.
+fn ffi_invoke(lib: &libloading::Library, symbol: &str, sig: &FfiSig, args: &[FfiArg]) -> Result<Val> {
+    use libffi::low;
+    use libffi::middle::Type;
+    // `cif` points into `types` and `ret`, which live until the call is done.
+    let types: Vec<Type> = sig.args.iter().flat_map(|t| t.c_types()).collect();
+    let mut raw: Vec<*mut low::ffi_type> = types.iter().map(Type::as_raw_ptr).collect();
+    let total = types.len();
+    let ret = sig.ret.c_types().pop().unwrap_or_else(Type::void);
+    let mut cif = low::ffi_cif::default();
+    let prepared = unsafe {
+        match sig.fixed {
+            Some(n) => {
+                let fixed = sig.args[..n].iter().map(|t| t.c_types().len()).sum();
+                low::prep_cif_var(&mut cif, low::ffi_abi_FFI_DEFAULT_ABI, fixed, total, ret.as_raw_ptr(), raw.as_mut_ptr())
+            }
+            None => low::prep_cif(&mut cif, low::ffi_abi_FFI_DEFAULT_ABI, total, ret.as_raw_ptr(), raw.as_mut_ptr()),
+        }
+    };
+    prepared.map_err(|e| anyhow!("ERROR_FFI {}: cannot lay out the call: {:?}", symbol, e))?;
+    let func: libloading::Symbol<unsafe extern "C" fn()> =
+        unsafe { lib.get(symbol.as_bytes()) }.map_err(|e| anyhow!("ERROR_FFI {}: {}", symbol, e))?;
+    let code = low::CodePtr::from_fun(*func);
+    let mut argv: Vec<libffi::middle::Arg> = args.iter().map(FfiArg::arg).collect();
+    let argv = argv.as_mut_ptr() as *mut *mut std::ffi::c_void;
+    // libffi widens integer returns narrower than a register to a whole `ffi_arg`.
+    unsafe {
+        Ok(match sig.ret {
+            FfiTy::Void => {
+                low::call::<()>(&mut cif, code, argv);
+                Val::Unit
+            }
+            FfiTy::I32 => Val::Int(low::call::<u64>(&mut cif, code, argv) as u32 as i32 as i64),
+            FfiTy::Bool => Val::Bool(low::call::<u64>(&mut cif, code, argv) as u8 != 0),
+            FfiTy::F32 => Val::Float(low::call::<f32>(&mut cif, code, argv) as f64),
+            FfiTy::F64 => Val::Float(low::call::<f64>(&mut cif, code, argv)),
+            FfiTy::Text => {
+                let p = low::call::<*const std::os::raw::c_char>(&mut cif, code, argv);
+                if p.is_null() {
+                    Val::Text(String::new())
+                } else {
+                    Val::Text(std::ffi::CStr::from_ptr(p).to_string_lossy().to_string())
+                }
+            }
+            FfiTy::Ptr => Val::Int(low::call::<*mut std::ffi::c_void>(&mut cif, code, argv) as usize as i64),
+            _ => Val::Int(low::call::<i64>(&mut cif, code, argv)),
+        })
+    }
+}
+
+/// Parse an `ffi.bind` signature record `{args: [..], ret: .., fixed?: n}`.
+fn ffi_parse_sig(sig: &Val) -> Result<FfiSig> {
+    let Val::Record(m) = sig else { bail!("ERROR_BADARG ffi.bind signature must be a record") };
+    let args = match m.get("args") {
+        Some(Val::List(l)) => l.iter().map(|t| match t {
+            Val::Text(s) => FfiTy::parse(s),
+            _ => bail!("ERROR_BADARG ffi.bind arg types must be text"),
+        }).collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+        _ => bail!("ERROR_BADARG ffi.bind args must be a list"),
+    };
+    if args.contains(&FfiTy::Void) {
+        bail!("ERROR_FFI void is only valid as a return type");
+    }
+    let ret = match m.get("ret") {
+        Some(Val::Text(s)) => FfiTy::parse(s)?,
+        None => FfiTy::Void,
+        _ => bail!("ERROR_BADARG ffi.bind ret must be text"),
+    };
+    if ret.is_out() {
+        bail!("ERROR_FFI {:?} is not a valid return type", ret);
+    }
+    let fixed = match m.get("fixed") {
+        None => None,
+        Some(Val::Int(n)) if *n >= 0 && (*n as usize) <= args.len() => Some(*n as usize),
+        Some(_) => bail!("ERROR_BADARG ffi.bind fixed must be an int from 0 to the number of args"),
+    };
+    // C promotes these before passing them to `...`, so the callee never sees them as such.
+    if let Some(t) = fixed.and_then(|n| args[n..].iter().find(|t| matches!(t, FfiTy::F32 | FfiTy::Bool))) {
+        bail!("ERROR_FFI {:?} cannot be a variadic argument; pass f64 or i32", t);
+    }
+    Ok(FfiSig { args, ret, fixed })
+}
+
+/// Marshal `vals` per `sig`, call, and unmarshal the return value and out-parameters.
+fn ffi_call_bound(handle: &str, symbol: &str, sig: &FfiSig, vals: &[Val]) -> Result<Val> {
+    let tys = &sig.args;
+    if vals.len() != tys.len() {
+        bail!("ERROR_ARITY ffi {} expects {} args, got {}", symbol, tys.len(), vals.len());
+    }
+    // Owned storage must outlive the call: pointers into it are among the arguments.
+    let mut cstrings: Vec<std::ffi::CString> = Vec::new();
+    let mut bufs: Vec<Vec<u8>> = Vec::new();
+    let mut cells: Vec<u64> = Vec::new();
+    for (ty, v) in tys.iter().zip(vals) {
+        match (ty, v) {
+            (FfiTy::I32 | FfiTy::I64 | FfiTy::Ptr, Val::Int(_)) => {}
+            (FfiTy::Bool, Val::Bool(_)) => {}
+            (FfiTy::F32 | FfiTy::F64, Val::Int(_) | Val::Float(_)) => {}
+            (FfiTy::Text, Val::Text(s)) => cstrings.push(std::ffi::CString::new(s.as_str())
+                .map_err(|_| anyhow!("ERROR_FFI text argument contains NUL"))?),
+            (FfiTy::Bytes, Val::Bytes(b)) => bufs.push(b.clone()),
+            (FfiTy::OutBytes, Val::Int(n)) if *n >= 0 => bufs.push(vec![0u8; *n as usize]),
+            (FfiTy::OutI64, Val::Int(n)) => cells.push(*n as u64),
+            (FfiTy::OutF64, Val::Float(x)) => cells.push(x.to_bits()),
+            (FfiTy::OutF64, Val::Int(n)) => cells.push((*n as f64).to_bits()),
+            (ty, v) => bail!("ERROR_BADARG ffi {} cannot pass {} as {:?}", symbol, v.type_name(), ty),
+        }
+    }
+    let num = |v: &Val| match v { Val::Float(x) => *x, Val::Int(n) => *n as f64, _ => 0.0 };
+    let int = |v: &Val| match v { Val::Int(n) => *n, _ => 0 };
+    let mut args = Vec::new();
+    let (mut bi, mut ci, mut si) = (0, 0, 0);
+    for (ty, v) in tys.iter().zip(vals) {
+        match ty {
+            FfiTy::I32 => args.push(FfiArg::I32(int(v) as i32)),
+            FfiTy::I64 => args.push(FfiArg::I64(int(v))),
+            FfiTy::Ptr => args.push(FfiArg::Ptr(int(v) as usize as *mut std::ffi::c_void)),
+            FfiTy::Bool => args.push(FfiArg::U8(matches!(v, Val::Bool(true)) as u8)),
+            FfiTy::F32 => args.push(FfiArg::F32(num(v) as f32)),
+            FfiTy::F64 => args.push(FfiArg::F64(num(v))),
+            FfiTy::Text => {
+                args.push(FfiArg::Ptr(cstrings[si].as_ptr() as *mut std::ffi::c_void));
+                si += 1;
+            }
+            FfiTy::Bytes | FfiTy::OutBytes => {
+                args.push(FfiArg::Ptr(bufs[bi].as_mut_ptr() as *mut std::ffi::c_void));
+                args.push(FfiArg::Usize(bufs[bi].len()));
+                bi += 1;
+            }
+            FfiTy::OutI64 | FfiTy::OutF64 => {
+                args.push(FfiArg::Ptr(&mut cells[ci] as *mut u64 as *mut std::ffi::c_void));
+                ci += 1;
+            }
+            FfiTy::Void => unreachable!("ffi_parse_sig rejects void arguments"),
+        }
+    }
+    let rv = FFI_LIBS.with(|libs| {
+        let libs = libs.borrow();
+        let lib = libs.get(handle).ok_or_else(|| anyhow!("ERROR_FFI handle not found: {}", handle))?;
+        ffi_invoke(lib, symbol, sig, &args)
+    })?;
+    if !tys.iter().any(|t| t.is_out()) {
+        return Ok(rv);
+    }
+    let (mut bufs, mut cells) = (bufs.into_iter(), cells.into_iter());
+    let mut outs = Vec::new();
+    for ty in tys.iter().filter(|t| t.is_out()) {
+        outs.push(match ty {
+            FfiTy::OutI64 => Val::Int(cells.next().unwrap() as i64),
+            FfiTy::OutF64 => Val::Float(f64::from_bits(cells.next().unwrap())),
+            _ => Val::Bytes(bufs.next().unwrap()),
+        });
+    }
+    let mut m = BTreeMap::new();
+    m.insert("ret".to_string(), rv);
+    m.insert("out".to_string(), Val::List(outs));
+    Ok(Val::Record(m))
+}
+
+/// The (handle, path) of a db or statement value from std/sqlite.
+fn sqlite_target(v: &Val, op: &str) -> Result<(String, String)> {
+    match v {
+        Val::Record(m) => match (m.get("handle"), m.get("path")) {
+            (Some(Val::Text(h)), Some(Val::Text(p))) if h.starts_with("sqlite:") => Ok((h.clone(), p.clone())),
+            _ => bail!("ERROR_BADARG {} expects a database from sqlite.open", op),
+        },
+        _ => bail!("ERROR_BADARG {} expects a database from sqlite.open", op),
+    }
+}
+
+fn sqlite_param(v: &Val, op: &str) -> Result<rusqlite::types::Value> {
+    use rusqlite::types::Value as SqlVal;
+    Ok(match v {
+        Val::Unit => SqlVal::Null,
+        Val::Int(n) => SqlVal::Integer(*n),
+        Val::Bool(b) => SqlVal::Integer(*b as i64),
+        Val::Float(f) => SqlVal::Real(*f),
+        Val::Text(s) => SqlVal::Text(s.clone()),
+        Val::Bytes(b) => SqlVal::Blob(b.clone()),
+        _ => bail!("ERROR_BADARG {} parameters must be null, int, bool, float, text or bytes", op),
+    })
+}
+
+fn sqlite_value(v: rusqlite::types::ValueRef<'_>) -> Val {
+    use rusqlite::types::ValueRef;
+    match v {
+        ValueRef::Null => Val::Unit,
+        ValueRef::Integer(n) => Val::Int(n),
+        ValueRef::Real(f) => Val::Float(f),
+        ValueRef::Text(t) => Val::Text(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => Val::Bytes(b.to_vec()),
+    }
+}
+
+/// Run one statement of std/sqlite and record it in the trace as a `sqlite` event carrying
+/// the digests of its parameters and of the result set (or the error). `params` is a list
+/// bound to `?1..`, a record bound by name, or `None` to run `sql` as a batch script.
+/// Returns a result value: the rows as records when `rows` is set, else `{changes, last_insert_id}`.
+fn sqlite_run(
+    tracer: &mut Tracer,
+    op: &str,
+    handle: &str,
+    path: &str,
+    sql: &str,
+    params: Option<&Val>,
+    rows: bool,
+) -> Result<Val> {
+    let params_json = params.and_then(|p| p.to_json()).unwrap_or(J::Null);
+    let (positional, named): (Vec<rusqlite::types::Value>, Vec<(String, rusqlite::types::Value)>) = match params {
+        None | Some(Val::Unit) => (Vec::new(), Vec::new()),
+        Some(Val::List(xs)) => (xs.iter().map(|x| sqlite_param(x, op)).collect::<Result<_>>()?, Vec::new()),
+        Some(Val::Record(m)) => {
+            let named = m
+                .iter()
+                .map(|(k, x)| {
+                    let k = if k.starts_with([':', '@', '$']) { k.clone() } else { format!(":{}", k) };
+                    Ok((k, sqlite_param(x, op)?))
+                })
+                .collect::<Result<_>>()?;
+            (Vec::new(), named)
+        }
+        Some(_) => bail!("ERROR_BADARG {} parameters must be a list or a record", op),
+    };
+    let db = SQLITE_DBS.with(|dbs| dbs.borrow().get(handle).cloned());
+    let outcome: std::result::Result<Val, String> = (|| {
+        let db = db.ok_or_else(|| format!("ERROR_SQLITE database is closed: {}", path))?;
+        let conn = db.lock().unwrap_or_else(|e| e.into_inner());
+        let err = |e: rusqlite::Error| format!("ERROR_SQLITE {}", e);
+        if params.is_none() && !rows {
+            conn.execute_batch(sql).map_err(err)?;
+        } else {
+            let mut stmt = conn.prepare_cached(sql).map_err(err)?;
+            if !named.is_empty() {
+                for (k, v) in &named {
+                    let idx = stmt
+                        .parameter_index(k)
+                        .map_err(err)?
+                        .ok_or_else(|| format!("ERROR_SQLITE no parameter {} in statement", k))?;
+                    stmt.raw_bind_parameter(idx, v).map_err(err)?;
+                }
+            } else {
+                if positional.len() != stmt.parameter_count() {
+                    return Err(format!(
+                        "ERROR_SQLITE statement expects {} parameter(s), got {}",
+                        stmt.parameter_count(),
+                        positional.len()
+                    ));
+                }
+                for (i, v) in positional.iter().enumerate() {
+                    stmt.raw_bind_parameter(i + 1, v).map_err(err)?;
+                }
+            }
+            if rows {
+                let names: Vec<String> = stmt.column_names().iter().map(|s| s.to_string()).collect();
+                let mut cursor = stmt.raw_query();
+                let mut out = Vec::new();
+                while let Some(row) = cursor.next().map_err(err)? {
+                    let mut rec = BTreeMap::new();
+                    for (i, name) in names.iter().enumerate() {
+                        rec.insert(name.clone(), sqlite_value(row.get_ref(i).map_err(err)?));
+                    }
+                    out.push(Val::Record(rec));
+                }
+                return Ok(Val::List(out));
+            }
+            stmt.raw_execute().map_err(err)?;
+        }
+        let mut m = BTreeMap::new();
+        m.insert("changes".to_string(), Val::Int(conn.changes() as i64));
+        m.insert("last_insert_id".to_string(), Val::Int(conn.last_insert_rowid()));
+        Ok(Val::Record(m))
+    })();
+
+    let mut ev = Map::new();
+    ev.insert("t".to_string(), J::Str("sqlite".to_string()));
+    ev.insert("op".to_string(), J::Str(op.to_string()));
+    ev.insert("db".to_string(), J::Str(path.to_string()));
+    ev.insert("sql".to_string(), J::Str(sql.to_string()));
+    ev.insert("params_digest".to_string(), J::Str(sha256_bytes(&canonical_json_bytes(&params_json))));
+    match &outcome {
+        Ok(v) => {
+            let digest = sha256_bytes(&canonical_json_bytes(&v.to_json().unwrap_or(J::Null)));
+            ev.insert("result_digest".to_string(), J::Str(digest));
+        }
+        Err(e) => {
+            ev.insert("err".to_string(), J::Str(e.clone()));
+        }
+    }
+    tracer.emit_event(J::Object(ev))?;
+    Ok(match outcome {
+        Ok(v) => mk_result_ok(v),
+        Err(e) => mk_result_err(Val::Text(e)),
+    })
+}
+
+/// An 8-bit image as std/png sees it: `channels` interleaved samples per pixel, row-major.
+struct PngImage {
+    width: usize,
+    height: usize,
+    channels: usize,
+    pixels: Vec<u8>,
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Adam7 passes as (x0, y0, dx, dy).
+const PNG_ADAM7: [(usize, usize, usize, usize); 7] =
+    [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+fn png_format_channels(format: &str) -> Option<usize> {
+    match format {
+        "gray" => Some(1),
+        "gray_alpha" => Some(2),
+        "rgb" => Some(3),
+        "rgba" => Some(4),
+        _ => None,
+    }
+}
+
+fn png_paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undoes the scanline filters of `rows` lines of `stride` bytes, each prefixed by its filter type.
+fn png_unfilter(data: &[u8], rows: usize, stride: usize, bpp: usize) -> Result<Vec<u8>> {
+    let mut out = vec![0u8; rows * stride];
+    for y in 0..rows {
+        let line = &data[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let (done, rest) = out.split_at_mut(y * stride);
+        let prev = if y == 0 { None } else { Some(&done[(y - 1) * stride..]) };
+        let cur = &mut rest[..stride];
+        for x in 0..stride {
+            let left = if x >= bpp { cur[x - bpp] } else { 0 };
+            let up = prev.map_or(0, |p| p[x]);
+            let up_left = if x >= bpp { prev.map_or(0, |p| p[x - bpp]) } else { 0 };
+            let raw = line[1 + x];
+            cur[x] = match line[0] {
+                0 => raw,
+                1 => raw.wrapping_add(left),
+                2 => raw.wrapping_add(up),
+                3 => raw.wrapping_add(((left as u16 + up as u16) / 2) as u8),
+                4 => raw.wrapping_add(png_paeth(left, up, up_left)),
+                f => bail!("ERROR_PNG bad filter type {}", f),
+            };
+        }
+    }
+    Ok(out)
+}
+
+/// Header fields and ancillary tables the scanline decoder needs.
+struct PngHeader {
+    color: u8,
+    depth: u8,
+    palette: Vec<[u8; 3]>,
+    trns: Vec<u8>,
+    channels: usize,
+}
+
+impl PngHeader {
+    fn samples(&self) -> usize {
+        match self.color {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn stride(&self, w: usize) -> usize {
+        (w * self.samples() * self.depth as usize).div_ceil(8)
+    }
+
+    /// Expands one unfiltered scanline of `w` pixels to 8-bit samples in `out`.
+    fn expand_row(&self, row: &[u8], w: usize, out: &mut Vec<u8>) -> Result<()> {
+        let depth = self.depth as usize;
+        let mask = ((1u16 << depth.min(8)) - 1) as u8;
+        let sample = |i: usize| -> u8 {
+            match depth {
+                8 => row[i],
+                16 => row[2 * i],
+                _ => {
+                    let per = 8 / depth;
+                    (row[i / per] >> (8 - depth * (i % per + 1))) & mask
+                }
+            }
+        };
+        for px in 0..w {
+            if self.color == 3 {
+                let idx = sample(px) as usize;
+                let rgb = self.palette.get(idx).ok_or_else(|| anyhow!("ERROR_PNG palette index {} out of range", idx))?;
+                out.extend_from_slice(rgb);
+                if self.channels == 4 {
+                    out.push(self.trns.get(idx).copied().unwrap_or(255));
+                }
+                continue;
+            }
+            let n = self.samples();
+            for s in 0..n {
+                let v = sample(px * n + s);
+                out.push(if depth < 8 { (v as u16 * 255 / mask as u16) as u8 } else { v });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn png_decode(bytes: &[u8]) -> Result<PngImage> {
+    use std::io::Read;
+    if bytes.len() < 8 || bytes[..8] != PNG_SIGNATURE {
+        bail!("ERROR_PNG not a png (bad signature)");
+    }
+    let mut pos = 8;
+    let mut dims: Option<(usize, usize, u8)> = None;
+    let mut hdr = PngHeader { color: 0, depth: 8, palette: Vec::new(), trns: Vec::new(), channels: 0 };
+    let mut idat: Vec<u8> = Vec::new();
+    let mut ended = false;
+    while pos + 12 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let end = pos.checked_add(12 + len).filter(|e| *e <= bytes.len())
+            .ok_or_else(|| anyhow!("ERROR_PNG truncated chunk"))?;
+        let kind = &bytes[pos + 4..pos + 8];
+        let data = &bytes[pos + 8..pos + 8 + len];
+        let crc = u32::from_be_bytes(bytes[end - 4..end].try_into().unwrap());
+        if crc32fast::hash(&bytes[pos + 4..end - 4]) != crc {
+            bail!("ERROR_PNG bad crc in {} chunk", String::from_utf8_lossy(kind));
+        }
+        match kind {
+            b"IHDR" => {
+                if len != 13 {
+                    bail!("ERROR_PNG bad IHDR");
+                }
+                let w = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+                let h = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                hdr.depth = data[8];
+                hdr.color = data[9];
+                let ok = matches!(
+                    (hdr.color, hdr.depth),
+                    (0, 1 | 2 | 4 | 8 | 16) | (2 | 4 | 6, 8 | 16) | (3, 1 | 2 | 4 | 8)
+                );
+                if !ok || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+                    bail!("ERROR_PNG unsupported header (color type {}, bit depth {})", hdr.color, hdr.depth);
+                }
+                if w == 0 || h == 0 {
+                    bail!("ERROR_PNG empty image");
+                }
+                dims = Some((w, h, data[12]));
+            }
+            b"PLTE" => hdr.palette = data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+            b"tRNS" => hdr.trns = data.to_vec(),
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => {
+                ended = true;
+                break;
+            }
+            _ if kind[0] & 0x20 == 0 => bail!("ERROR_PNG unknown critical chunk {}", String::from_utf8_lossy(kind)),
+            _ => {}
+        }
+        pos = end;
+    }
+    let (w, h, interlace) = dims.ok_or_else(|| anyhow!("ERROR_PNG missing IHDR"))?;
+    if !ended {
+        bail!("ERROR_PNG truncated (no IEND)");
+    }
+    if hdr.color == 3 && hdr.palette.is_empty() {
+        bail!("ERROR_PNG missing PLTE");
+    }
+    hdr.channels = match hdr.color {
+        3 if !hdr.trns.is_empty() => 4,
+        c => [1, 0, 3, 3, 2, 0, 4][c as usize],
+    };
+    let bpp = (hdr.samples() * hdr.depth as usize).div_ceil(8);
+    let passes: Vec<(usize, usize, usize, usize)> = if interlace == 1 { PNG_ADAM7.to_vec() } else { vec![(0, 0, 1, 1)] };
+    let mut need = 0usize;
+    for (x0, y0, dx, dy) in &passes {
+        let (pw, ph) = ((w + dx - 1 - x0) / dx, (h + dy - 1 - y0) / dy);
+        if pw > 0 && ph > 0 {
+            need = need.checked_add(ph * (hdr.stride(pw) + 1)).ok_or_else(|| anyhow!("ERROR_PNG image too large"))?;
+        }
+    }
+    let mut raw = Vec::with_capacity(need);
+    flate2::read::ZlibDecoder::new(idat.as_slice())
+        .take(need as u64)
+        .read_to_end(&mut raw)
+        .map_err(|e| anyhow!("ERROR_PNG bad image data: {}", e))?;
+    if raw.len() < need {
+        bail!("ERROR_PNG truncated image data");
+    }
+    let mut pixels = vec![0u8; w * h * hdr.channels];
+    let mut at = 0;
+    let mut row = Vec::new();
+    for (x0, y0, dx, dy) in passes {
+        let (pw, ph) = ((w + dx - 1 - x0) / dx, (h + dy - 1 - y0) / dy);
+        if pw == 0 || ph == 0 {
+            continue;
+        }
+        let stride = hdr.stride(pw);
+        let lines = png_unfilter(&raw[at..at + ph * (stride + 1)], ph, stride, bpp)?;
+        at += ph * (stride + 1);
+        for py in 0..ph {
+            row.clear();
+            hdr.expand_row(&lines[py * stride..(py + 1) * stride], pw, &mut row)?;
+            for px in 0..pw {
+                let dst = ((y0 + py * dy) * w + x0 + px * dx) * hdr.channels;
+                pixels[dst..dst + hdr.channels].copy_from_slice(&row[px * hdr.channels..(px + 1) * hdr.channels]);
+            }
+        }
+    }
+    Ok(PngImage { width: w, height: h, channels: hdr.channels, pixels })
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32fast::hash(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Encodes 8-bit, non-interlaced, with no ancillary chunks. Each row takes the filter with the
+/// smallest sum of absolute filtered values, and zlib runs at a fixed level on the pure-Rust
+/// backend, so the same image always encodes to the same bytes.
+fn png_encode(img: &PngImage) -> Result<Vec<u8>> {
+    use std::io::Write;
+    let color: u8 = match img.channels {
+        1 => 0,
+        2 => 4,
+        3 => 2,
+        4 => 6,
+        n => bail!("ERROR_PNG cannot encode {} channels", n),
+    };
+    let (bpp, stride) = (img.channels, img.width * img.channels);
+    let mut raw = Vec::with_capacity(img.height * (stride + 1));
+    let zero = vec![0u8; stride];
+    let mut cand = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    for y in 0..img.height {
+        let cur = &img.pixels[y * stride..(y + 1) * stride];
+        let prev = if y == 0 { &zero[..] } else { &img.pixels[(y - 1) * stride..y * stride] };
+        let (mut best_ft, mut best_score) = (0u8, u64::MAX);
+        for ft in 0..5u8 {
+            for x in 0..stride {
+                let left = if x >= bpp { cur[x - bpp] } else { 0 };
+                let up_left = if x >= bpp { prev[x - bpp] } else { 0 };
+                cand[x] = cur[x].wrapping_sub(match ft {
+                    0 => 0,
+                    1 => left,
+                    2 => prev[x],
+                    3 => ((left as u16 + prev[x] as u16) / 2) as u8,
+                    _ => png_paeth(left, prev[x], up_left),
+                });
+            }
+            let score: u64 = cand.iter().map(|b| (*b as i8).unsigned_abs() as u64).sum();
+            if score < best_score {
+                (best_ft, best_score) = (ft, score);
+                best.copy_from_slice(&cand);
+            }
+        }
+        raw.push(best_ft);
+        raw.extend_from_slice(&best);
+    }
+    let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::new(9));
+    z.write_all(&raw).map_err(|e| anyhow!("ERROR_PNG deflate: {}", e))?;
+    let idat = z.finish().map_err(|e| anyhow!("ERROR_PNG deflate: {}", e))?;
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(img.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(img.height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, color, 0, 0, 0]);
+    let mut out = PNG_SIGNATURE.to_vec();
+    png_chunk(&mut out, b"IHDR", &ihdr);
+    png_chunk(&mut out, b"IDAT", &idat);
+    png_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+/// Converts between gray, gray_alpha, rgb and rgba. Gray is integer Rec. 601 luma; dropping
+/// alpha discards it and adding alpha makes the image opaque.
+fn png_convert(img: &PngImage, channels: usize) -> PngImage {
+    let mut pixels = Vec::with_capacity(img.width * img.height * channels);
+    for px in img.pixels.chunks_exact(img.channels) {
+        let (color, alpha) = match img.channels {
+            1 | 2 => (&px[..1], px.get(1).copied()),
+            _ => (&px[..3], px.get(3).copied()),
+        };
+        match (channels, color.len()) {
+            (1 | 2, 3) => {
+                let luma = (299 * color[0] as u32 + 587 * color[1] as u32 + 114 * color[2] as u32 + 500) / 1000;
+                pixels.push(luma as u8);
+            }
+            (3 | 4, 1) => pixels.extend_from_slice(&[color[0]; 3]),
+            _ => pixels.extend_from_slice(color),
+        }
+        if channels == 2 || channels == 4 {
+            pixels.push(alpha.unwrap_or(255));
+        }
+    }
+    PngImage { width: img.width, height: img.height, channels, pixels }
+}
+
+fn png_image_val(img: PngImage, cid: Option<String>) -> Val {
+    let mut m = BTreeMap::new();
+    m.insert("width".to_string(), Val::Int(img.width as i64));
+    m.insert("height".to_string(), Val::Int(img.height as i64));
+    m.insert("channels".to_string(), Val::Int(img.channels as i64));
+    m.insert("pixels".to_string(), Val::Bytes(img.pixels));
+    if let Some(cid) = cid {
+        m.insert("cid".to_string(), Val::Text(cid));
+    }
+    Val::Record(m)
+}
+
+/// Reads an image record; `pixels` may be bytes or a list of ints in 0..=255.
+fn png_image_from_val(v: &Val, op: &str) -> Result<PngImage> {
+    let m = match v {
+        Val::Record(m) => m,
+        _ => bail!("ERROR_BADARG {} expects an image record {{width, height, channels, pixels}}", op),
+    };
+    let dim = |k: &str| -> Result<usize> {
+        match m.get(k) {
+            Some(Val::Int(n)) if *n > 0 && *n <= u32::MAX as i64 => Ok(*n as usize),
+            _ => bail!("ERROR_BADARG {} image {} must be a positive int", op, k),
+        }
+    };
+    let (width, height, channels) = (dim("width")?, dim("height")?, dim("channels")?);
+    if channels > 4 {
+        bail!("ERROR_BADARG {} image channels must be 1 to 4", op);
+    }
+    let pixels = match m.get("pixels") {
+        Some(Val::Bytes(b)) => b.clone(),
+        Some(Val::List(xs)) => xs.iter().map(|x| match x {
+            Val::Int(n) if (0..=255).contains(n) => Ok(*n as u8),
+            _ => bail!("ERROR_BADARG {} pixel samples must be ints in 0..=255", op),
+        }).collect::<Result<Vec<u8>>>()?,
+        _ => bail!("ERROR_BADARG {} image pixels must be bytes or a list of ints", op),
+    };
+    let want = width.checked_mul(height).and_then(|n| n.checked_mul(channels))
+        .ok_or_else(|| anyhow!("ERROR_BADARG {} image too large", op))?;
+    if pixels.len() != want {
+        bail!("ERROR_BADARG {} expects {} pixel samples ({}x{}x{}), got {}", op, want, width, height, channels, pixels.len());
+    }
+    Ok(PngImage { width, height, channels, pixels })
+}
+
+/// Byte offset of pixel (x, y), checked against the image bounds.
+fn png_offset(img: &PngImage, x: &Val, y: &Val, op: &str) -> Result<usize> {
+    match (x, y) {
+        (Val::Int(x), Val::Int(y)) if *x >= 0 && *y >= 0 && (*x as usize) < img.width && (*y as usize) < img.height => {
+            Ok((*y as usize * img.width + *x as usize) * img.channels)
+        }
+        (Val::Int(x), Val::Int(y)) => bail!("ERROR_OOB {} ({}, {}) outside {}x{}", op, x, y, img.width, img.height),
+        _ => bail!("ERROR_BADARG {} x and y must be ints", op),
+    }
+}
+
+/// Registers input bytes as an `artifact_in` under `name`, once per name.
+fn register_input(tracer: &mut Tracer, name: &str, bytes: &[u8]) -> Result<String> {
+    let cid = sha256_bytes(bytes);
+    match tracer.artifact_cids.get(name) {
+        Some(prev) if *prev == cid => {}
+        Some(prev) => bail!("ERROR_M3_ARTIFACT_CID_MISMATCH {}: {} vs {}", name, prev, cid),
+        None => tracer.artifact_in(name, &cid)?,
+    }
+    Ok(cid)
+}
+
+/// `data` of a compress or archive call: text is taken as its UTF-8 bytes.
+fn compress_input(v: &Val, op: &str) -> Result<Vec<u8>> {
+    match v {
+        Val::Bytes(b) => Ok(b.clone()),
+        Val::Text(s) => Ok(s.as_bytes().to_vec()),
+        _ => bail!("ERROR_BADARG {} expects bytes or text", op),
+    }
+}
+
+fn compress_level(args: &[Val], op: &str, range: std::ops::RangeInclusive<i64>, default: i64) -> Result<i64> {
+    match args.get(1) {
+        None => Ok(default),
+        Some(Val::Int(n)) if range.contains(n) => Ok(*n),
+        Some(_) => bail!("ERROR_BADARG {} level must be an int from {} to {}", op, range.start(), range.end()),
+    }
+}
+
+/// What one decompress or extract call may produce unless its options raise or lower `max_bytes`.
+const COMPRESS_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// `(data[, {max_bytes}])` of a decompress or extract call.
+fn decompress_args<'a>(args: &'a [Val], op: &str) -> Result<(&'a [u8], u64)> {
+    match args {
+        [Val::Bytes(data)] => Ok((data, COMPRESS_MAX_BYTES)),
+        [Val::Bytes(data), Val::Record(o)] => match o.get("max_bytes") {
+            None => Ok((data, COMPRESS_MAX_BYTES)),
+            Some(Val::Int(n)) if *n >= 0 => Ok((data, *n as u64)),
+            Some(_) => bail!("ERROR_BADARG {} max_bytes must be a non-negative int", op),
+        },
+        _ => bail!("ERROR_BADARG {} expects (bytes[, {{max_bytes}}])", op),
+    }
+}
+
+/// Appends all of `r` to `out`, failing once more than `budget` bytes have been read in total.
+fn read_capped(r: impl std::io::Read, out: &mut Vec<u8>, budget: &mut u64) -> std::io::Result<()> {
+    use std::io::Read;
+    let start = out.len();
+    r.take(budget.saturating_add(1)).read_to_end(out)?;
+    let n = (out.len() - start) as u64;
+    if n > *budget {
+        return Err(std::io::Error::other("output exceeds max_bytes"));
+    }
+    *budget -= n;
+    Ok(())
+}
+
+/// The `[{name, data}]` entries of `compress.tar_create` / `compress.zip_create`.
+fn archive_entries(v: &Val, op: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let Val::List(xs) = v else { bail!("ERROR_BADARG {} expects a list of {{name, data}}", op) };
+    xs.iter()
+        .map(|x| match x {
+            Val::Record(m) => match (m.get("name"), m.get("data")) {
+                (Some(Val::Text(n)), Some(d)) if !n.is_empty() => Ok((n.clone(), compress_input(d, op)?)),
+                _ => bail!("ERROR_BADARG {} entries need a text name and bytes or text data", op),
+            },
+            _ => bail!("ERROR_BADARG {} expects a list of {{name, data}}", op),
+        })
+        .collect()
+}
+
+/// Entry records of an extracted archive, each registered as an `artifact_in` under its CID.
+fn archive_extracted(tracer: &mut Tracer, files: Vec<(String, Vec<u8>)>) -> Result<Val> {
+    let mut out = Vec::with_capacity(files.len());
+    for (name, data) in files {
+        let cid = register_input(tracer, &sha256_bytes(&data), &data)?;
+        let mut m = BTreeMap::new();
+        m.insert("name".to_string(), Val::Text(name));
+        m.insert("size".to_string(), Val::Int(data.len() as i64));
+        m.insert("cid".to_string(), Val::Text(cid));
+        m.insert("data".to_string(), Val::Bytes(data));
+        out.push(Val::Record(m));
+    }
+    Ok(Val::List(out))
+}
+
+/// A tar of regular files with fixed metadata (mode 0644, uid/gid 0, mtime 0).
+fn tar_create(entries: &[(String, Vec<u8>)]) -> std::io::Result<Vec<u8>> {
+    let mut b = tar::Builder::new(Vec::new());
+    for (name, data) in entries {
+        let mut h = tar::Header::new_gnu();
+        h.set_entry_type(tar::EntryType::Regular);
+        h.set_size(data.len() as u64);
+        h.set_mode(0o644);
+        h.set_uid(0);
+        h.set_gid(0);
+        h.set_mtime(0);
+        b.append_data(&mut h, name, data.as_slice())?;
+    }
+    b.into_inner()
+}
+
+/// The regular files of a tar, in archive order, holding at most `max_bytes` in total.
+fn tar_extract(bytes: &[u8], mut max_bytes: u64) -> std::io::Result<Vec<(String, Vec<u8>)>> {
+    let mut a = tar::Archive::new(bytes);
+    let mut files = Vec::new();
+    for e in a.entries()? {
+        let mut e = e?;
+        if !e.header().entry_type().is_file() {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&e.path_bytes()).into_owned();
+        let mut data = Vec::new();
+        read_capped(&mut e, &mut data, &mut max_bytes)?;
+        files.push((name, data));
+    }
+    Ok(files)
+}
+
+/// A zip with fixed timestamps (1980-01-01) and mode 0644.
+fn zip_create(entries: &[(String, Vec<u8>)], method: zip::CompressionMethod) -> zip::result::ZipResult<Vec<u8>> {
+    use std::io::Write;
+    let mut w = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let opts = zip::write::SimpleFileOptions::default()
+        .compression_method(method)
+        .last_modified_time(zip::DateTime::default())
+        .unix_permissions(0o644);
+    for (name, data) in entries {
+        w.start_file(name.as_str(), opts)?;
+        w.write_all(data)?;
+    }
+    Ok(w.finish()?.into_inner())
+}
+
+/// The files of a zip, in central-directory order, holding at most `max_bytes` in total.
+fn zip_extract(bytes: &[u8], mut max_bytes: u64) -> zip::result::ZipResult<Vec<(String, Vec<u8>)>> {
+    let mut a = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    let mut files = Vec::new();
+    for i in 0..a.len() {
+        let mut f = a.by_index(i)?;
+        if f.is_dir() {
+            continue;
+        }
+        let mut data = Vec::with_capacity(f.size().min(max_bytes) as usize);
+        read_capped(&mut f, &mut data, &mut max_bytes)?;
+        files.push((f.name().to_string(), data));
+    }
+    Ok(files)
+}
+
+const NET_RECEIPT_KIND: &str = "fard/net_receipt/v1";
+
+/// Settings of one `net.listen` server, shared by its workers.
+struct NetConfig {
+    id: String,
+    handler: Val,
+    max_body: usize,
+    max_response: usize,
+    timeout: Option<std::time::Duration>,
+    /// `out/net/<n>` when per-request receipts are on
+    receipts: Option<PathBuf>,
+    out_dir: PathBuf,
+}
+
+/// A running `net.listen` server.
+struct NetServer {
+    server: Arc<tiny_http::Server>,
+    stop: Arc<std::sync::atomic::AtomicBool>,
+    workers: Vec<std::thread::JoinHandle<()>>,
+    seq: Arc<std::sync::atomic::AtomicU64>,
+    /// Receipt digests by request sequence number
+    digests: Arc<Mutex<BTreeMap<u64, String>>>,
+}
+
+/// Servers started by `net.listen`, by handle; any task may stop them.
+static NET_SERVERS: Mutex<BTreeMap<String, NetServer>> = Mutex::new(BTreeMap::new());
+static NET_SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Decodes `a=1&b=x%20y` into a record; later duplicates win.
+fn net_query(q: &str) -> BTreeMap<String, Val> {
+    fn unescape(s: &str) -> String {
+        let b = s.as_bytes();
+        let mut out = Vec::with_capacity(b.len());
+        let mut i = 0;
+        while i < b.len() {
+            match b[i] {
+                b'+' => out.push(b' '),
+                b'%' if i + 2 < b.len() && b[i + 1].is_ascii_hexdigit() && b[i + 2].is_ascii_hexdigit() => {
+                    out.push(u8::from_str_radix(&s[i + 1..i + 3], 16).unwrap_or(b'%'));
+                    i += 2;
+                }
+                c => out.push(c),
+            }
+            i += 1;
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+    q.split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            (unescape(k), Val::Text(unescape(v)))
+        })
+        .collect()
+}
+
+/// Trace of one request: `req_<seq>.ndjson` beside its receipt, or discarded.
+fn net_request_tracer(cfg: &NetConfig, seq: u64) -> Result<(Tracer, Option<PathBuf>)> {
+    let path = match &cfg.receipts {
+        Some(dir) => dir.join(format!("req_{}.ndjson", seq)),
+        None => PathBuf::from("/dev/null"),
+    };
+    let w = fs::File::create(&path)
+        .or_else(|_| fs::File::create(std::env::temp_dir().join("fard_net_trace.ndjson")))?;
+    let tracer = Tracer {
+        first_event: true,
+        artifact_cids: BTreeMap::new(),
+        w,
+        out_dir: cfg.out_dir.clone(),
+        spawn_prefix: String::new(),
+        spawn_seq: 0,
+        pending_receipts: Default::default(),
+    };
+    Ok((tracer, cfg.receipts.as_ref().map(|_| path)))
+}
+
+/// A response body: bytes in hand, or a function called for each chunk until it returns null.
+enum NetBody {
+    Bytes(Vec<u8>),
+    Stream(Val),
+}
+
+/// Status, headers and body of a handler's return value. A record with `status` or `body`
+/// is a response; any other value is the body of a 200.
+fn net_response(v: Val) -> Result<(u16, BTreeMap<String, String>, NetBody)> {
+    let (status, headers, body) = match v {
+        Val::Record(mut m) if m.contains_key("status") || m.contains_key("body") => {
+            let status = match m.get("status") {
+                None => 200,
+                Some(Val::Int(n)) if (100..=999).contains(n) => *n as u16,
+                Some(_) => bail!("ERROR_NET response status must be an int from 100 to 999"),
+            };
+            let mut headers = BTreeMap::new();
+            if let Some(Val::Record(hm)) = m.get("headers") {
+                for (k, v) in hm {
+                    let v = match v {
+                        Val::Text(s) => s.clone(),
+                        Val::Int(n) => n.to_string(),
+                        _ => bail!("ERROR_NET response header {} must be text", k),
+                    };
+                    headers.insert(k.to_lowercase(), v);
+                }
+            }
+            (status, headers, m.remove("body").unwrap_or(Val::Unit))
+        }
+        other => (200, BTreeMap::new(), other),
+    };
+    let (ct, body) = match body {
+        Val::Unit => ("text/plain; charset=utf-8", NetBody::Bytes(Vec::new())),
+        Val::Text(s) => ("text/plain; charset=utf-8", NetBody::Bytes(s.into_bytes())),
+        Val::Bytes(b) => ("application/octet-stream", NetBody::Bytes(b)),
+        f @ (Val::Func(_) | Val::VmFunc(_) | Val::Builtin(_) | Val::BoundMethod(..)) => {
+            ("application/octet-stream", NetBody::Stream(f))
+        }
+        other => match other.to_json() {
+            Some(j) => ("application/json", NetBody::Bytes(json_to_string(&j).into_bytes())),
+            None => bail!("ERROR_NET response body must be text, bytes, a function or JSON"),
+        },
+    };
+    let mut headers = headers;
+    headers.entry("content-type".to_string()).or_insert_with(|| ct.to_string());
+    Ok((status, headers, body))
+}
+
+/// Reads a streamed body by calling its function for each chunk.
+struct NetStream<'a> {
+    next: Val,
+    tracer: &'a mut Tracer,
+    loader: &'a mut ModuleLoader,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+    sent: usize,
+    limit: usize,
+    hash: NativeSha256,
+    error: Option<String>,
+}
+
+impl std::io::Read for NetStream<'_> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let chunk = match call(self.next.clone(), vec![], self.tracer, self.loader) {
+                Ok(Val::Unit) => {
+                    self.done = true;
+                    continue;
+                }
+                Ok(Val::Text(s)) => s.into_bytes(),
+                Ok(Val::Bytes(b)) => b,
+                Ok(v) => Err(format!("ERROR_NET stream chunk must be text, bytes or null, got {}", v.type_name()))
+                    .map_err(|e| self.fail(e))?,
+                Err(e) => return Err(self.fail(format!("{:#}", e))),
+            };
+            self.sent += chunk.len();
+            if self.sent > self.limit {
+                return Err(self.fail(format!("ERROR_NET response exceeds max_response ({} bytes)", self.limit)));
+            }
+            self.hash.update(&chunk);
+            (self.buf, self.pos) = (chunk, 0);
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl NetStream<'_> {
+    fn fail(&mut self, e: String) -> std::io::Error {
+        self.done = true;
+        self.error = Some(e.clone());
+        std::io::Error::other(e)
+    }
+}
+
+fn net_plain(status: u16, msg: &str) -> (u16, BTreeMap<String, String>, NetBody) {
+    let headers = BTreeMap::from([("content-type".to_string(), "text/plain; charset=utf-8".to_string())]);
+    (status, headers, NetBody::Bytes(msg.as_bytes().to_vec()))
+}
+
+fn net_headers_json(h: &BTreeMap<String, String>) -> J {
+    J::Object(h.iter().map(|(k, v)| (k.clone(), J::Str(v.clone()))).collect())
+}
+
+fn net_response_digest(status: u16, headers: &BTreeMap<String, String>, body_digest: &str) -> String {
+    let mut m = Map::new();
+    m.insert("status".to_string(), J::Int(status as i64));
+    m.insert("headers".to_string(), net_headers_json(headers));
+    m.insert("body".to_string(), J::Str(body_digest.to_string()));
+    sha256_bytes(&canonical_json_bytes(&J::Object(m)))
+}
+
+/// Runs the handler on `req` on this worker. With a timeout the handler runs under a
+/// `DEADLINE`, so it stops at its next call once the time is up and is answered with a 504.
+fn net_call_handler(cfg: &NetConfig, loader: &mut ModuleLoader, tracer: &mut Tracer, req: Val) -> Result<Val, (u16, String)> {
+    let deadline = cfg.timeout.map(|t| std::time::Instant::now() + t);
+    let saved = DEADLINE.with(|d| std::mem::replace(&mut *d.borrow_mut(), deadline));
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call(cfg.handler.clone(), vec![req], tracer, loader)));
+    DEADLINE.with(|d| *d.borrow_mut() = saved);
+    match (r, cfg.timeout) {
+        (_, Some(limit)) if deadline.is_some_and(|d| std::time::Instant::now() >= d) => {
+            Err((504, format!("ERROR_NET handler timed out after {} ms", limit.as_millis())))
+        }
+        (Ok(r), _) => r.map_err(|e| (500, format!("{:#}", e))),
+        (Err(_), _) => Err((500, "ERROR_RUNTIME handler panicked".to_string())),
+    }
+}
+
+/// Serves one request. With receipts on it writes `req_<seq>.json`, binding the request
+/// digest to the response digest and the digest of the handler's trace, and returns the
+/// receipt's own digest.
+fn net_handle(cfg: &NetConfig, loader: &mut ModuleLoader, seq: u64, mut rq: tiny_http::Request) -> Option<String> {
+    use std::io::Read;
+    let method = rq.method().as_str().to_string();
+    let url = rq.url().to_string();
+    let mut headers = BTreeMap::new();
+    for h in rq.headers() {
+        headers.insert(h.field.as_str().as_str().to_lowercase(), h.value.as_str().to_string());
+    }
+    let mut body = Vec::new();
+    let too_big = rq.body_length().is_some_and(|n| n > cfg.max_body)
+        || rq.as_reader().take(cfg.max_body as u64 + 1).read_to_end(&mut body).is_err()
+        || body.len() > cfg.max_body;
+    let mut req = Map::new();
+    req.insert("method".to_string(), J::Str(method.clone()));
+    req.insert("url".to_string(), J::Str(url.clone()));
+    req.insert("headers".to_string(), net_headers_json(&headers));
+    req.insert("body".to_string(), J::Str(sha256_bytes(&body)));
+    let request_digest = sha256_bytes(&canonical_json_bytes(&J::Object(req)));
+
+    let (mut tracer, trace_path) = match net_request_tracer(cfg, seq) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("[fard] {}: cannot open request trace: {}", cfg.id, e);
+            return None;
+        }
+    };
+    let mut error: Option<String> = None;
+    let (status, resp_headers, resp_body) = if too_big {
+        net_plain(413, &format!("request body exceeds max_body ({} bytes)", cfg.max_body))
+    } else {
+        let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+        let mut r = BTreeMap::new();
+        r.insert("method".to_string(), Val::Text(method.clone()));
+        r.insert("path".to_string(), Val::Text(path.to_string()));
+        r.insert("url".to_string(), Val::Text(url.clone()));
+        r.insert("query".to_string(), Val::Record(net_query(query)));
+        r.insert(
+            "headers".to_string(),
+            Val::Record(headers.iter().map(|(k, v)| (k.clone(), Val::Text(v.clone()))).collect()),
+        );
+        r.insert("body".to_string(), match String::from_utf8(body) {
+            Ok(s) => Val::Text(s),
+            Err(e) => Val::Bytes(e.into_bytes()),
+        });
+        let outcome = net_call_handler(cfg, loader, &mut tracer, Val::Record(r))
+            .and_then(|v| net_response(v).map_err(|e| (500, format!("{:#}", e))));
+        match outcome {
+            Ok((status, h, NetBody::Bytes(b))) if b.len() > cfg.max_response => {
+                let _ = (status, h);
+                let msg = format!("ERROR_NET response exceeds max_response ({} bytes)", cfg.max_response);
+                eprintln!("[fard] {}: {}", cfg.id, msg);
+                error = Some(msg.clone());
+                net_plain(500, &msg)
+            }
+            Ok(r) => r,
+            Err((status, e)) => {
+                eprintln!("[fard] {} handler error: {}", cfg.id, e);
+                let shown = if status == 504 { e.clone() } else { format!("handler error: {}", e) };
+                error = Some(e);
+                net_plain(status, &shown)
+            }
+        }
+    };
+
+    let mut hdrs = Vec::new();
+    for (k, v) in &resp_headers {
+        match tiny_http::Header::from_bytes(k.as_bytes(), v.as_bytes()) {
+            Ok(h) => hdrs.push(h),
+            Err(_) => eprintln!("[fard] {}: dropping invalid response header {}", cfg.id, k),
+        }
+    }
+    let body_digest = match resp_body {
+        NetBody::Stream(f) => {
+            let mut stream = NetStream {
+                next: f,
+                tracer: &mut tracer,
+                loader,
+                buf: Vec::new(),
+                pos: 0,
+                done: false,
+                sent: 0,
+                limit: cfg.max_response,
+                hash: NativeSha256::new(),
+                error: None,
+            };
+            let sent = rq.respond(tiny_http::Response::new(status.into(), hdrs, &mut stream, None, None));
+            if let Some(e) = stream.error.take() {
+                eprintln!("[fard] {} stream error: {}", cfg.id, e);
+                error.get_or_insert(e);
+            } else if let Err(e) = sent {
+                error.get_or_insert(format!("ERROR_NET send failed: {}", e));
+            }
+            format!("sha256:{}", hex_lower(&stream.hash.finalize()))
+        }
+        NetBody::Bytes(b) => {
+            let digest = sha256_bytes(&b);
+            let len = b.len();
+            if let Err(e) = rq.respond(tiny_http::Response::new(status.into(), hdrs, std::io::Cursor::new(b), Some(len), None)) {
+                error.get_or_insert(format!("ERROR_NET send failed: {}", e));
+            }
+            digest
+        }
+    };
+    let _ = std::io::Write::flush(&mut tracer.w);
+
+    let (dir, trace_path) = (cfg.receipts.as_ref()?, trace_path?);
+    let mut m = Map::new();
+    m.insert("kind".to_string(), J::Str(NET_RECEIPT_KIND.to_string()));
+    m.insert("server".to_string(), J::Str(cfg.id.clone()));
+    m.insert("seq".to_string(), J::Int(seq as i64));
+    m.insert("method".to_string(), J::Str(method));
+    m.insert("url".to_string(), J::Str(url));
+    m.insert("status".to_string(), J::Int(status as i64));
+    m.insert("request_digest".to_string(), J::Str(request_digest));
+    m.insert("response_digest".to_string(), J::Str(net_response_digest(status, &resp_headers, &body_digest)));
+    m.insert("trace_digest".to_string(), J::Str(file_digest(&trace_path).unwrap_or_default()));
+    if let Some(e) = error {
+        m.insert("error".to_string(), J::Str(e));
+    }
+    let bytes = canonical_json_bytes(&J::Object(m));
+    if let Err(e) = fs::write(dir.join(format!("req_{}.json", seq)), &bytes) {
+        eprintln!("[fard] {}: cannot write receipt: {}", cfg.id, e);
+    }
+    Some(sha256_bytes(&bytes))
+}
+
+fn net_worker(
+    cfg: Arc<NetConfig>,
+    server: Arc<tiny_http::Server>,
+    stop: Arc<std::sync::atomic::AtomicBool>,
+    seq: Arc<std::sync::atomic::AtomicU64>,
+    digests: Arc<Mutex<BTreeMap<u64, String>>>,
+    ctx: ChildCtx,
+    mut loader: ModuleLoader,
+) {
+    use std::sync::atomic::Ordering;
+    let _ = ctx.enter();
+    loop {
+        // Once stopping, drain what the server already accepted, then exit.
+        let stopping = stop.load(Ordering::SeqCst);
+        let next = if stopping {
+            server.try_recv()
+        } else {
+            server.recv_timeout(std::time::Duration::from_millis(50))
+        };
+        match next {
+            Ok(Some(rq)) => {
+                let n = seq.fetch_add(1, Ordering::SeqCst) + 1;
+                if let Some(d) = net_handle(&cfg, &mut loader, n, rq) {
+                    digests.lock().unwrap().insert(n, d);
+                }
+            }
+            Ok(None) if stopping => break,
+            Ok(None) => {}
+            Err(_) => break,
+        }
+    }
+}
+
+/// Binds a server from `net.listen` options and starts its workers.
+fn net_start(tracer: &mut Tracer, loader: &ModuleLoader, opts: &BTreeMap<String, Val>, op: &str) -> Result<std::result::Result<(String, String, NetServer), String>> {
+    let int = |k: &str, default: usize| -> Result<usize> {
+        match opts.get(k) {
+            None => Ok(default),
+            Some(Val::Int(n)) if *n > 0 => Ok(*n as usize),
+            Some(_) => bail!("ERROR_BADARG {} {} must be a positive int", op, k),
+        }
+    };
+    let handler = match opts.get("handler") {
+        Some(f @ (Val::Func(_) | Val::VmFunc(_) | Val::Builtin(_) | Val::BoundMethod(..))) => f.clone(),
+        _ => bail!("ERROR_BADARG {} expects a handler function", op),
+    };
+    let (host, port) = net_opt_bind(opts, op)?;
+    let workers = int("workers", 4)?.min(256);
+    let timeout = net_opt_timeout(opts, op)?;
+    let receipts = matches!(opts.get("receipts"), Some(Val::Bool(true)));
+    cap_check(tracer, "listen", op, &port.to_string())?;
+
+    let bind = format!("{}:{}", host, port);
+    let server = match tiny_http::Server::http(&bind) {
+        Ok(s) => Arc::new(s),
+        Err(e) => return Ok(Err(format!("ERROR_NET {} cannot bind {}: {}", op, bind, e))),
+    };
+    let addr = server.server_addr().to_string();
+    let id = format!("net:{}", NET_SEQ.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1);
+    let receipts = if receipts {
+        let dir = tracer.out_dir.join("net").join(id.trim_start_matches("net:"));
+        fs::create_dir_all(&dir)?;
+        Some(dir)
+    } else {
+        None
+    };
+    let cfg = Arc::new(NetConfig {
+        id: id.clone(),
+        handler,
+        max_body: int("max_body", 1 << 20)?,
+        max_response: int("max_response", 16 << 20)?,
+        timeout,
+        receipts,
+        out_dir: tracer.out_dir.clone(),
+    });
+    let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let seq = Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let digests = Arc::new(Mutex::new(BTreeMap::new()));
+    let mut handles = Vec::with_capacity(workers);
+    for i in 0..workers {
+        let (cfg, server, stop, seq, digests) = (cfg.clone(), server.clone(), stop.clone(), seq.clone(), digests.clone());
+        let (ctx, wl) = (ChildCtx::capture(&id), loader.fork());
+        handles.push(
+            std::thread::Builder::new()
+                .name(format!("fard-net-{}", i))
+                .stack_size(16 << 20)
+                .spawn(move || net_worker(cfg, server, stop, seq, digests, ctx, wl))?,
+        );
+    }
+    let mut ev = Map::new();
+    ev.insert("t".to_string(), J::Str("net_listen".to_string()));
+    ev.insert("server".to_string(), J::Str(id.clone()));
+    ev.insert("addr".to_string(), J::Str(bind));
+    ev.insert("workers".to_string(), J::Int(workers as i64));
+    ev.insert("receipts".to_string(), J::Bool(cfg.receipts.is_some()));
+    tracer.emit_event(J::Object(ev))?;
+    Ok(Ok((id, addr, NetServer { server, stop, workers: handles, seq, digests })))
+}
+
+/// `addr` (default 127.0.0.1) and `port` (default 0) of a bind or listen options record.
+fn net_opt_bind(opts: &BTreeMap<String, Val>, op: &str) -> Result<(String, u16)> {
+    let host = match opts.get("addr") {
+        None => "127.0.0.1".to_string(),
+        Some(Val::Text(s)) => s.clone(),
+        Some(_) => bail!("ERROR_BADARG {} addr must be text", op),
+    };
+    let port = match opts.get("port") {
+        None => 0,
+        Some(Val::Int(n)) if (0..=65535).contains(n) => *n as u16,
+        Some(_) => bail!("ERROR_BADARG {} port must be an int from 0 to 65535", op),
+    };
+    Ok((host, port))
+}
+
+fn net_opt_timeout(opts: &BTreeMap<String, Val>, op: &str) -> Result<Option<std::time::Duration>> {
+    match opts.get("timeout_ms") {
+        None | Some(Val::Unit) => Ok(None),
+        Some(Val::Int(n)) if *n > 0 => Ok(Some(std::time::Duration::from_millis(*n as u64))),
+        Some(_) => bail!("ERROR_BADARG {} timeout_ms must be a positive int", op),
+    }
+}
+
+/// Byte counts of one socket, reported by `net.close`.
+#[derive(Default)]
+struct NetIo {
+    bytes_in: std::sync::atomic::AtomicU64,
+    bytes_out: std::sync::atomic::AtomicU64,
+}
+
+/// A WebSocket from `net.ws_connect` or `net.ws_accept`. A reader thread moves incoming
+/// messages into `inbox` and closes it when the connection ends.
+struct NetWs {
+    ws: Mutex<tungstenite::WebSocket<NetWsStream>>,
+    /// Another handle on the socket; shutting it down wakes the reader
+    sock: std::net::TcpStream,
+    reader: Mutex<Option<std::thread::JoinHandle<()>>>,
+    io: Arc<NetIo>,
+    /// Digest of each message the program has taken from the inbox, in order
+    taken: Arc<Mutex<Vec<[u8; 32]>>>,
+}
+
+/// The stream under a WebSocket. The handshake reads the socket directly. After it the
+/// reader thread waits for bytes without holding the WebSocket's lock and hands them over
+/// in `received`, so a read that runs out of them returns `WouldBlock`.
+struct NetWsStream {
+    sock: std::net::TcpStream,
+    received: Option<Vec<u8>>,
+}
+
+impl std::io::Read for NetWsStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.received {
+            None => self.sock.read(buf),
+            Some(r) if r.is_empty() => Err(std::io::ErrorKind::WouldBlock.into()),
+            Some(r) => {
+                let n = buf.len().min(r.len());
+                buf[..n].copy_from_slice(&r[..n]);
+                r.drain(..n);
+                Ok(n)
+            }
+        }
+    }
+}
+
+impl std::io::Write for NetWsStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sock.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.sock.flush()
+    }
+}
+
+/// The inbox of a WebSocket, which the program may keep reading after `net.close`.
+struct NetWsInbox {
+    queue: std::sync::Weak<Mutex<std::collections::VecDeque<Val>>>,
+    handle: String,
+    io: Arc<NetIo>,
+    taken: Arc<Mutex<Vec<[u8; 32]>>>,
+}
+
+/// Inboxes of the WebSockets whose inbox is still reachable.
+static NET_WS_INBOXES: Mutex<Vec<NetWsInbox>> = Mutex::new(Vec::new());
+
+#[derive(Clone)]
+enum NetSock {
+    Tcp(Arc<std::net::TcpStream>, Arc<NetIo>),
+    Listener(Arc<std::net::TcpListener>, Option<std::time::Duration>),
+    Udp(Arc<std::net::UdpSocket>, Arc<NetIo>),
+    Ws(Arc<NetWs>),
+}
+
+/// Open sockets by handle (`tcp:N`, `listener:N`, `udp:N`, `ws:N`); any task may use them.
+static NET_SOCKETS: Mutex<BTreeMap<String, NetSock>> = Mutex::new(BTreeMap::new());
+static NET_SOCKET_SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+fn net_sock_register(kind: &str, sock: NetSock) -> String {
+    let id = format!("{}:{}", kind, NET_SOCKET_SEQ.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1);
+    NET_SOCKETS.lock().unwrap().insert(id.clone(), sock);
+    id
+}
+
+/// The open socket named by a handle text or a record with `handle`.
+fn net_sock(v: &Val, op: &str) -> Result<std::result::Result<(String, NetSock), String>> {
+    let id = match v {
+        Val::Text(h) => h.clone(),
+        Val::Record(m) => match m.get("handle") {
+            Some(Val::Text(h)) => h.clone(),
+            _ => bail!("ERROR_BADARG {} expects a socket", op),
+        },
+        _ => bail!("ERROR_BADARG {} expects a socket", op),
+    };
+    Ok(match NET_SOCKETS.lock().unwrap().get(&id) {
+        Some(s) => Ok((id, s.clone())),
+        None => Err(format!("ERROR_NET {}: no open socket {}", op, id)),
+    })
+}
+
+fn net_io_err(op: &str, e: &std::io::Error, timeout: Option<std::time::Duration>) -> Val {
+    let msg = match (e.kind(), timeout) {
+        (std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut, Some(t)) => {
+            format!("ERROR_TIMEOUT {} timed out after {} ms", op, t.as_millis())
+        }
+        _ => format!("ERROR_NET {}: {}", op, e),
+    };
+    mk_result_err(Val::Text(msg))
+}
+
+/// Traces a newly opened socket.
+fn net_open_event(tracer: &mut Tracer, op: &str, handle: &str, addr: &str, peer: Option<&str>) -> Result<()> {
+    let mut ev = Map::new();
+    ev.insert("t".to_string(), J::Str("net_open".to_string()));
+    ev.insert("op".to_string(), J::Str(op.to_string()));
+    ev.insert("handle".to_string(), J::Str(handle.to_string()));
+    ev.insert("addr".to_string(), J::Str(addr.to_string()));
+    if let Some(p) = peer {
+        ev.insert("peer".to_string(), J::Str(p.to_string()));
+    }
+    tracer.emit_event(J::Object(ev))
+}
+
+/// Traces bytes sent or received on a socket by their count and sha256.
+fn net_io_event(tracer: &mut Tracer, op: &str, handle: &str, peer: Option<&str>, data: &[u8]) -> Result<()> {
+    let mut ev = Map::new();
+    ev.insert("t".to_string(), J::Str("net_io".to_string()));
+    ev.insert("op".to_string(), J::Str(op.to_string()));
+    ev.insert("handle".to_string(), J::Str(handle.to_string()));
+    ev.insert("bytes".to_string(), J::Int(data.len() as i64));
+    ev.insert("digest".to_string(), J::Str(sha256_bytes(data)));
+    if let Some(p) = peer {
+        ev.insert("peer".to_string(), J::Str(p.to_string()));
+    }
+    tracer.emit_event(J::Object(ev))
+}
+
+/// `data` of `net.write`, `net.udp_send` and `net.ws_send`.
+fn net_payload(v: &Val, op: &str) -> Result<Vec<u8>> {
+    match v {
+        Val::Text(s) => Ok(s.as_bytes().to_vec()),
+        Val::Bytes(b) => Ok(b.clone()),
+        _ => bail!("ERROR_BADARG {} expects text or bytes", op),
+    }
+}
+
+/// Polls a listener until a connection arrives or `timeout` passes.
+fn net_accept(listener: &std::net::TcpListener, timeout: Option<std::time::Duration>) -> std::io::Result<(std::net::TcpStream, std::net::SocketAddr)> {
+    let deadline = timeout.map(|t| std::time::Instant::now() + t);
+    listener.set_nonblocking(true)?;
+    loop {
+        match listener.accept() {
+            Ok((s, peer)) => {
+                s.set_nonblocking(false)?;
+                s.set_read_timeout(timeout)?;
+                s.set_write_timeout(timeout)?;
+                return Ok((s, peer));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if deadline.is_some_and(|d| std::time::Instant::now() >= d) {
+                    return Err(e);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Connects to `host:port`, waiting at most `timeout` for each address, and applies
+/// `timeout` to reads and writes.
+fn net_tcp_connect(host: &str, port: u16, timeout: Option<std::time::Duration>) -> std::io::Result<std::net::TcpStream> {
+    let s = match timeout {
+        None => std::net::TcpStream::connect((host, port))?,
+        Some(t) => {
+            use std::net::ToSocketAddrs;
+            let mut last = std::io::Error::new(std::io::ErrorKind::NotFound, "no address");
+            let mut connected = None;
+            for a in (host, port).to_socket_addrs()? {
+                match std::net::TcpStream::connect_timeout(&a, t) {
+                    Ok(s) => {
+                        connected = Some(s);
+                        break;
+                    }
+                    Err(e) => last = e,
+                }
+            }
+            connected.ok_or(last)?
+        }
+    };
+    s.set_read_timeout(timeout)?;
+    s.set_write_timeout(timeout)?;
+    Ok(s)
+}
+
+/// Registers a WebSocket after its handshake and starts the reader that fills its inbox.
+fn net_ws_open(mut ws: tungstenite::WebSocket<NetWsStream>) -> Result<(String, Val)> {
+    // Only the reader reads from here on, and it waits for as long as the connection lasts.
+    ws.get_ref().sock.set_read_timeout(None)?;
+    ws.get_mut().received = Some(Vec::new());
+    let sock = ws.get_ref().sock.try_clone()?;
+    let mut incoming = sock.try_clone()?;
+    let q: Arc<Mutex<std::collections::VecDeque<Val>>> = Arc::new(Mutex::new(std::collections::VecDeque::new()));
+    let closed = Arc::new(Mutex::new(false));
+    let inbox = Val::Chan(q.clone(), closed.clone());
+    let queue = Arc::downgrade(&q);
+    let nws = Arc::new(NetWs {
+        ws: Mutex::new(ws),
+        sock,
+        reader: Mutex::new(None),
+        io: Arc::new(NetIo::default()),
+        taken: Arc::new(Mutex::new(Vec::new())),
+    });
+    let r = nws.clone();
+    let reader = std::thread::Builder::new().name("fard-ws".to_string()).spawn(move || {
+        use std::io::Read;
+        let mut buf = vec![0u8; 64 << 10];
+        'conn: loop {
+            let n = match incoming.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let mut ws = r.ws.lock().unwrap();
+            ws.get_mut().received.get_or_insert_with(Vec::new).extend_from_slice(&buf[..n]);
+            loop {
+                let v = match ws.read() {
+                    Ok(tungstenite::Message::Text(s)) => Val::Text(s),
+                    Ok(tungstenite::Message::Binary(b)) => Val::Bytes(b),
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(_) => break 'conn,
+                };
+                q.lock().unwrap().push_back(v);
+            }
+        }
+        *closed.lock().unwrap() = true;
+    })?;
+    *nws.reader.lock().unwrap() = Some(reader);
+    let (io, taken) = (nws.io.clone(), nws.taken.clone());
+    let handle = net_sock_register("ws", NetSock::Ws(nws));
+    let mut inboxes = NET_WS_INBOXES.lock().unwrap();
+    inboxes.retain(|i| i.queue.strong_count() > 0);
+    inboxes.push(NetWsInbox { queue, handle: handle.clone(), io, taken });
+    Ok((handle, inbox))
+}
+
+/// Traces a message taken from a channel that is a WebSocket's inbox, as `net.read`
+/// traces what it returns, and counts it for that socket's `net_close`.
+fn net_ws_taken(tracer: &mut Tracer, q: &Arc<Mutex<std::collections::VecDeque<Val>>>, v: &Val) -> Result<()> {
+    let inbox = NET_WS_INBOXES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|i| std::ptr::eq(i.queue.as_ptr(), Arc::as_ptr(q)))
+        .map(|i| (i.handle.clone(), i.io.clone(), i.taken.clone()));
+    let Some((handle, io, taken)) = inbox else { return Ok(()) };
+    let data = match v {
+        Val::Text(s) => s.as_bytes(),
+        Val::Bytes(b) => b.as_slice(),
+        _ => &[],
+    };
+    io.bytes_in.fetch_add(data.len() as u64, std::sync::atomic::Ordering::SeqCst);
+    taken.lock().unwrap().push(sha256_raw(data).try_into().unwrap_or([0u8; 32]));
+    net_io_event(tracer, "net.ws_recv", &handle, None, data)
+}
+
+fn http_response_to_val(code: u16, resp: ureq::Response) -> Result<Val> {
+    let mut headers = BTreeMap::new();
+    for name in resp.headers_names() {
+        if let Some(v) = resp.header(&name) {
+            headers.insert(name.to_lowercase(), Val::Text(v.to_string()));
+        }
+    }
+    let body = resp.into_string().unwrap_or_default();
+    let mut m = BTreeMap::new();
+    m.insert("status".to_string(), Val::Int(code as i64));
+    m.insert("headers".to_string(), Val::Record(headers));
+    m.insert("body".to_string(), Val::Text(body));
+    Ok(Val::Record(m))
+}
+
+/// One canned `std/http` answer, keyed by method, URL and the digest of the request body.
+#[derive(Clone)]
+struct HttpFixture {
+    method: String,
+    url: String,
+    body_sha256: String,
+    status: i64,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+impl HttpFixture {
+    fn key(method: &str, url: &str, body_sha256: &str) -> String {
+        format!("{} {} {}", method, url, body_sha256)
+    }
+
+    fn to_val(&self) -> Val {
+        let mut m = BTreeMap::new();
+        m.insert("status".to_string(), Val::Int(self.status));
+        m.insert(
+            "headers".to_string(),
+            Val::Record(self.headers.iter().map(|(k, v)| (k.clone(), Val::Text(v.clone()))).collect()),
+        );
+        m.insert("body".to_string(), Val::Text(self.body.clone()));
+        Val::Record(m)
+    }
+
+    fn to_toml(&self) -> String {
+        let mut s = String::from("[[http_fixtures]]\n");
+        s.push_str(&format!("method = {}\n", toml_quote(&self.method)));
+        s.push_str(&format!("url = {}\n", toml_quote(&self.url)));
+        s.push_str(&format!("body_sha256 = {}\n", toml_quote(&self.body_sha256)));
+        s.push_str(&format!("status = {}\n", self.status));
+        s.push_str(&format!("body = {}\n", toml_quote(&self.body)));
+        if !self.headers.is_empty() {
+            s.push_str("\n[http_fixtures.headers]\n");
+            for (k, v) in &self.headers {
+                s.push_str(&format!("{} = {}\n", toml_quote(k), toml_quote(v)));
+            }
+        }
+        s
+    }
+}
+/// Parse the `[[http_fixtures]]` tables of one fixtures file.
+fn parse_http_fixtures(src: &str, file: &str) -> Result<Vec<HttpFixture>> {
+    let mut out: Vec<HttpFixture> = Vec::new();
+    let mut in_headers = false;
+    let lines = toml_lines(src).map_err(|e| anyhow!("ERROR_HTTP_FIXTURE {}: {}", file, e))?;
+    for (n, line) in lines {
+        let at = || format!("{}:{}", file, n);
+        let (key, value) = match line {
+            TomlLine::ArrayTable(name) if name == "http_fixtures" => {
+                out.push(HttpFixture {
+                    method: String::new(),
+                    url: String::new(),
+                    body_sha256: sha256_bytes(b""),
+                    status: 200,
+                    headers: BTreeMap::new(),
+                    body: String::new(),
+                });
+                in_headers = false;
+                continue;
+            }
+            TomlLine::Table(name) if name == "http_fixtures.headers" => {
+                if out.is_empty() { bail!("ERROR_HTTP_FIXTURE {} headers before any [[http_fixtures]]", at()); }
+                in_headers = true;
+                continue;
+            }
+            TomlLine::Table(name) | TomlLine::ArrayTable(name) => {
+                bail!("ERROR_HTTP_FIXTURE {} unexpected section {}", at(), name);
+            }
+            TomlLine::Pair(k, v) => (k, v),
+        };
+        let Some(fx) = out.last_mut() else {
+            bail!("ERROR_HTTP_FIXTURE {} key outside [[http_fixtures]]", at());
+        };
+        if key == "status" && !in_headers {
+            fx.status = parse_toml_int(&value).map_err(|_| anyhow!("ERROR_HTTP_FIXTURE {} status must be an int", at()))?;
+            continue;
+        }
+        let val = parse_toml_str(&value).map_err(|e| anyhow!("ERROR_HTTP_FIXTURE {} {} must be a string: {}", at(), key, e))?;
+        if in_headers {
+            fx.headers.insert(key.to_lowercase(), val);
+            continue;
+        }
+        match key.as_str() {
+            "method" => fx.method = val.to_uppercase(),
+            "url" => fx.url = val,
+            "body_sha256" => fx.body_sha256 = val,
+            "body" => fx.body = val,
+            other => bail!("ERROR_HTTP_FIXTURE {} unknown key {}", at(), other),
+        }
+    }
+    for fx in &out {
+        if fx.method.is_empty() || fx.url.is_empty() {
+            bail!("ERROR_HTTP_FIXTURE {} every fixture needs method and url", file);
+        }
+    }
+    Ok(out)
+}
+
+/// `--http-fixtures`: answers `std/http` calls from a directory of fixture files,
+/// or, when capturing, makes the real request and writes the response there.
+struct HttpFixtures {
+    dir: PathBuf,
+    capture: bool,
+    entries: HashMap<String, HttpFixture>,
+    captured: Mutex<Vec<HttpFixture>>,
+}
+
+impl HttpFixtures {
+    fn load(dir: &Path, capture: bool) -> Result<HttpFixtures> {
+        let mut entries: HashMap<String, HttpFixture> = HashMap::new();
+        let mut origin: HashMap<String, String> = HashMap::new();
+        if capture {
+            fs::create_dir_all(dir).with_context(|| format!("ERROR_HTTP_FIXTURE cannot create {}", dir.display()))?;
+        } else {
+            let mut files: Vec<PathBuf> = fs::read_dir(dir)
+                .with_context(|| format!("ERROR_HTTP_FIXTURE cannot read {}", dir.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().map(|x| x == "toml").unwrap_or(false))
+                .collect();
+            files.sort();
+            for p in files {
+                let name = p.file_name().unwrap_or_default().to_string_lossy().to_string();
+                let src = fs::read_to_string(&p)
+                    .with_context(|| format!("ERROR_HTTP_FIXTURE cannot read {}", p.display()))?;
+                for fx in parse_http_fixtures(&src, &name)? {
+                    let key = HttpFixture::key(&fx.method, &fx.url, &fx.body_sha256);
+                    if let Some(prev) = origin.insert(key.clone(), name.clone()) {
+                        bail!("ERROR_HTTP_FIXTURE duplicate fixture for {} {} in {} and {}", fx.method, fx.url, prev, name);
+                    }
+                    entries.insert(key, fx);
+                }
+            }
+        }
+        Ok(HttpFixtures { dir: dir.to_path_buf(), capture, entries, captured: Mutex::new(Vec::new()) })
+    }
+
+    fn serve(&self, method: &str, url: &str, body: Option<&str>) -> Result<Val> {
+        let digest = sha256_bytes(body.unwrap_or("").as_bytes());
+        match self.entries.get(&HttpFixture::key(method, url, &digest)) {
+            Some(fx) => Ok(fx.to_val()),
+            None => bail!("ERROR_HTTP_FIXTURE no fixture for {} {} (body {})", method, url, digest),
+        }
+    }
+
+    /// Record a live response into `captured.toml`; the first answer for a request wins.
+    fn record(&self, method: &str, url: &str, body: Option<&str>, resp: &Val) -> Result<()> {
+        let Val::Record(m) = resp else { return Ok(()) };
+        let fx = HttpFixture {
+            method: method.to_string(),
+            url: url.to_string(),
+            body_sha256: sha256_bytes(body.unwrap_or("").as_bytes()),
+            status: match m.get("status") { Some(Val::Int(n)) => *n, _ => 0 },
+            headers: match m.get("headers") {
+                Some(Val::Record(h)) => h.iter()
+                    .filter_map(|(k, v)| match v { Val::Text(t) => Some((k.clone(), t.clone())), _ => None })
+                    .collect(),
+                _ => BTreeMap::new(),
+            },
+            body: match m.get("body") { Some(Val::Text(t)) => t.clone(), _ => String::new() },
+        };
+        let mut captured = self.captured.lock().unwrap();
+        let key = HttpFixture::key(&fx.method, &fx.url, &fx.body_sha256);
+        if captured.iter().any(|c| HttpFixture::key(&c.method, &c.url, &c.body_sha256) == key) {
+            return Ok(());
+        }
+        captured.push(fx);
+        let mut src = String::from("# Captured by fardrun --http-capture\n");
+        for c in captured.iter() {
+            src.push('\n');
+            src.push_str(&c.to_toml());
+        }
+        let path = self.dir.join("captured.toml");
+        fs::write(&path, src).with_context(|| format!("ERROR_HTTP_FIXTURE cannot write {}", path.display()))
+    }
+}
+
+/// Perform a `std/http` call: over the network, or through `--http-fixtures` when set.
+/// Fixture runs add an `http_fixture` event naming the CID of the response served.
+fn http_call(
+    op: &str,
+    args: &[Val],
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: Option<&str>,
+    tracer: &mut Tracer,
+) -> Result<Val> {
+    let fixtures = HTTP_FIXTURES.with(|f| f.borrow().clone());
+    let live = || -> Result<Val> {
+        let mut req = ureq::request(method, url);
+        for (k, v) in headers {
+            req = req.set(k, v);
+        }
+        let result = match body {
+            Some(b) => req.send_string(b),
+            None => req.call(),
+        };
+        match result {
+            Ok(resp) => http_response_to_val(resp.status(), resp),
+            Err(ureq::Error::Status(code, resp)) => http_response_to_val(code, resp),
+            Err(e) => bail!("ERROR_{} {}", op.replace('.', "_").to_uppercase(), e),
+        }
+    };
+    let v = oracle_answer(op, args, tracer, || match &fixtures {
+        Some(fx) if !fx.capture => fx.serve(method, url, body),
+        Some(fx) => {
+            let v = live()?;
+            fx.record(method, url, body, &v)?;
+            Ok(v)
+        }
+        None => live(),
+    })?;
+    if fixtures.is_some() {
+        let mut m = Map::new();
+        m.insert("t".to_string(), J::Str("http_fixture".to_string()));
+        m.insert("op".to_string(), J::Str(op.to_string()));
+        m.insert("method".to_string(), J::Str(method.to_string()));
+        m.insert("url".to_string(), J::Str(url.to_string()));
+        m.insert("cid".to_string(), J::Str(sha256_bytes(&canonical_json_bytes(&v.to_json().unwrap_or(J::Null)))));
+        tracer.emit_event(J::Object(m))?;
+    }
+    Ok(v)
+}
+
+fn days_since_epoch(year: i64, month: i64, day: i64) -> i64 {
+    // Proleptic Gregorian calendar days since 1970-01-01
+    let y = if month <= 2 { year - 1 } else { year };
+    let m = if month <= 2 { month + 12 } else { month };
+    let d = day;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m - 3) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn unix_secs_to_iso8601(secs: i64) -> String {
+    let (secs, _neg) = if secs < 0 { (-secs, true) } else { (secs, false) };
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let h = rem / 3600;
+    let m = (rem % 3600) / 60;
+    let s = rem % 60;
+    // Convert days since epoch to year/month/day
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+    let y = yoe + era * 400;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2)/153;
+    let d = doy - (153*mp+2)/5 + 1;
+    let mo = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = if mo <= 2 { y + 1 } else { y };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, mo, d, h, m, s)
+}
+
+fn result_is_ok(v: &Val) -> Result<bool> {
+    match v {
+        Val::Record(m) => {
+            if m.len() != 2 {
+                bail!("{} expected result", QMARK_EXPECT_RESULT);
+            }
+            match m.get(RESULT_TAG_KEY) {
+                Some(Val::Text(t)) if t == RESULT_OK_TAG => {
+                    if !m.contains_key(RESULT_OK_VAL_KEY) {
+                        bail!("QMARK_EXPECT_RESULT ok missing v");
+                    }
+                    Ok(true)
+                }
+                Some(Val::Text(t)) if t == RESULT_ERR_TAG => {
+                    if !m.contains_key(RESULT_ERR_VAL_KEY) {
+                        bail!("QMARK_EXPECT_RESULT err missing e");
+                    }
+                    Ok(false)
+                }
+                _ => bail!("{} expected result tag", QMARK_EXPECT_RESULT),
+            }
+        }
+        _ => bail!("{} expected result", QMARK_EXPECT_RESULT),
+    }
+}
+
+fn result_unwrap_ok(v: &Val) -> Result<Val> {
+    match v {
+        Val::Record(m) => match m.get(RESULT_TAG_KEY) {
+            Some(Val::Text(t)) if t == RESULT_OK_TAG => m
+                .get(RESULT_OK_VAL_KEY)
+                .cloned()
+                .ok_or_else(|| anyhow!("QMARK_EXPECT_RESULT ok missing v")),
+            Some(Val::Text(t)) if t == RESULT_ERR_TAG => {
+                bail!("QMARK_EXPECT_RESULT tried unwrap ok on err")
+            }
+            Some(_) => bail!("{} expected result tag", QMARK_EXPECT_RESULT),
+            None => bail!("{} expected result", QMARK_EXPECT_RESULT),
+        },
+        _ => bail!("{} expected result", QMARK_EXPECT_RESULT),
+    }
+}
+fn result_unwrap_err(v: &Val) -> Result<Val> {
+    match v {
+        Val::Record(m) => match m.get(RESULT_TAG_KEY) {
+            Some(Val::Text(t)) if t == RESULT_ERR_TAG => match m.get(RESULT_ERR_VAL_KEY) {
+                Some(x) => Ok(x.clone()),
+                None => bail!("QMARK_EXPECT_RESULT err missing e"),
+            },
+            Some(Val::Text(t)) if t == RESULT_OK_TAG => {
+                bail!("QMARK_EXPECT_RESULT tried unwrap err on ok")
+            }
+            Some(_) => bail!("{} expected result tag", QMARK_EXPECT_RESULT),
+            None => bail!("{} expected result", QMARK_EXPECT_RESULT),
+        },
+        _ => bail!("{} expected result", QMARK_EXPECT_RESULT),
+    }
+}
+fn mk_result_ok(v: Val) -> Val {
+    let mut m = BTreeMap::new();
+    m.insert(
+        RESULT_TAG_KEY.to_string(),
+        Val::Text(RESULT_OK_TAG.to_string()),
+    );
+    m.insert(RESULT_OK_VAL_KEY.to_string(), v);
+    Val::Record(m)
+}
+
+fn mk_result_err(e: Val) -> Val {
+    let mut m = BTreeMap::new();
+    m.insert(
+        RESULT_TAG_KEY.to_string(),
+        Val::Text(RESULT_ERR_TAG.to_string()),
     );
     m.insert(RESULT_ERR_VAL_KEY.to_string(), e);
     Val::Record(m)
@@ -4533,6 +7290,7 @@ fn val_eq(a: &Val, b: &Val) -> bool {
         (Val::Float(x), Val::Float(y)) => x == y,
         (Val::Bool(x), Val::Bool(y)) => x == y,
         (Val::Text(x), Val::Text(y)) => x == y,
+        (Val::Bytes(x), Val::Bytes(y)) => x == y,
         (Val::Unit, Val::Unit) => true,
         (Val::List(xs), Val::List(ys)) => {
             xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| val_eq(x, y))
@@ -4554,22 +7312,12 @@ fn call(f: Val, args: Vec<Val>, tracer: &mut Tracer, loader: &mut ModuleLoader)
     let mut cur_f = f;
     let mut cur_args = args;
     loop {
+        check_deadline()?;
         match cur_f {
             Val::Builtin(b) => return call_builtin(b, cur_args, tracer, loader),
-            Val::VmFunc(fn_idx) => {
-                // Use pre-set raw pointer — no clone, no RefCell borrow
-                let ptr = VM_FNS_PTR.with(|p| p.get());
-                if !ptr.is_null() {
-                    // SAFETY: ptr points to VM_FNS vec which lives for the program duration
-                    // VM_FNS is never modified during execution, only at load time
-                    let fns = unsafe { &*ptr };
-                    return vm_exec_fn(fns, fn_idx, cur_args, tracer, loader);
-                }
-                // Fallback: clone (first call before ptr is set)
-                let fns = VM_FNS.with(|cell| cell.borrow().clone());
-                return vm_exec_fn(&fns, fn_idx, cur_args, tracer, loader);
-            }
+            Val::VmFunc(clo) => return vm_call(clo, cur_args, tracer, loader),
             Val::Func(fun) => {
+                vm_stats_count(false);
                 if fun.params.len() != cur_args.len() {
                     bail!("arity mismatch: expected {} args, got {}", fun.params.len(), cur_args.len());
                 }
@@ -4635,8 +7383,7 @@ fn eval_tco(expr: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut Module
                 av.push(eval(a, env, tracer, loader)?);
             }
             match fv {
-                Val::Func(_) => Ok(TcoResult::TailCall(fv, av)),
-                Val::VmFunc(_) => Ok(TcoResult::Done(call(fv, av, tracer, loader)?)),
+                Val::Func(_) | Val::VmFunc(_) => Ok(TcoResult::TailCall(fv, av)),
                 Val::Builtin(b) => Ok(TcoResult::Done(call_builtin(b, av, tracer, loader)?)),
                 Val::BoundMethod(receiver, func) => {
                     let mut full_args = vec![*receiver];
@@ -4670,13 +7417,13 @@ fn eval_tco(expr: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut Module
                         match gv {
                             Val::Bool(true) => {}
                             Val::Bool(false) => continue,
-                            _ => bail!("ERROR_RUNTIME match guard not bool"),
+                            _ => return Err(guard_not_bool(arm.guard_span.clone())),
                         }
                     }
                     return eval_tco(&arm.body, &mut env2, tracer, loader);
                 }
             }
-            bail!("ERROR_RUNTIME no match arm matched")
+            bail!("{} no match", ERROR_MATCH_NO_ARM)
         }
         // All other expressions are not tail calls — evaluate normally
         other => Ok(TcoResult::Done(eval(other, env, tracer, loader)?)),
@@ -4685,582 +7432,835 @@ fn eval_tco(expr: &Expr, env: &mut Env, tracer: &mut Tracer, loader: &mut Module
 
 // ═══════════════════════════════════════════════════════════════════════════
 // FARD BYTECODE VM
-// Compiles Expr trees to flat bytecode + executes in a register VM.
-// Eliminates Arc<Mutex<HashMap>> env lookups — uses integer slot indices.
-// Falls back to tree-walker for unsupported expressions.
+// Compiles whole modules (top-level fns, lets and expressions) to flat
+// bytecode for a stack VM. Locals live in integer slots, closures capture the
+// enclosing locals they use as upvalues, and every other name resolves in the
+// module env at run time, so forward and mutual references work. Operators,
+// member access, `?` and while share their helpers with eval(), and a unit
+// that fails to compile falls back to the tree-walker (see --vm-stats).
 // ═══════════════════════════════════════════════════════════════════════════
 
+#[derive(Debug, Clone, Copy)]
+enum VmBin {
+    Add, Sub, Mul, Div, Mod,
+    Eq, Ne, Lt, Le, Gt, Ge,
+}
+
+impl VmBin {
+    fn of(op: &str) -> Option<VmBin> {
+        Some(match op {
+            "+" => VmBin::Add, "-" => VmBin::Sub, "*" => VmBin::Mul,
+            "/" => VmBin::Div, "%" => VmBin::Mod,
+            "==" => VmBin::Eq, "!=" => VmBin::Ne,
+            "<" => VmBin::Lt, "<=" => VmBin::Le,
+            ">" => VmBin::Gt, ">=" => VmBin::Ge,
+            _ => return None,
+        })
+    }
+
+    fn sym(self) -> &'static str {
+        match self {
+            VmBin::Add => "+", VmBin::Sub => "-", VmBin::Mul => "*",
+            VmBin::Div => "/", VmBin::Mod => "%",
+            VmBin::Eq => "==", VmBin::Ne => "!=",
+            VmBin::Lt => "<", VmBin::Le => "<=",
+            VmBin::Gt => ">", VmBin::Ge => ">=",
+        }
+    }
+}
+
+/// A resolved name: a local slot, an upvalue of the running closure, or the
+/// top-level fn being compiled. Also says where a new closure copies each upvalue from.
+#[derive(Debug, Clone, Copy)]
+enum VmVar {
+    Slot(usize),
+    Upval(usize),
+    SelfFn,
+}
+
+/// `Pat` with its bindings resolved to slots.
+#[derive(Debug, Clone)]
+enum VmPat {
+    Wild,
+    Bind(usize),
+    LitInt(i64),
+    LitStr(String),
+    LitBool(bool),
+    LitNull,
+    Obj { items: Vec<(String, VmPat)>, rest: Option<usize> },
+    List { items: Vec<VmPat>, rest: Option<usize> },
+}
+
 #[derive(Debug, Clone)]
 enum VmOp {
-    LoadInt(i64),
-    LoadFloat(f64),
-    LoadBool(bool),
-    LoadNull,
-    LoadStr(String),
+    Const(Val),
     LoadSlot(usize),
     StoreSlot(usize),
-    Add, Sub, Mul, Div, Mod,
-    Eq, Ne, Lt, Le, Gt, Ge,
-    Neg, Not,
+    LoadUpval(usize),
+    LoadSelf,
+    LoadGlobal(String),
+    Bin(VmBin),
+    BinOther(String),
+    Unary(String),
+    Get(String),
+    Index,
+    Stringify,
+    Concat(usize),
+    MakeList(usize),
+    MakeRec(Vec<String>),
+    MakeClosure(usize, Vec<VmVar>), // proto index, where each upvalue comes from
+    Call(usize),
+    TailCall(usize),
+    CallBuiltin(Builtin, usize),
+    NamedCall(Vec<String>),
     Jump(usize),
-    JumpIfFalse(usize),
-    JumpIfTrue(usize),
+    JumpIfNot(usize),
+    Match(usize, usize),             // pattern index, target when it does not match
+    Guard(usize, Option<ErrorSpan>), // target when the guard is false
+    Bind(usize, &'static str),       // pattern index, what is bound (let/using/arg)
+    NoMatch,
+    Try,
+    While,
     Pop,
-    MakeList(usize),
-    MakeRec(usize),
-    GetField(String),
-    MakeClosure(usize),   // fn_idx into VmProgram.fns
-    VmCall(usize),        // n_args
     Return,
-    TryOp,
-    Halt,
+    ReturnEarly,
 }
 
-#[derive(Debug, Clone)]
-struct VmCompiledFn {
+/// A compiled fn body or top-level chunk.
+struct VmProto {
+    name: String,
     code: Vec<VmOp>,
-    n_slots: usize,
     n_params: usize,
-    slot_names: Vec<String>,
-    self_slot: Option<usize>,
-    free_vars: Vec<(usize, Val)>,  // sparse: (slot_idx, val) for non-param free vars
+    n_slots: usize,
+    /// Names named calls match against; `_` for destructuring params
+    param_names: Vec<String>,
+    protos: Vec<Arc<VmProto>>,
+    pats: Vec<VmPat>,
+    /// The module env free names resolve in
+    globals: Env,
+    /// Top-level chunks let `?` and `return` escape to eval_items like eval() does
+    top_level: bool,
+}
+
+/// A VM function value: a prototype plus the upvalues captured when it was created.
+struct VmClosure {
+    proto: Arc<VmProto>,
+    upvals: Vec<Val>,
+}
+
+impl std::fmt::Debug for VmClosure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<vm fn {}>", self.proto.name)
+    }
 }
 
-struct VmCompiler {
+struct VmFrame {
     code: Vec<VmOp>,
-    slot_names: Vec<String>,
+    locals: Vec<(String, usize)>,
+    n_slots: usize,
+    upvals: Vec<(String, VmVar)>,
+    protos: Vec<Arc<VmProto>>,
+    pats: Vec<VmPat>,
+    self_name: Option<String>,
+}
+
+struct VmCompiler<'a> {
+    frames: Vec<VmFrame>,
+    globals: &'a Env,
+    /// Import aliases the module never rebinds: `alias.f` folds to the export itself
+    imports: &'a std::collections::HashSet<String>,
 }
 
-impl VmCompiler {
-    fn new() -> Self { Self { code: Vec::new(), slot_names: Vec::new() } }
+impl<'a> VmCompiler<'a> {
+    fn new(globals: &'a Env, imports: &'a std::collections::HashSet<String>) -> Self {
+        Self { frames: Vec::new(), globals, imports }
+    }
 
-    fn slot_of(&mut self, name: &str) -> usize {
-        if let Some(i) = self.slot_names.iter().rposition(|s| s == name) { return i; }
-        let i = self.slot_names.len();
-        self.slot_names.push(name.to_string());
-        i
+    fn frame(&mut self) -> &mut VmFrame {
+        self.frames.last_mut().expect("vm: no frame")
     }
 
     fn emit(&mut self, op: VmOp) -> usize {
-        let i = self.code.len();
-        self.code.push(op);
-        i
+        let code = &mut self.frame().code;
+        code.push(op);
+        code.len() - 1
     }
 
-    fn patch(&mut self, idx: usize, target: usize) {
-        match &mut self.code[idx] {
-            VmOp::Jump(t) | VmOp::JumpIfFalse(t) | VmOp::JumpIfTrue(t) => *t = target,
+    /// Points the jump at `at` to the next instruction emitted.
+    fn patch(&mut self, at: usize) {
+        let code = &mut self.frame().code;
+        let target = code.len();
+        match &mut code[at] {
+            VmOp::Jump(t) | VmOp::JumpIfNot(t) | VmOp::Match(_, t) | VmOp::Guard(t, _) => *t = target,
             _ => {}
         }
     }
 
-    fn compile(&mut self, expr: &Expr, fns: &mut Vec<VmCompiledFn>) -> Result<()> {
-        match expr {
-            Expr::Int(n)      => { self.emit(VmOp::LoadInt(*n)); }
-            Expr::FloatLit(f) => { self.emit(VmOp::LoadFloat(*f)); }
-            Expr::Bool(b)     => { self.emit(VmOp::LoadBool(*b)); }
-            Expr::Null        => { self.emit(VmOp::LoadNull); }
-            Expr::Str(s)      => { self.emit(VmOp::LoadStr(s.clone())); }
+    fn fresh_slot(&mut self) -> usize {
+        let f = self.frame();
+        f.n_slots += 1;
+        f.n_slots - 1
+    }
+
+    fn resolve(&mut self, depth: usize, name: &str) -> Option<VmVar> {
+        let f = &self.frames[depth];
+        if let Some((_, s)) = f.locals.iter().rev().find(|(n, _)| n == name) {
+            return Some(VmVar::Slot(*s));
+        }
+        if let Some(i) = f.upvals.iter().position(|(n, _)| n == name) {
+            return Some(VmVar::Upval(i));
+        }
+        if f.self_name.as_deref() == Some(name) {
+            return Some(VmVar::SelfFn);
+        }
+        if depth == 0 {
+            return None;
+        }
+        let from = self.resolve(depth - 1, name)?;
+        let f = &mut self.frames[depth];
+        f.upvals.push((name.to_string(), from));
+        Some(VmVar::Upval(f.upvals.len() - 1))
+    }
+
+    /// `alias.name` for an import alias that is never rebound, when the export exists.
+    fn folded(&mut self, obj: &Expr, field: &str) -> Option<Val> {
+        let Expr::Var(alias) = obj else { return None };
+        if !self.imports.contains(alias) || self.resolve(self.frames.len() - 1, alias).is_some() {
+            return None;
+        }
+        match self.globals.get(alias)? {
+            Val::Record(m) => m.get(field).cloned(),
+            _ => None,
+        }
+    }
 
-            Expr::Var(name) => {
-                let slot = self.slot_of(name);
-                self.emit(VmOp::LoadSlot(slot));
+    fn pat(&mut self, p: &Pat, binds: &mut Vec<(String, usize)>) -> VmPat {
+        match p {
+            Pat::Wild => VmPat::Wild,
+            Pat::Bind(n) => {
+                let s = self.fresh_slot();
+                binds.push((n.clone(), s));
+                VmPat::Bind(s)
+            }
+            Pat::LitInt(i) => VmPat::LitInt(*i),
+            Pat::LitStr(s) => VmPat::LitStr(s.clone()),
+            Pat::LitBool(b) => VmPat::LitBool(*b),
+            Pat::LitNull => VmPat::LitNull,
+            Pat::Obj { items, rest } => {
+                let items = items.iter().map(|(k, sub)| (k.clone(), self.pat(sub, binds))).collect();
+                let rest = rest.as_ref().map(|r| {
+                    let s = self.fresh_slot();
+                    binds.push((r.clone(), s));
+                    s
+                });
+                VmPat::Obj { items, rest }
+            }
+            Pat::List { items, rest } => {
+                let items = items.iter().map(|sub| self.pat(sub, binds)).collect();
+                let rest = rest.as_ref().map(|r| {
+                    let s = self.fresh_slot();
+                    binds.push((r.clone(), s));
+                    s
+                });
+                VmPat::List { items, rest }
             }
+        }
+    }
 
-            Expr::Let(name, e1, e2) => {
-                self.compile(e1, fns)?;
-                let slot = self.slot_of(name);
-                self.emit(VmOp::StoreSlot(slot));
-                self.compile(e2, fns)?;
+    /// Pops a value and binds `p` against it, failing with ERROR_PAT_MISMATCH.
+    fn bind(&mut self, p: &Pat, what: &'static str) {
+        match p {
+            Pat::Bind(n) => {
+                let s = self.fresh_slot();
+                self.emit(VmOp::StoreSlot(s));
+                self.frame().locals.push((n.clone(), s));
+            }
+            Pat::Wild => {
+                self.emit(VmOp::Pop);
             }
+            p => {
+                let mut binds = Vec::new();
+                let vp = self.pat(p, &mut binds);
+                let f = self.frame();
+                f.pats.push(vp);
+                let pi = f.pats.len() - 1;
+                self.emit(VmOp::Bind(pi, what));
+                self.frame().locals.extend(binds);
+            }
+        }
+    }
 
-            Expr::If(cond, then_e, else_e) => {
-                self.compile(cond, fns)?;
-                let jf = self.emit(VmOp::JumpIfFalse(0));
-                self.compile(then_e, fns)?;
-                let jmp = self.emit(VmOp::Jump(0));
-                let else_start = self.code.len();
-                self.patch(jf, else_start);
-                self.compile(else_e, fns)?;
-                self.patch(jmp, self.code.len());
+    fn compile_fn(
+        &mut self,
+        name: &str,
+        params: &[Pat],
+        body: &Expr,
+        self_name: Option<&str>,
+        top_level: bool,
+    ) -> Result<(Arc<VmProto>, Vec<VmVar>)> {
+        self.frames.push(VmFrame {
+            code: Vec::new(),
+            locals: Vec::new(),
+            n_slots: params.len(),
+            upvals: Vec::new(),
+            protos: Vec::new(),
+            pats: Vec::new(),
+            self_name: self_name.map(str::to_string),
+        });
+        for (i, p) in params.iter().enumerate() {
+            match p {
+                Pat::Bind(n) => self.frame().locals.push((n.clone(), i)),
+                Pat::Wild => {}
+                p => {
+                    self.emit(VmOp::LoadSlot(i));
+                    self.bind(p, "arg");
+                }
+            }
+        }
+        let compiled = self.expr(body, !top_level);
+        self.emit(VmOp::Return);
+        let f = self.frames.pop().expect("vm: no frame");
+        compiled?;
+        let proto = VmProto {
+            name: name.to_string(),
+            code: f.code,
+            n_params: params.len(),
+            n_slots: f.n_slots,
+            param_names: params.iter().map(|p| match p {
+                Pat::Bind(n) => n.clone(),
+                _ => "_".to_string(),
+            }).collect(),
+            protos: f.protos,
+            pats: f.pats,
+            globals: self.globals.clone(),
+            top_level,
+        };
+        Ok((Arc::new(proto), f.upvals.into_iter().map(|(_, from)| from).collect()))
+    }
+
+    fn expr(&mut self, e: &Expr, tail: bool) -> Result<()> {
+        match e {
+            Expr::Int(n) => { self.emit(VmOp::Const(Val::Int(*n))); }
+            Expr::FloatLit(f) => { self.emit(VmOp::Const(Val::Float(*f))); }
+            Expr::Bool(b) => { self.emit(VmOp::Const(Val::Bool(*b))); }
+            Expr::Str(s) => { self.emit(VmOp::Const(Val::Text(s.clone()))); }
+            Expr::Null => { self.emit(VmOp::Const(Val::Unit)); }
+            Expr::StrInterp(parts) => {
+                for part in parts {
+                    match part {
+                        StrPart::Lit(s) => { self.emit(VmOp::Const(Val::Text(s.clone()))); }
+                        StrPart::Expr(e) => {
+                            self.expr(e, false)?;
+                            self.emit(VmOp::Stringify);
+                        }
+                    }
+                }
+                self.emit(VmOp::Concat(parts.len()));
             }
-
-            Expr::Bin(op, a, b) => {
-                if op == "&&" {
-                    self.compile(a, fns)?;
-                    let jf = self.emit(VmOp::JumpIfFalse(0));
-                    self.emit(VmOp::Pop);
-                    self.compile(b, fns)?;
-                    self.patch(jf, self.code.len());
-                    return Ok(());
-                }
-                if op == "||" {
-                    self.compile(a, fns)?;
-                    let jt = self.emit(VmOp::JumpIfTrue(0));
-                    self.emit(VmOp::Pop);
-                    self.compile(b, fns)?;
-                    self.patch(jt, self.code.len());
-                    return Ok(());
-                }
-                self.compile(a, fns)?;
-                self.compile(b, fns)?;
-                let bin_op = match op.as_str() {
-                    "+"  => VmOp::Add, "-"  => VmOp::Sub, "*"  => VmOp::Mul,
-                    "/"  => VmOp::Div, "%"  => VmOp::Mod,
-                    "==" => VmOp::Eq,  "!=" => VmOp::Ne,
-                    "<"  => VmOp::Lt,  "<=" => VmOp::Le,
-                    ">"  => VmOp::Gt,  ">=" => VmOp::Ge,
-                    _    => bail!("vm: unsupported binary op: {op}"),
+            Expr::Var(n) => {
+                let op = match self.resolve(self.frames.len() - 1, n) {
+                    Some(VmVar::Slot(s)) => VmOp::LoadSlot(s),
+                    Some(VmVar::Upval(i)) => VmOp::LoadUpval(i),
+                    Some(VmVar::SelfFn) => VmOp::LoadSelf,
+                    None => VmOp::LoadGlobal(n.clone()),
                 };
-                self.emit(bin_op);
+                self.emit(op);
             }
-
-            Expr::Unary(op, e) => {
-                self.compile(e, fns)?;
-                match op.as_str() {
-                    "-" => { self.emit(VmOp::Neg); }
-                    "!" => { self.emit(VmOp::Not); }
-                    _   => bail!("vm: unsupported unary op"),
+            Expr::List(xs) => {
+                for x in xs {
+                    self.expr(x, false)?;
                 }
+                self.emit(VmOp::MakeList(xs.len()));
             }
-
-            Expr::Get(obj, field) => {
-                self.compile(obj, fns)?;
-                self.emit(VmOp::GetField(field.clone()));
+            Expr::Rec(kvs) => {
+                for (_, v) in kvs {
+                    self.expr(v, false)?;
+                }
+                self.emit(VmOp::MakeRec(kvs.iter().map(|(k, _)| k.clone()).collect()));
             }
-
-            Expr::List(items) => {
-                for item in items { self.compile(item, fns)?; }
-                self.emit(VmOp::MakeList(items.len()));
+            Expr::Index(obj, idx) => {
+                self.expr(obj, false)?;
+                self.expr(idx, false)?;
+                self.emit(VmOp::Index);
             }
-
-            Expr::Rec(kvs) => {
-                for (k, v) in kvs {
-                    self.emit(VmOp::LoadStr(k.clone()));
-                    self.compile(v, fns)?;
+            Expr::Get(obj, k) => {
+                if let Some(v) = self.folded(obj, k) {
+                    self.emit(VmOp::Const(v));
+                } else {
+                    self.expr(obj, false)?;
+                    self.emit(VmOp::Get(k.clone()));
                 }
-                self.emit(VmOp::MakeRec(kvs.len()));
             }
-
-            Expr::Call(f_expr, args) => {
-                self.compile(f_expr, fns)?;
-                for arg in args { self.compile(arg, fns)?; }
-                self.emit(VmOp::VmCall(args.len()));
+            Expr::Let(name, e1, e2) => {
+                self.expr(e1, false)?;
+                let mark = self.frame().locals.len();
+                self.bind(&Pat::Bind(name.clone()), "let");
+                self.expr(e2, tail)?;
+                self.frame().locals.truncate(mark);
+            }
+            Expr::LetPat(pat, e1, e2) | Expr::Using(pat, e1, e2) => {
+                self.expr(e1, false)?;
+                let mark = self.frame().locals.len();
+                self.bind(pat, if matches!(e, Expr::Using(..)) { "using" } else { "let" });
+                self.expr(e2, tail)?;
+                self.frame().locals.truncate(mark);
+            }
+            Expr::If(c, t, f) => {
+                self.expr(c, false)?;
+                let to_else = self.emit(VmOp::JumpIfNot(0));
+                self.expr(t, tail)?;
+                let to_end = self.emit(VmOp::Jump(0));
+                self.patch(to_else);
+                self.expr(f, tail)?;
+                self.patch(to_end);
             }
-
             Expr::Fn(params, body) | Expr::Lambda(params, body) => {
-                // Don't VM-compile inline lambdas — they may capture tree-walker locals
-                // that are not available in VM slot tables
-                bail!("vm: inline Fn/Lambda not supported — use Item::Fn at top level");
-                #[allow(unreachable_code)]
-                let mut inner = VmCompiler::new();
-                for p in params.iter() {
-                    match p {
-                        Pat::Bind(name) => { inner.slot_of(name); }
-                        _ => bail!("vm: fn param must be simple bind"),
-                    }
-                }
-                inner.compile(body, fns)?;
-                inner.emit(VmOp::Return);
-                let fn_idx = fns.len();
-                let n_slots = inner.slot_names.len();
-                fns.push(VmCompiledFn {
-                    code: inner.code,
-                    n_slots,
-                    n_params: params.len(),
-                    slot_names: inner.slot_names,
-                    self_slot: None,
-                    free_vars: Vec::new(),
+                let (proto, captures) = self.compile_fn("<lambda>", params, body, None, false)?;
+                let f = self.frame();
+                f.protos.push(proto);
+                let pi = f.protos.len() - 1;
+                self.emit(VmOp::MakeClosure(pi, captures));
+            }
+            Expr::Unary(op, a) => {
+                self.expr(a, false)?;
+                self.emit(VmOp::Unary(op.clone()));
+            }
+            Expr::Bin(op, a, b) => {
+                self.expr(a, false)?;
+                self.expr(b, false)?;
+                self.emit(VmBin::of(op).map(VmOp::Bin).unwrap_or_else(|| VmOp::BinOther(op.clone())));
+            }
+            Expr::Call(f, args) => {
+                let builtin = match &**f {
+                    Expr::Get(obj, k) => match self.folded(obj, k) {
+                        Some(Val::Builtin(b)) => Some(b),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if builtin.is_none() {
+                    self.expr(f, false)?;
+                }
+                for a in args {
+                    self.expr(a, false)?;
+                }
+                self.emit(match builtin {
+                    Some(b) => VmOp::CallBuiltin(b, args.len()),
+                    None if tail => VmOp::TailCall(args.len()),
+                    None => VmOp::Call(args.len()),
                 });
-                self.emit(VmOp::MakeClosure(fn_idx));
             }
-
-            Expr::Try(e) => {
-                self.compile(e, fns)?;
-                self.emit(VmOp::TryOp);
+            Expr::NamedCall(f, named) => {
+                self.expr(f, false)?;
+                for (_, a) in named {
+                    self.expr(a, false)?;
+                }
+                self.emit(VmOp::NamedCall(named.iter().map(|(n, _)| n.clone()).collect()));
+            }
+            Expr::Try(x) => {
+                self.expr(x, false)?;
+                self.emit(VmOp::Try);
+            }
+            Expr::Match(scrut, arms) => {
+                self.expr(scrut, false)?;
+                let tmp = self.fresh_slot();
+                self.emit(VmOp::StoreSlot(tmp));
+                let mut to_end = Vec::new();
+                for arm in arms {
+                    let mark = self.frame().locals.len();
+                    self.emit(VmOp::LoadSlot(tmp));
+                    let mut binds = Vec::new();
+                    let vp = self.pat(&arm.pat, &mut binds);
+                    let f = self.frame();
+                    f.pats.push(vp);
+                    let pi = f.pats.len() - 1;
+                    let to_next = self.emit(VmOp::Match(pi, 0));
+                    self.frame().locals.extend(binds);
+                    let guard = match &arm.guard {
+                        Some(g) => {
+                            self.expr(g, false)?;
+                            Some(self.emit(VmOp::Guard(0, arm.guard_span.clone())))
+                        }
+                        None => None,
+                    };
+                    self.expr(&arm.body, tail)?;
+                    to_end.push(self.emit(VmOp::Jump(0)));
+                    self.frame().locals.truncate(mark);
+                    self.patch(to_next);
+                    if let Some(g) = guard {
+                        self.patch(g);
+                    }
+                }
+                self.emit(VmOp::NoMatch);
+                for j in to_end {
+                    self.patch(j);
+                }
             }
-
-            Expr::Return(e) => {
-                self.compile(e, fns)?;
-                self.emit(VmOp::Return);
+            Expr::While(init, cond, body) => {
+                self.expr(init, false)?;
+                self.expr(cond, false)?;
+                self.expr(body, false)?;
+                self.emit(VmOp::While);
+            }
+            Expr::Return(x) => {
+                self.expr(x, false)?;
+                self.emit(VmOp::ReturnEarly);
             }
-
-            // Unsupported — fall back to tree-walker
-            _ => bail!("vm: unsupported expr"),
         }
         Ok(())
     }
 }
 
-fn vm_arith_add(a: Val, b: Val) -> Result<Val> {
-    match (a, b) {
-        (Val::Int(x), Val::Int(y))     => Ok(Val::Int(x.wrapping_add(y))),
-        (Val::Float(x), Val::Float(y)) => Ok(Val::Float(x + y)),
-        (Val::Text(x), Val::Text(y))   => Ok(Val::Text(x + &y)),
-        _ => bail!("vm: type error in +"),
-    }
-}
-fn vm_arith_sub(a: Val, b: Val) -> Result<Val> {
-    match (a, b) {
-        (Val::Int(x), Val::Int(y))     => Ok(Val::Int(x.wrapping_sub(y))),
-        (Val::Float(x), Val::Float(y)) => Ok(Val::Float(x - y)),
-        _ => bail!("vm: type error in -"),
-    }
-}
-fn vm_arith_mul(a: Val, b: Val) -> Result<Val> {
-    match (a, b) {
-        (Val::Int(x), Val::Int(y))     => Ok(Val::Int(x.wrapping_mul(y))),
-        (Val::Float(x), Val::Float(y)) => Ok(Val::Float(x * y)),
-        _ => bail!("vm: type error in *"),
-    }
-}
-fn vm_arith_div(a: Val, b: Val) -> Result<Val> {
-    match (a, b) {
-        (Val::Int(_, ), Val::Int(0))   => bail!("ERROR_DIV_ZERO"),
-        (Val::Int(x), Val::Int(y))     => Ok(Val::Int(x / y)),
-        (Val::Float(x), Val::Float(y)) => Ok(Val::Float(x / y)),
-        _ => bail!("vm: type error in /"),
-    }
-}
-fn vm_arith_mod(a: Val, b: Val) -> Result<Val> {
-    match (a, b) {
-        (Val::Int(_, ), Val::Int(0)) => bail!("ERROR_DIV_ZERO"),
-        (Val::Int(x), Val::Int(y))   => Ok(Val::Int(x % y)),
-        _ => bail!("vm: type error in %"),
-    }
-}
-fn vm_val_eq(a: &Val, b: &Val) -> bool {
-    match (a, b) {
-        (Val::Int(x), Val::Int(y))     => x == y,
-        (Val::Float(x), Val::Float(y)) => x == y,
-        (Val::Bool(x), Val::Bool(y))   => x == y,
-        (Val::Text(x), Val::Text(y))   => x == y,
-        (Val::Unit, Val::Unit)         => true,
-        _ => false,
-    }
-}
-fn vm_val_lt(a: &Val, b: &Val) -> Result<bool> {
-    match (a, b) {
-        (Val::Int(x), Val::Int(y))     => Ok(x < y),
-        (Val::Float(x), Val::Float(y)) => Ok(x < y),
-        (Val::Text(x), Val::Text(y))   => Ok(x < y),
-        _ => bail!("vm: type error in <"),
-    }
+fn vm_disabled() -> bool {
+    std::env::var("FARD_NO_VM").is_ok_and(|v| !v.is_empty() && v != "0")
 }
 
-fn vm_exec_fn(
-    fns: &Vec<VmCompiledFn>,
-    fn_idx: usize,
-    args: Vec<Val>,
-    tracer: &mut Tracer,
-    loader: &mut ModuleLoader,
+/// Compiles a top-level `fn`; its own name resolves to itself.
+fn vm_compile_fn(
+    name: &str,
+    params: &[Pat],
+    body: &Expr,
+    globals: &Env,
+    imports: &std::collections::HashSet<String>,
 ) -> Result<Val> {
-    let f = &fns[fn_idx];
-    if args.len() != f.n_params {
-        bail!("arity mismatch: expected {} args, got {}", f.n_params, args.len());
-    }
-    let f = &fns[fn_idx];
-    let n_slots = f.n_slots.max(args.len());
-    // Region-based slot allocation
-    // For small fns (≤8 slots): use Vec with pre-reserved capacity from arena
-    // For larger fns: standard allocation
-    let mut slots = if n_slots <= 8 {
-        let mut v = Vec::with_capacity(8);
-        v.resize(n_slots, Val::Unit);
-        v
-    } else {
-        SLOT_ARENA.with(|arena| {
-            let mut a = arena.borrow_mut();
-            if let Some(mut frame) = a.pop() {
-                frame.resize(n_slots, Val::Unit);
-                for v in frame.iter_mut() { *v = Val::Unit; }
-                frame
-            } else {
-                vec![Val::Unit; n_slots]
-            }
-        })
-    };
-    // Populate free variables (sparse — only non-param, non-Unit slots)
-    for (slot_idx, val) in &f.free_vars {
-        if *slot_idx < slots.len() {
-            slots[*slot_idx] = val.clone();
-        }
-    }
-    // Params override initial vals
-    for (i, a) in args.into_iter().enumerate() { slots[i] = a; }
-    // Self-reference slot (cheap — just an index)
-    if let Some(self_slot) = f.self_slot {
-        if self_slot >= slots.len() { slots.resize(self_slot + 1, Val::Unit); }
-        slots[self_slot] = Val::VmFunc(fn_idx);
+    if vm_disabled() {
+        bail!("disabled by FARD_NO_VM");
     }
+    let (proto, _) = VmCompiler::new(globals, imports).compile_fn(name, params, body, Some(name), false)?;
+    Ok(Val::VmFunc(Arc::new(VmClosure { proto, upvals: Vec::new() })))
+}
 
-    let code = fns[fn_idx].code.clone();
-    let mut stack: Vec<Val> = Vec::with_capacity(32);
-    let mut ip = 0usize;
+/// Compiles a top-level `let` right-hand side or expression to a zero-arg chunk.
+fn vm_compile_chunk(
+    name: &str,
+    e: &Expr,
+    globals: &Env,
+    imports: &std::collections::HashSet<String>,
+) -> Result<Arc<VmClosure>> {
+    if vm_disabled() {
+        bail!("disabled by FARD_NO_VM");
+    }
+    let (proto, _) = VmCompiler::new(globals, imports).compile_fn(name, &[], e, None, true)?;
+    Ok(Arc::new(VmClosure { proto, upvals: Vec::new() }))
+}
 
-    loop {
-        match &code[ip] {
-            VmOp::LoadInt(n)   => { stack.push(Val::Int(*n)); ip += 1; }
-            VmOp::LoadFloat(f) => { stack.push(Val::Float(*f)); ip += 1; }
-            VmOp::LoadBool(b)  => { stack.push(Val::Bool(*b)); ip += 1; }
-            VmOp::LoadNull     => { stack.push(Val::Unit); ip += 1; }
-            VmOp::LoadStr(s)   => { stack.push(Val::Text(s.clone())); ip += 1; }
-
-            VmOp::LoadSlot(i) => {
-                let v = slots.get(*i).cloned().unwrap_or(Val::Unit);
-                stack.push(v); ip += 1;
-            }
-            VmOp::StoreSlot(i) => {
-                let v = stack.pop().unwrap_or(Val::Unit);
-                if *i >= slots.len() { slots.resize(*i + 1, Val::Unit); }
-                slots[*i] = v; ip += 1;
-            }
-
-            VmOp::Add => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_add(a, b)?); ip += 1; }
-            VmOp::Sub => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_sub(a, b)?); ip += 1; }
-            VmOp::Mul => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_mul(a, b)?); ip += 1; }
-            VmOp::Div => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_div(a, b)?); ip += 1; }
-            VmOp::Mod => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_mod(a, b)?); ip += 1; }
-            VmOp::Eq  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_eq(&a, &b))); ip += 1; }
-            VmOp::Ne  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(!vm_val_eq(&a, &b))); ip += 1; }
-            VmOp::Lt  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&a, &b)?)); ip += 1; }
-            VmOp::Le  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&a, &b)? || vm_val_eq(&a, &b))); ip += 1; }
-            VmOp::Gt  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&b, &a)?)); ip += 1; }
-            VmOp::Ge  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&b, &a)? || vm_val_eq(&a, &b))); ip += 1; }
-            VmOp::Neg => { let a = stack.pop().unwrap(); match a { Val::Int(n) => stack.push(Val::Int(-n)), Val::Float(f) => stack.push(Val::Float(-f)), _ => bail!("vm: neg type error") } ip += 1; }
-            VmOp::Not => { let a = stack.pop().unwrap(); match a { Val::Bool(b) => stack.push(Val::Bool(!b)), _ => bail!("vm: not type error") } ip += 1; }
-
-            VmOp::Jump(target)        => { ip = *target; }
-            VmOp::JumpIfFalse(target) => {
-                match stack.last() {
-                    Some(Val::Bool(false)) => { ip = *target; }
-                    Some(Val::Bool(true))  => { ip += 1; }
-                    _ => bail!("vm: JumpIfFalse requires bool"),
-                }
-            }
-            VmOp::JumpIfTrue(target) => {
-                match stack.last() {
-                    Some(Val::Bool(true))  => { ip = *target; }
-                    Some(Val::Bool(false)) => { ip += 1; }
-                    _ => bail!("vm: JumpIfTrue requires bool"),
-                }
-            }
-
-            VmOp::Pop => { stack.pop(); ip += 1; }
-
-            VmOp::MakeList(n) => {
-                let len = stack.len();
-                let items: Vec<Val> = stack.drain(len - n ..).collect();
-                stack.push(Val::List(items)); ip += 1;
-            }
-
-            VmOp::MakeRec(n) => {
-                let len = stack.len();
-                let pairs: Vec<Val> = stack.drain(len - n * 2 ..).collect();
-                let mut m = BTreeMap::new();
-                let mut i = 0;
-                while i + 1 < pairs.len() {
-                    let k = match &pairs[i] { Val::Text(s) => s.clone(), _ => bail!("vm: record key must be text") };
-                    m.insert(k, pairs[i+1].clone());
-                    i += 2;
+fn vm_pat_match(p: &VmPat, v: &Val, slots: &mut [Val]) -> bool {
+    match p {
+        VmPat::Wild => true,
+        VmPat::Bind(s) => {
+            slots[*s] = v.clone();
+            true
+        }
+        VmPat::LitInt(i) => matches!(v, Val::Int(j) if j == i),
+        VmPat::LitStr(s) => matches!(v, Val::Text(t) if t == s),
+        VmPat::LitBool(b) => matches!(v, Val::Bool(c) if c == b),
+        VmPat::LitNull => matches!(v, Val::Unit),
+        VmPat::List { items, rest } => match v {
+            Val::List(xs) => {
+                if xs.len() < items.len() {
+                    return false;
+                }
+                for (sub, x) in items.iter().zip(xs) {
+                    if !vm_pat_match(sub, x, slots) {
+                        return false;
+                    }
+                }
+                if let Some(r) = rest {
+                    slots[*r] = Val::List(xs[items.len()..].to_vec());
                 }
-                stack.push(Val::Record(m)); ip += 1;
+                true
             }
-
-            VmOp::GetField(field) => {
-                let obj = stack.pop().unwrap();
-                match obj {
-                    Val::Record(m) => {
-                        let v = m.get(field).cloned().ok_or_else(|| anyhow!("no member '{field}'"))?;
-                        stack.push(v);
+            _ => false,
+        },
+        VmPat::Obj { items, rest } => match v {
+            Val::Record(m) => {
+                for (k, sub) in items {
+                    match m.get(k) {
+                        Some(vv) if vm_pat_match(sub, vv, slots) => {}
+                        _ => return false,
                     }
-                    _ => bail!("vm: GetField on non-record"),
                 }
-                ip += 1;
+                if let Some(r) = rest {
+                    let rm = m.iter()
+                        .filter(|(k, _)| !items.iter().any(|(kk, _)| kk == *k))
+                        .map(|(k, vv)| (k.clone(), vv.clone()))
+                        .collect();
+                    slots[*r] = Val::Record(rm);
+                }
+                true
             }
+            _ => false,
+        },
+    }
+}
 
-            VmOp::MakeClosure(fn_idx) => {
-                stack.push(Val::VmFunc(*fn_idx)); ip += 1;
-            }
+fn vm_bin(op: VmBin, x: Val, y: Val, tracer: &mut Tracer) -> Result<Val> {
+    match (op, &x, &y) {
+        (VmBin::Add, Val::Int(l), Val::Int(r)) => Ok(Val::Int(l + r)),
+        (VmBin::Sub, Val::Int(l), Val::Int(r)) => Ok(Val::Int(l - r)),
+        (VmBin::Mul, Val::Int(l), Val::Int(r)) => Ok(Val::Int(l * r)),
+        (VmBin::Eq, Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l == r)),
+        (VmBin::Ne, Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l != r)),
+        (VmBin::Lt, Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l < r)),
+        (VmBin::Le, Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l <= r)),
+        (VmBin::Gt, Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l > r)),
+        (VmBin::Ge, Val::Int(l), Val::Int(r)) => Ok(Val::Bool(l >= r)),
+        _ => binary_op(op.sym(), x, y, tracer),
+    }
+}
 
-            VmOp::VmCall(n_args) => {
-                let len = stack.len();
-                let args: Vec<Val> = stack.drain(len - n_args ..).collect();
-                let fv = stack.pop().unwrap();
-                let result = vm_dispatch_call(fns, fv, args, tracer, loader)?;
-                stack.push(result); ip += 1;
-            }
+/// Calls a VM function. Like `call` for a `Func`, this is where `?` and `return`
+/// coming out of the body turn back into values.
+fn vm_call(clo: Arc<VmClosure>, args: Vec<Val>, tracer: &mut Tracer, loader: &mut ModuleLoader) -> Result<Val> {
+    let top_level = clo.proto.top_level;
+    let out = vm_run(clo, args, tracer, loader);
+    if top_level {
+        return out;
+    }
+    out.or_else(|err| {
+        if let Some(q) = err.downcast_ref::<QMarkUnwind>() {
+            Ok(mk_result_err(q.err.clone()))
+        } else if err.to_string() == "FARD_EARLY_RETURN" {
+            Ok(RETURN_VAL.with(|cell| cell.borrow_mut().take()).unwrap_or(Val::Unit))
+        } else {
+            Err(err)
+        }
+    })
+}
 
-            VmOp::TryOp => {
-                let v = stack.pop().unwrap();
-                match &v {
-                    Val::Record(m) => {
-                        match m.get("t").and_then(|t| if let Val::Text(s) = t { Some(s.as_str()) } else { None }) {
-                            Some("ok")  => { stack.push(m.get("v").cloned().unwrap_or(Val::Unit)); }
-                            Some("err") => { return Ok(mk_result_err(m.get("e").cloned().unwrap_or(Val::Unit))); }
-                            _ => bail!("vm: ? requires result record with t=ok or t=err"),
+fn vm_run(mut clo: Arc<VmClosure>, mut args: Vec<Val>, tracer: &mut Tracer, loader: &mut ModuleLoader) -> Result<Val> {
+    let mut stack: Vec<Val> = Vec::with_capacity(16);
+    loop {
+        vm_stats_count(true);
+        check_deadline()?;
+        let proto = clo.proto.clone();
+        if args.len() != proto.n_params {
+            bail!("arity mismatch: expected {} args, got {}", proto.n_params, args.len());
+        }
+        let mut slots = args;
+        slots.resize(proto.n_slots, Val::Unit);
+        stack.clear();
+        let code = &proto.code[..];
+        let mut ip = 0usize;
+        // Set by TailCall: the callee replaces this frame
+        let tail: (Arc<VmClosure>, Vec<Val>) = loop {
+            let op = &code[ip];
+            ip += 1;
+            match op {
+                VmOp::Const(v) => stack.push(v.clone()),
+                VmOp::LoadSlot(s) => stack.push(slots[*s].clone()),
+                VmOp::StoreSlot(s) => slots[*s] = vm_pop(&mut stack),
+                VmOp::LoadUpval(i) => stack.push(clo.upvals[*i].clone()),
+                VmOp::LoadSelf => stack.push(Val::VmFunc(clo.clone())),
+                VmOp::LoadGlobal(n) => {
+                    let v = proto.globals.get(n).ok_or_else(|| unbound_var(n, &proto.globals))?;
+                    stack.push(v);
+                }
+                VmOp::Bin(op) => {
+                    let y = vm_pop(&mut stack);
+                    let x = vm_pop(&mut stack);
+                    stack.push(vm_bin(*op, x, y, tracer)?);
+                }
+                VmOp::BinOther(op) => {
+                    let y = vm_pop(&mut stack);
+                    let x = vm_pop(&mut stack);
+                    stack.push(binary_op(op, x, y, tracer)?);
+                }
+                VmOp::Unary(op) => {
+                    let v = vm_pop(&mut stack);
+                    stack.push(unary_op(op, v)?);
+                }
+                VmOp::Get(k) => {
+                    let o = vm_pop(&mut stack);
+                    stack.push(get_member(o, k, tracer, loader)?);
+                }
+                VmOp::Index => {
+                    let i = vm_pop(&mut stack);
+                    let v = vm_pop(&mut stack);
+                    stack.push(index_val(v, i)?);
+                }
+                VmOp::Stringify => {
+                    let v = vm_pop(&mut stack);
+                    stack.push(Val::Text(interp_text(v)));
+                }
+                VmOp::Concat(n) => {
+                    let mut out = String::new();
+                    for part in stack.drain(stack.len() - n..) {
+                        if let Val::Text(s) = part {
+                            out.push_str(&s);
                         }
                     }
-                    _ => bail!("vm: ? requires result record"),
+                    stack.push(Val::Text(out));
                 }
-                ip += 1;
-            }
-
-            VmOp::Return => {
-                let result = stack.pop().unwrap_or(Val::Unit);
-                if slots.capacity() > 8 {
-                    SLOT_ARENA.with(|arena| arena.borrow_mut().push(slots));
+                VmOp::MakeList(n) => {
+                    let items = stack.split_off(stack.len() - n);
+                    stack.push(Val::List(items));
+                }
+                VmOp::MakeRec(keys) => {
+                    let vals = stack.split_off(stack.len() - keys.len());
+                    stack.push(Val::Record(keys.iter().cloned().zip(vals).collect()));
+                }
+                VmOp::MakeClosure(pi, captures) => {
+                    let upvals = captures.iter().map(|from| match from {
+                        VmVar::Slot(s) => slots[*s].clone(),
+                        VmVar::Upval(i) => clo.upvals[*i].clone(),
+                        VmVar::SelfFn => Val::VmFunc(clo.clone()),
+                    }).collect();
+                    stack.push(Val::VmFunc(Arc::new(VmClosure { proto: proto.protos[*pi].clone(), upvals })));
+                }
+                VmOp::Call(n) => {
+                    let call_args = stack.split_off(stack.len() - n);
+                    let v = match vm_pop(&mut stack) {
+                        Val::VmFunc(c) => vm_call(c, call_args, tracer, loader)?,
+                        f => call(f, call_args, tracer, loader)?,
+                    };
+                    stack.push(v);
+                }
+                VmOp::TailCall(n) => {
+                    let call_args = stack.split_off(stack.len() - n);
+                    match vm_pop(&mut stack) {
+                        Val::VmFunc(c) => break (c, call_args),
+                        f => return call(f, call_args, tracer, loader),
+                    }
+                }
+                VmOp::CallBuiltin(b, n) => {
+                    let call_args = stack.split_off(stack.len() - n);
+                    stack.push(call_builtin(b.clone(), call_args, tracer, loader)?);
+                }
+                VmOp::NamedCall(names) => {
+                    let vals = stack.split_off(stack.len() - names.len());
+                    let f = vm_pop(&mut stack);
+                    let params = named_call_params(&f)?;
+                    let ordered = order_named_args(&params, names.iter().cloned().zip(vals).collect())?;
+                    stack.push(call(f, ordered, tracer, loader)?);
+                }
+                VmOp::Jump(t) => ip = *t,
+                VmOp::JumpIfNot(t) => match vm_pop(&mut stack) {
+                    Val::Bool(true) => {}
+                    Val::Bool(false) => ip = *t,
+                    _ => bail!("if cond must be bool"),
+                },
+                VmOp::Match(pi, t) => {
+                    let v = vm_pop(&mut stack);
+                    if !vm_pat_match(&proto.pats[*pi], &v, &mut slots) {
+                        ip = *t;
+                    }
+                }
+                VmOp::Guard(t, span) => match vm_pop(&mut stack) {
+                    Val::Bool(true) => {}
+                    Val::Bool(false) => ip = *t,
+                    _ => return Err(guard_not_bool(span.clone())),
+                },
+                VmOp::Bind(pi, what) => {
+                    let v = vm_pop(&mut stack);
+                    if !vm_pat_match(&proto.pats[*pi], &v, &mut slots) {
+                        bail!("{} {} pattern did not match", ERROR_PAT_MISMATCH, what);
+                    }
+                }
+                VmOp::NoMatch => bail!("{} no match", ERROR_MATCH_NO_ARM),
+                VmOp::Try => {
+                    let rv = vm_pop(&mut stack);
+                    match qmark_split(&rv)? {
+                        Ok(v) => stack.push(v),
+                        Err(e) if proto.top_level => return Err(QMarkUnwind { err: e }.into()),
+                        Err(e) => return Ok(mk_result_err(e)),
+                    }
+                }
+                VmOp::While => {
+                    let body_fn = vm_pop(&mut stack);
+                    let cond_fn = vm_pop(&mut stack);
+                    let init = vm_pop(&mut stack);
+                    stack.push(run_while(init, cond_fn, body_fn, tracer, loader)?);
+                }
+                VmOp::Pop => {
+                    stack.pop();
+                }
+                VmOp::Return => return Ok(vm_pop(&mut stack)),
+                VmOp::ReturnEarly => {
+                    let v = vm_pop(&mut stack);
+                    if proto.top_level {
+                        RETURN_VAL.with(|cell| *cell.borrow_mut() = Some(v));
+                        bail!("FARD_EARLY_RETURN");
+                    }
+                    return Ok(v);
                 }
-                return Ok(result);
             }
-            VmOp::Halt   => { break; }
-        }
-    }
-    let result = stack.pop().unwrap_or(Val::Unit);
-    if slots.capacity() > 8 {
-        SLOT_ARENA.with(|arena| arena.borrow_mut().push(slots));
+        };
+        (clo, args) = tail;
     }
-    Ok(result)
 }
 
-fn vm_dispatch_call(
-    fns: &Vec<VmCompiledFn>,
-    fv: Val,
-    args: Vec<Val>,
-    tracer: &mut Tracer,
-    loader: &mut ModuleLoader,
-) -> Result<Val> {
-    match fv {
-        Val::VmFunc(fn_idx) => {
-            vm_exec_fn(fns, fn_idx, args, tracer, loader)
-        }
-        Val::Func(f)        => call(Val::Func(f), args, tracer, loader),
-        Val::Builtin(b)     => call_builtin(b, args, tracer, loader),
-        Val::BoundMethod(recv, func) => {
-            let mut full = vec![*recv];
-            full.extend(args);
-            vm_dispatch_call(&vec![], *func, full, tracer, loader)
-        }
-        _ => bail!("vm: call on non-function"),
-    }
+fn vm_pop(stack: &mut Vec<Val>) -> Val {
+    stack.pop().expect("vm: stack underflow")
 }
 
-/// Try to compile and run expr in the VM.
-/// Returns None if expr uses unsupported features — caller falls back to tree-walker.
-fn try_vm_eval(
-    expr: &Expr,
-    env: &mut Env,
-    tracer: &mut Tracer,
-    loader: &mut ModuleLoader,
-) -> Option<Result<Val>> {
-    let mut fns: Vec<VmCompiledFn> = Vec::new();
-    let mut compiler = VmCompiler::new();
+/// One compiled unit for --vm-stats: a top-level fn, let or expression.
+struct VmUnitStat {
+    module: String,
+    unit: String,
+    fallback: Option<String>,
+}
 
-    // Pre-populate slots from current env so Var lookups work
-    let env_keys = env.keys();
-    for k in &env_keys {
-        compiler.slot_of(k);
-    }
+static VM_STATS_ON: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+static VM_CALLS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+static TREE_CALLS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+static VM_UNITS: Mutex<Vec<VmUnitStat>> = Mutex::new(Vec::new());
+
+fn vm_stats_reset(on: bool) {
+    use std::sync::atomic::Ordering;
+    VM_STATS_ON.store(on, Ordering::Relaxed);
+    VM_CALLS.store(0, Ordering::Relaxed);
+    TREE_CALLS.store(0, Ordering::Relaxed);
+    VM_UNITS.lock().unwrap().clear();
+}
 
-    if compiler.compile(expr, &mut fns).is_err() {
-        return None;
+/// Counts a call on the VM or, with `vm == false`, on the tree-walker.
+fn vm_stats_count(vm: bool) {
+    use std::sync::atomic::Ordering;
+    if VM_STATS_ON.load(Ordering::Relaxed) {
+        let c = if vm { &VM_CALLS } else { &TREE_CALLS };
+        c.fetch_add(1, Ordering::Relaxed);
     }
-    compiler.emit(VmOp::Return);
-
-    let n_slots = compiler.slot_names.len();
-    let slot_names = compiler.slot_names.clone();
-
-    fns.push(VmCompiledFn {
-        code: compiler.code,
-        n_slots,
-        n_params: 0,
-        slot_names: slot_names.clone(),
-                    self_slot: None,
-                    free_vars: Vec::new(),
-                });
-    let entry_idx = fns.len() - 1;
+}
 
-    // Build initial slot values from env
-    let mut slots = vec![Val::Unit; n_slots];
-    for (i, name) in slot_names.iter().enumerate() {
-        if let Some(v) = env.get(name) {
-            slots[i] = v;
-        }
+fn vm_stats_unit(module: &str, unit: String, fallback: Option<String>) {
+    if VM_STATS_ON.load(std::sync::atomic::Ordering::Relaxed) {
+        VM_UNITS.lock().unwrap().push(VmUnitStat { module: module.to_string(), unit, fallback });
     }
-
-    // Execute: inject pre-loaded slots directly
-    let code = fns[entry_idx].code.clone();
-    let mut stack: Vec<Val> = Vec::with_capacity(32);
-    let mut ip = 0usize;
-
-    let result = (|| {
-        loop {
-            match &code[ip] {
-                VmOp::LoadInt(n)   => { stack.push(Val::Int(*n)); ip += 1; }
-                VmOp::LoadFloat(f) => { stack.push(Val::Float(*f)); ip += 1; }
-                VmOp::LoadBool(b)  => { stack.push(Val::Bool(*b)); ip += 1; }
-                VmOp::LoadNull     => { stack.push(Val::Unit); ip += 1; }
-                VmOp::LoadStr(s)   => { stack.push(Val::Text(s.clone())); ip += 1; }
-
-                VmOp::LoadSlot(i) => {
-                    stack.push(slots.get(*i).cloned().unwrap_or(Val::Unit)); ip += 1;
-                }
-                VmOp::StoreSlot(i) => {
-                    let v = stack.pop().unwrap_or(Val::Unit);
-                    if *i >= slots.len() { slots.resize(*i + 1, Val::Unit); }
-                    slots[*i] = v; ip += 1;
-                }
-                VmOp::Add => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_add(a, b)?); ip += 1; }
-                VmOp::Sub => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_sub(a, b)?); ip += 1; }
-                VmOp::Mul => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_mul(a, b)?); ip += 1; }
-                VmOp::Div => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_div(a, b)?); ip += 1; }
-                VmOp::Mod => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(vm_arith_mod(a, b)?); ip += 1; }
-                VmOp::Eq  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_eq(&a, &b))); ip += 1; }
-                VmOp::Ne  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(!vm_val_eq(&a, &b))); ip += 1; }
-                VmOp::Lt  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&a, &b)?)); ip += 1; }
-                VmOp::Le  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&a, &b)? || vm_val_eq(&a, &b))); ip += 1; }
-                VmOp::Gt  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&b, &a)?)); ip += 1; }
-                VmOp::Ge  => { let b = stack.pop().unwrap(); let a = stack.pop().unwrap(); stack.push(Val::Bool(vm_val_lt(&b, &a)? || vm_val_eq(&a, &b))); ip += 1; }
-                VmOp::Neg => { let a = stack.pop().unwrap(); match a { Val::Int(n) => stack.push(Val::Int(-n)), Val::Float(f) => stack.push(Val::Float(-f)), _ => bail!("vm: neg type error") } ip += 1; }
-                VmOp::Not => { let a = stack.pop().unwrap(); match a { Val::Bool(b) => stack.push(Val::Bool(!b)), _ => bail!("vm: not type error") } ip += 1; }
-                VmOp::Jump(target)        => { ip = *target; }
-                VmOp::JumpIfFalse(target) => { match stack.last() { Some(Val::Bool(false)) => { ip = *target; } Some(Val::Bool(true)) => { ip += 1; } _ => bail!("vm: JumpIfFalse needs bool") } }
-                VmOp::JumpIfTrue(target)  => { match stack.last() { Some(Val::Bool(true))  => { ip = *target; } Some(Val::Bool(false)) => { ip += 1; } _ => bail!("vm: JumpIfTrue needs bool") } }
-                VmOp::Pop => { stack.pop(); ip += 1; }
-                VmOp::MakeList(n) => { let len = stack.len(); let items: Vec<Val> = stack.drain(len - n ..).collect(); stack.push(Val::List(items)); ip += 1; }
-                VmOp::MakeRec(n) => {
-                    let len = stack.len(); let pairs: Vec<Val> = stack.drain(len - n * 2 ..).collect();
-                    let mut m = BTreeMap::new(); let mut i = 0;
-                    while i + 1 < pairs.len() { let k = match &pairs[i] { Val::Text(s) => s.clone(), _ => bail!("vm: rec key must be text") }; m.insert(k, pairs[i+1].clone()); i += 2; }
-                    stack.push(Val::Record(m)); ip += 1;
-                }
-                VmOp::GetField(field) => {
-                    let obj = stack.pop().unwrap();
-                    match obj { Val::Record(m) => { let v = m.get(field).cloned().ok_or_else(|| anyhow!("no member '{field}'"))?; stack.push(v); } _ => bail!("vm: GetField on non-record") }
-                    ip += 1;
-                }
-                VmOp::MakeClosure(fn_idx) => { stack.push(Val::VmFunc(*fn_idx)); ip += 1; }
-                VmOp::VmCall(n_args) => {
-                    let len = stack.len(); let args: Vec<Val> = stack.drain(len - n_args ..).collect();
-                    let fv = stack.pop().unwrap();
-                    let result = vm_dispatch_call(&fns, fv, args, tracer, loader)?;
-                    stack.push(result); ip += 1;
-                }
-                VmOp::TryOp => {
-                    let v = stack.pop().unwrap();
-                    match &v { Val::Record(m) => { match m.get("t").and_then(|t| if let Val::Text(s) = t { Some(s.as_str()) } else { None }) { Some("ok") => { stack.push(m.get("v").cloned().unwrap_or(Val::Unit)); } Some("err") => { return Ok(mk_result_err(m.get("e").cloned().unwrap_or(Val::Unit))); } _ => bail!("vm: ? needs result") } } _ => bail!("vm: ? needs record") }
-                    ip += 1;
-                }
-                VmOp::Return => { return Ok(stack.pop().unwrap_or(Val::Unit)); }
-                VmOp::Halt   => { break; }
-            }
-        }
-        Ok(stack.pop().unwrap_or(Val::Unit))
-    })();
-
-    Some(result)
 }
 
+/// Prints the --vm-stats summary to stderr and writes it to `vm_stats.json`.
+fn vm_stats_report(out_dir: &Path) -> Result<()> {
+    use std::sync::atomic::Ordering;
+    let units = VM_UNITS.lock().unwrap();
+    let vm_calls = VM_CALLS.load(Ordering::Relaxed);
+    let tree_calls = TREE_CALLS.load(Ordering::Relaxed);
+    let fallbacks: Vec<&VmUnitStat> = units.iter().filter(|u| u.fallback.is_some()).collect();
+    eprintln!(
+        "vm: {} of {} units compiled, {} fell back to the tree-walker",
+        units.len() - fallbacks.len(),
+        units.len(),
+        fallbacks.len()
+    );
+    eprintln!("vm: {} calls on the VM, {} on the tree-walker", vm_calls, tree_calls);
+    for u in &fallbacks {
+        eprintln!("  fallback {}: {}: {}", u.module, u.unit, u.fallback.as_deref().unwrap_or(""));
+    }
+    let mut m = Map::new();
+    m.insert("units".to_string(), J::Int(units.len() as i64));
+    m.insert("compiled".to_string(), J::Int((units.len() - fallbacks.len()) as i64));
+    m.insert("fallbacks".to_string(), J::Array(fallbacks.iter().map(|u| {
+        let mut f = Map::new();
+        f.insert("module".to_string(), J::Str(u.module.clone()));
+        f.insert("unit".to_string(), J::Str(u.unit.clone()));
+        f.insert("reason".to_string(), J::Str(u.fallback.clone().unwrap_or_default()));
+        J::Object(f)
+    }).collect()));
+    m.insert("vm_calls".to_string(), J::Int(vm_calls as i64));
+    m.insert("tree_calls".to_string(), J::Int(tree_calls as i64));
+    fs::write(out_dir.join("vm_stats.json"), json_to_string(&J::Object(m)).into_bytes())?;
+    Ok(())
+}
 
 fn call_builtin(
     b: Builtin,
@@ -5276,6 +8276,77 @@ fn call_builtin(
             let bs = hex_decode("89504e470d0a1a0a0000000d4948445200000001000000010802000000907753de0000000f494441547801010400fbff00ff0000030101008d1de5820000000049454e44ae426082")?;
             Ok(Val::Bytes(bs))
         }
+        Builtin::PngDecode => {
+            if args.len() != 1 { bail!("ERROR_BADARG png.decode expects 1 arg"); }
+            let bytes = match &args[0] {
+                Val::Bytes(b) => b,
+                _ => bail!("ERROR_BADARG png.decode expects bytes"),
+            };
+            let img = png_decode(bytes)?;
+            let cid = sha256_bytes(bytes);
+            register_input(tracer, &cid, bytes)?;
+            Ok(png_image_val(img, Some(cid)))
+        }
+        Builtin::PngRead => {
+            if args.len() != 1 { bail!("ERROR_BADARG png.read expects 1 arg"); }
+            let path = match &args[0] {
+                Val::Text(s) => s.clone(),
+                _ => bail!("ERROR_BADARG png.read expects a path"),
+            };
+            fs_sandbox_check(&path)?;
+            cap_check(tracer, "fs_read", "png.read", &path)?;
+            let bytes = fs::read(&path).map_err(|e| anyhow!("ERROR_IO png.read {}: {}", path, e))?;
+            let img = png_decode(&bytes)?;
+            let cid = register_input(tracer, &path, &bytes)?;
+            Ok(png_image_val(img, Some(cid)))
+        }
+        Builtin::PngEncode => {
+            if args.len() != 1 { bail!("ERROR_BADARG png.encode expects 1 arg"); }
+            Ok(Val::Bytes(png_encode(&png_image_from_val(&args[0], "png.encode")?)?))
+        }
+        Builtin::PngPixel => {
+            if args.len() != 3 { bail!("ERROR_BADARG png.pixel expects 3 args"); }
+            let img = png_image_from_val(&args[0], "png.pixel")?;
+            let at = png_offset(&img, &args[1], &args[2], "png.pixel")?;
+            Ok(Val::List(img.pixels[at..at + img.channels].iter().map(|b| Val::Int(*b as i64)).collect()))
+        }
+        Builtin::PngRow => {
+            if args.len() != 2 { bail!("ERROR_BADARG png.row expects 2 args"); }
+            let img = png_image_from_val(&args[0], "png.row")?;
+            let at = png_offset(&img, &Val::Int(0), &args[1], "png.row")?;
+            let row = &img.pixels[at..at + img.width * img.channels];
+            Ok(Val::List(
+                row.chunks_exact(img.channels)
+                    .map(|px| Val::List(px.iter().map(|b| Val::Int(*b as i64)).collect()))
+                    .collect(),
+            ))
+        }
+        Builtin::PngSetPixel => {
+            if args.len() != 4 { bail!("ERROR_BADARG png.set_pixel expects 4 args"); }
+            let mut img = png_image_from_val(&args[0], "png.set_pixel")?;
+            let at = png_offset(&img, &args[1], &args[2], "png.set_pixel")?;
+            let px = match &args[3] {
+                Val::List(xs) if xs.len() == img.channels => xs,
+                _ => bail!("ERROR_BADARG png.set_pixel expects a list of {} samples", img.channels),
+            };
+            for (i, x) in px.iter().enumerate() {
+                img.pixels[at + i] = match x {
+                    Val::Int(n) if (0..=255).contains(n) => *n as u8,
+                    _ => bail!("ERROR_BADARG png.set_pixel samples must be ints in 0..=255"),
+                };
+            }
+            Ok(png_image_val(img, None))
+        }
+        Builtin::PngConvert => {
+            if args.len() != 2 { bail!("ERROR_BADARG png.convert expects 2 args"); }
+            let img = png_image_from_val(&args[0], "png.convert")?;
+            let channels = match &args[1] {
+                Val::Text(f) => png_format_channels(f),
+                _ => None,
+            }
+            .ok_or_else(|| anyhow!("ERROR_BADARG png.convert format must be gray, gray_alpha, rgb or rgba"))?;
+            Ok(png_image_val(png_convert(&img, channels), None))
+        }
 
         Builtin::IntAdd => {
             if args.len() != 2 {
@@ -5824,11 +8895,8 @@ fn call_builtin(
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG http.get url must be text"),
             };
-            match ureq::get(&url).call() {
-                Ok(resp) => Ok(http_response_to_val(resp)?),
-                Err(ureq::Error::Status(code, resp)) => Ok(http_response_to_val_with_status(code, resp)?),
-                Err(e) => bail!("ERROR_HTTP_GET {}", e),
-            }
+            cap_check(tracer, "http", "http.get", &url)?;
+            http_call("http.get", &args, "GET", &url, &[], None, tracer)
         }
         Builtin::HttpPost => {
             // http.post(url, body_text) -> {status: int, body: text, headers: record}
@@ -5841,11 +8909,8 @@ fn call_builtin(
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG http.post body must be text"),
             };
-            match ureq::post(&url).send_string(&body) {
-                Ok(resp) => Ok(http_response_to_val(resp)?),
-                Err(ureq::Error::Status(code, resp)) => Ok(http_response_to_val_with_status(code, resp)?),
-                Err(e) => bail!("ERROR_HTTP_POST {}", e),
-            }
+            cap_check(tracer, "http", "http.post", &url)?;
+            http_call("http.post", &args, "POST", &url, &[], Some(&body), tracer)
         }
         Builtin::HttpRequest => {
             // http.request({method, url, body?, headers?}) -> {status, body, headers}
@@ -5862,33 +8927,27 @@ fn call_builtin(
                 Some(Val::Text(s)) => s.clone(),
                 _ => bail!("ERROR_BADARG http.request missing url"),
             };
-            let mut req = ureq::request(&method, &url);
-            if let Some(Val::Record(hdrs)) = rec.get("headers") {
-                for (k, v) in hdrs {
-                    if let Val::Text(vt) = v {
-                        req = req.set(k, vt);
-                    }
-                }
-            }
-            let result = if let Some(Val::Text(body)) = rec.get("body") {
-                req.send_string(body)
-            } else {
-                req.call()
+            cap_check(tracer, "http", "http.request", &url)?;
+            let headers: Vec<(String, String)> = match rec.get("headers") {
+                Some(Val::Record(hdrs)) => hdrs.iter()
+                    .filter_map(|(k, v)| match v { Val::Text(vt) => Some((k.clone(), vt.clone())), _ => None })
+                    .collect(),
+                _ => vec![],
             };
-            match result {
-                Ok(resp) => Ok(http_response_to_val(resp)?),
-                Err(ureq::Error::Status(code, resp)) => Ok(http_response_to_val_with_status(code, resp)?),
-                Err(e) => bail!("ERROR_HTTP_REQUEST {}", e),
-            }
+            let body = match rec.get("body") {
+                Some(Val::Text(b)) => Some(b.as_str()),
+                _ => None,
+            };
+            http_call("http.request", &args, &method, &url, &headers, body, tracer)
         }
         // --- std/time ---
-        Builtin::TimeNow => {
+        Builtin::TimeNow => oracle_answer("time.now", &args, tracer, || {
             let secs = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs();
             Ok(Val::Int(secs as i64))
-        }
+        }),
         Builtin::TimeParse => {
             if args.len() != 1 { bail!("ERROR_BADARG time.parse expects 1 arg"); }
             match &args[0] {
@@ -6171,7 +9230,7 @@ fn call_builtin(
         }
         Builtin::RandUuidV4 => {
             if args.len() != 0 { bail!("ERROR_RUNTIME rand.uuid_v4 expects 0 args"); }
-            Ok(Val::Text(valuecore::uuid::new_v4()))
+            oracle_answer("rand.uuid_v4", &args, tracer, || Ok(Val::Text(valuecore::uuid::new_v4())))
         }
         Builtin::ListLen => {
             match args.first() {
@@ -6679,19 +9738,15 @@ fn call_builtin(
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG witness.verify expects text run_id"),
             };
-            let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-            let receipt_path = format!("receipts/sha256_{}.json", hex);
-            // Try local first, then fall back to FARD_REGISTRY_URL if set
-            let receipt_bytes_opt: Option<Vec<u8>> = std::fs::read(&receipt_path).ok().or_else(|| {
-                if let Ok(base_url) = std::env::var("FARD_REGISTRY_URL") {
-                    let url = format!("{}/receipt/{}", base_url.trim_end_matches('/'), run_id);
-                    ureq::get(&url).call().ok()
-                        .and_then(|r| r.into_string().ok())
-                        .map(|s| s.into_bytes())
-                } else {
-                    None
+            let receipt_bytes_opt = match receipt_store().get(&run_id) {
+                Ok(b) => b,
+                Err(e) => {
+                    let mut m = BTreeMap::new();
+                    m.insert("e".to_string(), Val::Text(format!("receipt store error: {:#}", e)));
+                    m.insert("t".to_string(), Val::Text("err".to_string()));
+                    return Ok(Val::Record(m));
                 }
-            });
+            };
             match receipt_bytes_opt {
                 None => {
                     let mut m = BTreeMap::new();
@@ -6721,6 +9776,9 @@ fn call_builtin(
                                 m.insert("t".to_string(), Val::Text("err".to_string()));
                                 return Ok(Val::Record(m));
                             }
+                            if let Err(e) = check_parent_stdlib_root(&format!("run {}", run_id), receipt_stdlib_root(&receipt)) {
+                                return Ok(mk_result_err(Val::Text(e)));
+                            }
                             let val = jval_to_val(&receipt);
                             let mut m = BTreeMap::new();
                             m.insert("t".to_string(), Val::Text("ok".to_string()));
@@ -6742,21 +9800,16 @@ fn call_builtin(
             while let Some(run_id) = queue.pop() {
                 if visited.contains(&run_id) { continue; }
                 visited.insert(run_id.clone());
-                let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-                let receipt_path = format!("receipts/sha256_{}.json", hex);
-                let bytes_opt: Option<Vec<u8>> = std::fs::read(&receipt_path).ok().or_else(|| {
-                    if let Ok(base_url) = std::env::var("FARD_REGISTRY_URL") {
-                        let url = format!("{}/receipt/{}", base_url.trim_end_matches('/'), run_id);
-                        ureq::get(&url).call().ok()
-                            .and_then(|r| r.into_string().ok())
-                            .map(|s| s.into_bytes())
-                    } else { None }
-                });
+                let bytes_opt = receipt_store().get(&run_id);
                 let bytes = match bytes_opt {
-                    Some(b) => b,
-                    None => {
+                    Ok(Some(b)) => b,
+                    missing => {
+                        let reason = match missing {
+                            Err(e) => format!("receipt store error: {:#}", e),
+                            _ => "receipt not found".to_string(),
+                        };
                         let mut em = BTreeMap::new();
-                        em.insert("reason".to_string(), Val::Text(format!("receipt not found")));
+                        em.insert("reason".to_string(), Val::Text(reason));
                         em.insert("run_id".to_string(), Val::Text(run_id));
                         let mut m = BTreeMap::new();
                         m.insert("e".to_string(), Val::Record(em));
@@ -6781,9 +9834,14 @@ fn call_builtin(
                     J::Object(rm) => rm.get("run_id").and_then(|v| if let J::Str(s) = v { Some(s.clone()) } else { None }).unwrap_or_default(),
                     _ => String::new(),
                 };
-                if stored != run_id {
+                let reason = if stored != run_id {
+                    Some(format!("run_id mismatch: stored={}", stored))
+                } else {
+                    check_parent_stdlib_root("receipt", receipt_stdlib_root(&receipt)).err()
+                };
+                if let Some(reason) = reason {
                     let mut em = BTreeMap::new();
-                    em.insert("reason".to_string(), Val::Text(format!("run_id mismatch: stored={}", stored)));
+                    em.insert("reason".to_string(), Val::Text(reason));
                     em.insert("run_id".to_string(), Val::Text(run_id));
                     let mut m = BTreeMap::new();
                     m.insert("e".to_string(), Val::Record(em));
@@ -6811,6 +9869,7 @@ fn call_builtin(
         Builtin::FfiOpen => {
             if args.len() != 1 { bail!("ERROR_BADARG ffi.open expects 1 arg"); }
             let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG ffi.open expects text path") };
+            cap_check(tracer, "ffi", "ffi.open", &path)?;
             let mut m = BTreeMap::new();
             match unsafe { libloading::Library::new(&path) } {
                 Ok(lib) => {
@@ -6829,8 +9888,8 @@ fn call_builtin(
             if args.len() != 3 { bail!("ERROR_BADARG ffi.call expects 3 args"); }
             // Emit oracle boundary warning — ffi.call is non-deterministic
             let _ = tracer.emit_raw(&format!(
-                r#"{{"t":"ffi_oracle","symbol":"{}","boundary":"non-deterministic"}}"#,
-                match args.get(1) { Some(Val::Text(s)) => s.as_str(), _ => "unknown" }
+                r#"{{"t":"ffi_oracle","symbol":{},"boundary":"non-deterministic"}}"#,
+                json_to_string(&J::Str(match args.get(1) { Some(Val::Text(s)) => s.clone(), _ => "unknown".to_string() }))
             ));
             let handle = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG ffi.call: handle must be text") };
             let symbol = match &args[1] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG ffi.call: symbol must be text") };
@@ -6878,6 +9937,137 @@ fn call_builtin(
             }
             Ok(Val::Record(m))
         }
+        Builtin::FfiBind => {
+            if args.len() != 3 { bail!("ERROR_BADARG ffi.bind expects 3 args: (handle, symbol, {{args, ret}})"); }
+            let handle = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG ffi.bind: handle must be text") };
+            let symbol = match &args[1] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG ffi.bind: symbol must be text") };
+            ffi_parse_sig(&args[2])?;
+            FFI_LIBS.with(|libs| -> Result<()> {
+                let libs = libs.borrow();
+                let lib = libs.get(&handle).ok_or_else(|| anyhow!("ERROR_FFI handle not found: {}", handle))?;
+                unsafe { lib.get::<unsafe extern "C" fn()>(symbol.as_bytes()) }
+                    .map_err(|e| anyhow!("ERROR_FFI {}: {}", symbol, e))?;
+                Ok(())
+            })?;
+            let mut binding = BTreeMap::new();
+            binding.insert("handle".to_string(), Val::Text(handle));
+            binding.insert("sig".to_string(), args[2].clone());
+            binding.insert("symbol".to_string(), Val::Text(symbol));
+            Ok(Val::BoundMethod(Box::new(Val::Record(binding)), Box::new(Val::Builtin(Builtin::FfiBoundCall))))
+        }
+        Builtin::FfiBoundCall => {
+            let (binding, call_args) = match args.split_first() {
+                Some((Val::Record(b), rest)) => (b, rest),
+                _ => bail!("ERROR_BADARG ffi bound call without binding"),
+            };
+            let handle = match binding.get("handle") { Some(Val::Text(s)) => s.clone(), _ => bail!("ERROR_BADARG ffi binding missing handle") };
+            let symbol = match binding.get("symbol") { Some(Val::Text(s)) => s.clone(), _ => bail!("ERROR_BADARG ffi binding missing symbol") };
+            let sig = ffi_parse_sig(binding.get("sig").unwrap_or(&Val::Unit))?;
+            let _ = tracer.emit_raw(&format!(
+                r#"{{"t":"ffi_oracle","symbol":{},"boundary":"non-deterministic"}}"#,
+                json_to_string(&J::Str(symbol.clone()))
+            ));
+            ffi_call_bound(&handle, &symbol, &sig, call_args)
+        }
+        Builtin::SqliteOpen => {
+            if args.is_empty() || args.len() > 2 { bail!("ERROR_ARITY sqlite.open expects (path) or (path, {{readonly}})"); }
+            let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG sqlite.open path must be text") };
+            let readonly = match args.get(1) {
+                None => false,
+                Some(Val::Record(o)) => matches!(o.get("readonly"), Some(Val::Bool(true))),
+                Some(_) => bail!("ERROR_BADARG sqlite.open options must be a record"),
+            };
+            let conn = if path == ":memory:" {
+                rusqlite::Connection::open_in_memory()
+            } else {
+                fs_sandbox_check(&path)?;
+                if readonly {
+                    cap_check(tracer, "fs_read", "sqlite.open", &path)?;
+                    rusqlite::Connection::open_with_flags(&path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                } else {
+                    cap_check(tracer, "fs_write", "sqlite.open", &path)?;
+                    rusqlite::Connection::open(&path)
+                }
+            };
+            match conn {
+                Ok(conn) => {
+                    let handle = format!("sqlite:{}", SQLITE_SEQ.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1);
+                    SQLITE_DBS.with(|dbs| dbs.borrow_mut().insert(handle.clone(), Arc::new(Mutex::new(conn))));
+                    let mut m = BTreeMap::new();
+                    m.insert("handle".to_string(), Val::Text(handle));
+                    m.insert("path".to_string(), Val::Text(path));
+                    Ok(mk_result_ok(Val::Record(m)))
+                }
+                Err(e) => Ok(mk_result_err(Val::Text(format!("ERROR_SQLITE {}: {}", path, e)))),
+            }
+        }
+        Builtin::SqliteClose => {
+            if args.len() != 1 { bail!("ERROR_ARITY sqlite.close expects 1 arg"); }
+            let (handle, _) = sqlite_target(&args[0], "sqlite.close")?;
+            SQLITE_DBS.with(|dbs| dbs.borrow_mut().remove(&handle));
+            Ok(Val::Unit)
+        }
+        Builtin::SqliteExec | Builtin::SqliteQuery => {
+            let (op, rows) = if matches!(b, Builtin::SqliteQuery) { ("sqlite.query", true) } else { ("sqlite.exec", false) };
+            if args.len() != 2 && args.len() != 3 { bail!("ERROR_ARITY {} expects (db, sql) or (db, sql, params)", op); }
+            let (handle, path) = sqlite_target(&args[0], op)?;
+            let sql = match &args[1] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG {} sql must be text", op) };
+            // A query always goes through a prepared statement, even without parameters
+            let none = Val::List(Vec::new());
+            let params = args.get(2).or(if rows { Some(&none) } else { None });
+            sqlite_run(tracer, op, &handle, &path, &sql, params, rows)
+        }
+        Builtin::SqlitePrepare => {
+            if args.len() != 2 { bail!("ERROR_ARITY sqlite.prepare expects (db, sql)"); }
+            let (handle, path) = sqlite_target(&args[0], "sqlite.prepare")?;
+            let sql = match &args[1] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG sqlite.prepare sql must be text") };
+            let compiled = match SQLITE_DBS.with(|dbs| dbs.borrow().get(&handle).cloned()) {
+                Some(db) => db.lock().unwrap_or_else(|e| e.into_inner()).prepare_cached(&sql).map(|_| ()).map_err(|e| format!("ERROR_SQLITE {}", e)),
+                None => Err(format!("ERROR_SQLITE database is closed: {}", path)),
+            };
+            match compiled {
+                Ok(()) => {
+                    let mut m = BTreeMap::new();
+                    m.insert("handle".to_string(), Val::Text(handle));
+                    m.insert("path".to_string(), Val::Text(path));
+                    m.insert("sql".to_string(), Val::Text(sql));
+                    Ok(mk_result_ok(Val::Record(m)))
+                }
+                Err(e) => Ok(mk_result_err(Val::Text(e))),
+            }
+        }
+        Builtin::SqliteRun | Builtin::SqliteAll => {
+            let (op, rows) = if matches!(b, Builtin::SqliteAll) { ("sqlite.all", true) } else { ("sqlite.run", false) };
+            if args.is_empty() || args.len() > 2 { bail!("ERROR_ARITY {} expects (stmt) or (stmt, params)", op); }
+            let (handle, path) = sqlite_target(&args[0], op)?;
+            let sql = match &args[0] {
+                Val::Record(m) => match m.get("sql") { Some(Val::Text(s)) => s.clone(), _ => bail!("ERROR_BADARG {} expects a statement from sqlite.prepare", op) },
+                _ => unreachable!(),
+            };
+            let none = Val::List(Vec::new());
+            sqlite_run(tracer, op, &handle, &path, &sql, Some(args.get(1).unwrap_or(&none)), rows)
+        }
+        Builtin::SqliteTransaction => {
+            if args.len() != 2 { bail!("ERROR_ARITY sqlite.transaction expects (db, fn)"); }
+            let (handle, path) = sqlite_target(&args[0], "sqlite.transaction")?;
+            let begun = sqlite_run(tracer, "sqlite.transaction", &handle, &path, "BEGIN", None, false)?;
+            if !result_is_ok(&begun)? {
+                return Ok(begun);
+            }
+            // Commit unless the body failed or returned an err result
+            let r = call(args[1].clone(), vec![], tracer, loader);
+            let failed = match &r {
+                Ok(v) => is_result_val(v) && !result_is_ok(v)?,
+                Err(_) => true,
+            };
+            let end = if failed { "ROLLBACK" } else { "COMMIT" };
+            let ended = sqlite_run(tracer, "sqlite.transaction", &handle, &path, end, None, false)?;
+            let v = r?;
+            if !failed && !result_is_ok(&ended)? {
+                return Ok(ended);
+            }
+            Ok(v)
+        }
         Builtin::FfiClose => {
             if args.len() != 1 { bail!("ERROR_BADARG ffi.close expects 1 arg"); }
             let handle = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG ffi.close expects text") };
@@ -7013,8 +10203,8 @@ fn call_builtin(
                     if v1 == v2 {
                         // Deterministic — emit checked event and include in witness
                         let _ = tracer.emit_raw(&format!(
-                            r#"{{"t":"ffi_checked","symbol":"{}","result":{},"reason":"{}","deterministic":true}}"#,
-                            symbol, v1, reason.replace('"', "\\\"")
+                            r#"{{"t":"ffi_checked","symbol":{},"result":{},"reason":{},"deterministic":true}}"#,
+                            json_to_string(&J::Str(symbol.clone())), v1, json_to_string(&J::Str(reason.clone()))
                         ));
                         m.insert("t".to_string(), Val::Text("ok".to_string()));
                         m.insert("ok".to_string(), Val::Int(v1));
@@ -7023,8 +10213,8 @@ fn call_builtin(
                     } else {
                         // Non-deterministic — reject with evidence
                         let _ = tracer.emit_raw(&format!(
-                            r#"{{"t":"ffi_checked","symbol":"{}","deterministic":false,"run1":{},"run2":{}}}"#,
-                            symbol, v1, v2
+                            r#"{{"t":"ffi_checked","symbol":{},"deterministic":false,"run1":{},"run2":{}}}"#,
+                            json_to_string(&J::Str(symbol.clone())), v1, v2
                         ));
                         m.insert("t".to_string(), Val::Text("err".to_string()));
                         m.insert("e".to_string(), Val::Text(format!(
@@ -7087,70 +10277,379 @@ fn call_builtin(
                     }
                 }
             }
-            Ok(Val::Record(m))
-        }
-        Builtin::NetServe => {
-            // net.serve(port, handler_fn) -> blocking server
-            // handler_fn receives {method, path, headers, body} -> {status, headers, body}
-            if args.len() != 2 { bail!("ERROR_BADARG net.serve expects 2 args: port, handler"); }
-            let port = match &args[0] { Val::Int(n) => *n as u16, _ => bail!("ERROR_BADARG net.serve: port must be int") };
-            let handler = args[1].clone();
-            let addr = format!("0.0.0.0:{}", port);
-            let server = tiny_http::Server::http(&addr)
-                .map_err(|e| anyhow!("ERROR_NET net.serve failed to bind {}: {}", addr, e))?;
-            eprintln!("[fard] net.serve listening on http://{}", addr);
-            for raw_req in server.incoming_requests() {
-                let method = raw_req.method().as_str().to_string();
-                let url = raw_req.url().to_string();
-                let mut header_map = BTreeMap::new();
-                for h in raw_req.headers() {
-                    header_map.insert(
-                        h.field.as_str().as_str().to_lowercase(),
-                        Val::Text(h.value.as_str().to_string()),
-                    );
-                }
-                let mut body_bytes = Vec::new();
-                let mut req_body = raw_req;
-                std::io::Read::read_to_end(req_body.as_reader(), &mut body_bytes).ok();
-                let body_str = String::from_utf8_lossy(&body_bytes).to_string();
-                // Build request record
-                let mut req_rec = BTreeMap::new();
-                req_rec.insert("method".to_string(), Val::Text(method));
-                req_rec.insert("path".to_string(), Val::Text(url));
-                req_rec.insert("headers".to_string(), Val::Record(header_map));
-                req_rec.insert("body".to_string(), Val::Text(body_str));
-                let req_val = Val::Record(req_rec);
-                // Call handler
-                let resp_val = match call(handler.clone(), vec![req_val], tracer, loader) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        eprintln!("[fard] net.serve handler error: {}", e);
-                        let mut m = BTreeMap::new();
-                        m.insert("status".to_string(), Val::Int(500));
-                        m.insert("body".to_string(), Val::Text(format!("handler error: {}", e)));
-                        m.insert("headers".to_string(), Val::Record(BTreeMap::new()));
-                        Val::Record(m)
-                    }
-                };
-                // Extract response fields
-                let status = match &resp_val { Val::Record(m) => match m.get("status") { Some(Val::Int(n)) => *n as u32, _ => 200 }, _ => 200 };
-                let body_text = match &resp_val { Val::Record(m) => match m.get("body") { Some(Val::Text(s)) => s.clone(), Some(v) => format!("{:?}", v), _ => String::new() }, _ => String::new() };
-                let content_type = match &resp_val {
-                    Val::Record(m) => match m.get("headers") {
-                        Some(Val::Record(hm)) => match hm.get("content-type") {
-                            Some(Val::Text(ct)) => ct.clone(),
-                            _ => "text/plain".to_string(),
-                        },
-                        _ => "text/plain".to_string(),
-                    },
-                    _ => "text/plain".to_string(),
-                };
-                let response = tiny_http::Response::from_string(body_text)
-                    .with_status_code(status)
-                    .with_header(tiny_http::Header::from_bytes(b"Content-Type", content_type.as_bytes()).unwrap());
-                req_body.respond(response).ok();
+            Ok(Val::Record(m))
+        }
+        Builtin::NetServe => {
+            // net.serve(port, handler_fn) -> blocking server on 0.0.0.0:port
+            // handler_fn receives {method, path, url, query, headers, body} -> {status, headers, body}
+            if args.len() != 2 { bail!("ERROR_BADARG net.serve expects 2 args: port, handler"); }
+            let mut opts = BTreeMap::new();
+            opts.insert("addr".to_string(), Val::Text("0.0.0.0".to_string()));
+            opts.insert("port".to_string(), args[0].clone());
+            opts.insert("handler".to_string(), args[1].clone());
+            let (_, addr, server) = net_start(tracer, loader, &opts, "net.serve")?.map_err(|e| anyhow!(e))?;
+            eprintln!("[fard] net.serve listening on http://{}", addr);
+            for w in server.workers {
+                let _ = w.join();
+            }
+            Ok(Val::Unit)
+        }
+        Builtin::NetListen => {
+            let opts = match args.as_slice() {
+                [Val::Record(m)] => m,
+                _ => bail!("ERROR_BADARG net.listen expects an options record"),
+            };
+            match net_start(tracer, loader, opts, "net.listen")? {
+                Err(e) => Ok(mk_result_err(Val::Text(e))),
+                Ok((id, addr, server)) => {
+                    let port = server.server.server_addr().to_ip().map(|a| a.port() as i64).unwrap_or(0);
+                    NET_SERVERS.lock().unwrap().insert(id.clone(), server);
+                    let mut m = BTreeMap::new();
+                    m.insert("handle".to_string(), Val::Text(id));
+                    m.insert("addr".to_string(), Val::Text(addr));
+                    m.insert("port".to_string(), Val::Int(port));
+                    Ok(mk_result_ok(Val::Record(m)))
+                }
+            }
+        }
+        Builtin::NetStop => {
+            let id = match args.as_slice() {
+                [Val::Text(h)] => h.clone(),
+                [Val::Record(m)] => match m.get("handle") {
+                    Some(Val::Text(h)) => h.clone(),
+                    _ => bail!("ERROR_BADARG net.stop expects a server from net.listen"),
+                },
+                _ => bail!("ERROR_BADARG net.stop expects a server from net.listen"),
+            };
+            let Some(server) = NET_SERVERS.lock().unwrap().remove(&id) else {
+                return Ok(mk_result_err(Val::Text(format!("ERROR_NET net.stop: no running server {}", id))));
+            };
+            // Stop accepting, let workers finish in-flight and queued requests, then close.
+            server.stop.store(true, std::sync::atomic::Ordering::SeqCst);
+            for w in server.workers {
+                let _ = w.join();
+            }
+            drop(server.server);
+            let requests = server.seq.load(std::sync::atomic::Ordering::SeqCst) as i64;
+            let digests = server.digests.lock().unwrap();
+            let mut m = BTreeMap::new();
+            let mut ev = Map::new();
+            m.insert("requests".to_string(), Val::Int(requests));
+            if !digests.is_empty() {
+                let leaves: Vec<[u8; 32]> = digests
+                    .values()
+                    .map(|d| hex_decode(d.trim_start_matches("sha256:")).ok().and_then(|b| b.try_into().ok()).unwrap_or([0u8; 32]))
+                    .collect();
+                let root = format!("sha256:{}", hex_lower(&merkle_root_bytes(&leaves)));
+                m.insert("receipts_root".to_string(), Val::Text(root.clone()));
+                ev.insert("receipts_root".to_string(), J::Str(root));
+            }
+            ev.insert("t".to_string(), J::Str("net_stop".to_string()));
+            ev.insert("server".to_string(), J::Str(id));
+            ev.insert("requests".to_string(), J::Int(requests));
+            tracer.emit_event(J::Object(ev))?;
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetConnect => {
+            let opts = match args.as_slice() {
+                [Val::Record(m)] => m,
+                _ => bail!("ERROR_BADARG net.connect expects {{host, port, timeout_ms?}}"),
+            };
+            let host = match opts.get("host") {
+                Some(Val::Text(s)) => s.clone(),
+                _ => bail!("ERROR_BADARG net.connect host must be text"),
+            };
+            let port = match opts.get("port") {
+                Some(Val::Int(n)) if (1..=65535).contains(n) => *n as u16,
+                _ => bail!("ERROR_BADARG net.connect port must be an int from 1 to 65535"),
+            };
+            let timeout = net_opt_timeout(opts, "net.connect")?;
+            cap_check(tracer, "http", "net.connect", &format!("tcp://{}:{}", host, port))?;
+            let s = match net_tcp_connect(&host, port, timeout) {
+                Ok(s) => s,
+                Err(e) => return Ok(net_io_err("net.connect", &e, timeout)),
+            };
+            let local = s.local_addr().map(|a| a.to_string()).unwrap_or_default();
+            let peer = s.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            let id = net_sock_register("tcp", NetSock::Tcp(Arc::new(s), Arc::new(NetIo::default())));
+            net_open_event(tracer, "net.connect", &id, &local, Some(&peer))?;
+            let mut m = BTreeMap::new();
+            m.insert("handle".to_string(), Val::Text(id));
+            m.insert("addr".to_string(), Val::Text(local));
+            m.insert("peer".to_string(), Val::Text(peer));
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetTcpListen => {
+            let opts = match args.as_slice() {
+                [Val::Record(m)] => m,
+                _ => bail!("ERROR_BADARG net.tcp_listen expects {{addr?, port?, timeout_ms?}}"),
+            };
+            let (host, port) = net_opt_bind(opts, "net.tcp_listen")?;
+            let timeout = net_opt_timeout(opts, "net.tcp_listen")?;
+            cap_check(tracer, "listen", "net.tcp_listen", &port.to_string())?;
+            let l = match std::net::TcpListener::bind((host.as_str(), port)) {
+                Ok(l) => l,
+                Err(e) => return Ok(net_io_err("net.tcp_listen", &e, None)),
+            };
+            let addr = l.local_addr().map(|a| a.to_string()).unwrap_or_default();
+            let bound = l.local_addr().map(|a| a.port() as i64).unwrap_or(0);
+            let id = net_sock_register("listener", NetSock::Listener(Arc::new(l), timeout));
+            net_open_event(tracer, "net.tcp_listen", &id, &addr, None)?;
+            let mut m = BTreeMap::new();
+            m.insert("handle".to_string(), Val::Text(id));
+            m.insert("addr".to_string(), Val::Text(addr));
+            m.insert("port".to_string(), Val::Int(bound));
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetAccept | Builtin::NetWsAccept => {
+            let op = if matches!(b, Builtin::NetAccept) { "net.accept" } else { "net.ws_accept" };
+            let [listener] = args.as_slice() else { bail!("ERROR_BADARG {} expects a listener", op) };
+            let (l, timeout) = match net_sock(listener, op)? {
+                Ok((_, NetSock::Listener(l, t))) => (l, t),
+                Ok(_) => bail!("ERROR_BADARG {} expects a listener from net.tcp_listen", op),
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            let (s, peer) = match net_accept(&l, timeout) {
+                Ok(x) => x,
+                Err(e) => return Ok(net_io_err(op, &e, timeout)),
+            };
+            let local = s.local_addr().map(|a| a.to_string()).unwrap_or_default();
+            let peer = peer.to_string();
+            let mut m = BTreeMap::new();
+            let id = if matches!(b, Builtin::NetAccept) {
+                net_sock_register("tcp", NetSock::Tcp(Arc::new(s), Arc::new(NetIo::default())))
+            } else {
+                let mut path = String::new();
+                // The callback's error type is fixed by tungstenite.
+                #[allow(clippy::result_large_err)]
+                let record_path = |rq: &tungstenite::handshake::server::Request, resp| {
+                    path = rq.uri().path().to_string();
+                    Ok(resp)
+                };
+                let shaken = tungstenite::accept_hdr(NetWsStream { sock: s, received: None }, record_path);
+                let ws = match shaken {
+                    Ok(ws) => ws,
+                    Err(e) => return Ok(mk_result_err(Val::Text(format!("ERROR_NET net.ws_accept handshake: {}", e)))),
+                };
+                let (id, inbox) = net_ws_open(ws)?;
+                m.insert("path".to_string(), Val::Text(path));
+                m.insert("inbox".to_string(), inbox);
+                id
+            };
+            net_open_event(tracer, op, &id, &local, Some(&peer))?;
+            m.insert("handle".to_string(), Val::Text(id));
+            m.insert("addr".to_string(), Val::Text(local));
+            m.insert("peer".to_string(), Val::Text(peer));
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetRead => {
+            let (conn, max) = match args.as_slice() {
+                [c] => (c, 65536usize),
+                [c, Val::Int(n)] if *n > 0 => (c, *n as usize),
+                _ => bail!("ERROR_BADARG net.read expects (conn, max_bytes?)"),
+            };
+            let (id, s, io) = match net_sock(conn, "net.read")? {
+                Ok((id, NetSock::Tcp(s, io))) => (id, s, io),
+                Ok(_) => bail!("ERROR_BADARG net.read expects a TCP connection"),
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            use std::io::Read;
+            let mut buf = vec![0u8; max];
+            let n = match (&*s).read(&mut buf) {
+                Ok(n) => n,
+                Err(e) => return Ok(net_io_err("net.read", &e, s.read_timeout().ok().flatten())),
+            };
+            buf.truncate(n);
+            io.bytes_in.fetch_add(n as u64, std::sync::atomic::Ordering::SeqCst);
+            net_io_event(tracer, "net.read", &id, None, &buf)?;
+            Ok(mk_result_ok(Val::Bytes(buf)))
+        }
+        Builtin::NetWrite => {
+            let [conn, data] = args.as_slice() else { bail!("ERROR_BADARG net.write expects (conn, data)") };
+            let data = net_payload(data, "net.write")?;
+            let (id, s, io) = match net_sock(conn, "net.write")? {
+                Ok((id, NetSock::Tcp(s, io))) => (id, s, io),
+                Ok(_) => bail!("ERROR_BADARG net.write expects a TCP connection"),
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            use std::io::Write;
+            if let Err(e) = (&*s).write_all(&data) {
+                return Ok(net_io_err("net.write", &e, s.write_timeout().ok().flatten()));
+            }
+            io.bytes_out.fetch_add(data.len() as u64, std::sync::atomic::Ordering::SeqCst);
+            net_io_event(tracer, "net.write", &id, None, &data)?;
+            Ok(mk_result_ok(Val::Int(data.len() as i64)))
+        }
+        Builtin::NetUdpBind => {
+            let opts = match args.as_slice() {
+                [Val::Record(m)] => m,
+                _ => bail!("ERROR_BADARG net.udp_bind expects {{addr?, port?, timeout_ms?}}"),
+            };
+            let (host, port) = net_opt_bind(opts, "net.udp_bind")?;
+            let timeout = net_opt_timeout(opts, "net.udp_bind")?;
+            cap_check(tracer, "listen", "net.udp_bind", &port.to_string())?;
+            let s = match std::net::UdpSocket::bind((host.as_str(), port)).and_then(|s| {
+                s.set_read_timeout(timeout)?;
+                s.set_write_timeout(timeout)?;
+                Ok(s)
+            }) {
+                Ok(s) => s,
+                Err(e) => return Ok(net_io_err("net.udp_bind", &e, None)),
+            };
+            let addr = s.local_addr().map(|a| a.to_string()).unwrap_or_default();
+            let bound = s.local_addr().map(|a| a.port() as i64).unwrap_or(0);
+            let id = net_sock_register("udp", NetSock::Udp(Arc::new(s), Arc::new(NetIo::default())));
+            net_open_event(tracer, "net.udp_bind", &id, &addr, None)?;
+            let mut m = BTreeMap::new();
+            m.insert("handle".to_string(), Val::Text(id));
+            m.insert("addr".to_string(), Val::Text(addr));
+            m.insert("port".to_string(), Val::Int(bound));
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetUdpSend => {
+            let [sock, to, data] = args.as_slice() else { bail!("ERROR_BADARG net.udp_send expects (socket, \"host:port\", data)") };
+            let Val::Text(to) = to else { bail!("ERROR_BADARG net.udp_send destination must be \"host:port\"") };
+            let data = net_payload(data, "net.udp_send")?;
+            let (id, s, io) = match net_sock(sock, "net.udp_send")? {
+                Ok((id, NetSock::Udp(s, io))) => (id, s, io),
+                Ok(_) => bail!("ERROR_BADARG net.udp_send expects a socket from net.udp_bind"),
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            cap_check(tracer, "http", "net.udp_send", &format!("udp://{}", to))?;
+            let n = match s.send_to(&data, to.as_str()) {
+                Ok(n) => n,
+                Err(e) => return Ok(net_io_err("net.udp_send", &e, s.write_timeout().ok().flatten())),
+            };
+            io.bytes_out.fetch_add(n as u64, std::sync::atomic::Ordering::SeqCst);
+            net_io_event(tracer, "net.udp_send", &id, Some(to), &data[..n])?;
+            Ok(mk_result_ok(Val::Int(n as i64)))
+        }
+        Builtin::NetUdpRecv => {
+            let (sock, max) = match args.as_slice() {
+                [s] => (s, 65536usize),
+                [s, Val::Int(n)] if *n > 0 => (s, *n as usize),
+                _ => bail!("ERROR_BADARG net.udp_recv expects (socket, max_bytes?)"),
+            };
+            let (id, s, io) = match net_sock(sock, "net.udp_recv")? {
+                Ok((id, NetSock::Udp(s, io))) => (id, s, io),
+                Ok(_) => bail!("ERROR_BADARG net.udp_recv expects a socket from net.udp_bind"),
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            let mut buf = vec![0u8; max];
+            let (n, from) = match s.recv_from(&mut buf) {
+                Ok(x) => x,
+                Err(e) => return Ok(net_io_err("net.udp_recv", &e, s.read_timeout().ok().flatten())),
+            };
+            buf.truncate(n);
+            let from = from.to_string();
+            io.bytes_in.fetch_add(n as u64, std::sync::atomic::Ordering::SeqCst);
+            net_io_event(tracer, "net.udp_recv", &id, Some(&from), &buf)?;
+            let mut m = BTreeMap::new();
+            m.insert("data".to_string(), Val::Bytes(buf));
+            m.insert("from".to_string(), Val::Text(from));
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetWsConnect => {
+            let (url, opts) = match args.as_slice() {
+                [Val::Text(u)] => (u.clone(), BTreeMap::new()),
+                [Val::Text(u), Val::Record(o)] => (u.clone(), o.clone()),
+                _ => bail!("ERROR_BADARG net.ws_connect expects (url, {{timeout_ms?}}?)"),
+            };
+            let timeout = net_opt_timeout(&opts, "net.ws_connect")?;
+            let uri: tungstenite::http::Uri = match url.parse() {
+                Ok(u) => u,
+                Err(e) => bail!("ERROR_BADARG net.ws_connect bad url {}: {}", url, e),
+            };
+            if uri.scheme_str() != Some("ws") {
+                return Ok(mk_result_err(Val::Text(format!("ERROR_NET net.ws_connect supports ws:// urls only, got {}", url))));
+            }
+            let host = uri.host().unwrap_or("").trim_matches(['[', ']']).to_string();
+            let port = uri.port_u16().unwrap_or(80);
+            cap_check(tracer, "http", "net.ws_connect", &url)?;
+            let s = match net_tcp_connect(&host, port, timeout) {
+                Ok(s) => s,
+                Err(e) => return Ok(net_io_err("net.ws_connect", &e, timeout)),
+            };
+            let local = s.local_addr().map(|a| a.to_string()).unwrap_or_default();
+            let peer = s.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            let ws = match tungstenite::client::client(url.as_str(), NetWsStream { sock: s, received: None }) {
+                Ok((ws, _)) => ws,
+                Err(e) => return Ok(mk_result_err(Val::Text(format!("ERROR_NET net.ws_connect handshake: {}", e)))),
+            };
+            let (id, inbox) = net_ws_open(ws)?;
+            net_open_event(tracer, "net.ws_connect", &id, &local, Some(&peer))?;
+            let mut m = BTreeMap::new();
+            m.insert("handle".to_string(), Val::Text(id));
+            m.insert("addr".to_string(), Val::Text(local));
+            m.insert("peer".to_string(), Val::Text(peer));
+            m.insert("inbox".to_string(), inbox);
+            Ok(mk_result_ok(Val::Record(m)))
+        }
+        Builtin::NetWsSend => {
+            let [ws, msg] = args.as_slice() else { bail!("ERROR_BADARG net.ws_send expects (ws, message)") };
+            let (id, w) = match net_sock(ws, "net.ws_send")? {
+                Ok((id, NetSock::Ws(w))) => (id, w),
+                Ok(_) => bail!("ERROR_BADARG net.ws_send expects a WebSocket"),
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            let data = net_payload(msg, "net.ws_send")?;
+            let message = match msg {
+                Val::Text(s) => tungstenite::Message::Text(s.clone()),
+                _ => tungstenite::Message::Binary(data.clone()),
+            };
+            if let Err(e) = w.ws.lock().unwrap().send(message) {
+                return Ok(mk_result_err(Val::Text(format!("ERROR_NET net.ws_send: {}", e))));
             }
-            Ok(Val::Unit)
+            w.io.bytes_out.fetch_add(data.len() as u64, std::sync::atomic::Ordering::SeqCst);
+            net_io_event(tracer, "net.ws_send", &id, None, &data)?;
+            Ok(mk_result_ok(Val::Int(data.len() as i64)))
+        }
+        Builtin::NetClose => {
+            let [sock] = args.as_slice() else { bail!("ERROR_BADARG net.close expects a socket") };
+            let (id, s) = match net_sock(sock, "net.close")? {
+                Ok(x) => x,
+                Err(e) => return Ok(mk_result_err(Val::Text(e))),
+            };
+            NET_SOCKETS.lock().unwrap().remove(&id);
+            use std::sync::atomic::Ordering;
+            let mut m = BTreeMap::new();
+            let mut ev = Map::new();
+            let (bytes_in, bytes_out) = match &s {
+                NetSock::Tcp(s, io) => {
+                    let _ = s.shutdown(std::net::Shutdown::Both);
+                    (io.bytes_in.load(Ordering::SeqCst), io.bytes_out.load(Ordering::SeqCst))
+                }
+                NetSock::Listener(..) => (0, 0),
+                NetSock::Udp(_, io) => (io.bytes_in.load(Ordering::SeqCst), io.bytes_out.load(Ordering::SeqCst)),
+                NetSock::Ws(w) => {
+                    let _ = w.ws.lock().unwrap().close(None);
+                    // Give the peer a moment to answer the close frame before the socket is shut down.
+                    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(500);
+                    while w.reader.lock().unwrap().as_ref().is_some_and(|r| !r.is_finished()) && std::time::Instant::now() < deadline {
+                        std::thread::sleep(std::time::Duration::from_millis(5));
+                    }
+                    let _ = w.sock.shutdown(std::net::Shutdown::Both);
+                    if let Some(r) = w.reader.lock().unwrap().take() {
+                        let _ = r.join();
+                    }
+                    let taken = w.taken.lock().unwrap();
+                    m.insert("messages_in".to_string(), Val::Int(taken.len() as i64));
+                    ev.insert("messages_in".to_string(), J::Int(taken.len() as i64));
+                    if !taken.is_empty() {
+                        let root = format!("sha256:{}", hex_lower(&merkle_root_bytes(&taken)));
+                        ev.insert("recv_root".to_string(), J::Str(root));
+                    }
+                    (w.io.bytes_in.load(Ordering::SeqCst), w.io.bytes_out.load(Ordering::SeqCst))
+                }
+            };
+            ev.insert("t".to_string(), J::Str("net_close".to_string()));
+            ev.insert("handle".to_string(), J::Str(id));
+            ev.insert("bytes_in".to_string(), J::Int(bytes_in as i64));
+            ev.insert("bytes_out".to_string(), J::Int(bytes_out as i64));
+            tracer.emit_event(J::Object(ev))?;
+            m.insert("bytes_in".to_string(), Val::Int(bytes_in as i64));
+            m.insert("bytes_out".to_string(), Val::Int(bytes_out as i64));
+            Ok(mk_result_ok(Val::Record(m)))
         }
         Builtin::NetRespond => {
             Ok(Val::Unit)
@@ -7290,6 +10789,86 @@ fn call_builtin(
             Ok(Val::Record(m))
         }
 
+        Builtin::CompressGzipBytes | Builtin::CompressDeflate | Builtin::CompressZstd => {
+            let op = match b {
+                Builtin::CompressGzipBytes => "compress.gzip_compress",
+                Builtin::CompressDeflate => "compress.deflate_compress",
+                _ => "compress.zstd_compress",
+            };
+            if args.is_empty() || args.len() > 2 { bail!("ERROR_BADARG {} expects (data[, level])", op); }
+            let data = compress_input(&args[0], op)?;
+            use std::io::Write;
+            let out = match b {
+                Builtin::CompressZstd => {
+                    let level = compress_level(&args, op, 1..=22, 3)?;
+                    zstd::bulk::compress(&data, level as i32)
+                }
+                Builtin::CompressGzipBytes => {
+                    let level = flate2::Compression::new(compress_level(&args, op, 0..=9, 6)? as u32);
+                    let mut e = flate2::write::GzEncoder::new(Vec::new(), level);
+                    e.write_all(&data).and_then(|_| e.finish())
+                }
+                _ => {
+                    let level = flate2::Compression::new(compress_level(&args, op, 0..=9, 6)? as u32);
+                    let mut e = flate2::write::DeflateEncoder::new(Vec::new(), level);
+                    e.write_all(&data).and_then(|_| e.finish())
+                }
+            };
+            Ok(Val::Bytes(out.map_err(|e| anyhow!("ERROR_COMPRESS {}: {}", op, e))?))
+        }
+        Builtin::CompressGunzipBytes | Builtin::CompressInflate | Builtin::CompressUnzstd => {
+            let op = match b {
+                Builtin::CompressGunzipBytes => "compress.gzip_decompress",
+                Builtin::CompressInflate => "compress.deflate_decompress",
+                _ => "compress.zstd_decompress",
+            };
+            let (data, mut max_bytes) = decompress_args(&args, op)?;
+            let mut out = Vec::new();
+            let read = match b {
+                Builtin::CompressGunzipBytes => read_capped(flate2::read::MultiGzDecoder::new(data), &mut out, &mut max_bytes),
+                Builtin::CompressInflate => read_capped(flate2::read::DeflateDecoder::new(data), &mut out, &mut max_bytes),
+                _ => zstd::stream::read::Decoder::new(data).and_then(|d| read_capped(d, &mut out, &mut max_bytes)),
+            };
+            Ok(match read {
+                Ok(_) => mk_result_ok(Val::Bytes(out)),
+                Err(e) => mk_result_err(Val::Text(format!("ERROR_COMPRESS {}: {}", op, e))),
+            })
+        }
+        Builtin::CompressTarCreate => {
+            let [entries] = args.as_slice() else { bail!("ERROR_BADARG compress.tar_create expects a list of {{name, data}}") };
+            let entries = archive_entries(entries, "compress.tar_create")?;
+            let out = tar_create(&entries).map_err(|e| anyhow!("ERROR_COMPRESS compress.tar_create: {}", e))?;
+            Ok(Val::Bytes(out))
+        }
+        Builtin::CompressZipCreate => {
+            let (entries, method) = match args.as_slice() {
+                [e] => (e, zip::CompressionMethod::Deflated),
+                [e, Val::Record(o)] => match o.get("method") {
+                    None => (e, zip::CompressionMethod::Deflated),
+                    Some(Val::Text(m)) if m == "deflate" => (e, zip::CompressionMethod::Deflated),
+                    Some(Val::Text(m)) if m == "store" => (e, zip::CompressionMethod::Stored),
+                    Some(_) => bail!("ERROR_BADARG compress.zip_create method must be \"deflate\" or \"store\""),
+                },
+                _ => bail!("ERROR_BADARG compress.zip_create expects (entries[, {{method}}])"),
+            };
+            let entries = archive_entries(entries, "compress.zip_create")?;
+            let out = zip_create(&entries, method).map_err(|e| anyhow!("ERROR_COMPRESS compress.zip_create: {}", e))?;
+            Ok(Val::Bytes(out))
+        }
+        Builtin::CompressTarExtract | Builtin::CompressZipExtract => {
+            let op = if matches!(b, Builtin::CompressTarExtract) { "compress.tar_extract" } else { "compress.zip_extract" };
+            let (data, max_bytes) = decompress_args(&args, op)?;
+            let files = if matches!(b, Builtin::CompressTarExtract) {
+                tar_extract(data, max_bytes).map_err(|e| e.to_string())
+            } else {
+                zip_extract(data, max_bytes).map_err(|e| e.to_string())
+            };
+            match files {
+                Ok(files) => Ok(mk_result_ok(archive_extracted(tracer, files)?)),
+                Err(e) => Ok(mk_result_err(Val::Text(format!("ERROR_COMPRESS {}: {}", op, e)))),
+            }
+        }
+
         Builtin::GraphOf => {
             // graph.of(run_id) -> {ok: {nodes, edges}} | {err: text}
             if args.len() != 1 { bail!("ERROR_BADARG graph.of expects 1 arg"); }
@@ -7303,11 +10882,10 @@ fn call_builtin(
             while let Some(run_id) = queue.pop() {
                 if visited.contains(&run_id) { continue; }
                 visited.insert(run_id.clone());
-                let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-                let receipt_path = format!("receipts/sha256_{}.json", hex);
-                let bytes = match std::fs::read(&receipt_path) {
-                    Ok(b) => b,
-                    Err(_) => { err_m.insert("e".to_string(), Val::Text(format!("receipt not found: {}", run_id))); err_m.insert("t".to_string(), Val::Text("err".to_string())); had_err = true; break; }
+                let bytes = match receipt_store().get(&run_id) {
+                    Ok(Some(b)) => b,
+                    Ok(None) => { err_m.insert("e".to_string(), Val::Text(format!("receipt not found: {}", run_id))); err_m.insert("t".to_string(), Val::Text("err".to_string())); had_err = true; break; }
+                    Err(e) => { err_m.insert("e".to_string(), Val::Text(format!("receipt store error: {:#}", e))); err_m.insert("t".to_string(), Val::Text("err".to_string())); had_err = true; break; }
                 };
                 let receipt = match json_from_slice(&bytes) {
                     Ok(r) => r,
@@ -7354,9 +10932,7 @@ fn call_builtin(
                 if visited.contains(&run_id) { continue; }
                 visited.insert(run_id.clone());
                 if run_id != root_id { ancestors.push(Val::Text(run_id.clone())); }
-                let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-                let receipt_path = format!("receipts/sha256_{}.json", hex);
-                if let Ok(bytes) = std::fs::read(&receipt_path) {
+                if let Ok(Some(bytes)) = receipt_store().get(&run_id) {
                     if let Ok(J::Object(rm)) = json_from_slice(&bytes) {
                         if let Some(J::Array(deps)) = rm.get("derived_from") {
                             for dep in deps { if let J::Str(dep_id) = dep { queue.push(dep_id.clone()); } }
@@ -7376,9 +10952,7 @@ fn call_builtin(
             while let Some(run_id) = queue.pop() {
                 if visited.contains(&run_id) { continue; }
                 visited.insert(run_id.clone());
-                let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-                let receipt_path = format!("receipts/sha256_{}.json", hex);
-                if let Ok(bytes) = std::fs::read(&receipt_path) {
+                if let Ok(Some(bytes)) = receipt_store().get(&run_id) {
                     if let Ok(J::Object(rm)) = json_from_slice(&bytes) {
                         let deps = rm.get("derived_from").and_then(|d| if let J::Array(a) = d { Some(a.clone()) } else { None }).unwrap_or_default();
                         if deps.is_empty() {
@@ -7538,6 +11112,7 @@ fn call_builtin(
             [Val::Chan(q, closed)] => {
                 loop {
                     if let Some(v) = q.lock().unwrap().pop_front() {
+                        net_ws_taken(tracer, q, &v)?;
                         return Ok(Val::Record({
                             let mut m = BTreeMap::new();
                             m.insert("t".to_string(), Val::Text("some".to_string()));
@@ -7548,6 +11123,7 @@ fn call_builtin(
                     if *closed.lock().unwrap() {
                         return Ok(Val::Unit);
                     }
+                    check_deadline()?;
                     std::thread::sleep(std::time::Duration::from_millis(1));
                 }
             }
@@ -7555,13 +11131,17 @@ fn call_builtin(
         }
         Builtin::ChanTryRecv => match args.as_slice() {
             [Val::Chan(q, _)] => {
-                match q.lock().unwrap().pop_front() {
-                    Some(v) => Ok(Val::Record({
-                        let mut m = BTreeMap::new();
-                        m.insert("t".to_string(), Val::Text("some".to_string()));
-                        m.insert("v".to_string(), v);
-                        m
-                    })),
+                let next = q.lock().unwrap().pop_front();
+                match next {
+                    Some(v) => {
+                        net_ws_taken(tracer, q, &v)?;
+                        Ok(Val::Record({
+                            let mut m = BTreeMap::new();
+                            m.insert("t".to_string(), Val::Text("some".to_string()));
+                            m.insert("v".to_string(), v);
+                            m
+                        }))
+                    }
                     None => Ok(Val::Unit),
                 }
             }
@@ -7574,22 +11154,23 @@ fn call_builtin(
             }
             _ => bail!("ERROR_BADARG chan.close expects chan"),
         }
-        Builtin::IoReadStdinLines => {
+        Builtin::IoReadStdinLines => oracle_answer("io.read_stdin_lines", &args, tracer, || {
             use std::io::BufRead;
             let stdin = std::io::stdin();
             let lines: Vec<Val> = stdin.lock().lines()
                 .map(|l| Val::Text(l.unwrap_or_default()))
                 .collect();
             Ok(Val::List(lines))
-        }
-        Builtin::IoReadStdin => {
+        }),
+        Builtin::IoReadStdin => oracle_answer("io.read_stdin", &args, tracer, || {
             let mut buf = String::new();
             std::io::Read::read_to_string(&mut std::io::stdin(), &mut buf)
                 .map_err(|e| anyhow::anyhow!("io.read_stdin: {}", e))?;
             Ok(Val::Text(buf))
-        }
+        }),
         Builtin::IoListDir => match args.as_slice() {
             [Val::Text(path)] => {
+                cap_check(tracer, "fs_read", "io.list_dir", path)?;
                 let entries = std::fs::read_dir(path)
                     .map_err(|e| anyhow::anyhow!("io.list_dir: {}", e))?;
                 let mut names = Vec::new();
@@ -7603,6 +11184,7 @@ fn call_builtin(
         }
         Builtin::IoMakeDir => match args.as_slice() {
             [Val::Text(path)] => {
+                cap_check(tracer, "fs_write", "io.make_dir", path)?;
                 std::fs::create_dir_all(path)
                     .map_err(|e| anyhow::anyhow!("io.make_dir: {}", e))?;
                 Ok(Val::Bool(true))
@@ -7612,6 +11194,7 @@ fn call_builtin(
         Builtin::IoReadFile => {
             if args.len() != 1 { bail!("ERROR_BADARG io.read_file expects 1 arg"); }
             let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.read_file expects string path") };
+            cap_check(tracer, "fs_read", "io.read_file", &path)?;
             match std::fs::read_to_string(&path) {
                 Ok(s)  => Ok(Val::Record({ let mut m = BTreeMap::new(); m.insert("ok".to_string(), Val::Text(s)); m })),
                 Err(e) => Ok(Val::Record({ let mut m = BTreeMap::new(); m.insert("err".to_string(), Val::Text(e.to_string())); m })),
@@ -7621,6 +11204,7 @@ fn call_builtin(
             if args.len() != 2 { bail!("ERROR_BADARG io.write_file expects 2 args"); }
             let path    = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.write_file path must be string") };
             let content = match &args[1] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.write_file content must be string") };
+            cap_check(tracer, "fs_write", "io.write_file", &path)?;
             let content_with_newline = if content.ends_with('\n') { content } else { format!("{}\n", content) };
             match std::fs::write(&path, content_with_newline.as_bytes()) {
                 Ok(_)  => Ok(Val::Record({ let mut m = BTreeMap::new(); m.insert("ok".to_string(), Val::Unit); m })),
@@ -7631,6 +11215,7 @@ fn call_builtin(
             if args.len() != 2 { bail!("ERROR_BADARG io.append_file expects 2 args"); }
             let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.append_file path must be string") };
             let line = match &args[1] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.append_file content must be string") };
+            cap_check(tracer, "fs_write", "io.append_file", &path)?;
             use std::io::Write;
             match std::fs::OpenOptions::new().create(true).append(true).open(&path) {
                 Ok(mut file) => {
@@ -7647,6 +11232,7 @@ fn call_builtin(
         Builtin::IoReadLines => {
             if args.len() != 1 { bail!("ERROR_BADARG io.read_lines expects 1 arg"); }
             let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.read_lines expects string path") };
+            cap_check(tracer, "fs_read", "io.read_lines", &path)?;
             match std::fs::read_to_string(&path) {
                 Ok(s)  => {
                     let lines: Vec<Val> = s.lines().map(|l| Val::Text(l.to_string())).collect();
@@ -7658,11 +11244,13 @@ fn call_builtin(
         Builtin::IoFileExists => {
             if args.len() != 1 { bail!("ERROR_BADARG io.file_exists expects 1 arg"); }
             let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.file_exists expects string path") };
+            cap_check(tracer, "fs_read", "io.file_exists", &path)?;
             Ok(Val::Bool(std::path::Path::new(&path).exists()))
         }
         Builtin::IoDeleteFile => {
             if args.len() != 1 { bail!("ERROR_BADARG io.delete_file expects 1 arg"); }
             let path = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG io.delete_file expects string path") };
+            cap_check(tracer, "fs_write", "io.delete_file", &path)?;
             match std::fs::remove_file(&path) {
                 Ok(_)  => Ok(Val::Record({ let mut m = BTreeMap::new(); m.insert("ok".to_string(), Val::Unit); m })),
                 Err(e) => Ok(Val::Record({ let mut m = BTreeMap::new(); m.insert("err".to_string(), Val::Text(e.to_string())); m })),
@@ -7910,6 +11498,7 @@ fn call_builtin(
             if args.len() != 1 { bail!("ERROR_ARITY fs.read_text"); }
             match &args[0] {
                 Val::Text(path) => {
+                    cap_check(tracer, "fs_read", "fs.read_text", path)?;
                     let content = std::fs::read_to_string(path.as_str())
                         .map_err(|e| anyhow!("ERROR_IO fs.read_text {}: {}", path, e))?;
                     Ok(Val::Text(content))
@@ -7929,6 +11518,7 @@ fn call_builtin(
                 _ => bail!("ERROR_BADARG fs.write_text content must be text"),
             };
             fs_sandbox_check(&path)?;
+            cap_check(tracer, "fs_write", "fs.write_text", &path)?;
             if let Some(parent) = std::path::Path::new(&path).parent() {
                 if !parent.as_os_str().is_empty() {
                     std::fs::create_dir_all(parent)
@@ -7939,12 +11529,35 @@ fn call_builtin(
                 .map_err(|e| anyhow!("ERROR_IO fs.write_text {}: {}", path, e))?;
             Ok(Val::Unit)
         }
+        Builtin::FsReadBytes => {
+            let [Val::Text(path)] = args.as_slice() else { bail!("ERROR_BADARG fs.read_bytes expects a text path") };
+            fs_sandbox_check(path)?;
+            cap_check(tracer, "fs_read", "fs.read_bytes", path)?;
+            let bytes = std::fs::read(path.as_str()).map_err(|e| anyhow!("ERROR_IO fs.read_bytes {}: {}", path, e))?;
+            Ok(Val::Bytes(bytes))
+        }
+        Builtin::FsWriteBytes => {
+            let [Val::Text(path), Val::Bytes(bytes)] = args.as_slice() else {
+                bail!("ERROR_BADARG fs.write_bytes expects (path, bytes)")
+            };
+            fs_sandbox_check(path)?;
+            cap_check(tracer, "fs_write", "fs.write_bytes", path)?;
+            if let Some(parent) = std::path::Path::new(path).parent() {
+                if !parent.as_os_str().is_empty() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| anyhow!("ERROR_IO fs.write_bytes mkdir {}: {}", path, e))?;
+                }
+            }
+            std::fs::write(path, bytes).map_err(|e| anyhow!("ERROR_IO fs.write_bytes {}: {}", path, e))?;
+            Ok(Val::Unit)
+        }
         Builtin::FsExists => {
             if args.len() != 1 { bail!("ERROR_ARITY fs.exists expects 1 arg"); }
             let path = match &args[0] {
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG fs.exists path must be text"),
             };
+            cap_check(tracer, "fs_read", "fs.exists", &path)?;
             Ok(Val::Bool(std::path::Path::new(&path).exists()))
         }
         Builtin::FsReadDir => {
@@ -7953,6 +11566,7 @@ fn call_builtin(
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG fs.read_dir path must be text"),
             };
+            cap_check(tracer, "fs_read", "fs.read_dir", &path)?;
             let entries = std::fs::read_dir(&path)
                 .map_err(|e| anyhow!("ERROR_IO fs.read_dir {}: {}", path, e))?;
             let mut names: Vec<Val> = Vec::new();
@@ -7973,6 +11587,7 @@ fn call_builtin(
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG fs.stat path must be text"),
             };
+            cap_check(tracer, "fs_read", "fs.stat", &path)?;
             let meta = std::fs::metadata(&path)
                 .map_err(|e| anyhow!("ERROR_IO fs.stat {}: {}", path, e))?;
             let mut m = BTreeMap::new();
@@ -7988,6 +11603,7 @@ fn call_builtin(
                 _ => bail!("ERROR_BADARG fs.delete path must be text"),
             };
             fs_sandbox_check(&path)?;
+            cap_check(tracer, "fs_write", "fs.delete", &path)?;
             let p = std::path::Path::new(&path);
             if p.is_dir() {
                 std::fs::remove_dir_all(&path)
@@ -8005,6 +11621,7 @@ fn call_builtin(
                 _ => bail!("ERROR_BADARG fs.make_dir path must be text"),
             };
             fs_sandbox_check(&path)?;
+            cap_check(tracer, "fs_write", "fs.make_dir", &path)?;
             std::fs::create_dir_all(&path)
                 .map_err(|e| anyhow!("ERROR_IO fs.make_dir {}: {}", path, e))?;
             Ok(Val::Unit)
@@ -8192,6 +11809,7 @@ fn call_builtin(
                 Val::Text(s) => s.clone(),
                 _ => bail!("ERROR_BADARG import_artifact_named path must be string"),
             };
+            cap_check(tracer, "fs_read", "import_artifact_named", &p)?;
 
             let bytes = match fs::read(&p) {
                 Ok(b) => b,
@@ -8227,9 +11845,11 @@ fn call_builtin(
                 _ => bail!("ERROR_BADARG emit_artifact name must be string"),
             };
             // Accept:
+            //  - bytes
             //  - list[int] bytes
             //  - {text: string} (gate convenience)
             let bytes: Vec<u8> = match &args[1] {
+                Val::Bytes(b) => b.clone(),
                 Val::List(vs) => {
                     let mut out: Vec<u8> = Vec::with_capacity(vs.len());
                     for v in vs {
@@ -8294,10 +11914,11 @@ fn call_builtin(
             };
 
             // Payload encoding:
-            // - list[int] => raw bytes
+            // - bytes / list[int] => raw bytes
             // - {text:string} => utf8 bytes
             // - otherwise any jsonable => compact JSON bytes
             let bytes: Vec<u8> = match &args[2] {
+                Val::Bytes(b) => b.clone(),
                 Val::List(vs) => {
                     let mut out: Vec<u8> = Vec::with_capacity(vs.len());
                     for v in vs {
@@ -8759,7 +12380,7 @@ fn call_builtin(
             _ => bail!("ERROR_BADARG str.join expects (list, text)"),
         }
         Builtin::ListAny => match args.as_slice() {
-            [Val::List(items), Val::Func(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
+            [Val::List(items), Val::Func(_)|Val::VmFunc(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
                 let f = args[1].clone();
                 for item in items {
                     let r = call(f.clone(), vec![item.clone()], tracer, loader)?;
@@ -8770,7 +12391,7 @@ fn call_builtin(
             _ => bail!("ERROR_BADARG list.any expects (list, fn)"),
         }
         Builtin::ListAll => match args.as_slice() {
-            [Val::List(items), Val::Func(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
+            [Val::List(items), Val::Func(_)|Val::VmFunc(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
                 let f = args[1].clone();
                 for item in items {
                     let r = call(f.clone(), vec![item.clone()], tracer, loader)?;
@@ -8781,7 +12402,7 @@ fn call_builtin(
             _ => bail!("ERROR_BADARG list.all expects (list, fn)"),
         }
         Builtin::ListFind => match args.as_slice() {
-            [Val::List(items), Val::Func(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
+            [Val::List(items), Val::Func(_)|Val::VmFunc(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
                 let f = args[1].clone();
                 for item in items {
                     let r = call(f.clone(), vec![item.clone()], tracer, loader)?;
@@ -8798,7 +12419,7 @@ fn call_builtin(
             _ => bail!("ERROR_BADARG list.find expects (list, fn)"),
         }
         Builtin::ListFindIndex => match args.as_slice() {
-            [Val::List(items), Val::Func(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
+            [Val::List(items), Val::Func(_)|Val::VmFunc(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
                 let f = args[1].clone();
                 for (i, item) in items.iter().enumerate() {
                     let r = call(f.clone(), vec![item.clone()], tracer, loader)?;
@@ -8823,7 +12444,7 @@ fn call_builtin(
             _ => bail!("ERROR_BADARG list.drop expects (list, int)"),
         }
         Builtin::ListFlatMap => match args.as_slice() {
-            [Val::List(items), Val::Func(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
+            [Val::List(items), Val::Func(_)|Val::VmFunc(_)|Val::Builtin(_)|Val::BoundMethod(_,_)] => {
                 let f = args[1].clone();
                 let mut out = Vec::new();
                 for item in items {
@@ -8861,22 +12482,38 @@ fn call_builtin(
         }
         Builtin::ListParMap => match args.as_slice() {
             [Val::List(items), f] => {
-                let items = items.clone();
+                // Every item is a child span; spans are allocated and joined in list order.
+                let mut jobs = Vec::with_capacity(items.len());
+                let mut spans = Vec::with_capacity(items.len());
+                for item in items {
+                    let (spawn_id, trace_path, child) = tracer.child_tracer()?;
+                    jobs.push((ChildCtx::capture(&spawn_id), item.clone(), child));
+                    spans.push((spawn_id, trace_path));
+                }
+                // Contiguous chunks, one per pool thread, each with its own loader fork.
+                let width = task_pool().current_num_threads().max(1);
+                let per = jobs.len().div_ceil(width).max(1);
+                let mut chunks = Vec::new();
+                let mut rest = jobs.into_iter().peekable();
+                while rest.peek().is_some() {
+                    chunks.push((rest.by_ref().take(per).collect::<Vec<_>>(), loader.fork()));
+                }
                 let f = f.clone();
-                // Use thread-per-item for pure functions (no IO/module access)
-                let handles: Vec<_> = items.into_iter().map(|item| {
-                    let f2 = f.clone();
-                    std::thread::spawn(move || {
-                        let tmp = std::path::Path::new("/tmp");
-                        let null_path = tmp.join(format!("fard_par_{}.ndjson", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().subsec_nanos()));
-                        let mut tracer = Tracer::new(tmp, &null_path).unwrap();
-                        let mut loader = ModuleLoader::new(tmp);
-                        call(f2, vec![item], &mut tracer, &mut loader)
-                    })
-                }).collect();
-                let mut results = Vec::new();
-                for h in handles {
-                    results.push(h.join().map_err(|_| anyhow::anyhow!("par_map thread panicked"))??);
+                let done: Vec<ChildDone> = task_pool().install(|| {
+                    use rayon::prelude::*;
+                    chunks.into_par_iter().map(|(chunk, mut child_loader)| {
+                        let mut out: Vec<ChildDone> = chunk.into_iter()
+                            .map(|(ctx, item, child)| run_child(ctx, f.clone(), vec![item], child, &mut child_loader))
+                            .collect();
+                        if let Some(last) = out.last_mut() {
+                            last.loader = Some(child_loader);
+                        }
+                        out
+                    }).collect::<Vec<_>>().into_iter().flatten().collect()
+                });
+                let mut results = Vec::with_capacity(done.len());
+                for ((spawn_id, trace_path), d) in spans.iter().zip(done) {
+                    results.push(child_join(tracer, loader, spawn_id, trace_path, d)?);
                 }
                 Ok(Val::List(results))
             }
@@ -8918,33 +12555,7 @@ fn call_builtin(
         }
         Builtin::PromiseSpawn => match args.as_slice() {
             [f] => {
-                let fv = f.clone();
-                let spawn_id = format!("spawn_{}", uuid::Uuid::new_v4());
-                let tmp = std::env::temp_dir();
-                let trace_path = tmp.join(format!("promise_trace_{}.ndjson", &spawn_id));
-                let trace_path2 = trace_path.clone();
-                let slot: Arc<Mutex<Option<Result<Val, String>>>> = Arc::new(Mutex::new(None));
-                let slot2 = slot.clone();
-                let child_vm_fns = VM_FNS.with(|cell| cell.borrow().clone());
-                let child_self_slots = VM_SELF_SLOTS.with(|cell| cell.borrow().clone());
-                tracer.child_spawn(&spawn_id)?;
-                std::thread::spawn(move || {
-                    VM_FNS.with(|cell| { *cell.borrow_mut() = child_vm_fns; });
-                    VM_SELF_SLOTS.with(|cell| { *cell.borrow_mut() = child_self_slots; });
-                    let trace_file = fs::File::create(&trace_path2).unwrap_or_else(|_| {
-                        fs::File::create(std::env::temp_dir().join("fallback_trace.ndjson")).unwrap()
-                    });
-                    let mut child_tracer = Tracer {
-                        first_event: true,
-                        artifact_cids: std::collections::BTreeMap::new(),
-                        w: trace_file,
-                        out_dir: tmp.clone(),
-                    };
-                    let mut loader = ModuleLoader::new(&tmp);
-                    let result = call(fv, vec![], &mut child_tracer, &mut loader)
-                        .map_err(|e| e.to_string());
-                    *slot2.lock().unwrap() = Some(result);
-                });
+                let (spawn_id, trace_path, slot) = child_spawn_task(tracer, loader, f.clone())?;
                 Ok(Val::Promise(slot, spawn_id, trace_path))
             }
             _ => bail!("promise.spawn expects a function"),
@@ -8956,93 +12567,27 @@ fn call_builtin(
             // Every run on the same inputs produces the same result list and the same trace.
             match args.as_slice() {
                 [Val::List(fns)] => {
-                    let fns = fns.clone();
-                    let tmp = std::env::temp_dir();
-                    let mut handles: Vec<(
-                        String,  // spawn_id
-                        std::path::PathBuf,  // trace_path
-                        Arc<Mutex<Option<Result<Val, String>>>>,
-                    )> = Vec::new();
-
                     // Spawn all in order
-                    for fv in &fns {
-                        let fv = fv.clone();
+                    let mut handles = Vec::with_capacity(fns.len());
+                    for fv in fns {
                         if !matches!(fv, Val::Func(_) | Val::Builtin(_) | Val::VmFunc(_)) {
                             bail!("promise.spawn_ordered: all elements must be functions");
                         }
-                        let spawn_id = format!("spawn_{}", uuid::Uuid::new_v4());
-                        let trace_path = tmp.join(format!("promise_trace_{}.ndjson", &spawn_id));
-                        let trace_path2 = trace_path.clone();
-                        let slot: Arc<Mutex<Option<Result<Val, String>>>> = Arc::new(Mutex::new(None));
-                        let slot2 = slot.clone();
-                        let child_vm_fns = VM_FNS.with(|cell| cell.borrow().clone());
-                        let child_self_slots = VM_SELF_SLOTS.with(|cell| cell.borrow().clone());
-                        tracer.child_spawn(&spawn_id)?;
-                        let tmp2 = tmp.clone();
-                        std::thread::spawn(move || {
-                            VM_FNS.with(|cell| { *cell.borrow_mut() = child_vm_fns; });
-                            VM_SELF_SLOTS.with(|cell| { *cell.borrow_mut() = child_self_slots; });
-                            let trace_file = fs::File::create(&trace_path2).unwrap_or_else(|_| {
-                                fs::File::create(std::env::temp_dir().join("fallback_trace.ndjson")).unwrap()
-                            });
-                            let mut child_tracer = Tracer {
-                                first_event: true,
-                                artifact_cids: std::collections::BTreeMap::new(),
-                                w: trace_file,
-                                out_dir: tmp2,
-                            };
-                            let mut loader = ModuleLoader::new(&std::env::temp_dir());
-                            let result = call(fv, vec![], &mut child_tracer, &mut loader)
-                                .map_err(|e| e.to_string());
-                            *slot2.lock().unwrap() = Some(result);
-                        });
-                        handles.push((spawn_id, trace_path, slot));
+                        handles.push(child_spawn_task(tracer, loader, fv.clone())?);
                     }
 
                     // Await ALL in spawn order (deterministic join)
                     let mut results: Vec<Val> = Vec::new();
                     for (spawn_id, trace_path, slot) in &handles {
-                        // Spin-wait for this specific promise in order
-                        loop {
-                            let done = slot.lock().unwrap().is_some();
-                            if done { break; }
-                            std::thread::sleep(std::time::Duration::from_millis(1));
-                        }
-                        let result = slot.lock().unwrap().take().unwrap();
-                        let val = result.map_err(|e| anyhow::anyhow!("{}", e))?;
-
-                        // Compute digests
-                        let run_digest = if trace_path.exists() {
-                            let trace_bytes = fs::read(trace_path).unwrap_or_default();
-                            let digest = format!("sha256:{}", hex_lower(&{
-                                let mut h = NativeSha256::new();
-                                h.update(&trace_bytes);
-                                h.finalize()
-                            }));
-                            let _ = fs::remove_file(trace_path);
-                            digest
-                        } else {
-                            "sha256:no-trace".to_string()
-                        };
-                        let result_digest = if let Some(j) = val.to_json() {
-                            let s = json_to_string(&j);
-                            format!("sha256:{}", hex_lower(&{
-                                let mut h = NativeSha256::new();
-                                h.update(s.as_bytes());
-                                h.finalize()
-                            }))
-                        } else {
-                            "sha256:no-result".to_string()
-                        };
-                        tracer.child_receipt(spawn_id, &run_digest, &result_digest)?;
-                        results.push(val);
+                        let done = child_wait(slot);
+                        results.push(child_join(tracer, loader, spawn_id, trace_path, done)?);
                     }
 
                     // Emit ordered receipt event — proof that results are in spawn order
-                    let _ = tracer.emit_raw(&format!(
-                        r#"{{"t":"spawn_ordered_complete","count":{}}}"#,
-                        results.len()
-                    ));
+                    let mut m = Map::new();
+                    m.insert("t".to_string(), J::Str("spawn_ordered_complete".to_string()));
+                    m.insert("count".to_string(), J::Int(results.len() as i64));
+                    tracer.emit_event(J::Object(m))?;
 
                     Ok(Val::List(results))
                 }
@@ -9051,43 +12596,8 @@ fn call_builtin(
         }
         Builtin::PromiseAwait => match args.as_slice() {
             [Val::Promise(slot, spawn_id, trace_path)] => {
-                let spawn_id = spawn_id.clone();
-                let trace_path = trace_path.clone();
-                loop {
-                    let done = slot.lock().unwrap().is_some();
-                    if done {
-                        let result = slot.lock().unwrap().take().unwrap();
-                        let val = result.map_err(|e| anyhow::anyhow!("{}", e))?;
-                        // Read child trace and compute its digest
-                        let run_digest = if trace_path.exists() {
-                            let trace_bytes = fs::read(&trace_path).unwrap_or_default();
-                            let digest = format!("sha256:{}", hex_lower(&{
-                                let mut h = NativeSha256::new();
-                                h.update(&trace_bytes);
-                                h.finalize()
-                            }));
-                            let _ = fs::remove_file(&trace_path);
-                            digest
-                        } else {
-                            "sha256:no-trace".to_string()
-                        };
-                        // Compute result digest
-                        let result_digest = if let Some(j) = val.to_json() {
-                            let s = json_to_string(&j);
-                            format!("sha256:{}", hex_lower(&{
-                                let mut h = NativeSha256::new();
-                                h.update(s.as_bytes());
-                                h.finalize()
-                            }))
-                        } else {
-                            "sha256:no-result".to_string()
-                        };
-                        // Record child receipt in parent trace
-                        tracer.child_receipt(&spawn_id, &run_digest, &result_digest)?;
-                        return Ok(val);
-                    }
-                    std::thread::sleep(std::time::Duration::from_millis(1));
-                }
+                let done = child_wait(slot);
+                child_join(tracer, loader, spawn_id, trace_path, done)
             }
             _ => bail!("promise.await expects a promise"),
         }
@@ -9165,16 +12675,16 @@ fn call_builtin(
             }
             _ => bail!("ERROR_BADARG int.to_str_padded expects (int, width, pad_char)"),
         }
-        Builtin::UuidV4 => {
+        Builtin::UuidV4 => oracle_answer("uuid.v4", &args, tracer, || {
             Ok(Val::Text(uuid::Uuid::new_v4().to_string()))
-        }
+        }),
         Builtin::UuidValidate => match args.as_slice() {
             [Val::Text(s)] => Ok(Val::Bool(uuid::Uuid::parse_str(s).is_ok())),
             _ => bail!("ERROR_BADARG uuid.validate expects text"),
         }
-        Builtin::DateTimeNow => {
+        Builtin::DateTimeNow => oracle_answer("datetime.now", &args, tracer, || {
             Ok(Val::Int(chrono::Utc::now().timestamp()))
-        }
+        }),
         Builtin::DateTimeFormat => match args.as_slice() {
             [Val::Int(ts), Val::Text(fmt)] => {
                 let dt = chrono::DateTime::from_timestamp(*ts, 0)
@@ -9472,10 +12982,13 @@ fn call_builtin(
         }
         Builtin::EnvGet => match args.as_slice() {
             [Val::Text(key)] => {
-                match std::env::var(key.as_str()) {
-                    Ok(v) => { let mut m = BTreeMap::new(); m.insert("some".to_string(), Val::Text(v)); Ok(Val::Record(m)) }
-                    Err(_) => { let mut m = BTreeMap::new(); m.insert("none".to_string(), Val::Unit); Ok(Val::Record(m)) }
-                }
+                cap_check(tracer, "env", "env.get", key)?;
+                oracle_answer("env.get", &args, tracer, || {
+                    match std::env::var(key.as_str()) {
+                        Ok(v) => { let mut m = BTreeMap::new(); m.insert("some".to_string(), Val::Text(v)); Ok(Val::Record(m)) }
+                        Err(_) => { let mut m = BTreeMap::new(); m.insert("none".to_string(), Val::Unit); Ok(Val::Record(m)) }
+                    }
+                })
             }
             _ => bail!("ERROR_BADARG env.get expects text key"),
         }
@@ -9486,6 +12999,7 @@ fn call_builtin(
         Builtin::ProcessSpawn => {
             if args.len() < 2 { bail!("ERROR_BADARG process.spawn expects (text, list) or (text, list, stdin_text)"); }
             let cmd = match &args[0] { Val::Text(s) => s.clone(), _ => bail!("ERROR_BADARG process.spawn: cmd must be text") };
+            cap_check(tracer, "exec", "process.spawn", &cmd)?;
             let cmd_args = match &args[1] { Val::List(l) => l.clone(), _ => bail!("ERROR_BADARG process.spawn: args must be list") };
             let stdin_text: Option<String> = if args.len() >= 3 {
                 match &args[2] { Val::Text(s) => Some(s.clone()), Val::Unit => None, _ => None }
@@ -9494,25 +13008,27 @@ fn call_builtin(
                 Val::Text(s) => Ok(s.clone()),
                 _ => Err(anyhow::anyhow!("process.spawn: args must be text")),
             }).collect::<Result<Vec<_>>>()?;
-            let mut child = std::process::Command::new(cmd.as_str())
-                .args(&str_args)
-                .stdin(if stdin_text.is_some() { std::process::Stdio::piped() } else { std::process::Stdio::null() })
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped())
-                .spawn()
-                .map_err(|e| anyhow::anyhow!("ERROR_IO process.spawn: {}", e))?;
-            if let Some(text) = stdin_text {
-                if let Some(mut stdin) = child.stdin.take() {
-                    use std::io::Write;
-                    let _ = stdin.write_all(text.as_bytes());
-                }
-            }
-            let out = child.wait_with_output().map_err(|e| anyhow::anyhow!("ERROR_IO process.spawn wait: {}", e))?;
-            let mut m = BTreeMap::new();
-            m.insert("stdout".to_string(), Val::Text(String::from_utf8_lossy(&out.stdout).to_string()));
-            m.insert("stderr".to_string(), Val::Text(String::from_utf8_lossy(&out.stderr).to_string()));
-            m.insert("code".to_string(), Val::Int(out.status.code().unwrap_or(-1) as i64));
-            Ok(Val::Record(m))
+            oracle_answer("process.spawn", &args, tracer, || {
+                let mut child = std::process::Command::new(cmd.as_str())
+                    .args(&str_args)
+                    .stdin(if stdin_text.is_some() { std::process::Stdio::piped() } else { std::process::Stdio::null() })
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
+                    .spawn()
+                    .map_err(|e| anyhow::anyhow!("ERROR_IO process.spawn: {}", e))?;
+                if let Some(text) = stdin_text {
+                    if let Some(mut stdin) = child.stdin.take() {
+                        use std::io::Write;
+                        let _ = stdin.write_all(text.as_bytes());
+                    }
+                }
+                let out = child.wait_with_output().map_err(|e| anyhow::anyhow!("ERROR_IO process.spawn wait: {}", e))?;
+                let mut m = BTreeMap::new();
+                m.insert("stdout".to_string(), Val::Text(String::from_utf8_lossy(&out.stdout).to_string()));
+                m.insert("stderr".to_string(), Val::Text(String::from_utf8_lossy(&out.stderr).to_string()));
+                m.insert("code".to_string(), Val::Int(out.status.code().unwrap_or(-1) as i64));
+                Ok(Val::Record(m))
+            })
         }
         Builtin::ProcessExit => match args.as_slice() {
             [Val::Int(code)] => { std::process::exit(*code as i32); }
@@ -9559,7 +13075,7 @@ fn call_builtin(
             [Val::Bytes(_)]  => Ok(Val::Text("bytes".to_string())),
             [Val::List(_)]   => Ok(Val::Text("list".to_string())),
             [Val::Record(_)] => Ok(Val::Text("record".to_string())),
-            [Val::Func(_)]   => Ok(Val::Text("func".to_string())),
+            [Val::Func(_) | Val::VmFunc(_)] => Ok(Val::Text("func".to_string())),
             [Val::Builtin(_)]=> Ok(Val::Text("func".to_string())),
             [Val::BoundMethod(_,_)] => Ok(Val::Text("func".to_string())),
             _ => bail!("ERROR_BADARG type_of expects 1 arg"),
@@ -9812,6 +13328,7 @@ fn insertion_sort(xs: &mut [i64]) {
         xs[j] = key;
     }
 }
+#[derive(Clone)]
 struct Lockfile {
     modules: HashMap<String, String>,
 }
@@ -10023,24 +13540,52 @@ impl ModuleLoader {
             pkg_deps: HashMap::new(),
         }
     }
-    fn load_fard_toml(&mut self, toml_path: &Path) {
-        if let Ok(src) = fs::read_to_string(toml_path) {
-            // Parse [deps] section: name = "name@version"
-            let mut in_deps = false;
-            for line in src.lines() {
-                let line = line.trim();
-                if line == "[deps]" { in_deps = true; continue; }
-                if line.starts_with('[') { in_deps = false; continue; }
-                if in_deps && line.contains('=') {
-                    let mut parts = line.splitn(2, '=');
-                    let k = parts.next().unwrap_or("").trim().to_string();
-                    let v = parts.next().unwrap_or("").trim().trim_matches('"').to_string();
-                    if !k.is_empty() && !v.is_empty() {
+    /// A loader for a child task: same roots, lockfile and module cache.
+    fn fork(&self) -> Self {
+        Self {
+            root_dir: self.root_dir.clone(),
+            registry_dir: self.registry_dir.clone(),
+            cache: self.cache.clone(),
+            stack: Vec::new(),
+            lock: self.lock.clone(),
+            enforce_lockfile: self.enforce_lockfile,
+            graph: self.graph.clone(),
+            current: self.current,
+            pkg_deps: self.pkg_deps.clone(),
+        }
+    }
+    /// Fold a finished child's module graph and cache back into this loader.
+    fn absorb(&mut self, child: ModuleLoader) {
+        let ids: Vec<usize> = child.graph.nodes.iter()
+            .map(|n| self.graph.intern_node(&n.spec, n.kind, n.path.clone(), n.digest.clone()))
+            .collect();
+        for e in &child.graph.edges {
+            let (from, to) = (ids[e.from], ids[e.to]);
+            if !self.graph.edges.iter().any(|x| x.from == from && x.to == to) {
+                self.graph.add_edge(from, to);
+            }
+        }
+        for (k, v) in child.cache {
+            self.cache.entry(k).or_insert(v);
+        }
+    }
+    /// Read `[deps]` (short name = "name@version") from a `fard.toml`, returning
+    /// the package it declares, if any.
+    fn load_fard_toml(&mut self, toml_path: &Path) -> Option<String> {
+        let src = fs::read_to_string(toml_path).ok()?;
+        let mut section = String::new();
+        for (_, line) in toml_lines(&src).unwrap_or_default() {
+            match line {
+                TomlLine::Table(name) | TomlLine::ArrayTable(name) => section = name,
+                TomlLine::Pair(k, v) if section == "deps" => {
+                    if let Ok(v) = parse_toml_str(&v) {
                         self.pkg_deps.insert(k, v);
                     }
                 }
+                TomlLine::Pair(..) => {}
             }
         }
+        package_name(&src)
     }
 
     fn graph_note_import(
@@ -10149,11 +13694,26 @@ impl ModuleLoader {
         // Convert valuecore::Val -> fardrun::Val directly (no v0 wire encoding)
         Ok(vcore_to_fardrun(vcore))
     }
-    fn set_vm_fns_ptr() {
-        VM_FNS.with(|cell| {
-            let ptr: *const Vec<VmCompiledFn> = &*cell.borrow();
-            VM_FNS_PTR.with(|p| p.set(ptr));
-        });
+    /// Evaluates a top-level `let` or expression on the VM, or with eval() if it cannot be compiled.
+    fn eval_top(
+        &mut self,
+        e: &Expr,
+        unit: String,
+        module: &str,
+        env: &mut Env,
+        imports: &std::collections::HashSet<String>,
+        tracer: &mut Tracer,
+    ) -> Result<Val> {
+        match vm_compile_chunk(&unit, e, env, imports) {
+            Ok(chunk) => {
+                vm_stats_unit(module, unit, None);
+                vm_call(chunk, Vec::new(), tracer, self)
+            }
+            Err(err) => {
+                vm_stats_unit(module, unit, Some(err.to_string()));
+                eval(e, env, tracer, self)
+            }
+        }
     }
 
     fn eval_items(
@@ -10165,6 +13725,32 @@ impl ModuleLoader {
     ) -> Result<Val> {
         let mut exports: Option<Vec<String>> = None;
         let mut last: Val = Val::Unit;
+        let module = self
+            .current
+            .and_then(|i| self.graph.nodes.get(i))
+            .map(|n| n.spec.clone())
+            .unwrap_or_else(|| "<repl>".to_string());
+        // Import aliases nothing else in the module binds; the VM folds their members
+        let mut imports = std::collections::HashSet::new();
+        let mut rebound = std::collections::HashSet::new();
+        for it in &items {
+            match it {
+                Item::Import(_, alias) if !imports.insert(alias.clone()) => {
+                    rebound.insert(alias.clone());
+                }
+                Item::Artifact(name, _) | Item::Let(name, _, _) | Item::Fn(name, _, _, _) => {
+                    rebound.insert(name.clone());
+                }
+                Item::TypeDef(name, kind) => {
+                    rebound.insert(name.clone());
+                    if let TypeDefKind::Sum(variants) = kind {
+                        rebound.extend(variants.iter().map(|(v, _)| v.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        imports.retain(|a| !rebound.contains(a));
         for it in items {
             match it {
                 Item::Import(path, alias) => {
@@ -10173,11 +13759,10 @@ impl ModuleLoader {
                 }
                 Item::Artifact(name, run_id) => {
                     // Witness composition: load a prior verified run by RunID
-                    let hex = run_id.strip_prefix("sha256:").unwrap_or(&run_id);
-                    let receipt_path = format!("receipts/sha256_{}.json", hex);
-                    let bytes = std::fs::read(&receipt_path).map_err(|_| {
-                        anyhow!("ERROR_ARTIFACT run_id {} not found in receipts/", run_id)
-                    })?;
+                    let store = receipt_store();
+                    let bytes = store.get(&run_id)
+                        .map_err(|e| anyhow!("ERROR_ARTIFACT receipt store error: {:#}", e))?
+                        .ok_or_else(|| anyhow!("ERROR_ARTIFACT run_id {} not found in {}", run_id, store.describe()))?;
                     let receipt = json_from_slice(&bytes)
                         .map_err(|e| anyhow!("ERROR_ARTIFACT malformed receipt: {}", e))?;
                     // Verify RunID matches
@@ -10188,6 +13773,8 @@ impl ModuleLoader {
                     if stored_id != run_id {
                         bail!("ERROR_ARTIFACT run_id mismatch: stored={} requested={}", stored_id, run_id);
                     }
+                    check_parent_stdlib_root(&format!("run {}", run_id), receipt_stdlib_root(&receipt))
+                        .map_err(|e| anyhow!("ERROR_ARTIFACT {}", e))?;
                     // Extract output field
                     let output = match &receipt {
                         J::Object(m) => m.get("output").cloned().ok_or_else(|| anyhow!("ERROR_ARTIFACT receipt missing output"))?,
@@ -10200,7 +13787,8 @@ impl ModuleLoader {
                     env.set(name, val);
                 }
                 Item::Let(name, rhs, span) => {
-                    let v = eval(&rhs, env, tracer, self).map_err(|e| {
+                    let unit = format!("let {}", name);
+                    let v = self.eval_top(&rhs, unit, &module, env, &imports, tracer).map_err(|e| {
                         if let Some(sp) = &span {
                             e.context(format!("  --> {}:{}:{}", sp.file, sp.line, sp.col))
                         } else { e }
@@ -10209,99 +13797,21 @@ impl ModuleLoader {
                 }
                 Item::Fn(name, params, _ret, body) => {
                     let raw_params: Vec<Pat> = params.into_iter().map(|(p, _)| p).collect();
-                    // Try to compile to VM bytecode for pure functions with simple bind params
-                    let has_while = expr_contains_while(&body);
-                    let all_bind = !has_while && raw_params.iter().all(|p| matches!(p, Pat::Bind(_)));
-                    let vm_val = if all_bind {
-                        let mut fns: Vec<VmCompiledFn> = Vec::new();
-                        let mut compiler = VmCompiler::new();
-                        // Pre-allocate param slots
-                        for p in &raw_params {
-                            if let Pat::Bind(n) = p { compiler.slot_of(n); }
+                    let f = match vm_compile_fn(&name, &raw_params, &body, env, &imports) {
+                        Ok(f) => {
+                            vm_stats_unit(&module, format!("fn {}", name), None);
+                            f
                         }
-                        // Pre-populate env slots so closures over outer vars work
-                        let env_keys = env.keys();
-                        for k in &env_keys {
-                            compiler.slot_of(k);
-                        }
-                        if compiler.compile(&body, &mut fns).is_ok() {
-                            compiler.emit(VmOp::Return);
-                            // Add self-name slot so recursive calls work
-                            let self_slot = compiler.slot_of(&name);
-                            let n_slots = compiler.slot_names.len();
-                            let slot_names = compiler.slot_names.clone();
-                            // Build free_vars: only slots actually referenced as Var in the body
-                            let n_params_local = raw_params.len();
-                            let mut free_vars: Vec<(usize, Val)> = Vec::new();
-                            for (i, sname) in slot_names.iter().enumerate() {
-                                if i >= n_params_local && sname != &name {
-                                    // Only include if the body actually uses this var
-                                    if expr_contains_var(&body, sname) {
-                                        if let Some(v) = env.get(sname) {
-                                            if !matches!(v, Val::Unit) {
-                                                free_vars.push((i, v));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            fns.push(VmCompiledFn {
-                                code: compiler.code,
-                                n_slots,
-                                n_params: raw_params.len(),
-                                slot_names,
-                                self_slot: Some(self_slot),
-                                free_vars,
-                            });
-                            let fn_idx = fns.len() - 1;
-                            // Store compiled fns in a thread-local for the VM to access
-                            VM_FNS.with(|cell| {
-                                let mut v = cell.borrow_mut();
-                                let base = v.len();
-                                // Remap fn_indices and record self-slot
-                                for mut f in fns {
-                                    for op in f.code.iter_mut() {
-                                        if let VmOp::MakeClosure(i) = op { *i += base; }
-                                    }
-                                    v.push(f);
-                                }
-                                let global_idx = base + fn_idx;
-                                // Register self-slot so vm_exec_fn pre-populates it
-                                VM_SELF_SLOTS.with(|ss| {
-                                    ss.borrow_mut().insert(global_idx, (self_slot, Val::VmFunc(global_idx)));
-                                });
-                                Some(Val::VmFunc(global_idx))
+                        Err(e) => {
+                            vm_stats_unit(&module, format!("fn {}", name), Some(e.to_string()));
+                            Val::Func(Func {
+                                params: raw_params,
+                                body,
+                                env: env.clone(),
                             })
-                        } else {
-                            None
                         }
-                    } else { None };
-
-                    let f = vm_val.unwrap_or_else(|| Val::Func(Func {
-                        params: raw_params,
-                        body,
-                        env: env.clone(),
-                    }));
-                    env.set(name.clone(), f.clone());
-                    // For VM fns: inject self-reference into the fn's own slot table
-                    // so recursive calls (Var("fib") -> LoadSlot(N)) resolve correctly
-                    if let Val::VmFunc(fn_idx) = &f {
-                        let fi = *fn_idx;
-                        VM_FNS.with(|cell| {
-                            let fns = cell.borrow();
-                            if let Some(compiled) = fns.get(fi) {
-                                // find slot index for own name
-                                if let Some(self_slot) = compiled.slot_names.iter().position(|s| s == &name) {
-                                    drop(fns);
-                                    // store initial_vals hint: slot N = VmFunc(fi)
-                                    // We do this via a separate thread-local
-                                    VM_SELF_SLOTS.with(|ss| {
-                                        ss.borrow_mut().insert(fi, (self_slot, Val::VmFunc(fi)));
-                                    });
-                                }
-                            }
-                        });
-                    }
+                    };
+                    env.set(name, f);
                 }
                 Item::Export(ns) => exports = Some(ns),
                 Item::Test(_, _, _) => {
@@ -10340,7 +13850,11 @@ impl ModuleLoader {
                     }
                 }
                 Item::Expr(e, span) => {
-                    last = eval(&e, env, tracer, self).map_err(|e| {
+                    let unit = match &span {
+                        Some(sp) => format!("expr at line {}", sp.line),
+                        None => "expr".to_string(),
+                    };
+                    last = self.eval_top(&e, unit, &module, env, &imports, tracer).map_err(|e| {
                         if let Some(sp) = &span {
                             e.context(format!("  --> {}:{}:{}", sp.file, sp.line, sp.col))
                         } else { e }
@@ -10552,637 +14066,11 @@ impl ModuleLoader {
         Ok(())
     }
     fn builtin_std(&self, name: &str) -> Result<BTreeMap<String, Val>> {
-        if name == "std/record" {
-            return self.builtin_std("std/rec");
-        }
-
-        match name {
-            "std/list" => {
-                let mut m = BTreeMap::new();
-                m.insert("len".to_string(), Val::Builtin(Builtin::Len));
-                m.insert("range".to_string(), Val::Builtin(Builtin::ListRange));
-                m.insert("repeat".to_string(), Val::Builtin(Builtin::ListRepeat));
-                m.insert("concat".to_string(), Val::Builtin(Builtin::ListConcat));
-                m.insert("group_by".to_string(), Val::Builtin(Builtin::ListGroupBy));
-                m.insert("group_by".to_string(), Val::Builtin(Builtin::ListGroupBy));
-                m.insert("fold".to_string(), Val::Builtin(Builtin::ListFold));
-                m.insert("map".to_string(), Val::Builtin(Builtin::ListMap));
-                m.insert("filter".to_string(), Val::Builtin(Builtin::ListFilter));
-                m.insert("get".to_string(), Val::Builtin(Builtin::ListGet));
-                m.insert("len".to_string(), Val::Builtin(Builtin::ListLen));
-                m.insert("head".to_string(), Val::Builtin(Builtin::ListHead));
-                m.insert("tail".to_string(), Val::Builtin(Builtin::ListTail));
-                m.insert("append".to_string(), Val::Builtin(Builtin::ListAppend));
-                m.insert("zip".to_string(), Val::Builtin(Builtin::ListZip));
-                m.insert("reverse".to_string(), Val::Builtin(Builtin::ListReverse));
-                m.insert("flatten".to_string(), Val::Builtin(Builtin::ListFlatten));
-                m.insert("set".to_string(), Val::Builtin(Builtin::ListSet));
-                m.insert("any".to_string(), Val::Builtin(Builtin::ListAny));
-                m.insert("all".to_string(), Val::Builtin(Builtin::ListAll));
-                m.insert("find".to_string(), Val::Builtin(Builtin::ListFind));
-                m.insert("find_index".to_string(), Val::Builtin(Builtin::ListFindIndex));
-                m.insert("take".to_string(), Val::Builtin(Builtin::ListTake));
-                m.insert("drop".to_string(), Val::Builtin(Builtin::ListDrop));
-                m.insert("flat_map".to_string(), Val::Builtin(Builtin::ListFlatMap));
-                m.insert("par_map".to_string(), Val::Builtin(Builtin::ListParMap));
-                m.insert("zip_with".to_string(), Val::Builtin(Builtin::ListZipWith));
-                m.insert("chunk".to_string(), Val::Builtin(Builtin::ListChunk));
-                m.insert("sort_by".to_string(), Val::Builtin(Builtin::ListSortBy));
-                m.insert(
-                    "sort_by_int_key".to_string(),
-                    Val::Builtin(Builtin::ListSortByIntKey),
-                );
-                m.insert("sort_int".to_string(), Val::Builtin(Builtin::SortInt));
-                m.insert(
-                    "dedupe_sorted_int".to_string(),
-                    Val::Builtin(Builtin::DedupeSortedInt),
-                );
-                m.insert("hist_int".to_string(), Val::Builtin(Builtin::HistInt));
-                Ok(m)
-            }
-            "std/result" => {
-                let mut m = BTreeMap::new();
-                m.insert("ok".to_string(), Val::Builtin(Builtin::ResultOk));
-                m.insert("err".to_string(), Val::Builtin(Builtin::ResultErr));
-                m.insert("andThen".to_string(), Val::Builtin(Builtin::ResultAndThen));
-                m.insert("and_then".to_string(), Val::Builtin(Builtin::ResultAndThen));
-                m.insert("unwrap_ok".to_string(), Val::Builtin(Builtin::ResultUnwrapOk));
-                m.insert("unwrap_err".to_string(), Val::Builtin(Builtin::ResultUnwrapErr));
-                m.insert("is_ok".to_string(), Val::Builtin(Builtin::ResultIsOk));
-                m.insert("is_err".to_string(), Val::Builtin(Builtin::ResultIsErr));
-                m.insert("map".to_string(), Val::Builtin(Builtin::ResultMap));
-                m.insert("map_err".to_string(), Val::Builtin(Builtin::ResultMapErr));
-                m.insert("or_else".to_string(), Val::Builtin(Builtin::ResultOrElse));
-                m.insert("unwrap".to_string(), Val::Builtin(Builtin::ResultUnwrapOk));
-                m.insert("unwrap_or".to_string(), Val::Builtin(Builtin::ResultUnwrapOr));
-                Ok(m)
-            }
-            "std/grow" => {
-                let mut m = BTreeMap::new();
-                m.insert("append".to_string(), Val::Builtin(Builtin::GrowAppend));
-                m.insert("merge".to_string(), Val::Builtin(Builtin::RecMerge));
-                m.insert(
-                    "unfold_tree".to_string(),
-                    Val::Builtin(Builtin::GrowUnfoldTree),
-                );
-                m.insert("unfold".to_string(), Val::Builtin(Builtin::Unfold));
-                Ok(m)
-            }
-            "std/flow" => {
-                let mut m = BTreeMap::new();
-                m.insert("id".to_string(), Val::Builtin(Builtin::FlowId));
-                m.insert("pipe".to_string(), Val::Builtin(Builtin::FlowPipe));
-                m.insert("tap".to_string(), Val::Builtin(Builtin::FlowTap));
-                Ok(m)
-            }
-            "std/str" => {
-                let mut m = BTreeMap::new();
-                m.insert("len".to_string(), Val::Builtin(Builtin::StrLen));
-                m.insert("trim".to_string(), Val::Builtin(Builtin::StrTrim));
-                m.insert(
-                    "split_lines".to_string(),
-                    Val::Builtin(Builtin::StrSplitLines),
-                );
-                m.insert("toLower".to_string(), Val::Builtin(Builtin::StrToLower));
-                m.insert("lower".to_string(), Val::Builtin(Builtin::StrToLower));
-                m.insert("concat".to_string(), Val::Builtin(Builtin::StrConcat));
-                m.insert("split".to_string(), Val::Builtin(Builtin::StrSplit));
-                m.insert("upper".to_string(), Val::Builtin(Builtin::StrUpper));
-                m.insert("contains".to_string(), Val::Builtin(Builtin::StrContains));
-                m.insert("starts_with".to_string(), Val::Builtin(Builtin::StrStartsWith));
-                m.insert("ends_with".to_string(), Val::Builtin(Builtin::StrEndsWith));
-                m.insert("replace".to_string(), Val::Builtin(Builtin::StrReplace));
-                m.insert("slice".to_string(), Val::Builtin(Builtin::StrSlice));
-                m.insert("format".to_string(), Val::Builtin(Builtin::StrFormat));
-                m.insert("from_int".to_string(), Val::Builtin(Builtin::StrFromInt));
-                m.insert("join".to_string(), Val::Builtin(Builtin::StrJoin));
-                m.insert("from_float".to_string(), Val::Builtin(Builtin::StrFromFloat));
-                m.insert("pad_left".to_string(), Val::Builtin(Builtin::StrPadLeft));
-                m.insert("pad_right".to_string(), Val::Builtin(Builtin::StrPadRight));
-                m.insert("repeat".to_string(), Val::Builtin(Builtin::StrRepeat));
-                m.insert("index_of".to_string(), Val::Builtin(Builtin::StrIndexOf));
-                m.insert("chars".to_string(), Val::Builtin(Builtin::StrChars));
-                Ok(m)
-            }
-            "std/ast" => {
-                let mut m = BTreeMap::new();
-                m.insert("parse".to_string(), Val::Builtin(Builtin::AstParse));
-                Ok(m)
-            }
-            "std/promise" => {
-                let mut m = BTreeMap::new();
-                m.insert("spawn".to_string(), Val::Builtin(Builtin::PromiseSpawn));
-                m.insert("await".to_string(), Val::Builtin(Builtin::PromiseAwait));
-                m.insert("spawn_ordered".to_string(), Val::Builtin(Builtin::PromiseSpawnOrdered));
-                Ok(m)
-            }
-            "std/bigint" => {
-                let mut m = BTreeMap::new();
-                m.insert("from_int".to_string(), Val::Builtin(Builtin::BigFromInt));
-                m.insert("from_str".to_string(), Val::Builtin(Builtin::BigFromStr));
-                m.insert("add".to_string(), Val::Builtin(Builtin::BigAdd));
-                m.insert("sub".to_string(), Val::Builtin(Builtin::BigSub));
-                m.insert("mul".to_string(), Val::Builtin(Builtin::BigMul));
-                m.insert("div".to_string(), Val::Builtin(Builtin::BigDiv));
-                m.insert("mod".to_string(), Val::Builtin(Builtin::BigMod));
-                m.insert("pow".to_string(), Val::Builtin(Builtin::BigPow));
-                m.insert("to_str".to_string(), Val::Builtin(Builtin::BigToStr));
-                m.insert("eq".to_string(), Val::Builtin(Builtin::BigEq));
-                m.insert("lt".to_string(), Val::Builtin(Builtin::BigLt));
-                m.insert("gt".to_string(), Val::Builtin(Builtin::BigGt));
-                Ok(m)
-            }
-            "std/mutex" => {
-                let mut m = BTreeMap::new();
-                m.insert("new".to_string(), Val::Builtin(Builtin::MutexNew));
-                m.insert("lock".to_string(), Val::Builtin(Builtin::MutexLock));
-                m.insert("unlock".to_string(), Val::Builtin(Builtin::MutexUnlock));
-                m.insert("with_lock".to_string(), Val::Builtin(Builtin::MutexWithLock));
-                Ok(m)
-            }
-            "std/chan" => {
-                let mut m = BTreeMap::new();
-                m.insert("new".to_string(), Val::Builtin(Builtin::ChanNew));
-                m.insert("send".to_string(), Val::Builtin(Builtin::ChanSend));
-                m.insert("recv".to_string(), Val::Builtin(Builtin::ChanRecv));
-                m.insert("try_recv".to_string(), Val::Builtin(Builtin::ChanTryRecv));
-                m.insert("close".to_string(), Val::Builtin(Builtin::ChanClose));
-                Ok(m)
-            }
-            "std/uuid" => {
-                let mut m = BTreeMap::new();
-                m.insert("v4".to_string(), Val::Builtin(Builtin::UuidV4));
-                m.insert("validate".to_string(), Val::Builtin(Builtin::UuidValidate));
-                Ok(m)
-            }
-            "std/datetime" => {
-                let mut m = BTreeMap::new();
-                m.insert("now".to_string(), Val::Builtin(Builtin::DateTimeNow));
-                m.insert("format".to_string(), Val::Builtin(Builtin::DateTimeFormat));
-                m.insert("parse".to_string(), Val::Builtin(Builtin::DateTimeParse));
-                m.insert("add".to_string(), Val::Builtin(Builtin::DateTimeAdd));
-                m.insert("diff".to_string(), Val::Builtin(Builtin::DateTimeSub));
-                m.insert("field".to_string(), Val::Builtin(Builtin::DateTimeField));
-                Ok(m)
-            }
-            "std/set" => {
-                let mut m = BTreeMap::new();
-                m.insert("new".to_string(), Val::Builtin(Builtin::SetNew));
-                m.insert("add".to_string(), Val::Builtin(Builtin::SetAdd));
-                m.insert("remove".to_string(), Val::Builtin(Builtin::SetRemove));
-                m.insert("has".to_string(), Val::Builtin(Builtin::SetHas));
-                m.insert("union".to_string(), Val::Builtin(Builtin::SetUnion));
-                m.insert("intersect".to_string(), Val::Builtin(Builtin::SetIntersect));
-                m.insert("diff".to_string(), Val::Builtin(Builtin::SetDiff));
-                m.insert("to_list".to_string(), Val::Builtin(Builtin::SetToList));
-                m.insert("from_list".to_string(), Val::Builtin(Builtin::SetFromList));
-                m.insert("size".to_string(), Val::Builtin(Builtin::SetSize));
-                Ok(m)
-            }
-            "std/map" => {
-                let mut m = BTreeMap::new();
-                m.insert("get".to_string(), Val::Builtin(Builtin::MapGet));
-                m.insert("set".to_string(), Val::Builtin(Builtin::MapSet));
-                m.insert("keys".to_string(), Val::Builtin(Builtin::RecKeys));
-                m.insert("values".to_string(), Val::Builtin(Builtin::RecValues));
-                m.insert("has".to_string(), Val::Builtin(Builtin::RecHas));
-                m.insert("delete".to_string(), Val::Builtin(Builtin::MapDelete));
-                m.insert("entries".to_string(), Val::Builtin(Builtin::MapEntries));
-                m.insert("new".to_string(), Val::Builtin(Builtin::RecEmpty));
-                m.insert("from_entries".to_string(), Val::Builtin(Builtin::RecEmpty));
-                Ok(m)
-            }
-            "std/rec" => {
-                let mut m = BTreeMap::new();
-                m.insert("empty".to_string(), Val::Builtin(Builtin::RecEmpty));
-                m.insert("keys".to_string(), Val::Builtin(Builtin::RecKeys));
-                m.insert("values".to_string(), Val::Builtin(Builtin::RecValues));
-                m.insert("has".to_string(), Val::Builtin(Builtin::RecHas));
-                m.insert("get".to_string(), Val::Builtin(Builtin::RecGet));
-                m.insert("getOr".to_string(), Val::Builtin(Builtin::RecGetOr));
-                m.insert("getOrErr".to_string(), Val::Builtin(Builtin::RecGetOrErr));
-                m.insert("set".to_string(), Val::Builtin(Builtin::RecSet));
-                m.insert("remove".to_string(), Val::Builtin(Builtin::RecRemove));
-                m.insert("merge".to_string(), Val::Builtin(Builtin::RecMerge));
-                m.insert("select".to_string(), Val::Builtin(Builtin::RecSelect));
-                m.insert("rename".to_string(), Val::Builtin(Builtin::RecRename));
-                m.insert("update".to_string(), Val::Builtin(Builtin::RecUpdate));
-                Ok(m)
-            }
-            "std/json" => {
-                let mut m = BTreeMap::new();
-                m.insert("encode".to_string(), Val::Builtin(Builtin::JsonEncode));
-                m.insert("decode".to_string(), Val::Builtin(Builtin::JsonDecode));
-                m.insert("canonicalize".to_string(), Val::Builtin(Builtin::JsonCanonicalize));
-                Ok(m)
-            }
-
-            "std/type" => {
-                let mut m = BTreeMap::new();
-                m.insert("of".to_string(), Val::Builtin(Builtin::TypeOf));
-                Ok(m)
-            }
-            "std/cast" =>
-            {
-                let mut m = BTreeMap::new();
-                m.insert("float".to_string(), Val::Builtin(Builtin::CastFloat));
-                m.insert("int".to_string(),   Val::Builtin(Builtin::CastInt));
-                m.insert("text".to_string(), Val::Builtin(Builtin::CastText));
-                Ok(m)
-            }
-            "std/int" => {
-                let mut m = BTreeMap::new();
-                m.insert("add".to_string(), Val::Builtin(Builtin::IntAdd));
-                m.insert("eq".to_string(), Val::Builtin(Builtin::IntEq));
-                m.insert("parse".to_string(), Val::Builtin(Builtin::IntParse));
-                m.insert("pow".to_string(), Val::Builtin(Builtin::IntPow));
-                m.insert("to_hex".to_string(), Val::Builtin(Builtin::IntToHex));
-                m.insert("to_bin".to_string(), Val::Builtin(Builtin::IntToBin));
-                m.insert("mul".to_string(), Val::Builtin(Builtin::IntMul));
-                m.insert("div".to_string(), Val::Builtin(Builtin::IntDiv));
-                m.insert("sub".to_string(), Val::Builtin(Builtin::IntSub));
-                m.insert("abs".to_string(), Val::Builtin(Builtin::IntAbs));
-                m.insert("min".to_string(), Val::Builtin(Builtin::IntMin));
-                m.insert("max".to_string(), Val::Builtin(Builtin::IntMax));
-                m.insert("to_text".to_string(), Val::Builtin(Builtin::IntToText));
-                m.insert("from_text".to_string(), Val::Builtin(Builtin::IntFromText));
-                m.insert("neg".to_string(), Val::Builtin(Builtin::IntNeg));
-                m.insert("clamp".to_string(), Val::Builtin(Builtin::IntClamp));
-                m.insert("mod".to_string(), Val::Builtin(Builtin::IntMod));
-                m.insert("lt".to_string(), Val::Builtin(Builtin::IntLt));
-                m.insert("gt".to_string(), Val::Builtin(Builtin::IntGt));
-                m.insert("le".to_string(), Val::Builtin(Builtin::IntLe));
-                m.insert("ge".to_string(), Val::Builtin(Builtin::IntGe));
-                                m.insert("to_str_padded".to_string(), Val::Builtin(Builtin::IntToStrPadded));
-Ok(m)
-            }
-            "std/fs" => {
-                let mut m = BTreeMap::new();
-                m.insert("read_text".to_string(), Val::Builtin(Builtin::FsReadText));
-                m.insert("write_text".to_string(), Val::Builtin(Builtin::FsWriteText));
-                m.insert("exists".to_string(), Val::Builtin(Builtin::FsExists));
-                m.insert("read_dir".to_string(), Val::Builtin(Builtin::FsReadDir));
-                m.insert("stat".to_string(), Val::Builtin(Builtin::FsStat));
-                m.insert("delete".to_string(), Val::Builtin(Builtin::FsDelete));
-                m.insert("make_dir".to_string(), Val::Builtin(Builtin::FsMakeDir));
-                Ok(m)
-            }
-            "std/option" => {
-                let mut m = BTreeMap::new();
-                m.insert("none".to_string(), Val::Builtin(Builtin::OptionNone));
-                m.insert("None".to_string(), Val::Builtin(Builtin::OptionNone));
-                m.insert("some".to_string(), Val::Builtin(Builtin::OptionSome));
-                m.insert("Some".to_string(), Val::Builtin(Builtin::OptionSome));
-                m.insert("is_none".to_string(), Val::Builtin(Builtin::OptionIsNone));
-                m.insert("isNone".to_string(), Val::Builtin(Builtin::OptionIsNone));
-                m.insert("is_some".to_string(), Val::Builtin(Builtin::OptionIsSome));
-                m.insert("isSome".to_string(), Val::Builtin(Builtin::OptionIsSome));
-                m.insert("from_nullable".to_string(), Val::Builtin(Builtin::OptionFromNullable));
-                m.insert("fromNullable".to_string(), Val::Builtin(Builtin::OptionFromNullable));
-                m.insert("to_nullable".to_string(), Val::Builtin(Builtin::OptionToNullable));
-                m.insert("toNullable".to_string(), Val::Builtin(Builtin::OptionToNullable));
-                m.insert("map".to_string(), Val::Builtin(Builtin::OptionMap));
-                m.insert("and_then".to_string(), Val::Builtin(Builtin::OptionAndThen));
-                m.insert("andThen".to_string(), Val::Builtin(Builtin::OptionAndThen));
-                m.insert("unwrap_or".to_string(), Val::Builtin(Builtin::OptionUnwrapOr));
-                m.insert("unwrapOr".to_string(), Val::Builtin(Builtin::OptionUnwrapOr));
-                m.insert("unwrap_or_else".to_string(), Val::Builtin(Builtin::OptionUnwrapOrElse));
-                m.insert("unwrapOrElse".to_string(), Val::Builtin(Builtin::OptionUnwrapOrElse));
-                m.insert("to_result".to_string(), Val::Builtin(Builtin::OptionToResult));
-                m.insert("toResult".to_string(), Val::Builtin(Builtin::OptionToResult));
-                Ok(m)
-            }
-            "std/bits" => {
-                let mut m = std::collections::BTreeMap::new();
-                m.insert("band".to_string(),     Val::Builtin(Builtin::BitAnd));
-                m.insert("bor".to_string(),      Val::Builtin(Builtin::BitOr));
-                m.insert("bxor".to_string(),     Val::Builtin(Builtin::BitXor));
-                m.insert("bnot".to_string(),     Val::Builtin(Builtin::BitNot));
-                m.insert("bshl".to_string(),     Val::Builtin(Builtin::BitShl));
-                m.insert("bshr".to_string(),     Val::Builtin(Builtin::BitShr));
-                m.insert("popcount".to_string(), Val::Builtin(Builtin::BitPopcount));
-                Ok(m)
-            }
-            "std/math" => {
-                let mut m = BTreeMap::new();
-                m.insert("abs".to_string(), Val::Builtin(Builtin::MathAbs));
-                m.insert("min".to_string(), Val::Builtin(Builtin::MathMin));
-                m.insert("max".to_string(), Val::Builtin(Builtin::MathMax));
-                m.insert("pow".to_string(), Val::Builtin(Builtin::MathPow));
-                m.insert("sqrt".to_string(), Val::Builtin(Builtin::MathSqrt));
-                m.insert("floor".to_string(), Val::Builtin(Builtin::MathFloor));
-                m.insert("ceil".to_string(), Val::Builtin(Builtin::MathCeil));
-                m.insert("round".to_string(), Val::Builtin(Builtin::MathRound));
-                m.insert("log".to_string(), Val::Builtin(Builtin::MathLog));
-                m.insert("log2".to_string(), Val::Builtin(Builtin::MathLog2));
-                m.insert("sin".to_string(), Val::Builtin(Builtin::MathSin));
-                m.insert("cos".to_string(), Val::Builtin(Builtin::MathCos));
-                m.insert("tan".to_string(), Val::Builtin(Builtin::MathTan));
-                m.insert("atan2".to_string(), Val::Builtin(Builtin::MathAtan2));
-                m.insert("exp".to_string(), Val::Builtin(Builtin::MathExp));
-                m.insert("pi".to_string(), Val::Float(std::f64::consts::PI));
-                m.insert("e".to_string(), Val::Float(std::f64::consts::E));
-                m.insert("inf".to_string(), Val::Float(f64::INFINITY));
-                m.insert("asin".to_string(), Val::Builtin(Builtin::MathAsin));
-                m.insert("acos".to_string(), Val::Builtin(Builtin::MathAcos));
-                m.insert("atan".to_string(), Val::Builtin(Builtin::MathAtan));
-                m.insert("log10".to_string(), Val::Builtin(Builtin::MathLog10));
-                Ok(m)
-            }
-            "std/null" => {
-                let mut m = BTreeMap::new();
-                m.insert("isNull".to_string(), Val::Builtin(Builtin::NullIsNull));
-                m.insert("coalesce".to_string(), Val::Builtin(Builtin::NullCoalesce));
-                m.insert("guardNotNull".to_string(), Val::Builtin(Builtin::NullGuard));
-                Ok(m)
-            }
-            "std/path" => {
-                let mut m = BTreeMap::new();
-                m.insert("base".to_string(), Val::Builtin(Builtin::PathBase));
-                m.insert("dir".to_string(), Val::Builtin(Builtin::PathDir));
-                m.insert("ext".to_string(), Val::Builtin(Builtin::PathExt));
-                m.insert("isAbs".to_string(), Val::Builtin(Builtin::PathIsAbs));
-                m.insert("join".to_string(), Val::Builtin(Builtin::PathJoin));
-                m.insert("joinAll".to_string(), Val::Builtin(Builtin::PathJoinAll));
-                m.insert("normalize".to_string(), Val::Builtin(Builtin::PathNormalize));
-                Ok(m)
-            }
-            "std/time" => {
-                let mut m = BTreeMap::new();
-                m.insert("now".to_string(), Val::Builtin(Builtin::TimeNow));
-                m.insert("parse".to_string(), Val::Builtin(Builtin::TimeParse));
-                m.insert("format".to_string(), Val::Builtin(Builtin::TimeFormat));
-                m.insert("add".to_string(), Val::Builtin(Builtin::TimeAdd));
-                m.insert("sub".to_string(), Val::Builtin(Builtin::TimeSub));
-                let mut d = BTreeMap::new();
-                d.insert("ms".to_string(), Val::Builtin(Builtin::TimeDurationMs));
-                d.insert("sec".to_string(), Val::Builtin(Builtin::TimeDurationSec));
-                d.insert("min".to_string(), Val::Builtin(Builtin::TimeDurationMin));
-                m.insert("Duration".to_string(), Val::Record(d));
-                Ok(m)
-            }
-            "std/trace" => {
-                let mut m = BTreeMap::new();
-                m.insert("emit".to_string(), Val::Builtin(Builtin::Emit));
-                m.insert("info".to_string(), Val::Builtin(Builtin::TraceInfo));
-                m.insert("warn".to_string(), Val::Builtin(Builtin::TraceWarn));
-                m.insert("error".to_string(), Val::Builtin(Builtin::TraceError));
-                m.insert("span".to_string(), Val::Builtin(Builtin::TraceSpan));
-                Ok(m)
-            }
-            "std/sembit" => {
-                let mut m = BTreeMap::new();
-                m.insert("partition".to_string(), Val::Builtin(Builtin::SembitPartition));
-                Ok(m)
-            }
-            "std/artifact" => {
-                let mut m = BTreeMap::new();
-                m.insert("import".to_string(), Val::Builtin(Builtin::ImportArtifact));
-                m.insert("emit".to_string(), Val::Builtin(Builtin::EmitArtifact));
-                m.insert("ref".to_string(), Val::Builtin(Builtin::Unimplemented("std/trace.ref")));
-                m.insert("derive".to_string(), Val::Builtin(Builtin::Unimplemented("std/trace.derive")));
-                Ok(m)
-            }
-            "std/cli" => {
-                let mut m = BTreeMap::new();
-                m.insert("args".to_string(),      Val::Builtin(Builtin::CliArgs));
-                m.insert("get".to_string(),       Val::Builtin(Builtin::CliGet));
-                m.insert("get_int".to_string(),   Val::Builtin(Builtin::CliGetInt));
-                m.insert("get_float".to_string(), Val::Builtin(Builtin::CliGetFloat));
-                m.insert("get_bool".to_string(),  Val::Builtin(Builtin::CliGetBool));
-                m.insert("has".to_string(),       Val::Builtin(Builtin::CliHas));
-                // cli.parse(spec) is implemented in FARD stdlib, not as a builtin
-                Ok(m)
-            }
-            "std/io" => {
-                let mut m = BTreeMap::new();
-                m.insert("read_file".to_string(),   Val::Builtin(Builtin::IoReadFile));
-                m.insert("write_file".to_string(),  Val::Builtin(Builtin::IoWriteFile));
-                m.insert("append_file".to_string(), Val::Builtin(Builtin::IoAppendFile));
-                m.insert("read_lines".to_string(),  Val::Builtin(Builtin::IoReadLines));
-                m.insert("file_exists".to_string(), Val::Builtin(Builtin::IoFileExists));
-                m.insert("delete_file".to_string(), Val::Builtin(Builtin::IoDeleteFile));
-                m.insert("read_stdin".to_string(),  Val::Builtin(Builtin::IoReadStdin));
-                m.insert("read_stdin_lines".to_string(), Val::Builtin(Builtin::IoReadStdinLines));
-                m.insert("list_dir".to_string(),    Val::Builtin(Builtin::IoListDir));
-                m.insert("make_dir".to_string(),    Val::Builtin(Builtin::IoMakeDir));
-                Ok(m)
-            }
-            "std/bytes" => {
-                let mut m = BTreeMap::new();
-                m.insert("concat".to_string(),      Val::Builtin(Builtin::BytesConcat));
-                m.insert("to_str".to_string(),       Val::Builtin(Builtin::BytesToStr));
-                m.insert("len".to_string(),          Val::Builtin(Builtin::BytesLen));
-                m.insert("get".to_string(),          Val::Builtin(Builtin::BytesGet));
-                m.insert("of_list".to_string(),      Val::Builtin(Builtin::BytesOfList));
-                m.insert("to_list".to_string(),      Val::Builtin(Builtin::BytesToList));
-                m.insert("of_str".to_string(),       Val::Builtin(Builtin::BytesOfStr));
-                m.insert("merkle_root".to_string(),  Val::Builtin(Builtin::BytesMerkleRoot));
-                Ok(m)
-            }
-
-            "std/codec" => {
-                let mut m = BTreeMap::new();
-                m.insert("base64url_encode".to_string(), Val::Builtin(Builtin::CodecBase64UrlEncode));
-                m.insert("base64url_encode_hex".to_string(), Val::Builtin(Builtin::CodecBase64UrlEncodeHex));
-                m.insert("base64url_decode".to_string(), Val::Builtin(Builtin::CodecBase64UrlDecode));
-                m.insert("hex_encode".to_string(), Val::Builtin(Builtin::CodecHexEncode));
-                m.insert("hex_decode".to_string(), Val::Builtin(Builtin::CodecHexDecode));
-                Ok(m)
-            }
-            "std/cell" => {
-                let mut m = BTreeMap::new();
-                m.insert("new".to_string(), Val::Builtin(Builtin::CellNew));
-                m.insert("get".to_string(), Val::Builtin(Builtin::CellGet));
-                m.insert("set".to_string(), Val::Builtin(Builtin::CellSet));
-                Ok(m)
-            }
-            "std/base64" => {
-                let mut m = BTreeMap::new();
-                m.insert("encode".to_string(), Val::Builtin(Builtin::Base64Encode));
-                m.insert("decode".to_string(), Val::Builtin(Builtin::Base64Decode));
-                Ok(m)
-            }
-            "std/csv" => {
-                let mut m = BTreeMap::new();
-                m.insert("parse".to_string(), Val::Builtin(Builtin::CsvParse));
-                m.insert("encode".to_string(), Val::Builtin(Builtin::CsvEncode));
-                Ok(m)
-            }
-            "std/eval" => {
-                let mut m = BTreeMap::new();
-                m.insert("eval".to_string(), Val::Builtin(Builtin::FardEval));
-                Ok(m)
-            }
-            "std/witness" => {
-                let mut m = BTreeMap::new();
-                m.insert("self_digest".to_string(), Val::Builtin(Builtin::WitnessSelfDigest));
-                m.insert("deps".to_string(), Val::Builtin(Builtin::WitnessDeps));
-                m.insert("verify".to_string(), Val::Builtin(Builtin::WitnessVerify));
-                m.insert("verify_chain".to_string(), Val::Builtin(Builtin::WitnessVerifyChain));
-                Ok(m)
-            }
-            "std/ffi" => {
-                let mut m = BTreeMap::new();
-                m.insert("open".to_string(),  Val::Builtin(Builtin::FfiOpen));
-                m.insert("call".to_string(),  Val::Builtin(Builtin::FfiCall));
-                m.insert("call_pure".to_string(), Val::Builtin(Builtin::FfiCallPure));
-                m.insert("call_str".to_string(), Val::Builtin(Builtin::FfiCallStr));
-                m.insert("call_checked".to_string(), Val::Builtin(Builtin::FfiCallChecked));
-                m.insert("load".to_string(),  Val::Builtin(Builtin::FfiOpen));
-                m.insert("close".to_string(), Val::Builtin(Builtin::FfiClose));
-                Ok(m)
-            }
-            "std/re" => {
-                let mut m = BTreeMap::new();
-                m.insert("is_match".to_string(), Val::Builtin(Builtin::ReMatch));
-                m.insert("find".to_string(), Val::Builtin(Builtin::ReFind));
-                m.insert("find_all".to_string(), Val::Builtin(Builtin::ReFindAll));
-                m.insert("split".to_string(), Val::Builtin(Builtin::ReSplit));
-                m.insert("replace".to_string(), Val::Builtin(Builtin::ReReplace));
-                Ok(m)
-            }
-            "std/env" => {
-                let mut m = BTreeMap::new();
-                m.insert("get".to_string(), Val::Builtin(Builtin::EnvGet));
-                m.insert("args".to_string(), Val::Builtin(Builtin::EnvArgs));
-                Ok(m)
-            }
-            "std/process" => {
-                let mut m = BTreeMap::new();
-                m.insert("spawn".to_string(), Val::Builtin(Builtin::ProcessSpawn));
-                m.insert("exit".to_string(), Val::Builtin(Builtin::ProcessExit));
-                Ok(m)
-            }
-            "std/hash" => {
-                let mut m = BTreeMap::new();
-                m.insert("sha256_bytes".to_string(), Val::Builtin(Builtin::HashSha256Bytes));
-                m.insert("sha256_text".to_string(), Val::Builtin(Builtin::HashSha256Text));
-                Ok(m)
-            }
-            "std/http" => {
-                let mut m = BTreeMap::new();
-                m.insert("get".to_string(), Val::Builtin(Builtin::HttpGet));
-                m.insert("post".to_string(), Val::Builtin(Builtin::HttpPost));
-                m.insert("request".to_string(), Val::Builtin(Builtin::HttpRequest));
-                Ok(m)
-            }
-            "std/net" => {
-                let mut m = BTreeMap::new();
-                m.insert("serve".to_string(), Val::Builtin(Builtin::NetServe));
-                Ok(m)
-            }
-            "std/record" => {
-                let m: BTreeMap<String, Val> = BTreeMap::new();
-                Ok(m)
-            }
-
-            "std/png" => {
-                let mut m = BTreeMap::new();
-
-                m.insert("red_1x1".to_string(), Val::Builtin(Builtin::PngRed1x1));
-
-                Ok(m)
-            }
-
-            "std/compress" => {
-                let mut m = BTreeMap::new();
-                m.insert("gzip".to_string(),   Val::Builtin(Builtin::CompressGzip));
-                m.insert("gunzip".to_string(),  Val::Builtin(Builtin::CompressGunzip));
-                Ok(m)
-            }
-            "std/graph" => {
-                let mut m = BTreeMap::new();
-                m.insert("of".to_string(),        Val::Builtin(Builtin::GraphOf));
-                m.insert("ancestors".to_string(),  Val::Builtin(Builtin::GraphAncestors));
-                m.insert("leaves".to_string(),     Val::Builtin(Builtin::GraphLeaves));
-                m.insert("to_dot".to_string(),     Val::Builtin(Builtin::GraphToDot));
-                Ok(m)
-            }
-            "std/rand" => {
-                let mut m = BTreeMap::new();
-                m.insert("uuid_v4".to_string(), Val::Builtin(Builtin::RandUuidV4));
-                Ok(m)
-            }
-            "std/crypto" => {
-                let mut m = BTreeMap::new();
-                m.insert("ed25519_verify".to_string(), Val::Builtin(Builtin::CryptoEd25519Verify));
-                m.insert("hmac_sha256".to_string(), Val::Builtin(Builtin::CryptoHmacSha256));
-                m.insert("sha512".to_string(), Val::Builtin(Builtin::CryptoSha512));
-                m.insert("aes_encrypt".to_string(), Val::Builtin(Builtin::CryptoAesEncrypt));
-                m.insert("aes_decrypt".to_string(), Val::Builtin(Builtin::CryptoAesDecrypt));
-                m.insert("merkle_root".to_string(), Val::Builtin(Builtin::CryptoMerkleRoot));
-                Ok(m)
-            }
-            "std/float" => {
-                let mut m = BTreeMap::new();
-                m.insert("from_int".to_string(), Val::Builtin(Builtin::FloatFromInt));
-                m.insert("to_int".to_string(), Val::Builtin(Builtin::FloatToInt));
-                m.insert("from_text".to_string(), Val::Builtin(Builtin::FloatFromText));
-                m.insert("to_text".to_string(), Val::Builtin(Builtin::FloatToText));
-                m.insert("add".to_string(), Val::Builtin(Builtin::FloatAdd));
-                m.insert("sub".to_string(), Val::Builtin(Builtin::FloatSub));
-                m.insert("mul".to_string(), Val::Builtin(Builtin::FloatMul));
-                m.insert("div".to_string(), Val::Builtin(Builtin::FloatDiv));
-                m.insert("exp".to_string(), Val::Builtin(Builtin::FloatExp));
-                m.insert("ln".to_string(), Val::Builtin(Builtin::FloatLn));
-                m.insert("sqrt".to_string(), Val::Builtin(Builtin::FloatSqrt));
-                m.insert("pow".to_string(), Val::Builtin(Builtin::FloatPow));
-                m.insert("abs".to_string(), Val::Builtin(Builtin::FloatAbs));
-                m.insert("neg".to_string(), Val::Builtin(Builtin::FloatNeg));
-                m.insert("floor".to_string(), Val::Builtin(Builtin::FloatFloor));
-                m.insert("ceil".to_string(), Val::Builtin(Builtin::FloatCeil));
-                m.insert("round".to_string(), Val::Builtin(Builtin::FloatRound));
-                m.insert("lt".to_string(), Val::Builtin(Builtin::FloatLt));
-                m.insert("gt".to_string(), Val::Builtin(Builtin::FloatGt));
-                m.insert("le".to_string(), Val::Builtin(Builtin::FloatLe));
-                m.insert("ge".to_string(), Val::Builtin(Builtin::FloatGe));
-                m.insert("eq".to_string(), Val::Builtin(Builtin::FloatEq));
-                m.insert("nan".to_string(), Val::Builtin(Builtin::FloatNan));
-                m.insert("inf".to_string(), Val::Builtin(Builtin::FloatInf));
-                m.insert("is_nan".to_string(), Val::Builtin(Builtin::FloatIsNan));
-                m.insert("to_str_fixed".to_string(), Val::Builtin(Builtin::FloatToStrFixed));
-                m.insert("is_inf".to_string(), Val::Builtin(Builtin::FloatIsInf));
-                m.insert("is_finite".to_string(), Val::Builtin(Builtin::FloatIsFinite));
-                m.insert("min".to_string(), Val::Builtin(Builtin::FloatMin));
-                m.insert("max".to_string(), Val::Builtin(Builtin::FloatMax));
-                Ok(m)
-            }
-            "std/linalg" => {
-                let mut m = BTreeMap::new();
-                m.insert("dot".to_string(), Val::Builtin(Builtin::LinalgDot));
-                m.insert("norm".to_string(), Val::Builtin(Builtin::LinalgNorm));
-                m.insert("zeros".to_string(), Val::Builtin(Builtin::LinalgZeros));
-                m.insert("eye".to_string(), Val::Builtin(Builtin::LinalgEye));
-                m.insert("matvec".to_string(), Val::Builtin(Builtin::LinalgMatvec));
-                m.insert("relu".to_string(), Val::Builtin(Builtin::LinalgRelu));
-                m.insert("softmax".to_string(), Val::Builtin(Builtin::LinalgSoftmax));
-                m.insert("argmax".to_string(), Val::Builtin(Builtin::LinalgArgmax));
-                m.insert("matmul".to_string(), Val::Builtin(Builtin::LinalgMatmul));
-                m.insert("transpose".to_string(), Val::Builtin(Builtin::LinalgTranspose));
-                m.insert("eigh".to_string(), Val::Builtin(Builtin::LinalgEigh));
-                m.insert("vec_add".to_string(), Val::Builtin(Builtin::LinalgVecAdd));
-                m.insert("vec_sub".to_string(), Val::Builtin(Builtin::LinalgVecSub));
-                m.insert("vec_scale".to_string(), Val::Builtin(Builtin::LinalgVecScale));
-                m.insert("mat_add".to_string(), Val::Builtin(Builtin::LinalgMatAdd));
-                m.insert("mat_scale".to_string(), Val::Builtin(Builtin::LinalgMatScale));
-                m.insert("vec_exp".to_string(), Val::Builtin(Builtin::LinalgVecExp));
-                m.insert("vec_log".to_string(), Val::Builtin(Builtin::LinalgVecLog));
-                m.insert("vec_sum".to_string(), Val::Builtin(Builtin::LinalgVecSum));
-                m.insert("vec_max".to_string(), Val::Builtin(Builtin::LinalgVecMax));
-                m.insert("vec_mul".to_string(), Val::Builtin(Builtin::LinalgVecMul));
-                m.insert("vec_relu".to_string(), Val::Builtin(Builtin::LinalgVecRelu));
-                m.insert("vec_relu_grad".to_string(), Val::Builtin(Builtin::LinalgVecReluGrad));
-                m.insert("softmax_grad".to_string(), Val::Builtin(Builtin::LinalgSoftmaxGrad));
-                m.insert("cross_entropy".to_string(), Val::Builtin(Builtin::LinalgCrossEntropy));
-                m.insert("outer".to_string(), Val::Builtin(Builtin::LinalgOuter));
-                m.insert("mat_mul_vec_grad".to_string(), Val::Builtin(Builtin::LinalgMatMulVecGrad));
-                m.insert("vec_scalar_add".to_string(), Val::Builtin(Builtin::LinalgVecScalarAdd));
-                m.insert("mat_row_sum".to_string(), Val::Builtin(Builtin::LinalgMatRowSum));
-                Ok(m)
-            }
-            _ => bail!("unknown std module: {name}"),
-        }
+        let name = if name == "std/record" { "std/rec" } else { name };
+        let Some((_, exports)) = STD_MODULES.iter().find(|(m, _)| *m == name) else {
+            bail!("unknown std module: {name}")
+        };
+        Ok(exports.iter().map(|(k, e)| (k.to_string(), std_export_val(e))).collect())
     }
     fn builtin_digest(&self, name: &str) -> String {
         if name == "std/record" {
@@ -11194,56 +14082,9 @@ Ok(m)
         format!("sha256:{}", hex_lower(&h.finalize()))
     }
 
+    /// Content digest of the builtin surface this runtime ships, computed by build.rs.
     fn stdlib_root_digest(&self) -> String {
-        let names: [&str; 29] = [
-            "std/artifact",
-            "std/bytes",
-            "std/codec",
-            "std/env",
-            "std/flow",
-            "std/fs",
-            "std/grow",
-            "std/hash",
-            "std/http",
-            "std/int",
-            "std/json",
-            "std/list",
-            "std/map",
-            "std/option",
-            "std/record",
-            "std/result",
-            "std/str",
-            "std/time",
-            "std/sembit",
-            "std/trace",
-            "std/png",
-            "std/rec",
-            "std/crypto",
-            "std/rand",
-            "std/ffi",
-            "std/witness",
-            "std/net",
-            "std/compress",
-            "std/graph",
-        ];
-
-        let mut pairs: Vec<(String, String)> = names
-            .into_iter()
-            .map(|n| (n.to_string(), self.builtin_digest(n)))
-            .collect();
-
-        pairs.sort_by(|a, b| a.0.cmp(&b.0));
-
-        let mut pre = String::new();
-        pre.push_str("stdlib_root_v0\n");
-        for (n, d) in pairs {
-            pre.push_str(&n);
-            pre.push_str("=");
-            pre.push_str(&d);
-            pre.push_str("\n");
-        }
-
-        format!("sha256:{}", sha256_bytes_hex(pre.as_bytes()))
+        env!("FARD_STDLIB_ROOT_DIGEST").to_string()
     }
 }
 fn base_env() -> Env {
@@ -1,11 +1,10 @@
 use std::env;
 use std::fs;
 
-#[path = "../verify/trace_verify.rs"]
-mod trace_verify;
-
-#[path = "../verify/artifact_verify.rs"]
-mod artifact_verify;
+use fard_v0_5_language_gate::build;
+use fard_v0_5_language_gate::receipt_store::{receipt_run_id, ReceiptStore};
+use fard_v0_5_language_gate::signing::TrustPolicy;
+use fard_v0_5_language_gate::{artifact_verify, trace_verify};
 
 #[path = "../verify/bundle_verify.rs"]
 mod bundle_verify;
@@ -14,9 +13,12 @@ fn usage() -> ! {
     eprintln!("usage:");
     eprintln!("  fardverify trace   --out <dir>");
     eprintln!("  fardverify artifact --out <dir>");
-    eprintln!("  fardverify bundle  --out <dir>");
-    eprintln!("  fardverify chain   --out <dir> [--registry <dir>] [--depth <n>]");
+    eprintln!("  fardverify bundle  --out <dir> [--stdlib-roots <file>]");
+    eprintln!("  fardverify build   --out <dir>");
+    eprintln!("  fardverify chain   --out <dir> [--receipts <store>]... [--receipt-cache <dir>] [--registry <dir>] [--trust <file>] [--depth <n>] [--stdlib-roots <file>]");
+    eprintln!("  fardverify signature --out <dir> --trust <file>");
     eprintln!("  fardverify prove   --out <dir> --spec <spec.json>");
+    eprintln!("  fardverify replay  --out <dir> --program <file.fard> [--fardrun <exe>]");
     std::process::exit(2);
 }
 
@@ -37,14 +39,71 @@ fn get_out(args: &[String]) -> String {
     out.unwrap_or_else(|| usage())
 }
 
+/// The `--trust <file>` policy, if one was given.
+fn trust_policy(args: &[String]) -> Option<TrustPolicy> {
+    let path = args.windows(2).find(|w| w[0] == "--trust").map(|w| w[1].clone())?;
+    match TrustPolicy::load(std::path::Path::new(&path)) {
+        Ok(policy) => Some(policy),
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Check a run's detached `signature.json` against its `preimage_sha256` and the policy.
+/// Returns the signer's key.
+fn verify_signature(outdir: &str, trust: &TrustPolicy) -> Result<String, String> {
+    let run_id = trace_verify::extract_run_digest(outdir)?;
+    let sig: Option<serde_json::Value> = match fs::read(format!("{}/signature.json", outdir)) {
+        Ok(b) => Some(serde_json::from_slice(&b).map_err(|e| format!("SIGNATURE_MALFORMED {}", e))?),
+        Err(_) => None,
+    };
+    if let Some(signed) = sig.as_ref().and_then(|s| s.get("run_id")).and_then(|v| v.as_str()) {
+        if signed != run_id {
+            return Err(format!("SIGNATURE_RUN_MISMATCH expected={} got={}", run_id, signed));
+        }
+    }
+    let package = sig.as_ref().and_then(|s| s.get("package")).and_then(|v| v.as_str()).unwrap_or("");
+    trust.check(&run_id, package, sig.as_ref()).map_err(|e| format!("{:#}", e))
+}
+
+/// Stdlib root digests of runtimes this verifier accepts: the one it was built
+/// with, plus any listed (one per line, `#` comments) in `--stdlib-roots <file>`.
+fn known_stdlib_roots(args: &[String]) -> Vec<String> {
+    let mut known = vec![env!("FARD_STDLIB_ROOT_DIGEST").to_string()];
+    if let Some(path) = args.windows(2).find(|w| w[0] == "--stdlib-roots").map(|w| w[1].clone()) {
+        let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("cannot read --stdlib-roots {}: {}", path, e);
+            std::process::exit(2);
+        });
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if !line.is_empty() {
+                known.push(line.to_string());
+            }
+        }
+    }
+    known
+}
 
 // ── Chain verification ────────────────────────────────────────────────────────
 
+/// Where `fardverify chain` resolves child runs and artifact receipts. `explicit` is set
+/// when a store was named on the command line; only then is a missing entry an error.
+struct ChainStore {
+    store: ReceiptStore,
+    explicit: bool,
+    /// With `--trust`, every run and receipt in the chain must be signed by a trusted key.
+    trust: Option<TrustPolicy>,
+}
+
 /// Recursively verify a receipt chain.
 /// Returns (total_nodes_verified, max_depth_reached).
 fn verify_chain(
     outdir: &str,
-    registry: Option<&str>,
+    store: &ChainStore,
+    known_roots: &[String],
     max_depth: usize,
     current_depth: usize,
 ) -> Result<(usize, usize), String> {
@@ -55,6 +114,13 @@ fn verify_chain(
     // Verify this node's trace
     trace_verify::verify_trace_outdir(outdir)
         .map_err(|e| format!("node {} trace fail: {}", outdir, e))?;
+    bundle_verify::verify_stdlib_root(outdir, known_roots)
+        .map_err(|e| format!("node {} stdlib fail: {}", outdir, e))?;
+    if let Some(trust) = &store.trust {
+        let key = verify_signature(outdir, trust)
+            .map_err(|e| format!("node {} signature fail: {}", outdir, e))?;
+        println!("  signed {} by {}", outdir, key);
+    }
 
     let mut total_nodes = 1usize;
     let mut max_d = current_depth;
@@ -63,33 +129,34 @@ fn verify_chain(
     let trace_path = format!("{}/trace.ndjson", outdir);
     let children = trace_verify::extract_child_receipts(&trace_path)
         .map_err(|e| format!("extract_child_receipts: {}", e))?;
+    let nested = trace_verify::extract_nested_spawn_ids(&trace_path)
+        .map_err(|e| format!("extract_nested_spawn_ids: {}", e))?;
 
     for (spawn_id, run_digest, result_digest) in &children {
+        if nested.contains(spawn_id) {
+            // Span is a sub-trace of this run and was checked with it
+            println!("  child {} (children/{}.ndjson)", spawn_id, spawn_id);
+            continue;
+        }
         if run_digest == "sha256:no-trace" {
             // Child had no trace — skip chain verification for this node
             println!("  skip child {} (no-trace)", spawn_id);
             continue;
         }
 
-        // Look up child outdir in registry
-        let child_outdir = match registry {
-            Some(reg) => {
-                // Registry layout: <registry>/<run_digest>/
-                let digest_hex = run_digest.trim_start_matches("sha256:");
-                format!("{}/{}", reg, digest_hex)
+        // Look up the child's run bundle in the store (`<dir>/<hex>/`)
+        let child_outdir = match store.store.bundle(run_digest) {
+            Some(p) => p.to_string_lossy().to_string(),
+            None if store.explicit => {
+                return Err(format!("CHAIN_MISSING_CHILD spawn_id={} digest={}", spawn_id, run_digest));
             }
             None => {
-                // No registry — skip deep verification
+                // No store named — skip deep verification
                 println!("  skip child {} (no registry)", spawn_id);
                 continue;
             }
         };
 
-        // Check child outdir exists
-        if !std::path::Path::new(&child_outdir).exists() {
-            return Err(format!("CHAIN_MISSING_CHILD spawn_id={} digest={}", spawn_id, run_digest));
-        }
-
         // Verify child's run digest matches what was recorded
         let child_actual_digest = trace_verify::extract_run_digest(&child_outdir)
             .map_err(|e| format!("child digest extract: {}", e))?;
@@ -118,15 +185,90 @@ fn verify_chain(
 
         // Recurse
         let (child_nodes, child_depth) = verify_chain(
-            &child_outdir, registry, max_depth, current_depth + 1
+            &child_outdir, store, known_roots, max_depth, current_depth + 1
         )?;
         total_nodes += child_nodes;
         max_d = max_d.max(child_depth);
     }
 
+    // Artifact imports: every run this one derived from must resolve to a receipt
+    // whose run_id matches, and so must everything those receipts derive from.
+    let deps = trace_verify::extract_artifact_deps(&trace_path)
+        .map_err(|e| format!("extract_artifact_deps: {}", e))?;
+    let mut queue: Vec<(String, usize)> = deps.into_iter().map(|d| (d, current_depth + 1)).collect();
+    let mut seen = std::collections::BTreeSet::new();
+    while let Some((run_id, depth)) = queue.pop() {
+        if !seen.insert(run_id.clone()) {
+            continue;
+        }
+        if depth > max_depth {
+            return Err(format!("CHAIN_MAX_DEPTH_EXCEEDED {}", max_depth));
+        }
+        let bytes = match store.store.get(&run_id) {
+            Ok(Some(b)) => b,
+            Ok(None) if !store.explicit => {
+                println!("  skip receipt {} (not in {})", run_id, store.store.describe());
+                continue;
+            }
+            Ok(None) => return Err(format!("CHAIN_MISSING_RECEIPT run_id={}", run_id)),
+            Err(e) => return Err(format!("CHAIN_RECEIPT_STORE run_id={} {:#}", run_id, e)),
+        };
+        let stored = receipt_run_id(&bytes);
+        if stored.as_deref() != Some(run_id.as_str()) {
+            return Err(format!(
+                "CHAIN_RECEIPT_MISMATCH run_id={} stored={}",
+                run_id,
+                stored.unwrap_or_default()
+            ));
+        }
+        let receipt: serde_json::Value = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("CHAIN_RECEIPT_MALFORMED run_id={} {}", run_id, e))?;
+        if let Some(trust) = &store.trust {
+            trust.check_receipt(&receipt)
+                .map_err(|e| format!("CHAIN_RECEIPT_UNTRUSTED run_id={} {:#}", run_id, e))?;
+        }
+        for dep in receipt["derived_from"].as_array().into_iter().flatten() {
+            if let Some(d) = dep.as_str() {
+                queue.push((d.to_string(), depth + 1));
+            }
+        }
+        println!("  verified receipt {} depth={}", run_id, depth);
+        total_nodes += 1;
+        max_d = max_d.max(depth);
+    }
+
     Ok((total_nodes, max_d))
 }
 
+// ── Replay verification ───────────────────────────────────────────────────────
+
+/// Re-execute a recorded run with `fardrun run --replay <outdir>` and check
+/// that the replayed run reproduces the recorded run digest.
+/// Returns the reproduced digest.
+fn verify_replay(outdir: &str, program: &str, fardrun: &str) -> Result<String, String> {
+    let expected = trace_verify::extract_run_digest(outdir)
+        .map_err(|e| format!("REPLAY_MISSING_DIGEST {}", e))?;
+    let replay_out = env::temp_dir().join(format!("fard_replay_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&replay_out);
+    let out = std::process::Command::new(fardrun)
+        .arg("run")
+        .arg("--program").arg(program)
+        .arg("--out").arg(&replay_out)
+        .arg("--replay").arg(outdir)
+        .output()
+        .map_err(|e| format!("REPLAY_SPAWN_FAIL {}: {}", fardrun, e))?;
+    let replay_dir = replay_out.to_string_lossy().to_string();
+    let got = trace_verify::extract_run_digest(&replay_dir);
+    let _ = fs::remove_dir_all(&replay_out);
+    let got = got.map_err(|_| {
+        format!("REPLAY_RUN_FAIL {}", String::from_utf8_lossy(&out.stderr).trim())
+    })?;
+    if got != expected {
+        return Err(format!("REPLAY_DIGEST_MISMATCH expected={} got={}", expected, got));
+    }
+    Ok(got)
+}
+
 // ── Proof verification ────────────────────────────────────────────────────────
 
 /// A proof spec is a JSON file describing obligations a run must satisfy:
@@ -318,15 +460,28 @@ fn main() {
     if sub == "chain" {
         // Walk the receipt chain from a root run outdir
         // Verifies: this run + all child receipts recursively
-        let registry = args.windows(2)
-            .find(|w| w[0] == "--registry")
-            .map(|w| w[1].clone());
+        // `--registry <dir>` is the older spelling of `--receipts dir:<dir>`
+        let specs: Vec<String> = args.windows(2)
+            .filter(|w| w[0] == "--receipts" || w[0] == "--registry")
+            .map(|w| if w[0] == "--registry" { format!("dir:{}", w[1]) } else { w[1].clone() })
+            .collect();
+        let cache = args.windows(2)
+            .find(|w| w[0] == "--receipt-cache")
+            .map(|w| std::path::PathBuf::from(&w[1]));
+        let store = match ReceiptStore::configure(&specs, cache.as_deref(), None) {
+            Ok(store) => ChainStore { store, explicit: !specs.is_empty(), trust: trust_policy(&args) },
+            Err(e) => {
+                eprintln!("CHAIN_VERIFY_FAIL {:#}", e);
+                std::process::exit(2);
+            }
+        };
         let max_depth: usize = args.windows(2)
             .find(|w| w[0] == "--depth")
             .and_then(|w| w[1].parse().ok())
             .unwrap_or(32);
 
-        match verify_chain(&outdir, registry.as_deref(), max_depth, 0) {
+        let known_roots = known_stdlib_roots(&args);
+        match verify_chain(&outdir, &store, &known_roots, max_depth, 0) {
             Ok(stats) => {
                 println!("chain ok — {} node(s) verified, depth {}", stats.0, stats.1);
                 let p = format!("{}/PASS_CHAIN.txt", outdir);
@@ -341,6 +496,22 @@ fn main() {
         }
     }
 
+    if sub == "signature" {
+        let trust = trust_policy(&args).unwrap_or_else(|| usage());
+        match verify_signature(&outdir, &trust) {
+            Ok(key) => {
+                println!("signature ok — {}", key);
+                let p = format!("{}/PASS_SIGNATURE.txt", outdir);
+                let _ = fs::write(&p, format!("PASS {}\n", key));
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("SIGNATURE_VERIFY_FAIL {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
     if sub == "prove" {
         // Proof-carrying code: verify a run satisfies a spec
         let spec_path = args.windows(2)
@@ -363,6 +534,34 @@ fn main() {
         }
     }
 
+    if sub == "replay" {
+        let program = args.windows(2)
+            .find(|w| w[0] == "--program")
+            .map(|w| w[1].clone())
+            .unwrap_or_else(|| usage());
+        let fardrun = args.windows(2)
+            .find(|w| w[0] == "--fardrun")
+            .map(|w| w[1].clone())
+            .unwrap_or_else(|| {
+                env::current_exe()
+                    .map(|p| p.with_file_name("fardrun").to_string_lossy().to_string())
+                    .unwrap_or_else(|_| "fardrun".to_string())
+            });
+
+        match verify_replay(&outdir, &program, &fardrun) {
+            Ok(digest) => {
+                println!("replay ok — {}", digest);
+                let p = format!("{}/PASS_REPLAY.txt", outdir);
+                let _ = fs::write(&p, format!("PASS {}\n", digest));
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("REPLAY_VERIFY_FAIL {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
     if sub == "artifact" {
         match trace_verify::verify_trace_outdir(&outdir) {
             Ok(()) => match artifact_verify::verify_artifact_outdir(&outdir) {
@@ -387,8 +586,35 @@ fn main() {
         }
     }
 
+    if sub == "build" {
+        // The build manifest in <dir>: schema, chain digest and every step's outputs
+        match build::verify_build(std::path::Path::new(&outdir), None, None) {
+            Ok(true) => {
+                println!("build ok");
+                let p = format!("{}/PASS_BUILD.txt", outdir);
+                let _ = fs::write(&p, b"PASS\n");
+                std::process::exit(0);
+            }
+            Ok(false) => {
+                let p = format!("{}/FAIL_BUILD.txt", outdir);
+                let _ = fs::write(&p, b"FAIL\n");
+                eprintln!("BUILD_VERIFY_FAIL");
+                std::process::exit(2);
+            }
+            Err(e) => {
+                let p = format!("{}/FAIL_BUILD.txt", outdir);
+                let _ = fs::write(&p, format!("FAIL {:#}\n", e).as_bytes());
+                eprintln!("BUILD_VERIFY_FAIL {:#}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
     if sub == "bundle" {
-        match bundle_verify::verify_bundle_outdir(&outdir) {
+        let known_roots = known_stdlib_roots(&args);
+        let verified = bundle_verify::verify_bundle_outdir(&outdir)
+            .and_then(|_| bundle_verify::verify_stdlib_root(&outdir, &known_roots));
+        match verified {
             Ok(()) => {
                 let p = format!("{}/PASS_BUNDLE.txt", outdir);
                 let _ = fs::write(&p, b"PASS\n");
@@ -0,0 +1,1123 @@
+//! The build subsystem behind `fard-build` and `fardbuild`.
+//!
+//! A build is defined either by a `fard.build.toml`:
+//!
+//!   [build]
+//!   name = "my-project"
+//!   version = "1.0.0"
+//!
+//!   [[step]]
+//!   name = "compile"
+//!   program = "steps/compile.fard"
+//!   out = "build/compile/"
+//!
+//!   [[step]]
+//!   name = "test"
+//!   program = "steps/test.fard"
+//!   out = "build/test/"
+//!   depends_on = ["compile"]
+//!   env = { MODE = "strict" }
+//!   args = ["--fast"]
+//!
+//! or by a single FARD program spec, which is a one-step build whose outputs
+//! go straight into the out dir. Each step is a witnessed `fardrun` run.
+//!
+//! Each step is keyed by a digest over its program's module graph, its
+//! declared env/args and the run digests it depends on. A step whose key
+//! has a cached successful run is skipped and its outputs are restored
+//! from the content-addressed cache (default `<out>/.cache`).
+//!
+//! Steps form a DAG through `depends_on` and run up to `jobs` at a time.
+//! A step without `depends_on` is a root. When a step fails, its dependents
+//! are skipped and independent branches go on. `FARD_PRIOR_DIGEST` is the
+//! run digest of the last step in `depends_on`.
+//!
+//! Every build writes one manifest, `build.receipt.json`, of kind
+//! `fard/build_manifest/v1`. `verify_build` re-checks a finished build from
+//! its outputs, and `migrate_manifest` converts manifests written by older
+//! versions of either tool.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+
+/// The `kind` of manifests written by this version.
+pub const MANIFEST_KIND: &str = "fard/build_manifest/v1";
+
+/// The manifest's file name in the build's out dir.
+pub const MANIFEST_FILE: &str = "build.receipt.json";
+
+// ── Build config ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct BuildStep {
+    pub name: String,
+    pub program: PathBuf,
+    pub out: PathBuf,
+    pub depends_on: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub args: Vec<String>,
+    pub no_trace: bool,
+}
+
+/// Where a build was defined: a `fard.build.toml` or a single FARD program spec.
+#[derive(Debug, Clone)]
+pub struct BuildSource {
+    /// `"toml"` or `"spec"`.
+    pub form: &'static str,
+    pub path: PathBuf,
+    pub digest: String,
+}
+
+#[derive(Debug)]
+pub struct BuildConfig {
+    pub name: String,
+    pub version: String,
+    pub steps: Vec<BuildStep>,
+    /// Directory step paths are relative to.
+    pub base: PathBuf,
+    pub source: BuildSource,
+}
+
+impl BuildConfig {
+    /// Load a `fard.build.toml`; step paths are relative to its directory.
+    pub fn from_toml(path: &Path) -> Result<BuildConfig> {
+        let src = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let (name, version, steps) = parse_build_toml(&src, base)?;
+        Ok(BuildConfig {
+            name,
+            version,
+            steps,
+            base: base.to_path_buf(),
+            source: BuildSource { form: "toml", path: path.to_path_buf(), digest: digest_of(src.as_bytes()) },
+        })
+    }
+
+    /// A one-step build that runs the FARD program `spec` with its outputs in `out`.
+    pub fn from_spec(spec: &Path, out: &Path, no_trace: bool) -> Result<BuildConfig> {
+        let bytes = std::fs::read(spec).with_context(|| format!("cannot read spec {}", spec.display()))?;
+        let name = spec.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "build".into());
+        let step = BuildStep {
+            name: name.clone(),
+            program: spec.to_path_buf(),
+            out: out.to_path_buf(),
+            depends_on: Vec::new(),
+            env: BTreeMap::new(),
+            args: Vec::new(),
+            no_trace,
+        };
+        Ok(BuildConfig {
+            name,
+            version: "0.0.0".into(),
+            steps: vec![step],
+            base: spec.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            source: BuildSource { form: "spec", path: spec.to_path_buf(), digest: digest_of(&bytes) },
+        })
+    }
+}
+
+fn parse_build_toml(src: &str, base: &Path) -> Result<(String, String, Vec<BuildStep>)> {
+    let mut name = String::from("unnamed");
+    let mut version = String::from("0.0.0");
+    let mut steps: Vec<BuildStep> = Vec::new();
+    let mut current_step: Option<BuildStep> = None;
+    let mut in_build = false;
+    let mut in_step = false;
+
+    for raw_line in src.lines() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        if line == "[build]" {
+            in_build = true; in_step = false;
+            if let Some(s) = current_step.take() { steps.push(s); }
+            continue;
+        }
+        if line == "[[step]]" {
+            if let Some(s) = current_step.take() { steps.push(s); }
+            in_step = true; in_build = false;
+            current_step = Some(BuildStep {
+                name: String::new(),
+                program: PathBuf::new(),
+                out: PathBuf::new(),
+                depends_on: Vec::new(),
+                env: BTreeMap::new(),
+                args: Vec::new(),
+                no_trace: false,
+            });
+            continue;
+        }
+        if line.starts_with('[') { in_build = false; in_step = false; continue; }
+
+        if let Some((k, raw)) = line.split_once('=') {
+            let k = k.trim();
+            let v = raw.trim().trim_matches('"').to_string();
+
+            if in_build {
+                match k {
+                    "name" => name = v,
+                    "version" => version = v,
+                    _ => {}
+                }
+            } else if in_step {
+                if let Some(ref mut s) = current_step {
+                    match k {
+                        "name" => s.name = v,
+                        "program" => s.program = base.join(&v),
+                        "out" => s.out = if v.starts_with('/') { PathBuf::from(&v) } else { base.join(&v) },
+                        "no_trace" => s.no_trace = v == "true",
+                        "depends_on" => {
+                            // depends_on = ["a", "b"]
+                            s.depends_on = v.trim_matches(|c| c == '[' || c == ']')
+                                .split(',')
+                                .map(|x| x.trim().trim_matches('"').to_string())
+                                .filter(|x| !x.is_empty())
+                                .collect();
+                        }
+                        "args" => {
+                            // args = ["--fast", "x"]
+                            s.args = raw.trim().trim_matches(|c| c == '[' || c == ']')
+                                .split(',')
+                                .map(|x| x.trim().trim_matches('"').to_string())
+                                .filter(|x| !x.is_empty())
+                                .collect();
+                        }
+                        "env" => {
+                            // env = { MODE = "strict", LEVEL = "2" }
+                            for pair in raw.trim().trim_matches(|c| c == '{' || c == '}').split(',') {
+                                if let Some((ek, ev)) = pair.split_once('=') {
+                                    let ek = ek.trim().trim_matches('"');
+                                    if !ek.is_empty() {
+                                        s.env.insert(ek.to_string(), ev.trim().trim_matches('"').to_string());
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    if let Some(s) = current_step { steps.push(s); }
+
+    // Validate
+    for (i, s) in steps.iter().enumerate() {
+        if s.name.is_empty() { bail!("step {} has no name", i); }
+        if s.program == PathBuf::new() { bail!("step {:?} has no program", s.name); }
+        if s.out == PathBuf::new() { bail!("step {:?} has no out dir", s.name); }
+        if steps[..i].iter().any(|p| p.name == s.name) { bail!("duplicate step {:?}", s.name); }
+        for d in &s.depends_on {
+            if !steps.iter().any(|p| &p.name == d) {
+                bail!("step {:?} depends on unknown step {:?}", s.name, d);
+            }
+        }
+    }
+    check_acyclic(&steps)?;
+
+    Ok((name, version, steps))
+}
+
+/// Fail with the offending path if `depends_on` has a cycle.
+fn check_acyclic(steps: &[BuildStep]) -> Result<()> {
+    // 0 = unvisited, 1 = on the current path, 2 = done
+    fn visit<'a>(i: usize, steps: &'a [BuildStep], mark: &mut [u8], path: &mut Vec<&'a str>) -> Result<()> {
+        match mark[i] {
+            2 => return Ok(()),
+            1 => {
+                let start = path.iter().position(|n| *n == steps[i].name).unwrap_or(0);
+                let mut cycle = path[start..].to_vec();
+                cycle.push(&steps[i].name);
+                bail!("dependency cycle: {}", cycle.join(" -> "));
+            }
+            _ => {}
+        }
+        mark[i] = 1;
+        path.push(&steps[i].name);
+        for d in &steps[i].depends_on {
+            if let Some(j) = steps.iter().position(|s| &s.name == d) {
+                visit(j, steps, mark, path)?;
+            }
+        }
+        path.pop();
+        mark[i] = 2;
+        Ok(())
+    }
+    let mut mark = vec![0u8; steps.len()];
+    for i in 0..steps.len() {
+        visit(i, steps, &mut mark, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+// ── Step execution ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+struct StepResult {
+    name: String,
+    ok: bool,
+    skipped: bool,
+    run_digest: Option<String>,
+    prior_digest: Option<String>,
+    depends_on: Vec<String>,
+    out_dir: PathBuf,
+    duration_ms: u128,
+    error: Option<String>,
+    key: String,
+    cache: CacheStatus,
+}
+
+impl StepResult {
+    fn new(step: &BuildStep, prior_digest: Option<&str>) -> StepResult {
+        StepResult {
+            name: step.name.clone(),
+            ok: false,
+            skipped: false,
+            run_digest: None,
+            prior_digest: prior_digest.map(String::from),
+            depends_on: step.depends_on.clone(),
+            out_dir: step.out.clone(),
+            duration_ms: 0,
+            error: None,
+            key: String::new(),
+            cache: CacheStatus::Miss,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CacheStatus {
+    Hit,
+    Miss,
+    Forced,
+}
+
+impl CacheStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+            CacheStatus::Forced => "forced",
+        }
+    }
+}
+
+fn run_step(step: &BuildStep, out: &Path, fardrun: &Path, prior_digest: Option<&str>) -> Result<StepResult> {
+    std::fs::create_dir_all(out)?;
+
+    let start = std::time::Instant::now();
+
+    let mut cmd = Command::new(fardrun);
+    cmd.arg("run")
+        .arg("--program").arg(&step.program)
+        .arg("--out").arg(out);
+
+    if step.no_trace {
+        cmd.arg("--no-trace");
+    }
+
+    if !step.args.is_empty() {
+        cmd.arg("--").args(&step.args);
+    }
+
+    // Pass prior digest as env var for chaining
+    if let Some(digest) = prior_digest {
+        cmd.env("FARD_PRIOR_DIGEST", digest);
+    }
+
+    // Pass step name
+    cmd.env("FARD_BUILD_STEP", &step.name);
+
+    for (k, v) in &step.env {
+        cmd.env(k, v);
+    }
+
+    let output = cmd.output()
+        .with_context(|| format!("failed to run fardrun for step {:?}", step.name))?;
+
+    let duration_ms = start.elapsed().as_millis();
+    let ok = output.status.success();
+
+    // Extract run digest from stdout
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let run_digest = stderr.lines()
+        .chain(stdout.lines())
+        .find_map(|l| {
+            l.strip_prefix("fard_run_digest=").map(|d| d.trim().to_string())
+        });
+
+    let error = if ok { None } else {
+        Some(stderr.lines().last().unwrap_or("unknown error").to_string())
+    };
+
+    if ok {
+        eprintln!("  [{}] ✓  {}  ({}ms)", step.name,
+            run_digest.as_deref().unwrap_or("no-digest"), duration_ms);
+    } else {
+        eprintln!("  [{}] ✗  FAILED  ({}ms)", step.name, duration_ms);
+        if let Some(ref e) = error {
+            eprintln!("       {}", e);
+        }
+    }
+
+    Ok(StepResult {
+        ok,
+        run_digest,
+        out_dir: out.to_path_buf(),
+        duration_ms,
+        error,
+        ..StepResult::new(step, prior_digest)
+    })
+}
+
+/// Everything a worker needs to bring one step up to date.
+struct BuildContext<'a> {
+    base: &'a Path,
+    fardrun: &'a Path,
+    runtime: String,
+    cache: StepCache,
+    force_all: bool,
+    force_steps: Vec<String>,
+}
+
+/// Restore `step` from the cache when its key has a stored run, otherwise
+/// run it and cache a successful result. `deps` maps each dependency to
+/// its run digest.
+fn execute_step(step: &BuildStep, ctx: &BuildContext, deps: &BTreeMap<String, String>) -> Result<StepResult> {
+    let prior = step.depends_on.last().and_then(|d| deps.get(d)).map(String::as_str);
+    let key = step_key(step, ctx.base, &ctx.runtime, prior, deps);
+    let forced = ctx.force_all || ctx.force_steps.contains(&step.name);
+    let cached = if forced { None } else { ctx.cache.lookup(&key) };
+    let hit = match cached {
+        Some(ref manifest) => {
+            std::fs::create_dir_all(&step.out)?;
+            ctx.cache.restore(manifest, &step.out)?
+        }
+        None => false,
+    };
+
+    if hit {
+        let run_digest = cached.as_ref()
+            .and_then(|m| m.get("run_digest"))
+            .and_then(|v| v.as_str())
+            .map(String::from);
+        eprintln!("  [{}] ✓  {}  (cached)", step.name, run_digest.as_deref().unwrap_or("no-digest"));
+        return Ok(StepResult {
+            ok: true,
+            run_digest,
+            key,
+            cache: CacheStatus::Hit,
+            ..StepResult::new(step, prior)
+        });
+    }
+
+    let mut result = run_step(step, &step.out, ctx.fardrun, prior)?;
+    if result.ok {
+        if let Some(ref digest) = result.run_digest {
+            ctx.cache.store(&key, step, digest)?;
+        }
+    }
+    result.key = key;
+    result.cache = if forced { CacheStatus::Forced } else { CacheStatus::Miss };
+    Ok(result)
+}
+
+// ── Step keys ─────────────────────────────────────────────────────────────────
+
+/// Runtime identity (`fardrun --version`): covers the interpreter and every
+/// `std/` module a step can import.
+fn runtime_identity(fardrun: &Path) -> Result<String> {
+    let output = Command::new(fardrun).arg("--version").output()
+        .with_context(|| format!("failed to run {} --version", fardrun.display()))?;
+    if !output.status.success() {
+        bail!("{} --version failed", fardrun.display());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The string literals passed to `import(...)` in `src`.
+fn import_specs(src: &str) -> Vec<String> {
+    let mut specs = Vec::new();
+    let mut rest = src;
+    while let Some(i) = rest.find("import(") {
+        rest = rest[i + "import(".len()..].trim_start();
+        if let Some(lit) = rest.strip_prefix('"') {
+            if let Some(end) = lit.find('"') {
+                specs.push(lit[..end].to_string());
+            }
+        }
+    }
+    specs
+}
+
+/// Digest of every source file reachable from `program` through relative
+/// imports, keyed by path relative to `base`. Resolution mirrors fardrun:
+/// `lib/` specs resolve from the program's directory, others from the
+/// importing file's. Package and registry imports are pinned by the
+/// program's `fard.toml` / `fard.lock`, which are included when present.
+fn module_graph(program: &Path, base: &Path) -> BTreeMap<String, String> {
+    let root = program.parent().unwrap_or(Path::new("."));
+    let rel = |p: &Path| p.strip_prefix(base).unwrap_or(p).display().to_string();
+    let mut graph = BTreeMap::new();
+    let mut seen: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut stack = vec![program.to_path_buf()];
+    while let Some(file) = stack.pop() {
+        if !seen.insert(file.clone()) { continue; }
+        let Ok(bytes) = std::fs::read(&file) else {
+            graph.insert(rel(&file), "missing".to_string());
+            continue;
+        };
+        graph.insert(rel(&file), digest_of(&bytes));
+        let here = file.parent().unwrap_or(Path::new("."));
+        for spec in import_specs(&String::from_utf8_lossy(&bytes)) {
+            if spec.starts_with("std/") || spec.starts_with("pkg:") || spec.starts_with("pkg/")
+                || spec.starts_with("registry/") {
+                continue;
+            }
+            let dir = if spec.starts_with("lib/") { root } else { here };
+            stack.push(dir.join(format!("{}.fard", spec)));
+        }
+    }
+    for manifest in ["fard.toml", "fard.lock"] {
+        let path = root.join(manifest);
+        if let Ok(bytes) = std::fs::read(&path) {
+            graph.insert(rel(&path), digest_of(&bytes));
+        }
+    }
+    graph
+}
+
+fn step_key(
+    step: &BuildStep,
+    base: &Path,
+    runtime: &str,
+    prior_digest: Option<&str>,
+    deps: &BTreeMap<String, String>,
+) -> String {
+    let key = json!({
+        "kind": "fard/build_step_key/v0.1",
+        "step": step.name,
+        "runtime": runtime,
+        "modules": module_graph(&step.program, base),
+        "env": step.env,
+        "args": step.args,
+        "no_trace": step.no_trace,
+        "prior_digest": prior_digest,
+        "deps": deps,
+    });
+    digest_of(key.to_string().as_bytes())
+}
+
+// ── Step cache ────────────────────────────────────────────────────────────────
+
+/// Content-addressed store of step outputs:
+///   objects/<sha256>     file contents
+///   steps/<key>.json     {key, step, run_digest, files: {relpath: sha256:...}}
+struct StepCache {
+    dir: PathBuf,
+}
+
+fn files_under(dir: &Path, skip: &[PathBuf], acc: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if skip.contains(&path) { continue; }
+        if path.is_dir() {
+            files_under(&path, skip, acc)?;
+        } else {
+            acc.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl StepCache {
+    fn object_path(&self, digest: &str) -> PathBuf {
+        self.dir.join("objects").join(digest.trim_start_matches("sha256:"))
+    }
+
+    fn manifest_path(&self, key: &str) -> PathBuf {
+        self.dir.join("steps").join(format!("{}.json", key.trim_start_matches("sha256:")))
+    }
+
+    fn lookup(&self, key: &str) -> Option<Value> {
+        let bytes = std::fs::read(self.manifest_path(key)).ok()?;
+        let manifest: Value = serde_json::from_slice(&bytes).ok()?;
+        (manifest.get("key").and_then(|v| v.as_str()) == Some(key)).then_some(manifest)
+    }
+
+    /// Copy a cached step's files back into `out`. Returns false, touching
+    /// nothing, if any object is missing or does not match its digest.
+    fn restore(&self, manifest: &Value, out: &Path) -> Result<bool> {
+        let Some(files) = manifest.get("files").and_then(|v| v.as_object()) else { return Ok(false) };
+        let mut blobs = Vec::new();
+        for (rel, digest) in files {
+            let digest = digest.as_str().unwrap_or("");
+            let Ok(bytes) = std::fs::read(self.object_path(digest)) else { return Ok(false) };
+            if digest_of(&bytes) != digest || Path::new(rel).is_absolute() || rel.contains("..") {
+                return Ok(false);
+            }
+            blobs.push((out.join(rel), bytes));
+        }
+        for (path, bytes) in blobs {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, bytes)?;
+        }
+        Ok(true)
+    }
+
+    /// Record the outputs of a successful run of `step` under `key`.
+    /// Runs whose `digests.json` is missing or not ok are never cached.
+    fn store(&self, key: &str, step: &BuildStep, run_digest: &str) -> Result<()> {
+        let ok = std::fs::read(step.out.join("digests.json")).ok()
+            .and_then(|b| serde_json::from_slice::<Value>(&b).ok())
+            .and_then(|d| d.get("ok").and_then(|v| v.as_bool()))
+            .unwrap_or(false);
+        if !ok { return Ok(()); }
+
+        let mut paths = Vec::new();
+        // A spec build writes its manifest into the step's own out dir.
+        let skip = [self.dir.clone(), step.out.join(MANIFEST_FILE)];
+        files_under(&step.out, &skip, &mut paths)?;
+        std::fs::create_dir_all(self.dir.join("objects"))?;
+        std::fs::create_dir_all(self.dir.join("steps"))?;
+        let mut files = BTreeMap::new();
+        for path in paths {
+            let bytes = std::fs::read(&path)?;
+            let digest = digest_of(&bytes);
+            let object = self.object_path(&digest);
+            if !object.exists() {
+                // Steps store concurrently; a per-key temp name keeps writers apart.
+                let tmp = object.with_extension(format!("{}.tmp", key.trim_start_matches("sha256:")));
+                std::fs::write(&tmp, &bytes)?;
+                std::fs::rename(&tmp, &object)?;
+            }
+            let rel = path.strip_prefix(&step.out).unwrap_or(&path).display().to_string();
+            files.insert(rel, digest);
+        }
+        let manifest = json!({
+            "key": key,
+            "step": step.name,
+            "run_digest": run_digest,
+            "files": files,
+        });
+        let path = self.manifest_path(key);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&manifest)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+// ── Manifest ──────────────────────────────────────────────────────────────────
+
+fn digest_of(data: &[u8]) -> String {
+    format!("sha256:{}", crate::sha256_hex(data))
+}
+
+/// sha256 of all step run digests in file order.
+fn chain_digest<'a>(digests: impl Iterator<Item = &'a str>) -> String {
+    digest_of(digests.collect::<Vec<_>>().join(":").as_bytes())
+}
+
+fn build_manifest(config: &BuildConfig, results: &[StepResult], total_ms: u128) -> Value {
+    let steps: Vec<Value> = results.iter().map(|r| {
+        json!({
+            "name": r.name,
+            "ok": r.ok,
+            "skipped": r.skipped,
+            "run_digest": r.run_digest,
+            "prior_digest": r.prior_digest,
+            "depends_on": r.depends_on,
+            "out": r.out_dir.display().to_string(),
+            "duration_ms": r.duration_ms,
+            "error": r.error,
+            "key": (!r.key.is_empty()).then_some(&r.key),
+            "cache": r.cache.as_str(),
+        })
+    }).collect();
+
+    let source = json!({
+        "form": config.source.form,
+        "path": config.source.path.display().to_string(),
+        "digest": config.source.digest,
+    });
+    let built_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    manifest_from_steps(&config.name, &config.version, source, json!(built_at), total_ms as u64, steps)
+}
+
+// ── Scheduling ────────────────────────────────────────────────────────────────
+
+/// Run `selected` (indices into `config.steps`) as a DAG, at most `jobs` at
+/// a time. Dependencies outside the selection count as done, with the run
+/// digest found in their out dir. Results come back in file order.
+fn run_dag(config: &BuildConfig, selected: &[usize], ctx: &BuildContext, jobs: usize) -> Vec<StepResult> {
+    let index = |name: &str| config.steps.iter().position(|s| s.name == name);
+    let mut done: BTreeMap<usize, StepResult> = BTreeMap::new();
+    let mut pending: Vec<usize> = selected.to_vec();
+    let mut running = 0usize;
+
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel::<(usize, StepResult)>();
+        loop {
+            let mut i = 0;
+            while i < pending.len() && running < jobs {
+                let step = &config.steps[pending[i]];
+                let mut deps = BTreeMap::new();
+                let mut waiting = false;
+                let mut failed = None;
+                for d in &step.depends_on {
+                    let Some(j) = index(d) else { continue };
+                    if !selected.contains(&j) {
+                        if let Some(digest) = out_digest(&config.steps[j].out) {
+                            deps.insert(d.clone(), digest);
+                        }
+                        continue;
+                    }
+                    match done.get(&j) {
+                        None => waiting = true,
+                        Some(r) if !r.ok => failed = Some(d.clone()),
+                        Some(r) => {
+                            if let Some(ref digest) = r.run_digest {
+                                deps.insert(d.clone(), digest.clone());
+                            }
+                        }
+                    }
+                }
+                if let Some(d) = failed {
+                    eprintln!("  [{}] -  skipped ({} failed)", step.name, d);
+                    let idx = pending.remove(i);
+                    done.insert(idx, StepResult {
+                        skipped: true,
+                        error: Some(format!("dependency {:?} failed", d)),
+                        ..StepResult::new(step, None)
+                    });
+                    // A new skip can unblock earlier entries; rescan.
+                    i = 0;
+                    continue;
+                }
+                if waiting {
+                    i += 1;
+                    continue;
+                }
+                let idx = pending.remove(i);
+                let tx = tx.clone();
+                running += 1;
+                scope.spawn(move || {
+                    let result = execute_step(step, ctx, &deps).unwrap_or_else(|e| {
+                        eprintln!("  [{}] ✗  FAILED", step.name);
+                        eprintln!("       {:#}", e);
+                        StepResult { error: Some(format!("{:#}", e)), ..StepResult::new(step, None) }
+                    });
+                    let _ = tx.send((idx, result));
+                });
+            }
+            if running == 0 {
+                break;
+            }
+            let (idx, result) = rx.recv().expect("build worker exited without a result");
+            running -= 1;
+            done.insert(idx, result);
+        }
+    });
+
+    done.into_values().collect()
+}
+
+/// The run digest recorded in `out/digests.json`, if any.
+fn out_digest(out: &Path) -> Option<String> {
+    let bytes = std::fs::read(out.join("digests.json")).ok()?;
+    let digests: Value = serde_json::from_slice(&bytes).ok()?;
+    digests.get("preimage_sha256")?.as_str().map(String::from)
+}
+
+// ── Verification ──────────────────────────────────────────────────────────────
+
+/// Check one step's out dir: `digests.json` must hash to `run_digest`, every
+/// file it lists must match, and the trace and artifacts must verify.
+fn verify_step_outputs(out: &Path, run_digest: &str) -> Result<()> {
+    let bytes = std::fs::read(out.join("digests.json"))
+        .map_err(|e| anyhow!("cannot read {}: {}", out.join("digests.json").display(), e))?;
+    let mut digests: Value = serde_json::from_slice(&bytes)?;
+    let claimed = digests.get("preimage_sha256").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    if claimed != run_digest {
+        bail!("digests.json is for {} but the receipt records {}", claimed, run_digest);
+    }
+    if let Some(m) = digests.as_object_mut() {
+        m.remove("preimage_sha256");
+    }
+    let computed = digest_of(digests.to_string().as_bytes());
+    if computed != run_digest {
+        bail!("digests.json preimage hashes to {}, not {}", computed, run_digest);
+    }
+
+    let files = digests.get("files").and_then(|v| v.as_object()).cloned().unwrap_or_default();
+    for (name, expected) in &files {
+        let expected = expected.as_str().unwrap_or("");
+        if expected == "sha256:no-trace" {
+            continue;
+        }
+        let bytes = std::fs::read(out.join(name)).map_err(|_| anyhow!("{} is missing", name))?;
+        // Runs that read their own digest hash it as `sha256:self`.
+        let normalized = String::from_utf8_lossy(&bytes).replace(run_digest, "sha256:self");
+        if digest_of(&bytes) != expected && digest_of(normalized.as_bytes()) != expected {
+            bail!("{} does not match its digest {}", name, expected);
+        }
+    }
+
+    let dir = out.to_string_lossy();
+    if files.get("trace.ndjson").and_then(|v| v.as_str()) != Some("sha256:no-trace") {
+        crate::trace_verify::verify_trace_outdir(&dir).map_err(|e| anyhow!("trace: {}", e))?;
+    }
+    if files.contains_key("artifact_graph.json") {
+        crate::artifact_verify::verify_artifact_outdir(&dir).map_err(|e| anyhow!("artifact: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Re-check a finished build from its manifest in `out_dir`: the manifest
+/// must pass `validate_manifest` and every step's outputs must match their
+/// run digest. With `reexec` (the `fardrun` to use), each step is run again
+/// from `config` into a scratch dir and must reproduce its recorded run
+/// digest. Returns whether everything held.
+pub fn verify_build(out_dir: &Path, config: Option<&BuildConfig>, reexec: Option<&Path>) -> Result<bool> {
+    let receipt_path = out_dir.join(MANIFEST_FILE);
+    let receipt_bytes = std::fs::read(&receipt_path)
+        .with_context(|| format!("cannot read {}", receipt_path.display()))?;
+    let receipt: Value = serde_json::from_slice(&receipt_bytes)?;
+    if let Err(e) = validate_manifest(&receipt) {
+        eprintln!("  {:#}", e);
+        return Ok(false);
+    }
+    let steps = receipt.get("steps").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    let mut ok = receipt.get("ok").and_then(|v| v.as_bool()).unwrap_or(false);
+    if !ok {
+        eprintln!("  build receipt records a failed build");
+    }
+
+    for step in &steps {
+        let name = step.get("name").and_then(|v| v.as_str()).unwrap_or("?");
+        let Some(run_digest) = step.get("run_digest").and_then(|v| v.as_str()) else { continue };
+        let mut checked = match step.get("out").and_then(|v| v.as_str()) {
+            Some(out) => verify_step_outputs(Path::new(out), run_digest),
+            None => Err(anyhow!("no out dir recorded")),
+        };
+
+        if let (Ok(()), Some(fardrun)) = (&checked, reexec) {
+            checked = match config.and_then(|c| c.steps.iter().find(|s| s.name == name)) {
+                None => Err(anyhow!("not in the build config; cannot re-execute")),
+                Some(def) => {
+                    let scratch = out_dir.join(".verify").join(name);
+                    let _ = std::fs::remove_dir_all(&scratch);
+                    let prior = step.get("prior_digest").and_then(|v| v.as_str());
+                    let rerun = run_step(def, &scratch, fardrun, prior);
+                    let _ = std::fs::remove_dir_all(&scratch);
+                    match rerun?.run_digest {
+                        Some(d) if d == run_digest => Ok(()),
+                        d => Err(anyhow!("re-execution produced {}", d.as_deref().unwrap_or("no digest"))),
+                    }
+                }
+            };
+        }
+
+        match checked {
+            Ok(()) => eprintln!("  [{}] verified  {}", name, run_digest),
+            Err(e) => {
+                eprintln!("  [{}] FAILED  {:#}", name, e);
+                ok = false;
+            }
+        }
+    }
+    let _ = std::fs::remove_dir(out_dir.join(".verify"));
+    Ok(ok)
+}
+
+// ── Running a build ───────────────────────────────────────────────────────────
+
+/// How to run a build.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub out_dir: PathBuf,
+    /// Defaults to `<out_dir>/.cache`.
+    pub cache_dir: Option<PathBuf>,
+    pub fardrun: PathBuf,
+    pub jobs: usize,
+    /// Run only this step; its dependencies' digests are read from their out dirs.
+    pub only_step: Option<String>,
+    pub force_all: bool,
+    pub force_steps: Vec<String>,
+}
+
+impl BuildOptions {
+    pub fn new(out_dir: impl Into<PathBuf>) -> BuildOptions {
+        BuildOptions {
+            out_dir: out_dir.into(),
+            cache_dir: None,
+            fardrun: default_fardrun(),
+            jobs: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            only_step: None,
+            force_all: false,
+            force_steps: Vec::new(),
+        }
+    }
+}
+
+/// `fardrun` next to the running binary, else whatever is on `PATH`.
+pub fn default_fardrun() -> PathBuf {
+    std::env::current_exe().ok()
+        .and_then(|p| p.parent().map(|d| d.join("fardrun")))
+        .filter(|p| p.exists())
+        .unwrap_or_else(|| PathBuf::from("fardrun"))
+}
+
+/// Run `config`, write its manifest to `<out_dir>/build.receipt.json` and return it.
+pub fn run_build(config: &BuildConfig, opts: &BuildOptions) -> Result<Value> {
+    for name in opts.force_steps.iter().chain(opts.only_step.iter()) {
+        if !config.steps.iter().any(|s| &s.name == name) {
+            bail!("no step named {:?}", name);
+        }
+    }
+    std::fs::create_dir_all(&opts.out_dir)?;
+
+    eprintln!("fard-build: {} v{}", config.name, config.version);
+    eprintln!("  {} step(s), {} job(s)", config.steps.len(), opts.jobs);
+    eprintln!();
+
+    let ctx = BuildContext {
+        base: &config.base,
+        fardrun: &opts.fardrun,
+        runtime: runtime_identity(&opts.fardrun)?,
+        cache: StepCache { dir: opts.cache_dir.clone().unwrap_or_else(|| opts.out_dir.join(".cache")) },
+        force_all: opts.force_all,
+        force_steps: opts.force_steps.clone(),
+    };
+    let selected: Vec<usize> = (0..config.steps.len())
+        .filter(|&i| opts.only_step.as_ref().is_none_or(|only| &config.steps[i].name == only))
+        .collect();
+
+    let total_start = std::time::Instant::now();
+    let results = run_dag(config, &selected, &ctx, opts.jobs.max(1));
+    let total_ms = total_start.elapsed().as_millis();
+
+    let manifest = build_manifest(config, &results, total_ms);
+    let manifest_path = opts.out_dir.join(MANIFEST_FILE);
+    std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+
+    eprintln!();
+    if results.iter().all(|r| r.ok) {
+        eprintln!("build ok — {} step(s) in {}ms ({} cached)", results.len(), total_ms, manifest["cache_hits"]);
+    } else {
+        eprintln!("build FAILED — {} passed, {} failed, {} skipped",
+            manifest["passed"], manifest["failed"], manifest["skipped"]);
+    }
+    eprintln!("receipt: {}", manifest_path.display());
+    eprintln!("chain:   {}", manifest["chain_digest"].as_str().unwrap_or(""));
+    Ok(manifest)
+}
+
+// ── Schema ────────────────────────────────────────────────────────────────────
+
+fn is_digest(s: &str) -> bool {
+    s.strip_prefix("sha256:")
+        .is_some_and(|h| h.len() == 64 && h.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')))
+}
+
+/// Assemble a manifest from its step entries; the totals, `ok` and the
+/// chain digest are always derived from the steps.
+fn manifest_from_steps(name: &str, version: &str, source: Value, built_at: Value, total_ms: u64, steps: Vec<Value>) -> Value {
+    let count = |f: &dyn Fn(&Value) -> bool| steps.iter().filter(|s| f(s)).count();
+    let is = |s: &Value, k: &str| s.get(k).and_then(|v| v.as_bool()).unwrap_or(false);
+    let passed = count(&|s| is(s, "ok"));
+    let skipped = count(&|s| is(s, "skipped"));
+    let cache_hits = count(&|s| s.get("cache").and_then(|v| v.as_str()) == Some("hit"));
+    let chain = chain_digest(steps.iter().filter_map(|s| s.get("run_digest").and_then(|v| v.as_str())));
+    json!({
+        "kind": MANIFEST_KIND,
+        "name": name,
+        "version": version,
+        "source": source,
+        "built_at": built_at,
+        "ok": passed == steps.len(),
+        "chain_digest": chain,
+        "total_duration_ms": total_ms,
+        "step_count": steps.len(),
+        "passed": passed,
+        "failed": steps.len() - passed - skipped,
+        "skipped": skipped,
+        "cache_hits": cache_hits,
+        "steps": steps,
+    })
+}
+
+/// Check that `manifest` is a well-formed `fard/build_manifest/v1`: every
+/// field has its type, dependencies name steps of the build, and the
+/// totals, `ok` and `chain_digest` agree with the steps. Errors start with
+/// `BUILD_MANIFEST_`.
+pub fn validate_manifest(manifest: &Value) -> Result<()> {
+    let kind = manifest.get("kind").and_then(|v| v.as_str());
+    if kind != Some(MANIFEST_KIND) {
+        if kind == Some("fard/build_receipt/v0.1") || manifest.get("fard_build_version").is_some() {
+            bail!("BUILD_MANIFEST_LEGACY written by an older build tool; convert it with `fard-build --migrate`");
+        }
+        bail!("BUILD_MANIFEST_KIND expected {}, got {:?}", MANIFEST_KIND, kind);
+    }
+    let field = |obj: &Value, k: &str| obj.get(k).cloned().ok_or_else(|| anyhow!("BUILD_MANIFEST_FIELD missing {}", k));
+    let string = |obj: &Value, k: &str| -> Result<String> {
+        field(obj, k)?.as_str().map(String::from).ok_or_else(|| anyhow!("BUILD_MANIFEST_FIELD {} must be a string", k))
+    };
+    let optional = |obj: &Value, k: &str, digest: bool| -> Result<Option<String>> {
+        match field(obj, k)? {
+            Value::Null => Ok(None),
+            Value::String(s) if !digest || is_digest(&s) => Ok(Some(s)),
+            _ => bail!("BUILD_MANIFEST_FIELD {} must be {}or null", k, if digest { "a sha256: digest " } else { "a string " }),
+        }
+    };
+    let number = |obj: &Value, k: &str| -> Result<u64> {
+        field(obj, k)?.as_u64().ok_or_else(|| anyhow!("BUILD_MANIFEST_FIELD {} must be a non-negative integer", k))
+    };
+    let boolean = |obj: &Value, k: &str| -> Result<bool> {
+        field(obj, k)?.as_bool().ok_or_else(|| anyhow!("BUILD_MANIFEST_FIELD {} must be a boolean", k))
+    };
+
+    let name = string(manifest, "name")?;
+    let version = string(manifest, "version")?;
+    let source = field(manifest, "source")?;
+    if !matches!(string(&source, "form")?.as_str(), "toml" | "spec") {
+        bail!("BUILD_MANIFEST_FIELD source.form must be \"toml\" or \"spec\"");
+    }
+    optional(&source, "path", false)?;
+    optional(&source, "digest", true)?;
+    let built_at = field(manifest, "built_at")?;
+    if !(built_at.is_null() || built_at.is_u64()) {
+        bail!("BUILD_MANIFEST_FIELD built_at must be a unix time or null");
+    }
+    let total_ms = number(manifest, "total_duration_ms")?;
+
+    let steps = field(manifest, "steps")?.as_array().cloned()
+        .ok_or_else(|| anyhow!("BUILD_MANIFEST_FIELD steps must be an array"))?;
+    let mut names = BTreeSet::new();
+    for step in &steps {
+        let step_name = string(step, "name")?;
+        if !names.insert(step_name.clone()) {
+            bail!("BUILD_MANIFEST_STEP duplicate step {:?}", step_name);
+        }
+    }
+    for step in &steps {
+        let step_name = string(step, "name")?;
+        let ok = boolean(step, "ok")?;
+        if boolean(step, "skipped")? && ok {
+            bail!("BUILD_MANIFEST_STEP {:?} is both ok and skipped", step_name);
+        }
+        if optional(step, "run_digest", true)?.is_none() && ok {
+            bail!("BUILD_MANIFEST_STEP {:?} is ok but has no run_digest", step_name);
+        }
+        optional(step, "prior_digest", true)?;
+        optional(step, "out", false)?;
+        optional(step, "error", false)?;
+        optional(step, "key", true)?;
+        number(step, "duration_ms")?;
+        let deps = field(step, "depends_on")?;
+        for d in deps.as_array().ok_or_else(|| anyhow!("BUILD_MANIFEST_FIELD depends_on must be an array"))? {
+            if !d.as_str().is_some_and(|d| names.contains(d)) {
+                bail!("BUILD_MANIFEST_STEP {:?} depends on unknown step {}", step_name, d);
+            }
+        }
+        if !matches!(string(step, "cache")?.as_str(), "hit" | "miss" | "forced") {
+            bail!("BUILD_MANIFEST_FIELD cache must be \"hit\", \"miss\" or \"forced\"");
+        }
+    }
+
+    let expected = manifest_from_steps(&name, &version, source, built_at, total_ms, steps);
+    for k in ["ok", "step_count", "passed", "failed", "skipped", "cache_hits", "chain_digest"] {
+        if manifest.get(k) != expected.get(k) {
+            bail!("BUILD_MANIFEST_MISMATCH {} is {} but the steps give {}",
+                k, manifest.get(k).unwrap_or(&Value::Null), expected[k]);
+        }
+    }
+    Ok(())
+}
+
+/// Convert a manifest from an older `fard-build` (`fard/build_receipt/v0.1`)
+/// or `fardbuild` (`fard_build_version`) to the current schema; current
+/// manifests come back unchanged. `dir` is where the old manifest was read
+/// from: `fardbuild` wrote it next to its run's outputs. Fields the old
+/// format did not record become null.
+pub fn migrate_manifest(old: &Value, dir: &Path) -> Result<Value> {
+    let text = |v: &Value, k: &str| v.get(k).and_then(|x| x.as_str()).map(String::from);
+    let digest = |v: &Value, k: &str| text(v, k).filter(|s| is_digest(s));
+    let flag = |v: &Value, k: &str| v.get(k).and_then(|x| x.as_bool()).unwrap_or(false);
+    let millis = |v: &Value, k: &str| v.get(k).and_then(|x| x.as_u64()).unwrap_or(0);
+
+    let kind = text(old, "kind");
+    let (mut manifest, from) = match kind.as_deref() {
+        Some(MANIFEST_KIND) => return Ok(old.clone()),
+        Some("fard/build_receipt/v0.1") => {
+            let steps = old.get("steps").and_then(|v| v.as_array()).cloned().unwrap_or_default()
+                .iter()
+                .map(|s| json!({
+                    "name": text(s, "name").unwrap_or_default(),
+                    "ok": flag(s, "ok"),
+                    "skipped": flag(s, "skipped"),
+                    "run_digest": digest(s, "run_digest"),
+                    "prior_digest": digest(s, "prior_digest"),
+                    "depends_on": s.get("depends_on").cloned().unwrap_or_else(|| json!([])),
+                    "out": text(s, "out"),
+                    "duration_ms": millis(s, "duration_ms"),
+                    "error": text(s, "error"),
+                    "key": digest(s, "key"),
+                    "cache": text(s, "cache").unwrap_or_else(|| "miss".into()),
+                }))
+                .collect();
+            let manifest = manifest_from_steps(
+                &text(old, "name").unwrap_or_else(|| "unnamed".into()),
+                &text(old, "version").unwrap_or_else(|| "0.0.0".into()),
+                json!({ "form": "toml", "path": null, "digest": null }),
+                Value::Null,
+                millis(old, "total_duration_ms"),
+                steps,
+            );
+            (manifest, "fard/build_receipt/v0.1".to_string())
+        }
+        None if old.get("fard_build_version").is_some() => {
+            let ok = flag(old, "ok");
+            let step = json!({
+                "name": "build",
+                "ok": ok,
+                "skipped": false,
+                "run_digest": digest(old, "run_id"),
+                "prior_digest": null,
+                "depends_on": [],
+                "out": dir.display().to_string(),
+                "duration_ms": millis(old, "duration_ms"),
+                "error": if ok { None } else { Some("build failed") },
+                "key": null,
+                "cache": "miss",
+            });
+            let manifest = manifest_from_steps(
+                "build",
+                "0.0.0",
+                json!({ "form": "spec", "path": null, "digest": digest(old, "spec_sha256") }),
+                old.get("built_at").filter(|v| v.is_u64()).cloned().unwrap_or(Value::Null),
+                millis(old, "duration_ms"),
+                vec![step],
+            );
+            (manifest, format!("fardbuild/{}", text(old, "fard_build_version").unwrap_or_default()))
+        }
+        _ => bail!("BUILD_MANIFEST_KIND not a build manifest this tool knows (kind {:?})", kind),
+    };
+    manifest["migrated_from"] = json!(from);
+    validate_manifest(&manifest)?;
+    Ok(manifest)
+}
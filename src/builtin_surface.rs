@@ -0,0 +1,1033 @@
+// The builtin surface of fardrun: every `Builtin` variant and the exports of each std module.
+//
+// This file is `include!`d by src/bin/fardrun.rs and by build.rs. build.rs hashes
+// `BUILTIN_VARIANTS` and `STD_MODULES` into the stdlib root digest, so the digest follows
+// the compiled tables rather than the layout of the source.
+
+macro_rules! builtins {
+    ($($variant:ident $(($($payload:ty),*))?),* $(,)?) => {
+        #[derive(Clone, Debug)]
+        pub(crate) enum Builtin {
+            $($variant $(($($payload),*))?),*
+        }
+
+        /// Every `Builtin` variant with its payload types, in declaration order.
+        #[allow(dead_code)]
+        pub(crate) const BUILTIN_VARIANTS: &[&str] = &[$(stringify!($variant $(($($payload),*))?)),*];
+    };
+}
+
+builtins! {
+    PngRed1x1,
+    // std/png
+    PngDecode,
+    PngRead,
+    PngEncode,
+    PngPixel,
+    PngRow,
+    PngSetPixel,
+    PngConvert,
+    Unimplemented(&'static str),
+    // Type checking constructors
+    TypeCheck(String, Vec<String>),   // type_name, required_fields
+    // std/math
+    MathAbs, MathMin, MathMax, MathPow, MathSqrt,
+    MathFloor, MathCeil, MathRound, MathLog, MathLog2, MathExp,
+    // std/bits
+    BitAnd, BitOr, BitXor, BitNot, BitShl, BitShr, BitPopcount,
+    // std/bytes
+    BytesConcat, BytesLen, BytesGet, BytesOfList, BytesMerkleRoot, BytesOfStr, BytesToList, BytesToStr,
+    // std/io
+    IoReadFile, IoWriteFile, IoAppendFile, IoReadLines, IoFileExists, IoDeleteFile,
+    IoReadStdin, IoListDir, IoMakeDir, IoReadStdinLines,
+    ChanNew, ChanSend, ChanRecv, ChanTryRecv, ChanClose,
+    MutexNew, MutexLock, MutexUnlock, MutexWithLock,
+    // std/cli
+    CliArgs, CliGet, CliGetInt, CliGetFloat, CliGetBool, CliHas,
+    // std/null
+    NullIsNull, NullCoalesce, NullGuard,
+    // std/path
+    PathBase, PathDir, PathExt, PathIsAbs, PathJoin, PathJoinAll, PathNormalize,
+    ListMap,
+    ListFilter,
+    ListRange,
+    ListRepeat,
+    ListConcat,
+    ListFold,
+    StrTrim,
+    StrToLower,
+    StrSplitLines,
+    ResultOk,
+    ResultAndThen,
+    ResultUnwrapOk,
+    ResultUnwrapErr,
+    ResultUnwrapOr,
+    ResultIsOk,
+    ResultIsErr,
+    ResultMap,
+    ResultMapErr,
+    ResultOrElse,
+    TraceInfo,
+    TraceWarn,
+    TraceError,
+    TraceSpan,
+    ListGroupBy,
+    SembitPartition,
+    HttpGet,
+    HttpPost,
+    HttpRequest,
+    TimeNow,
+    TimeParse,
+    TimeFormat,
+    TimeAdd,
+    TimeSub,
+    TimeDurationMs,
+    TimeDurationSec,
+    TimeDurationMin,
+    OptionNone,
+    OptionSome,
+    OptionIsNone,
+    OptionIsSome,
+    OptionFromNullable,
+    OptionToNullable,
+    OptionMap,
+    OptionAndThen,
+    OptionUnwrapOr,
+    OptionUnwrapOrElse,
+    OptionToResult,
+    RecEmpty,
+    RecKeys,
+    RecValues,
+    RecHas,
+    RecGet,
+    RecGetOr,
+    RecGetOrErr,
+    RecSet,
+    RecRemove,
+    RecMerge,
+    RecSelect,
+    RecRename,
+    RecUpdate,
+    ResultErr,
+    ListGet,
+    ListLen,
+    ListHead,
+    ListTail,
+    ListAppend,
+    ListZip,
+    ListReverse,
+    ListFlatten,
+    ListSortByIntKey,
+    GrowUnfoldTree,
+    GrowAppend,
+    ImportArtifact,
+    ImportArtifactNamed,
+    EmitArtifact,
+    EmitArtifactDerived,
+    Emit,
+    Len,
+    IntParse,
+    IntPow,
+    IntAdd,
+    IntEq,
+    SortInt,
+    DedupeSortedInt,
+    HistInt,
+    Unfold,
+    FlowPipe,
+    FlowId,
+    FlowTap,
+    StrLen,
+    StrConcat,
+    MapGet,
+    MapSet,
+    JsonEncode,
+    JsonDecode,
+    JsonCanonicalize,
+    CryptoEd25519Verify,
+    CryptoHmacSha256,
+    CodecBase64UrlEncode,
+    CodecBase64UrlDecode,
+    CodecBase64UrlEncodeHex,
+    RandUuidV4,
+    StrSplit,
+    StrUpper,
+    StrContains,
+    StrStartsWith,
+    StrEndsWith,
+    StrReplace,
+    StrSlice,
+    StrFormat,
+    StrFromInt,
+    StrFromFloat,
+    StrPadLeft,
+    StrPadRight,
+    StrRepeat,
+    StrIndexOf,
+    StrChars,
+    FsReadText,
+    FsWriteText,
+    FsReadBytes,  // fs.read_bytes(path) -> bytes
+    FsWriteBytes, // fs.write_bytes(path, bytes) -> null
+    FsExists,
+    FsReadDir,
+    FsStat,
+    FsDelete,
+    FsMakeDir,
+    CodecHexEncode,
+    CodecHexDecode,
+    HashSha256Text,
+    WitnessSelfDigest,   // witness.self_digest() -> Text
+    WitnessDeps,         // witness.deps() -> List of run_id Text
+    WitnessVerify,       // witness.verify(run_id) -> {ok: record} | {err: text}
+    WitnessVerifyChain,  // witness.verify_chain(run_id) -> {ok: depth} | {err: {run_id, reason}}
+    FfiOpen,   // ffi.open(path) -> {ok: handle_id} | {err: text}
+    FfiCall,   // ffi.call(handle_id, symbol, args) -> {ok: val} | {err: text}
+    FfiClose,  // ffi.close(handle_id) -> null
+    FfiCallPure, // ffi.call_pure(handle_id, symbol, args) -> same as call but hashed into witness
+    FfiCallStr,  // ffi.call_str(handle_id, symbol, args) -> {ok: text} | {err: text}
+    FfiCallChecked,
+    FfiBind, // ffi.bind(handle_id, symbol, {args, ret}) -> callable
+    FfiBoundCall, // receiver of the callable returned by ffi.bind
+    NetServe,   // net.serve(port, handler) -> never (blocking)
+    NetRespond, // net.respond(req, status, headers, body) -> null (internal)
+    NetListen,  // net.listen({handler, addr, port, workers, ...}) -> result {handle, addr, port}
+    NetStop,    // net.stop(server) -> result {requests, receipts_root?}
+    NetConnect,   // net.connect({host, port, timeout_ms?}) -> result conn
+    NetTcpListen, // net.tcp_listen({addr?, port?, timeout_ms?}) -> result listener
+    NetAccept,    // net.accept(listener) -> result conn
+    NetRead,      // net.read(conn[, max_bytes]) -> result bytes (empty at end of stream)
+    NetWrite,     // net.write(conn, text|bytes) -> result int
+    NetUdpBind,   // net.udp_bind({addr?, port?, timeout_ms?}) -> result socket
+    NetUdpSend,   // net.udp_send(socket, "host:port", text|bytes) -> result int
+    NetUdpRecv,   // net.udp_recv(socket[, max_bytes]) -> result {data, from}
+    NetWsConnect, // net.ws_connect(url[, {timeout_ms}]) -> result ws {handle, inbox}
+    NetWsAccept,  // net.ws_accept(listener) -> result ws {handle, path, inbox}
+    NetWsSend,    // net.ws_send(ws, text|bytes) -> result int
+    NetClose,     // net.close(socket) -> result {bytes_in, bytes_out, messages_in?}
+    SqliteOpen,        // sqlite.open(path[, {readonly}]) -> result db
+    SqliteClose,       // sqlite.close(db) -> null
+    SqliteExec,        // sqlite.exec(db, sql[, params]) -> result {changes, last_insert_id}
+    SqliteQuery,       // sqlite.query(db, sql[, params]) -> result list of row records
+    SqlitePrepare,     // sqlite.prepare(db, sql) -> result stmt
+    SqliteRun,         // sqlite.run(stmt[, params]) -> result {changes, last_insert_id}
+    SqliteAll,         // sqlite.all(stmt[, params]) -> result list of row records
+    SqliteTransaction, // sqlite.transaction(db, fn) -> fn's value; rolled back if it fails
+    CryptoSha512,         // crypto.sha512(bytes) -> text
+    CryptoAesEncrypt,     // crypto.aes_encrypt(key_hex, nonce_hex, plaintext) -> {ok: hex} | {err: text}
+    CryptoAesDecrypt,     // crypto.aes_decrypt(key_hex, nonce_hex, ciphertext_hex) -> {ok: text} | {err: text}
+    CryptoMerkleRoot,     // crypto.merkle_root(list_of_hex) -> hex
+    CompressGzip,         // compress.gzip(text) -> bytes_hex
+    CompressGunzip,       // compress.gunzip(bytes_hex) -> {ok: text} | {err: text}
+    CompressGzipBytes,     // compress.gzip_compress(data[, level]) -> bytes
+    CompressGunzipBytes,   // compress.gzip_decompress(bytes) -> result bytes
+    CompressDeflate,       // compress.deflate_compress(data[, level]) -> bytes (raw deflate)
+    CompressInflate,       // compress.deflate_decompress(bytes) -> result bytes
+    CompressZstd,          // compress.zstd_compress(data[, level]) -> bytes
+    CompressUnzstd,        // compress.zstd_decompress(bytes) -> result bytes
+    CompressTarCreate,     // compress.tar_create([{name, data}]) -> bytes
+    CompressTarExtract,    // compress.tar_extract(bytes) -> result [{name, size, cid, data}]
+    CompressZipCreate,     // compress.zip_create([{name, data}][, {method}]) -> bytes
+    CompressZipExtract,    // compress.zip_extract(bytes) -> result [{name, size, cid, data}]
+    GraphOf,       // graph.of(run_id) -> {nodes, edges} | {err: text}
+    GraphAncestors, // graph.ancestors(run_id) -> list of run_ids
+    GraphLeaves,    // graph.leaves(run_id) -> list of root run_ids
+    GraphToDot,     // graph.to_dot(graph) -> dot string
+    HashSha256Bytes,
+    IntMul,
+    IntDiv,
+    IntSub,
+    IntAbs,
+    IntMin,
+    IntMax,
+    IntToText,
+    IntFromText,
+    IntNeg,
+    IntClamp,
+    IntMod,
+    IntLt,
+    IntGt,
+    IntLe,
+    IntGe,
+    // float builtins
+    FloatFromInt,
+    FloatToInt,
+    FloatFromText,
+    FloatToText,
+    FloatAdd,
+    FloatSub,
+    FloatMul,
+    FloatDiv,
+    FloatExp,
+    FloatLn,
+    FloatSqrt,
+    FloatPow,
+    FloatAbs,
+    FloatNeg,
+    FloatFloor,
+    FloatCeil,
+    FloatRound,
+    FloatLt,
+    FloatGt,
+    FloatLe,
+    FloatGe,
+    FloatEq,
+    FloatNan,
+    FloatInf,
+    FloatIsNan,
+    FloatIsFinite,
+    FloatMin,
+    FloatMax,
+    // linalg builtins
+    LinalgDot,
+    LinalgNorm,
+    LinalgZeros,
+    LinalgEye,
+    LinalgMatvec,
+    LinalgMatmul,
+    LinalgRelu,
+    LinalgSoftmax,
+    LinalgArgmax,
+    LinalgVecExp, LinalgVecLog, LinalgVecSum, LinalgVecMax, LinalgVecMul,
+    LinalgVecRelu, LinalgVecReluGrad, LinalgSoftmaxGrad, LinalgCrossEntropy,
+    LinalgOuter, LinalgMatMulVecGrad, LinalgVecScalarAdd, LinalgMatRowSum,
+    ListSet,
+    CastFloat, CastInt, CastText, StrJoin, ListAny, ListAll, ListFind, ListFindIndex, ListTake, ListDrop, ListFlatMap,
+    MathSin, MathCos, MathTan, MathAtan2, IntToHex, IntToBin, FloatIsInf, TypeOf,
+    EnvGet, EnvArgs, ProcessSpawn, ProcessExit,
+    ReMatch, ReFind, ReFindAll, ReSplit, ReReplace, FardEval,
+    Base64Encode, Base64Decode, CsvParse, CsvEncode,
+    MapDelete, MapEntries,
+    SetNew, SetAdd, SetRemove, SetHas, SetUnion, SetIntersect, SetDiff, SetToList, SetFromList, SetSize,
+    ListZipWith, ListChunk, ListSortBy,
+    MathAsin, MathAcos, MathAtan, MathLog10,
+    FloatToStrFixed,
+    UuidV4, UuidValidate,
+    IntToStrPadded,
+    BigFromInt, BigFromStr, BigAdd, BigSub, BigMul, BigDiv, BigPow, BigToStr, BigEq, BigLt, BigGt, BigMod,
+    PromiseSpawn, PromiseAwait, PromiseSpawnOrdered,
+    AstParse,
+    DateTimeNow, DateTimeFormat, DateTimeParse, DateTimeAdd, DateTimeSub, DateTimeField,
+    ListParMap,
+    CellNew, CellGet, CellSet,
+    LinalgTranspose,
+    LinalgEigh,
+    LinalgVecAdd,
+    LinalgVecSub,
+    LinalgVecScale,
+    LinalgMatAdd,
+    LinalgMatScale,
+}
+
+/// The value of one std module export.
+pub(crate) enum StdExport {
+    Fn(Builtin),
+    Float(f64),
+    /// a record of builtins, e.g. `time.Duration`
+    Record(&'static [(&'static str, Builtin)]),
+}
+
+/// Every builtin std module with its exports. `std/record` is an alias of `std/rec`.
+pub(crate) static STD_MODULES: &[(&str, &[(&str, StdExport)])] = &[
+    (
+        "std/list",
+        &[
+            ("len", StdExport::Fn(Builtin::ListLen)),
+            ("range", StdExport::Fn(Builtin::ListRange)),
+            ("repeat", StdExport::Fn(Builtin::ListRepeat)),
+            ("concat", StdExport::Fn(Builtin::ListConcat)),
+            ("group_by", StdExport::Fn(Builtin::ListGroupBy)),
+            ("fold", StdExport::Fn(Builtin::ListFold)),
+            ("map", StdExport::Fn(Builtin::ListMap)),
+            ("filter", StdExport::Fn(Builtin::ListFilter)),
+            ("get", StdExport::Fn(Builtin::ListGet)),
+            ("head", StdExport::Fn(Builtin::ListHead)),
+            ("tail", StdExport::Fn(Builtin::ListTail)),
+            ("append", StdExport::Fn(Builtin::ListAppend)),
+            ("zip", StdExport::Fn(Builtin::ListZip)),
+            ("reverse", StdExport::Fn(Builtin::ListReverse)),
+            ("flatten", StdExport::Fn(Builtin::ListFlatten)),
+            ("set", StdExport::Fn(Builtin::ListSet)),
+            ("any", StdExport::Fn(Builtin::ListAny)),
+            ("all", StdExport::Fn(Builtin::ListAll)),
+            ("find", StdExport::Fn(Builtin::ListFind)),
+            ("find_index", StdExport::Fn(Builtin::ListFindIndex)),
+            ("take", StdExport::Fn(Builtin::ListTake)),
+            ("drop", StdExport::Fn(Builtin::ListDrop)),
+            ("flat_map", StdExport::Fn(Builtin::ListFlatMap)),
+            ("par_map", StdExport::Fn(Builtin::ListParMap)),
+            ("zip_with", StdExport::Fn(Builtin::ListZipWith)),
+            ("chunk", StdExport::Fn(Builtin::ListChunk)),
+            ("sort_by", StdExport::Fn(Builtin::ListSortBy)),
+            ("sort_by_int_key", StdExport::Fn(Builtin::ListSortByIntKey)),
+            ("sort_int", StdExport::Fn(Builtin::SortInt)),
+            ("dedupe_sorted_int", StdExport::Fn(Builtin::DedupeSortedInt)),
+            ("hist_int", StdExport::Fn(Builtin::HistInt)),
+        ],
+    ),
+    (
+        "std/result",
+        &[
+            ("ok", StdExport::Fn(Builtin::ResultOk)),
+            ("err", StdExport::Fn(Builtin::ResultErr)),
+            ("andThen", StdExport::Fn(Builtin::ResultAndThen)),
+            ("and_then", StdExport::Fn(Builtin::ResultAndThen)),
+            ("unwrap_ok", StdExport::Fn(Builtin::ResultUnwrapOk)),
+            ("unwrap_err", StdExport::Fn(Builtin::ResultUnwrapErr)),
+            ("is_ok", StdExport::Fn(Builtin::ResultIsOk)),
+            ("is_err", StdExport::Fn(Builtin::ResultIsErr)),
+            ("map", StdExport::Fn(Builtin::ResultMap)),
+            ("map_err", StdExport::Fn(Builtin::ResultMapErr)),
+            ("or_else", StdExport::Fn(Builtin::ResultOrElse)),
+            ("unwrap", StdExport::Fn(Builtin::ResultUnwrapOk)),
+            ("unwrap_or", StdExport::Fn(Builtin::ResultUnwrapOr)),
+        ],
+    ),
+    (
+        "std/grow",
+        &[
+            ("append", StdExport::Fn(Builtin::GrowAppend)),
+            ("merge", StdExport::Fn(Builtin::RecMerge)),
+            ("unfold_tree", StdExport::Fn(Builtin::GrowUnfoldTree)),
+            ("unfold", StdExport::Fn(Builtin::Unfold)),
+        ],
+    ),
+    (
+        "std/flow",
+        &[
+            ("id", StdExport::Fn(Builtin::FlowId)),
+            ("pipe", StdExport::Fn(Builtin::FlowPipe)),
+            ("tap", StdExport::Fn(Builtin::FlowTap)),
+        ],
+    ),
+    (
+        "std/str",
+        &[
+            ("len", StdExport::Fn(Builtin::StrLen)),
+            ("trim", StdExport::Fn(Builtin::StrTrim)),
+            ("split_lines", StdExport::Fn(Builtin::StrSplitLines)),
+            ("toLower", StdExport::Fn(Builtin::StrToLower)),
+            ("lower", StdExport::Fn(Builtin::StrToLower)),
+            ("concat", StdExport::Fn(Builtin::StrConcat)),
+            ("split", StdExport::Fn(Builtin::StrSplit)),
+            ("upper", StdExport::Fn(Builtin::StrUpper)),
+            ("contains", StdExport::Fn(Builtin::StrContains)),
+            ("starts_with", StdExport::Fn(Builtin::StrStartsWith)),
+            ("ends_with", StdExport::Fn(Builtin::StrEndsWith)),
+            ("replace", StdExport::Fn(Builtin::StrReplace)),
+            ("slice", StdExport::Fn(Builtin::StrSlice)),
+            ("format", StdExport::Fn(Builtin::StrFormat)),
+            ("from_int", StdExport::Fn(Builtin::StrFromInt)),
+            ("join", StdExport::Fn(Builtin::StrJoin)),
+            ("from_float", StdExport::Fn(Builtin::StrFromFloat)),
+            ("pad_left", StdExport::Fn(Builtin::StrPadLeft)),
+            ("pad_right", StdExport::Fn(Builtin::StrPadRight)),
+            ("repeat", StdExport::Fn(Builtin::StrRepeat)),
+            ("index_of", StdExport::Fn(Builtin::StrIndexOf)),
+            ("chars", StdExport::Fn(Builtin::StrChars)),
+        ],
+    ),
+    (
+        "std/ast",
+        &[
+            ("parse", StdExport::Fn(Builtin::AstParse)),
+        ],
+    ),
+    (
+        "std/promise",
+        &[
+            ("spawn", StdExport::Fn(Builtin::PromiseSpawn)),
+            ("await", StdExport::Fn(Builtin::PromiseAwait)),
+            ("spawn_ordered", StdExport::Fn(Builtin::PromiseSpawnOrdered)),
+        ],
+    ),
+    (
+        "std/bigint",
+        &[
+            ("from_int", StdExport::Fn(Builtin::BigFromInt)),
+            ("from_str", StdExport::Fn(Builtin::BigFromStr)),
+            ("add", StdExport::Fn(Builtin::BigAdd)),
+            ("sub", StdExport::Fn(Builtin::BigSub)),
+            ("mul", StdExport::Fn(Builtin::BigMul)),
+            ("div", StdExport::Fn(Builtin::BigDiv)),
+            ("mod", StdExport::Fn(Builtin::BigMod)),
+            ("pow", StdExport::Fn(Builtin::BigPow)),
+            ("to_str", StdExport::Fn(Builtin::BigToStr)),
+            ("eq", StdExport::Fn(Builtin::BigEq)),
+            ("lt", StdExport::Fn(Builtin::BigLt)),
+            ("gt", StdExport::Fn(Builtin::BigGt)),
+        ],
+    ),
+    (
+        "std/mutex",
+        &[
+            ("new", StdExport::Fn(Builtin::MutexNew)),
+            ("lock", StdExport::Fn(Builtin::MutexLock)),
+            ("unlock", StdExport::Fn(Builtin::MutexUnlock)),
+            ("with_lock", StdExport::Fn(Builtin::MutexWithLock)),
+        ],
+    ),
+    (
+        "std/chan",
+        &[
+            ("new", StdExport::Fn(Builtin::ChanNew)),
+            ("send", StdExport::Fn(Builtin::ChanSend)),
+            ("recv", StdExport::Fn(Builtin::ChanRecv)),
+            ("try_recv", StdExport::Fn(Builtin::ChanTryRecv)),
+            ("close", StdExport::Fn(Builtin::ChanClose)),
+        ],
+    ),
+    (
+        "std/uuid",
+        &[
+            ("v4", StdExport::Fn(Builtin::UuidV4)),
+            ("validate", StdExport::Fn(Builtin::UuidValidate)),
+        ],
+    ),
+    (
+        "std/datetime",
+        &[
+            ("now", StdExport::Fn(Builtin::DateTimeNow)),
+            ("format", StdExport::Fn(Builtin::DateTimeFormat)),
+            ("parse", StdExport::Fn(Builtin::DateTimeParse)),
+            ("add", StdExport::Fn(Builtin::DateTimeAdd)),
+            ("diff", StdExport::Fn(Builtin::DateTimeSub)),
+            ("field", StdExport::Fn(Builtin::DateTimeField)),
+        ],
+    ),
+    (
+        "std/set",
+        &[
+            ("new", StdExport::Fn(Builtin::SetNew)),
+            ("add", StdExport::Fn(Builtin::SetAdd)),
+            ("remove", StdExport::Fn(Builtin::SetRemove)),
+            ("has", StdExport::Fn(Builtin::SetHas)),
+            ("union", StdExport::Fn(Builtin::SetUnion)),
+            ("intersect", StdExport::Fn(Builtin::SetIntersect)),
+            ("diff", StdExport::Fn(Builtin::SetDiff)),
+            ("to_list", StdExport::Fn(Builtin::SetToList)),
+            ("from_list", StdExport::Fn(Builtin::SetFromList)),
+            ("size", StdExport::Fn(Builtin::SetSize)),
+        ],
+    ),
+    (
+        "std/map",
+        &[
+            ("get", StdExport::Fn(Builtin::MapGet)),
+            ("set", StdExport::Fn(Builtin::MapSet)),
+            ("keys", StdExport::Fn(Builtin::RecKeys)),
+            ("values", StdExport::Fn(Builtin::RecValues)),
+            ("has", StdExport::Fn(Builtin::RecHas)),
+            ("delete", StdExport::Fn(Builtin::MapDelete)),
+            ("entries", StdExport::Fn(Builtin::MapEntries)),
+            ("new", StdExport::Fn(Builtin::RecEmpty)),
+            ("from_entries", StdExport::Fn(Builtin::RecEmpty)),
+        ],
+    ),
+    (
+        "std/rec",
+        &[
+            ("empty", StdExport::Fn(Builtin::RecEmpty)),
+            ("keys", StdExport::Fn(Builtin::RecKeys)),
+            ("values", StdExport::Fn(Builtin::RecValues)),
+            ("has", StdExport::Fn(Builtin::RecHas)),
+            ("get", StdExport::Fn(Builtin::RecGet)),
+            ("getOr", StdExport::Fn(Builtin::RecGetOr)),
+            ("getOrErr", StdExport::Fn(Builtin::RecGetOrErr)),
+            ("set", StdExport::Fn(Builtin::RecSet)),
+            ("remove", StdExport::Fn(Builtin::RecRemove)),
+            ("merge", StdExport::Fn(Builtin::RecMerge)),
+            ("select", StdExport::Fn(Builtin::RecSelect)),
+            ("rename", StdExport::Fn(Builtin::RecRename)),
+            ("update", StdExport::Fn(Builtin::RecUpdate)),
+        ],
+    ),
+    (
+        "std/json",
+        &[
+            ("encode", StdExport::Fn(Builtin::JsonEncode)),
+            ("decode", StdExport::Fn(Builtin::JsonDecode)),
+            ("canonicalize", StdExport::Fn(Builtin::JsonCanonicalize)),
+        ],
+    ),
+    (
+        "std/type",
+        &[
+            ("of", StdExport::Fn(Builtin::TypeOf)),
+        ],
+    ),
+    (
+        "std/cast",
+        &[
+            ("float", StdExport::Fn(Builtin::CastFloat)),
+            ("int", StdExport::Fn(Builtin::CastInt)),
+            ("text", StdExport::Fn(Builtin::CastText)),
+        ],
+    ),
+    (
+        "std/int",
+        &[
+            ("add", StdExport::Fn(Builtin::IntAdd)),
+            ("eq", StdExport::Fn(Builtin::IntEq)),
+            ("parse", StdExport::Fn(Builtin::IntParse)),
+            ("pow", StdExport::Fn(Builtin::IntPow)),
+            ("to_hex", StdExport::Fn(Builtin::IntToHex)),
+            ("to_bin", StdExport::Fn(Builtin::IntToBin)),
+            ("mul", StdExport::Fn(Builtin::IntMul)),
+            ("div", StdExport::Fn(Builtin::IntDiv)),
+            ("sub", StdExport::Fn(Builtin::IntSub)),
+            ("abs", StdExport::Fn(Builtin::IntAbs)),
+            ("min", StdExport::Fn(Builtin::IntMin)),
+            ("max", StdExport::Fn(Builtin::IntMax)),
+            ("to_text", StdExport::Fn(Builtin::IntToText)),
+            ("from_text", StdExport::Fn(Builtin::IntFromText)),
+            ("neg", StdExport::Fn(Builtin::IntNeg)),
+            ("clamp", StdExport::Fn(Builtin::IntClamp)),
+            ("mod", StdExport::Fn(Builtin::IntMod)),
+            ("lt", StdExport::Fn(Builtin::IntLt)),
+            ("gt", StdExport::Fn(Builtin::IntGt)),
+            ("le", StdExport::Fn(Builtin::IntLe)),
+            ("ge", StdExport::Fn(Builtin::IntGe)),
+            ("to_str_padded", StdExport::Fn(Builtin::IntToStrPadded)),
+        ],
+    ),
+    (
+        "std/fs",
+        &[
+            ("read_text", StdExport::Fn(Builtin::FsReadText)),
+            ("write_text", StdExport::Fn(Builtin::FsWriteText)),
+            ("read_bytes", StdExport::Fn(Builtin::FsReadBytes)),
+            ("write_bytes", StdExport::Fn(Builtin::FsWriteBytes)),
+            ("exists", StdExport::Fn(Builtin::FsExists)),
+            ("read_dir", StdExport::Fn(Builtin::FsReadDir)),
+            ("stat", StdExport::Fn(Builtin::FsStat)),
+            ("delete", StdExport::Fn(Builtin::FsDelete)),
+            ("make_dir", StdExport::Fn(Builtin::FsMakeDir)),
+        ],
+    ),
+    (
+        "std/option",
+        &[
+            ("none", StdExport::Fn(Builtin::OptionNone)),
+            ("None", StdExport::Fn(Builtin::OptionNone)),
+            ("some", StdExport::Fn(Builtin::OptionSome)),
+            ("Some", StdExport::Fn(Builtin::OptionSome)),
+            ("is_none", StdExport::Fn(Builtin::OptionIsNone)),
+            ("isNone", StdExport::Fn(Builtin::OptionIsNone)),
+            ("is_some", StdExport::Fn(Builtin::OptionIsSome)),
+            ("isSome", StdExport::Fn(Builtin::OptionIsSome)),
+            ("from_nullable", StdExport::Fn(Builtin::OptionFromNullable)),
+            ("fromNullable", StdExport::Fn(Builtin::OptionFromNullable)),
+            ("to_nullable", StdExport::Fn(Builtin::OptionToNullable)),
+            ("toNullable", StdExport::Fn(Builtin::OptionToNullable)),
+            ("map", StdExport::Fn(Builtin::OptionMap)),
+            ("and_then", StdExport::Fn(Builtin::OptionAndThen)),
+            ("andThen", StdExport::Fn(Builtin::OptionAndThen)),
+            ("unwrap_or", StdExport::Fn(Builtin::OptionUnwrapOr)),
+            ("unwrapOr", StdExport::Fn(Builtin::OptionUnwrapOr)),
+            ("unwrap_or_else", StdExport::Fn(Builtin::OptionUnwrapOrElse)),
+            ("unwrapOrElse", StdExport::Fn(Builtin::OptionUnwrapOrElse)),
+            ("to_result", StdExport::Fn(Builtin::OptionToResult)),
+            ("toResult", StdExport::Fn(Builtin::OptionToResult)),
+        ],
+    ),
+    (
+        "std/bits",
+        &[
+            ("band", StdExport::Fn(Builtin::BitAnd)),
+            ("bor", StdExport::Fn(Builtin::BitOr)),
+            ("bxor", StdExport::Fn(Builtin::BitXor)),
+            ("bnot", StdExport::Fn(Builtin::BitNot)),
+            ("bshl", StdExport::Fn(Builtin::BitShl)),
+            ("bshr", StdExport::Fn(Builtin::BitShr)),
+            ("popcount", StdExport::Fn(Builtin::BitPopcount)),
+        ],
+    ),
+    (
+        "std/math",
+        &[
+            ("abs", StdExport::Fn(Builtin::MathAbs)),
+            ("min", StdExport::Fn(Builtin::MathMin)),
+            ("max", StdExport::Fn(Builtin::MathMax)),
+            ("pow", StdExport::Fn(Builtin::MathPow)),
+            ("sqrt", StdExport::Fn(Builtin::MathSqrt)),
+            ("floor", StdExport::Fn(Builtin::MathFloor)),
+            ("ceil", StdExport::Fn(Builtin::MathCeil)),
+            ("round", StdExport::Fn(Builtin::MathRound)),
+            ("log", StdExport::Fn(Builtin::MathLog)),
+            ("log2", StdExport::Fn(Builtin::MathLog2)),
+            ("sin", StdExport::Fn(Builtin::MathSin)),
+            ("cos", StdExport::Fn(Builtin::MathCos)),
+            ("tan", StdExport::Fn(Builtin::MathTan)),
+            ("atan2", StdExport::Fn(Builtin::MathAtan2)),
+            ("exp", StdExport::Fn(Builtin::MathExp)),
+            ("pi", StdExport::Float(std::f64::consts::PI)),
+            ("e", StdExport::Float(std::f64::consts::E)),
+            ("inf", StdExport::Float(f64::INFINITY)),
+            ("asin", StdExport::Fn(Builtin::MathAsin)),
+            ("acos", StdExport::Fn(Builtin::MathAcos)),
+            ("atan", StdExport::Fn(Builtin::MathAtan)),
+            ("log10", StdExport::Fn(Builtin::MathLog10)),
+        ],
+    ),
+    (
+        "std/null",
+        &[
+            ("isNull", StdExport::Fn(Builtin::NullIsNull)),
+            ("coalesce", StdExport::Fn(Builtin::NullCoalesce)),
+            ("guardNotNull", StdExport::Fn(Builtin::NullGuard)),
+        ],
+    ),
+    (
+        "std/path",
+        &[
+            ("base", StdExport::Fn(Builtin::PathBase)),
+            ("dir", StdExport::Fn(Builtin::PathDir)),
+            ("ext", StdExport::Fn(Builtin::PathExt)),
+            ("isAbs", StdExport::Fn(Builtin::PathIsAbs)),
+            ("join", StdExport::Fn(Builtin::PathJoin)),
+            ("joinAll", StdExport::Fn(Builtin::PathJoinAll)),
+            ("normalize", StdExport::Fn(Builtin::PathNormalize)),
+        ],
+    ),
+    (
+        "std/time",
+        &[
+            ("now", StdExport::Fn(Builtin::TimeNow)),
+            ("parse", StdExport::Fn(Builtin::TimeParse)),
+            ("format", StdExport::Fn(Builtin::TimeFormat)),
+            ("add", StdExport::Fn(Builtin::TimeAdd)),
+            ("sub", StdExport::Fn(Builtin::TimeSub)),
+            (
+                "Duration",
+                StdExport::Record(&[
+                    ("ms", Builtin::TimeDurationMs),
+                    ("sec", Builtin::TimeDurationSec),
+                    ("min", Builtin::TimeDurationMin),
+                ]),
+            ),
+        ],
+    ),
+    (
+        "std/trace",
+        &[
+            ("emit", StdExport::Fn(Builtin::Emit)),
+            ("info", StdExport::Fn(Builtin::TraceInfo)),
+            ("warn", StdExport::Fn(Builtin::TraceWarn)),
+            ("error", StdExport::Fn(Builtin::TraceError)),
+            ("span", StdExport::Fn(Builtin::TraceSpan)),
+        ],
+    ),
+    (
+        "std/sembit",
+        &[
+            ("partition", StdExport::Fn(Builtin::SembitPartition)),
+        ],
+    ),
+    (
+        "std/artifact",
+        &[
+            ("import", StdExport::Fn(Builtin::ImportArtifact)),
+            ("emit", StdExport::Fn(Builtin::EmitArtifact)),
+            ("ref", StdExport::Fn(Builtin::Unimplemented("std/trace.ref"))),
+            ("derive", StdExport::Fn(Builtin::Unimplemented("std/trace.derive"))),
+        ],
+    ),
+    (
+        "std/cli",
+        &[
+            ("args", StdExport::Fn(Builtin::CliArgs)),
+            ("get", StdExport::Fn(Builtin::CliGet)),
+            ("get_int", StdExport::Fn(Builtin::CliGetInt)),
+            ("get_float", StdExport::Fn(Builtin::CliGetFloat)),
+            ("get_bool", StdExport::Fn(Builtin::CliGetBool)),
+            ("has", StdExport::Fn(Builtin::CliHas)),
+        ],
+    ),
+    (
+        "std/io",
+        &[
+            ("read_file", StdExport::Fn(Builtin::IoReadFile)),
+            ("write_file", StdExport::Fn(Builtin::IoWriteFile)),
+            ("append_file", StdExport::Fn(Builtin::IoAppendFile)),
+            ("read_lines", StdExport::Fn(Builtin::IoReadLines)),
+            ("file_exists", StdExport::Fn(Builtin::IoFileExists)),
+            ("delete_file", StdExport::Fn(Builtin::IoDeleteFile)),
+            ("read_stdin", StdExport::Fn(Builtin::IoReadStdin)),
+            ("read_stdin_lines", StdExport::Fn(Builtin::IoReadStdinLines)),
+            ("list_dir", StdExport::Fn(Builtin::IoListDir)),
+            ("make_dir", StdExport::Fn(Builtin::IoMakeDir)),
+        ],
+    ),
+    (
+        "std/bytes",
+        &[
+            ("concat", StdExport::Fn(Builtin::BytesConcat)),
+            ("to_str", StdExport::Fn(Builtin::BytesToStr)),
+            ("len", StdExport::Fn(Builtin::BytesLen)),
+            ("get", StdExport::Fn(Builtin::BytesGet)),
+            ("of_list", StdExport::Fn(Builtin::BytesOfList)),
+            ("to_list", StdExport::Fn(Builtin::BytesToList)),
+            ("of_str", StdExport::Fn(Builtin::BytesOfStr)),
+            ("merkle_root", StdExport::Fn(Builtin::BytesMerkleRoot)),
+        ],
+    ),
+    (
+        "std/codec",
+        &[
+            ("base64url_encode", StdExport::Fn(Builtin::CodecBase64UrlEncode)),
+            ("base64url_encode_hex", StdExport::Fn(Builtin::CodecBase64UrlEncodeHex)),
+            ("base64url_decode", StdExport::Fn(Builtin::CodecBase64UrlDecode)),
+            ("hex_encode", StdExport::Fn(Builtin::CodecHexEncode)),
+            ("hex_decode", StdExport::Fn(Builtin::CodecHexDecode)),
+        ],
+    ),
+    (
+        "std/cell",
+        &[
+            ("new", StdExport::Fn(Builtin::CellNew)),
+            ("get", StdExport::Fn(Builtin::CellGet)),
+            ("set", StdExport::Fn(Builtin::CellSet)),
+        ],
+    ),
+    (
+        "std/base64",
+        &[
+            ("encode", StdExport::Fn(Builtin::Base64Encode)),
+            ("decode", StdExport::Fn(Builtin::Base64Decode)),
+        ],
+    ),
+    (
+        "std/csv",
+        &[
+            ("parse", StdExport::Fn(Builtin::CsvParse)),
+            ("encode", StdExport::Fn(Builtin::CsvEncode)),
+        ],
+    ),
+    (
+        "std/eval",
+        &[
+            ("eval", StdExport::Fn(Builtin::FardEval)),
+        ],
+    ),
+    (
+        "std/witness",
+        &[
+            ("self_digest", StdExport::Fn(Builtin::WitnessSelfDigest)),
+            ("deps", StdExport::Fn(Builtin::WitnessDeps)),
+            ("verify", StdExport::Fn(Builtin::WitnessVerify)),
+            ("verify_chain", StdExport::Fn(Builtin::WitnessVerifyChain)),
+        ],
+    ),
+    (
+        "std/ffi",
+        &[
+            ("open", StdExport::Fn(Builtin::FfiOpen)),
+            ("call", StdExport::Fn(Builtin::FfiCall)),
+            ("call_pure", StdExport::Fn(Builtin::FfiCallPure)),
+            ("call_str", StdExport::Fn(Builtin::FfiCallStr)),
+            ("call_checked", StdExport::Fn(Builtin::FfiCallChecked)),
+            ("bind", StdExport::Fn(Builtin::FfiBind)),
+            ("load", StdExport::Fn(Builtin::FfiOpen)),
+            ("close", StdExport::Fn(Builtin::FfiClose)),
+        ],
+    ),
+    (
+        "std/re",
+        &[
+            ("is_match", StdExport::Fn(Builtin::ReMatch)),
+            ("find", StdExport::Fn(Builtin::ReFind)),
+            ("find_all", StdExport::Fn(Builtin::ReFindAll)),
+            ("split", StdExport::Fn(Builtin::ReSplit)),
+            ("replace", StdExport::Fn(Builtin::ReReplace)),
+        ],
+    ),
+    (
+        "std/env",
+        &[
+            ("get", StdExport::Fn(Builtin::EnvGet)),
+            ("args", StdExport::Fn(Builtin::EnvArgs)),
+        ],
+    ),
+    (
+        "std/process",
+        &[
+            ("spawn", StdExport::Fn(Builtin::ProcessSpawn)),
+            ("exit", StdExport::Fn(Builtin::ProcessExit)),
+        ],
+    ),
+    (
+        "std/hash",
+        &[
+            ("sha256_bytes", StdExport::Fn(Builtin::HashSha256Bytes)),
+            ("sha256_text", StdExport::Fn(Builtin::HashSha256Text)),
+        ],
+    ),
+    (
+        "std/http",
+        &[
+            ("get", StdExport::Fn(Builtin::HttpGet)),
+            ("post", StdExport::Fn(Builtin::HttpPost)),
+            ("request", StdExport::Fn(Builtin::HttpRequest)),
+        ],
+    ),
+    (
+        "std/sqlite",
+        &[
+            ("open", StdExport::Fn(Builtin::SqliteOpen)),
+            ("close", StdExport::Fn(Builtin::SqliteClose)),
+            ("exec", StdExport::Fn(Builtin::SqliteExec)),
+            ("query", StdExport::Fn(Builtin::SqliteQuery)),
+            ("prepare", StdExport::Fn(Builtin::SqlitePrepare)),
+            ("run", StdExport::Fn(Builtin::SqliteRun)),
+            ("all", StdExport::Fn(Builtin::SqliteAll)),
+            ("transaction", StdExport::Fn(Builtin::SqliteTransaction)),
+        ],
+    ),
+    (
+        "std/net",
+        &[
+            ("serve", StdExport::Fn(Builtin::NetServe)),
+            ("listen", StdExport::Fn(Builtin::NetListen)),
+            ("stop", StdExport::Fn(Builtin::NetStop)),
+            ("connect", StdExport::Fn(Builtin::NetConnect)),
+            ("tcp_listen", StdExport::Fn(Builtin::NetTcpListen)),
+            ("accept", StdExport::Fn(Builtin::NetAccept)),
+            ("read", StdExport::Fn(Builtin::NetRead)),
+            ("write", StdExport::Fn(Builtin::NetWrite)),
+            ("udp_bind", StdExport::Fn(Builtin::NetUdpBind)),
+            ("udp_send", StdExport::Fn(Builtin::NetUdpSend)),
+            ("udp_recv", StdExport::Fn(Builtin::NetUdpRecv)),
+            ("ws_connect", StdExport::Fn(Builtin::NetWsConnect)),
+            ("ws_accept", StdExport::Fn(Builtin::NetWsAccept)),
+            ("ws_send", StdExport::Fn(Builtin::NetWsSend)),
+            ("close", StdExport::Fn(Builtin::NetClose)),
+        ],
+    ),
+    ("std/record", &[]),
+    (
+        "std/png",
+        &[
+            ("red_1x1", StdExport::Fn(Builtin::PngRed1x1)),
+            ("decode", StdExport::Fn(Builtin::PngDecode)),
+            ("read", StdExport::Fn(Builtin::PngRead)),
+            ("encode", StdExport::Fn(Builtin::PngEncode)),
+            ("pixel", StdExport::Fn(Builtin::PngPixel)),
+            ("row", StdExport::Fn(Builtin::PngRow)),
+            ("set_pixel", StdExport::Fn(Builtin::PngSetPixel)),
+            ("convert", StdExport::Fn(Builtin::PngConvert)),
+        ],
+    ),
+    (
+        "std/compress",
+        &[
+            ("gzip", StdExport::Fn(Builtin::CompressGzip)),
+            ("gunzip", StdExport::Fn(Builtin::CompressGunzip)),
+            ("gzip_compress", StdExport::Fn(Builtin::CompressGzipBytes)),
+            ("gzip_decompress", StdExport::Fn(Builtin::CompressGunzipBytes)),
+            ("deflate_compress", StdExport::Fn(Builtin::CompressDeflate)),
+            ("deflate_decompress", StdExport::Fn(Builtin::CompressInflate)),
+            ("zstd_compress", StdExport::Fn(Builtin::CompressZstd)),
+            ("zstd_decompress", StdExport::Fn(Builtin::CompressUnzstd)),
+            ("tar_create", StdExport::Fn(Builtin::CompressTarCreate)),
+            ("tar_extract", StdExport::Fn(Builtin::CompressTarExtract)),
+            ("zip_create", StdExport::Fn(Builtin::CompressZipCreate)),
+            ("zip_extract", StdExport::Fn(Builtin::CompressZipExtract)),
+        ],
+    ),
+    (
+        "std/graph",
+        &[
+            ("of", StdExport::Fn(Builtin::GraphOf)),
+            ("ancestors", StdExport::Fn(Builtin::GraphAncestors)),
+            ("leaves", StdExport::Fn(Builtin::GraphLeaves)),
+            ("to_dot", StdExport::Fn(Builtin::GraphToDot)),
+        ],
+    ),
+    (
+        "std/rand",
+        &[
+            ("uuid_v4", StdExport::Fn(Builtin::RandUuidV4)),
+        ],
+    ),
+    (
+        "std/crypto",
+        &[
+            ("ed25519_verify", StdExport::Fn(Builtin::CryptoEd25519Verify)),
+            ("hmac_sha256", StdExport::Fn(Builtin::CryptoHmacSha256)),
+            ("sha512", StdExport::Fn(Builtin::CryptoSha512)),
+            ("aes_encrypt", StdExport::Fn(Builtin::CryptoAesEncrypt)),
+            ("aes_decrypt", StdExport::Fn(Builtin::CryptoAesDecrypt)),
+            ("merkle_root", StdExport::Fn(Builtin::CryptoMerkleRoot)),
+        ],
+    ),
+    (
+        "std/float",
+        &[
+            ("from_int", StdExport::Fn(Builtin::FloatFromInt)),
+            ("to_int", StdExport::Fn(Builtin::FloatToInt)),
+            ("from_text", StdExport::Fn(Builtin::FloatFromText)),
+            ("to_text", StdExport::Fn(Builtin::FloatToText)),
+            ("add", StdExport::Fn(Builtin::FloatAdd)),
+            ("sub", StdExport::Fn(Builtin::FloatSub)),
+            ("mul", StdExport::Fn(Builtin::FloatMul)),
+            ("div", StdExport::Fn(Builtin::FloatDiv)),
+            ("exp", StdExport::Fn(Builtin::FloatExp)),
+            ("ln", StdExport::Fn(Builtin::FloatLn)),
+            ("sqrt", StdExport::Fn(Builtin::FloatSqrt)),
+            ("pow", StdExport::Fn(Builtin::FloatPow)),
+            ("abs", StdExport::Fn(Builtin::FloatAbs)),
+            ("neg", StdExport::Fn(Builtin::FloatNeg)),
+            ("floor", StdExport::Fn(Builtin::FloatFloor)),
+            ("ceil", StdExport::Fn(Builtin::FloatCeil)),
+            ("round", StdExport::Fn(Builtin::FloatRound)),
+            ("lt", StdExport::Fn(Builtin::FloatLt)),
+            ("gt", StdExport::Fn(Builtin::FloatGt)),
+            ("le", StdExport::Fn(Builtin::FloatLe)),
+            ("ge", StdExport::Fn(Builtin::FloatGe)),
+            ("eq", StdExport::Fn(Builtin::FloatEq)),
+            ("nan", StdExport::Fn(Builtin::FloatNan)),
+            ("inf", StdExport::Fn(Builtin::FloatInf)),
+            ("is_nan", StdExport::Fn(Builtin::FloatIsNan)),
+            ("to_str_fixed", StdExport::Fn(Builtin::FloatToStrFixed)),
+            ("is_inf", StdExport::Fn(Builtin::FloatIsInf)),
+            ("is_finite", StdExport::Fn(Builtin::FloatIsFinite)),
+            ("min", StdExport::Fn(Builtin::FloatMin)),
+            ("max", StdExport::Fn(Builtin::FloatMax)),
+        ],
+    ),
+    (
+        "std/linalg",
+        &[
+            ("dot", StdExport::Fn(Builtin::LinalgDot)),
+            ("norm", StdExport::Fn(Builtin::LinalgNorm)),
+            ("zeros", StdExport::Fn(Builtin::LinalgZeros)),
+            ("eye", StdExport::Fn(Builtin::LinalgEye)),
+            ("matvec", StdExport::Fn(Builtin::LinalgMatvec)),
+            ("relu", StdExport::Fn(Builtin::LinalgRelu)),
+            ("softmax", StdExport::Fn(Builtin::LinalgSoftmax)),
+            ("argmax", StdExport::Fn(Builtin::LinalgArgmax)),
+            ("matmul", StdExport::Fn(Builtin::LinalgMatmul)),
+            ("transpose", StdExport::Fn(Builtin::LinalgTranspose)),
+            ("eigh", StdExport::Fn(Builtin::LinalgEigh)),
+            ("vec_add", StdExport::Fn(Builtin::LinalgVecAdd)),
+            ("vec_sub", StdExport::Fn(Builtin::LinalgVecSub)),
+            ("vec_scale", StdExport::Fn(Builtin::LinalgVecScale)),
+            ("mat_add", StdExport::Fn(Builtin::LinalgMatAdd)),
+            ("mat_scale", StdExport::Fn(Builtin::LinalgMatScale)),
+            ("vec_exp", StdExport::Fn(Builtin::LinalgVecExp)),
+            ("vec_log", StdExport::Fn(Builtin::LinalgVecLog)),
+            ("vec_sum", StdExport::Fn(Builtin::LinalgVecSum)),
+            ("vec_max", StdExport::Fn(Builtin::LinalgVecMax)),
+            ("vec_mul", StdExport::Fn(Builtin::LinalgVecMul)),
+            ("vec_relu", StdExport::Fn(Builtin::LinalgVecRelu)),
+            ("vec_relu_grad", StdExport::Fn(Builtin::LinalgVecReluGrad)),
+            ("softmax_grad", StdExport::Fn(Builtin::LinalgSoftmaxGrad)),
+            ("cross_entropy", StdExport::Fn(Builtin::LinalgCrossEntropy)),
+            ("outer", StdExport::Fn(Builtin::LinalgOuter)),
+            ("mat_mul_vec_grad", StdExport::Fn(Builtin::LinalgMatMulVecGrad)),
+            ("vec_scalar_add", StdExport::Fn(Builtin::LinalgVecScalarAdd)),
+            ("mat_row_sum", StdExport::Fn(Builtin::LinalgMatRowSum)),
+        ],
+    ),
+];
@@ -15,7 +15,7 @@ pub struct Cli {
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
-    Run(RunArgs),
+    Run(Box<RunArgs>),
     Repl,
     Test(TestArgs),
     Publish(PublishArgs),
@@ -23,6 +23,7 @@ pub enum Command {
     New(NewArgs),
     Search(SearchArgs),
     Notebook(NotebookArgs),
+    Check(CheckArgs),
 }
 
 #[derive(Args, Debug)]
@@ -42,6 +43,10 @@ pub struct TestArgs {
 
     #[arg(long, default_value_t = false)]
     pub json: bool,
+
+    /// Serve std/http calls from the fixture files in this directory
+    #[arg(long)]
+    pub http_fixtures: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -64,9 +69,50 @@ pub struct RunArgs {
     #[arg(long, default_value_t = false)]
     pub no_trace: bool,
 
-    #[arg(long, default_value_t = false)]
+    /// Type-check the program and its imports with fardcheck before running; abort on errors
+    #[arg(long, alias = "strict_types", default_value_t = false)]
     pub strict_types: bool,
 
+    /// Record every oracle answer (time, randomness, env, stdin, http, process) into the trace
+    #[arg(long, default_value_t = false)]
+    pub record: bool,
+
+    /// Answer oracle calls from the trace of a previously recorded run
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Capability policy (fs, http, process, ffi, listen, env); overrides `[permissions]` in fard.toml
+    #[arg(long)]
+    pub policy: Option<PathBuf>,
+
+    /// Serve std/http calls from the fixture files in this directory; unmatched requests fail
+    #[arg(long)]
+    pub http_fixtures: Option<PathBuf>,
+
+    /// With --http-fixtures, make real requests and write their responses to captured.toml there
+    #[arg(long, default_value_t = false)]
+    pub http_capture: bool,
+
+    /// Receipt store to publish to and resolve run IDs from: a directory, sqlite:<db> or a registry URL (repeatable)
+    #[arg(long)]
+    pub receipts: Vec<String>,
+
+    /// Local cache for receipts fetched from a remote receipt store
+    #[arg(long)]
+    pub receipt_cache: Option<PathBuf>,
+
+    /// Sign the run ID with this Ed25519 key (PKCS#8 PEM); writes signature.json and signs the receipt
+    #[arg(long)]
+    pub sign_key: Option<PathBuf>,
+
+    /// Also accept parent runs (artifact imports, --replay traces) from runtimes whose stdlib root digests are listed in this file
+    #[arg(long)]
+    pub stdlib_roots: Option<PathBuf>,
+
+    /// Report which functions run on the bytecode VM and which fell back to the tree-walker
+    #[arg(long, default_value_t = false)]
+    pub vm_stats: bool,
+
     /// Program arguments passed after --
     #[arg(last = true)]
     pub program_args: Vec<String>,
@@ -102,15 +148,32 @@ pub struct NotebookArgs {
     /// Output file (default: overwrites input with results)
     #[arg(long)]
     pub output: Option<std::path::PathBuf>,
-    /// Directory for cell outputs
+    /// Directory for cell receipts, the cell cache and the notebook receipt
     #[arg(long, default_value = "./notebook_out")]
     pub out_dir: String,
+    /// Rerun every cell, ignoring cached results
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Program to parse (not run); diagnostics are printed as JSON lines
+    #[arg(long)]
+    pub program: PathBuf,
+
+    /// Print the parsed AST, without source positions, instead of diagnostics
+    #[arg(long)]
+    pub ast: bool,
 }
 
 #[derive(Args, Debug)]
 pub struct SearchArgs {
     /// Search query (package name or keyword)
     pub query: Option<String>,
+    /// fardregistry server to search (default: $FARD_REGISTRY_URL, else the static package index)
+    #[arg(long)]
+    pub registry: Option<String>,
 }
 
 impl Cli {
@@ -149,6 +212,16 @@ impl Cli {
                 enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
             };
             return (dummy, true, false, None, None, None, None);
@@ -156,7 +229,7 @@ impl Cli {
 
         let want_repl = matches!(cli.cmd, Some(Command::Repl));
         let run = match cli.cmd {
-            Some(Command::Run(r)) => r,
+            Some(Command::Run(r)) => *r,
             Some(Command::Test(t)) => {
                 let dummy = RunArgs {
                     program: t.program.clone(),
@@ -166,6 +239,16 @@ impl Cli {
                     enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                 };
                 return (dummy, false, false, Some(t), None, None, None);
@@ -179,6 +262,16 @@ impl Cli {
                     enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                 };
                 return (dummy, false, false, None, Some(p), None, None);
@@ -192,6 +285,16 @@ impl Cli {
                     enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                 };
                 return (dummy, false, false, None, None, Some(i), None);
@@ -205,11 +308,21 @@ impl Cli {
                     enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                 };
                 return (dummy, false, false, None, None, None, Some(n));
             }
-            Some(Command::Notebook(_)) => {
+            Some(Command::Notebook(_)) | Some(Command::Check(_)) => {
                 // Handled directly in fardrun.rs
                 let dummy = RunArgs {
                     program: std::path::PathBuf::from("."),
@@ -219,6 +332,16 @@ impl Cli {
                     enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                 };
                 return (dummy, false, false, None, None, None, None);
@@ -229,6 +352,9 @@ impl Cli {
                 // Store search query in env for fardrun.rs to handle
                 std::env::set_var("FARD_SEARCH_QUERY", &query);
                 std::env::set_var("FARD_SEARCH_MODE", "1");
+                if let Some(url) = &s.registry {
+                    std::env::set_var("FARD_SEARCH_REGISTRY", url);
+                }
                 let dummy = RunArgs {
                     program: PathBuf::from("."),
                     out: PathBuf::from("."),
@@ -237,6 +363,16 @@ impl Cli {
                     enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                 };
                 return (dummy, false, false, None, None, None, None);
@@ -251,12 +387,23 @@ impl Cli {
                         enforce_lockfile: false,
                     no_trace: false,
                     strict_types: false,
+                    vm_stats: false,
+                    receipts: vec![],
+                    receipt_cache: None,
+                    sign_key: None,
+                    stdlib_roots: None,
+                    http_fixtures: None,
+                    http_capture: false,
+                    record: false,
+                    replay: None,
+                    policy: None,
                     program_args: vec![],
                     };
                     return (dummy, false, true, None, None, None, None);
                 }
                 eprintln!("usage: fardrun run --program <file.fard> --out <dir>");
                 eprintln!("       fardrun test --program <file.fard>");
+                eprintln!("       fardrun check --program <file.fard>");
                 eprintln!("       fardrun repl");
                 eprintln!("       fardrun --version");
                 std::process::exit(0);
@@ -274,19 +421,30 @@ impl Cli {
             let mut input = std::path::PathBuf::from("notebook.fardnb.md");
             let mut output: Option<std::path::PathBuf> = None;
             let mut out_dir = "./notebook_out".to_string();
+            let mut force = false;
             let mut i = 2;
             while i < args.len() {
                 match args[i].as_str() {
                     "--input" => { i += 1; if i < args.len() { input = std::path::PathBuf::from(&args[i]); } }
                     "--output" => { i += 1; if i < args.len() { output = Some(std::path::PathBuf::from(&args[i])); } }
                     "--out-dir" => { i += 1; if i < args.len() { out_dir = args[i].clone(); } }
+                    "--force" => { force = true; }
                     _ => {}
                 }
                 i += 1;
             }
-            Some(Command::Notebook(NotebookArgs { input, output, out_dir }))
+            Some(Command::Notebook(NotebookArgs { input, output, out_dir, force }))
         } else {
             None
         }
     }
 }
+
+impl Cli {
+    pub fn parse_compat_check() -> Option<CheckArgs> {
+        match Cli::try_parse_from(std::env::args_os()) {
+            Ok(Cli { cmd: Some(Command::Check(c)), .. }) => Some(c),
+            _ => None,
+        }
+    }
+}
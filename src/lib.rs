@@ -43,7 +43,7 @@ pub fn load_config(path: &Path) -> Result<Config> {
 }
 
 fn parse_config(s: &str) -> Result<Config> {
-    let mut section = "";
+    let mut section = String::new();
     let mut runner_cmd: Vec<String> = vec![];
     let mut runner_args: Vec<String> = vec![];
     let mut trace_relpath = String::new();
@@ -53,18 +53,15 @@ fn parse_config(s: &str) -> Result<Config> {
     let mut require_result_file = true;
     let mut cg1 = true;
 
-    for line in s.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') { continue; }
-        if line.starts_with('[') && line.ends_with(']') {
-            section = &line[1..line.len()-1];
-            continue;
-        }
-        let (key, val) = match line.split_once('=') {
-            Some((k, v)) => (k.trim(), v.trim()),
-            None => continue,
+    for (_, line) in toml_lines(s)? {
+        let (key, val) = match &line {
+            TomlLine::Table(name) | TomlLine::ArrayTable(name) => {
+                section = name.clone();
+                continue;
+            }
+            TomlLine::Pair(k, v) => (k.as_str(), v.as_str()),
         };
-        match (section, key) {
+        match (section.as_str(), key) {
             ("runner", "cmd")  => runner_cmd  = parse_toml_str_array(val)?,
             ("runner", "args") => runner_args = parse_toml_str_array(val)?,
             ("artifacts", "trace_relpath")  => trace_relpath  = parse_toml_str(val)?,
@@ -84,16 +81,167 @@ fn parse_config(s: &str) -> Result<Config> {
     })
 }
 
-fn parse_toml_str(s: &str) -> Result<String> {
+/// One line of a TOML file as the config readers see it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TomlLine {
+    /// `[name]`
+    Table(String),
+    /// `[[name]]`
+    ArrayTable(String),
+    /// `key = value`: the unquoted key and the raw text of the value
+    Pair(String, String),
+}
+
+/// Split `src` into table headers and `key = value` pairs, each with its 1-based
+/// line number. `#` comments are dropped outside strings, keys may be quoted, and
+/// an array may span several lines; its value is returned joined onto one line.
+/// This is the TOML subset every fard config file uses.
+pub fn toml_lines(src: &str) -> Result<Vec<(usize, TomlLine)>> {
+    let mut out = Vec::new();
+    let mut lines = src.lines().enumerate();
+    while let Some((i, raw)) = lines.next() {
+        let n = i + 1;
+        let line = toml_strip_comment(raw).trim();
+        if line.is_empty() { continue; }
+        if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            out.push((n, TomlLine::ArrayTable(name.trim().to_string())));
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            out.push((n, TomlLine::Table(name.trim().to_string())));
+            continue;
+        }
+        let (key, rest) = if line.starts_with(['"', '\'']) {
+            let (k, len) = toml_scan_string(line).with_context(|| format!("line {}", n))?;
+            (k, &line[len..])
+        } else {
+            let end = line.find('=').unwrap_or(line.len());
+            let k = line[..end].trim();
+            if k.is_empty() || !k.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+                bail!("line {}: expected key = value, got: {}", n, line);
+            }
+            (k.to_string(), &line[end..])
+        };
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            bail!("line {}: expected key = value, got: {}", n, line);
+        };
+        let mut value = value.trim().to_string();
+        while toml_open_brackets(&value) > 0 {
+            let Some((_, more)) = lines.next() else { bail!("line {}: unterminated array for {}", n, key) };
+            value.push(' ');
+            value.push_str(toml_strip_comment(more).trim());
+        }
+        if value.is_empty() { bail!("line {}: missing value for {}", n, key); }
+        out.push((n, TomlLine::Pair(key, value)));
+    }
+    Ok(out)
+}
+
+/// `line` up to a `#` that is not inside a string.
+fn toml_strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// `[` minus `]` outside strings, so a caller knows an array continues on the next line.
+fn toml_open_brackets(value: &str) -> i64 {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in value.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// Decode the basic (`"…"`) or literal (`'…'`) string at the start of `s`; returns
+/// the text and the number of bytes it spans.
+fn toml_scan_string(s: &str) -> Result<(String, usize)> {
+    if let Some(body) = s.strip_prefix('\'') {
+        let end = body.find('\'').ok_or_else(|| anyhow!("unterminated string: {}", s))?;
+        return Ok((body[..end].to_string(), end + 2));
+    }
+    let body = s.strip_prefix('"').ok_or_else(|| anyhow!("expected quoted string, got: {}", s))?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, i + 2)),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('b') => out.push('\u{8}'),
+                Some('f') => out.push('\u{c}'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some(u @ ('u' | 'U')) => {
+                    let len = if u == 'u' { 4 } else { 8 };
+                    let hex: String = (0..len).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                    let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                        .ok_or_else(|| anyhow!("bad unicode escape \\{}{}", u, hex))?;
+                    out.push(c);
+                }
+                other => bail!("bad escape \\{}", other.map(String::from).unwrap_or_default()),
+            },
+            c => out.push(c),
+        }
+    }
+    bail!("unterminated string: {}", s)
+}
+
+/// `s` as a TOML basic string, the inverse of [`parse_toml_str`].
+pub fn toml_quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn parse_toml_str(s: &str) -> Result<String> {
     let s = s.trim();
-    if s.starts_with('"') && s.ends_with('"') {
-        Ok(s[1..s.len()-1].to_string())
-    } else {
-        bail!("expected quoted string, got: {}", s)
+    let (text, len) = toml_scan_string(s)?;
+    if !s[len..].trim().is_empty() {
+        bail!("expected quoted string, got: {}", s);
     }
+    Ok(text)
 }
 
-fn parse_toml_bool(s: &str) -> Result<bool> {
+pub fn parse_toml_int(s: &str) -> Result<i64> {
+    let s = s.trim();
+    s.replace('_', "").parse().map_err(|_| anyhow!("expected int, got: {}", s))
+}
+
+pub fn parse_toml_bool(s: &str) -> Result<bool> {
     match s.trim() {
         "true"  => Ok(true),
         "false" => Ok(false),
@@ -101,19 +249,36 @@ fn parse_toml_bool(s: &str) -> Result<bool> {
     }
 }
 
-fn parse_toml_str_array(s: &str) -> Result<Vec<String>> {
+/// An array of scalars, each read by `item`; a trailing comma is allowed.
+pub fn parse_toml_array<T>(s: &str, item: impl Fn(&str) -> Result<T>) -> Result<Vec<T>> {
     let s = s.trim();
-    if !s.starts_with('[') || !s.ends_with(']') {
-        bail!("expected array, got: {}", s);
-    }
-    let inner = &s[1..s.len()-1];
+    let Some(mut rest) = s.strip_prefix('[') else { bail!("expected array, got: {}", s) };
     let mut out = vec![];
-    for part in inner.split(',') {
-        let part = part.trim();
-        if part.is_empty() { continue; }
-        out.push(parse_toml_str(part)?);
+    loop {
+        rest = rest.trim_start();
+        if let Some(tail) = rest.strip_prefix(']') {
+            if !tail.trim().is_empty() { bail!("expected array, got: {}", s); }
+            return Ok(out);
+        }
+        let len = if rest.starts_with(['"', '\'']) {
+            toml_scan_string(rest)?.1
+        } else {
+            rest.find([',', ']']).ok_or_else(|| anyhow!("unterminated array: {}", s))?
+        };
+        let part = rest[..len].trim();
+        if part.is_empty() || part.starts_with('[') { bail!("expected array of scalars, got: {}", s); }
+        out.push(item(part)?);
+        rest = rest[len..].trim_start();
+        if let Some(tail) = rest.strip_prefix(',') {
+            rest = tail;
+        } else if !rest.starts_with(']') {
+            bail!("expected , or ] in array: {}", s);
+        }
     }
-    Ok(out)
+}
+
+pub fn parse_toml_str_array(s: &str) -> Result<Vec<String>> {
+    parse_toml_array(s, parse_toml_str)
 }
 
 #[derive(Debug, Clone)]
@@ -366,110 +531,234 @@ pub fn matches_any_regex(text: &str, patterns: &[String]) -> Result<bool> {
     Ok(false)
 }
 
+pub mod build;
 pub mod cli;
 pub mod digest;
+pub mod receipt_store;
+pub mod registry;
+pub mod signing;
+
+#[path = "verify/trace_verify.rs"]
+pub mod trace_verify;
+#[path = "verify/artifact_verify.rs"]
+pub mod artifact_verify;
 
 pub mod gates;
 
-/// Parse a FARD source string and return a list of error messages with line/col.
-/// Used by fard-lsp for diagnostics.
-/// Run the HM type checker and return errors as (line, col, message) triples.
-/// Used by --strict-types to abort execution on type errors.
-/// Calls fardcheck binary as subprocess.
-pub fn type_check_strict(source: &str, filename: &str) -> Vec<(u32, u32, String)> {
-    // Write source to temp file and run fardcheck
-    let tmp = std::env::temp_dir().join("fardcheck_strict_tmp.fard");
-    if std::fs::write(&tmp, source).is_err() { return vec![]; }
-    // Find fardcheck next to current exe
-    let exe = std::env::current_exe().ok()
-        .and_then(|p| p.parent().map(|d| d.join("fardcheck")))
-        .unwrap_or_else(|| std::path::PathBuf::from("fardcheck"));
-    let out = std::process::Command::new(&exe)
-        .arg(tmp.to_str().unwrap_or(filename))
-        .output();
-    let _ = std::fs::remove_file(&tmp);
-    match out {
-        Err(_) => vec![],
-        Ok(output) => {
-            if output.status.success() { return vec![]; }
-            // Parse "TYPE ERROR line N: message" from stderr
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let mut errors = Vec::new();
-            for line in stderr.lines() {
-                if let Some(rest) = line.strip_prefix("TYPE ERROR line ") {
-                    if let Some((lnum, msg)) = rest.split_once(": ") {
-                        let ln: u32 = lnum.trim().parse().unwrap_or(0);
-                        errors.push((ln, 0u32, msg.to_string()));
-                    }
-                }
+/// A problem found in a FARD source, for editor diagnostics.
+/// Lines and columns are 0-based; columns count chars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceDiagnostic {
+    pub line: u32,
+    pub col: u32,
+    pub end_line: u32,
+    pub end_col: u32,
+    pub code: String,
+    pub message: String,
+}
+
+/// Locate a sibling tool binary: next to the current exe (or one level up, for
+/// test harnesses under target/*/deps), else rely on PATH.
+fn sibling_exe(name: &str) -> PathBuf {
+    if let Some(dir) = std::env::current_exe().ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+        for d in [Some(dir.as_path()), dir.parent()].into_iter().flatten() {
+            let p = d.join(name);
+            if p.is_file() {
+                return p;
             }
-            errors
         }
     }
+    PathBuf::from(name)
+}
+
+/// A fresh temp path for handing an unsaved buffer to a tool.
+fn scratch_fard(tag: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static SEQ: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "fard_{}_{}_{}.fard",
+        tag,
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    ))
 }
 
-pub fn parse_check(source: &str, filename: &str) -> Vec<(u32, u32, String)> {
-    // We shell out to the binary parser indirectly by re-using the run_fard path.
-    // For now, use a subprocess approach: write to temp file and run fardrun --parse-only.
-    // This avoids needing to expose the internal Parser struct.
-    let mut errors = Vec::new();
-    let tmp = std::env::temp_dir().join("fard_lsp_check.fard");
-    if std::fs::write(&tmp, source).is_err() {
-        return errors;
-    }
-    let fardrun = std::env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|d| d.join("fardrun")))
-        .unwrap_or_else(|| std::path::PathBuf::from("fardrun"));
-    let out_tmp = std::env::temp_dir().join("fard_lsp_check_out");
-    let result = std::process::Command::new(&fardrun)
-        .args(["run", "--program", &tmp.to_string_lossy(), "--out", &out_tmp.to_string_lossy()])
+/// Parse (without running) a FARD source via `fardrun check` and return its
+/// parse diagnostics. Used by fard-lsp.
+pub fn check_source(source: &str) -> Vec<SourceDiagnostic> {
+    let tmp = scratch_fard("check");
+    if fs::write(&tmp, source).is_err() {
+        return vec![];
+    }
+    let out = Command::new(sibling_exe("fardrun"))
+        .arg("check")
+        .arg("--program")
+        .arg(&tmp)
         .output();
-    match result {
-        Ok(out) if !out.status.success() => {
-            let stderr = String::from_utf8_lossy(&out.stderr);
-            for line in stderr.lines() {
-                if line.contains("ERROR_PARSE") || line.contains("parse error") || line.contains("ERROR_EVAL") {
-                    let (l, c) = extract_lsp_pos(line);
-                    errors.push((l, c, line.to_string()));
-                }
-            }
-            if errors.is_empty() && !stderr.trim().is_empty() {
-                errors.push((0, 0, stderr.lines().last().unwrap_or("error").to_string()));
-            }
-        }
+    let _ = fs::remove_file(&tmp);
+    let out = match out {
+        Ok(o) => o,
         Err(e) => {
-            errors.push((0, 0, format!("fardrun not found: {}", e)));
+            return vec![SourceDiagnostic {
+                line: 0,
+                col: 0,
+                end_line: 0,
+                end_col: 1,
+                code: "ERROR_IO".to_string(),
+                message: format!("fardrun not found: {}", e),
+            }]
         }
-        _ => {}
+    };
+    let mut diags = Vec::new();
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        let Ok(v) = json_from_str(line) else { continue };
+        let num = |k: &str| {
+            v.get("span")
+                .and_then(|s| s.get(k))
+                .and_then(|n| n.as_i64())
+                .unwrap_or(1)
+                .max(1) as u32
+                - 1
+        };
+        let text = |k: &str| v.get(k).and_then(|x| x.as_str()).unwrap_or("").to_string();
+        diags.push(SourceDiagnostic {
+            line: num("line"),
+            col: num("col"),
+            end_line: num("end_line"),
+            end_col: num("end_col"),
+            code: text("code"),
+            message: text("message"),
+        });
+    }
+    diags
+}
+
+/// A type error reported by fardcheck; 1-based position in `file`, which is the checked
+/// program or one of the modules it imports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDiagnostic {
+    pub file: String,
+    pub line: u32,
+    pub col: u32,
+    pub message: String,
+}
+
+/// Type-check a program file and every module it imports with fardcheck.
+/// Used by `fardrun run --strict-types`; errors if fardcheck cannot be run or fails
+/// without reporting a type error.
+pub fn type_check_file(program: &Path, registry: Option<&Path>) -> Result<Vec<TypeDiagnostic>> {
+    let mut cmd = Command::new(sibling_exe("fardcheck"));
+    cmd.arg(program);
+    if let Some(reg) = registry {
+        cmd.arg("--registry").arg(reg);
     }
-    let _ = std::fs::remove_file(&tmp);
-    let _ = std::fs::remove_dir_all(&out_tmp);
-    errors
+    run_fardcheck(cmd)
 }
 
-fn extract_lsp_pos(msg: &str) -> (u32, u32) {
-    let mut line = 0u32;
-    let mut col = 0u32;
-    if let Some(idx) = msg.find("line ") {
-        let rest = &msg[idx + 5..];
-        if let Some(n) = rest.split_whitespace().next().and_then(|s| s.parse::<u32>().ok()) {
-            line = n.saturating_sub(1);
-        }
+fn run_fardcheck(mut cmd: Command) -> Result<Vec<TypeDiagnostic>> {
+    let output = cmd
+        .output()
+        .with_context(|| format!("cannot run {}", Path::new(cmd.get_program()).display()))?;
+    if output.status.success() {
+        return Ok(vec![]);
     }
-    if let Some(idx) = msg.find("col ") {
-        let rest = &msg[idx + 4..];
-        if let Some(n) = rest.split_whitespace().next().and_then(|s| s.parse::<u32>().ok()) {
-            col = n.saturating_sub(1);
-        }
+    // "TYPE ERROR file:line:col: message"
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut errors = Vec::new();
+    for line in stderr.lines() {
+        let Some(rest) = line.strip_prefix("TYPE ERROR ") else { continue };
+        let Some((loc, msg)) = rest.split_once(": ") else { continue };
+        let mut parts = loc.rsplitn(3, ':');
+        let (Some(col), Some(ln), Some(file)) = (parts.next(), parts.next(), parts.next()) else { continue };
+        let (Ok(col), Ok(ln)) = (col.parse::<u32>(), ln.parse::<u32>()) else { continue };
+        errors.push(TypeDiagnostic { file: file.to_string(), line: ln, col, message: msg.to_string() });
+    }
+    if errors.is_empty() {
+        bail!("fardcheck failed ({}): {}", output.status, stderr.trim());
+    }
+    Ok(errors)
+}
+
+/// Run fardcheck over a FARD source and return the type errors located in it, each
+/// spanning from the reported column to the end of its line. `origin` is where the
+/// source lives on disk, so its relative imports resolve. Used by fard-lsp.
+pub fn type_check_source(source: &str, origin: Option<&Path>) -> Vec<SourceDiagnostic> {
+    let tmp = scratch_fard("typecheck");
+    if fs::write(&tmp, source).is_err() {
+        return vec![];
     }
-    // Also try :line:col format
-    let parts: Vec<&str> = msg.split(':').collect();
-    if parts.len() >= 3 {
-        if let (Ok(l), Ok(c)) = (parts[parts.len()-2].trim().parse::<u32>(), parts[parts.len()-1].trim().split_whitespace().next().unwrap_or("0").parse::<u32>()) {
-            if l > 0 { line = l - 1; }
-            if c > 0 { col = c - 1; }
+    let label = origin.map(Path::to_path_buf).unwrap_or_else(|| tmp.clone());
+    let mut cmd = Command::new(sibling_exe("fardcheck"));
+    cmd.arg(&tmp).arg("--as").arg(&label);
+    let found = run_fardcheck(cmd);
+    let _ = fs::remove_file(&tmp);
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => {
+            return vec![SourceDiagnostic {
+                line: 0,
+                col: 0,
+                end_line: 0,
+                end_col: 1,
+                code: "ERROR_IO".to_string(),
+                message: format!("{:#}", e),
+            }]
         }
+    };
+    let label = label.display().to_string();
+    let lines: Vec<&str> = source.lines().collect();
+    found
+        .into_iter()
+        .filter(|d| d.file == label)
+        .map(|d| {
+            let ln = d.line.saturating_sub(1);
+            let text = lines.get(ln as usize).copied().unwrap_or("");
+            let col = d.col.saturating_sub(1);
+            SourceDiagnostic {
+                line: ln,
+                col,
+                end_line: ln,
+                end_col: (text.chars().count() as u32).max(col + 1),
+                code: "TYPE_ERROR".to_string(),
+                message: d.message,
+            }
+        })
+        .collect()
+}
+
+/// Format a FARD source with `fardfmt --stdin`; None if fardfmt is unavailable or fails.
+pub fn format_source(source: &str) -> Option<String> {
+    run_fardfmt(source, &[])
+}
+
+/// Format only the top-level items touching 0-based lines `start..=end`; the rest of the
+/// source comes back unchanged. `None` when fardfmt is unavailable or the source does not parse.
+pub fn format_range_source(source: &str, start: u32, end: u32) -> Option<String> {
+    run_fardfmt(source, &["--range", &format!("{}:{}", start + 1, end.max(start) + 1)])
+}
+
+fn run_fardfmt(source: &str, args: &[&str]) -> Option<String> {
+    use std::io::Write;
+    let mut child = Command::new(sibling_exe("fardfmt"))
+        .arg("--stdin")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(source.as_bytes()).ok()?;
+    let out = child.wait_with_output().ok()?;
+    if !out.status.success() {
+        return None;
     }
-    (line, col)
+    String::from_utf8(out.stdout).ok()
+}
+
+/// Parse a FARD source string and return its parse errors as 0-based (line, col, message).
+pub fn parse_check(source: &str, _filename: &str) -> Vec<(u32, u32, String)> {
+    check_source(source)
+        .into_iter()
+        .map(|d| (d.line, d.col, d.message))
+        .collect()
 }
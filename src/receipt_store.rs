@@ -0,0 +1,415 @@
+//! Receipt store: where run receipts (`{run_id, output, derived_from, preimage}`) live.
+//!
+//! `witness.verify`, `witness.verify_chain`, `graph.*`, `artifact` imports and
+//! `fardverify chain` all resolve run IDs through a [`ReceiptStore`]: an ordered
+//! list of backends plus a local cache for receipts fetched from remote ones.
+//!
+//! Backends are named by a spec string:
+//!
+//! - `dir:<path>` or a bare path — `<path>/sha256_<hex>.json`; run bundles in `<path>/<hex>/`
+//! - `sqlite:<path>` — the `receipts` table of a `fardregistry` database
+//! - `http://…` / `https://…` — a `fardregistry` server (`GET /receipt/<id>`, `POST /publish`)
+//!
+//! Configured by `--receipts` (repeatable) and `--receipt-cache`, or in `fard.toml`:
+//!
+//! ```toml
+//! [receipts]
+//! store = ["dir:receipts", "https://registry.example.com"]
+//! cache = ".fard/receipt-cache"
+//! ```
+//!
+//! With neither, the store is `dir:receipts` relative to the working directory,
+//! followed by `FARD_REGISTRY_URL` when that is set.
+//!
+//! Receipts carry the run's digest `preimage`, so [`verify_receipt`] can recompute a
+//! run ID from the receipt alone.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{parse_toml_str, parse_toml_str_array, toml_lines, TomlLine};
+
+/// Schema of the `receipts` table, shared with `fardregistry`.
+pub const RECEIPTS_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS receipts (
+        run_id       TEXT PRIMARY KEY,
+        raw_json     TEXT NOT NULL,
+        published_at INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+    );
+";
+
+fn run_hex(run_id: &str) -> &str {
+    run_id.strip_prefix("sha256:").unwrap_or(run_id)
+}
+
+/// A receipt whose run ID was recomputed from its own content.
+#[derive(Debug, Clone)]
+pub struct VerifiedReceipt {
+    pub run_id: String,
+    pub derived_from: Vec<String>,
+    /// Canonical JSON of the receipt; identical content always yields identical bytes.
+    pub canonical: Vec<u8>,
+}
+
+/// Top-level receipt fields. All but `signature`, which is checked against its own key,
+/// are bound by the run ID.
+const RECEIPT_FIELDS: &[&str] = &["derived_from", "output", "package", "preimage", "run_id", "signature"];
+
+/// Canonicalize a receipt and check that its `run_id` is the digest of its `preimage`
+/// (the `digests.json` of the run, less `preimage_sha256`), that the preimage commits
+/// to `output` as the run's `result.json` and to the receipt's `derived_from` and
+/// `package`, and that it has no other fields. Errors start with `RECEIPT_`.
+pub fn verify_receipt(bytes: &[u8]) -> Result<VerifiedReceipt> {
+    use valuecore::json::{from_slice, to_string, JsonVal};
+    let v = from_slice(bytes).map_err(|e| anyhow!("RECEIPT_MALFORMED {}", e))?;
+    let fields = v.as_object().ok_or_else(|| anyhow!("RECEIPT_MALFORMED receipt must be an object"))?;
+    if let Some(k) = fields.keys().find(|k| !RECEIPT_FIELDS.contains(&k.as_str())) {
+        bail!("RECEIPT_UNKNOWN_FIELD {} is not covered by the run ID", k);
+    }
+    let run_id = v
+        .get("run_id")
+        .and_then(|x| x.as_str())
+        .filter(|id| id.starts_with("sha256:"))
+        .ok_or_else(|| anyhow!("RECEIPT_MALFORMED run_id must be a sha256: digest"))?
+        .to_string();
+    let derived_from = v
+        .get("derived_from")
+        .and_then(|x| x.as_array())
+        .ok_or_else(|| anyhow!("RECEIPT_MALFORMED missing derived_from"))?
+        .iter()
+        .map(|d| d.as_str().filter(|d| d.starts_with("sha256:")).map(str::to_string))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow!("RECEIPT_MALFORMED derived_from must list sha256: run IDs"))?;
+    let output = v.get("output").ok_or_else(|| anyhow!("RECEIPT_MALFORMED missing output"))?;
+    let preimage = v
+        .get("preimage")
+        .filter(|p| p.as_object().is_some())
+        .ok_or_else(|| anyhow!("RECEIPT_MALFORMED missing preimage; republish with a current fardrun"))?;
+
+    let computed = format!("sha256:{}", crate::sha256_hex(to_string(preimage).as_bytes()));
+    if computed != run_id {
+        bail!("RECEIPT_RUN_ID_MISMATCH run_id={} computed={}", run_id, computed);
+    }
+    // Runs without parents or a package leave those out of the preimage.
+    let bound_parents: Vec<&str> = match preimage.get("derived_from") {
+        None => vec![],
+        Some(ids) => ids
+            .as_array()
+            .and_then(|ids| ids.iter().map(|d| d.as_str()).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| anyhow!("RECEIPT_MALFORMED preimage derived_from must list run IDs"))?,
+    };
+    if bound_parents != derived_from.iter().map(String::as_str).collect::<Vec<_>>() {
+        bail!("RECEIPT_LINEAGE_MISMATCH derived_from differs from the one {} commits to", run_id);
+    }
+    let package = v.get("package");
+    if package.is_some() && package.and_then(|p| p.as_str()).is_none() {
+        bail!("RECEIPT_MALFORMED package must be text");
+    }
+    if package != preimage.get("package") {
+        bail!("RECEIPT_PACKAGE_MISMATCH package differs from the one {} commits to", run_id);
+    }
+    if preimage.get("ok").and_then(|x| x.as_bool()) != Some(true) {
+        bail!("RECEIPT_MALFORMED preimage is not of a successful run");
+    }
+    let committed = preimage
+        .get("files")
+        .and_then(|f| f.get("result.json"))
+        .and_then(|x| x.as_str())
+        .ok_or_else(|| anyhow!("RECEIPT_MALFORMED preimage has no result.json digest"))?;
+    // result.json is `{"result": output}`; runs that read their own digest hash it with
+    // the run ID written as `sha256:self`.
+    let result = to_string(&JsonVal::Object([("result".to_string(), output.clone())].into_iter().collect()));
+    let self_normalized = result.replace(&run_id, "sha256:self");
+    let matches = |text: &str| format!("sha256:{}", crate::sha256_hex(text.as_bytes())) == committed;
+    if !matches(&result) && !matches(&self_normalized) {
+        bail!("RECEIPT_OUTPUT_MISMATCH output does not hash to {}", committed);
+    }
+    Ok(VerifiedReceipt { run_id, derived_from, canonical: to_string(&v).into_bytes() })
+}
+
+/// Whether `bytes` is a receipt whose preimage hashes to `run_id`.
+fn receipt_checks_out(bytes: &[u8], run_id: &str) -> bool {
+    verify_receipt(bytes).is_ok_and(|r| r.run_id == run_id)
+}
+
+/// The `run_id` a receipt claims, if it parses.
+pub fn receipt_run_id(bytes: &[u8]) -> Option<String> {
+    let v: serde_json::Value = serde_json::from_slice(bytes).ok()?;
+    v.get("run_id")?.as_str().map(str::to_string)
+}
+
+pub trait ReceiptBackend: Send + Sync {
+    /// The spec this backend was built from, for messages.
+    fn describe(&self) -> String;
+    /// Raw receipt bytes for `run_id`, or `None` when this backend does not have it.
+    fn get(&self, run_id: &str) -> Result<Option<Vec<u8>>>;
+    fn put(&self, run_id: &str, receipt: &[u8]) -> Result<()>;
+    /// Output directory of the run whose trace digest is `run_digest`, if stored here.
+    fn bundle(&self, _run_digest: &str) -> Option<PathBuf> {
+        None
+    }
+    /// Whether receipts from this backend are worth keeping in the local cache.
+    fn is_remote(&self) -> bool {
+        false
+    }
+}
+
+pub struct DirBackend {
+    pub root: PathBuf,
+}
+
+impl DirBackend {
+    fn path(&self, run_id: &str) -> PathBuf {
+        self.root.join(format!("sha256_{}.json", run_hex(run_id)))
+    }
+}
+
+impl ReceiptBackend for DirBackend {
+    fn describe(&self) -> String {
+        format!("dir:{}", self.root.display())
+    }
+
+    fn get(&self, run_id: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(run_id)) {
+            Ok(b) => Ok(Some(b)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("read {}", self.path(run_id).display())),
+        }
+    }
+
+    fn put(&self, run_id: &str, receipt: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.root).with_context(|| format!("create {}", self.root.display()))?;
+        std::fs::write(self.path(run_id), receipt).with_context(|| format!("write {}", self.path(run_id).display()))
+    }
+
+    fn bundle(&self, run_digest: &str) -> Option<PathBuf> {
+        let p = self.root.join(run_hex(run_digest));
+        p.is_dir().then_some(p)
+    }
+}
+
+pub struct SqliteBackend {
+    pub path: PathBuf,
+}
+
+impl SqliteBackend {
+    fn open(&self) -> Result<rusqlite::Connection> {
+        let conn = rusqlite::Connection::open(&self.path)
+            .with_context(|| format!("open {}", self.path.display()))?;
+        conn.execute_batch(RECEIPTS_SCHEMA)?;
+        Ok(conn)
+    }
+}
+
+impl ReceiptBackend for SqliteBackend {
+    fn describe(&self) -> String {
+        format!("sqlite:{}", self.path.display())
+    }
+
+    fn get(&self, run_id: &str) -> Result<Option<Vec<u8>>> {
+        use rusqlite::OptionalExtension;
+        let raw: Option<String> = self
+            .open()?
+            .query_row("SELECT raw_json FROM receipts WHERE run_id = ?1", [run_id], |r| r.get(0))
+            .optional()?;
+        Ok(raw.map(String::into_bytes))
+    }
+
+    fn put(&self, run_id: &str, receipt: &[u8]) -> Result<()> {
+        let raw = std::str::from_utf8(receipt).context("receipt is not UTF-8")?;
+        self.open()?.execute(
+            "INSERT OR REPLACE INTO receipts (run_id, raw_json) VALUES (?1, ?2)",
+            rusqlite::params![run_id, raw],
+        )?;
+        Ok(())
+    }
+}
+
+pub struct HttpBackend {
+    pub base: String,
+}
+
+impl ReceiptBackend for HttpBackend {
+    fn describe(&self) -> String {
+        self.base.clone()
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    fn get(&self, run_id: &str) -> Result<Option<Vec<u8>>> {
+        let url = format!("{}/receipt/{}", self.base.trim_end_matches('/'), run_id);
+        match ureq::get(&url).call() {
+            Ok(resp) => Ok(Some(resp.into_string()?.into_bytes())),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(anyhow!("GET {}: {}", url, e)),
+        }
+    }
+
+    fn put(&self, _run_id: &str, receipt: &[u8]) -> Result<()> {
+        let url = format!("{}/publish", self.base.trim_end_matches('/'));
+        let body = std::str::from_utf8(receipt).context("receipt is not UTF-8")?;
+        ureq::post(&url)
+            .set("Content-Type", "application/json")
+            .send_string(body)
+            .map_err(|e| anyhow!("POST {}: {}", url, e))?;
+        Ok(())
+    }
+}
+
+/// Build a backend from its spec; relative paths are taken from `base`.
+pub fn backend(spec: &str, base: &Path) -> Result<Box<dyn ReceiptBackend>> {
+    let spec = spec.trim();
+    if spec.starts_with("http://") || spec.starts_with("https://") {
+        return Ok(Box::new(HttpBackend { base: spec.to_string() }));
+    }
+    if let Some(p) = spec.strip_prefix("sqlite:") {
+        return Ok(Box::new(SqliteBackend { path: base.join(p) }));
+    }
+    let p = spec.strip_prefix("dir:").unwrap_or(spec);
+    if p.is_empty() {
+        bail!("ERROR_RECEIPTS empty receipt store spec");
+    }
+    Ok(Box::new(DirBackend { root: base.join(p) }))
+}
+
+pub struct ReceiptStore {
+    backends: Vec<Box<dyn ReceiptBackend>>,
+    cache: Option<DirBackend>,
+}
+
+impl ReceiptStore {
+    pub fn new(backends: Vec<Box<dyn ReceiptBackend>>, cache: Option<PathBuf>) -> ReceiptStore {
+        ReceiptStore { backends, cache: cache.map(|root| DirBackend { root }) }
+    }
+
+    /// Resolve the store from CLI flags, falling back to `[receipts]` in `fard_toml`,
+    /// then to the `receipts/` directory and `FARD_REGISTRY_URL`.
+    pub fn configure(specs: &[String], cache: Option<&Path>, fard_toml: Option<&Path>) -> Result<ReceiptStore> {
+        let cwd = Path::new("");
+        let mut backends = Vec::new();
+        for s in specs {
+            backends.push(backend(s, cwd)?);
+        }
+        let mut cache = cache.map(Path::to_path_buf);
+        if let Some(toml) = fard_toml {
+            if let Ok(src) = std::fs::read_to_string(toml) {
+                let base = toml.parent().unwrap_or(cwd);
+                let (toml_specs, toml_cache) = parse_receipts_section(&src)
+                    .with_context(|| format!("in {}", toml.display()))?;
+                if backends.is_empty() {
+                    for s in toml_specs {
+                        backends.push(backend(&s, base)?);
+                    }
+                }
+                if cache.is_none() {
+                    cache = toml_cache.map(|c| base.join(c));
+                }
+            }
+        }
+        if backends.is_empty() {
+            backends.push(backend("dir:receipts", cwd)?);
+            if let Ok(url) = std::env::var("FARD_REGISTRY_URL") {
+                backends.push(backend(&url, cwd)?);
+            }
+        }
+        let cache = cache.or_else(|| std::env::var("HOME").ok().map(|h| Path::new(&h).join(".fard/cache/receipts")));
+        Ok(ReceiptStore::new(backends, cache))
+    }
+
+    pub fn describe(&self) -> String {
+        self.backends.iter().map(|b| b.describe()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Raw receipt bytes for `run_id` from the first backend that has it. Receipts
+    /// fetched from a remote backend are kept in the cache only once [`verify_receipt`]
+    /// recomputes `run_id` from their preimage, and the cache is consulted before any
+    /// remote backend is asked.
+    pub fn get(&self, run_id: &str) -> Result<Option<Vec<u8>>> {
+        let mut first_err = None;
+        let mut cache_checked = false;
+        for b in &self.backends {
+            if b.is_remote() && !cache_checked {
+                cache_checked = true;
+                if let Some(bytes) = self.cached(run_id) {
+                    return Ok(Some(bytes));
+                }
+            }
+            match b.get(run_id) {
+                Ok(Some(bytes)) => {
+                    if b.is_remote() && receipt_checks_out(&bytes, run_id) {
+                        if let Some(c) = &self.cache {
+                            let _ = c.put(run_id, &bytes);
+                        }
+                    }
+                    return Ok(Some(bytes));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    first_err.get_or_insert(e.context(format!("receipt store {}", b.describe())));
+                }
+            }
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// A cached receipt for `run_id`; an entry that does not verify is dropped.
+    fn cached(&self, run_id: &str) -> Option<Vec<u8>> {
+        let cache = self.cache.as_ref()?;
+        let bytes = cache.get(run_id).ok()??;
+        if receipt_checks_out(&bytes, run_id) {
+            return Some(bytes);
+        }
+        let _ = std::fs::remove_file(cache.path(run_id));
+        None
+    }
+
+    /// Publish a receipt to the first backend.
+    pub fn put(&self, run_id: &str, receipt: &[u8]) -> Result<()> {
+        match self.backends.first() {
+            Some(b) => b.put(run_id, receipt),
+            None => bail!("ERROR_RECEIPTS no receipt store configured"),
+        }
+    }
+
+    /// Output directory of a stored run bundle, from the first backend that has it.
+    pub fn bundle(&self, run_digest: &str) -> Option<PathBuf> {
+        self.backends.iter().find_map(|b| b.bundle(run_digest))
+    }
+}
+
+/// `store` and `cache` from the `[receipts]` section of a `fard.toml`. `store` is one
+/// spec or a list of them.
+fn parse_receipts_section(src: &str) -> Result<(Vec<String>, Option<String>)> {
+    let mut specs = Vec::new();
+    let mut cache = None;
+    let mut in_section = false;
+    for (n, line) in toml_lines(src).map_err(|e| anyhow!("ERROR_RECEIPTS {}", e))? {
+        let (k, v) = match line {
+            TomlLine::Table(name) => {
+                in_section = name == "receipts";
+                continue;
+            }
+            TomlLine::ArrayTable(_) => {
+                in_section = false;
+                continue;
+            }
+            TomlLine::Pair(k, v) => (k, v),
+        };
+        if !in_section {
+            continue;
+        }
+        let bad = |e: anyhow::Error| anyhow!("ERROR_RECEIPTS line {}: {}: {}", n, k, e);
+        match k.as_str() {
+            "store" if v.starts_with('[') => specs = parse_toml_str_array(&v).map_err(bad)?,
+            "store" => specs = vec![parse_toml_str(&v).map_err(bad)?],
+            "cache" => cache = Some(parse_toml_str(&v).map_err(bad)?),
+            other => bail!("ERROR_RECEIPTS unknown key {} in [receipts]", other),
+        }
+    }
+    Ok((specs, cache))
+}
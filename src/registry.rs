@@ -0,0 +1,1132 @@
+//! The receipt registry served by `fardregistry` (SQLite-backed).
+//!
+//! Routes:
+//!   POST /publish              — body: receipt JSON → {ok: run_id} | {err: msg}
+//!   GET  /audit[/<run_id>]     — publish attempts, newest first
+//!   GET  /receipt/<run_id>     — fetch receipt by run_id
+//!   GET  /verify/<run_id>      — verify receipt chain recursively
+//!   GET  /stats                — {count: N, run_ids: [...]}
+//!   GET  /receipts             — receipts oldest first, paginated; filters: program, package, since, until
+//!   GET  /lineage/<run_id>     — ancestors (direction=up) or descendants (direction=down), to depth=N
+//!   GET  /packages             — list all packages
+//!   GET  /packages?limit=N     — newest version of each package, paginated
+//!   GET  /packages/<name>      — list versions of a package
+//!   GET  /search?q=<text>      — packages whose name or description contains text, paginated
+//!   POST /packages/publish     — publish a package entry
+//!   GET  /sync/range/<prefix>  — count and digest of the run IDs under a hex prefix, and of its 16 sub-ranges
+//!   GET  /sync/ids/<prefix>    — the run IDs under a hex prefix
+//!   GET  /crdt/state | /crdt/digest | /crdt/get/<kind>/<req_hex>
+//!   GET  /crdt/delta?replica=<url> — CRDT changes that replica has not acknowledged, with their `upto`
+//!   POST /crdt/propose | /crdt/merge
+//!   POST /crdt/ack             — body: {replica, upto}
+//!   GET  /health               — "ok"
+//!
+//! Receipts are immutable. Publish recomputes the run ID from the receipt's `preimage`
+//! (400 on mismatch), requires every `derived_from` parent to be published already
+//! (422), and answers 409 when a different receipt exists for the run ID; republishing
+//! identical content is a no-op. Every attempt lands in the `audit_log` table.
+//!
+//! With a trust policy (a `[signers]` section, see [`crate::signing`]), `POST /publish`
+//! answers 403 for receipts that are unsigned or signed by a key not trusted for their package.
+//!
+//! Paginated routes take `limit` (default 100, at most 1000) and `cursor`, and answer
+//! `{"<items>": [...], "next_cursor": ..}`; pass `next_cursor` back until it is null.
+//! A receipt's program digest is the `module_graph.json` digest in its preimage; `since`
+//! and `until` are Unix seconds of publication, `until` exclusive.
+//!
+//! Replication: every `sync_interval`, a registry reconciles with each peer. Receipts are
+//! compared as a Merkle trie over the hex digits of their run IDs — only sub-ranges whose
+//! digests differ are descended into, and only the run IDs missing on one side are
+//! transferred, through the same checks as `POST /publish`. The Inherit-Cert CRDT state
+//! (kept in the `crdt_certs` table) is exchanged as deltas: each side sends the other
+//! what changed since the other's last acknowledgement, from a delta log kept beside
+//! the state, and only a replica it has no record of is sent the whole state.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{Connection, params};
+use inherit_cert_crdt::{CertStore, InheritCertDelta, InheritCertState, EffectKey, RunID, SqliteCertStore};
+use crate::receipt_store::{verify_receipt, RECEIPTS_SCHEMA};
+use crate::signing::TrustPolicy;
+
+// ── JSON output ──────────────────────────────────────────────────────────────
+fn json_str(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c    => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn ok_json(inner: &str) -> String { format!("{{\"ok\":{}}}", inner) }
+fn err_json(msg: &str) -> String  { format!("{{\"err\":{}}}", json_str(msg)) }
+
+// ── Database ──────────────────────────────────────────────────────────────────
+struct Db { conn: Connection, certs: SqliteCertStore, trust: Option<TrustPolicy> }
+
+impl Db {
+    fn open(path: &std::path::Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+        conn.execute_batch(RECEIPTS_SCHEMA)?;
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS packages (
+                name         TEXT NOT NULL,
+                version      TEXT NOT NULL,
+                entry_digest TEXT NOT NULL,
+                tarball_url  TEXT NOT NULL,
+                published_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+                PRIMARY KEY (name, version)
+            );
+            CREATE TABLE IF NOT EXISTS receipt_index (
+                run_id       TEXT PRIMARY KEY,
+                program      TEXT,
+                package      TEXT,
+                published_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS receipt_index_time    ON receipt_index (published_at, run_id);
+            CREATE INDEX IF NOT EXISTS receipt_index_program ON receipt_index (program);
+            CREATE INDEX IF NOT EXISTS receipt_index_package ON receipt_index (package);
+            CREATE TABLE IF NOT EXISTS receipt_parents (
+                parent TEXT NOT NULL,
+                child  TEXT NOT NULL,
+                PRIMARY KEY (parent, child)
+            );
+            CREATE INDEX IF NOT EXISTS receipt_parents_child ON receipt_parents (child);
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id             INTEGER PRIMARY KEY AUTOINCREMENT,
+                run_id         TEXT,
+                receipt_sha256 TEXT NOT NULL,
+                publisher      TEXT NOT NULL,
+                signer         TEXT,
+                status         INTEGER NOT NULL,
+                detail         TEXT NOT NULL,
+                at             INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+            );
+        ")?;
+        let has_description: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('packages') WHERE name = 'description'", [], |r| r.get(0))?;
+        if !has_description {
+            conn.execute_batch("ALTER TABLE packages ADD COLUMN description TEXT NOT NULL DEFAULT ''")?;
+        }
+        // Receipts written before the index existed, or by `fardrun --receipts sqlite:`.
+        let unindexed: Vec<(String, String)> = conn.prepare(
+            "SELECT run_id, raw_json FROM receipts WHERE run_id NOT IN (SELECT run_id FROM receipt_index)")?
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .filter_map(|x| x.ok())
+            .collect();
+        for (run_id, raw) in &unindexed {
+            index_receipt(&conn, run_id, raw)?;
+        }
+        let certs = SqliteCertStore::from_connection(Connection::open(path)?).map_err(|e| anyhow!(e))?;
+        Ok(Db { conn, certs, trust: None })
+    }
+
+    /// Publish a receipt: status and run ID, or status and reason. Recorded in the audit log.
+    fn publish_receipt(&mut self, raw: &str, publisher: &str) -> std::result::Result<String, (u16, String)> {
+        let outcome = self.try_publish(raw);
+        let v = serde_json::from_str::<serde_json::Value>(raw).ok();
+        let run_id = v.as_ref().and_then(|v| v.get("run_id")).and_then(|x| x.as_str()).map(str::to_string);
+        let signer = v.as_ref().and_then(|v| v.pointer("/signature/key")).and_then(|k| k.as_str()).map(str::to_string);
+        let (status, detail) = match &outcome {
+            Ok(msg) => (200, msg.clone()),
+            Err((code, msg)) => (*code, msg.clone()),
+        };
+        let _ = self.conn.execute(
+            "INSERT INTO audit_log (run_id, receipt_sha256, publisher, signer, status, detail) VALUES (?1,?2,?3,?4,?5,?6)",
+            params![run_id, sha256_hex(raw.as_bytes()), publisher, signer, status, detail],
+        );
+        outcome.map(|_| run_id.unwrap_or_default())
+    }
+
+    fn try_publish(&mut self, raw: &str) -> std::result::Result<String, (u16, String)> {
+        if let Err(e) = self.check_trust(raw) {
+            return Err((403, format!("{:#}", e)));
+        }
+        let receipt = verify_receipt(raw.as_bytes()).map_err(|e| (400, format!("{:#}", e)))?;
+        let canonical = String::from_utf8(receipt.canonical).map_err(|e| (400, e.to_string()))?;
+        if let Some(existing) = self.get_receipt(&receipt.run_id) {
+            if existing == canonical {
+                return Ok("unchanged".to_string());
+            }
+            return Err((409, format!("RECEIPT_CONFLICT {} is already published with different content", receipt.run_id)));
+        }
+        let missing: Vec<&str> = receipt.derived_from.iter()
+            .filter(|d| self.get_receipt(d).is_none())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err((422, format!("RECEIPT_MISSING_PARENT {}", missing.join(" "))));
+        }
+        self.store_receipt(&receipt.run_id, &canonical).map_err(|e| (500, e.to_string()))?;
+        Ok("created".to_string())
+    }
+
+    /// Insert a verified receipt and its index rows in one transaction.
+    fn store_receipt(&mut self, run_id: &str, canonical: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("INSERT INTO receipts (run_id, raw_json) VALUES (?1, ?2)", params![run_id, canonical])?;
+        index_receipt(&tx, run_id, canonical)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn audit(&self, run_id: Option<&str>) -> Result<String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT run_id, receipt_sha256, publisher, signer, status, detail, at FROM audit_log
+             WHERE ?1 IS NULL OR run_id = ?1 ORDER BY id DESC LIMIT 100")?;
+        let rows: Vec<String> = stmt.query_map(params![run_id], |r| {
+            let opt = |s: Option<String>| s.map(|s| json_str(&s)).unwrap_or_else(|| "null".to_string());
+            Ok(format!("{{\"run_id\":{},\"receipt_sha256\":{},\"publisher\":{},\"signer\":{},\"status\":{},\"detail\":{},\"at\":{}}}",
+                opt(r.get(0)?),
+                json_str(&r.get::<_,String>(1)?),
+                json_str(&r.get::<_,String>(2)?),
+                opt(r.get(3)?),
+                r.get::<_,i64>(4)?,
+                json_str(&r.get::<_,String>(5)?),
+                r.get::<_,i64>(6)?))
+        })?.filter_map(|x| x.ok()).collect();
+        Ok(format!("[{}]", rows.join(",")))
+    }
+
+    /// Under a trust policy, reject receipts that are unsigned or signed by an untrusted key.
+    fn check_trust(&self, raw: &str) -> Result<()> {
+        let Some(trust) = &self.trust else { return Ok(()) };
+        let v: serde_json::Value = serde_json::from_str(raw)?;
+        trust.check_receipt(&v)?;
+        Ok(())
+    }
+
+    fn crdt_state(&self) -> Result<InheritCertState> {
+        self.certs.load().map_err(|e| anyhow!(e))
+    }
+
+    /// Join `delta` into the stored CRDT state in one transaction; returns the number of
+    /// effects whose canonical run ID changed.
+    fn crdt_apply(&mut self, delta: &InheritCertDelta) -> Result<usize> {
+        Ok(self.certs.apply(delta).map_err(|e| anyhow!(e))?.len())
+    }
+
+    /// The CRDT changes `replica` has not acknowledged, and the log position to acknowledge.
+    fn crdt_pending(&self, replica: &str) -> Result<(u64, InheritCertDelta)> {
+        self.certs.pending(replica).map_err(|e| anyhow!(e))
+    }
+
+    fn crdt_ack(&mut self, replica: &str, upto: u64) -> Result<()> {
+        self.certs.ack(replica, upto).map_err(|e| anyhow!(e))
+    }
+
+    /// Run IDs whose hex digest starts with `prefix`, in order.
+    fn range_ids(&self, prefix: &str) -> Result<Vec<String>> {
+        if prefix.len() > 64 || !prefix.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f')) {
+            bail!("range prefix must be lowercase hex");
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT run_id FROM receipts WHERE substr(run_id, 1, ?1) = ?2 ORDER BY run_id")?;
+        let ids = stmt.query_map(params![7 + prefix.len() as i64, format!("sha256:{}", prefix)], |r| r.get(0))?
+            .filter_map(|x| x.ok())
+            .collect();
+        Ok(ids)
+    }
+
+    fn range(&self, prefix: &str) -> Result<Range> {
+        let ids = self.range_ids(prefix)?;
+        let mut buckets: Vec<Range> = Vec::new();
+        for chunk in ids.chunk_by(|a, b| a.as_bytes().get(7 + prefix.len()) == b.as_bytes().get(7 + prefix.len())) {
+            if let Some(c) = chunk[0].get(7 + prefix.len()..8 + prefix.len()) {
+                buckets.push(Range::of(&format!("{}{}", prefix, c), chunk, vec![]));
+            }
+        }
+        Ok(Range::of(prefix, &ids, buckets))
+    }
+
+    fn get_receipt(&self, run_id: &str) -> Option<String> {
+        self.conn.query_row(
+            "SELECT raw_json FROM receipts WHERE run_id = ?1",
+            params![run_id],
+            |row| row.get(0),
+        ).ok()
+    }
+
+    fn verify_chain(&self, run_id: &str, depth: u32) -> Result<u32> {
+        if depth > 64 { bail!("chain too deep"); }
+        let raw = self.get_receipt(run_id)
+            .ok_or_else(|| anyhow::anyhow!("receipt not found: {}", run_id))?;
+        let v: serde_json::Value = serde_json::from_str(&raw)?;
+        let deps = v.get("derived_from").and_then(|x| x.as_array())
+            .ok_or_else(|| anyhow::anyhow!("missing derived_from"))?;
+        let mut max_depth = depth;
+        for dep in deps {
+            if let Some(dep_id) = dep.as_str() {
+                let d = self.verify_chain(dep_id, depth+1)?;
+                if d > max_depth { max_depth = d; }
+            }
+        }
+        Ok(max_depth)
+    }
+
+    fn stats(&self) -> Result<String> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM receipts", [], |r| r.get(0))?;
+        let mut stmt = self.conn.prepare("SELECT run_id FROM receipts ORDER BY published_at DESC LIMIT 100")?;
+        let ids: Vec<String> = stmt.query_map([], |r| r.get(0))?
+            .filter_map(|x| x.ok())
+            .collect();
+        let arr = ids.iter().map(|s| json_str(s)).collect::<Vec<_>>().join(",");
+        Ok(format!("{{\"count\":{},\"run_ids\":[{}]}}", count, arr))
+    }
+
+    /// Receipts matching `filter`, oldest first, after `cursor` (`<published_at>:<run_id>`).
+    fn list_receipts(&self, filter: &ReceiptFilter, cursor: Option<&str>, limit: usize) -> Result<String> {
+        let cursor = match cursor {
+            Some(c) => {
+                let (at, id) = c.split_once(':').ok_or_else(|| anyhow!("bad cursor"))?;
+                Some((at.parse::<i64>().map_err(|_| anyhow!("bad cursor"))?, id.to_string()))
+            }
+            None => None,
+        };
+        let mut stmt = self.conn.prepare(
+            "SELECT i.run_id, i.program, i.package, i.published_at,
+                    (SELECT group_concat(parent, ' ') FROM receipt_parents WHERE child = i.run_id)
+             FROM receipt_index i
+             WHERE (?1 IS NULL OR i.program = ?1) AND (?2 IS NULL OR i.package = ?2)
+               AND (?3 IS NULL OR i.published_at >= ?3) AND (?4 IS NULL OR i.published_at < ?4)
+               AND (?5 IS NULL OR (i.published_at, i.run_id) > (?5, ?6))
+             ORDER BY i.published_at, i.run_id LIMIT ?7")?;
+        let (at, id) = cursor.unzip();
+        let rows: Vec<(String, String)> = stmt.query_map(
+            params![filter.program, filter.package, filter.since, filter.until, at, id, limit as i64 + 1],
+            |r| {
+                let opt = |s: Option<String>| s.map(|s| json_str(&s)).unwrap_or_else(|| "null".to_string());
+                let run_id: String = r.get(0)?;
+                let published_at: i64 = r.get(3)?;
+                let parents: Option<String> = r.get(4)?;
+                let parents: Vec<String> = parents.iter().flat_map(|p| p.split(' ')).map(json_str).collect();
+                Ok((format!("{}:{}", published_at, run_id),
+                    format!("{{\"run_id\":{},\"program\":{},\"package\":{},\"published_at\":{},\"derived_from\":[{}]}}",
+                        json_str(&run_id), opt(r.get(1)?), opt(r.get(2)?), published_at, parents.join(","))))
+            })?.filter_map(|x| x.ok()).collect();
+        Ok(page("receipts", rows, limit))
+    }
+
+    /// Ancestors (`up`) or descendants of `run_id` to `depth` generations, breadth first;
+    /// `None` when the receipt is not published.
+    fn lineage(&self, run_id: &str, up: bool, depth: u32) -> Result<Option<String>> {
+        if self.get_receipt(run_id).is_none() {
+            return Ok(None);
+        }
+        let mut stmt = self.conn.prepare(if up {
+            "SELECT parent FROM receipt_parents WHERE child = ?1 ORDER BY parent"
+        } else {
+            "SELECT child FROM receipt_parents WHERE parent = ?1 ORDER BY child"
+        })?;
+        let mut seen = std::collections::BTreeSet::from([run_id.to_string()]);
+        let (mut nodes, mut edges) = (vec![], vec![]);
+        let mut frontier = vec![run_id.to_string()];
+        for generation in 1..=depth {
+            let mut next = vec![];
+            for id in &frontier {
+                let related: Vec<String> = stmt.query_map(params![id], |r| r.get(0))?.filter_map(|x| x.ok()).collect();
+                for other in related {
+                    let (parent, child) = if up { (&other, id) } else { (id, &other) };
+                    edges.push(format!("{{\"parent\":{},\"child\":{}}}", json_str(parent), json_str(child)));
+                    if seen.insert(other.clone()) {
+                        nodes.push(format!("{{\"run_id\":{},\"depth\":{}}}", json_str(&other), generation));
+                        next.push(other);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        Ok(Some(format!("{{\"run_id\":{},\"direction\":{},\"depth\":{},\"nodes\":[{}],\"edges\":[{}]}}",
+            json_str(run_id), json_str(if up { "up" } else { "down" }), depth, nodes.join(","), edges.join(","))))
+    }
+
+    fn publish_package(&mut self, name: &str, version: &str, entry_digest: &str, tarball_url: &str, description: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO packages (name, version, entry_digest, tarball_url, description) VALUES (?1,?2,?3,?4,?5)",
+            params![name, version, entry_digest, tarball_url, description],
+        )?;
+        Ok(())
+    }
+
+    /// The newest version of each package whose name or description contains `query`
+    /// (case-insensitively), by name, after the package named `cursor`.
+    fn search_packages(&self, query: Option<&str>, cursor: Option<&str>, limit: usize) -> Result<String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, version, description, entry_digest, tarball_url, published_at FROM packages p
+             WHERE (?1 IS NULL OR instr(lower(name), ?1) > 0 OR instr(lower(description), ?1) > 0)
+               AND (?2 IS NULL OR name > ?2)
+               AND version = (SELECT version FROM packages q WHERE q.name = p.name
+                              ORDER BY published_at DESC, version DESC LIMIT 1)
+             ORDER BY name LIMIT ?3")?;
+        let rows: Vec<(String, String)> = stmt.query_map(
+            params![query.map(str::to_lowercase), cursor, limit as i64 + 1],
+            |r| {
+                let name: String = r.get(0)?;
+                Ok((name.clone(),
+                    format!("{{\"name\":{},\"version\":{},\"description\":{},\"entry_digest\":{},\"tarball_url\":{},\"published_at\":{}}}",
+                        json_str(&name),
+                        json_str(&r.get::<_,String>(1)?),
+                        json_str(&r.get::<_,String>(2)?),
+                        json_str(&r.get::<_,String>(3)?),
+                        json_str(&r.get::<_,String>(4)?),
+                        r.get::<_,i64>(5)?)))
+            })?.filter_map(|x| x.ok()).collect();
+        Ok(page("packages", rows, limit))
+    }
+
+    fn list_packages(&self) -> Result<String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, version, entry_digest, tarball_url, published_at FROM packages ORDER BY name, published_at DESC")?;
+        let rows: Vec<String> = stmt.query_map([], |r| {
+            Ok(format!("{{\"name\":{},\"version\":{},\"entry_digest\":{},\"tarball_url\":{},\"published_at\":{}}}",
+                json_str(&r.get::<_,String>(0)?),
+                json_str(&r.get::<_,String>(1)?),
+                json_str(&r.get::<_,String>(2)?),
+                json_str(&r.get::<_,String>(3)?),
+                r.get::<_,i64>(4)?))
+        })?.filter_map(|x| x.ok()).collect();
+        Ok(format!("[{}]", rows.join(",")))
+    }
+
+    fn list_package_versions(&self, name: &str) -> Result<String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT version, entry_digest, tarball_url, published_at FROM packages WHERE name=?1 ORDER BY published_at DESC")?;
+        let rows: Vec<String> = stmt.query_map(params![name], |r| {
+            Ok(format!("{{\"version\":{},\"entry_digest\":{},\"tarball_url\":{},\"published_at\":{}}}",
+                json_str(&r.get::<_,String>(0)?),
+                json_str(&r.get::<_,String>(1)?),
+                json_str(&r.get::<_,String>(2)?),
+                r.get::<_,i64>(3)?))
+        })?.filter_map(|x| x.ok()).collect();
+        Ok(format!("[{}]", rows.join(",")))
+    }
+
+    /// Publish every receipt in `dir`, parents before children.
+    fn seed_dir(&mut self, dir: &str) -> usize {
+        let pending: Vec<String> = std::fs::read_dir(dir).into_iter().flatten().flatten()
+            .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+            .collect();
+        let publisher = format!("seed:{}", dir);
+        publish_in_order(pending, |raw| {
+            self.publish_receipt(raw, &publisher).map(|_| 200).unwrap_or_else(|(code, _)| code)
+        })
+    }
+}
+
+/// Record a stored receipt's program, package and parents for the query routes.
+fn index_receipt(conn: &Connection, run_id: &str, raw: &str) -> Result<()> {
+    let v: serde_json::Value = serde_json::from_str(raw)?;
+    let program = v.pointer("/preimage/files/module_graph.json").and_then(|x| x.as_str());
+    let package = v.get("package").and_then(|x| x.as_str());
+    conn.execute(
+        "INSERT OR IGNORE INTO receipt_index (run_id, program, package, published_at)
+         SELECT run_id, ?2, ?3, published_at FROM receipts WHERE run_id = ?1",
+        params![run_id, program, package],
+    )?;
+    for parent in v.get("derived_from").and_then(|x| x.as_array()).into_iter().flatten().filter_map(|p| p.as_str()) {
+        conn.execute("INSERT OR IGNORE INTO receipt_parents (parent, child) VALUES (?1, ?2)", params![parent, run_id])?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct ReceiptFilter {
+    program: Option<String>,
+    package: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+}
+
+/// One page of `(cursor, json)` rows fetched with `limit + 1`: the extra row only
+/// signals that there is a next page.
+fn page(field: &str, mut rows: Vec<(String, String)>, limit: usize) -> String {
+    let next = if rows.len() > limit {
+        rows.truncate(limit);
+        rows.last().map(|(c, _)| json_str(c))
+    } else {
+        None
+    };
+    let items: Vec<&str> = rows.iter().map(|(_, j)| j.as_str()).collect();
+    format!("{{\"{}\":[{}],\"next_cursor\":{}}}", field, items.join(","), next.unwrap_or_else(|| "null".to_string()))
+}
+
+const DEFAULT_PAGE: usize = 100;
+const MAX_PAGE: usize = 1000;
+/// The deepest receipt chain `/verify` follows; lineage queries stop there too.
+const MAX_LINEAGE_DEPTH: u32 = 64;
+
+/// Split `/path?a=1&b=x%20y` into the path and decoded query parameters.
+fn split_query(url: &str) -> (&str, Vec<(String, String)>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params = query.split('&').filter(|p| !p.is_empty()).map(|p| {
+        let (k, v) = p.split_once('=').unwrap_or((p, ""));
+        (percent_decode(k), percent_decode(v))
+    }).collect();
+    (path, params)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok()) {
+                Some(b) => {
+                    out.push(b);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("sha256:{}", crate::sha256_hex(data))
+}
+
+/// The Inherit-Cert state's digest, for cheap comparison between replicas.
+fn crdt_digest(state: &InheritCertState) -> String {
+    sha256_hex(state.to_json().to_string().as_bytes())
+}
+
+// ── Anti-entropy ──────────────────────────────────────────────────────────────
+
+/// Every run ID under a hex `prefix`: how many, the digest of the sorted list, and the
+/// same for each non-empty one-digit-longer sub-range.
+struct Range { prefix: String, count: usize, digest: String, buckets: Vec<Range> }
+
+/// Ranges with at most this many run IDs on both sides together are compared ID by ID.
+const LEAF_RANGE: usize = 32;
+
+impl Range {
+    fn of(prefix: &str, ids: &[String], buckets: Vec<Range>) -> Range {
+        Range { prefix: prefix.to_string(), count: ids.len(), digest: sha256_hex(ids.join("\n").as_bytes()), buckets }
+    }
+
+    fn to_json(&self) -> String {
+        let buckets: Vec<String> = self.buckets.iter().map(|b| b.to_json()).collect();
+        format!("{{\"prefix\":{},\"count\":{},\"digest\":{},\"buckets\":[{}]}}",
+            json_str(&self.prefix), self.count, json_str(&self.digest), buckets.join(","))
+    }
+
+    fn from_json(v: &serde_json::Value) -> Result<Range> {
+        let field = |k: &str| v.get(k).and_then(|x| x.as_str()).map(str::to_string)
+            .ok_or_else(|| anyhow!("range missing {}", k));
+        Ok(Range {
+            prefix: field("prefix")?,
+            count: v.get("count").and_then(|x| x.as_u64()).unwrap_or(0) as usize,
+            digest: field("digest")?,
+            buckets: v.get("buckets").and_then(|x| x.as_array()).into_iter().flatten()
+                .map(Range::from_json)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn bucket(&self, prefix: &str) -> Option<&Range> {
+        self.buckets.iter().find(|b| b.prefix == prefix)
+    }
+}
+
+fn http_get(url: &str) -> Result<String> {
+    ureq::get(url).call()
+        .map_err(|e| anyhow!("GET {}: {}", url, e))?
+        .into_string()
+        .with_context(|| format!("GET {}", url))
+}
+
+/// Status of a POST; error statuses are answers, not failures.
+fn http_post(url: &str, body: &str) -> Result<u16> {
+    match ureq::post(url).set("Content-Type", "application/json").send_string(body) {
+        Ok(resp) => Ok(resp.status()),
+        Err(ureq::Error::Status(code, _)) => Ok(code),
+        Err(e) => Err(anyhow!("POST {}: {}", url, e)),
+    }
+}
+
+/// What one round of anti-entropy with the peers moved.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Receipts fetched from peers and published here.
+    pub pulled: usize,
+    /// Receipts this registry published to peers.
+    pub pushed: usize,
+    /// Effects whose canonical run ID changed here.
+    pub crdt_pulled: usize,
+    /// Effects sent to peers as CRDT deltas.
+    pub crdt_pushed: usize,
+}
+
+// ── HTTP handler ──────────────────────────────────────────────────────────────
+fn handle(req: &mut tiny_http::Request, db: &Arc<Mutex<Db>>) -> (u16, &'static str, String) {
+    let method = req.method().to_string();
+    let full_url = req.url().to_string();
+    let (url, query) = split_query(&full_url);
+    let param = |k: &str| query.iter().find(|(n, _)| n == k).map(|(_, v)| v.as_str());
+    let limit = match param("limit").map(str::parse::<usize>) {
+        None => DEFAULT_PAGE,
+        Some(Ok(n)) if n > 0 => n.min(MAX_PAGE),
+        Some(_) => return (400, "application/json", err_json("limit must be a positive integer")),
+    };
+    let publisher = req.remote_addr().map(|a| a.ip().to_string()).unwrap_or_else(|| "unknown".to_string());
+    let mut body = String::new();
+    let _ = std::io::Read::read_to_string(req.as_reader(), &mut body);
+
+    match (method.as_str(), url) {
+        ("GET", "/health") =>
+            (200, "text/plain", "ok".into()),
+
+        ("GET", "/stats") => {
+            let db = db.lock().unwrap();
+            match db.stats() {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("POST", "/publish") => {
+            let mut db = db.lock().unwrap();
+            match db.publish_receipt(&body, &publisher) {
+                Ok(id) => (200, "application/json", ok_json(&json_str(&id))),
+                Err((status, msg)) => (status, "application/json", err_json(&msg)),
+            }
+        }
+
+        ("GET", "/receipts") => {
+            let time = |k: &str| param(k).map(|v| v.parse::<i64>().map_err(|_| format!("{} must be Unix seconds", k))).transpose();
+            let filter = match (time("since"), time("until")) {
+                (Ok(since), Ok(until)) => ReceiptFilter {
+                    program: param("program").map(str::to_string),
+                    package: param("package").map(str::to_string),
+                    since,
+                    until,
+                },
+                (Err(e), _) | (_, Err(e)) => return (400, "application/json", err_json(&e)),
+            };
+            let db = db.lock().unwrap();
+            match db.list_receipts(&filter, param("cursor"), limit) {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (400, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ if url.starts_with("/lineage/") => {
+            let run_id = url.trim_start_matches("/lineage/");
+            let up = match param("direction").unwrap_or("up") {
+                "up" => true,
+                "down" => false,
+                _ => return (400, "application/json", err_json("direction must be up or down")),
+            };
+            let depth = match param("depth").map(str::parse::<u32>) {
+                None => MAX_LINEAGE_DEPTH,
+                Some(Ok(n)) => n.min(MAX_LINEAGE_DEPTH),
+                Some(Err(_)) => return (400, "application/json", err_json("depth must be a non-negative integer")),
+            };
+            let db = db.lock().unwrap();
+            match db.lineage(run_id, up, depth) {
+                Ok(Some(s)) => (200, "application/json", s),
+                Ok(None)    => (404, "application/json", err_json(&format!("not found: {}", run_id))),
+                Err(e)      => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("GET", "/search") => {
+            let db = db.lock().unwrap();
+            match db.search_packages(param("q").filter(|q| !q.is_empty()), param("cursor"), limit) {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("GET", "/packages") if !query.is_empty() => {
+            let db = db.lock().unwrap();
+            match db.search_packages(None, param("cursor"), limit) {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("GET", "/packages") => {
+            let db = db.lock().unwrap();
+            match db.list_packages() {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("POST", "/packages/publish") => {
+            match serde_json::from_str::<serde_json::Value>(&body) {
+                Ok(v) => {
+                    let name    = v.get("name").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let version = v.get("version").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let digest  = v.get("entry_digest").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let url     = v.get("tarball_url").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let desc    = v.get("description").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    if name.is_empty() || version.is_empty() {
+                        return (400, "application/json", err_json("name and version required"));
+                    }
+                    let mut db = db.lock().unwrap();
+                    match db.publish_package(&name, &version, &digest, &url, &desc) {
+                        Ok(_)  => (200, "application/json", ok_json(&json_str(&format!("{}@{}", name, version)))),
+                        Err(e) => (500, "application/json", err_json(&e.to_string())),
+                    }
+                }
+                Err(e) => (400, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ if url.starts_with("/packages/") => {
+            let name = url.trim_start_matches("/packages/");
+            let db = db.lock().unwrap();
+            match db.list_package_versions(name) {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (404, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("GET", "/audit") => {
+            let db = db.lock().unwrap();
+            match db.audit(None) {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ if url.starts_with("/audit/") => {
+            let run_id = url.trim_start_matches("/audit/");
+            let db = db.lock().unwrap();
+            match db.audit(Some(run_id)) {
+                Ok(s)  => (200, "application/json", s),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ if url.starts_with("/receipt/") => {
+            let run_id = url.trim_start_matches("/receipt/");
+            let db = db.lock().unwrap();
+            match db.get_receipt(run_id) {
+                Some(raw) => (200, "application/json", raw),
+                None      => (404, "application/json", err_json(&format!("not found: {}", run_id))),
+            }
+        }
+
+        _ if url.starts_with("/verify/") => {
+            let run_id = url.trim_start_matches("/verify/");
+            let db = db.lock().unwrap();
+            match db.verify_chain(run_id, 0) {
+                Ok(depth) => (200, "application/json",
+                    format!("{{\"ok\":true,\"run_id\":{},\"depth\":{}}}", json_str(run_id), depth)),
+                Err(e) => (404, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        // CRDT routes for distributed receipt convergence
+        ("GET", "/crdt/state") => {
+            let db = db.lock().unwrap();
+            match db.crdt_state() {
+                Ok(state) => (200, "application/json", state.to_json().to_string()),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("GET", "/crdt/digest") => {
+            let db = db.lock().unwrap();
+            match db.crdt_state() {
+                Ok(state) => (200, "application/json", ok_json(&json_str(&crdt_digest(&state)))),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("GET", "/crdt/delta") => {
+            let Some(replica) = param("replica").filter(|r| !r.is_empty()) else {
+                return (400, "application/json", err_json("usage: /crdt/delta?replica=<url>"));
+            };
+            match db.lock().unwrap().crdt_pending(replica) {
+                Ok((upto, delta)) => {
+                    let mut v = delta.to_json();
+                    v["upto"] = serde_json::json!(upto);
+                    (200, "application/json", v.to_string())
+                }
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("POST", "/crdt/ack") => {
+            // body: {"replica": "<url>", "upto": N}
+            let v = match serde_json::from_str::<serde_json::Value>(&body) {
+                Ok(v) => v,
+                Err(e) => return (400, "application/json", err_json(&e.to_string())),
+            };
+            let (Some(replica), Some(upto)) = (v.get("replica").and_then(|x| x.as_str()), v.get("upto").and_then(|x| x.as_u64())) else {
+                return (400, "application/json", err_json("replica and upto required"));
+            };
+            match db.lock().unwrap().crdt_ack(replica, upto) {
+                Ok(()) => (200, "application/json", ok_json("true")),
+                Err(e) => (500, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("POST", "/crdt/propose") => {
+            // body: {"effect_kind": "...", "req_hex": "...", "run_id": "sha256:..."}
+            match serde_json::from_str::<serde_json::Value>(&body) {
+                Ok(v) => {
+                    let kind = v.get("effect_kind").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let req_hex = v.get("req_hex").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let run_id_str = v.get("run_id").and_then(|x| x.as_str()).unwrap_or("").to_string();
+                    let run_id = RunID::new(run_id_str.clone());
+                    if !run_id.is_valid() {
+                        return (400, "application/json", err_json("invalid run_id"));
+                    }
+                    let req_bytes = match hex::decode(&req_hex) {
+                        Ok(b) => b,
+                        Err(_) => return (400, "application/json", err_json("invalid req_hex")),
+                    };
+                    let key = EffectKey::from_kind_req(&kind, &req_bytes);
+                    let mut db = db.lock().unwrap();
+                    match db.crdt_apply(&InheritCertDelta::single(key.clone(), run_id)) {
+                        Ok(_) => (200, "application/json",
+                            ok_json(&format!("{{\"effect_key\":{}}}", json_str(key.as_str())))),
+                        Err(e) => (500, "application/json", err_json(&e.to_string())),
+                    }
+                }
+                Err(e) => (400, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        ("POST", "/crdt/merge") => {
+            // body: an InheritCertDelta JSON ({"updates": ..}) or a full InheritCertState ({"certs": ..})
+            match serde_json::from_str::<serde_json::Value>(&body) {
+                Ok(v) => {
+                    let remote = if v.get("updates").is_some() {
+                        InheritCertDelta::from_json(&v)
+                    } else {
+                        InheritCertState::from_json(&v).map(|s| InheritCertDelta::from_state(&s))
+                    };
+                    let remote = match remote {
+                        Ok(d) => d,
+                        Err(e) => return (400, "application/json", err_json(&e)),
+                    };
+                    let mut db = db.lock().unwrap();
+                    match db.crdt_apply(&remote).and_then(|_| db.certs.len().map_err(|e| anyhow!(e))) {
+                        Ok(n) => (200, "application/json", ok_json(&n.to_string())),
+                        Err(e) => (500, "application/json", err_json(&e.to_string())),
+                    }
+                }
+                Err(e) => (400, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ if url.starts_with("/crdt/get/") => {
+            // GET /crdt/get/<effect_kind>/<req_hex>
+            let rest = url.trim_start_matches("/crdt/get/");
+            let parts: Vec<&str> = rest.splitn(2, '/').collect();
+            if parts.len() != 2 {
+                return (400, "application/json", err_json("usage: /crdt/get/<kind>/<req_hex>"));
+            }
+            let kind = parts[0];
+            let req_hex = parts[1];
+            let req_bytes = match hex::decode(req_hex) {
+                Ok(b) => b,
+                Err(_) => return (400, "application/json", err_json("invalid req_hex")),
+            };
+            let key = EffectKey::from_kind_req(kind, &req_bytes);
+            match db.lock().unwrap().certs.get(&key).unwrap_or_default() {
+                Some(run_id) => (200, "application/json",
+                    ok_json(&json_str(run_id.as_str()))),
+                None => (404, "application/json",
+                    err_json(&format!("not found: {}", key.as_str()))),
+            }
+        }
+
+        // Anti-entropy summaries for replication
+        _ if url.starts_with("/sync/range/") => {
+            let prefix = url.trim_start_matches("/sync/range/");
+            let db = db.lock().unwrap();
+            match db.range(prefix) {
+                Ok(r)  => (200, "application/json", r.to_json()),
+                Err(e) => (400, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ if url.starts_with("/sync/ids/") => {
+            let prefix = url.trim_start_matches("/sync/ids/");
+            let db = db.lock().unwrap();
+            match db.range_ids(prefix) {
+                Ok(ids) => (200, "application/json",
+                    format!("[{}]", ids.iter().map(|s| json_str(s)).collect::<Vec<_>>().join(","))),
+                Err(e) => (400, "application/json", err_json(&e.to_string())),
+            }
+        }
+
+        _ => (404, "application/json", err_json("not found")),
+    }
+}
+
+// ── Server ────────────────────────────────────────────────────────────────────
+
+pub struct RegistryConfig {
+    pub db: PathBuf,
+    /// Listen address; port 0 picks a free port.
+    pub addr: String,
+    pub trust: Option<TrustPolicy>,
+    /// Directory of receipts to publish at startup.
+    pub seed: Option<PathBuf>,
+    /// Base URLs of registries to replicate with.
+    pub peers: Vec<String>,
+    pub sync_interval: Duration,
+}
+
+impl RegistryConfig {
+    pub fn new(db: impl Into<PathBuf>, addr: impl Into<String>) -> RegistryConfig {
+        RegistryConfig {
+            db: db.into(),
+            addr: addr.into(),
+            trust: None,
+            seed: None,
+            peers: vec![],
+            sync_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// State shared by the request loop and the replication loop.
+struct Shared {
+    db: Arc<Mutex<Db>>,
+    url: String,
+    peers: Mutex<Vec<String>>,
+}
+
+/// A running registry. Dropping it stops the server and replication.
+pub struct Registry {
+    shared: Arc<Shared>,
+    server: Arc<tiny_http::Server>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl Registry {
+    pub fn start(cfg: RegistryConfig) -> Result<Registry> {
+        let mut db = Db::open(&cfg.db).with_context(|| format!("open {}", cfg.db.display()))?;
+        db.trust = cfg.trust;
+        // CRDT state used to live next to the registry as JSON; fold it in once.
+        let legacy = PathBuf::from(std::env::var("FARD_REGISTRY_DIR").unwrap_or_else(|_| "_registry".to_string()))
+            .join("inherit_cert_state.json");
+        if let Ok(bytes) = std::fs::read(&legacy) {
+            let state = serde_json::from_slice::<serde_json::Value>(&bytes).ok()
+                .and_then(|v| InheritCertState::from_json(&v).ok())
+                .ok_or_else(|| anyhow!("cannot parse {}", legacy.display()))?;
+            let n = db.crdt_apply(&InheritCertDelta::from_state(&state))?;
+            eprintln!("[fardregistry] imported {} CRDT entries from {}", n, legacy.display());
+        }
+        if let Some(dir) = &cfg.seed {
+            let count = db.seed_dir(&dir.to_string_lossy());
+            eprintln!("[fardregistry] seeded {} receipts from {}", count, dir.display());
+        }
+        let server = tiny_http::Server::http(&cfg.addr)
+            .map_err(|e| anyhow!("listen on {}: {}", cfg.addr, e))?;
+        let addr = server.server_addr().to_ip()
+            .ok_or_else(|| anyhow!("listen on {}: not an IP address", cfg.addr))?;
+        let host = if addr.ip().is_unspecified() { "127.0.0.1".to_string() } else { addr.ip().to_string() };
+        let shared = Arc::new(Shared {
+            db: Arc::new(Mutex::new(db)),
+            url: format!("http://{}:{}", host, addr.port()),
+            peers: Mutex::new(cfg.peers),
+        });
+        let server = Arc::new(server);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut threads = vec![];
+        let (srv, sh) = (Arc::clone(&server), Arc::clone(&shared));
+        threads.push(std::thread::spawn(move || {
+            for mut req in srv.incoming_requests() {
+                let (status, ct, body) = handle(&mut req, &sh.db);
+                let resp = tiny_http::Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(tiny_http::Header::from_bytes(b"Content-Type", ct.as_bytes()).unwrap())
+                    .with_header(tiny_http::Header::from_bytes(b"Access-Control-Allow-Origin", b"*").unwrap());
+                let _ = req.respond(resp);
+            }
+        }));
+        let (st, sh, interval) = (Arc::clone(&stop), Arc::clone(&shared), cfg.sync_interval);
+        threads.push(std::thread::spawn(move || {
+            let tick = Duration::from_millis(20).min(interval);
+            let mut waited = Duration::ZERO;
+            while !st.load(Ordering::Relaxed) {
+                std::thread::sleep(tick);
+                waited += tick;
+                if waited >= interval {
+                    waited = Duration::ZERO;
+                    sh.sync_once();
+                }
+            }
+        }));
+        Ok(Registry { shared, server, stop, threads })
+    }
+
+    /// Base URL, e.g. `http://127.0.0.1:7370`.
+    pub fn url(&self) -> &str {
+        &self.shared.url
+    }
+
+    pub fn add_peer(&self, url: &str) {
+        self.shared.peers.lock().unwrap().push(url.trim_end_matches('/').to_string());
+    }
+
+    /// Reconcile with every peer now. Unreachable peers are reported and skipped.
+    pub fn sync_once(&self) -> SyncReport {
+        self.shared.sync_once()
+    }
+
+    /// Serve until the process exits.
+    pub fn wait(mut self) {
+        if let Some(t) = self.threads.drain(..1).next() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.server.unblock();
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Shared {
+    fn sync_once(&self) -> SyncReport {
+        let peers = self.peers.lock().unwrap().clone();
+        let mut report = SyncReport::default();
+        for peer in peers {
+            if let Err(e) = self.sync_receipts(&peer, &mut report).and_then(|_| self.sync_crdt(&peer, &mut report)) {
+                eprintln!("[fardregistry] sync with {}: {:#}", peer, e);
+            }
+        }
+        report
+    }
+
+    /// Find the run IDs each side lacks by descending into differing ranges, then move them.
+    fn sync_receipts(&self, peer: &str, report: &mut SyncReport) -> Result<()> {
+        let (mut pull, mut push) = (vec![], vec![]);
+        self.diff_range(peer, "", &mut pull, &mut push)?;
+
+        let mut pending = vec![];
+        for id in &pull {
+            pending.push(http_get(&format!("{}/receipt/{}", peer, id))?);
+        }
+        let publisher = format!("peer:{}", peer);
+        report.pulled += publish_in_order(pending, |raw| {
+            self.db.lock().unwrap().publish_receipt(raw, &publisher).map(|_| 200).unwrap_or_else(|(code, _)| code)
+        });
+
+        let pending: Vec<String> = {
+            let db = self.db.lock().unwrap();
+            push.iter().filter_map(|id| db.get_receipt(id)).collect()
+        };
+        let target = format!("{}/publish", peer);
+        report.pushed += publish_in_order(pending, |raw| http_post(&target, raw).unwrap_or(503));
+        Ok(())
+    }
+
+    fn diff_range(&self, peer: &str, prefix: &str, pull: &mut Vec<String>, push: &mut Vec<String>) -> Result<()> {
+        let local = self.db.lock().unwrap().range(prefix)?;
+        let remote: serde_json::Value = serde_json::from_str(&http_get(&format!("{}/sync/range/{}", peer, prefix))?)?;
+        let remote = Range::from_json(&remote)?;
+        if local.digest == remote.digest {
+            return Ok(());
+        }
+        if local.count + remote.count <= LEAF_RANGE || prefix.len() >= 64 {
+            let theirs: Vec<String> = serde_json::from_str(&http_get(&format!("{}/sync/ids/{}", peer, prefix))?)?;
+            let ours = self.db.lock().unwrap().range_ids(prefix)?;
+            pull.extend(theirs.iter().filter(|id| ours.binary_search(id).is_err()).cloned());
+            push.extend(ours.iter().filter(|id| theirs.binary_search(id).is_err()).cloned());
+            return Ok(());
+        }
+        for c in "0123456789abcdef".chars() {
+            let sub = format!("{}{}", prefix, c);
+            let (l, r) = (local.bucket(&sub), remote.bucket(&sub));
+            if l.map(|b| &b.digest) != r.map(|b| &b.digest) {
+                self.diff_range(peer, &sub, pull, push)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the peer the CRDT changes it has not acknowledged, then fetch and acknowledge
+    /// the ones it holds for this registry.
+    fn sync_crdt(&self, peer: &str, report: &mut SyncReport) -> Result<()> {
+        let (upto, delta) = self.db.lock().unwrap().crdt_pending(peer)?;
+        if !delta.is_empty() {
+            let code = http_post(&format!("{}/crdt/merge", peer), &delta.to_json().to_string())?;
+            if code != 200 {
+                bail!("POST {}/crdt/merge: status {}", peer, code);
+            }
+            report.crdt_pushed += delta.len();
+        }
+        self.db.lock().unwrap().crdt_ack(peer, upto)?;
+
+        let theirs: serde_json::Value = serde_json::from_str(&http_get(&format!("{}/crdt/delta?replica={}", peer, self.url))?)?;
+        let upto = theirs.get("upto").and_then(|n| n.as_u64()).ok_or_else(|| anyhow!("peer CRDT delta has no upto"))?;
+        let delta = InheritCertDelta::from_json(&theirs).map_err(|e| anyhow!("peer CRDT delta: {}", e))?;
+        {
+            let mut db = self.db.lock().unwrap();
+            let caught_up = db.crdt_pending(peer)?.1.is_empty();
+            report.crdt_pulled += db.crdt_apply(&delta)?;
+            // What the peer just sent is all it could lack, so do not send it back.
+            if caught_up {
+                let (head, _) = db.crdt_pending(peer)?;
+                db.crdt_ack(peer, head)?;
+            }
+        }
+        let ack = serde_json::json!({ "replica": self.url, "upto": upto });
+        let code = http_post(&format!("{}/crdt/ack", peer), &ack.to_string())?;
+        if code != 200 {
+            bail!("POST {}/crdt/ack: status {}", peer, code);
+        }
+        Ok(())
+    }
+}
+
+/// Publish receipts with `publish` (which returns an HTTP status), retrying those whose
+/// parents are not published yet (422) while any progress is made. Returns how many landed.
+fn publish_in_order(mut pending: Vec<String>, mut publish: impl FnMut(&str) -> u16) -> usize {
+    let mut published = 0;
+    loop {
+        let before = pending.len();
+        let mut deferred = vec![];
+        for raw in pending {
+            match publish(&raw) {
+                200 => published += 1,
+                422 => deferred.push(raw),
+                _ => {}
+            }
+        }
+        pending = deferred;
+        if pending.is_empty() || pending.len() == before {
+            return published;
+        }
+    }
+}
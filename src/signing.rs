@@ -0,0 +1,226 @@
+//! Ed25519 run signatures and the signer trust policy.
+//!
+//! `fardrun run --sign-key key.pem` signs the run's `preimage_sha256` together with its
+//! package and writes a detached `signature.json` next to `digests.json`; the same
+//! signature rides along in the run's receipt. Keys are PKCS#8 PEM (`openssl genpkey -algorithm ed25519` or
+//! `fardkey generate`); public keys are written `ed25519:<hex>`.
+//!
+//! A trust policy lists the public keys allowed to sign for each package:
+//!
+//! ```toml
+//! [signers]
+//! "*" = ["ed25519:9f2c…"]              # any package, including runs outside one
+//! "acme/*" = ["ed25519:41d0…"]         # every package under acme/
+//! "acme/billing" = ["ed25519:77ab…"]
+//! ```
+//!
+//! With a policy in force, unsigned receipts, bad signatures and signers not listed
+//! for the receipt's package are all rejected.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::path::Path;
+
+use crate::{parse_toml_str, parse_toml_str_array, toml_lines, TomlLine};
+
+/// DER prefix of a PKCS#8 Ed25519 private key; the 32-byte seed follows.
+const PKCS8_PREFIX: [u8; 16] = [0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20];
+/// DER prefix of an Ed25519 SubjectPublicKeyInfo; the 32-byte key follows.
+const SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let b64 = base64::engine::general_purpose::STANDARD.encode(der);
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    for chunk in b64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+fn unpem(src: &str, label: &str) -> Result<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let body = src
+        .split_once(&begin)
+        .and_then(|(_, rest)| rest.split_once(&end))
+        .map(|(b, _)| b)
+        .ok_or_else(|| anyhow!("ERROR_SIGN expected a PEM {} block", label))?;
+    let b64: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(b64)
+        .map_err(|e| anyhow!("ERROR_SIGN bad PEM base64: {}", e))
+}
+
+pub fn generate_key() -> Result<SigningKey> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow!("ERROR_SIGN no randomness: {}", e))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+pub fn signing_key_pem(key: &SigningKey) -> String {
+    let mut der = PKCS8_PREFIX.to_vec();
+    der.extend_from_slice(&key.to_bytes());
+    pem("PRIVATE KEY", &der)
+}
+
+pub fn public_key_pem(key: &VerifyingKey) -> String {
+    let mut der = SPKI_PREFIX.to_vec();
+    der.extend_from_slice(key.as_bytes());
+    pem("PUBLIC KEY", &der)
+}
+
+pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let src = std::fs::read_to_string(path).with_context(|| format!("ERROR_SIGN cannot read {}", path.display()))?;
+    let der = unpem(&src, "PRIVATE KEY")?;
+    let seed: [u8; 32] = der
+        .strip_prefix(&PKCS8_PREFIX[..])
+        .and_then(|s| s.try_into().ok())
+        .ok_or_else(|| anyhow!("ERROR_SIGN {} is not a PKCS#8 Ed25519 private key", path.display()))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// `ed25519:<hex>`, the form keys take in signatures and trust policies.
+pub fn key_id(key: &VerifyingKey) -> String {
+    format!("ed25519:{}", valuecore::hex_lower(key.as_bytes()))
+}
+
+fn parse_key_id(id: &str) -> Result<VerifyingKey> {
+    let hex = id.strip_prefix("ed25519:").ok_or_else(|| anyhow!("key must be ed25519:<hex>"))?;
+    let bytes: [u8; 32] = hex_bytes(hex)?.try_into().map_err(|_| anyhow!("key must be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("bad key: {}", e))
+}
+
+fn hex_bytes(s: &str) -> Result<Vec<u8>> {
+    hex::decode(s).map_err(|e| anyhow!("bad hex: {}", e))
+}
+
+/// The package a `fard.toml` declares: `name` under `[package]`, or at the top level.
+pub fn package_name(fard_toml: &str) -> Option<String> {
+    let mut section = String::new();
+    for (_, line) in toml_lines(fard_toml).ok()? {
+        match line {
+            TomlLine::Table(name) | TomlLine::ArrayTable(name) => section = name,
+            TomlLine::Pair(k, v) if k == "name" && (section.is_empty() || section == "package") => {
+                return parse_toml_str(&v).ok();
+            }
+            TomlLine::Pair(..) => {}
+        }
+    }
+    None
+}
+
+/// A detached signature over a run's `preimage_sha256` and the package it ran in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunSignature {
+    pub key: String,
+    pub sig: String,
+}
+
+impl RunSignature {
+    /// The signed message: canonical `{"package": .., "run_id": ..}`, with an empty
+    /// package for runs outside one, so a receipt cannot be moved to another package.
+    pub fn message(run_id: &str, package: &str) -> Vec<u8> {
+        let text = |s: &str| serde_json::Value::from(s).to_string();
+        format!("{{\"package\":{},\"run_id\":{}}}", text(package), text(run_id)).into_bytes()
+    }
+
+    pub fn sign(key: &SigningKey, run_id: &str, package: &str) -> RunSignature {
+        let sig = key.sign(&RunSignature::message(run_id, package));
+        RunSignature { key: key_id(&key.verifying_key()), sig: valuecore::hex_lower(&sig.to_bytes()) }
+    }
+
+    pub fn verify(&self, run_id: &str, package: &str) -> Result<()> {
+        let vk = parse_key_id(&self.key)?;
+        let sig: [u8; 64] = hex_bytes(&self.sig)?.try_into().map_err(|_| anyhow!("signature must be 64 bytes"))?;
+        vk.verify(&RunSignature::message(run_id, package), &Signature::from_bytes(&sig))
+            .map_err(|_| match package {
+                "" => anyhow!("signature does not match {}", run_id),
+                p => anyhow!("signature does not match {} in package {}", run_id, p),
+            })
+    }
+
+    /// Read `{"alg": "ed25519", "key": .., "sig": ..}`, as stored in receipts and `signature.json`.
+    pub fn from_json(v: &serde_json::Value) -> Result<RunSignature> {
+        if v.get("alg").and_then(|a| a.as_str()) != Some("ed25519") {
+            bail!("unsupported signature alg");
+        }
+        let field = |k: &str| v.get(k).and_then(|x| x.as_str()).map(str::to_string).ok_or_else(|| anyhow!("signature missing {}", k));
+        Ok(RunSignature { key: field("key")?, sig: field("sig")? })
+    }
+}
+
+/// Authorized signer keys per package pattern: an exact name, a `namespace/*`, or `*`.
+#[derive(Clone, Debug, Default)]
+pub struct TrustPolicy {
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl TrustPolicy {
+    pub fn load(path: &Path) -> Result<TrustPolicy> {
+        let src = std::fs::read_to_string(path).with_context(|| format!("ERROR_TRUST cannot read {}", path.display()))?;
+        TrustPolicy::parse(&src).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<TrustPolicy> {
+        let mut rules = Vec::new();
+        let mut in_signers = false;
+        for (n, line) in toml_lines(src).map_err(|e| anyhow!("ERROR_TRUST {}", e))? {
+            let (pattern, v) = match line {
+                TomlLine::Table(name) | TomlLine::ArrayTable(name) => {
+                    in_signers = name == "signers";
+                    continue;
+                }
+                TomlLine::Pair(k, v) => (k, v),
+            };
+            if !in_signers {
+                continue;
+            }
+            let keys = parse_toml_str_array(&v)
+                .map_err(|e| anyhow!("ERROR_TRUST line {}: signers for {} must be a list: {}", n, pattern, e))?;
+            for key in &keys {
+                parse_key_id(key).map_err(|e| anyhow!("ERROR_TRUST {}: {}", key, e))?;
+            }
+            rules.push((pattern, keys));
+        }
+        Ok(TrustPolicy { rules })
+    }
+
+    fn matches(pattern: &str, package: &str) -> bool {
+        match pattern.strip_suffix("/*") {
+            _ if pattern == "*" => true,
+            Some(ns) => package.starts_with(&format!("{}/", ns)),
+            None => pattern == package,
+        }
+    }
+
+    /// Whether `key` may sign for `package` (empty for runs outside a package).
+    pub fn authorizes(&self, package: &str, key: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|(p, keys)| TrustPolicy::matches(p, package) && keys.iter().any(|k| k == key))
+    }
+
+    /// Check the signature a receipt or `signature.json` carries for `run_id` in `package`.
+    pub fn check(&self, run_id: &str, package: &str, signature: Option<&serde_json::Value>) -> Result<String> {
+        let sig = match signature {
+            Some(s) => RunSignature::from_json(s).map_err(|e| anyhow!("ERROR_UNTRUSTED {}: {}", run_id, e))?,
+            None => bail!("ERROR_UNSIGNED {} carries no signature", run_id),
+        };
+        sig.verify(run_id, package).map_err(|e| anyhow!("ERROR_BAD_SIGNATURE {}", e))?;
+        if !self.authorizes(package, &sig.key) {
+            let what = if package.is_empty() { "runs outside a package".to_string() } else { format!("package {}", package) };
+            bail!("ERROR_UNTRUSTED {} is signed by {}, which is not trusted for {}", run_id, sig.key, what);
+        }
+        Ok(sig.key)
+    }
+
+    /// Check a receipt (`{run_id, package?, signature?, ..}`).
+    pub fn check_receipt(&self, receipt: &serde_json::Value) -> Result<String> {
+        let run_id = receipt.get("run_id").and_then(|v| v.as_str()).ok_or_else(|| anyhow!("ERROR_UNTRUSTED receipt has no run_id"))?;
+        let package = receipt.get("package").and_then(|v| v.as_str()).unwrap_or("");
+        self.check(run_id, package, receipt.get("signature"))
+    }
+}
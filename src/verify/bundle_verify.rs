@@ -154,8 +154,11 @@ pub fn verify_bundle_outdir(outdir: &str) -> Result<(), String> {
     expect_only_keys(
         dobj,
         &[
+            "derived_from",
             "files",
             "ok",
+            "package",
+            "policy_digest",
             "preimage_sha256",
             "runtime_version",
             "trace_format_version",
@@ -171,6 +174,33 @@ pub fn verify_bundle_outdir(outdir: &str) -> Result<(), String> {
     if !is_sha256(preimage_sha256) {
         return Err("M5_BAD_preimage_sha256".into());
     }
+    // optional: present only when the run was confined by a capability policy
+    let policy_digest = match dobj.get("policy_digest") {
+        None => None,
+        Some(_) => {
+            let pd = expect_str(dobj, "policy_digest")?;
+            if !is_sha256(pd) {
+                return Err("M5_BAD_policy_digest".into());
+            }
+            Some(pd)
+        }
+    };
+
+    // optional: present only when the run imported other runs / ran in a package
+    let derived_from = match dobj.get("derived_from") {
+        None => None,
+        Some(v) => {
+            let ids = v.as_array().ok_or_else(|| "M5_BAD_derived_from".to_string())?;
+            if !ids.iter().all(|id| id.as_str().is_some_and(is_sha256)) {
+                return Err("M5_BAD_derived_from".into());
+            }
+            Some(v.clone())
+        }
+    };
+    let package = match dobj.get("package") {
+        None => None,
+        Some(_) => Some(expect_str(dobj, "package")?),
+    };
 
     let files_obj = expect_obj(dobj, "files")?;
 
@@ -268,8 +298,17 @@ pub fn verify_bundle_outdir(outdir: &str) -> Result<(), String> {
 
     let preimage = {
         let mut m = std::collections::BTreeMap::new();
+        if let Some(ids) = derived_from {
+            m.insert("derived_from".to_string(), ids);
+        }
+        if let Some(pkg) = package {
+            m.insert("package".to_string(), JsonVal::Str(pkg.to_string()));
+        }
         m.insert("files".to_string(), JsonVal::Object(pre_files.into_iter().map(|(k,v)| (k, JsonVal::Str(v))).collect()));
         m.insert("ok".to_string(), JsonVal::Bool(ok));
+        if let Some(pd) = policy_digest {
+            m.insert("policy_digest".to_string(), JsonVal::Str(pd.to_string()));
+        }
         m.insert("runtime_version".to_string(), JsonVal::Str(runtime_version.to_string()));
         m.insert("stdlib_root_digest".to_string(), JsonVal::Str(stdlib_root_digest.to_string()));
         m.insert("trace_format_version".to_string(), JsonVal::Str(trace_format_version.to_string()));
@@ -285,3 +324,19 @@ pub fn verify_bundle_outdir(outdir: &str) -> Result<(), String> {
 
     Ok(())
 }
+
+/// Reject a run whose digests.json claims a stdlib root that no known runtime ships.
+pub fn verify_stdlib_root(outdir: &str, known: &[String]) -> Result<(), String> {
+    let dig_p = format!("{}/digests.json", outdir);
+    let dig_bytes = fs::read(&dig_p).map_err(|_| "M5_MISSING_digests.json".to_string())?;
+    let dig_v: JsonVal =
+        from_slice(&dig_bytes).map_err(|_| "M5_DIGESTS_PARSE_FAIL".to_string())?;
+    let dobj = dig_v
+        .as_object()
+        .ok_or_else(|| "M5_DIGESTS_NOT_OBJECT".to_string())?;
+    let stdlib_root_digest = expect_str(dobj, "stdlib_root_digest")?;
+    if !known.iter().any(|k| k == stdlib_root_digest) {
+        return Err(format!("M5_UNKNOWN_STDLIB_ROOT {}", stdlib_root_digest));
+    }
+    Ok(())
+}
@@ -152,6 +152,21 @@ pub fn verify_trace_outdir(outdir: &str) -> Result<(), String> {
         "ffi_oracle",
         "ffi_checked",
         "spawn_ordered_complete",
+        // Recorded oracle answers (--record / --replay)
+        "oracle",
+        // Calls refused by the --policy / [permissions] capability policy
+        "capability_denied",
+        // std/http answers served or captured by --http-fixtures
+        "http_fixture",
+        // std/sqlite statements with the digest of their result set
+        "sqlite",
+        // std/net servers started by net.listen and their request receipts at net.stop
+        "net_listen",
+        "net_stop",
+        // std/net sockets: opened, bytes moved (count and digest), closed
+        "net_open",
+        "net_io",
+        "net_close",
     ]
     .into_iter()
     .collect();
@@ -224,7 +239,11 @@ pub fn verify_trace_outdir(outdir: &str) -> Result<(), String> {
                 saw_non_module_resolve = true;
             }
             "artifact_in" | "artifact_out" => {
-                expect_only_keys(obj, &["cid", "name", "t"])?;
+                if t == "artifact_in" {
+                    expect_only_keys(obj, &["cid", "name", "path", "t"])?;
+                } else {
+                    expect_only_keys(obj, &["cid", "name", "parents", "t"])?;
+                }
                 let cid = expect_str(obj, "cid")?;
                 if !is_sha256(cid) {
                     return Err("M2_BAD_CID".into());
@@ -240,23 +259,120 @@ pub fn verify_trace_outdir(outdir: &str) -> Result<(), String> {
                 saw_non_module_resolve = true;
             }
             "child_spawn" => {
-                // {t: "child_spawn", spawn_id: "spawn_<uuid>"}
+                // {t: "child_spawn", spawn_id: "spawn_<n>[.<n>...]"}
                 let _spawn_id = expect_str(obj, "spawn_id")?;
                 saw_non_module_resolve = true;
             }
             "child_receipt" => {
-                // {t: "child_receipt", spawn_id: "...", run_digest: "sha256:...", result_digest: "sha256:..."}
-                let _spawn_id = expect_str(obj, "spawn_id")?;
-                let run_digest = expect_str(obj, "run_digest")?;
-                let result_digest = expect_str(obj, "result_digest")?;
-                if !is_sha256(run_digest) && run_digest != "sha256:no-trace" {
-                    return Err(format!("M2_BAD_RUN_DIGEST {}", run_digest));
+                // {t: "child_receipt", spawn_id: "...", trace?: "children/<spawn_id>.ndjson", run_digest: "sha256:...", result_digest: "sha256:..."}
+                verify_child_receipt(outdir, obj, &allowed_t)?;
+                saw_non_module_resolve = true;
+            }
+            "oracle" => {
+                expect_only_keys(obj, &["args_digest", "err", "op", "t", "v"])?;
+                let _op = expect_str(obj, "op")?;
+                let args_digest = expect_str(obj, "args_digest")?;
+                if !is_sha256(args_digest) {
+                    return Err(format!("M2_BAD_ARGS_DIGEST {}", args_digest));
+                }
+                if obj.contains_key("v") == obj.contains_key("err") {
+                    return Err("M2_ORACLE_NEEDS_V_XOR_ERR".into());
+                }
+                saw_non_module_resolve = true;
+            }
+            "http_fixture" => {
+                expect_only_keys(obj, &["cid", "method", "op", "t", "url"])?;
+                let _op = expect_str(obj, "op")?;
+                let _url = expect_str(obj, "url")?;
+                let cid = expect_str(obj, "cid")?;
+                if !is_sha256(cid) { return Err("M2_BAD_CID".into()); }
+                saw_non_module_resolve = true;
+            }
+            "sqlite" => {
+                let has_err = obj.contains_key("err");
+                if has_err {
+                    expect_only_keys(obj, &["db", "err", "op", "params_digest", "sql", "t"])?;
+                    let _err = expect_str(obj, "err")?;
+                } else {
+                    expect_only_keys(obj, &["db", "op", "params_digest", "result_digest", "sql", "t"])?;
+                    let result_digest = expect_str(obj, "result_digest")?;
+                    if !is_sha256(result_digest) { return Err("M2_BAD_CID".into()); }
+                }
+                let _op = expect_str(obj, "op")?;
+                let _db = expect_str(obj, "db")?;
+                let _sql = expect_str(obj, "sql")?;
+                let params_digest = expect_str(obj, "params_digest")?;
+                if !is_sha256(params_digest) { return Err(format!("M2_BAD_ARGS_DIGEST {}", params_digest)); }
+                saw_non_module_resolve = true;
+            }
+            "net_listen" => {
+                expect_only_keys(obj, &["addr", "receipts", "server", "t", "workers"])?;
+                let _server = expect_str(obj, "server")?;
+                let _addr = expect_str(obj, "addr")?;
+                if !matches!(obj.get("workers"), Some(JsonVal::Int(n)) if *n > 0) {
+                    return Err("M2_NET_BAD_WORKERS".into());
                 }
-                if !is_sha256(result_digest) && result_digest != "sha256:no-result" {
-                    return Err(format!("M2_BAD_RESULT_DIGEST {}", result_digest));
+                if !matches!(obj.get("receipts"), Some(JsonVal::Bool(_))) {
+                    return Err("M2_NET_BAD_RECEIPTS".into());
+                }
+                saw_non_module_resolve = true;
+            }
+            "net_stop" => {
+                expect_only_keys(obj, &["receipts_root", "requests", "server", "t"])?;
+                let _server = expect_str(obj, "server")?;
+                if !matches!(obj.get("requests"), Some(JsonVal::Int(n)) if *n >= 0) {
+                    return Err("M2_NET_BAD_REQUESTS".into());
+                }
+                if obj.contains_key("receipts_root") && !is_sha256(expect_str(obj, "receipts_root")?) {
+                    return Err("M2_BAD_CID".into());
                 }
                 saw_non_module_resolve = true;
             }
+            "net_open" => {
+                expect_only_keys(obj, &["addr", "handle", "op", "peer", "t"])?;
+                let _op = expect_str(obj, "op")?;
+                let _handle = expect_str(obj, "handle")?;
+                let _addr = expect_str(obj, "addr")?;
+                if obj.contains_key("peer") {
+                    let _peer = expect_str(obj, "peer")?;
+                }
+                saw_non_module_resolve = true;
+            }
+            "net_io" => {
+                expect_only_keys(obj, &["bytes", "digest", "handle", "op", "peer", "t"])?;
+                let _op = expect_str(obj, "op")?;
+                let _handle = expect_str(obj, "handle")?;
+                if !matches!(obj.get("bytes"), Some(JsonVal::Int(n)) if *n >= 0) {
+                    return Err("M2_NET_BAD_BYTES".into());
+                }
+                if !is_sha256(expect_str(obj, "digest")?) { return Err("M2_BAD_CID".into()); }
+                if obj.contains_key("peer") {
+                    let _peer = expect_str(obj, "peer")?;
+                }
+                saw_non_module_resolve = true;
+            }
+            "net_close" => {
+                expect_only_keys(obj, &["bytes_in", "bytes_out", "handle", "messages_in", "recv_root", "t"])?;
+                let _handle = expect_str(obj, "handle")?;
+                for k in ["bytes_in", "bytes_out", "messages_in"] {
+                    match obj.get(k) {
+                        Some(JsonVal::Int(n)) if *n >= 0 => {}
+                        None if k == "messages_in" => {}
+                        _ => return Err("M2_NET_BAD_BYTES".into()),
+                    }
+                }
+                if obj.contains_key("recv_root") && !is_sha256(expect_str(obj, "recv_root")?) {
+                    return Err("M2_BAD_CID".into());
+                }
+                saw_non_module_resolve = true;
+            }
+            "capability_denied" => {
+                expect_only_keys(obj, &["cap", "op", "t", "target"])?;
+                let _cap = expect_str(obj, "cap")?;
+                let _op = expect_str(obj, "op")?;
+                let _target = expect_str(obj, "target")?;
+                saw_non_module_resolve = true;
+            }
             "artifact_in_named" => {
                 let cid = expect_str(obj, "cid")?;
                 if !is_sha256(cid) { return Err("M2_BAD_CID".into()); }
@@ -325,6 +441,69 @@ pub fn verify_trace_outdir(outdir: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Check a `child_receipt`. When it names the child's sub-trace under `trace`,
+/// that file must hash to `run_digest` and hold well-formed trace events; receipts
+/// in it are checked the same way, so each span is read once however deep it sits.
+fn verify_child_receipt(
+    outdir: &str,
+    obj: &std::collections::BTreeMap<String, JsonVal>,
+    allowed_t: &BTreeSet<&str>,
+) -> Result<(), String> {
+    let spawn_id = expect_str(obj, "spawn_id")?;
+    let run_digest = expect_str(obj, "run_digest")?;
+    let result_digest = expect_str(obj, "result_digest")?;
+    if !is_sha256(run_digest) && run_digest != "sha256:no-trace" {
+        return Err(format!("M2_BAD_RUN_DIGEST {}", run_digest));
+    }
+    if !is_sha256(result_digest) && result_digest != "sha256:no-result" {
+        return Err(format!("M2_BAD_RESULT_DIGEST {}", result_digest));
+    }
+    let rel = match obj.get("trace") {
+        None => return Ok(()),
+        Some(v) => v
+            .as_str()
+            .ok_or_else(|| format!("M2_CHILD_TRACE_NOT_STRING {}", spawn_id))?,
+    };
+    if rel != format!("children/{}.ndjson", spawn_id) {
+        return Err(format!("M2_CHILD_TRACE_PATH {} {}", spawn_id, rel));
+    }
+    let bytes = fs::read(format!("{}/{}", outdir, rel))
+        .map_err(|_| format!("M2_MISSING_CHILD_TRACE {}", rel))?;
+    let got = {
+        let mut h = valuecore::Sha256::new();
+        h.update(&bytes);
+        let hex: String = h.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("sha256:{}", hex)
+    };
+    if got != run_digest {
+        return Err(format!("M2_CHILD_RUN_DIGEST_MISMATCH {} expected {} got {}", spawn_id, run_digest, got));
+    }
+    let text = std::str::from_utf8(&bytes).map_err(|_| format!("M2_CHILD_TRACE_NOT_UTF8 {}", spawn_id))?;
+    for line in text.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let v: JsonVal = json_from_str(line)
+            .map_err(|_| format!("M2_CHILD_EVENT_PARSE_FAIL {}", spawn_id))?;
+        let ev = v
+            .as_object()
+            .ok_or_else(|| format!("M2_CHILD_EVENT_NOT_OBJECT {}", spawn_id))?;
+        let t = expect_t(ev)?;
+        if !allowed_t.contains(t) {
+            return Err(format!("M2_BAD_EVENT_TAG {} in {}", t, spawn_id));
+        }
+        if t == "child_receipt" {
+            // Grandchildren are numbered under their parent, which also rules out cycles
+            let inner = expect_str(ev, "spawn_id")?;
+            if !inner.starts_with(&format!("{}.", spawn_id)) {
+                return Err(format!("M2_CHILD_SPAWN_ID {} in {}", inner, spawn_id));
+            }
+            verify_child_receipt(outdir, ev, allowed_t)?;
+        }
+    }
+    Ok(())
+}
+
 /// Extract all child_receipt entries from a trace
 pub fn extract_child_receipts(trace_path: &str) -> Result<Vec<(String, String, String)>, String> {
     let bytes = std::fs::read(trace_path).map_err(|e| format!("IO: {e}"))?;
@@ -346,6 +525,45 @@ pub fn extract_child_receipts(trace_path: &str) -> Result<Vec<(String, String, S
     Ok(receipts)
 }
 
+/// Run IDs named by `artifact_dep` events: prior runs this run imported as artifacts.
+pub fn extract_artifact_deps(trace_path: &str) -> Result<Vec<String>, String> {
+    let bytes = std::fs::read(trace_path).map_err(|e| format!("IO: {e}"))?;
+    let text = std::str::from_utf8(&bytes).map_err(|_| "UTF8".to_string())?;
+    let mut deps = Vec::new();
+    for line in text.split('\n') {
+        if line.is_empty() { continue; }
+        if let Ok(v) = valuecore::json::from_str(line) {
+            if let Some(obj) = v.as_object() {
+                if obj.get("t").and_then(|t| t.as_str()) == Some("artifact_dep") {
+                    if let Some(run_id) = obj.get("run_id").and_then(|v| v.as_str()) {
+                        deps.push(run_id.to_string());
+                    }
+                }
+            }
+        }
+    }
+    Ok(deps)
+}
+
+/// Spawn ids of the child_receipt entries that name their sub-trace under `trace`;
+/// verify_trace_outdir checks those, so they have no separate run to look up.
+pub fn extract_nested_spawn_ids(trace_path: &str) -> Result<BTreeSet<String>, String> {
+    let bytes = std::fs::read(trace_path).map_err(|e| format!("IO: {e}"))?;
+    let text = std::str::from_utf8(&bytes).map_err(|_| "UTF8".to_string())?;
+    let mut ids = BTreeSet::new();
+    for line in text.split('\n') {
+        if line.is_empty() { continue; }
+        if let Ok(v) = valuecore::json::from_str(line) {
+            if let Some(obj) = v.as_object() {
+                if obj.get("t").and_then(|t| t.as_str()) == Some("child_receipt") && obj.contains_key("trace") {
+                    ids.insert(obj.get("spawn_id").and_then(|v| v.as_str()).unwrap_or("").to_string());
+                }
+            }
+        }
+    }
+    Ok(ids)
+}
+
 /// Extract the run digest from a digests.json
 pub fn extract_run_digest(outdir: &str) -> Result<String, String> {
     let path = format!("{}/digests.json", outdir);
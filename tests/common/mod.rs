@@ -0,0 +1,111 @@
+//! Helpers shared by the intent tests that drive the built binaries.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// A scratch directory that is removed when the returned guard drops.
+pub fn tmpdir() -> tempfile::TempDir {
+    tempfile::Builder::new().prefix("fard_").tempdir().expect("tempdir")
+}
+
+/// Write `src` to `d/main.fard` and run it with `--out out` from `d`.
+pub fn fardrun(d: &Path, src: &str, extra: &[&str]) -> Output {
+    fs::write(d.join("main.fard"), src).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["run", "--program", "main.fard", "--out", "out"])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+/// The `result` field of `d/out/result.json`.
+pub fn result(d: &Path) -> serde_json::Value {
+    let v: serde_json::Value = serde_json::from_slice(&fs::read(d.join("out/result.json")).unwrap()).unwrap();
+    v["result"].clone()
+}
+
+/// Trace events of kind `t` from `d/out/trace.ndjson`, in order.
+pub fn events(d: &Path, t: &str) -> Vec<serde_json::Value> {
+    fs::read_to_string(d.join("out/trace.ndjson"))
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .filter(|v| v["t"] == t)
+        .collect()
+}
+
+/// Assert `fardverify trace` accepts `d/out`.
+pub fn verify_trace(d: &Path) {
+    let o = Command::new(env!("CARGO_BIN_EXE_fardverify")).args(["trace", "--out"]).arg(d.join("out")).output().unwrap();
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+}
+
+pub fn sha256_hex(b: &[u8]) -> String {
+    use sha2::Digest;
+    hex::encode(sha2::Sha256::digest(b))
+}
+
+/// `sha256:` digest of `s`, as receipts spell it.
+pub fn digest(s: &str) -> String {
+    format!("sha256:{}", sha256_hex(s.as_bytes()))
+}
+
+/// A receipt that passes the registry's checks: the run ID is the digest of the
+/// canonical preimage, which commits to the module graph `program`, to
+/// `{"result": output}`, to `derived_from` and to `package`.
+pub fn receipt(program: &str, output: serde_json::Value, derived_from: &[&str], package: Option<&str>) -> serde_json::Value {
+    let mut preimage = serde_json::json!({
+        "files": {
+            "module_graph.json": digest(program),
+            "result.json": digest(&serde_json::json!({ "result": output }).to_string()),
+            "trace.ndjson": digest(&output.to_string()),
+        },
+        "ok": true,
+        "runtime_version": "0.5.0",
+        "stdlib_root_digest": digest("stdlib"),
+        "trace_format_version": "0.1.0",
+    });
+    if !derived_from.is_empty() {
+        preimage["derived_from"] = serde_json::json!(derived_from);
+    }
+    if let Some(p) = package {
+        preimage["package"] = serde_json::json!(p);
+    }
+    let mut r = serde_json::json!({
+        "derived_from": derived_from,
+        "output": output,
+        "preimage": preimage,
+        "run_id": digest(&preimage.to_string()),
+    });
+    if let Some(p) = package {
+        r["package"] = serde_json::json!(p);
+    }
+    r
+}
+
+/// POST `body` as JSON and return the status code.
+pub fn post(url: &str, body: &serde_json::Value) -> u16 {
+    match ureq::post(url).send_string(&body.to_string()) {
+        Ok(resp) => resp.status(),
+        Err(ureq::Error::Status(code, _)) => code,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// GET `url` and return the status code with the JSON body.
+pub fn get(url: &str) -> (u16, serde_json::Value) {
+    let (code, body) = match ureq::get(url).call() {
+        Ok(resp) => (resp.status(), resp.into_string().unwrap()),
+        Err(ureq::Error::Status(code, resp)) => (code, resp.into_string().unwrap()),
+        Err(e) => panic!("{}", e),
+    };
+    (code, serde_json::from_str(&body).unwrap())
+}
+
+/// GET `url`, which must succeed, as JSON.
+pub fn get_json(url: &str) -> serde_json::Value {
+    serde_json::from_str(&ureq::get(url).call().unwrap().into_string().unwrap()).unwrap()
+}
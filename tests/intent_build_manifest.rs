@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run(exe: &str, d: &Path, args: &[&str]) -> Output {
+    Command::new(exe).current_dir(d).args(args).output().unwrap()
+}
+
+fn text(o: &Output) -> String {
+    format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr))
+}
+
+fn json(path: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+const FARDBUILD: &str = env!("CARGO_BIN_EXE_fardbuild");
+const FARD_BUILD: &str = env!("CARGO_BIN_EXE_fard-build");
+const FARDVERIFY: &str = env!("CARGO_BIN_EXE_fardverify");
+const FARDRUN: &str = env!("CARGO_BIN_EXE_fardrun");
+
+#[test]
+fn both_front_ends_write_one_manifest_schema() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("release.fard"), "import(\"std/str\") as str\n\nstr.concat(\"v\", \"1\")\n").unwrap();
+    fs::create_dir_all(d.join("steps")).unwrap();
+    fs::write(d.join("steps/one.fard"), "1 + 1\n").unwrap();
+    fs::write(
+        d.join("fard.build.toml"),
+        "[build]\nname = \"p\"\nversion = \"2.0.0\"\n\n[[step]]\nname = \"one\"\nprogram = \"steps/one.fard\"\nout = \"out/one\"\n",
+    )
+    .unwrap();
+
+    // Spec form through fardbuild: outputs land in the out dir itself, as before.
+    let o = run(FARDBUILD, d, &["run", "--spec", "release.fard", "--out", "spec_out"]);
+    assert!(o.status.success(), "{}", text(&o));
+    assert!(text(&o).contains("fardbuild: ok"));
+    assert!(d.join("spec_out/result.json").exists());
+    let spec_manifest = json(&d.join("spec_out/build.receipt.json"));
+    assert_eq!(spec_manifest["kind"], "fard/build_manifest/v1");
+    assert_eq!(spec_manifest["source"]["form"], "spec");
+    assert_eq!(spec_manifest["steps"][0]["name"], "release");
+    assert_eq!(spec_manifest["steps"][0]["out"], "spec_out");
+
+    // The same spec through fard-build chains to the same digest.
+    let o = run(FARD_BUILD, d, &["--spec", "release.fard", "--out", "spec_out2"]);
+    assert!(o.status.success(), "{}", text(&o));
+    assert_eq!(json(&d.join("spec_out2/build.receipt.json"))["chain_digest"], spec_manifest["chain_digest"]);
+
+    // Config form through fardbuild.
+    let o = run(FARDBUILD, d, &["run", "--config", "fard.build.toml", "--out", "out"]);
+    assert!(o.status.success(), "{}", text(&o));
+    let toml_manifest = json(&d.join("out/build.receipt.json"));
+    assert_eq!(toml_manifest["kind"], "fard/build_manifest/v1");
+    assert_eq!((toml_manifest["source"]["form"].as_str(), toml_manifest["version"].as_str()), (Some("toml"), Some("2.0.0")));
+
+    let o = run(FARDBUILD, d, &["check", "--config", "fard.build.toml"]);
+    assert!(text(&o).contains("1 step(s)"), "{}", text(&o));
+    assert!(!run(FARDBUILD, d, &["check", "--spec", "release.fard", "--config", "fard.build.toml"]).status.success());
+
+    for out in ["spec_out", "out"] {
+        let o = run(FARDVERIFY, d, &["build", "--out", out]);
+        assert!(o.status.success(), "{}: {}", out, text(&o));
+        assert!(d.join(out).join("PASS_BUILD.txt").exists());
+    }
+
+    // Totals that disagree with the steps are rejected.
+    let mut bad = toml_manifest.clone();
+    bad["passed"] = serde_json::json!(0);
+    fs::write(d.join("out/build.receipt.json"), bad.to_string()).unwrap();
+    let o = run(FARDVERIFY, d, &["build", "--out", "out"]);
+    assert!(!o.status.success());
+    assert!(text(&o).contains("BUILD_MANIFEST_MISMATCH passed"), "{}", text(&o));
+}
+
+#[test]
+fn legacy_manifests_are_refused_until_migrated() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("spec.fard"), "[1, 2]\n").unwrap();
+
+    // What fardbuild 1.0.0 left behind: a plain fardrun out dir plus build-manifest.json.
+    let o = run(FARDRUN, d, &["run", "--program", "spec.fard", "--out", "legacy"]);
+    assert!(o.status.success(), "{}", text(&o));
+    let run_id = json(&d.join("legacy/digests.json"))["preimage_sha256"].clone();
+    let spec_sha = format!("sha256:{}", fard_v0_5_language_gate::sha256_hex(b"[1, 2]\n"));
+    let old = serde_json::json!({
+        "fard_build_version": "1.0.0",
+        "spec_sha256": spec_sha,
+        "run_id": run_id,
+        "ok": true,
+        "built_at": 1700000000,
+        "duration_ms": 12,
+        "result": [1, 2],
+    });
+    fs::write(d.join("legacy/build-manifest.json"), old.to_string()).unwrap();
+    fs::write(d.join("legacy/build.receipt.json"), old.to_string()).unwrap();
+
+    let o = run(FARDVERIFY, d, &["build", "--out", "legacy"]);
+    assert!(!o.status.success());
+    assert!(text(&o).contains("BUILD_MANIFEST_LEGACY"), "{}", text(&o));
+
+    let o = run(
+        FARDBUILD,
+        d,
+        &["migrate", "--manifest", "legacy/build-manifest.json", "--out", "legacy/build.receipt.json"],
+    );
+    assert!(o.status.success(), "{}", text(&o));
+    let migrated = json(&d.join("legacy/build.receipt.json"));
+    assert_eq!(migrated["kind"], "fard/build_manifest/v1");
+    assert_eq!(migrated["migrated_from"], "fardbuild/1.0.0");
+    assert_eq!(migrated["source"]["digest"], spec_sha.as_str());
+    assert_eq!(migrated["steps"][0]["run_digest"], run_id);
+    assert_eq!(migrated["built_at"], 1700000000);
+    let o = run(FARDVERIFY, d, &["build", "--out", "legacy"]);
+    assert!(o.status.success(), "{}", text(&o));
+
+    // An old fard-build receipt keeps its chain digest; what it never recorded is null.
+    let digest = |s: &str| format!("sha256:{}", fard_v0_5_language_gate::sha256_hex(s.as_bytes()));
+    let (a, b) = (digest("a"), digest("b"));
+    let old = serde_json::json!({
+        "kind": "fard/build_receipt/v0.1",
+        "name": "old",
+        "version": "0.3.0",
+        "ok": true,
+        "chain_digest": digest(&format!("{}:{}", a, b)),
+        "total_duration_ms": 40,
+        "steps": [
+            { "name": "a", "ok": true, "run_digest": a, "duration_ms": 20, "error": null },
+            { "name": "b", "ok": true, "run_digest": b, "duration_ms": 20, "error": null },
+        ],
+        "step_count": 2,
+        "passed": 2,
+        "failed": 0,
+    });
+    fs::write(d.join("old.json"), old.to_string()).unwrap();
+    let o = run(FARD_BUILD, d, &["--migrate", "old.json"]);
+    assert!(o.status.success(), "{}", text(&o));
+    let migrated: serde_json::Value = serde_json::from_slice(&o.stdout).unwrap();
+    assert_eq!(migrated["chain_digest"], old["chain_digest"]);
+    assert_eq!(migrated["migrated_from"], "fard/build_receipt/v0.1");
+    assert!(migrated["steps"][1]["out"].is_null());
+    assert_eq!(migrated["steps"][1]["cache"], "miss");
+
+    fs::write(d.join("junk.json"), "{\"kind\": \"something/else\"}").unwrap();
+    let o = run(FARD_BUILD, d, &["--migrate", "junk.json"]);
+    assert!(!o.status.success());
+    assert!(text(&o).contains("BUILD_MANIFEST_KIND"), "{}", text(&o));
+}
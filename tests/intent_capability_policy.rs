@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run_in(cwd: &Path, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(cwd)
+        .args(["run", "--program", "main.fard", "--out", "out"])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn read_json(p: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(p).unwrap()).unwrap()
+}
+
+fn denied_events(out: &Path) -> Vec<serde_json::Value> {
+    fs::read_to_string(out.join("trace.ndjson"))
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .filter(|v| v["t"] == "capability_denied")
+        .collect()
+}
+
+const READ_PROG: &str = r#"import("std/fs") as fs
+fs.read_text("data/in.txt")
+"#;
+
+#[test]
+fn policy_allows_listed_root_and_commits_digest() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("data")).unwrap();
+    fs::write(d.join("data/in.txt"), "hello").unwrap();
+    fs::write(d.join("main.fard"), READ_PROG).unwrap();
+    fs::write(d.join("policy.toml"), "fs_read = [\"data\"]\n").unwrap();
+
+    let out = run_in(d, &["--policy", "policy.toml"]);
+    assert!(out.status.success(), "run failed: {}", String::from_utf8_lossy(&out.stderr));
+
+    let dig = read_json(&d.join("out/digests.json"));
+    let pd = dig["policy_digest"].as_str().expect("policy_digest");
+    assert!(pd.starts_with("sha256:"));
+
+    let v = Command::new(env!("CARGO_BIN_EXE_fardverify"))
+        .args(["bundle", "--out"])
+        .arg(d.join("out"))
+        .output()
+        .unwrap();
+    assert!(v.status.success(), "bundle verify: {}", String::from_utf8_lossy(&v.stderr));
+
+    // no policy: the digest surface is unchanged
+    let out = run_in(d, &[]);
+    assert!(out.status.success());
+    assert!(read_json(&d.join("out/digests.json")).get("policy_digest").is_none());
+}
+
+#[test]
+fn policy_denies_unlisted_root() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("data")).unwrap();
+    fs::write(d.join("data/in.txt"), "hello").unwrap();
+    fs::write(d.join("main.fard"), READ_PROG).unwrap();
+    fs::write(d.join("policy.toml"), "[permissions]\nfs_read = [\"public\"]\n").unwrap();
+
+    let out = run_in(d, &["--policy", "policy.toml"]);
+    assert!(!out.status.success());
+    let err = read_json(&d.join("out/error.json"));
+    assert_eq!(err["code"], "ERROR_CAPABILITY");
+
+    let evs = denied_events(&d.join("out"));
+    assert_eq!(evs.len(), 1);
+    assert_eq!(evs[0]["cap"], "fs_read");
+    assert_eq!(evs[0]["op"], "fs.read_text");
+    assert_eq!(evs[0]["target"], "data/in.txt");
+}
+
+#[test]
+fn fard_toml_permissions_gate_env() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(
+        d.join("main.fard"),
+        "import(\"std/env\") as env\nlet a = env.get(\"FARD_CAP_OK\")\nenv.get(\"HOME\")\n",
+    )
+    .unwrap();
+    fs::write(
+        d.join("fard.toml"),
+        "name = \"capdemo\"\nversion = \"0.1.0\"\n\n[permissions]\nenv = [\"FARD_CAP_*\"]\n",
+    )
+    .unwrap();
+
+    let out = run_in(d, &[]);
+    assert!(!out.status.success());
+    assert_eq!(read_json(&d.join("out/error.json"))["code"], "ERROR_CAPABILITY");
+    let evs = denied_events(&d.join("out"));
+    assert_eq!(evs.len(), 1);
+    assert_eq!(evs[0]["cap"], "env");
+    assert_eq!(evs[0]["target"], "HOME");
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_cannot_escape_an_allowed_root() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("data")).unwrap();
+    fs::create_dir_all(d.join("secret")).unwrap();
+    fs::write(d.join("secret/key.txt"), "hunter2").unwrap();
+    std::os::unix::fs::symlink(d.join("secret"), d.join("data/escape")).unwrap();
+    std::os::unix::fs::symlink(d.join("secret/planted.txt"), d.join("data/dangling")).unwrap();
+    fs::write(d.join("policy.toml"), "fs_read = [\"data\"]\nfs_write = [\"data\"]\n").unwrap();
+
+    fs::write(d.join("main.fard"), "import(\"std/io\") as io\nio.read_file(\"data/escape/key.txt\")\n").unwrap();
+    let out = run_in(d, &["--policy", "policy.toml"]);
+    assert!(!out.status.success());
+    assert_eq!(read_json(&d.join("out/error.json"))["code"], "ERROR_CAPABILITY");
+    assert_eq!(denied_events(&d.join("out"))[0]["target"], "data/escape/key.txt");
+
+    // A write through a symlink whose target does not exist yet is judged by the target.
+    fs::write(d.join("main.fard"), "import(\"std/io\") as io\nio.write_file(\"data/dangling\", \"x\")\n").unwrap();
+    let out = run_in(d, &["--policy", "policy.toml"]);
+    assert!(!out.status.success());
+    assert_eq!(read_json(&d.join("out/error.json"))["code"], "ERROR_CAPABILITY");
+    assert!(!d.join("secret/planted.txt").exists());
+}
+
+#[test]
+fn hash_inside_a_quoted_policy_value_is_not_a_comment() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("data#1")).unwrap();
+    fs::write(d.join("data#1/in.txt"), "hello").unwrap();
+    fs::write(d.join("main.fard"), "import(\"std/fs\") as fs\nfs.read_text(\"data#1/in.txt\")\n").unwrap();
+    fs::write(d.join("policy.toml"), "fs_read = [\"data#1\"] # the numbered data dir\n").unwrap();
+
+    let out = run_in(d, &["--policy", "policy.toml"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(read_json(&d.join("out/result.json"))["result"], "hello");
+}
+
+#[test]
+fn multi_line_lists_and_roots_relative_to_the_policy_file() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("app/data")).unwrap();
+    fs::create_dir_all(d.join("data")).unwrap();
+    fs::write(d.join("app/data/in.txt"), "inside").unwrap();
+    fs::write(d.join("data/in.txt"), "outside").unwrap();
+    fs::write(
+        d.join("app/fard.toml"),
+        "[permissions]\nfs_read = [\n  \"data\",   # relative to this file\n  \"logs#old\",\n]\n",
+    )
+    .unwrap();
+    let run = |read: &str| {
+        let src = format!("import(\"std/fs\") as fs\nfs.read_text(\"{}\")\n", read);
+        fs::write(d.join("app/main.fard"), src).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_fardrun"))
+            .current_dir(d)
+            .args(["run", "--program", "app/main.fard", "--out", "out"])
+            .output()
+            .unwrap()
+    };
+
+    // Started from the parent directory, "data" still means app/data.
+    let out = run("app/data/in.txt");
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(read_json(&d.join("out/result.json"))["result"], "inside");
+    let out = run("data/in.txt");
+    assert!(!out.status.success());
+    assert_eq!(read_json(&d.join("out/error.json"))["code"], "ERROR_CAPABILITY");
+
+    // The same holds for a --policy file kept in another directory.
+    fs::create_dir_all(d.join("conf")).unwrap();
+    fs::write(d.join("conf/policy.toml"), "fs_read = [\"../app/data\"]\n").unwrap();
+    fs::write(d.join("main.fard"), "import(\"std/fs\") as fs\nfs.read_text(\"app/data/in.txt\")\n").unwrap();
+    let out = run_in(d, &["--policy", "conf/policy.toml"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+}
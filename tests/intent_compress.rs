@@ -0,0 +1,205 @@
+use std::fs;
+use std::io::{Read, Write};
+
+mod common;
+use common::{fardrun, result, sha256_hex, tmpdir, verify_trace};
+
+#[test]
+fn codecs_round_trip_bytes_and_interoperate() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let text = "fard ".repeat(300);
+    fs::write(d.join("in.zst"), zstd::bulk::compress(text.as_bytes(), 19).unwrap()).unwrap();
+    let mut g = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    g.write_all(text.as_bytes()).unwrap();
+    fs::write(d.join("in.gz"), g.finish().unwrap()).unwrap();
+    let src = r#"import("std/compress") as compress
+import("std/bytes") as bytes
+import("std/str") as str
+import("std/fs") as fs
+
+let text = str.repeat("fard ", 300)
+let raw = bytes.of_list([0, 255, 7, 0, 255])
+let zst = compress.zstd_compress(text, 19)
+let gz = compress.gzip_compress(raw)
+let _ = fs.write_bytes("out.zst", zst)
+let _ = fs.write_bytes("out.gz", gz)
+let _ = fs.write_bytes("out.deflate", compress.deflate_compress(text, 9))
+{
+  zstd: bytes.to_str(compress.zstd_decompress(fs.read_bytes("in.zst"))?) == text,
+  gzip: bytes.to_str(compress.gzip_decompress(fs.read_bytes("in.gz"))?) == text,
+  raw: [
+    compress.gzip_decompress(gz)? == raw,
+    compress.zstd_decompress(compress.zstd_compress(raw))? == raw,
+    compress.deflate_decompress(compress.deflate_compress(raw, 0))? == raw
+  ],
+  smaller: bytes.len(zst) < 100,
+  stable: compress.gzip_compress(raw) == gz,
+  legacy: compress.gunzip(compress.gzip("hi")).ok,
+  bad: [compress.gzip_decompress(raw).e, compress.zstd_decompress(raw).e]
+}
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["zstd"], true);
+    assert_eq!(r["gzip"], true);
+    assert_eq!(r["raw"], serde_json::json!([true, true, true]));
+    assert_eq!(r["smaller"], true);
+    assert_eq!(r["stable"], true);
+    assert_eq!(r["legacy"], "hi");
+    assert!(r["bad"][0].as_str().unwrap().starts_with("ERROR_COMPRESS compress.gzip_decompress"));
+    assert!(r["bad"][1].as_str().unwrap().starts_with("ERROR_COMPRESS compress.zstd_decompress"));
+
+    // What fardrun writes, standard decoders read.
+    assert_eq!(zstd::decode_all(fs::read(d.join("out.zst")).unwrap().as_slice()).unwrap(), text.as_bytes());
+    let mut back = Vec::new();
+    flate2::read::GzDecoder::new(fs::read(d.join("out.gz")).unwrap().as_slice()).read_to_end(&mut back).unwrap();
+    assert_eq!(back, [0, 255, 7, 0, 255]);
+    back.clear();
+    flate2::read::DeflateDecoder::new(fs::read(d.join("out.deflate")).unwrap().as_slice()).read_to_end(&mut back).unwrap();
+    assert_eq!(back, text.as_bytes());
+}
+
+#[test]
+fn archives_are_reproducible_and_extracted_entries_are_artifacts() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    // A tar and a zip written by other tools, with a directory entry each.
+    let mut tb = tar::Builder::new(Vec::new());
+    let mut h = tar::Header::new_gnu();
+    h.set_entry_type(tar::EntryType::Directory);
+    h.set_size(0);
+    tb.append_data(&mut h, "docs/", std::io::empty()).unwrap();
+    let mut h = tar::Header::new_gnu();
+    h.set_size(5);
+    h.set_mtime(1_700_000_000);
+    tb.append_data(&mut h, "docs/readme.md", &b"# hi\n"[..]).unwrap();
+    fs::write(d.join("in.tar"), tb.into_inner().unwrap()).unwrap();
+    let mut zw = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zw.add_directory("img/", zip::write::SimpleFileOptions::default()).unwrap();
+    zw.start_file("img/dot.bin", zip::write::SimpleFileOptions::default()).unwrap();
+    zw.write_all(&[1, 2, 3, 4]).unwrap();
+    fs::write(d.join("in.zip"), zw.finish().unwrap().into_inner()).unwrap();
+
+    let src = r#"import("std/compress") as compress
+import("std/bytes") as bytes
+import("std/list") as list
+import("std/str") as str
+import("std/fs") as fs
+
+let entries = [{ name: "a.txt", data: "alpha" }, { name: "deep/b.bin", data: bytes.of_list([0, 1, 2]) }]
+let t = compress.tar_create(entries)
+let z = compress.zip_create(entries)
+let stored = compress.zip_create(entries, { method: "store" })
+let repeated = [{ name: "r.txt", data: str.repeat("ab", 500) }]
+let _ = fs.write_bytes("made.tar", t)
+let _ = fs.write_bytes("made.zip", z)
+fn names(xs) { list.map(xs, fn(e) { [e.name, e.size, e.cid] }) }
+let foreign_tar = compress.tar_extract(fs.read_bytes("in.tar"))?
+let foreign_zip = compress.zip_extract(fs.read_bytes("in.zip"))?
+let own = compress.zip_extract(stored)?
+let readme = list.get(foreign_tar, 0)
+let out = emit_artifact_derived("readme.html", "readme.html", "<h1>hi</h1>", [readme.cid])?
+{
+  stable: [compress.tar_create(entries) == t, compress.zip_create(entries) == z],
+  tar: names(compress.tar_extract(t)?),
+  zip: names(compress.zip_extract(z)?),
+  stored: [
+    bytes.len(compress.zip_create(repeated, { method: "store" })) > bytes.len(compress.zip_create(repeated)) + 900,
+    list.get(own, 1).data == bytes.of_list([0, 1, 2])
+  ],
+  foreign: [names(foreign_tar), names(foreign_zip)],
+  out: out.name,
+  bad: compress.zip_extract(bytes.of_list([1, 2, 3])).e
+}
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    let a = format!("sha256:{}", sha256_hex(b"alpha"));
+    let b = format!("sha256:{}", sha256_hex(&[0, 1, 2]));
+    let own = serde_json::json!([["a.txt", 5, a], ["deep/b.bin", 3, b]]);
+    assert_eq!(r["stable"], serde_json::json!([true, true]));
+    assert_eq!(r["tar"], own);
+    assert_eq!(r["zip"], own);
+    assert_eq!(r["stored"], serde_json::json!([true, true]));
+    let readme = format!("sha256:{}", sha256_hex(b"# hi\n"));
+    let dot = format!("sha256:{}", sha256_hex(&[1, 2, 3, 4]));
+    assert_eq!(r["foreign"], serde_json::json!([[["docs/readme.md", 5, readme]], [["img/dot.bin", 4, dot]]]));
+    assert_eq!(r["out"], "readme.html");
+    assert!(r["bad"].as_str().unwrap().starts_with("ERROR_COMPRESS compress.zip_extract"));
+
+    // Archives fardrun writes open in the standard readers with fixed metadata.
+    let mut ta = tar::Archive::new(fs::File::open(d.join("made.tar")).unwrap());
+    let meta: Vec<(String, u64, u32)> = ta
+        .entries()
+        .unwrap()
+        .map(|e| {
+            let e = e.unwrap();
+            (e.path().unwrap().display().to_string(), e.header().mtime().unwrap(), e.header().mode().unwrap())
+        })
+        .collect();
+    assert_eq!(meta, [("a.txt".to_string(), 0, 0o644), ("deep/b.bin".to_string(), 0, 0o644)]);
+    let mut za = zip::ZipArchive::new(fs::File::open(d.join("made.zip")).unwrap()).unwrap();
+    let mut s = String::new();
+    za.by_name("a.txt").unwrap().read_to_string(&mut s).unwrap();
+    assert_eq!(s, "alpha");
+
+    // Each distinct extracted file is an input of the run, and the derived artifact cites one.
+    let trace = fs::read_to_string(d.join("out/trace.ndjson")).unwrap();
+    let events: Vec<serde_json::Value> = trace.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let inputs: Vec<&str> = events
+        .iter()
+        .filter(|e| e["t"] == "artifact_in")
+        .map(|e| e["cid"].as_str().unwrap())
+        .collect();
+    assert_eq!(inputs, [readme.as_str(), dot.as_str(), a.as_str(), b.as_str()]);
+    let derived = events.iter().find(|e| e["t"] == "artifact_out").unwrap();
+    assert_eq!(derived["parents"][0]["cid"], readme);
+    verify_trace(d);
+}
+
+#[test]
+fn decompression_and_extraction_stop_at_max_bytes() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    // 64 MiB of zeros squeezed into a few KiB.
+    fs::write(d.join("bomb.zst"), zstd::bulk::compress(&vec![0u8; 64 << 20], 19).unwrap()).unwrap();
+    let src = r#"import("std/compress") as compress
+import("std/bytes") as bytes
+import("std/list") as list
+import("std/str") as str
+import("std/fs") as fs
+
+let text = str.repeat("x", 1000)
+let gz = compress.gzip_compress(text)
+let t = compress.tar_create([{ name: "a", data: text }, { name: "b", data: text }])
+let z = compress.zip_create([{ name: "a", data: text }, { name: "b", data: text }])
+{
+  bomb: compress.zstd_decompress(fs.read_bytes("bomb.zst"), { max_bytes: 1048576 }).e,
+  exact: bytes.len(compress.gzip_decompress(gz, { max_bytes: 1000 })?),
+  over: [
+    compress.gzip_decompress(gz, { max_bytes: 999 }).e,
+    compress.deflate_decompress(compress.deflate_compress(text), { max_bytes: 10 }).e,
+    compress.tar_extract(t, { max_bytes: 1999 }).e,
+    compress.zip_extract(z, { max_bytes: 1999 }).e
+  ],
+  both: [list.len(compress.tar_extract(t, { max_bytes: 2000 })?), list.len(compress.zip_extract(z, { max_bytes: 2000 })?)]
+}
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["bomb"], "ERROR_COMPRESS compress.zstd_decompress: output exceeds max_bytes");
+    assert_eq!(r["exact"], 1000);
+    let over = r["over"].as_array().unwrap();
+    for (e, op) in over.iter().zip(["gzip_decompress", "deflate_decompress", "tar_extract", "zip_extract"]) {
+        let e = e.as_str().unwrap();
+        assert!(e.starts_with(&format!("ERROR_COMPRESS compress.{op}: ")) && e.ends_with("output exceeds max_bytes"), "{e}");
+    }
+    assert_eq!(r["both"], serde_json::json!([2, 2]));
+
+    let o = fardrun(d, "import(\"std/compress\") as compress\ncompress.gzip_decompress(compress.gzip_compress(\"a\"), { max_bytes: -1 })\n", &[]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_BADARG compress.gzip_decompress max_bytes must be a non-negative int"));
+}
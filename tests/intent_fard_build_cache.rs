@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+use common::tmpdir;
+
+const CONFIG: &str = r#"[build]
+name = "cached"
+version = "0.1.0"
+
+[[step]]
+name = "a"
+program = "steps/a.fard"
+out = "build/a"
+env = { MODE = "MODE_VALUE" }
+args = ["x", "y"]
+
+[[step]]
+name = "b"
+program = "steps/b.fard"
+out = "build/b"
+depends_on = ["a"]
+"#;
+
+fn project(d: &Path, mode: &str) {
+    fs::create_dir_all(d.join("steps")).unwrap();
+    fs::write(d.join("fard.build.toml"), CONFIG.replace("MODE_VALUE", mode)).unwrap();
+    fs::write(
+        d.join("steps/a.fard"),
+        "import(\"std/env\") as env\nimport(\"std/cli\") as cli\n\n{ mode: env.get(\"MODE\"), args: cli.args() }\n",
+    )
+    .unwrap();
+    fs::write(d.join("steps/b.fard"), "import(\"./helper\") as h\n\nh.twice(21)\n").unwrap();
+    if !d.join("steps/helper.fard").exists() {
+        fs::write(d.join("steps/helper.fard"), "fn twice(x) { x * 2 }\n\nexport { twice }\n").unwrap();
+    }
+}
+
+fn build(d: &Path, extra: &[&str]) -> serde_json::Value {
+    let out = Command::new(env!("CARGO_BIN_EXE_fard-build"))
+        .current_dir(d)
+        .args(["--config", "fard.build.toml", "--out", "build", "--fardrun", env!("CARGO_BIN_EXE_fardrun")])
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    serde_json::from_slice(&fs::read(d.join("build/build.receipt.json")).unwrap()).unwrap()
+}
+
+fn cache(receipt: &serde_json::Value) -> Vec<&str> {
+    receipt["steps"].as_array().unwrap().iter().map(|s| s["cache"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn unchanged_steps_are_restored_from_the_cache() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    project(d, "strict");
+
+    let first = build(d, &[]);
+    assert_eq!(cache(&first), ["miss", "miss"]);
+    assert_eq!(first["cache_hits"], 0);
+    let result_a = fs::read(d.join("build/a/result.json")).unwrap();
+    let a: serde_json::Value = serde_json::from_slice(&result_a).unwrap();
+    assert_eq!(a["result"]["args"], serde_json::json!(["x", "y"]), "{}", a);
+    let result_b = fs::read(d.join("build/b/result.json")).unwrap();
+
+    // Nothing changed: both steps hit and their outputs come back byte for byte.
+    fs::remove_dir_all(d.join("build/a")).unwrap();
+    fs::remove_dir_all(d.join("build/b")).unwrap();
+    let second = build(d, &[]);
+    assert_eq!(cache(&second), ["hit", "hit"]);
+    assert_eq!(second["cache_hits"], 2);
+    assert_eq!(second["chain_digest"], first["chain_digest"]);
+    assert_eq!(second["steps"][0]["key"], first["steps"][0]["key"]);
+    assert_eq!(fs::read(d.join("build/a/result.json")).unwrap(), result_a);
+    assert_eq!(fs::read(d.join("build/b/result.json")).unwrap(), result_b);
+    assert!(d.join("build/a/digests.json").exists());
+
+    // Editing a module only b imports reruns b alone.
+    fs::write(d.join("steps/helper.fard"), "fn twice(x) { x + x }\n\nexport { twice }\n").unwrap();
+    let edited = build(d, &[]);
+    assert_eq!(cache(&edited), ["hit", "miss"]);
+    assert_ne!(edited["steps"][1]["key"], first["steps"][1]["key"]);
+
+    // A declared env change reruns a, and b because a's run digest moved.
+    project(d, "lenient");
+    let env_changed = build(d, &[]);
+    assert_eq!(cache(&env_changed), ["miss", "miss"]);
+    assert_ne!(env_changed["chain_digest"], first["chain_digest"]);
+
+    // Overrides.
+    assert_eq!(cache(&build(d, &["--force-step", "b"])), ["hit", "forced"]);
+    assert_eq!(cache(&build(d, &["--force"])), ["forced", "forced"]);
+    assert_eq!(cache(&build(d, &[])), ["hit", "hit"]);
+}
+
+#[test]
+fn failed_steps_are_not_cached() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("steps")).unwrap();
+    fs::write(
+        d.join("fard.build.toml"),
+        "[build]\nname = \"f\"\n\n[[step]]\nname = \"bad\"\nprogram = \"steps/bad.fard\"\nout = \"build/bad\"\n",
+    )
+    .unwrap();
+    fs::write(d.join("steps/bad.fard"), "let x = 1 / 0\nx\n").unwrap();
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_fard-build"))
+            .current_dir(d)
+            .args(["--out", "build", "--fardrun", env!("CARGO_BIN_EXE_fardrun")])
+            .output()
+            .unwrap()
+    };
+    assert!(!run().status.success());
+    assert!(!run().status.success());
+    let receipt: serde_json::Value =
+        serde_json::from_slice(&fs::read(d.join("build/build.receipt.json")).unwrap()).unwrap();
+    assert_eq!(receipt["steps"][0]["cache"], "miss");
+    assert_eq!(receipt["cache_hits"], 0);
+}
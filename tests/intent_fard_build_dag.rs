@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+/// Write `steps/<name>.fard` for each program and a config with one step per entry.
+fn project(d: &Path, steps: &[(&str, &str, &[&str])]) {
+    fs::create_dir_all(d.join("steps")).unwrap();
+    let mut toml = String::from("[build]\nname = \"dag\"\nversion = \"0.1.0\"\n");
+    for (name, program, deps) in steps {
+        fs::write(d.join(format!("steps/{}.fard", name)), program).unwrap();
+        toml.push_str(&format!("\n[[step]]\nname = \"{0}\"\nprogram = \"steps/{0}.fard\"\nout = \"build/{0}\"\n", name));
+        if !deps.is_empty() {
+            let deps: Vec<String> = deps.iter().map(|d| format!("\"{}\"", d)).collect();
+            toml.push_str(&format!("depends_on = [{}]\n", deps.join(", ")));
+        }
+    }
+    fs::write(d.join("fard.build.toml"), toml).unwrap();
+}
+
+fn fard_build(d: &Path, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fard-build"))
+        .current_dir(d)
+        .args(["--out", "build", "--fardrun", env!("CARGO_BIN_EXE_fardrun")])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn receipt(d: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(d.join("build/build.receipt.json")).unwrap()).unwrap()
+}
+
+fn stderr(o: &Output) -> String {
+    String::from_utf8_lossy(&o.stderr).to_string()
+}
+
+#[test]
+fn failures_skip_dependents_and_independent_branches_finish() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    project(
+        d,
+        &[
+            ("broken", "let x = 1 / 0\nx\n", &[]),
+            ("after_broken", "1\n", &["broken"]),
+            ("after_after", "2\n", &["after_broken"]),
+            ("left", "10\n", &[]),
+            ("right", "20\n", &[]),
+            ("join", "import(\"std/env\") as env\n\nenv.get(\"FARD_PRIOR_DIGEST\")\n", &["left", "right"]),
+        ],
+    );
+    let out = fard_build(d, &["-j", "4"]);
+    assert!(!out.status.success());
+    let r = receipt(d);
+    let status: Vec<(&str, bool, bool)> = r["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].as_str().unwrap(), s["ok"].as_bool().unwrap(), s["skipped"].as_bool().unwrap()))
+        .collect();
+    assert_eq!(
+        status,
+        [
+            ("broken", false, false),
+            ("after_broken", false, true),
+            ("after_after", false, true),
+            ("left", true, false),
+            ("right", true, false),
+            ("join", true, false),
+        ],
+        "{}",
+        stderr(&out)
+    );
+    assert_eq!((r["passed"].as_u64(), r["failed"].as_u64(), r["skipped"].as_u64()), (Some(3), Some(1), Some(2)));
+    // The join sees the run digest of the last step it depends on.
+    let right = r["steps"][4]["run_digest"].as_str().unwrap();
+    assert_eq!(r["steps"][5]["prior_digest"], right);
+    let join: serde_json::Value = serde_json::from_slice(&fs::read(d.join("build/join/result.json")).unwrap()).unwrap();
+    assert_eq!(join["result"]["some"], right, "{}", join);
+}
+
+#[test]
+fn cycles_and_unknown_dependencies_are_rejected() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    project(d, &[("a", "1\n", &["c"]), ("b", "2\n", &["a"]), ("c", "3\n", &["b"])]);
+    let out = fard_build(d, &[]);
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("dependency cycle: a -> c -> b -> a"), "{}", stderr(&out));
+    assert!(!d.join("build/a").exists());
+
+    project(d, &[("a", "1\n", &["nope"])]);
+    let out = fard_build(d, &[]);
+    assert!(stderr(&out).contains("depends on unknown step \"nope\""), "{}", stderr(&out));
+}
+
+#[test]
+fn job_count_does_not_change_the_build() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let steps: Vec<(String, String, Vec<String>)> = (0..6)
+        .map(|i| {
+            let deps = if i >= 2 { vec![format!("s{}", i - 2)] } else { vec![] };
+            (format!("s{}", i), format!("{} * 7\n", i), deps)
+        })
+        .collect();
+    let borrowed: Vec<Vec<&str>> = steps.iter().map(|s| s.2.iter().map(String::as_str).collect()).collect();
+    let spec: Vec<(&str, &str, &[&str])> =
+        steps.iter().zip(&borrowed).map(|(s, deps)| (s.0.as_str(), s.1.as_str(), deps.as_slice())).collect();
+    project(d, &spec);
+
+    assert!(fard_build(d, &["-j", "1", "--force"]).status.success());
+    let serial = receipt(d);
+    assert!(fard_build(d, &["-j", "6", "--force"]).status.success());
+    let parallel = receipt(d);
+    assert_eq!(serial["chain_digest"], parallel["chain_digest"]);
+    assert_eq!(parallel["passed"], 6);
+    assert!(stderr(&fard_build(d, &["-j", "0"])).contains("-j expects a positive number"));
+}
+
+#[test]
+fn verify_rechecks_outputs_chain_and_reexecution() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    project(d, &[("gen", "[1, 2, 3]\n", &[]), ("sum", "import(\"std/list\") as list\n\nlist.len([4, 5])\n", &["gen"])]);
+    assert!(fard_build(d, &[]).status.success());
+
+    let verify = |extra: &[&str]| {
+        let mut args = vec!["--verify"];
+        args.extend_from_slice(extra);
+        fard_build(d, &args)
+    };
+    let ok = verify(&[]);
+    assert!(ok.status.success(), "{}", stderr(&ok));
+    assert!(String::from_utf8_lossy(&ok.stdout).contains("build receipt ok"));
+    let ok = verify(&["--reexec"]);
+    assert!(ok.status.success(), "{}", stderr(&ok));
+    assert!(!d.join("build/.verify").exists());
+
+    // A tampered output no longer matches its digests.json.
+    let result = d.join("build/sum/result.json");
+    let original = fs::read(&result).unwrap();
+    fs::write(&result, b"{\"result\":3}").unwrap();
+    let bad = verify(&[]);
+    assert!(!bad.status.success());
+    assert!(stderr(&bad).contains("[sum] FAILED  result.json does not match"), "{}", stderr(&bad));
+    fs::write(&result, &original).unwrap();
+
+    // A receipt whose chain digest was edited fails even though `ok` is true.
+    let path = d.join("build/build.receipt.json");
+    let mut r = receipt(d);
+    r["chain_digest"] = serde_json::json!(format!("sha256:{}", "0".repeat(64)));
+    fs::write(&path, r.to_string()).unwrap();
+    let bad = verify(&[]);
+    assert!(!bad.status.success());
+    assert!(stderr(&bad).contains("BUILD_MANIFEST_MISMATCH chain_digest"), "{}", stderr(&bad));
+
+    // A program changed since the build still verifies from outputs but not on re-execution.
+    assert!(fard_build(d, &[]).status.success());
+    fs::write(d.join("steps/gen.fard"), "[1, 2, 3, 4]\n").unwrap();
+    assert!(verify(&[]).status.success());
+    let bad = verify(&["--reexec"]);
+    assert!(!bad.status.success());
+    assert!(stderr(&bad).contains("[gen] FAILED  re-execution produced"), "{}", stderr(&bad));
+}
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn fardcheck(program: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardcheck")).arg(program).output().unwrap()
+}
+
+fn type_errors(out: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&out.stderr)
+        .lines()
+        .filter_map(|l| l.strip_prefix("TYPE ERROR "))
+        .map(str::to_string)
+        .collect()
+}
+
+const GEO: &str = r#"import("std/str") as str
+
+a Shape is Circle(r: Int) or Square(side: Int) or Dot
+
+fn area(s: Shape) -> Int {
+  match s {
+    {r} => r * r * 3,
+    {side} => side * side,
+    _ => 0
+  }
+}
+
+fn label(name, n) {
+  str.concat(name, str.from_int(n))
+}
+
+export { area, label, Circle, Square, Dot }
+"#;
+
+#[test]
+fn imported_functions_and_stdlib_builtins_are_typed() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("lib")).unwrap();
+    fs::write(d.join("lib/geo.fard"), GEO).unwrap();
+    let main = d.join("main.fard");
+    fs::write(
+        &main,
+        r#"import("std/list") as list
+import("std/str") as str
+import("lib/geo") as geo
+
+let q = geo.label(1, 2)
+let n = str.len(42)
+let ys = list.map([1, 2, 3])
+let zs = list.map([1, 2], fn(x, i) { x })
+let w = list.unfold
+emit({ q: q, n: n })
+"#,
+    )
+    .unwrap();
+    let out = fardcheck(&main);
+    assert_eq!(out.status.code(), Some(1));
+    let m = main.display();
+    assert_eq!(
+        type_errors(&out),
+        vec![
+            format!("{}:5:19: argument 1 of geo.label: expected Text, got Int", m),
+            format!("{}:6:17: argument 1 of str.len: expected Text, got Int", m),
+            format!("{}:7:18: list.map expects 2 argument(s), got 1", m),
+            format!("{}:8:27: argument 2 of list.map: expected (Int) -> a, got (b, c) -> b", m),
+            format!("{}:9:13: module 'std/list' has no export 'unfold'", m),
+        ]
+    );
+}
+
+#[test]
+fn errors_inside_imported_modules_carry_their_own_file() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("lib")).unwrap();
+    fs::write(d.join("lib/util.fard"), "import(\"std/str\") as str\n\nfn shout(s) {\n  str.upper(s) + 1\n}\n\nexport { shout }\n").unwrap();
+    let main = d.join("main.fard");
+    fs::write(&main, "import(\"lib/util\") as util\n\nutil.shout(\"hi\")\n").unwrap();
+    let errors = type_errors(&fardcheck(&main));
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(
+        errors[0].ends_with("lib/util.fard:4:16: operator '+' expects Int or Float operands, got Text and Int"),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn declared_types_check_constructors_and_match_exhaustiveness() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("lib")).unwrap();
+    fs::write(d.join("lib/geo.fard"), GEO).unwrap();
+    let main = d.join("main.fard");
+    fs::write(
+        &main,
+        r#"import("lib/geo") as geo
+
+a Point is { x: Int, y: Int }
+
+let p = Point({ x: 1 })
+let c = geo.Circle({ r: "big" })
+let k = match c {
+  {r} => r
+}
+let m = match true {
+  true => 1
+}
+emit({ p: p, k: k, m: m })
+"#,
+    )
+    .unwrap();
+    let m = main.display();
+    assert_eq!(
+        type_errors(&fardcheck(&main)),
+        vec![
+            format!("{}:5:15: Point: missing required field(s): y", m),
+            format!("{}:6:20: field 'r' of Shape::Circle: expected Int, got Text", m),
+            format!("{}:7:9: non-exhaustive match on Shape: no arm covers Square, Dot", m),
+            format!("{}:10:9: non-exhaustive match on Bool: no arm covers false", m),
+        ]
+    );
+}
+
+#[test]
+fn strict_types_runs_fardcheck_before_executing() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let main = d.join("main.fard");
+    fs::write(&main, "import(\"std/str\") as str\n\nlet n = str.len(42)\nemit({ n: n })\n").unwrap();
+    let out_dir = d.join("out");
+    let run = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .args(["run", "--strict_types", "--program"])
+        .arg(&main)
+        .arg("--out")
+        .arg(&out_dir)
+        .output()
+        .unwrap();
+    assert_eq!(run.status.code(), Some(2), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(!out_dir.join("result.json").exists());
+    let err: serde_json::Value = serde_json::from_slice(&fs::read(out_dir.join("error.json")).unwrap()).unwrap();
+    assert_eq!(err["code"], "ERROR_TYPE");
+    let te = &err["type_errors"][0];
+    assert_eq!(te["file"], main.display().to_string());
+    assert_eq!((te["line"].as_i64(), te["col"].as_i64()), (Some(3), Some(17)));
+    assert_eq!(te["message"], "argument 1 of str.len: expected Text, got Int");
+
+    fs::write(&main, "import(\"std/str\") as str\n\nlet n = str.len(\"abc\")\nemit({ n: n })\n").unwrap();
+    let ok = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .args(["run", "--strict-types", "--program"])
+        .arg(&main)
+        .arg("--out")
+        .arg(&out_dir)
+        .output()
+        .unwrap();
+    assert!(ok.status.success(), "{}", String::from_utf8_lossy(&ok.stderr));
+}
+
+#[test]
+fn strict_types_fails_closed_when_fardcheck_cannot_run() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    // a fardrun with no fardcheck beside it or on PATH
+    let bin = d.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let fardrun = bin.join("fardrun");
+    fs::copy(env!("CARGO_BIN_EXE_fardrun"), &fardrun).unwrap();
+    let main = d.join("main.fard");
+    fs::write(&main, "emit({ n: 1 })\n").unwrap();
+    let out_dir = d.join("out");
+    let run = Command::new(&fardrun)
+        .args(["run", "--strict-types", "--program"])
+        .arg(&main)
+        .arg("--out")
+        .arg(&out_dir)
+        .env("PATH", &bin)
+        .output()
+        .unwrap();
+    assert_eq!(run.status.code(), Some(2), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(!out_dir.join("result.json").exists());
+    let err: serde_json::Value = serde_json::from_slice(&fs::read(out_dir.join("error.json")).unwrap()).unwrap();
+    assert_eq!(err["code"], "ERROR_TYPE");
+    assert!(err["message"].as_str().unwrap().starts_with("cannot type-check: cannot run fardcheck"), "{}", err);
+}
+
+mod stdlib {
+    include!(concat!(env!("OUT_DIR"), "/stdlib_exports.rs"));
+}
+
+#[test]
+fn every_stdlib_function_has_a_signature() {
+    let missing: Vec<String> = stdlib::STDLIB_EXPORTS
+        .iter()
+        .filter(|(_, _, kind)| *kind == "fn")
+        .map(|(m, name, _)| format!("{}::{}", m, name))
+        .filter(|key| !stdlib::STDLIB_SIGS.iter().any(|(k, _)| k == key))
+        .collect();
+    assert!(missing.is_empty(), "no signature in src/stdlib_types_v1.txt for: {}", missing.join(", "));
+}
+
+fn fard_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+    entries.sort();
+    for p in entries {
+        if p.is_dir() {
+            fard_files(&p, out);
+        } else if p.extension().is_some_and(|e| e == "fard") {
+            out.push(p);
+        }
+    }
+}
+
+#[test]
+fn example_programs_check_clean() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = Vec::new();
+    fard_files(&root.join("examples"), &mut files);
+    let mut checked = 0;
+    for p in &files {
+        let out = fardcheck(p);
+        // exit 2 is a parse error: not every example targets the current grammar
+        if out.status.code() == Some(2) {
+            continue;
+        }
+        assert!(out.status.success(), "{}: {:?}", p.display(), type_errors(&out));
+        checked += 1;
+    }
+    assert!(checked >= 10, "only {} examples checked", checked);
+}
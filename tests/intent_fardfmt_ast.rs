@@ -0,0 +1,188 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+mod common;
+use common::tmpdir;
+
+fn fardfmt(src: &str, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fardfmt"))
+        .arg("--stdin")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(src.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn parses(dir: &Path, src: &str) -> bool {
+    let p = dir.join("main.fard");
+    fs::write(&p, src).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .args(["check", "--program", p.to_str().unwrap()])
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+/// fardrun's own parse of `src`, printed without source positions.
+fn fardrun_ast(dir: &Path, src: &str) -> Vec<u8> {
+    let p = dir.join("main.fard");
+    fs::write(&p, src).unwrap();
+    let o = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .args(["check", "--ast", "--program", p.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(o.status.success() && !o.stdout.is_empty(), "{}", String::from_utf8_lossy(&o.stderr));
+    o.stdout
+}
+
+fn fard_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+    entries.sort();
+    for p in entries {
+        if p.is_dir() {
+            fard_files(&p, out);
+        } else if p.extension().is_some_and(|e| e == "fard") {
+            out.push(p);
+        }
+    }
+}
+
+fn outside_strings_and_comments(line: &str) -> bool {
+    !line.contains('"') && !line.contains('`') && !line.contains("//") && !line.contains('#')
+}
+
+#[test]
+fn formatting_is_idempotent_and_preserves_the_ast_over_examples_and_packages() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = Vec::new();
+    fard_files(&root.join("examples"), &mut files);
+    fard_files(&root.join("packages"), &mut files);
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let mut formatted_count = 0;
+    for f in &files {
+        let src = fs::read_to_string(f).unwrap();
+        let name = f.strip_prefix(root).unwrap().display();
+        let out = fardfmt(&src, &[]);
+        if !parses(d, &src) {
+            // fardfmt parses exactly what fardrun parses, and never rewrites what it cannot
+            assert!(!out.status.success(), "{}: fardfmt accepted a file fardrun rejects", name);
+            assert!(out.stdout.is_empty(), "{}", name);
+            continue;
+        }
+        assert!(out.status.success(), "{}: {}", name, String::from_utf8_lossy(&out.stderr));
+        let once = String::from_utf8(out.stdout).unwrap();
+
+        let twice = fardfmt(&once, &[]);
+        assert!(twice.status.success(), "{}", name);
+        assert_eq!(String::from_utf8(twice.stdout).unwrap(), once, "{}: fmt(fmt(x)) != fmt(x)", name);
+
+        assert!(parses(d, &once), "{}: fardrun rejects the formatted output", name);
+        // The trees come from fardrun's parser, not fardfmt's.
+        assert!(fardrun_ast(d, &src) == fardrun_ast(d, &once), "{}: formatting changed the AST", name);
+        for line in once.lines() {
+            assert!(
+                line.chars().count() <= 100 || !outside_strings_and_comments(line),
+                "{}: line over 100 columns: {}",
+                name,
+                line
+            );
+        }
+        formatted_count += 1;
+    }
+    assert!(formatted_count >= 20, "only {} files formatted", formatted_count);
+}
+
+#[test]
+fn comments_stay_attached_to_their_nodes() {
+    let src = r#"# header
+import("std/list") as list // why list
+
+
+fn f(a, # about a
+     b) {
+  # inside
+  let x = [1, # one
+    2,
+    # before three
+    3]
+  match x { [] => 0, # empty
+    _ => 1
+    # after the last arm
+  }
+}
+// end
+"#;
+    let out = fardfmt(src, &[]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        r#"# header
+import("std/list") as list // why list
+
+fn f(
+  a, # about a
+  b
+) {
+  # inside
+  let x = [
+    1, # one
+    2,
+    # before three
+    3
+  ]
+  match x {
+    [] => 0, # empty
+    _  => 1
+    # after the last arm
+  }
+}
+// end
+"#
+    );
+}
+
+#[test]
+fn range_formats_only_the_touched_items() {
+    let src = "fn a(x) {  x }\nlet b = [1,2,   3]\n\n\nfn c(y) {   y }\n";
+    let out = fardfmt(src, &["--range", "2:2"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "fn a(x) {  x }\nlet b = [1, 2, 3]\n\n\nfn c(y) {   y }\n"
+    );
+
+    let whole = fardfmt(src, &["--range", "1:5"]);
+    assert_eq!(String::from_utf8(whole.stdout).unwrap(), "fn a(x) { x }\nlet b = [1, 2, 3]\n\nfn c(y) { y }\n");
+
+    let bad = fardfmt(src, &["--range", "3:1"]);
+    assert_eq!(bad.status.code(), Some(2));
+}
+
+#[test]
+fn long_calls_break_one_argument_per_line() {
+    let src = "let r = some_function_name(first_argument_value, second_argument_value, third_argument_value_long_name)\n";
+    let out = fardfmt(src, &[]);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "let r = some_function_name(\n  first_argument_value,\n  second_argument_value,\n  third_argument_value_long_name\n)\n"
+    );
+}
+
+#[test]
+fn fardrun_ast_ignores_layout_and_comments_only() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let base = fardrun_ast(d, "fn f(a) { a * 2 }\nlet x = f(1 + 2)\nx\n");
+    assert!(String::from_utf8_lossy(&base).contains("ErrorSpan"));
+    assert!(!String::from_utf8_lossy(&base).contains("byte_start"));
+    assert_eq!(base, fardrun_ast(d, "# doubles\nfn f(a) {\n  a * 2\n}\n\n\nlet x = f(1+2) // three\nx\n"));
+    assert_ne!(base, fardrun_ast(d, "fn f(a) { a * 2 }\nlet x = f(1) + 2\nx\n"));
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn check(src: &str) -> (Output, Vec<serde_json::Value>) {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("main.fard"), src).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["check", "--program", "main.fard"])
+        .output()
+        .unwrap();
+    let diags = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    (out, diags)
+}
+
+#[test]
+fn check_parses_without_running() {
+    let (out, diags) = check("import(\"std/trace\") as trace\nlet _ = trace.emit({a: 1})\n1 / 0\n");
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(diags.is_empty());
+}
+
+#[test]
+fn check_reports_parse_error_span() {
+    let (out, diags) = check("let a = 1\nlet b = (a +\n");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0]["code"], "ERROR_PARSE");
+    assert_eq!(diags[0]["span"]["line"], 3);
+}
+
+#[test]
+fn check_reports_lex_error_at_offending_token() {
+    let (out, diags) = check("let a = 1\nlet b = @\n");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(diags[0]["span"]["line"], 2);
+    assert_eq!(diags[0]["span"]["col"], 9);
+}
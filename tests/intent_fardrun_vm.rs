@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run(dir: &Path, src: &str, out: &str, no_vm: bool) -> Output {
+    let main = dir.join("main.fard");
+    fs::write(&main, src).unwrap();
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_fardrun"));
+    cmd.args(["run", "--vm-stats", "--program"]).arg(&main).arg("--out").arg(dir.join(out));
+    if no_vm {
+        cmd.env("FARD_NO_VM", "1");
+    }
+    cmd.output().unwrap()
+}
+
+fn read_json(p: PathBuf) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(&p).unwrap_or_else(|_| panic!("missing {}", p.display()))).unwrap()
+}
+
+const PROGRAM: &str = r#"import("std/list") as list
+import("std/str") as str
+import("std/trace") as trace
+
+fn ev(n) { if n == 0 then true else od(n - 1) }
+fn od(n) { if n == 0 then false else ev(n - 1) }
+fn add(x, y) { x + y }
+fn tick(n) {
+  let _ = trace.emit({tick: n})
+  n * 2
+}
+fn area(s) {
+  match s {
+    {r} => r * r * 3,
+    {side} if side > 0 => side * side,
+    [a, b, ...rest] => a + b + list.len(rest),
+    _ => 0
+  }
+}
+fn adder(k) { fn(x) { x + k } }
+fn count(n, acc) { if n == 0 then acc else count(n - 1, acc + 1) }
+fn first({a: x, b}) { x + b }
+fn safe_div(a, b) {
+  let q = if b == 0 then { t: "err", e: "div0" } else { t: "ok", v: a / b }
+  let v = q?
+  { t: "ok", v: v * 10 }
+}
+fn early(n) { if n > 3 then return "big" else "small" }
+let xs = list.map([1, 2, 3], fn(x) { x * 2 })
+let nested = fn(a) { fn(b) { fn(c) { a + b + c } } }
+let name = "fard"
+let w = while 0 fn(s) { s < 5 } fn(s) { s + 1 }
+let {p, q} = {p: 1, q: 2}
+{
+  even: ev(10),
+  named: add(y: 2, x: 1),
+  area: [area({r: 2}), area({side: 3}), area({side: -1}), area([1, 2, 3, 4])],
+  add5: adder(5)(10),
+  tail: count(200000, 0),
+  first: first({a: 1, b: 2}),
+  div: [safe_div(10, 2), safe_div(1, 0)],
+  early: [early(5), early(1)],
+  nested: nested(1)(2)(3),
+  interp: "hi ${name} ${1 + 2} ${xs}",
+  idx: xs[1],
+  w: w.value,
+  pq: p + q,
+  method: xs.map(fn(x) { x + 1 }),
+  upper: str.upper(name),
+  ticks: list.map([1, 2, 3], tick)
+}
+"#;
+
+#[test]
+fn whole_programs_run_on_the_vm_and_match_the_tree_walker() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let vm = run(d, PROGRAM, "vm", false);
+    assert!(vm.status.success(), "{}", String::from_utf8_lossy(&vm.stderr));
+    let tw = run(d, PROGRAM, "tw", true);
+    assert!(tw.status.success(), "{}", String::from_utf8_lossy(&tw.stderr));
+
+    let result = read_json(d.join("vm/result.json"));
+    assert_eq!(result, read_json(d.join("tw/result.json")));
+    for f in ["trace.ndjson", "digests.json"] {
+        let vm_bytes = fs::read(d.join("vm").join(f)).unwrap();
+        assert_eq!(vm_bytes, fs::read(d.join("tw").join(f)).unwrap(), "{f} differs between the VM and the tree-walker");
+    }
+    let trace = String::from_utf8(fs::read(d.join("vm/trace.ndjson")).unwrap()).unwrap();
+    assert_eq!(trace.matches(r#""tick":"#).count(), 3, "{trace}");
+    let r = &result["result"];
+    assert_eq!(r["even"], true);
+    assert_eq!(r["named"], 3);
+    assert_eq!(r["area"], serde_json::json!([12, 9, 0, 5]));
+    assert_eq!(r["add5"], 15);
+    assert_eq!(r["tail"], 200000);
+    assert_eq!(r["div"], serde_json::json!([{"t": "ok", "v": 50}, {"t": "err", "e": "div0"}]));
+    assert_eq!(r["early"], serde_json::json!(["big", "small"]));
+    assert_eq!(r["interp"], "hi fard 3 [2,4,6]");
+    assert_eq!(r["method"], serde_json::json!([3, 5, 7]));
+    assert_eq!(r["ticks"], serde_json::json!([2, 4, 6]));
+
+    let stats = read_json(d.join("vm/vm_stats.json"));
+    assert_eq!(stats["units"], 18);
+    assert_eq!(stats["compiled"], 18);
+    assert_eq!(stats["fallbacks"], serde_json::json!([]));
+    assert_eq!(stats["tree_calls"], 0);
+    assert!(stats["vm_calls"].as_i64().unwrap() > 200000);
+    let stderr = String::from_utf8_lossy(&vm.stderr);
+    assert!(stderr.contains("vm: 18 of 18 units compiled, 0 fell back to the tree-walker"), "{}", stderr);
+}
+
+#[test]
+fn vm_stats_lists_every_fallback_with_its_reason() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let out = run(d, "fn sq(x) { x * x }\nlet y = sq(4)\ny\n", "tw", true);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("vm: 0 of 3 units compiled, 3 fell back to the tree-walker"), "{}", stderr);
+    assert!(stderr.contains(": fn sq: disabled by FARD_NO_VM"), "{}", stderr);
+
+    let stats = read_json(d.join("tw/vm_stats.json"));
+    let fallbacks = stats["fallbacks"].as_array().unwrap();
+    let units: Vec<&str> = fallbacks.iter().map(|f| f["unit"].as_str().unwrap()).collect();
+    assert_eq!(units, ["fn sq", "let y", "expr at line 3"]);
+    assert_eq!(fallbacks[0]["reason"], "disabled by FARD_NO_VM");
+    assert_eq!(stats["vm_calls"], 0);
+    assert_eq!(stats["tree_calls"], 1);
+}
+
+#[test]
+fn runtime_errors_are_the_same_on_both_engines() {
+    let cases = [
+        ("fn f(x) { if x then 1 else 2 }\nf(3)\n", "if cond must be bool"),
+        ("fn f(x, y) { x }\nf(1)\n", "arity mismatch: expected 2 args, got 1"),
+        ("fn f(x) { match x { 1 => 1 } }\nf(2)\n", "ERROR_MATCH_NO_ARM"),
+        ("fn f([a, b]) { a }\nf(3)\n", "ERROR_PAT_MISMATCH"),
+        ("fn f(x) { x + \"a\" }\nf(1)\n", "bad binop +"),
+        ("fn f(r) { r.nme }\nf({name: 1})\n", "did you mean 'name'"),
+        ("fn f(x) { undefined_name }\nf(1)\n", "unbound var: undefined_name"),
+        ("fn f(x) { x }\nf(z: 1)\n", "named arg 'z' not found"),
+    ];
+    for (src, want) in cases.iter() {
+        let tmp = tmpdir();
+        let d = tmp.path();
+        let vm = run(d, src, "vm", false);
+        let tw = run(d, src, "tw", true);
+        assert!(!vm.status.success() && !tw.status.success(), "{}", src);
+        let e_vm = read_json(d.join("vm/error.json"));
+        let e_tw = read_json(d.join("tw/error.json"));
+        assert_eq!(e_vm, e_tw, "{}", src);
+        assert!(e_vm["message"].as_str().unwrap().contains(want), "{}: {}", src, e_vm["message"]);
+    }
+}
+
+#[test]
+fn guard_errors_inside_functions_keep_their_span() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let out = run(d, "fn f(x) { match x { y if y + 1 => 1, _ => 2 } }\nf(5)\n", "vm", false);
+    assert!(!out.status.success());
+    let err = read_json(d.join("vm/error.json"));
+    assert_eq!(err["code"], "ERROR_RUNTIME");
+    assert_eq!(err["span"]["line"], 1);
+    assert_eq!(err["span"]["col"], 26);
+}
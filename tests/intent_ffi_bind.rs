@@ -0,0 +1,195 @@
+
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run_program(prog: &Path, out: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .arg("run")
+        .arg("--program")
+        .arg(prog)
+        .arg("--out")
+        .arg(out)
+        .output()
+        .unwrap()
+}
+
+const LIB_C: &str = r#"
+#include <stdint.h>
+#include <stddef.h>
+double mix(int64_t a, double b, int32_t c, float d) { return a + b + c + d; }
+int64_t xor_into(const uint8_t *src, size_t n, uint8_t *dst, size_t m, int64_t *count) {
+  size_t k = n < m ? n : m;
+  for (size_t i = 0; i < k; i++) dst[i] = src[i] ^ 0xff;
+  *count += (int64_t)k;
+  return (int64_t)k;
+}
+double spill(double a, double b, double c, double d, double e, double f, double g, double h,
+             double i, int64_t x1, int64_t x2, int64_t x3, int64_t x4, int64_t x5, int64_t x6,
+             int64_t x7, int64_t x8, int64_t x9) {
+  return a + b + c + d + e + f + g + h + i + x1 + x2 + x3 + x4 + x5 + x6 + x7 + x8 + x9;
+}
+/* Folds its arguments in order, so any argument landing in the wrong place changes the result. */
+int64_t many(int64_t a0, double b0, int64_t a1, double b1, int64_t a2, double b2, int64_t a3, double b3,
+             int64_t a4, double b4, int64_t a5, double b5, int64_t a6, double b6, int64_t a7, double b7,
+             int64_t a8, double b8, int64_t a9, double b9, int32_t c, float d) {
+  int64_t as[] = {a0, a1, a2, a3, a4, a5, a6, a7, a8, a9};
+  double bs[] = {b0, b1, b2, b3, b4, b5, b6, b7, b8, b9};
+  uint64_t h = 0;
+  for (int k = 0; k < 10; k++) h = (h * 31 + (uint64_t)as[k]) * 31 + (uint64_t)(bs[k] * 4);
+  return (int64_t)((h * 31 + (uint64_t)c) * 31 + (uint64_t)(d * 4));
+}
+"#;
+
+/// `many` in Rust, for the arguments the test passes.
+fn many_expected() -> i64 {
+    let step = |h: u64, x: u64| h.wrapping_mul(31).wrapping_add(x);
+    let mut h = 0u64;
+    for k in 0..10 {
+        h = step(step(h, 100 + k), k); // a_k = 100 + k, b_k = k / 4
+    }
+    step(step(h, 7), 3) as i64 // c = 7, d = 0.75
+}
+
+#[test]
+fn ffi_bind_marshals_floats_buffers_and_out_params() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("lib.c"), LIB_C).unwrap();
+    let lib = d.join("libfardt.so");
+    let cc = Command::new("cc")
+        .args(["-shared", "-fPIC", "-o"])
+        .arg(&lib)
+        .arg(d.join("lib.c"))
+        .status();
+    if !matches!(cc, Ok(s) if s.success()) {
+        eprintln!("skipping: no C compiler");
+        return;
+    }
+
+    let prog = d.join("main.fard");
+    fs::write(
+        &prog,
+        format!(
+            r#"import("std/ffi") as ffi
+import("std/bytes") as bytes
+let m = ffi.open("libm.so.6")
+let pow = ffi.bind(m.ok, "pow", {{args: ["f64", "f64"], ret: "f64"}})
+let sqrtf = ffi.bind(m.ok, "sqrtf", {{args: ["f32"], ret: "f32"}})
+let t = ffi.open("{}")
+let mix = ffi.bind(t.ok, "mix", {{args: ["i64", "f64", "i32", "f32"], ret: "f64"}})
+let xor_into = ffi.bind(t.ok, "xor_into", {{args: ["bytes", "out_bytes", "out_i64"], ret: "i64"}})
+let spill = ffi.bind(t.ok, "spill", {{args: ["f64", "f64", "f64", "f64", "f64", "f64", "f64", "f64", "f64",
+  "i64", "i64", "i64", "i64", "i64", "i64", "i64", "i64", "i64"], ret: "f64"}})
+let many = ffi.bind(t.ok, "many", {{args: ["i64", "f64", "i64", "f64", "i64", "f64", "i64", "f64", "i64", "f64",
+  "i64", "f64", "i64", "f64", "i64", "f64", "i64", "f64", "i64", "f64", "i32", "f32"], ret: "i64"}})
+let c = ffi.open("libc.so.6")
+let snprintf = ffi.bind(c.ok, "snprintf", {{args: ["out_bytes", "text", "i64", "f64", "text"], ret: "i32", fixed: 2}})
+let printed = snprintf(32, "%ld|%.2f|%s", 42, 2.5, "end")
+let x = xor_into(bytes.of_list([1, 2, 3]), 2, 40)
+{{ pow: pow(2.0, 10.0), sqrt: sqrtf(2.25), mix: mix(1, 2.5, 3, 0.5),
+   ret: x.ret, dst: bytes.to_list(x.out[1]), count: x.out[2],
+   spill: spill(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 1, 2, 3, 4, 5, 6, 7, 8, 9),
+   many: many(100, 0.0, 101, 0.25, 102, 0.5, 103, 0.75, 104, 1.0, 105, 1.25, 106, 1.5, 107, 1.75, 108, 2.0, 109, 2.25, 7, 0.75),
+   printed: printed.ret, text: bytes.to_list(printed.out[0]) }}
+"#,
+            lib.display()
+        ),
+    )
+    .unwrap();
+
+    let out = d.join("out");
+    let o = run_program(&prog, &out);
+    assert!(o.status.success(), "run failed: {}", String::from_utf8_lossy(&o.stderr));
+    let v: serde_json::Value =
+        serde_json::from_slice(&fs::read(out.join("result.json")).unwrap()).unwrap();
+    let r = &v["result"];
+    assert_eq!(r["pow"].as_f64(), Some(1024.0));
+    assert_eq!(r["sqrt"].as_f64(), Some(1.5));
+    assert_eq!(r["mix"].as_f64(), Some(7.0));
+    assert_eq!(r["ret"], 2);
+    assert_eq!(r["dst"], serde_json::json!([254, 253]));
+    assert_eq!(r["count"], 42);
+    assert_eq!(r["spill"].as_f64(), Some(90.0));
+    assert_eq!(r["many"].as_i64(), Some(many_expected()));
+    // A variadic callee gets the C variadic calling convention.
+    assert_eq!(r["printed"], 11);
+    let text: Vec<u8> = r["text"].as_array().unwrap().iter().map(|b| b.as_u64().unwrap() as u8).collect();
+    assert_eq!(&text[..12], b"42|2.50|end\0");
+
+    let trace = fs::read_to_string(out.join("trace.ndjson")).unwrap();
+    assert_eq!(trace.matches(r#""t":"ffi_oracle""#).count(), 7);
+}
+
+#[test]
+fn ffi_bind_rejects_bad_signature() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let prog = d.join("main.fard");
+    fs::write(
+        &prog,
+        r#"import("std/ffi") as ffi
+let m = ffi.open("libm.so.6")
+ffi.bind(m.ok, "pow", {args: ["f64", "complex"], ret: "f64"})
+"#,
+    )
+    .unwrap();
+    let out = d.join("out");
+    let o = run_program(&prog, &out);
+    assert!(!o.status.success());
+    let e: serde_json::Value =
+        serde_json::from_slice(&fs::read(out.join("error.json")).unwrap()).unwrap();
+    assert_eq!(e["code"], "ERROR_FFI");
+
+    // C promotes a float passed to `...` to double, so the signature must say so.
+    fs::write(
+        &prog,
+        r#"import("std/ffi") as ffi
+let c = ffi.open("libc.so.6")
+ffi.bind(c.ok, "printf", {args: ["text", "f32"], ret: "i32", fixed: 1})
+"#,
+    )
+    .unwrap();
+    let o = run_program(&prog, &out);
+    assert!(!o.status.success());
+    let e: serde_json::Value =
+        serde_json::from_slice(&fs::read(out.join("error.json")).unwrap()).unwrap();
+    assert_eq!(e["code"], "ERROR_FFI");
+    assert!(e["message"].as_str().unwrap().contains("variadic"), "{}", e);
+}
+
+#[test]
+fn ffi_trace_events_escape_symbols_and_reasons() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let prog = d.join("main.fard");
+    fs::write(
+        &prog,
+        r#"import("std/ffi") as ffi
+let c = ffi.open("libc.so.6")
+let bad = ffi.call(c.ok, "no\"such\\sym", [])
+let ok = ffi.call_checked(c.ok, "abs", [-3], "quote \" backslash \\ newline \n end")
+{ bad: bad.t, ok: ok.ok }
+"#,
+    )
+    .unwrap();
+    let out = d.join("out");
+    let o = run_program(&prog, &out);
+    assert!(o.status.success(), "run failed: {}", String::from_utf8_lossy(&o.stderr));
+
+    let trace = fs::read_to_string(out.join("trace.ndjson")).unwrap();
+    let evs: Vec<serde_json::Value> = trace
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap_or_else(|e| panic!("bad trace line {l}: {e}")))
+        .collect();
+    let oracle = evs.iter().find(|e| e["t"] == "ffi_oracle").unwrap();
+    assert_eq!(oracle["symbol"], "no\"such\\sym");
+    let checked = evs.iter().find(|e| e["t"] == "ffi_checked").unwrap();
+    assert_eq!(checked["reason"], "quote \" backslash \\ newline \n end");
+    assert_eq!(checked["result"], 3);
+}
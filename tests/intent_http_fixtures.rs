@@ -0,0 +1,214 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run_program(prog: &Path, out: &Path, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .arg("run")
+        .arg("--program")
+        .arg(prog)
+        .arg("--out")
+        .arg(out)
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn fixture_events(out: &Path) -> Vec<serde_json::Value> {
+    fs::read_to_string(out.join("trace.ndjson"))
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .filter(|v| v["t"] == "http_fixture")
+        .collect()
+}
+
+/// Answer `n` requests with `<METHOD> <path> <body>`, then stop listening.
+fn serve(n: usize) -> (u16, std::thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let h = std::thread::spawn(move || {
+        for stream in listener.incoming().take(n) {
+            let mut stream = stream.unwrap();
+            let mut r = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            r.read_line(&mut line).unwrap();
+            let mut parts = line.split_whitespace();
+            let (method, path) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+            let mut len = 0;
+            loop {
+                let mut h = String::new();
+                r.read_line(&mut h).unwrap();
+                if h.trim().is_empty() {
+                    break;
+                }
+                if let Some((k, v)) = h.split_once(':') {
+                    if k.eq_ignore_ascii_case("content-length") {
+                        len = v.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; len];
+            r.read_exact(&mut body).unwrap();
+            let reply = format!("{} {} {}", method, path, String::from_utf8_lossy(&body));
+            let status = if path == "/missing" { "404 Not Found" } else { "200 OK" };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nX-Fixture: \"yes\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                reply.len(),
+                reply
+            )
+            .unwrap();
+        }
+    });
+    (port, h)
+}
+
+#[test]
+fn captured_responses_are_served_offline() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let (port, server) = serve(4);
+    let prog = d.join("main.fard");
+    fs::write(
+        &prog,
+        format!(
+            r#"import("std/http") as http
+let base = "http://127.0.0.1:{}"
+let a = http.get("${{base}}/users/1")
+let b = http.post("${{base}}/echo", "hello\nworld")
+let c = http.request({{ method: "put", url: "${{base}}/echo", body: "x", headers: {{ "x-k": "v" }} }})
+let e = http.get("${{base}}/missing")
+{{ a: a, b: b.body, c: c.status, e: e.status }}
+"#,
+            port
+        ),
+    )
+    .unwrap();
+    let fx = d.join("fixtures");
+    let fx_arg = fx.to_string_lossy().to_string();
+
+    let cap = d.join("cap");
+    let out = run_program(&prog, &cap, &["--http-fixtures", &fx_arg, "--http-capture"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    server.join().unwrap();
+
+    let captured = fs::read_to_string(fx.join("captured.toml")).unwrap();
+    assert_eq!(captured.matches("[[http_fixtures]]").count(), 4, "{}", captured);
+    assert!(captured.contains("body = \"POST /echo hello\\nworld\""), "{}", captured);
+    assert!(captured.contains("\"x-fixture\" = \"\\\"yes\\\"\""), "{}", captured);
+
+    let result: serde_json::Value = serde_json::from_slice(&fs::read(cap.join("result.json")).unwrap()).unwrap();
+    let r = &result["result"];
+    assert_eq!(r["a"]["status"], 200);
+    assert_eq!(r["a"]["body"], "GET /users/1 ");
+    assert_eq!(r["a"]["headers"]["content-type"], "text/plain");
+    assert_eq!(r["b"], "POST /echo hello\nworld");
+    assert_eq!(r["c"], 200);
+    assert_eq!(r["e"], 404);
+
+    // The server is gone: the same program now runs from the fixtures alone.
+    let srv = d.join("srv");
+    let out = run_program(&prog, &srv, &["--http-fixtures", &fx_arg]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(fs::read(cap.join("result.json")).unwrap(), fs::read(srv.join("result.json")).unwrap());
+
+    let served = fixture_events(&srv);
+    assert_eq!(served, fixture_events(&cap));
+    let ops: Vec<&str> = served.iter().map(|e| e["op"].as_str().unwrap()).collect();
+    assert_eq!(ops, ["http.get", "http.post", "http.request", "http.get"]);
+    assert_eq!(served[2]["method"], "PUT");
+    assert!(served.iter().all(|e| e["cid"].as_str().unwrap().starts_with("sha256:")));
+    assert_ne!(served[0]["cid"], served[3]["cid"]);
+
+    let verify = Command::new(env!("CARGO_BIN_EXE_fardverify"))
+        .args(["trace", "--out"])
+        .arg(&srv)
+        .output()
+        .unwrap();
+    assert!(verify.status.success(), "{}", String::from_utf8_lossy(&verify.stderr));
+}
+
+const FIXTURES: &str = r#"# hand-written
+[[http_fixtures]]  # user 1
+method = "GET"
+url = 'https://api.example.com/users/1'   # a literal string
+status = 200
+body = "{\"id\": 1, \"name\": \"ada\"}"
+
+[http_fixtures.headers]
+content-type = "application/json"
+"#;
+
+#[test]
+fn unmatched_requests_fail_and_fardrun_test_uses_fixtures() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let fx = d.join("fx");
+    fs::create_dir_all(&fx).unwrap();
+    fs::write(fx.join("users.toml"), FIXTURES).unwrap();
+    let fx_arg = fx.to_string_lossy().to_string();
+
+    let prog = d.join("main.fard");
+    fs::write(
+        &prog,
+        "import(\"std/http\") as http\nimport(\"std/json\") as json\nlet r = http.get(\"https://api.example.com/users/1\")\njson.decode(r.body).name\n",
+    )
+    .unwrap();
+    let out = run_program(&prog, &d.join("ok"), &["--http-fixtures", &fx_arg]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let result = fs::read_to_string(d.join("ok/result.json")).unwrap();
+    assert!(result.contains("\"ada\""), "{}", result);
+    assert_eq!(fixture_events(&d.join("ok")).len(), 1);
+
+    let miss = d.join("miss.fard");
+    fs::write(&miss, "import(\"std/http\") as http\nhttp.post(\"https://api.example.com/users/1\", \"x\")\n").unwrap();
+    let out = run_program(&miss, &d.join("miss"), &["--http-fixtures", &fx_arg]);
+    assert!(!out.status.success());
+    let err = fs::read_to_string(d.join("miss/error.json")).unwrap();
+    assert!(err.contains("ERROR_HTTP_FIXTURE no fixture for POST https://api.example.com/users/1"), "{}", err);
+
+    let tests = d.join("users_test.fard");
+    fs::write(
+        &tests,
+        "import(\"std/http\") as http\ntest \"user 1\" { http.get(\"https://api.example.com/users/1\").status == 200 }\ntest \"content type\" { http.get(\"https://api.example.com/users/1\").headers[\"content-type\"] == \"application/json\" }\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .args(["test", "--json", "--program"])
+        .arg(&tests)
+        .args(["--http-fixtures", &fx_arg])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", stdout);
+    assert!(stdout.contains("\"passed\":2"), "{}", stdout);
+}
+
+#[test]
+fn malformed_fixture_files_are_rejected() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let fx = d.join("fx");
+    fs::create_dir_all(&fx).unwrap();
+    fs::write(fx.join("a.toml"), FIXTURES).unwrap();
+    fs::write(fx.join("b.toml"), FIXTURES).unwrap();
+    let prog = d.join("main.fard");
+    fs::write(&prog, "1\n").unwrap();
+    let fx_arg = fx.to_string_lossy().to_string();
+    let out = run_program(&prog, &d.join("dup"), &["--http-fixtures", &fx_arg]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("duplicate fixture for GET https://api.example.com/users/1 in a.toml and b.toml"), "{}", stderr);
+
+    fs::write(fx.join("b.toml"), "[[http_fixtures]]\nmethod = \"GET\"\nurl = \"https://x\"\nstatus = \"ok\"\n").unwrap();
+    let out = run_program(&prog, &d.join("bad"), &["--http-fixtures", &fx_arg]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("b.toml:4 status must be an int"));
+}
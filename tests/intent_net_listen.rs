@@ -0,0 +1,213 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{fardrun, result, tmpdir, verify_trace};
+
+/// One HTTP/1.1 exchange over a fresh connection: (status, lowercased head, body).
+fn exchange(addr: &str, method: &str, path: &str, body: &[u8]) -> (u16, String, Vec<u8>) {
+    let mut s = TcpStream::connect(addr).unwrap();
+    s.set_read_timeout(Some(Duration::from_secs(20))).unwrap();
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        path,
+        body.len()
+    );
+    s.write_all(head.as_bytes()).unwrap();
+    s.write_all(body).unwrap();
+    let mut raw = Vec::new();
+    s.read_to_end(&mut raw).unwrap();
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8_lossy(&raw[..split]).to_lowercase();
+    let status = head[9..12].parse().unwrap();
+    (status, head, raw[split + 4..].to_vec())
+}
+
+const PACKAGES: &str = r#"import("std/str") as str
+import("std/http") as http
+import("std/chan") as chan
+import("std/list") as list
+import("std/promise") as promise
+import("http_server") as hs
+import("web") as web
+
+let c = chan.new()
+fn released(got) { "released" }
+fn sent(x) { "sent" }
+fn chunk_of(m) { if m == null then null else m.v }
+fn stream(req) {
+  let q = chan.new()
+  let _ = list.map(["one,", "two,", "three"], fn(x) { chan.send(q, x) })
+  { status: 200, body: fn() { chunk_of(chan.try_recv(q)) } }
+}
+fn slow(req) { hs.ok(str.from_int(list.fold(list.range(0, 3000000), 0, fn(acc, x) { acc + x }))) }
+
+let routes = [
+  hs.get_route("/wait", fn(req) { hs.ok(released(chan.recv(c))) }),
+  hs.get_route("/send", fn(req) { hs.ok(sent(chan.send(c, 1))) }),
+  hs.get_route("/stream", stream),
+  hs.get_route("/slow", slow)
+]
+let srv = hs.listen_router({ workers: 2, timeout_ms: 200, receipts: true }, routes)?
+let base = str.concat("http://", srv.addr)
+let waiting = promise.spawn(fn() { http.get(str.concat(base, "/wait")) })
+let send = http.get(str.concat(base, "/send?n=1"))
+let wait = promise.await(waiting)
+let streamed = http.get(str.concat(base, "/stream"))
+let slowed = http.get(str.concat(base, "/slow"))
+let missing = http.get(str.concat(base, "/missing"))
+let stopped = hs.stop(srv)?
+
+let application = web.app({ routes: [web.get("/users/:id", fn(req) { web.ok({ id: web.req_param(req, "id"), full: web.req_query(req, "full") }) })] })
+let w = web.start(application, { addr: "127.0.0.1" })?
+let user = http.get(str.concat(str.concat("http://", w.addr), "/users/7?full=a%20b"))
+let _ = web.stop(w)?
+{
+  wait: wait.body, send: send.body, stream: streamed.body, slow: slowed.status,
+  missing: missing.status, stopped: stopped, user: user.body
+}
+"#;
+
+#[test]
+fn packages_serve_concurrently_in_process_and_stop() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::copy("packages/http-server/main.fard", d.join("http_server.fard")).unwrap();
+    fs::copy("packages/fard-web/main.fard", d.join("web.fard")).unwrap();
+    let o = fardrun(d, PACKAGES, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    // /wait only returns once /send has run beside it on the second worker.
+    assert_eq!((r["wait"].as_str(), r["send"].as_str()), (Some("released"), Some("sent")));
+    assert_eq!(r["stream"], "one,two,three");
+    assert_eq!(r["slow"], 504);
+    assert_eq!(r["missing"], 404);
+    assert_eq!(r["stopped"]["requests"], 5);
+    assert!(r["stopped"]["receipts_root"].as_str().unwrap().starts_with("sha256:"));
+    assert_eq!(r["user"], r#"{"full":"a b","id":"7"}"#);
+
+    let receipt = |n: u64| -> serde_json::Value {
+        let p = d.join(format!("out/net/1/req_{}.json", n));
+        serde_json::from_slice(&fs::read(p).unwrap()).unwrap()
+    };
+    let all: Vec<serde_json::Value> = (1..=5).map(receipt).collect();
+    let slow = all.iter().find(|x| x["url"] == "/slow").unwrap();
+    assert_eq!(slow["status"], 504);
+    assert!(slow["error"].as_str().unwrap().contains("timed out after 200 ms"));
+    assert!(all.iter().all(|x| x["kind"] == "fard/net_receipt/v1"));
+    assert!(!d.join("out/net/2").exists(), "receipts are opt-in");
+    verify_trace(d);
+}
+
+const ECHO: &str = r#"import("std/net") as net
+import("std/fs") as fs
+
+fn echo(req) { { status: 200, headers: { "X-Method": req.method }, body: req.body } }
+let srv = net.listen({ handler: echo, workers: 3, max_body: 64, max_response: 32, receipts: true })?
+let _ = fs.write_text("addr.txt", srv.addr)
+fn wait() { if fs.exists("stop") then null else wait() }
+let _ = wait()
+net.stop(srv)?
+"#;
+
+#[test]
+fn binary_bodies_limits_receipts_and_graceful_stop() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("main.fard"), ECHO).unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["run", "--program", "main.fard", "--out", "out"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let t0 = Instant::now();
+    let addr = loop {
+        if let Ok(a) = fs::read_to_string(d.join("addr.txt")) {
+            break a;
+        }
+        assert!(t0.elapsed() < Duration::from_secs(30), "server never came up");
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let binary = [0u8, 159, 146, 150, 255];
+    let (status, head, body) = exchange(&addr, "POST", "/bin", &binary);
+    assert_eq!((status, body.as_slice()), (200, &binary[..]));
+    assert!(head.contains("content-type: application/octet-stream"), "{}", head);
+    assert!(head.contains("x-method: post"), "{}", head);
+
+    let (status, head, body) = exchange(&addr, "PUT", "/text", b"hello");
+    assert_eq!((status, body.as_slice()), (200, &b"hello"[..]));
+    assert!(head.contains("content-type: text/plain"), "{}", head);
+    let (status, _, _) = exchange(&addr, "PUT", "/text", b"hello");
+    assert_eq!(status, 200);
+
+    let (status, _, body) = exchange(&addr, "POST", "/big", &[b'x'; 100]);
+    assert_eq!(status, 413);
+    assert!(String::from_utf8_lossy(&body).contains("max_body (64 bytes)"));
+    let (status, _, body) = exchange(&addr, "POST", "/echo", &[b'y'; 40]);
+    assert_eq!(status, 500);
+    assert!(String::from_utf8_lossy(&body).contains("max_response (32 bytes)"));
+
+    fs::write(d.join("stop"), "").unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let r = result(d);
+    assert_eq!(r["requests"], 5);
+
+    let receipt = |n: u64| -> serde_json::Value {
+        serde_json::from_slice(&fs::read(d.join(format!("out/net/1/req_{}.json", n))).unwrap()).unwrap()
+    };
+    let statuses: Vec<i64> = (1..=5).map(|n| receipt(n)["status"].as_i64().unwrap()).collect();
+    assert_eq!(statuses, [200, 200, 200, 413, 500]);
+    // The same request gets the same digests; a different one does not.
+    let (a, b, c) = (receipt(2), receipt(3), receipt(1));
+    assert_eq!(a["request_digest"], b["request_digest"]);
+    assert_eq!(a["response_digest"], b["response_digest"]);
+    assert_ne!(a["request_digest"], c["request_digest"]);
+    assert!(receipt(5)["error"].as_str().unwrap().contains("max_response"));
+    assert!(d.join("out/net/1/req_1.ndjson").exists());
+    verify_trace(d);
+
+    // Nothing is listening after net.stop.
+    assert!(TcpStream::connect(addr.trim()).is_err());
+}
+
+const RUNAWAY: &str = r#"import("std/net") as net
+import("std/http") as http
+import("std/str") as str
+import("std/mutex") as mutex
+
+let m = mutex.new(0)
+fn spin(n) { let _ = mutex.unlock(m, n) spin(n + 1) }
+fn handler(req) { if req.path == "/spin" then spin(1) else "ok" }
+fn pause(n) { if n == 0 then null else pause(n - 1) }
+let srv = net.listen({ handler: handler, workers: 1, timeout_ms: 200 })?
+let base = str.concat("http://", srv.addr)
+let first = http.get(str.concat(base, "/spin"))
+let stopped_at = mutex.lock(m)
+let _ = pause(200000)
+let later = mutex.lock(m)
+let second = http.get(str.concat(base, "/spin"))
+let ok = http.get(str.concat(base, "/ok"))
+let _ = net.stop(srv)?
+{ first: first.status, second: second.status, ok: ok.body, spun: stopped_at > 0, stopped: later == stopped_at }
+"#;
+
+#[test]
+fn timed_out_handlers_stop_on_their_worker() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let o = fardrun(d, RUNAWAY, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!((r["first"].as_i64(), r["second"].as_i64()), (Some(504), Some(504)));
+    assert_eq!(r["ok"], "ok");
+    assert_eq!(r["spun"], true);
+    // Nothing keeps running the handler once its 504 has gone out.
+    assert_eq!(r["stopped"], true);
+}
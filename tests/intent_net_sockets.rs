@@ -0,0 +1,211 @@
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{events, fardrun, result, sha256_hex, tmpdir, verify_trace};
+
+const ECHO: &str = r#"import("std/net") as net
+import("std/str") as str
+import("std/bytes") as bytes
+import("std/promise") as promise
+import("websocket") as ws
+
+let l = net.tcp_listen({ timeout_ms: 10000 })?
+fn tcp_echo() {
+  let c = net.accept(l)?
+  let _ = net.write(c, net.read(c)?)?
+  net.close(c)?
+}
+let tcp_server = promise.spawn(tcp_echo)
+let c = net.connect({ host: "127.0.0.1", port: l.port, timeout_ms: 10000 })?
+let _ = net.write(c, "hello tcp")?
+let back = net.read(c)?
+let eof = net.read(c)?
+let tcp_closed = net.close(c)?
+let _ = promise.await(tcp_server)
+
+let u1 = net.udp_bind({ timeout_ms: 10000 })?
+let u2 = net.udp_bind({ timeout_ms: 10000 })?
+let _ = net.udp_send(u1, u2.addr, bytes.of_list([1, 2, 3]))?
+let dg = net.udp_recv(u2)?
+
+fn echo_all(conn) {
+  let m = ws.recv(conn)
+  if m == null then net.close(conn)? else echo_one(conn, m)
+}
+fn echo_one(conn, m) {
+  let _ = ws.send(conn, m)?
+  echo_all(conn)
+}
+let wl = net.tcp_listen({ timeout_ms: 10000 })?
+let ws_server = promise.spawn(fn() {
+  let conn = ws.accept(wl)?
+  { path: conn.path, closed: echo_all(conn) }
+})
+let conn = ws.connect(str.concat(str.concat("ws://", wl.addr), "/echo"))?
+let _ = ws.send(conn, "hi ws")?
+let _ = ws.send(conn, bytes.of_list([9, 8]))?
+let r1 = ws.recv(conn)
+let r2 = ws.recv(conn)
+let ws_closed = ws.close(conn)?
+let served = promise.await(ws_server)
+{
+  tcp: [bytes.to_str(back), bytes.len(eof), tcp_closed],
+  udp: [bytes.to_list(dg.data), dg.from == u1.addr],
+  ws: [r1, bytes.to_list(r2), ws_closed, served]
+}
+"#;
+
+#[test]
+fn tcp_udp_and_websocket_echo_in_process() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::copy("packages/websocket/main.fard", d.join("websocket.fard")).unwrap();
+    let o = fardrun(d, ECHO, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["tcp"], serde_json::json!(["hello tcp", 0, {"bytes_in": 9, "bytes_out": 9}]));
+    assert_eq!(r["udp"], serde_json::json!([[1, 2, 3], true]));
+    let closed = serde_json::json!({"bytes_in": 7, "bytes_out": 7, "messages_in": 2});
+    assert_eq!(r["ws"], serde_json::json!(["hi ws", [9, 8], closed, {"path": "/echo", "closed": closed}]));
+
+    // Every transfer is traced by size and content digest.
+    let io = events(d, "net_io");
+    let digest = format!("sha256:{}", sha256_hex(b"hello tcp"));
+    let ops: Vec<(&str, i64, &str)> = io
+        .iter()
+        .map(|e| (e["op"].as_str().unwrap(), e["bytes"].as_i64().unwrap(), e["digest"].as_str().unwrap()))
+        .collect();
+    assert!(ops.contains(&("net.write", 9, digest.as_str())), "{:?}", ops);
+    assert!(ops.contains(&("net.read", 9, digest.as_str())), "{:?}", ops);
+    let udp = io.iter().find(|e| e["op"] == "net.udp_recv").unwrap();
+    assert_eq!(udp["digest"], format!("sha256:{}", sha256_hex(&[1, 2, 3])));
+    assert!(udp["peer"].is_string());
+    let ws_close = events(d, "net_close").into_iter().find(|e| e["messages_in"] == 2).unwrap();
+    assert!(ws_close["recv_root"].as_str().unwrap().starts_with("sha256:"));
+    // Each WebSocket message is traced as the program takes it from the inbox.
+    let taken: Vec<&str> = io.iter().filter(|e| e["op"] == "net.ws_recv").map(|e| e["digest"].as_str().unwrap()).collect();
+    assert_eq!(taken, [format!("sha256:{}", sha256_hex(b"hi ws")), format!("sha256:{}", sha256_hex(&[9, 8]))]);
+    verify_trace(d);
+}
+
+const WS_UNREAD: &str = r#"import("std/net") as net
+import("std/str") as str
+import("std/chan") as chan
+import("std/promise") as promise
+
+let l = net.tcp_listen({ timeout_ms: 10000 })?
+let client = promise.spawn(fn() {
+  let c = net.ws_connect(str.concat("ws://", l.addr), { timeout_ms: 10000 })?
+  let _ = net.ws_send(c, "one")?
+  let _ = net.ws_send(c, "two")?
+  let _ = net.ws_send(c, "three")?
+  net.close(c)?
+})
+let conn = net.ws_accept(l)?
+let first = chan.recv(conn.inbox)
+let sent = promise.await(client)
+{ first: first.v, sent: sent.bytes_out, closed: net.close(conn)? }
+"#;
+
+#[test]
+fn websocket_messages_left_in_the_inbox_are_not_traced() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let o = fardrun(d, WS_UNREAD, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!((r["first"].as_str(), r["sent"].as_i64()), (Some("one"), Some(11)));
+    // The client has sent all three by now; only the one taken counts.
+    assert_eq!(r["closed"], serde_json::json!({"bytes_in": 3, "bytes_out": 0, "messages_in": 1}));
+    let taken: Vec<serde_json::Value> = events(d, "net_io").into_iter().filter(|e| e["op"] == "net.ws_recv").collect();
+    assert_eq!(taken.len(), 1);
+    assert_eq!(taken[0]["digest"], format!("sha256:{}", sha256_hex(b"one")));
+    verify_trace(d);
+}
+
+const WS_SERVER: &str = r#"import("std/net") as net
+import("std/fs") as fs
+import("std/chan") as chan
+
+let l = net.tcp_listen({ timeout_ms: 20000 })?
+let _ = fs.write_text("addr.txt", l.addr)
+let conn = net.ws_accept(l)?
+fn reply(m) { if m == null then null else net.ws_send(conn, m.v)? }
+let first = chan.recv(conn.inbox)
+let _ = reply(first)
+let second = chan.recv(conn.inbox)
+let _ = reply(second)
+let third = chan.recv(conn.inbox)
+{ path: conn.path, first: first.v, third: third, closed: net.close(conn)? }
+"#;
+
+#[test]
+fn websocket_server_talks_to_an_outside_client() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("main.fard"), WS_SERVER).unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["run", "--program", "main.fard", "--out", "out"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let t0 = Instant::now();
+    let addr = loop {
+        if let Ok(a) = fs::read_to_string(d.join("addr.txt")) {
+            break a;
+        }
+        assert!(t0.elapsed() < Duration::from_secs(30), "server never came up");
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let (mut client, _) = tungstenite::connect(format!("ws://{}/chat?room=1", addr)).unwrap();
+    client.send(tungstenite::Message::Text("ping".into())).unwrap();
+    assert_eq!(client.read().unwrap(), tungstenite::Message::Text("ping".into()));
+    client.send(tungstenite::Message::Binary(vec![0, 255])).unwrap();
+    assert_eq!(client.read().unwrap(), tungstenite::Message::Binary(vec![0, 255]));
+    client.close(None).unwrap();
+    while client.read().is_ok() {}
+
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let r = result(d);
+    assert_eq!(r["path"], "/chat");
+    assert_eq!(r["first"], "ping");
+    assert_eq!(r["third"], serde_json::Value::Null, "inbox closes when the client leaves");
+    assert_eq!(r["closed"], serde_json::json!({"bytes_in": 6, "bytes_out": 6, "messages_in": 2}));
+    let open = events(d, "net_open");
+    assert_eq!(open.iter().map(|e| e["op"].as_str().unwrap()).collect::<Vec<_>>(), ["net.tcp_listen", "net.ws_accept"]);
+}
+
+#[test]
+fn timeouts_and_policy_are_enforced() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let src = r#"import("std/net") as net
+
+let l = net.tcp_listen({ timeout_ms: 100 })?
+let accepted = net.accept(l)
+let c = net.connect({ host: "127.0.0.1", port: l.port, timeout_ms: 100 })?
+let read = net.read(c)
+let u = net.udp_bind({ timeout_ms: 100 })?
+{ accepted: accepted.e, read: read.e, udp: net.udp_recv(u).e, gone: net.read("tcp:999").e }
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["accepted"], "ERROR_TIMEOUT net.accept timed out after 100 ms");
+    assert_eq!(r["read"], "ERROR_TIMEOUT net.read timed out after 100 ms");
+    assert_eq!(r["udp"], "ERROR_TIMEOUT net.udp_recv timed out after 100 ms");
+    assert_eq!(r["gone"], "ERROR_NET net.read: no open socket tcp:999");
+
+    // Outbound sockets need the host in http_hosts; bound ones need the port in listen.
+    fs::write(d.join("policy.toml"), "http_hosts = [\"example.com\"]\nlisten = [0]\n").unwrap();
+    let o = fardrun(d, "import(\"std/net\") as net\nnet.connect({ host: \"127.0.0.1\", port: 9 })\n", &["--policy", "policy.toml"]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_CAPABILITY net.connect denied http tcp://127.0.0.1:9"), "{}", String::from_utf8_lossy(&o.stderr));
+    let o = fardrun(d, "import(\"std/net\") as net\nnet.udp_bind({ port: 5353 })\n", &["--policy", "policy.toml"]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_CAPABILITY net.udp_bind denied listen 5353"));
+    let o = fardrun(d, "import(\"std/net\") as net\nnet.ws_connect(\"ws://127.0.0.1:9/\")\n", &["--policy", "policy.toml"]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_CAPABILITY net.ws_connect denied http"));
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn notebook(d: &Path, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["notebook", "--input", "nb.fardnb.md", "--out-dir", "out"])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn receipt(d: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(d.join("out/notebook.receipt.json")).unwrap()).unwrap()
+}
+
+fn statuses(r: &serde_json::Value) -> Vec<String> {
+    r["cells"].as_array().unwrap().iter().map(|c| c["status"].as_str().unwrap().to_string()).collect()
+}
+
+const NOTEBOOK: &str = "# Sales\n\n```fard\nimport(\"std/list\") as list\nlet xs = [1, 2, 3]\n```\n\nCount them:\n\n```fard\nlet n = list.len(xs)\nn\n```\n\n```fard\nlet rate = 10\nrate\n```\n\n```fard\nn * rate\n```\n";
+
+#[test]
+fn cells_share_bindings_and_only_invalidated_cells_rerun() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(d.join("nb.fardnb.md"), NOTEBOOK).unwrap();
+
+    let o = notebook(d, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let first = receipt(d);
+    assert_eq!(statuses(&first), ["ran", "ran", "ran", "ran"]);
+    let rendered = fs::read_to_string(d.join("nb.fardnb.md")).unwrap();
+    assert!(rendered.contains("```fard\nn * rate\n```\n\n```output\n30\n```\n"), "{}", rendered);
+    assert!(rendered.contains("\nCount them:\n"));
+    let cell: serde_json::Value = serde_json::from_slice(&fs::read(d.join("out/cells/cell_3.json")).unwrap()).unwrap();
+    assert_eq!(cell["kind"], "fard/notebook_cell/v1");
+    assert_eq!(cell["result"], 30);
+    assert_eq!(cell["upstream"].as_array().unwrap().len(), 2);
+
+    // Rendering again reuses every cell and leaves the notebook and its Merkle root unchanged.
+    assert!(notebook(d, &[]).status.success());
+    let again = receipt(d);
+    assert_eq!(statuses(&again), ["cached", "cached", "cached", "cached"]);
+    assert_eq!(again["merkle_root"], first["merkle_root"]);
+    assert_eq!(fs::read_to_string(d.join("nb.fardnb.md")).unwrap(), rendered);
+
+    // Editing a cell reruns it and its dependents only.
+    fs::write(d.join("nb.fardnb.md"), rendered.replace("let rate = 10", "let rate = 7")).unwrap();
+    assert!(notebook(d, &[]).status.success());
+    let edited = receipt(d);
+    assert_eq!(statuses(&edited), ["cached", "cached", "ran", "ran"]);
+    assert_ne!(edited["merkle_root"], first["merkle_root"]);
+    assert!(fs::read_to_string(d.join("nb.fardnb.md")).unwrap().contains("```output\n21\n```"));
+
+    // A cell that reads an import replays the cached cell that made it, since a module
+    // cannot be restored from the cache.
+    let rendered = fs::read_to_string(d.join("nb.fardnb.md")).unwrap();
+    fs::write(d.join("nb.fardnb.md"), rendered.replace("let n = list.len(xs)", "let n = list.len(xs) + 1")).unwrap();
+    assert!(notebook(d, &[]).status.success());
+    assert_eq!(statuses(&receipt(d)), ["replayed", "ran", "cached", "ran"]);
+
+    assert!(notebook(d, &["--force"]).status.success());
+    assert_eq!(statuses(&receipt(d)), ["ran", "ran", "ran", "ran"]);
+}
+
+#[test]
+fn cell_errors_render_inline_with_notebook_spans() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(
+        d.join("nb.fardnb.md"),
+        "```fard\nlet q = 4\n```\n\n```fard\nlet z = q - 4\nq / z\n```\n\n```fard\nlet c = (\n```\n\n```fard\nq + 1\n```\n",
+    )
+    .unwrap();
+
+    let o = notebook(d, &[]);
+    assert!(!o.status.success());
+    let rendered = fs::read_to_string(d.join("nb.fardnb.md")).unwrap();
+    assert!(
+        rendered.contains("error: ERROR_DIV_ZERO division by zero\n --> nb.fardnb.md:7:5\n  |\n7 | q / z\n  |     ^\n"),
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("```fard\nq + 1\n```\n\n```output\n5\n```"), "{}", rendered);
+
+    let r = receipt(d);
+    assert_eq!((r["ok"].as_bool(), r["failed"].as_u64()), (Some(false), Some(2)));
+    let cell: serde_json::Value = serde_json::from_slice(&fs::read(d.join("out/cells/cell_2.json")).unwrap()).unwrap();
+    assert_eq!(cell["ok"], false);
+    assert_eq!(cell["error"]["span"]["file"], "nb.fardnb.md");
+    assert!(cell["output"].as_str().unwrap().starts_with("error: "), "{}", cell);
+
+    // Failed cells are never served from the cache.
+    notebook(d, &[]);
+    assert_eq!(statuses(&receipt(d)), ["cached", "ran", "ran", "cached"]);
+}
+
+#[test]
+fn cell_traces_are_kept_per_cell_and_bound_into_their_receipts() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let nb = "```fard\nimport(\"std/trace\") as trace\nlet _ = trace.emit({cell: 0})\n1\n```\n\n```fard\nlet _ = trace.emit({cell: 1})\n2\n```\n";
+    fs::write(d.join("nb.fardnb.md"), nb).unwrap();
+    let o = notebook(d, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let first = receipt(d);
+    for i in 0..2 {
+        let cell: serde_json::Value = serde_json::from_slice(&fs::read(d.join(format!("out/cells/cell_{i}.json"))).unwrap()).unwrap();
+        let trace = fs::read(d.join(format!("out/cells/cell_{i}.ndjson"))).unwrap();
+        assert_eq!(cell["trace"], format!("cells/cell_{i}.ndjson"));
+        assert_eq!(cell["trace_sha256"], format!("sha256:{}", common::sha256_hex(&trace)));
+        let emits: Vec<serde_json::Value> = String::from_utf8(trace)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .filter(|e| e["t"] == "emit")
+            .collect();
+        assert_eq!(emits, [serde_json::json!({"t": "emit", "v": {"cell": i}})]);
+    }
+
+    // A cached cell restores its trace, so its receipt and the Merkle root stay the same.
+    assert!(notebook(d, &[]).status.success());
+    let again = receipt(d);
+    assert_eq!(statuses(&again), ["cached", "cached"]);
+    assert_eq!(again["merkle_root"], first["merkle_root"]);
+    assert!(fs::read_to_string(d.join("out/cells/cell_1.ndjson")).unwrap().contains("\"cell\":1"));
+
+    // Notebooks run side by side do not share a trace file.
+    let other = tmpdir();
+    fs::write(other.path().join("nb.fardnb.md"), nb.replace("cell: 1", "cell: 9")).unwrap();
+    let (a, b) = std::thread::scope(|s| {
+        let a = s.spawn(|| notebook(d, &["--force"]));
+        let b = s.spawn(|| notebook(other.path(), &[]));
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    assert!(a.status.success() && b.status.success());
+    assert!(fs::read_to_string(d.join("out/cells/cell_1.ndjson")).unwrap().contains("\"cell\":1"));
+    assert!(fs::read_to_string(other.path().join("out/cells/cell_1.ndjson")).unwrap().contains("\"cell\":9"));
+}
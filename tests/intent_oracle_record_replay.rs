@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run_program(prog: &Path, out: &Path, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .arg("run")
+        .arg("--program")
+        .arg(prog)
+        .arg("--out")
+        .arg(out)
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn run_digest(out: &Path) -> String {
+    let b = fs::read(out.join("digests.json")).unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&b).unwrap();
+    v["preimage_sha256"].as_str().unwrap().to_string()
+}
+
+const PROG: &str = r#"import("std/uuid") as uuid
+import("std/datetime") as dt
+let a = uuid.v4()
+let t = dt.now()
+{ id: a, now: t }
+"#;
+
+#[test]
+fn record_then_replay_reproduces_run_digest() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let prog = d.join("main.fard");
+    fs::write(&prog, PROG).unwrap();
+
+    let rec = d.join("rec");
+    let out = run_program(&prog, &rec, &["--record"]);
+    assert!(out.status.success(), "record failed: {}", String::from_utf8_lossy(&out.stderr));
+
+    let trace = fs::read_to_string(rec.join("trace.ndjson")).unwrap();
+    let ops: Vec<String> = trace
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .filter(|v| v["t"] == "oracle")
+        .map(|v| v["op"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(ops, vec!["uuid.v4", "datetime.now"]);
+
+    let rep = d.join("rep");
+    let rec_arg = rec.to_string_lossy().to_string();
+    let out = run_program(&prog, &rep, &["--replay", &rec_arg]);
+    assert!(out.status.success(), "replay failed: {}", String::from_utf8_lossy(&out.stderr));
+
+    assert_eq!(run_digest(&rec), run_digest(&rep));
+    assert_eq!(
+        fs::read(rec.join("result.json")).unwrap(),
+        fs::read(rep.join("result.json")).unwrap()
+    );
+
+    let verify = Command::new(env!("CARGO_BIN_EXE_fardverify"))
+        .args(["trace", "--out", &rec_arg])
+        .output()
+        .unwrap();
+    assert!(verify.status.success(), "{}", String::from_utf8_lossy(&verify.stderr));
+
+    let verify = Command::new(env!("CARGO_BIN_EXE_fardverify"))
+        .args(["replay", "--out", &rec_arg, "--program"])
+        .arg(&prog)
+        .args(["--fardrun", env!("CARGO_BIN_EXE_fardrun")])
+        .output()
+        .unwrap();
+    assert!(verify.status.success(), "{}", String::from_utf8_lossy(&verify.stderr));
+}
+
+#[test]
+fn replay_divergence_is_an_error() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let prog = d.join("main.fard");
+    fs::write(&prog, PROG).unwrap();
+    let rec = d.join("rec");
+    assert!(run_program(&prog, &rec, &["--record"]).status.success());
+    let rec_arg = rec.to_string_lossy().to_string();
+
+    // Different call order: datetime.now is asked first.
+    let swapped = d.join("swapped.fard");
+    fs::write(
+        &swapped,
+        "import(\"std/uuid\") as uuid\nimport(\"std/datetime\") as dt\nlet t = dt.now()\nlet a = uuid.v4()\n{ id: a, now: t }\n",
+    )
+    .unwrap();
+    let out = run_program(&swapped, &d.join("o1"), &["--replay", &rec_arg]);
+    assert!(!out.status.success());
+    let err = fs::read_to_string(d.join("o1").join("error.json")).unwrap();
+    assert!(err.contains("ERROR_REPLAY_DIVERGE"), "{}", err);
+
+    // Extra call beyond the recording.
+    let extra = d.join("extra.fard");
+    fs::write(
+        &extra,
+        "import(\"std/uuid\") as uuid\nimport(\"std/datetime\") as dt\nlet a = uuid.v4()\nlet t = dt.now()\nlet b = uuid.v4()\n{ id: b }\n",
+    )
+    .unwrap();
+    let out = run_program(&extra, &d.join("o2"), &["--replay", &rec_arg]);
+    assert!(!out.status.success());
+    let err = fs::read_to_string(d.join("o2").join("error.json")).unwrap();
+    assert!(err.contains("ERROR_REPLAY_EXHAUSTED"), "{}", err);
+}
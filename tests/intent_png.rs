@@ -0,0 +1,184 @@
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+
+mod common;
+use common::{fardrun, result, sha256_hex, tmpdir};
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let mut body = kind.to_vec();
+    body.extend_from_slice(data);
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+}
+
+/// A PNG as another encoder might write it: unfiltered scanlines, optional Adam7.
+/// `row(x0, dx, y)` packs the pixels x0, x0+dx, ... of line y.
+fn foreign_png(w: u32, h: u32, depth: u8, color: u8, interlace: bool, extra: &[(&[u8], Vec<u8>)], row: impl Fn(usize, usize, usize) -> Vec<u8>) -> Vec<u8> {
+    let passes: Vec<(usize, usize, usize, usize)> = if interlace {
+        vec![(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+    } else {
+        vec![(0, 0, 1, 1)]
+    };
+    let mut raw = Vec::new();
+    for (x0, y0, dx, dy) in passes {
+        if x0 >= w as usize || y0 >= h as usize {
+            continue;
+        }
+        for y in (y0..h as usize).step_by(dy) {
+            raw.push(0);
+            raw.extend(row(x0, dx, y));
+        }
+    }
+    let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+    z.write_all(&raw).unwrap();
+    let mut ihdr = w.to_be_bytes().to_vec();
+    ihdr.extend_from_slice(&h.to_be_bytes());
+    ihdr.extend_from_slice(&[depth, color, 0, 0, interlace as u8]);
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"tEXt", b"Software\0elsewhere");
+    for (kind, data) in extra {
+        chunk(&mut out, kind, data);
+    }
+    chunk(&mut out, b"IDAT", &z.finish().unwrap());
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[test]
+fn encode_is_byte_stable_and_round_trips() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let src = r#"import("std/png") as png
+
+let img = { width: 3, height: 2, channels: 4, pixels: [255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 9, 9, 9, 255, 200, 100, 50, 255, 1, 2, 3, 4] }
+let bytes = png.encode(img)
+let back = png.decode(bytes)
+let gray = png.convert(back, "gray")
+let painted = png.set_pixel(gray, 2, 1, [7])
+let out = emit_artifact("tile.png", png.encode(painted))?
+{
+  same: back.pixels == png.decode(png.encode(back)).pixels,
+  stable: png.encode(back) == bytes,
+  cid: back.cid,
+  size: [back.width, back.height, back.channels],
+  pixel: png.pixel(back, 1, 0),
+  row: png.row(gray, 1),
+  rgb: png.pixel(png.convert(gray, "rgb"), 0, 0),
+  painted: png.pixel(png.decode(png.encode(painted)), 2, 1),
+  out: out.cid
+}
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["same"], true);
+    assert_eq!(r["stable"], true);
+    assert_eq!(r["size"], serde_json::json!([3, 2, 4]));
+    assert_eq!(r["pixel"], serde_json::json!([0, 255, 0, 128]));
+    assert_eq!(r["row"], serde_json::json!([[9], [124], [2]]));
+    assert_eq!(r["rgb"], serde_json::json!([76, 76, 76]));
+    assert_eq!(r["painted"], serde_json::json!([7]));
+
+    // The encoder's output is pinned: any change to filtering or zlib settings shows up here.
+    let written = fs::read(d.join("out/artifacts/tile.png")).unwrap();
+    assert_eq!(r["out"], format!("sha256:{}", sha256_hex(&written)));
+    assert_eq!(
+        r["cid"],
+        "sha256:d2d2953842bbf6703480a5149bd6f490cd6cee7a5b53e0bda53f1395f56853d1"
+    );
+
+    // Each distinct decoded input is registered once, by CID, and the trace verifies.
+    let trace = fs::read_to_string(d.join("out/trace.ndjson")).unwrap();
+    let inputs: Vec<serde_json::Value> = trace
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .filter(|v| v["t"] == "artifact_in")
+        .collect();
+    assert_eq!(inputs.len(), 2, "{}", trace);
+    assert_eq!(inputs[0]["cid"], r["cid"]);
+    let o = Command::new(env!("CARGO_BIN_EXE_fardverify")).args(["trace", "--out"]).arg(d.join("out")).output().unwrap();
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+}
+
+#[test]
+fn decodes_palette_low_depth_16_bit_and_interlaced_inputs() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("img")).unwrap();
+    // 2-bit palette with transparency for the first entry.
+    let palette = [(&b"PLTE"[..], vec![10, 20, 30, 40, 50, 60, 70, 80, 90]), (&b"tRNS"[..], vec![0])];
+    fs::write(
+        d.join("img/pal.png"),
+        foreign_png(3, 1, 2, 3, false, &palette, |_, _, _| vec![0b0001_1000]),
+    )
+    .unwrap();
+    // 1-bit gray.
+    fs::write(d.join("img/bits.png"), foreign_png(4, 1, 1, 0, false, &[], |_, _, _| vec![0b1010_0000])).unwrap();
+    // 16-bit RGB keeps the high byte of each sample.
+    fs::write(
+        d.join("img/deep.png"),
+        foreign_png(1, 1, 16, 2, false, &[], |_, _, _| vec![0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]),
+    )
+    .unwrap();
+    // Adam7 interlaced 5x5 RGBA where each pixel encodes its own coordinates.
+    fs::write(
+        d.join("img/adam7.png"),
+        foreign_png(5, 5, 8, 6, true, &[], |x0, dx, y| {
+            (x0..5).step_by(dx).flat_map(|x| [x as u8, y as u8, (x * 5 + y) as u8, 255]).collect()
+        }),
+    )
+    .unwrap();
+    let src = r#"import("std/png") as png
+
+let pal = png.read("img/pal.png")
+let bits = png.read("img/bits.png")
+let deep = png.read("img/deep.png")
+let adam = png.read("img/adam7.png")
+{
+  pal: [pal.channels, png.row(pal, 0)],
+  bits: png.row(bits, 0),
+  deep: png.pixel(deep, 0, 0),
+  adam: [png.pixel(adam, 0, 0), png.pixel(adam, 3, 2), png.pixel(adam, 4, 4), png.pixel(adam, 1, 3)]
+}
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["pal"], serde_json::json!([4, [[10, 20, 30, 0], [40, 50, 60, 255], [70, 80, 90, 255]]]));
+    assert_eq!(r["bits"], serde_json::json!([[255], [0], [255], [0]]));
+    assert_eq!(r["deep"], serde_json::json!([0x12, 0x56, 0x9a]));
+    assert_eq!(r["adam"], serde_json::json!([[0, 0, 0, 255], [3, 2, 17, 255], [4, 4, 24, 255], [1, 3, 8, 255]]));
+
+    // Files read from disk are registered under their path.
+    let trace = fs::read_to_string(d.join("out/trace.ndjson")).unwrap();
+    let bytes = fs::read(d.join("img/adam7.png")).unwrap();
+    assert!(
+        trace.contains(&format!("\"cid\":\"sha256:{}\",\"name\":\"img/adam7.png\"", sha256_hex(&bytes))),
+        "{}",
+        trace
+    );
+}
+
+#[test]
+fn bad_input_and_out_of_range_access_are_errors() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let mut broken = foreign_png(1, 1, 8, 0, false, &[], |_, _, _| vec![0]);
+    let n = broken.len();
+    broken[n - 20] ^= 0xff;
+    fs::write(d.join("broken.png"), broken).unwrap();
+    let o = fardrun(d, "import(\"std/png\") as png\npng.read(\"broken.png\")\n", &[]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_PNG bad crc"), "{}", String::from_utf8_lossy(&o.stderr));
+
+    let o = fardrun(d, "import(\"std/png\") as png\npng.pixel(png.decode(png.red_1x1()), 1, 0)\n", &[]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_OOB png.pixel (1, 0) outside 1x1"));
+
+    let o = fardrun(d, "import(\"std/png\") as png\npng.encode({ width: 2, height: 1, channels: 3, pixels: [1, 2, 3] })\n", &[]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("expects 6 pixel samples (2x1x3), got 3"));
+
+    let o = fardrun(d, "import(\"std/png\") as png\npng.read(\"../broken.png\")\n", &[]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_SANDBOX"));
+}
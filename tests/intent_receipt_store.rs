@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Command, Output};
+
+mod common;
+use common::tmpdir;
+
+/// Run `fardrun run` from `cwd`, so a default `receipts/` store would land there.
+fn run_in(cwd: &Path, prog: &str, out: &str, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(cwd)
+        .args(["run", "--program", prog, "--out", out])
+        .args(extra)
+        .env("HOME", cwd)
+        .env_remove("FARD_REGISTRY_URL")
+        .output()
+        .unwrap()
+}
+
+fn run_id(out: &Path) -> String {
+    let v: serde_json::Value = serde_json::from_slice(&fs::read(out.join("digests.json")).unwrap()).unwrap();
+    v["preimage_sha256"].as_str().unwrap().to_string()
+}
+
+fn result_at(out: &Path) -> serde_json::Value {
+    let v: serde_json::Value = serde_json::from_slice(&fs::read(out.join("result.json")).unwrap()).unwrap();
+    v["result"].clone()
+}
+
+fn consumer(run_id: &str) -> String {
+    format!(
+        r#"import("std/witness") as w
+import("std/graph") as graph
+import("std/list") as list
+artifact prior = "{id}"
+{{ prior: prior, verified: w.verify("{id}").t, ancestors: graph.ancestors("{id}"), nodes: list.len(graph.of("{id}").ok.nodes) }}
+"#,
+        id = run_id
+    )
+}
+
+fn write(dir: &Path, name: &str, src: &str) {
+    fs::write(dir.join(name), src).unwrap();
+}
+
+#[test]
+fn directory_store_serves_artifacts_witness_graph_and_fardverify() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    write(d, "producer.fard", "{ answer: 42 }\n");
+    let out = run_in(d, "producer.fard", "p", &["--receipts", "store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let id = run_id(&d.join("p"));
+    let hex = id.strip_prefix("sha256:").unwrap();
+    assert!(d.join("store").join(format!("sha256_{}.json", hex)).exists());
+    assert!(!d.join("receipts").exists());
+
+    write(d, "consumer.fard", &consumer(&id));
+    let out = run_in(d, "consumer.fard", "c", &["--receipts", "dir:store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let r = result_at(&d.join("c"));
+    assert_eq!(r["prior"]["answer"], 42);
+    assert_eq!(r["verified"], "ok");
+    assert_eq!(r["nodes"], 1);
+
+    // Without the store the artifact cannot be resolved.
+    let out = run_in(d, "consumer.fard", "c2", &[]);
+    assert!(!out.status.success());
+    let err = fs::read_to_string(d.join("c2/error.json")).unwrap();
+    assert!(err.contains("not found in dir:receipts"), "{}", err);
+
+    let verify = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_fardverify"))
+            .current_dir(d)
+            .args(["chain", "--out", "c"])
+            .args(extra)
+            .env("HOME", d)
+            .output()
+            .unwrap()
+    };
+    let ok = verify(&["--receipts", "dir:store"]);
+    let stdout = String::from_utf8_lossy(&ok.stdout);
+    assert!(ok.status.success(), "{}{}", stdout, String::from_utf8_lossy(&ok.stderr));
+    assert!(stdout.contains(&format!("verified receipt {} depth=1", id)), "{}", stdout);
+    assert!(stdout.contains("chain ok — 2 node(s) verified, depth 1"), "{}", stdout);
+
+    let missing = verify(&["--receipts", "dir:elsewhere"]);
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("CHAIN_MISSING_RECEIPT"));
+
+    // A receipt stored under the wrong run ID is rejected.
+    let path = d.join("store").join(format!("sha256_{}.json", hex));
+    let forged = fs::read_to_string(&path).unwrap().replace(hex, &"0".repeat(64));
+    fs::write(&path, forged).unwrap();
+    let bad = verify(&["--receipts", "dir:store"]);
+    assert!(!bad.status.success());
+    assert!(String::from_utf8_lossy(&bad.stderr).contains("CHAIN_RECEIPT_MISMATCH"));
+}
+
+#[test]
+fn sqlite_store_is_configured_from_fard_toml() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    write(d, "fard.toml", "[package]\nname = \"demo\"\n\n[receipts]\nstore = [\n  \"sqlite:registry.db\",  # shared with fardregistry\n]\n");
+    write(d, "producer.fard", "[1, 2, 3]\n");
+    let out = run_in(d, "producer.fard", "p", &[]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let id = run_id(&d.join("p"));
+
+    let conn = rusqlite::Connection::open(d.join("registry.db")).unwrap();
+    let raw: String = conn
+        .query_row("SELECT raw_json FROM receipts WHERE run_id = ?1", [&id], |r| r.get(0))
+        .unwrap();
+    assert!(raw.contains("\"output\":[1,2,3]"), "{}", raw);
+
+    write(d, "consumer.fard", &consumer(&id));
+    let out = run_in(d, "consumer.fard", "c", &[]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(result_at(&d.join("c"))["prior"], serde_json::json!([1, 2, 3]));
+
+    // Several stores need a list; a bare comma-separated value is not TOML.
+    write(d, "fard.toml", "[receipts]\nstore = \"dir:a\", \"dir:b\"\n");
+    let out = run_in(d, "producer.fard", "p2", &[]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("ERROR_RECEIPTS"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+struct Registry(Child);
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn start_registry(d: &Path) -> (Registry, String) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let child = Command::new(env!("CARGO_BIN_EXE_fardregistry"))
+        .args(["--port", &port.to_string(), "--db"])
+        .arg(d.join("registry.db"))
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let url = format!("http://127.0.0.1:{}", port);
+    for _ in 0..100 {
+        if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    (Registry(child), url)
+}
+
+#[test]
+fn http_store_publishes_and_caches_fetched_receipts() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let (registry, url) = start_registry(d);
+    write(d, "producer.fard", "\"hello\"\n");
+    let out = run_in(d, "producer.fard", "p", &["--receipts", &url]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let id = run_id(&d.join("p"));
+
+    write(d, "consumer.fard", &consumer(&id));
+    let args = ["--receipts", "dir:local", "--receipts", &url, "--receipt-cache", "cache"];
+    let out = run_in(d, "consumer.fard", "c", &args);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(result_at(&d.join("c"))["prior"], "hello");
+    let cached = d.join("cache").join(format!("sha256_{}.json", id.strip_prefix("sha256:").unwrap()));
+    assert!(cached.exists());
+
+    // A tampered cache entry is dropped and fetched again from the registry.
+    let genuine = fs::read_to_string(&cached).unwrap();
+    fs::write(&cached, genuine.replace("\"hello\"", "\"pwned\"")).unwrap();
+    let out = run_in(d, "consumer.fard", "c1", &args);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(result_at(&d.join("c1"))["prior"], "hello");
+    assert_eq!(fs::read_to_string(&cached).unwrap(), genuine);
+
+    // A registry serving a receipt whose preimage does not hash to its run ID cannot
+    // plant it in the cache.
+    let forged = genuine.replace("\"hello\"", "\"pwned\"");
+    let fake = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let fake_url = format!("http://{}", fake.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut conn in fake.incoming().flatten() {
+            use std::io::{Read, Write};
+            let mut buf = [0u8; 4096];
+            let _ = conn.read(&mut buf);
+            let _ = write!(conn, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", forged.len(), forged);
+        }
+    });
+    fs::remove_file(&cached).unwrap();
+    run_in(d, "consumer.fard", "c3", &["--receipts", &fake_url, "--receipt-cache", "cache"]);
+    assert!(!cached.exists());
+
+    // The registry is gone; the cached receipt still resolves.
+    let out = run_in(d, "consumer.fard", "c", &args);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    drop(registry);
+    let out = run_in(d, "consumer.fard", "c2", &args);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(fs::read(d.join("c/result.json")).unwrap(), fs::read(d.join("c2/result.json")).unwrap());
+}
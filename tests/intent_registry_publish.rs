@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Command};
+
+mod common;
+use common::{get_json, tmpdir};
+
+struct Registry(Child);
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn start_registry(d: &Path, extra: &[&Path]) -> (Registry, String) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let child = Command::new(env!("CARGO_BIN_EXE_fardregistry"))
+        .args(["--port", &port.to_string(), "--db"])
+        .arg(d.join("registry.db"))
+        .args(extra)
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    (Registry(child), format!("http://127.0.0.1:{}", port))
+}
+
+/// Run `prog` with a local directory receipt store and return its receipt.
+fn run(d: &Path, prog: &str, src: &str) -> serde_json::Value {
+    fs::write(d.join(prog), src).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["run", "--program", prog, "--out", &format!("out_{}", prog), "--receipts", "local"])
+        .env("HOME", d)
+        .env_remove("FARD_REGISTRY_URL")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let digests: serde_json::Value =
+        serde_json::from_slice(&fs::read(d.join(format!("out_{}/digests.json", prog))).unwrap()).unwrap();
+    let hex = digests["preimage_sha256"].as_str().unwrap().strip_prefix("sha256:").unwrap().to_string();
+    serde_json::from_slice(&fs::read(d.join("local").join(format!("sha256_{}.json", hex))).unwrap()).unwrap()
+}
+
+fn publish(url: &str, receipt: &serde_json::Value) -> (u16, String) {
+    match ureq::post(&format!("{}/publish", url)).send_string(&receipt.to_string()) {
+        Ok(resp) => (resp.status(), resp.into_string().unwrap()),
+        Err(ureq::Error::Status(code, resp)) => (code, resp.into_string().unwrap()),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn publish_recomputes_run_ids_and_keeps_receipts_immutable() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let (_registry, url) = start_registry(d, &[]);
+    let parent = run(d, "parent.fard", "{ n: 41 }\n");
+    let id = parent["run_id"].as_str().unwrap().to_string();
+    assert_eq!(parent["preimage"]["files"]["result.json"].as_str().map(|s| s.starts_with("sha256:")), Some(true));
+    let child = run(d, "child.fard", &format!("artifact p = \"{}\"\np.n + 1\n", id));
+    assert_eq!(child["derived_from"], serde_json::json!([id]));
+
+    // Parents must be published first.
+    let (code, body) = publish(&url, &child);
+    assert_eq!(code, 422, "{}", body);
+    assert!(body.contains(&format!("RECEIPT_MISSING_PARENT {}", id)), "{}", body);
+
+    // Content that does not hash to the run ID is rejected.
+    let mut forged = parent.clone();
+    forged["output"]["n"] = serde_json::json!(1000);
+    let (code, body) = publish(&url, &forged);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("RECEIPT_OUTPUT_MISMATCH"), "{}", body);
+    let mut forged = parent.clone();
+    forged["preimage"]["runtime_version"] = serde_json::json!("0.0.0");
+    let (code, body) = publish(&url, &forged);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("RECEIPT_RUN_ID_MISMATCH"), "{}", body);
+    let mut legacy = parent.clone();
+    legacy.as_object_mut().unwrap().remove("preimage");
+    let (code, body) = publish(&url, &legacy);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("missing preimage"), "{}", body);
+
+    assert_eq!(publish(&url, &parent).0, 200);
+
+    // Lineage, package and any other field must be bound by the run ID.
+    let mut forged = child.clone();
+    forged["derived_from"] = serde_json::json!([]);
+    let (code, body) = publish(&url, &forged);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("RECEIPT_LINEAGE_MISMATCH"), "{}", body);
+    forged["derived_from"] = serde_json::json!([id, id]);
+    let (code, body) = publish(&url, &forged);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("RECEIPT_LINEAGE_MISMATCH"), "{}", body);
+    let mut forged = child.clone();
+    forged["package"] = serde_json::json!("acme/billing");
+    let (code, body) = publish(&url, &forged);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("RECEIPT_PACKAGE_MISMATCH"), "{}", body);
+    let mut forged = child.clone();
+    forged["note"] = serde_json::json!("unbound");
+    let (code, body) = publish(&url, &forged);
+    assert_eq!(code, 400, "{}", body);
+    assert!(body.contains("RECEIPT_UNKNOWN_FIELD note"), "{}", body);
+
+    assert_eq!(publish(&url, &child).0, 200);
+    // Identical republish is a no-op; anything else for the same run ID conflicts.
+    assert_eq!(publish(&url, &parent).0, 200);
+    let mut changed = parent.clone();
+    changed["signature"] = serde_json::json!({ "alg": "ed25519", "key": "ed25519:00", "sig": "00" });
+    let (code, body) = publish(&url, &changed);
+    assert_eq!(code, 409, "{}", body);
+    assert!(body.contains("RECEIPT_CONFLICT"), "{}", body);
+
+    let stored = get_json(&format!("{}/receipt/{}", url, id));
+    assert_eq!(stored, parent);
+    let chain = get_json(&format!("{}/verify/{}", url, child["run_id"].as_str().unwrap()));
+    assert_eq!(chain["depth"], 1);
+
+    let audit = get_json(&format!("{}/audit/{}", url, id));
+    let statuses: Vec<i64> = audit.as_array().unwrap().iter().map(|e| e["status"].as_i64().unwrap()).collect();
+    assert_eq!(statuses, [409, 200, 200, 400, 400, 400]);
+    let latest = &audit[0];
+    assert_eq!(latest["publisher"], "127.0.0.1");
+    assert!(latest["detail"].as_str().unwrap().contains("RECEIPT_CONFLICT"));
+    assert!(latest["receipt_sha256"].as_str().unwrap().starts_with("sha256:"));
+    assert!(latest["at"].as_i64().unwrap() > 0);
+    let all = get_json(&format!("{}/audit", url));
+    assert_eq!(all.as_array().unwrap().len(), 12);
+}
+
+#[test]
+fn seeding_publishes_parents_before_children() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let parent = run(d, "parent.fard", "\"root\"\n");
+    let id = parent["run_id"].as_str().unwrap().to_string();
+    run(d, "child.fard", &format!("artifact p = \"{}\"\n[p]\n", id));
+    let (_registry, url) = start_registry(d, &[Path::new("--seed"), &d.join("local")]);
+    let stats = get_json(&format!("{}/stats", url));
+    assert_eq!(stats["count"], 2);
+}
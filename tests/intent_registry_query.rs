@@ -0,0 +1,217 @@
+use std::path::Path;
+use std::process::Command;
+
+use fard_v0_5_language_gate::registry::{Registry, RegistryConfig};
+
+mod common;
+use common::{digest, get, post, receipt, tmpdir};
+
+fn start(db: &Path) -> Registry {
+    Registry::start(RegistryConfig::new(db, "127.0.0.1:0")).unwrap()
+}
+
+fn id(r: &serde_json::Value) -> &str {
+    r["run_id"].as_str().unwrap()
+}
+
+fn ids(nodes: &serde_json::Value) -> Vec<(String, u64)> {
+    let mut v: Vec<(String, u64)> = nodes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|n| (n["run_id"].as_str().unwrap().to_string(), n["depth"].as_u64().unwrap()))
+        .collect();
+    v.sort();
+    v
+}
+
+#[test]
+fn lineage_follows_derived_from_both_ways() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let reg = start(&d.join("r.db"));
+    let root = receipt("root", serde_json::json!("root"), &[], None);
+    let a = receipt("a", serde_json::json!("a"), &[id(&root)], None);
+    let b = receipt("b", serde_json::json!("b"), &[id(&a)], None);
+    let c = receipt("c", serde_json::json!("c"), &[id(&root)], None);
+    let join = receipt("join", serde_json::json!("join"), &[id(&b), id(&c)], None);
+    for r in [&root, &a, &b, &c, &join] {
+        assert_eq!(post(&format!("{}/publish", reg.url()), r), 200);
+    }
+
+    let lineage = |run: &str, q: &str| get(&format!("{}/lineage/{}?{}", reg.url(), run, q));
+    let (code, down1) = lineage(id(&root), "direction=down&depth=1");
+    assert_eq!(code, 200);
+    let mut expect = vec![(id(&a).to_string(), 1), (id(&c).to_string(), 1)];
+    expect.sort();
+    assert_eq!(ids(&down1["nodes"]), expect);
+
+    let (_, down) = lineage(id(&root), "direction=down");
+    let mut expect = vec![(id(&a).to_string(), 1), (id(&c).to_string(), 1), (id(&b).to_string(), 2), (id(&join).to_string(), 2)];
+    expect.sort();
+    assert_eq!(ids(&down["nodes"]), expect);
+    // join is reached from both b and c; every edge is listed, every node once.
+    assert_eq!(down["edges"].as_array().unwrap().len(), 5);
+
+    let (_, up) = lineage(id(&join), "direction=up&depth=2");
+    let mut expect = vec![(id(&b).to_string(), 1), (id(&c).to_string(), 1), (id(&a).to_string(), 2), (id(&root).to_string(), 2)];
+    expect.sort();
+    assert_eq!(ids(&up["nodes"]), expect);
+    assert_eq!(up["direction"], "up");
+
+    assert_eq!(lineage(id(&b), "direction=sideways").0, 400);
+    assert_eq!(lineage(id(&b), "depth=-1").0, 400);
+    assert_eq!(lineage(&digest("nope"), "").0, 404);
+}
+
+#[test]
+fn receipts_list_with_filters_and_cursors() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let db = d.join("r.db");
+    let mut published = vec![];
+    {
+        let reg = start(&db);
+        for i in 0..25 {
+            let program = if i % 5 == 0 { "report" } else { "etl" };
+            let package = if i % 2 == 0 { Some("acme/etl") } else { None };
+            let r = receipt(program, serde_json::json!({ "i": i }), &[], package);
+            assert_eq!(post(&format!("{}/publish", reg.url()), &r), 200);
+            published.push(id(&r).to_string());
+        }
+    }
+    // Restart: listing works from the stored index.
+    let reg = start(&db);
+
+    let mut seen = vec![];
+    let mut cursor: Option<String> = None;
+    let mut pages = 0;
+    loop {
+        let mut url = format!("{}/receipts?limit=10", reg.url());
+        if let Some(c) = &cursor {
+            url.push_str(&format!("&cursor={}", c));
+        }
+        let (code, page) = get(&url);
+        assert_eq!(code, 200, "{}", page);
+        pages += 1;
+        seen.extend(page["receipts"].as_array().unwrap().iter().map(|r| r["run_id"].as_str().unwrap().to_string()));
+        match page["next_cursor"].as_str() {
+            Some(c) => cursor = Some(c.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 3);
+    let mut sorted = seen.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), 25);
+    published.sort();
+    assert_eq!(sorted, published);
+
+    let count = |q: &str| {
+        let (code, page) = get(&format!("{}/receipts?{}", reg.url(), q));
+        assert_eq!(code, 200, "{}", page);
+        page["receipts"].as_array().unwrap().len()
+    };
+    assert_eq!(count(&format!("program={}", digest("report"))), 5);
+    assert_eq!(count("package=acme%2Fetl"), 13);
+    assert_eq!(count(&format!("program={}&package=acme/etl", digest("report"))), 3);
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(count(&format!("since={}", now - 3600)), 25);
+    assert_eq!(count(&format!("until={}", now - 3600)), 0);
+    assert_eq!(count(&format!("since={}", now + 3600)), 0);
+    let (_, one) = get(&format!("{}/receipts?package=acme/etl&limit=1", reg.url()));
+    assert_eq!(one["receipts"][0]["package"], "acme/etl");
+    assert_eq!(one["receipts"][0]["program"].as_str().map(|p| p.starts_with("sha256:")), Some(true));
+    assert_eq!(get(&format!("{}/receipts?since=yesterday", reg.url())).0, 400);
+    assert_eq!(get(&format!("{}/receipts?limit=0", reg.url())).0, 400);
+}
+
+#[test]
+fn receipts_stored_before_the_index_are_indexed_on_startup() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let db = d.join("r.db");
+    let parent = receipt("p", serde_json::json!(1), &[], None);
+    let child = receipt("c", serde_json::json!(2), &[id(&parent)], None);
+    {
+        // As written by `fardrun --receipts sqlite:` or an older registry.
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.execute_batch(fard_v0_5_language_gate::receipt_store::RECEIPTS_SCHEMA).unwrap();
+        for r in [&parent, &child] {
+            conn.execute("INSERT INTO receipts (run_id, raw_json) VALUES (?1, ?2)", [id(r), &r.to_string()]).unwrap();
+        }
+    }
+    let reg = start(&db);
+    let (_, down) = get(&format!("{}/lineage/{}?direction=down", reg.url(), id(&parent)));
+    assert_eq!(ids(&down["nodes"]), vec![(id(&child).to_string(), 1)]);
+    let (_, all) = get(&format!("{}/receipts", reg.url()));
+    assert_eq!(all["receipts"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn package_search_is_paginated_and_used_by_fardrun_search() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let reg = start(&d.join("r.db"));
+    let publish = |name: &str, version: &str, description: &str| {
+        let body = serde_json::json!({
+            "name": name, "version": version, "description": description,
+            "entry_digest": digest(name), "tarball_url": format!("https://example.com/{}-{}.tgz", name, version),
+        });
+        assert_eq!(post(&format!("{}/packages/publish", reg.url()), &body), 200);
+    };
+    publish("csv", "1.0.0", "CSV parser");
+    publish("json5", "0.1.0", "JSON5 parsing");
+    publish("json5", "0.2.0", "JSON5 parsing and printing");
+    publish("yaml", "2.0.0", "YAML support");
+    for i in 0..5 {
+        publish(&format!("util{}", i), "1.0.0", "helpers");
+    }
+
+    let (_, hits) = get(&format!("{}/search?q=PARS", reg.url()));
+    let names: Vec<(&str, &str)> = hits["packages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| (p["name"].as_str().unwrap(), p["version"].as_str().unwrap()))
+        .collect();
+    assert_eq!(names, [("csv", "1.0.0"), ("json5", "0.2.0")]);
+    assert!(hits["next_cursor"].is_null());
+
+    let (_, first) = get(&format!("{}/packages?limit=4", reg.url()));
+    assert_eq!(first["packages"].as_array().unwrap().len(), 4);
+    let cursor = first["next_cursor"].as_str().unwrap().to_string();
+    let (_, rest) = get(&format!("{}/packages?limit=4&cursor={}", reg.url(), cursor));
+    // Eight packages, one entry each however many versions they have.
+    assert_eq!(rest["packages"].as_array().unwrap().len(), 4);
+    assert_eq!(rest["packages"][3]["name"], "yaml");
+    assert!(rest["next_cursor"].is_null());
+    // Without a query string the listing keeps its original shape: every version.
+    let (_, all) = get(&format!("{}/packages", reg.url()));
+    assert_eq!(all.as_array().unwrap().len(), 9);
+
+    // Bodies are parsed as JSON proper: \u escapes decode, truncated bodies are rejected.
+    let raw = |body: &str| match ureq::post(&format!("{}/packages/publish", reg.url())).send_string(body) {
+        Ok(resp) => resp.status(),
+        Err(ureq::Error::Status(code, _)) => code,
+        Err(e) => panic!("{}", e),
+    };
+    let body = r#"{"name": "caf\u00e9", "version": "1.0.0", "description": "tab\there \ud83d\ude00",
+        "entry_digest": "sha256:00", "tarball_url": "https://example.com/cafe.tgz"}"#;
+    assert_eq!(raw(body), 200);
+    let (_, hits) = get(&format!("{}/search?q=caf%C3%A9", reg.url()));
+    assert_eq!(hits["packages"][0]["description"], "tab\there \u{1f600}");
+    assert_eq!(raw(r#"{"name": "trunc"#), 400);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(d)
+        .args(["search", "json", "--registry", reg.url()])
+        .env("HOME", d)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("1 package(s) found"), "{}", stdout);
+    assert!(stdout.contains("json5@0.2.0  —  JSON5 parsing and printing"), "{}", stdout);
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use fard_v0_5_language_gate::registry::{Registry, RegistryConfig, SyncReport};
+
+mod common;
+use common::{get_json, post, receipt, tmpdir};
+
+fn start(d: &std::path::Path, name: &str, interval: Duration) -> Registry {
+    let mut cfg = RegistryConfig::new(d.join(format!("{}.db", name)), "127.0.0.1:0");
+    cfg.sync_interval = interval;
+    Registry::start(cfg).unwrap()
+}
+
+fn summary(r: &Registry) -> (serde_json::Value, serde_json::Value, serde_json::Value) {
+    let range = get_json(&format!("{}/sync/range/", r.url()));
+    let stats = get_json(&format!("{}/stats", r.url()));
+    (range["digest"].clone(), stats["count"].clone(), get_json(&format!("{}/crdt/state", r.url())))
+}
+
+#[test]
+fn three_registries_converge_transferring_only_missing_entries() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let regs: Vec<Registry> = ["a", "b", "c"].iter().map(|n| start(d, n, Duration::from_secs(3600))).collect();
+
+    // 90 shared-nothing receipts spread over the three, plus a chain whose parent
+    // lives on a different registry than its child.
+    for i in 0..90 {
+        assert_eq!(post(&format!("{}/publish", regs[i % 3].url()), &receipt("graph", serde_json::json!({ "n": i }), &[], None)), 200);
+    }
+    let parent = receipt("graph", serde_json::json!("parent"), &[], None);
+    let parent_id = parent["run_id"].as_str().unwrap().to_string();
+    let child = receipt("graph", serde_json::json!("child"), &[&parent_id], None);
+    assert_eq!(post(&format!("{}/publish", regs[0].url()), &parent), 200);
+    assert_eq!(post(&format!("{}/publish", regs[0].url()), &child), 200);
+    // A receipt everyone already has is never transferred.
+    let common = receipt("graph", serde_json::json!("common"), &[], None);
+    for r in &regs {
+        assert_eq!(post(&format!("{}/publish", r.url()), &common), 200);
+    }
+
+    // Two registries disagree about the canonical run for one effect; the minimum wins.
+    let (low, high) = (format!("sha256:{}", "1".repeat(64)), format!("sha256:{}", "9".repeat(64)));
+    let propose = |r: &Registry, req_hex: &str, run_id: &str| {
+        post(
+            &format!("{}/crdt/propose", r.url()),
+            &serde_json::json!({ "effect_kind": "http.get", "req_hex": req_hex, "run_id": run_id }),
+        )
+    };
+    assert_eq!(propose(&regs[1], "aa", &high), 200);
+    assert_eq!(propose(&regs[2], "aa", &low), 200);
+    assert_eq!(propose(&regs[0], "bb", &high), 200);
+
+    // Ring: a → b → c → a. One round moves every missing entry at least one hop.
+    for i in 0..3 {
+        regs[i].add_peer(regs[(i + 1) % 3].url());
+    }
+    let mut total = SyncReport::default();
+    for _ in 0..2 {
+        for r in &regs {
+            let round = r.sync_once();
+            total.pulled += round.pulled;
+            total.pushed += round.pushed;
+        }
+    }
+    // 93 distinct receipts; a starts with 33 and b and c with 31 each. Every transfer
+    // fills a gap, so the total is exactly what was missing.
+    assert_eq!(total.pulled + total.pushed, 60 + 62 + 62);
+    let expected = summary(&regs[0]);
+    assert_eq!(expected.1, 93);
+    for r in &regs[1..] {
+        assert_eq!(summary(r), expected);
+    }
+    assert_eq!(expected.2["certs"].as_object().unwrap().len(), 2);
+    assert_eq!(get_json(&format!("{}/crdt/get/http.get/aa", regs[1].url()))["ok"], low.as_str());
+
+    // Converged replicas have nothing left to exchange.
+    for r in &regs {
+        assert_eq!(r.sync_once(), SyncReport::default());
+    }
+    // Replicated receipts are stored byte-for-byte and still verify as a chain.
+    assert_eq!(get_json(&format!("{}/receipt/{}", regs[2].url(), child["run_id"].as_str().unwrap())), child);
+    assert_eq!(get_json(&format!("{}/verify/{}", regs[1].url(), child["run_id"].as_str().unwrap()))["depth"], 1);
+    assert_eq!(get_json(&format!("{}/audit/{}", regs[2].url(), parent_id)).as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn crdt_sync_ships_only_what_the_peer_has_not_acknowledged() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let a = start(d, "a", Duration::from_secs(3600));
+    let b = start(d, "b", Duration::from_secs(3600));
+    let propose = |r: &Registry, req_hex: &str| {
+        let run_id = format!("sha256:{}", req_hex.repeat(32));
+        let body = serde_json::json!({ "effect_kind": "http.get", "req_hex": req_hex, "run_id": run_id });
+        assert_eq!(post(&format!("{}/crdt/propose", r.url()), &body), 200);
+    };
+    let crdt = |pushed, pulled| SyncReport { crdt_pushed: pushed, crdt_pulled: pulled, ..SyncReport::default() };
+    for req in ["01", "02", "03"] {
+        propose(&a, req);
+    }
+    propose(&b, "10");
+    a.add_peer(b.url());
+
+    // First contact: neither side has a record of the other, so whole states move.
+    assert_eq!(a.sync_once(), crdt(3, 1));
+    assert_eq!(a.sync_once(), SyncReport::default());
+    // From then on only the changes since the last acknowledgement do.
+    propose(&a, "04");
+    propose(&b, "11");
+    propose(&b, "12");
+    assert_eq!(a.sync_once(), crdt(1, 2));
+    assert_eq!(get_json(&format!("{}/crdt/state", a.url())), get_json(&format!("{}/crdt/state", b.url())));
+    let unacked = get_json(&format!("{}/crdt/delta?replica={}", b.url(), a.url()));
+    assert_eq!(unacked["updates"], serde_json::json!({}));
+
+    // The delta log and acknowledgements live in the database and survive a restart.
+    drop(a);
+    let a = start(d, "a", Duration::from_secs(3600));
+    a.add_peer(b.url());
+    propose(&a, "05");
+    let report = a.sync_once();
+    assert_eq!(report.crdt_pushed, 1);
+    assert_eq!(get_json(&format!("{}/crdt/state", b.url()))["certs"].as_object().unwrap().len(), 8);
+}
+
+#[test]
+fn peers_sync_periodically() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let a = start(d, "a", Duration::from_secs(3600));
+    let mut cfg = RegistryConfig::new(d.join("b.db"), "127.0.0.1:0");
+    cfg.peers = vec![a.url().to_string()];
+    cfg.sync_interval = Duration::from_millis(100);
+    let b = Registry::start(cfg).unwrap();
+
+    let r = receipt("graph", serde_json::json!([1, 2, 3]), &[], None);
+    assert_eq!(post(&format!("{}/publish", a.url()), &r), 200);
+    let url = format!("{}/receipt/{}", b.url(), r["run_id"].as_str().unwrap());
+    let mut found = false;
+    for _ in 0..100 {
+        if ureq::get(&url).call().is_ok() {
+            found = true;
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(found, "b never pulled the receipt from its peer");
+    let audit = get_json(&format!("{}/audit/{}", b.url(), r["run_id"].as_str().unwrap()));
+    assert_eq!(audit[0]["publisher"], format!("peer:{}", a.url()).as_str());
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn fardkey(cwd: &Path, args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_fardkey")).current_dir(cwd).args(args).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8_lossy(&out.stdout).trim().to_string()
+}
+
+fn run_in(cwd: &Path, prog: &str, out: &str, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(cwd)
+        .args(["run", "--program", prog, "--out", out])
+        .args(extra)
+        .env("HOME", cwd)
+        .env_remove("FARD_REGISTRY_URL")
+        .output()
+        .unwrap()
+}
+
+fn verify_in(cwd: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardverify")).current_dir(cwd).args(args).env("HOME", cwd).output().unwrap()
+}
+
+fn json(path: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn signed_runs_verify_against_the_trust_policy() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let alice = fardkey(d, &["generate", "--out", "alice.pem", "--pub", "alice.pub.pem"]);
+    fardkey(d, &["generate", "--out", "mallory.pem"]);
+    assert!(alice.starts_with("ed25519:") && alice.len() == 8 + 64, "{}", alice);
+    assert_eq!(fardkey(d, &["pub", "alice.pem"]), alice);
+    assert!(fs::read_to_string(d.join("alice.pub.pem")).unwrap().starts_with("-----BEGIN PUBLIC KEY-----"));
+
+    let pkg = d.join("billing");
+    fs::create_dir_all(&pkg).unwrap();
+    fs::write(pkg.join("fard.toml"), "[package]\nname = \"acme/billing\"\n").unwrap();
+    fs::write(pkg.join("main.fard"), "{ total: 7 }\n").unwrap();
+    let out = run_in(d, "billing/main.fard", "signed", &["--sign-key", "alice.pem", "--receipts", "store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let out = run_in(d, "billing/main.fard", "forged", &["--sign-key", "mallory.pem", "--receipts", "store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let out = run_in(d, "billing/main.fard", "unsigned", &["--receipts", "store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(!d.join("unsigned/signature.json").exists());
+
+    // Signing does not change the run itself.
+    let run_id = json(&d.join("signed/digests.json"))["preimage_sha256"].as_str().unwrap().to_string();
+    assert_eq!(json(&d.join("unsigned/digests.json"))["preimage_sha256"], run_id.as_str());
+    // The package is part of the run ID's preimage.
+    assert_eq!(json(&d.join("signed/digests.json"))["package"], "acme/billing");
+    let bundle = verify_in(d, &["bundle", "--out", "signed"]);
+    assert!(bundle.status.success(), "{}", String::from_utf8_lossy(&bundle.stderr));
+    let sig = json(&d.join("signed/signature.json"));
+    assert_eq!(sig["alg"], "ed25519");
+    assert_eq!(sig["key"], alice.as_str());
+    assert_eq!(sig["package"], "acme/billing");
+    assert_eq!(sig["run_id"], run_id.as_str());
+
+    // Lists may span lines; a `#` inside a string is not a comment.
+    fs::write(
+        d.join("trust.toml"),
+        format!("[signers]\n\"acme/*\" = [ # billing team\n  \"{}\",\n]\n\"#draft\" = []\n", alice),
+    )
+    .unwrap();
+    fs::write(d.join("other.toml"), format!("[signers]\n\"other/pkg\" = [\"{}\"]\n", alice)).unwrap();
+
+    let ok = verify_in(d, &["signature", "--out", "signed", "--trust", "trust.toml"]);
+    assert!(ok.status.success(), "{}", String::from_utf8_lossy(&ok.stderr));
+    assert!(String::from_utf8_lossy(&ok.stdout).contains(&format!("signature ok — {}", alice)));
+
+    let expect_fail = |out: &str, trust: &str, code: &str| {
+        let r = verify_in(d, &["signature", "--out", out, "--trust", trust]);
+        assert!(!r.status.success());
+        let stderr = String::from_utf8_lossy(&r.stderr);
+        assert!(stderr.contains(code), "{}", stderr);
+    };
+    expect_fail("unsigned", "trust.toml", "ERROR_UNSIGNED");
+    expect_fail("forged", "trust.toml", "ERROR_UNTRUSTED");
+    expect_fail("signed", "other.toml", "not trusted for package acme/billing");
+
+    // A signature that does not match the run ID is rejected.
+    fs::create_dir_all(d.join("tampered")).unwrap();
+    fs::copy(d.join("signed/digests.json"), d.join("tampered/digests.json")).unwrap();
+    let mut bad = sig.clone();
+    bad["sig"] = serde_json::json!("00".repeat(64));
+    fs::write(d.join("tampered/signature.json"), bad.to_string()).unwrap();
+    expect_fail("tampered", "trust.toml", "ERROR_BAD_SIGNATURE");
+
+    // So is one moved to another package the same key may sign for.
+    let mut moved = sig.clone();
+    moved["package"] = serde_json::json!("acme/payroll");
+    fs::write(d.join("tampered/signature.json"), moved.to_string()).unwrap();
+    expect_fail("tampered", "trust.toml", "ERROR_BAD_SIGNATURE");
+
+    // All three runs share a run ID; the unsigned one published the receipt last.
+    let hex = run_id.strip_prefix("sha256:").unwrap();
+    let receipt = json(&d.join("store").join(format!("sha256_{}.json", hex)));
+    assert_eq!(receipt["package"], "acme/billing");
+    assert!(receipt.get("signature").is_none());
+
+    fs::write(d.join("consumer.fard"), format!("artifact prior = \"{}\"\nprior.total\n", run_id)).unwrap();
+    let out = run_in(d, "consumer.fard", "consumer", &["--sign-key", "alice.pem", "--receipts", "store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    fs::write(d.join("trust_all.toml"), format!("[signers]\n\"*\" = [\"{}\"]\n", alice)).unwrap();
+    let chain = |trust: &str| verify_in(d, &["chain", "--out", "consumer", "--receipts", "dir:store", "--trust", trust]);
+    let bad = chain("trust_all.toml");
+    assert!(!bad.status.success());
+    assert!(String::from_utf8_lossy(&bad.stderr).contains("CHAIN_RECEIPT_UNTRUSTED"));
+    assert!(String::from_utf8_lossy(&bad.stderr).contains("ERROR_UNSIGNED"));
+
+    // Republish with alice's key; now the whole chain is trusted.
+    let out = run_in(d, "billing/main.fard", "signed", &["--sign-key", "alice.pem", "--receipts", "store"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let good = chain("trust_all.toml");
+    let stdout = String::from_utf8_lossy(&good.stdout);
+    assert!(good.status.success(), "{}{}", stdout, String::from_utf8_lossy(&good.stderr));
+    assert!(stdout.contains("chain ok — 2 node(s) verified"), "{}", stdout);
+
+    // Rewriting the package of a stored receipt breaks its signature.
+    let path = d.join("store").join(format!("sha256_{}.json", hex));
+    let mut receipt = json(&path);
+    receipt["package"] = serde_json::json!("acme/payroll");
+    fs::write(&path, receipt.to_string()).unwrap();
+    let moved = chain("trust_all.toml");
+    assert!(!moved.status.success());
+    let stderr = String::from_utf8_lossy(&moved.stderr);
+    assert!(stderr.contains("CHAIN_RECEIPT_UNTRUSTED") && stderr.contains("ERROR_BAD_SIGNATURE"), "{}", stderr);
+}
+
+struct Registry(Child);
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn registry_rejects_unsigned_and_untrusted_receipts() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let alice = fardkey(d, &["generate", "--out", "alice.pem"]);
+    fardkey(d, &["generate", "--out", "mallory.pem"]);
+    fs::write(d.join("trust.toml"), format!("# registry policy\n[signers]\n\"*\" = [\"{}\"]\n", alice)).unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let _registry = Registry(
+        Command::new(env!("CARGO_BIN_EXE_fardregistry"))
+            .current_dir(d)
+            .args(["--port", &port.to_string(), "--db", "registry.db", "--trust", "trust.toml"])
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    for _ in 0..100 {
+        if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let url = format!("http://127.0.0.1:{}", port);
+
+    fs::write(d.join("main.fard"), "[1, 2]\n").unwrap();
+    for (out, key) in [("signed", Some("alice.pem")), ("forged", Some("mallory.pem")), ("unsigned", None)] {
+        let mut args = vec!["--receipts", "local"];
+        if let Some(k) = key {
+            args.extend(["--sign-key", k]);
+        }
+        let r = run_in(d, "main.fard", out, &args);
+        assert!(r.status.success(), "{}", String::from_utf8_lossy(&r.stderr));
+    }
+    let run_id = json(&d.join("signed/digests.json"))["preimage_sha256"].as_str().unwrap().to_string();
+    let receipt = |key: &str| {
+        let mut r = json(&d.join("local").join(format!("sha256_{}.json", run_id.strip_prefix("sha256:").unwrap())));
+        match key {
+            "" => {
+                r.as_object_mut().unwrap().remove("signature");
+            }
+            k => {
+                let sig = json(&d.join(k).join("signature.json"));
+                r["signature"] = serde_json::json!({ "alg": "ed25519", "key": sig["key"], "sig": sig["sig"] });
+            }
+        }
+        r.to_string()
+    };
+    let publish = |body: String| match ureq::post(&format!("{}/publish", url)).send_string(&body) {
+        Ok(resp) => (resp.status(), resp.into_string().unwrap()),
+        Err(ureq::Error::Status(code, resp)) => (code, resp.into_string().unwrap()),
+        Err(e) => panic!("{}", e),
+    };
+
+    let (code, body) = publish(receipt(""));
+    assert_eq!(code, 403, "{}", body);
+    assert!(body.contains("ERROR_UNSIGNED"), "{}", body);
+    let (code, body) = publish(receipt("forged"));
+    assert_eq!(code, 403, "{}", body);
+    assert!(body.contains("ERROR_UNTRUSTED"), "{}", body);
+    assert_eq!(ureq::get(&format!("{}/receipt/{}", url, run_id)).call().map(|_| ()).err().map(|e| matches!(e, ureq::Error::Status(404, _))), Some(true));
+
+    let mut moved: serde_json::Value = serde_json::from_str(&receipt("signed")).unwrap();
+    moved["package"] = serde_json::json!("acme/billing");
+    let (code, body) = publish(moved.to_string());
+    assert_eq!(code, 403, "{}", body);
+    assert!(body.contains("ERROR_BAD_SIGNATURE"), "{}", body);
+
+    let (code, body) = publish(receipt("signed"));
+    assert_eq!(code, 200, "{}", body);
+    let stored = ureq::get(&format!("{}/receipt/{}", url, run_id)).call().unwrap().into_string().unwrap();
+    assert!(stored.contains(&alice), "{}", stored);
+
+    // fardrun publishing straight to the registry is held to the same policy.
+    fs::write(d.join("other.fard"), "\"other\"\n").unwrap();
+    let r = run_in(d, "other.fard", "o1", &["--receipts", &url]);
+    assert!(r.status.success());
+    let other_id = json(&d.join("o1/digests.json"))["preimage_sha256"].as_str().unwrap().to_string();
+    assert!(ureq::get(&format!("{}/receipt/{}", url, other_id)).call().is_err());
+    let r = run_in(d, "other.fard", "o2", &["--receipts", &url, "--sign-key", "alice.pem"]);
+    assert!(r.status.success(), "{}", String::from_utf8_lossy(&r.stderr));
+    assert!(ureq::get(&format!("{}/receipt/{}", url, other_id)).call().is_ok());
+}
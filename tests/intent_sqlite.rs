@@ -0,0 +1,129 @@
+use std::fs;
+use std::process::Command;
+
+mod common;
+use common::{events, fardrun, result, tmpdir};
+
+const PROGRAM: &str = r#"import("std/sqlite") as sqlite
+
+let db = sqlite.open("data/app.db")?
+let _ = sqlite.exec(db, "CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY, name TEXT, score REAL, avatar BLOB, note TEXT)")?
+let add = sqlite.prepare(db, "INSERT INTO users (name, score, avatar) VALUES (?1, ?2, ?3)")?
+let first = sqlite.run(add, ["ada", 1.5, null])?
+let kept = sqlite.transaction(db, fn() { sqlite.run(add, ["bob", 2.5, null]) })?
+let dropped = sqlite.transaction(db, fn() {
+  let _ = sqlite.run(add, ["eve", 3.0, null])
+  sqlite.exec(db, "INSERT INTO missing VALUES (1)", [])
+})
+let by_name = sqlite.query(db, "SELECT id, name, score, note FROM users WHERE name = :name", { name: "bob" })?
+let names = sqlite.all(sqlite.prepare(db, "SELECT name FROM users ORDER BY id")?)?
+{ first: first, kept: kept, dropped: dropped, by_name: by_name, names: names }
+"#;
+
+#[test]
+fn rows_map_to_records_and_transactions_roll_back() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::create_dir_all(d.join("data")).unwrap();
+    let o = fardrun(d, PROGRAM, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["first"], serde_json::json!({"changes": 1, "last_insert_id": 1}));
+    assert_eq!(r["kept"]["last_insert_id"], 2);
+    assert_eq!(r["dropped"]["t"], "err");
+    assert!(r["dropped"]["e"].as_str().unwrap().contains("no such table: missing"), "{}", r);
+    assert_eq!(r["by_name"], serde_json::json!([{"id": 2, "name": "bob", "note": null, "score": 2.5}]));
+    assert_eq!(r["names"], serde_json::json!([{"name": "ada"}, {"name": "bob"}]));
+
+    // Every statement is in the trace, failures included, and the trace still verifies.
+    let events = events(d, "sqlite");
+    let sql: Vec<&str> = events.iter().map(|e| e["sql"].as_str().unwrap()).collect();
+    assert!(sql.contains(&"BEGIN") && sql.contains(&"COMMIT") && sql.contains(&"ROLLBACK"), "{:?}", sql);
+    let failed: Vec<&serde_json::Value> = events.iter().filter(|e| e.get("err").is_some()).collect();
+    assert_eq!(failed.len(), 1);
+    assert!(failed[0].get("result_digest").is_none());
+    let o = Command::new(env!("CARGO_BIN_EXE_fardverify")).args(["trace", "--out"]).arg(d.join("out")).output().unwrap();
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+}
+
+#[test]
+fn result_digests_track_what_the_database_returned() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let read = "import(\"std/sqlite\") as sqlite\n\nlet db = sqlite.open(\"kv.db\")?\nsqlite.query(db, \"SELECT v FROM kv\")?\n";
+    let setup = |v: i64| {
+        let src = format!(
+            "import(\"std/sqlite\") as sqlite\n\nlet db = sqlite.open(\"kv.db\")?\nsqlite.exec(db, \"DROP TABLE IF EXISTS kv; CREATE TABLE kv (v INTEGER); INSERT INTO kv VALUES ({});\")?\n",
+            v
+        );
+        assert!(fardrun(d, &src, &[]).status.success());
+    };
+    let digest = || {
+        assert!(fardrun(d, read, &[]).status.success());
+        let events = events(d, "sqlite");
+        assert_eq!(events.len(), 1);
+        events[0]["result_digest"].as_str().unwrap().to_string()
+    };
+    setup(1);
+    let one = digest();
+    assert_eq!(digest(), one);
+    setup(2);
+    assert_ne!(digest(), one);
+    assert_eq!(result(d), serde_json::json!([{"v": 2}]));
+}
+
+#[test]
+fn database_paths_are_sandboxed_and_policed() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let o = fardrun(d, "import(\"std/sqlite\") as sqlite\nsqlite.open(\"../escape.db\")\n", &[]);
+    assert!(!o.status.success());
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_SANDBOX"));
+    assert!(!d.join("../escape.db").exists());
+
+    fs::write(d.join("policy.toml"), "fs_read = [\".\"]\nfs_write = []\n").unwrap();
+    let o = fardrun(d, "import(\"std/sqlite\") as sqlite\nsqlite.open(\"app.db\")\n", &["--policy", "policy.toml"]);
+    assert!(String::from_utf8_lossy(&o.stderr).contains("ERROR_CAPABILITY"), "{}", String::from_utf8_lossy(&o.stderr));
+    assert!(!d.join("app.db").exists());
+
+    // In-memory databases touch no files.
+    let o = fardrun(
+        d,
+        "import(\"std/sqlite\") as sqlite\nlet db = sqlite.open(\":memory:\")?\nsqlite.query(db, \"SELECT 1 + 1 AS two\")?\n",
+        &["--policy", "policy.toml"],
+    );
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    assert_eq!(result(d), serde_json::json!([{"two": 2}]));
+}
+
+#[test]
+fn spawned_tasks_share_the_databases_their_parent_opened() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let src = r#"import("std/sqlite") as sqlite
+import("std/list") as list
+import("std/promise") as promise
+
+let db = sqlite.open("app.db")?
+let _ = sqlite.exec(db, "CREATE TABLE IF NOT EXISTS t (v INTEGER)")?
+let add = sqlite.prepare(db, "INSERT INTO t (v) VALUES (?1)")?
+let added = list.par_map([1, 2, 3, 4], fn(v) { sqlite.run(add, [v])?.changes })
+let own = promise.spawn(fn() {
+  let mine = sqlite.open(":memory:")?
+  let _ = sqlite.exec(mine, "CREATE TABLE t (v INTEGER); INSERT INTO t VALUES (10);")?
+  let theirs = sqlite.query(db, "SELECT count(*) AS n FROM t")?
+  { mine: mine.handle, theirs: theirs, own: sqlite.query(mine, "SELECT v FROM t")? }
+})
+let seen = promise.await(own)
+{ added: added, seen: seen, db: db.handle, total: sqlite.query(db, "SELECT sum(v) AS s FROM t")? }
+"#;
+    let o = fardrun(d, src, &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r = result(d);
+    assert_eq!(r["added"], serde_json::json!([1, 1, 1, 1]));
+    assert_eq!(r["seen"]["theirs"], serde_json::json!([{"n": 4}]));
+    assert_eq!(r["seen"]["own"], serde_json::json!([{"v": 10}]));
+    // A database opened in a child gets a handle of its own.
+    assert_ne!(r["seen"]["mine"], r["db"]);
+    assert_eq!(r["total"], serde_json::json!([{"s": 10}]));
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+mod common;
+use common::tmpdir;
+
+fn version_stdlib_root() -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .arg("--version")
+        .output()
+        .unwrap();
+    let text = String::from_utf8_lossy(&out.stdout).to_string();
+    text.lines()
+        .find_map(|l| l.strip_prefix("stdlib_root_cid="))
+        .expect("stdlib_root_cid line")
+        .to_string()
+}
+
+fn run_ok(dir: &Path) -> PathBuf {
+    let prog = dir.join("main.fard");
+    fs::write(&prog, "{ ok: true }\n").unwrap();
+    let out = dir.join("out");
+    let st = Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .arg("run")
+        .arg("--program")
+        .arg(&prog)
+        .arg("--out")
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(st.success());
+    out
+}
+
+fn verify_bundle(out: &Path, extra: &[&str]) -> (bool, String) {
+    let o = Command::new(env!("CARGO_BIN_EXE_fardverify"))
+        .arg("bundle")
+        .arg("--out")
+        .arg(out)
+        .args(extra)
+        .output()
+        .unwrap();
+    (o.status.success(), String::from_utf8_lossy(&o.stderr).to_string())
+}
+
+/// Rewrite digests.json with a different stdlib root and a consistent preimage.
+fn forge_stdlib_root(out: &Path, root: &str) {
+    let p = out.join("digests.json");
+    let mut d: serde_json::Value = serde_json::from_slice(&fs::read(&p).unwrap()).unwrap();
+    d["stdlib_root_digest"] = serde_json::Value::String(root.to_string());
+    let pre = serde_json::json!({
+        "files": d["files"],
+        "ok": d["ok"],
+        "runtime_version": d["runtime_version"],
+        "stdlib_root_digest": d["stdlib_root_digest"],
+        "trace_format_version": d["trace_format_version"],
+    });
+    let mut h = Sha256::new();
+    h.update(serde_json::to_string(&pre).unwrap().as_bytes());
+    d["preimage_sha256"] = serde_json::Value::String(format!("sha256:{}", hex::encode(h.finalize())));
+    fs::write(&p, serde_json::to_vec(&d).unwrap()).unwrap();
+}
+
+#[test]
+fn version_and_digests_share_a_real_stdlib_root() {
+    let root = version_stdlib_root();
+    assert_ne!(root, "sha256:dev");
+    assert!(root.starts_with("sha256:") && root.len() == 71, "{}", root);
+
+    let tmp = tmpdir();
+    let out = run_ok(tmp.path());
+    let d: serde_json::Value =
+        serde_json::from_slice(&fs::read(out.join("digests.json")).unwrap()).unwrap();
+    assert_eq!(d["stdlib_root_digest"], serde_json::Value::String(root));
+
+    let (ok, err) = verify_bundle(&out, &[]);
+    assert!(ok, "{}", err);
+}
+
+#[test]
+fn bundle_with_unknown_stdlib_root_is_rejected() {
+    let tmp = tmpdir();
+    let dir = tmp.path();
+    let out = run_ok(dir);
+    let foreign = format!("sha256:{}", "ab".repeat(32));
+    forge_stdlib_root(&out, &foreign);
+
+    let (ok, err) = verify_bundle(&out, &[]);
+    assert!(!ok);
+    assert!(err.contains("M5_UNKNOWN_STDLIB_ROOT"), "{}", err);
+
+    let roots = dir.join("roots.txt");
+    fs::write(&roots, format!("# other runtime\n{}\n", foreign)).unwrap();
+    let (ok, err) = verify_bundle(&out, &["--stdlib-roots", roots.to_str().unwrap()]);
+    assert!(ok, "{}", err);
+}
+
+fn fardrun_in(dir: &Path, src: &str, out: &str, extra: &[&str]) -> std::process::Output {
+    fs::write(dir.join(format!("{}.fard", out)), src).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(dir)
+        .args(["run", "--program", &format!("{}.fard", out), "--out", out, "--receipts", "store"])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+/// Re-publish the only receipt in `store` as if a runtime with stdlib root `root` made it.
+fn forge_receipt_stdlib_root(store: &Path, root: &str) -> String {
+    let entry = fs::read_dir(store).unwrap().map(|e| e.unwrap().path()).find(|p| p.extension().is_some_and(|x| x == "json")).unwrap();
+    let mut r: serde_json::Value = serde_json::from_slice(&fs::read(entry).unwrap()).unwrap();
+    r["preimage"]["stdlib_root_digest"] = serde_json::Value::String(root.to_string());
+    let hex = hex::encode(Sha256::digest(serde_json::to_string(&r["preimage"]).unwrap().as_bytes()));
+    r["run_id"] = serde_json::Value::String(format!("sha256:{}", hex));
+    fs::write(store.join(format!("sha256_{}.json", hex)), serde_json::to_vec(&r).unwrap()).unwrap();
+    format!("sha256:{}", hex)
+}
+
+#[test]
+fn parent_runs_from_unknown_runtimes_are_refused_on_import() {
+    let tmp = tmpdir();
+    let dir = tmp.path();
+    let o = fardrun_in(dir, "{ ok: true }\n", "parent", &["--record"]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let foreign = format!("sha256:{}", "cd".repeat(32));
+    let run_id = forge_receipt_stdlib_root(&dir.join("store"), &foreign);
+    forge_stdlib_root(&dir.join("parent"), &foreign);
+    let roots = dir.join("roots.txt");
+    fs::write(&roots, format!("{}\n", foreign)).unwrap();
+    let roots = ["--stdlib-roots", roots.to_str().unwrap()];
+
+    let child = format!("artifact p = \"{}\"\np.ok\n", run_id);
+    let o = fardrun_in(dir, &child, "child", &[]);
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert!(!o.status.success());
+    assert!(err.contains(&format!("ERROR_ARTIFACT run {} comes from a runtime with unknown stdlib root {}", run_id, foreign)), "{}", err);
+    let o = fardrun_in(dir, &child, "child", &roots);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+
+    // Spawned tasks accept the same extra roots as the run that started them.
+    let task = format!(
+        "import(\"std/promise\") as promise\nimport(\"std/witness\") as witness\nlet p = promise.spawn(fn() {{ witness.verify(\"{}\") }})\npromise.await(p).t\n",
+        run_id
+    );
+    let o = fardrun_in(dir, &task, "task", &roots);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("task/result.json")).unwrap()).unwrap();
+    assert_eq!(r["result"], "ok");
+    let o = fardrun_in(dir, &task, "task", &[]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let r: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("task/result.json")).unwrap()).unwrap();
+    assert_eq!(r["result"], "err");
+
+    let o = fardrun_in(dir, "{ ok: true }\n", "again", &["--replay", "parent"]);
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert!(!o.status.success());
+    assert!(err.contains("ERROR_REPLAY recorded run parent comes from a runtime with unknown stdlib root"), "{}", err);
+    let o = fardrun_in(dir, "{ ok: true }\n", "again", &["--replay", "parent", roots[0], roots[1]]);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+}
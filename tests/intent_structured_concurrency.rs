@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::tmpdir;
+
+fn run_threads(cwd: &Path, out: &str, threads: &str, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardrun"))
+        .current_dir(cwd)
+        .env("FARD_THREADS", threads)
+        .args(["run", "--program", "main.fard", "--out", out])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn verify_out(cmd: &str, out: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fardverify"))
+        .args([cmd, "--out", out.to_str().unwrap()])
+        .output()
+        .unwrap()
+}
+
+fn all_events(out: &Path) -> Vec<serde_json::Value> {
+    fs::read_to_string(out.join("trace.ndjson"))
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect()
+}
+
+const HELPER: &str = "fn double(x) { x * 2 }\n{ double: double }\n";
+
+const PROG: &str = r#"import("std/list") as list
+import("std/promise") as promise
+import("std/trace") as trace
+import("./helper") as h
+fn work(x) {
+  let _ = trace.emit({x: x})
+  h.double(x)
+}
+let xs = list.par_map([1, 2, 3, 4, 5], work)
+let p = promise.spawn(fn() { h.double(21) })
+let ys = promise.spawn_ordered([fn() { 1 }, fn() { list.par_map([7, 8], fn(y) { h.double(y) }) }])
+{ xs: xs, p: promise.await(p), ys: ys }
+"#;
+
+fn setup() -> tempfile::TempDir {
+    let tmp = tmpdir();
+    fs::write(tmp.path().join("helper.fard"), HELPER).unwrap();
+    fs::write(tmp.path().join("main.fard"), PROG).unwrap();
+    tmp
+}
+
+#[test]
+fn children_resolve_relative_imports_and_nest_their_spans() {
+    let tmp = setup();
+    let d = tmp.path();
+    let out = run_threads(d, "out", "4", &[]);
+    assert!(out.status.success(), "run failed: {}", String::from_utf8_lossy(&out.stderr));
+
+    let result: serde_json::Value =
+        serde_json::from_slice(&fs::read(d.join("out/result.json")).unwrap()).unwrap();
+    assert_eq!(result["result"], serde_json::json!({"p": 42, "xs": [2, 4, 6, 8, 10], "ys": [1, [14, 16]]}));
+
+    let evs = all_events(&d.join("out"));
+    let receipts: Vec<&serde_json::Value> = evs.iter().filter(|e| e["t"] == "child_receipt").collect();
+    let ids: Vec<&str> = receipts.iter().map(|e| e["spawn_id"].as_str().unwrap()).collect();
+    // spawn_5 is awaited after spawn_6 and spawn_7, but the receipts still merge in spawn order
+    assert_eq!(ids, ["spawn_0", "spawn_1", "spawn_2", "spawn_3", "spawn_4", "spawn_5", "spawn_6", "spawn_7"]);
+
+    // emit inside a par_map item lands in that item's sub-trace, named by its receipt
+    for (i, r) in receipts.iter().take(5).enumerate() {
+        assert!(r.get("events").is_none());
+        let rel = r["trace"].as_str().unwrap();
+        assert_eq!(rel, format!("children/spawn_{}.ndjson", i));
+        let ev: serde_json::Value =
+            serde_json::from_slice(&fs::read(d.join("out").join(rel)).unwrap()).unwrap();
+        assert_eq!(ev, serde_json::json!({"t": "emit", "v": {"x": i + 1}}));
+    }
+    // par_map inside a promise: the grandchildren's receipts sit in the child's file only
+    let inner = fs::read_to_string(d.join("out/children/spawn_7.ndjson")).unwrap();
+    assert!(inner.contains("children/spawn_7.0.ndjson") && inner.contains("children/spawn_7.1.ndjson"), "{}", inner);
+    assert!(d.join("out/children/spawn_7.1.ndjson").exists());
+    let top = fs::read_to_string(d.join("out/trace.ndjson")).unwrap();
+    assert!(!top.contains("spawn_7.1"), "{}", top);
+
+    let tv = verify_out("trace", &d.join("out"));
+    assert!(tv.status.success(), "trace verify: {}", String::from_utf8_lossy(&tv.stderr));
+    let bv = verify_out("bundle", &d.join("out"));
+    assert!(bv.status.success(), "bundle verify: {}", String::from_utf8_lossy(&bv.stderr));
+}
+
+#[test]
+fn trace_is_identical_across_runs_and_pool_sizes() {
+    let tmp = setup();
+    let d = tmp.path();
+    for (o, n) in [("o1", "4"), ("o2", "4"), ("o3", "1")] {
+        let out = run_threads(d, o, n, &[]);
+        assert!(out.status.success(), "run failed: {}", String::from_utf8_lossy(&out.stderr));
+    }
+    let t1 = fs::read(d.join("o1/trace.ndjson")).unwrap();
+    assert_eq!(t1, fs::read(d.join("o2/trace.ndjson")).unwrap());
+    assert_eq!(t1, fs::read(d.join("o3/trace.ndjson")).unwrap());
+    let c1 = fs::read(d.join("o1/children/spawn_7.ndjson")).unwrap();
+    assert_eq!(c1, fs::read(d.join("o3/children/spawn_7.ndjson")).unwrap());
+}
+
+#[test]
+fn tampered_child_span_fails_trace_verify() {
+    let tmp = setup();
+    let d = tmp.path();
+    assert!(run_threads(d, "out", "2", &[]).status.success());
+    let p = d.join("out/children/spawn_2.ndjson");
+    let s = fs::read_to_string(&p).unwrap().replace("\"x\":3", "\"x\":9");
+    fs::write(&p, s).unwrap();
+    let tv = verify_out("trace", &d.join("out"));
+    assert!(!tv.status.success());
+    assert!(String::from_utf8_lossy(&tv.stderr).contains("M2_CHILD_RUN_DIGEST_MISMATCH"));
+}
+
+#[test]
+fn tampered_grandchild_span_and_missing_child_trace_fail_trace_verify() {
+    let tmp = setup();
+    let d = tmp.path();
+    assert!(run_threads(d, "out", "2", &[]).status.success());
+    let p = d.join("out/children/spawn_7.1.ndjson");
+    let mut b = fs::read(&p).unwrap();
+    b.push(b'\n');
+    fs::write(&p, b).unwrap();
+    let tv = verify_out("trace", &d.join("out"));
+    assert!(!tv.status.success());
+    assert!(String::from_utf8_lossy(&tv.stderr).contains("M2_CHILD_RUN_DIGEST_MISMATCH spawn_7.1"));
+
+    fs::remove_file(&p).unwrap();
+    let tv = verify_out("trace", &d.join("out"));
+    assert!(!tv.status.success());
+    assert!(String::from_utf8_lossy(&tv.stderr).contains("M2_MISSING_CHILD_TRACE children/spawn_7.1.ndjson"));
+}
+
+#[test]
+fn replay_answers_child_oracles_from_their_own_spans() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    fs::write(
+        d.join("main.fard"),
+        r#"import("std/uuid") as uuid
+import("std/list") as list
+import("std/promise") as promise
+let ids = list.par_map([1, 2, 3], fn(x) { uuid.v4() })
+let p = promise.spawn(fn() { uuid.v4() })
+{ ids: ids, p: promise.await(p), top: uuid.v4() }
+"#,
+    )
+    .unwrap();
+    assert!(run_threads(d, "rec", "4", &["--record"]).status.success());
+    let out = run_threads(d, "rep", "4", &["--replay", "rec"]);
+    assert!(out.status.success(), "replay failed: {}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(
+        fs::read(d.join("rec/result.json")).unwrap(),
+        fs::read(d.join("rep/result.json")).unwrap()
+    );
+    assert_eq!(
+        fs::read(d.join("rec/trace.ndjson")).unwrap(),
+        fs::read(d.join("rep/trace.ndjson")).unwrap()
+    );
+}
+
+#[test]
+fn receipts_merge_in_spawn_order_past_unawaited_children() {
+    let tmp = tmpdir();
+    let d = tmp.path();
+    let prog = r#"import("std/promise") as promise
+let a = promise.spawn(fn() { 1 })
+let b = promise.spawn(fn() { 2 })
+let c = promise.spawn(fn() {
+  let inner = promise.spawn(fn() { 3 })
+  promise.await(inner) + 10
+})
+let z = promise.await(c)
+{ a: promise.await(a), z: z }
+"#;
+    fs::write(d.join("main.fard"), prog).unwrap();
+    for (o, n) in [("o1", "1"), ("o4", "4")] {
+        let out = run_threads(d, o, n, &[]);
+        assert!(out.status.success(), "run failed: {}", String::from_utf8_lossy(&out.stderr));
+        let result: serde_json::Value = serde_json::from_slice(&fs::read(d.join(o).join("result.json")).unwrap()).unwrap();
+        assert_eq!(result["result"], serde_json::json!({"a": 1, "z": 13}));
+    }
+    let evs = all_events(&d.join("o1"));
+    let ids: Vec<&str> = evs.iter().filter(|e| e["t"] == "child_receipt").map(|e| e["spawn_id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["spawn_0", "spawn_2"]);
+    assert_eq!(fs::read(d.join("o1/trace.ndjson")).unwrap(), fs::read(d.join("o4/trace.ndjson")).unwrap());
+    let tv = verify_out("trace", &d.join("o1"));
+    assert!(tv.status.success(), "trace verify: {}", String::from_utf8_lossy(&tv.stderr));
+}